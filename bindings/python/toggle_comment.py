@@ -0,0 +1,54 @@
+# toggle_comment.py - generated from src/porcelain.rs by python_wrapper(); do not edit
+"""Run toggle_comment_indent with --porcelain and parse its records."""
+
+import subprocess
+from collections import namedtuple
+
+PORCELAIN_VERSION = 1
+
+# Operation done: zero-indexed line span (or -) and number of lines
+Ok = namedtuple('Ok', ['mode', 'file', 'first_line', 'last_line', 'count'])
+# Nothing to do for this file type; reason is always unsupported
+Unchanged = namedtuple('Unchanged', ['mode', 'file', 'reason'])
+# One answer of a read-only query (--detect, --column, --relative, --query-range, --lint-comments, --inspect, --verify-only)
+Value = namedtuple('Value', ['mode', 'file', 'key', 'value'])
+# One line of --query-lines: zero-indexed line, C / . / B, leading spaces and tabs, flag or none
+Line = namedtuple('Line', ['mode', 'file', 'line', 'state', 'leading_whitespace', 'flag_kind'])
+# Operation failed: the process exit code and a human message
+Error = namedtuple('Error', ['mode', 'file', 'exit_code', 'message'])
+
+RECORD_KINDS = {
+    'ok': Ok,
+    'unchanged': Unchanged,
+    'value': Value,
+    'line': Line,
+    'error': Error,
+}
+
+_UNESCAPE = {'t': '\t', 'n': '\n', 'r': '\r', '\\': '\\'}
+
+
+def _unescape(field):
+    out, chars = [], iter(field)
+    for c in chars:
+        out.append(_UNESCAPE.get(next(chars, ''), '') if c == '\\' else c)
+    return ''.join(out)
+
+
+def parse_record(line):
+    """Parse one porcelain line into its namedtuple (ValueError if malformed)."""
+    parts = [_unescape(p) for p in line.rstrip('\n').split('\t')]
+    if len(parts) < 4 or parts[0] != str(PORCELAIN_VERSION):
+        raise ValueError('unsupported porcelain record: %r' % line)
+    kind = RECORD_KINDS.get(parts[1])
+    if kind is None or len(parts) - 2 != len(kind._fields):
+        raise ValueError('unsupported porcelain record: %r' % line)
+    return kind(*parts[2:])
+
+
+def run(*args, binary='toggle_comment_indent'):
+    """Run one command; returns (exit_code, [records])."""
+    done = subprocess.run([binary, '--porcelain', *map(str, args)],
+                          capture_output=True, text=True)
+    records = [parse_record(l) for l in done.stdout.splitlines() if l]
+    return done.returncode, records
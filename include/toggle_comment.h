@@ -0,0 +1,75 @@
+/* toggle_comment.h - generated from src/ffi.rs by c_header(); do not edit */
+#ifndef TOGGLE_COMMENT_H
+#define TOGGLE_COMMENT_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Return codes (same numbers as the CLI exit codes) */
+#define TC_OK 0 /* Success */
+#define TC_ERR_INVALID_ARGUMENT 1 /* Invalid argument */
+#define TC_ERR_FILE_NOT_FOUND 2 /* File not found */
+#define TC_ERR_NO_EXTENSION 3 /* No file extension */
+#define TC_ERR_UNSUPPORTED 4 /* Unsupported extension (plain .json) */
+#define TC_ERR_NOT_FOUND 5 /* Line not found */
+#define TC_ERR_IO 6 /* I/O error */
+#define TC_ERR_PATH 7 /* Path error */
+#define TC_ERR_LINE_TOO_LONG 8 /* Line too long (joins, splits and style conversions) */
+#define TC_ERR_TOO_MANY_LINES 9 /* Too many lines */
+#define TC_ERR_PERMISSION_DENIED 10 /* Permission denied */
+#define TC_ERR_INCOMPLETE_WRITE 11 /* Incomplete write (original unchanged) */
+#define TC_ERR_READ_ONLY 12 /* Target file is read-only */
+#define TC_ERR_FILE_TOO_LARGE 13 /* File too large */
+#define TC_ERR_NOTEBOOK 14 /* Not a notebook, or not a code cell */
+#define TC_ERR_PROTECTED_REGION 15 /* Line is in a protected region */
+#define TC_ERR_BACKUP_INTEGRITY 16 /* Backup does not match its checksum */
+#define TC_ERR_CONCURRENT_MODIFICATION 17 /* File changed on disk during the operation */
+#define TC_ERR_NOT_REGULAR_FILE 18 /* Not a regular file */
+#define TC_ERR_OWN_ARTIFACT 19 /* Target is a toggle_comment backup or temp file */
+#define TC_ERR_INVALID_UTF8 20 /* Not valid UTF-8 */
+#define TC_ERR_INTERNAL 99 /* Internal error */
+#define TC_ERR_CANCELLED 130 /* Cancelled (original unchanged) */
+
+/* Paths: NUL-terminated UTF-8. Lines: zero-indexed. */
+
+/* Toggle the extension's line comment on one line */
+int tc_toggle_line(const char *path, size_t line);
+
+/* Toggle a Rust doc comment (///) on one line */
+int tc_toggle_docstring_line(const char *path, size_t line);
+
+/* Toggle line comments on start..=end (each line by its own state) */
+int tc_toggle_range(const char *path, size_t start, size_t end);
+
+/* Toggle Rust doc comments on start..=end */
+int tc_toggle_docstring_range(const char *path, size_t start, size_t end);
+
+/* Toggle block comment markers around start..=end */
+int tc_toggle_block(const char *path, size_t start, size_t end);
+
+/* Add 4 spaces at the start of one line */
+int tc_indent_line(const char *path, size_t line);
+
+/* Remove up to 4 leading spaces from one line */
+int tc_unindent_line(const char *path, size_t line);
+
+/* Indent start..=end */
+int tc_indent_range(const char *path, size_t start, size_t end);
+
+/* Unindent start..=end */
+int tc_unindent_range(const char *path, size_t start, size_t end);
+
+/* Static, NUL-terminated description of a code (never free it) */
+const char *tc_error_message(int code);
+
+/* Stop running and later operations before they replace a file (TC_ERR_CANCELLED); async-signal-safe */
+void tc_request_cancel(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TOGGLE_COMMENT_H */
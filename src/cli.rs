@@ -0,0 +1,615 @@
+//! # cli.rs
+//!
+//! Declarative mode table for the command line (`main.rs`)
+//!
+//! Each mode is written down once, as a `Mode`: its name, the argument
+//! forms it accepts and what runs it. Argument checking, the
+//! `requires ...` errors, the USAGE lines, `--capabilities` and
+//! `--completions` all read the same descriptors, so they cannot disagree.
+//!
+//! A form is a list of `Arg`s:
+//! - Positional arguments, in order: required ones first, then optional
+//!   ones, then at most one repeated one (one or more values)
+//! - Flagged arguments (`--nth <k>`, `--to block|line`, `--json`), anywhere
+//!   after the mode flag
+//!
+//! Forms are tried in order; the first one whose shape fits the arguments
+//! is used, and its numbers are then checked. An argument starting with
+//! `--` that is not one of the form's flags is an ordinary value.
+
+/// Name of the basic mode (no mode flag: `<file_path> <line_number>`)
+pub const BASIC_MODE: &str = "toggle";
+
+/// What an argument holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    /// The file the mode reads or edits (project config lookup,
+    /// `--preview`, `--force-readonly`)
+    Target,
+
+    /// Zero-indexed line number (`$`, `%`, `N,M`, `+N` / `-N` are resolved
+    /// before parsing, see `Mode::line_after_target()`)
+    Line,
+
+    /// Any other non-negative integer
+    Number,
+
+    /// One of these `|`-separated words
+    Choice(&'static str),
+
+    /// Anything else (paths, text), passed through as given
+    Text,
+
+    /// Flag without a value (`--json`)
+    Switch,
+}
+
+/// How many values an argument takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Required,
+    Optional,
+    /// One or more (positional only, last)
+    Repeated,
+}
+
+/// One argument of a form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arg {
+    /// Name in the usage text (`<name>`), and for `ModeArgs` lookups
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub arity: Arity,
+    /// Flag written before the value (`--nth`); `None` for positional
+    pub flag: Option<&'static str>,
+}
+
+impl Arg {
+    /// Required positional argument
+    pub const fn new(name: &'static str, kind: ArgKind) -> Arg {
+        Arg {
+            name,
+            kind,
+            arity: Arity::Required,
+            flag: None,
+        }
+    }
+
+    /// Required `<flag> <name>` pair
+    pub const fn flagged(flag: &'static str, name: &'static str, kind: ArgKind) -> Arg {
+        Arg {
+            name,
+            kind,
+            arity: Arity::Required,
+            flag: Some(flag),
+        }
+    }
+
+    /// Optional flag without a value (looked up by the flag itself)
+    pub const fn switch(flag: &'static str) -> Arg {
+        Arg {
+            name: flag,
+            kind: ArgKind::Switch,
+            arity: Arity::Optional,
+            flag: Some(flag),
+        }
+    }
+
+    pub const fn required(self) -> Arg {
+        Arg {
+            arity: Arity::Required,
+            ..self
+        }
+    }
+
+    pub const fn optional(self) -> Arg {
+        Arg {
+            arity: Arity::Optional,
+            ..self
+        }
+    }
+
+    pub const fn repeated(self) -> Arg {
+        Arg {
+            arity: Arity::Repeated,
+            ..self
+        }
+    }
+
+    /// Usage text: `<file_path>`, `[max_run]`, `<line_number>...`,
+    /// `--to block|line`, `[--nth <k>]`, `[--json]`
+    pub fn synopsis(&self) -> String {
+        let value = match self.kind {
+            ArgKind::Choice(words) => words.to_string(),
+            ArgKind::Switch => String::new(),
+            _ if self.flag.is_none() && self.arity == Arity::Optional => self.name.to_string(),
+            _ => format!("<{}>", self.name),
+        };
+        let text = match self.flag {
+            Some(flag) if value.is_empty() => flag.to_string(),
+            Some(flag) => format!("{} {}", flag, value),
+            None => value,
+        };
+        match self.arity {
+            Arity::Required => text,
+            Arity::Optional => format!("[{}]", text),
+            Arity::Repeated => format!("{}...", text),
+        }
+    }
+}
+
+/// One mode of the command line
+///
+/// `H` is what runs it; `main.rs` decides what that is.
+#[derive(Debug)]
+pub struct Mode<H: 'static> {
+    /// Name without `--` (BASIC_MODE for basic mode), as in porcelain records
+    pub name: &'static str,
+
+    /// Accepted argument lists after the mode flag, tried in order
+    pub forms: &'static [&'static [Arg]],
+
+    /// What the mode reads on stdin, shown before its usage line
+    pub stdin: Option<&'static str>,
+
+    pub handler: H,
+}
+
+/// Arguments that match no form, or a number that is not one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// `mode` is `--<name>` (or `Basic mode`); `forms` its synopses
+    Arguments { mode: String, forms: Vec<String> },
+
+    /// Line or number argument that is not a non-negative integer
+    NotANumber { name: &'static str },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Arguments { mode, forms } if forms.iter().all(String::is_empty) => {
+                write!(f, "{} takes no arguments", mode)
+            }
+            ParseError::Arguments { mode, forms } => {
+                write!(f, "{} requires {}", mode, forms.join(" or "))
+            }
+            ParseError::NotANumber { name } => write!(f, "{} must be a valid integer", name),
+        }
+    }
+}
+
+/// Arguments of one mode, checked against one of its forms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeArgs<'a> {
+    /// `(name, value, parsed number)` in command line order
+    values: Vec<(&'static str, &'a str, Option<usize>)>,
+}
+
+impl<'a> ModeArgs<'a> {
+    /// First value of an argument (a switch's value is the flag itself)
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.values
+            .iter()
+            .find(|(arg, _, _)| *arg == name)
+            .map(|(_, value, _)| *value)
+    }
+
+    /// Value of a required argument (`""` if the form has none of that name)
+    pub fn text(&self, name: &str) -> &'a str {
+        self.get(name).unwrap_or_default()
+    }
+
+    /// Every value of a repeated argument
+    pub fn all(&self, name: &str) -> Vec<&'a str> {
+        self.values
+            .iter()
+            .filter(|(arg, _, _)| *arg == name)
+            .map(|(_, value, _)| *value)
+            .collect()
+    }
+
+    /// Whether an optional argument or switch was given
+    pub fn has(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Line or number argument, `None` if not given
+    pub fn number_opt(&self, name: &str) -> Option<usize> {
+        self.values
+            .iter()
+            .find(|(arg, _, _)| *arg == name)
+            .and_then(|(_, _, number)| *number)
+    }
+
+    /// Required line or number argument (checked while parsing)
+    pub fn number(&self, name: &str) -> usize {
+        self.number_opt(name).unwrap_or_default()
+    }
+
+    /// Every value of a repeated line or number argument
+    pub fn numbers(&self, name: &str) -> Vec<usize> {
+        self.values
+            .iter()
+            .filter(|(arg, _, _)| *arg == name)
+            .filter_map(|(_, _, number)| *number)
+            .collect()
+    }
+}
+
+impl<H> Mode<H> {
+    /// `--<name>`, empty for basic mode
+    pub fn flag(&self) -> String {
+        if self.name == BASIC_MODE {
+            String::new()
+        } else {
+            format!("--{}", self.name)
+        }
+    }
+
+    /// One synopsis per form, flag first: `--block <file_path> <start_line> <end_line>`
+    pub fn synopses(&self) -> Vec<String> {
+        self.forms
+            .iter()
+            .map(|form| {
+                std::iter::once(self.flag())
+                    .chain(form.iter().map(Arg::synopsis))
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    /// Usage lines for `program`, with the stdin input in front if any
+    pub fn usage(&self, program: &str) -> Vec<String> {
+        self.synopses()
+            .iter()
+            .map(|synopsis| {
+                let line = format!("{} {}", program, synopsis);
+                match self.stdin {
+                    Some(input) => format!("{} | {}", input, line),
+                    None => line,
+                }
+            })
+            .collect()
+    }
+
+    /// Whether any form takes line numbers (these modes get `$`, `%`, ...)
+    pub fn takes_lines(&self) -> bool {
+        self.forms
+            .iter()
+            .any(|form| form.iter().any(|arg| arg.kind == ArgKind::Line))
+    }
+
+    /// Whether the positional argument `position` places after the target
+    /// (0: the one right after it) is a line number in any form
+    ///
+    /// Flagged arguments are not counted; a repeated last argument covers
+    /// every position from its own on.
+    pub fn line_after_target(&self, position: usize) -> bool {
+        self.forms.iter().any(|form| {
+            let after_target: Vec<&Arg> = form
+                .iter()
+                .filter(|arg| arg.flag.is_none())
+                .skip_while(|arg| arg.kind != ArgKind::Target)
+                .skip(1)
+                .collect();
+            let spec = match after_target.get(position) {
+                Some(spec) => Some(*spec),
+                None => after_target
+                    .last()
+                    .copied()
+                    .filter(|spec| spec.arity == Arity::Repeated),
+            };
+            spec.is_some_and(|spec| spec.kind == ArgKind::Line)
+        })
+    }
+
+    /// Flagged argument `--<flag>` of any form (`--nth`, `--to`, `--json`)
+    pub fn flagged_arg(&self, flag: &str) -> Option<&Arg> {
+        self.forms
+            .iter()
+            .flat_map(|form| form.iter())
+            .find(|arg| arg.flag == Some(flag))
+    }
+
+    /// Index in `args` (program name first) of the first argument after the mode flag
+    fn first_arg(&self) -> usize {
+        if self.name == BASIC_MODE { 1 } else { 2 }
+    }
+
+    /// Index in `args` of the target file, if the mode has one and it is given
+    ///
+    /// Positional arguments are counted as in the first form with a target,
+    /// so this works before the arguments are checked (and with `%` not yet
+    /// expanded).
+    pub fn target_index(&self, args: &[String]) -> Option<usize> {
+        let form = self
+            .forms
+            .iter()
+            .find(|form| form.iter().any(|arg| arg.kind == ArgKind::Target))?;
+        let position = form
+            .iter()
+            .filter(|arg| arg.flag.is_none())
+            .position(|arg| arg.kind == ArgKind::Target)?;
+
+        let mut positional = 0;
+        let mut index = self.first_arg();
+        while index < args.len() {
+            match form
+                .iter()
+                .find(|arg| arg.flag == Some(args[index].as_str()))
+            {
+                Some(arg) if arg.kind == ArgKind::Switch => index += 1,
+                Some(_) => index += 2,
+                None if positional == position => return Some(index),
+                None => {
+                    positional += 1;
+                    index += 1;
+                }
+            }
+        }
+        None
+    }
+
+    /// Check `args` (program name first) against the forms
+    ///
+    /// # Returns
+    /// * `Ok(args)` - Values of the first form that fits
+    /// * `Err(ParseError::Arguments)` - No form fits
+    /// * `Err(ParseError::NotANumber)` - A form fits but a number is invalid
+    pub fn parse<'a>(&self, args: &'a [String]) -> Result<ModeArgs<'a>, ParseError> {
+        let args = args.get(self.first_arg()..).unwrap_or_default();
+        for form in self.forms {
+            if let Some(values) = match_form(form, args) {
+                return number_values(values);
+            }
+        }
+
+        Err(ParseError::Arguments {
+            mode: if self.name == BASIC_MODE {
+                "Basic mode".to_string()
+            } else {
+                self.flag()
+            },
+            forms: self
+                .forms
+                .iter()
+                .map(|form| form.iter().map(Arg::synopsis).collect::<Vec<_>>().join(" "))
+                .collect(),
+        })
+    }
+}
+
+/// Find the mode `arg` (the first argument) selects
+///
+/// Not starting with `--`: basic mode (`arg` is its file).
+pub fn find_mode<'m, H>(modes: &'m [Mode<H>], arg: &str) -> Option<&'m Mode<H>> {
+    let name = match arg.strip_prefix("--") {
+        Some(name) if name != BASIC_MODE => name,
+        Some(_) => return None,
+        None => BASIC_MODE,
+    };
+    modes.iter().find(|mode| mode.name == name)
+}
+
+/// Assign `args` to the arguments of `form`, `None` if the shape does not fit
+fn match_form<'a>(form: &[Arg], args: &'a [String]) -> Option<Vec<(Arg, &'a str)>> {
+    let mut values: Vec<(Arg, &'a str)> = Vec::with_capacity(args.len());
+    let mut positional: Vec<&'a str> = Vec::with_capacity(args.len());
+
+    let mut index = 0;
+    while index < args.len() {
+        let arg = args[index].as_str();
+        match form.iter().find(|spec| spec.flag == Some(arg)) {
+            Some(spec) if values.iter().any(|(seen, _)| seen == spec) => return None,
+            Some(spec) if spec.kind == ArgKind::Switch => {
+                values.push((*spec, arg));
+                index += 1;
+            }
+            Some(spec) => {
+                values.push((*spec, args.get(index + 1)?.as_str()));
+                index += 2;
+            }
+            None => {
+                positional.push(arg);
+                index += 1;
+            }
+        }
+    }
+
+    let flagged_missing = form.iter().any(|spec| {
+        spec.flag.is_some()
+            && spec.arity == Arity::Required
+            && !values.iter().any(|(seen, _)| seen == spec)
+    });
+    if flagged_missing {
+        return None;
+    }
+
+    let mut remaining = positional.into_iter().peekable();
+    for spec in form.iter().filter(|spec| spec.flag.is_none()) {
+        match spec.arity {
+            Arity::Required => values.push((*spec, remaining.next()?)),
+            Arity::Optional => {
+                if let Some(value) = remaining.next() {
+                    values.push((*spec, value));
+                }
+            }
+            Arity::Repeated => {
+                remaining.peek()?;
+                values.extend(remaining.by_ref().map(|value| (*spec, value)));
+            }
+        }
+    }
+    if remaining.next().is_some() {
+        return None;
+    }
+
+    // Choice words are part of the shape (`--to block|line`)
+    let choices_fit = values.iter().all(|(spec, value)| match spec.kind {
+        ArgKind::Choice(words) => words.split('|').any(|word| word == *value),
+        _ => true,
+    });
+    choices_fit.then_some(values)
+}
+
+/// Parse the line and number values of a matched form
+fn number_values(values: Vec<(Arg, &str)>) -> Result<ModeArgs<'_>, ParseError> {
+    let mut parsed = Vec::with_capacity(values.len());
+    for (spec, value) in values {
+        let number = match spec.kind {
+            ArgKind::Line | ArgKind::Number => match value.parse::<usize>() {
+                Ok(n) => Some(n),
+                Err(_) => return Err(ParseError::NotANumber { name: spec.name }),
+            },
+            _ => None,
+        };
+        parsed.push((spec.name, value, number));
+    }
+    Ok(ModeArgs { values: parsed })
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    const FILE: Arg = Arg::new("file_path", ArgKind::Target);
+    const MODES: &[Mode<()>] = &[
+        Mode {
+            name: BASIC_MODE,
+            forms: &[&[FILE, Arg::new("line_number", ArgKind::Line)]],
+            stdin: None,
+            handler: (),
+        },
+        Mode {
+            name: "at-line-containing",
+            forms: &[&[
+                Arg::new("literal", ArgKind::Text),
+                FILE,
+                Arg::flagged("--nth", "k", ArgKind::Number).optional(),
+            ]],
+            stdin: None,
+            handler: (),
+        },
+        Mode {
+            name: "list-basic",
+            forms: &[
+                &[
+                    FILE,
+                    Arg::switch("--stdin-lines").required(),
+                    Arg::new("max_lines", ArgKind::Number).optional(),
+                ],
+                &[FILE, Arg::new("line_number", ArgKind::Line).repeated()],
+            ],
+            stdin: None,
+            handler: (),
+        },
+        Mode {
+            name: "convert-style",
+            forms: &[&[
+                FILE,
+                Arg::flagged("--to", "to", ArgKind::Choice("block|line")),
+            ]],
+            stdin: Some("<input>"),
+            handler: (),
+        },
+    ];
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("tc")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_find_and_parse_modes() {
+        let basic = find_mode(MODES, "a.rs").unwrap();
+        let list = args(&["a.rs", "4"]);
+        let parsed = basic.parse(&list).unwrap();
+        assert_eq!(
+            (parsed.text("file_path"), parsed.number("line_number")),
+            ("a.rs", 4)
+        );
+        assert_eq!(
+            basic.parse(&args(&["a.rs", "x"])).unwrap_err().to_string(),
+            "line_number must be a valid integer"
+        );
+        assert_eq!(
+            basic.parse(&args(&["a.rs"])).unwrap_err().to_string(),
+            "Basic mode requires <file_path> <line_number>"
+        );
+        assert!(find_mode(MODES, "--toggle").is_none());
+        assert!(find_mode(MODES, "--nope").is_none());
+
+        // Flags anywhere after the mode flag; target found before parsing
+        let content = find_mode(MODES, "--at-line-containing").unwrap();
+        let list = args(&["--at-line-containing", "--nth", "2", "fn main", "a.rs"]);
+        let parsed = content.parse(&list).unwrap();
+        assert_eq!(
+            (parsed.text("literal"), parsed.number_opt("k")),
+            ("fn main", Some(2))
+        );
+        assert_eq!(content.target_index(&list), Some(5));
+        assert_eq!(content.target_index(&args(&["--at-line-containing"])), None);
+
+        // First fitting form wins; an unknown --flag is a plain value
+        let lists = find_mode(MODES, "--list-basic").unwrap();
+        let list = args(&["--list-basic", "a.rs", "--stdin-lines"]);
+        let parsed = lists.parse(&list).unwrap();
+        assert!(parsed.has("--stdin-lines") && !parsed.has("max_lines"));
+        let list = args(&["--list-basic", "a.rs", "3", "1"]);
+        let parsed = lists.parse(&list).unwrap();
+        assert_eq!(parsed.numbers("line_number"), vec![3, 1]);
+        assert_eq!(
+            lists
+                .parse(&args(&["--list-basic", "a.rs", "--stdin"]))
+                .unwrap_err(),
+            ParseError::NotANumber {
+                name: "line_number"
+            }
+        );
+        assert!(lists.takes_lines() && !content.takes_lines());
+        assert!(lists.line_after_target(0) && lists.line_after_target(5));
+        assert!(basic.line_after_target(0) && !basic.line_after_target(1));
+        assert!(!content.line_after_target(0));
+        assert_eq!(content.flagged_arg("--nth").map(|arg| arg.name), Some("k"));
+        assert!(content.flagged_arg("--to").is_none());
+
+        let convert = find_mode(MODES, "--convert-style").unwrap();
+        assert!(
+            convert
+                .parse(&args(&["--convert-style", "a.rs", "--to", "line"]))
+                .is_ok()
+        );
+        assert_eq!(
+            convert
+                .parse(&args(&["--convert-style", "a.rs", "--to", "box"]))
+                .unwrap_err()
+                .to_string(),
+            "--convert-style requires <file_path> --to block|line"
+        );
+    }
+
+    #[test]
+    fn test_usage_lines() {
+        assert_eq!(MODES[0].usage("tc"), vec!["tc <file_path> <line_number>"]);
+        assert_eq!(
+            MODES[1].synopses(),
+            vec!["--at-line-containing <literal> <file_path> [--nth <k>]"]
+        );
+        assert_eq!(
+            MODES[2].synopses(),
+            vec![
+                "--list-basic <file_path> --stdin-lines [max_lines]",
+                "--list-basic <file_path> <line_number>..."
+            ]
+        );
+        assert_eq!(
+            MODES[3].usage("tc"),
+            vec!["<input> | tc --convert-style <file_path> --to block|line"]
+        );
+    }
+}
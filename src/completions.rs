@@ -0,0 +1,240 @@
+//! # completions.rs
+//!
+//! Shell completion scripts (`--completions bash|zsh|fish`)
+//!
+//! The scripts are generated from the binary's own mode and option tables
+//! (the ones `--capabilities` lists), so a new mode or option completes as
+//! soon as it is added there; nothing is maintained by hand.
+//!
+//! Each option carries a value hint:
+//! - `""` - Flag without a value
+//! - `"a|b|c"` - One of these words
+//! - `"<dir>"` - A directory
+//! - `"<...>"` - Any other value (not completed)
+//!
+//! Other words complete as mode flags / options after `-`, files otherwise.
+//!
+//! ```text
+//! toggle_comment --completions bash > /etc/bash_completion.d/toggle_comment
+//! toggle_comment --completions zsh > ~/.zfunc/_toggle_comment
+//! toggle_comment --completions fish > ~/.config/fish/completions/toggle_comment.fish
+//! ```
+
+/// Shell a script is generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    /// `bash`, `zsh` or `fish`
+    pub fn from_name(name: &str) -> Option<Shell> {
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None,
+        }
+    }
+}
+
+/// What completes after an option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueHint<'a> {
+    None,
+    Words(&'a str),
+    Directory,
+    Free,
+}
+
+/// Parse a value hint (see the module docs)
+fn value_hint(hint: &str) -> ValueHint<'_> {
+    match hint {
+        "" => ValueHint::None,
+        "<dir>" => ValueHint::Directory,
+        _ if hint.starts_with('<') => ValueHint::Free,
+        words => ValueHint::Words(words),
+    }
+}
+
+/// Generate the completion script
+///
+/// # Arguments
+/// * `shell` - Target shell
+/// * `binary` - Command name the script registers for
+/// * `modes` - Mode names without `--` (`toggle`, basic mode, has no flag)
+/// * `options` - `(option, value hint)` pairs, options with `--`
+pub fn completion_script(
+    shell: Shell,
+    binary: &str,
+    modes: &[&str],
+    options: &[(&str, &str)],
+) -> String {
+    let function = format!("_{}", binary.replace(['-', '.'], "_"));
+    let flags: Vec<String> = modes
+        .iter()
+        .filter(|mode| **mode != "toggle")
+        .map(|mode| format!("--{}", mode))
+        .chain(options.iter().map(|(option, _)| option.to_string()))
+        .collect();
+    let options_with = |wanted: fn(ValueHint) -> bool| -> Vec<&str> {
+        options
+            .iter()
+            .filter(|(_, hint)| wanted(value_hint(hint)))
+            .map(|(option, _)| *option)
+            .collect()
+    };
+    let directories = options_with(|hint| hint == ValueHint::Directory);
+    let free = options_with(|hint| hint == ValueHint::Free);
+
+    let mut script = String::new();
+    match shell {
+        Shell::Bash => {
+            script.push_str(&format!(
+                "# bash completion for {} (generated by --completions bash)\n",
+                binary
+            ));
+            script.push_str(&format!("{}() {{\n", function));
+            script.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+            script.push_str("    local prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+            script.push_str("    case \"$prev\" in\n");
+            for (option, hint) in options {
+                if let ValueHint::Words(words) = value_hint(hint) {
+                    script.push_str(&format!(
+                        "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;\n",
+                        option,
+                        words.replace('|', " ")
+                    ));
+                }
+            }
+            if !directories.is_empty() {
+                script.push_str(&format!(
+                    "        {}) COMPREPLY=($(compgen -d -- \"$cur\")); return ;;\n",
+                    directories.join("|")
+                ));
+            }
+            if !free.is_empty() {
+                script.push_str(&format!("        {}) return ;;\n", free.join("|")));
+            }
+            script.push_str("    esac\n");
+            script.push_str("    if [[ \"$cur\" == -* ]]; then\n");
+            script.push_str(&format!(
+                "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n",
+                flags.join(" ")
+            ));
+            script.push_str("    else\n");
+            script.push_str("        COMPREPLY=($(compgen -f -- \"$cur\"))\n");
+            script.push_str("    fi\n");
+            script.push_str("}\n");
+            script.push_str(&format!(
+                "complete -o filenames -F {} {}\n",
+                function, binary
+            ));
+        }
+        Shell::Zsh => {
+            script.push_str(&format!("#compdef {}\n", binary));
+            script.push_str(&format!(
+                "# zsh completion for {} (generated by --completions zsh)\n",
+                binary
+            ));
+            script.push_str(&format!("{}() {{\n", function));
+            script.push_str("    case \"$words[CURRENT-1]\" in\n");
+            for (option, hint) in options {
+                if let ValueHint::Words(words) = value_hint(hint) {
+                    script.push_str(&format!(
+                        "        {}) compadd -- {}; return ;;\n",
+                        option,
+                        words.replace('|', " ")
+                    ));
+                }
+            }
+            if !directories.is_empty() {
+                script.push_str(&format!(
+                    "        {}) _files -/; return ;;\n",
+                    directories.join("|")
+                ));
+            }
+            if !free.is_empty() {
+                script.push_str(&format!("        {}) return ;;\n", free.join("|")));
+            }
+            script.push_str("    esac\n");
+            script.push_str("    if [[ \"$PREFIX\" == -* ]]; then\n");
+            script.push_str(&format!("        compadd -- {}\n", flags.join(" ")));
+            script.push_str("    else\n");
+            script.push_str("        _files\n");
+            script.push_str("    fi\n");
+            script.push_str("}\n");
+            script.push_str(&format!("{} \"$@\"\n", function));
+        }
+        Shell::Fish => {
+            script.push_str(&format!(
+                "# fish completion for {} (generated by --completions fish)\n",
+                binary
+            ));
+            for mode in modes.iter().filter(|mode| **mode != "toggle") {
+                script.push_str(&format!("complete -c {} -l {} -d 'mode'\n", binary, mode));
+            }
+            for (option, hint) in options {
+                let long = option.trim_start_matches('-');
+                let value = match value_hint(hint) {
+                    ValueHint::None => String::new(),
+                    ValueHint::Words(words) => format!(" -x -a '{}'", words.replace('|', " ")),
+                    ValueHint::Directory => " -x -a '(__fish_complete_directories)'".to_string(),
+                    ValueHint::Free => " -x".to_string(),
+                };
+                script.push_str(&format!(
+                    "complete -c {} -l {}{} -d 'option'\n",
+                    binary, long, value
+                ));
+            }
+        }
+    }
+    script
+}
+
+#[cfg(test)]
+mod completions_tests {
+    use super::*;
+
+    const MODES: [&str; 2] = ["toggle", "block"];
+    const OPTIONS: [(&str, &str); 4] = [
+        ("--force", ""),
+        ("--style", "c89|default"),
+        ("--temp-dir", "<dir>"),
+        ("--timeout", "<seconds>"),
+    ];
+
+    #[test]
+    fn test_completion_scripts() {
+        let bash = completion_script(Shell::Bash, "tc", &MODES, &OPTIONS);
+        assert!(bash.contains(
+            "        --style) COMPREPLY=($(compgen -W \"c89 default\" -- \"$cur\")); return ;;\n"
+        ));
+        assert!(
+            bash.contains("        --temp-dir) COMPREPLY=($(compgen -d -- \"$cur\")); return ;;\n")
+        );
+        assert!(bash.contains("        --timeout) return ;;\n"));
+        // Basic mode has no flag
+        assert!(bash.contains("compgen -W \"--block --force --style --temp-dir --timeout\""));
+        assert!(bash.ends_with("complete -o filenames -F _tc tc\n"));
+
+        let zsh = completion_script(Shell::Zsh, "tc", &MODES, &OPTIONS);
+        assert!(zsh.starts_with("#compdef tc\n"));
+        assert!(zsh.contains("        --style) compadd -- c89 default; return ;;\n"));
+
+        let fish = completion_script(Shell::Fish, "tc", &MODES, &OPTIONS);
+        assert_eq!(
+            fish.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "complete -c tc -l block -d 'mode'",
+                "complete -c tc -l force -d 'option'",
+                "complete -c tc -l style -x -a 'c89 default' -d 'option'",
+                "complete -c tc -l temp-dir -x -a '(__fish_complete_directories)' -d 'option'",
+                "complete -c tc -l timeout -x -d 'option'",
+            ]
+        );
+        assert_eq!(Shell::from_name("tcsh"), None);
+    }
+}
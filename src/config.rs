@@ -0,0 +1,713 @@
+//! # config.rs
+//!
+//! Defaults below the CLI flags: project config and environment variables
+//!
+//! Teams put one file at the top of a project instead of passing the same
+//! flags on every call:
+//!
+//! ```text
+//! # .toggle_comment.toml
+//! indent_width = 2          # spaces per indent / unindent step (1..=16)
+//! indent_tab = false        # as --indent-tab (one tab per step instead)
+//! preserve_indent = true    # as --preserve-indent
+//! backup = false            # no backup_toggle_comment_* copies
+//! skip_blank = true         # as --skip-blank
+//! style = "c89"             # as --style c89 (or "default")
+//!
+//! [extensions]
+//! tpl = "py"                # treat .tpl files as .py
+//!
+//! [presets.team]            # --preset team (same keys as the top level)
+//! indent_width = 3
+//! ```
+//!
+//! ## Presets
+//! `--preset <name>` selects a bundle of the keys above in one flag, for
+//! editor keybindings: a `[presets.<name>]` table of the project config,
+//! else one of BUILTIN_PRESETS (a project table of the same name wins).
+//! Names that are not TOML bare keys are quoted: `[presets."k&r"]`.
+//!
+//! ## Discovery
+//! `find_project_config()` looks in the target's directory, then in each
+//! parent, at most MAX_CONFIG_SEARCH_DEPTH directories. The first file found
+//! wins (no merging). The search also stops after a directory that holds
+//! `.git`: a config above the repository root belongs to someone else.
+//!
+//! ## Format
+//! A small TOML subset, standard library only: `key = value` lines, one
+//! `[extensions]` table, `[presets.<name>]` tables, `#` comments and blank
+//! lines. Values are integers,
+//! `true` / `false` or `"strings"` without escapes. Unknown keys and tables,
+//! repeated keys and out-of-range values are errors (a typo must not be
+//! silently ignored); files over MAX_CONFIG_BYTES are refused.
+//!
+//! ## Environment
+//! `env_config()` reads ENV_VARIABLES (empty means unset), e.g. for wrapper
+//! scripts that cannot thread flags through:
+//!
+//! ```text
+//! TOGGLE_COMMENT_INDENT_WIDTH=2        TOGGLE_COMMENT_PRESERVE_INDENT=1
+//! TOGGLE_COMMENT_NO_BACKUP=1           TOGGLE_COMMENT_BACKUP_DIR=/tmp/bak
+//! ```
+//!
+//! ## Precedence
+//! CLI flags > `--preset` > environment > project config > built-in
+//! defaults. `resolve_options()` layers the environment, project config
+//! and defaults into `ToggleOptions`; the CLI applies the preset, then
+//! parses its flags on top.
+
+use crate::toggle_comment_indent_module::{
+    LineStyle, MAX_INDENT_WIDTH, SUPPORTED_EXTENSIONS, ToggleOptions,
+};
+use std::path::{Path, PathBuf};
+
+/// File name searched for
+pub const CONFIG_FILE_NAME: &str = ".toggle_comment.toml";
+
+/// Directories searched at most, the target's own included
+pub const MAX_CONFIG_SEARCH_DEPTH: usize = 32;
+
+/// Largest accepted config file
+pub const MAX_CONFIG_BYTES: u64 = 64 * 1024;
+
+/// Presets shipped in-code: `(name, settings in the config syntax)`
+pub const BUILTIN_PRESETS: [(&str, &str); 4] = [
+    (
+        "python-black",
+        "indent_width = 4\npreserve_indent = true\nskip_blank = true\nstyle = \"default\"\n",
+    ),
+    (
+        "k&r",
+        "indent_width = 4\npreserve_indent = true\nskip_blank = true\nstyle = \"c89\"\n",
+    ),
+    (
+        "google",
+        "indent_width = 2\npreserve_indent = true\nskip_blank = false\nstyle = \"default\"\n",
+    ),
+    (
+        "gofmt",
+        "indent_tab = true\npreserve_indent = true\nskip_blank = true\nstyle = \"default\"\n",
+    ),
+];
+
+/// Environment variables `env_config()` reads, with the values they take
+pub const ENV_VARIABLES: [(&str, &str); 4] = [
+    ("TOGGLE_COMMENT_INDENT_WIDTH", "1..=16"),
+    ("TOGGLE_COMMENT_PRESERVE_INDENT", "1 / 0 / true / false"),
+    ("TOGGLE_COMMENT_NO_BACKUP", "1 / 0 / true / false"),
+    ("TOGGLE_COMMENT_BACKUP_DIR", "an existing directory"),
+];
+
+/// Why a config file was refused (lines are one-based)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Not readable, not UTF-8, or larger than MAX_CONFIG_BYTES
+    Unreadable,
+
+    /// Not `key = value`, `[table]`, a comment or blank
+    Malformed { line: usize },
+
+    /// Key or table this version does not know
+    UnknownKey { line: usize },
+
+    /// Key already set earlier in the same table
+    DuplicateKey { line: usize },
+
+    /// Wrong type or out of range (e.g. `indent_width = 0`, an extension
+    /// mapped to one that is not supported)
+    InvalidValue { line: usize },
+
+    /// Environment variable (one of ENV_VARIABLES) with an invalid value
+    InvalidEnv { name: &'static str },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Unreadable => write!(
+                f,
+                "Config is unreadable, not UTF-8 or over {} bytes",
+                MAX_CONFIG_BYTES
+            ),
+            ConfigError::Malformed { line } => {
+                write!(f, "Config line {} is not key = value or [table]", line)
+            }
+            ConfigError::UnknownKey { line } => {
+                write!(f, "Config line {}: unknown key or table", line)
+            }
+            ConfigError::DuplicateKey { line } => {
+                write!(f, "Config line {}: key is already set", line)
+            }
+            ConfigError::InvalidValue { line } => {
+                write!(f, "Config line {}: invalid value for this key", line)
+            }
+            ConfigError::InvalidEnv { name } => {
+                write!(f, "Environment variable {} has an invalid value", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Settings of one layer (config file or environment); `None` / empty: not
+/// set there
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigLayer {
+    /// `indent_width` (1..=MAX_INDENT_WIDTH)
+    pub indent_width: Option<usize>,
+
+    /// `indent_tab`
+    pub indent_tab: Option<bool>,
+
+    /// `preserve_indent`
+    pub preserve_indent: Option<bool>,
+
+    /// `backup` (`false`: `ToggleOptions::no_backup`)
+    pub backup: Option<bool>,
+
+    /// `ToggleOptions::backup_dir` (environment only)
+    pub backup_dir: Option<PathBuf>,
+
+    /// `skip_blank`
+    pub skip_blank: Option<bool>,
+
+    /// `style` (`"default"` / `"c89"`)
+    pub line_style: Option<LineStyle>,
+
+    /// `[extensions]` entries, `(alias, extension)` in file order
+    pub extension_aliases: Vec<(String, String)>,
+
+    /// `[presets.<name>]` tables, `(name, settings)` in file order (not
+    /// applied by `apply()`; see `find_preset()`)
+    pub presets: Vec<(String, ConfigLayer)>,
+}
+
+impl ConfigLayer {
+    /// Write the settings this config has into `options`
+    pub fn apply(&self, options: &mut ToggleOptions) {
+        if let Some(width) = self.indent_width {
+            options.indent_width = width;
+        }
+        if let Some(tab) = self.indent_tab {
+            options.indent_tab = tab;
+        }
+        if let Some(preserve) = self.preserve_indent {
+            options.preserve_indent = preserve;
+        }
+        if let Some(backup) = self.backup {
+            options.no_backup = !backup;
+        }
+        if let Some(dir) = &self.backup_dir {
+            options.backup_dir = Some(dir.clone());
+        }
+        if let Some(skip) = self.skip_blank {
+            options.skip_blank = skip;
+        }
+        if let Some(style) = self.line_style {
+            options.line_style = style;
+        }
+        options
+            .extension_aliases
+            .extend(self.extension_aliases.iter().cloned());
+    }
+}
+
+/// Library options below the CLI flags: built-in defaults, then the
+/// project config (if any), then the environment
+pub fn resolve_options(project: Option<&ConfigLayer>, env: &ConfigLayer) -> ToggleOptions {
+    let mut options = ToggleOptions::default();
+    if let Some(project) = project {
+        project.apply(&mut options);
+    }
+    env.apply(&mut options);
+    options
+}
+
+/// Settings of the preset `name`: the project config's `[presets.<name>]`,
+/// else the built-in one
+///
+/// # Returns
+/// * `None` - No such preset
+pub fn find_preset(name: &str, project: Option<&ConfigLayer>) -> Option<ConfigLayer> {
+    let defined = project.and_then(|config| {
+        config
+            .presets
+            .iter()
+            .find(|(preset, _)| preset == name)
+            .map(|(_, layer)| layer.clone())
+    });
+    defined.or_else(|| {
+        BUILTIN_PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .and_then(|(_, settings)| parse_project_config(settings).ok())
+    })
+}
+
+/// Read ENV_VARIABLES through `lookup` (`std::env::var(name).ok()` in the
+/// CLI; a map in tests)
+///
+/// # Returns
+/// * `Err(ConfigError::InvalidEnv)` - First variable with an invalid value
+pub fn env_config(lookup: impl Fn(&str) -> Option<String>) -> Result<ConfigLayer, ConfigError> {
+    let [width_var, preserve_var, no_backup_var, dir_var] = ENV_VARIABLES.map(|(name, _)| name);
+    let get = |name: &str| lookup(name).filter(|value| !value.is_empty());
+    let mut layer = ConfigLayer::default();
+
+    if let Some(value) = get(width_var) {
+        match value.parse::<usize>() {
+            Ok(width) if (1..=MAX_INDENT_WIDTH).contains(&width) => {
+                layer.indent_width = Some(width)
+            }
+            _ => return Err(ConfigError::InvalidEnv { name: width_var }),
+        }
+    }
+    if let Some(value) = get(preserve_var) {
+        layer.preserve_indent =
+            Some(parse_env_bool(&value).ok_or(ConfigError::InvalidEnv { name: preserve_var })?);
+    }
+    if let Some(value) = get(no_backup_var) {
+        let no_backup = parse_env_bool(&value).ok_or(ConfigError::InvalidEnv {
+            name: no_backup_var,
+        })?;
+        layer.backup = Some(!no_backup);
+    }
+    if let Some(value) = get(dir_var) {
+        let dir = PathBuf::from(value);
+        if !dir.is_dir() {
+            return Err(ConfigError::InvalidEnv { name: dir_var });
+        }
+        layer.backup_dir = Some(dir);
+    }
+
+    Ok(layer)
+}
+
+/// `1` / `true` or `0` / `false`
+fn parse_env_bool(value: &str) -> Option<bool> {
+    match value {
+        "1" | "true" => Some(true),
+        "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Table a config line belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Table {
+    Top,
+    Extensions,
+    /// Index into `ConfigLayer::presets`
+    Preset(usize),
+}
+
+/// One parsed value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value<'a> {
+    Integer(u64),
+    Bool(bool),
+    Text(&'a str),
+}
+
+/// Parse config text
+///
+/// # Returns
+/// * `Ok(ConfigLayer)` - Every line valid (an empty file sets nothing)
+/// * `Err(ConfigError)` - First invalid line
+pub fn parse_project_config(text: &str) -> Result<ConfigLayer, ConfigError> {
+    let mut config = ConfigLayer::default();
+    let mut table = Table::Top;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = index + 1;
+        let content = strip_comment(raw_line).trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = content.strip_prefix('[') {
+            table = match name.strip_suffix(']').map(str::trim) {
+                Some("extensions") => Table::Extensions,
+                Some(header) => match header.strip_prefix("presets.").map(preset_name) {
+                    Some(Some(name)) => {
+                        if config.presets.iter().any(|(known, _)| known == name) {
+                            return Err(ConfigError::DuplicateKey { line });
+                        }
+                        config
+                            .presets
+                            .push((name.to_string(), ConfigLayer::default()));
+                        Table::Preset(config.presets.len() - 1)
+                    }
+                    Some(None) => return Err(ConfigError::Malformed { line }),
+                    None => return Err(ConfigError::UnknownKey { line }),
+                },
+                None => return Err(ConfigError::Malformed { line }),
+            };
+            continue;
+        }
+
+        let (key, value) = match content.split_once('=') {
+            Some((key, value)) => (key.trim(), parse_value(value.trim())),
+            None => return Err(ConfigError::Malformed { line }),
+        };
+        let value = match value {
+            Some(value) if is_bare_key(key) => value,
+            _ => return Err(ConfigError::Malformed { line }),
+        };
+
+        match table {
+            Table::Top => set_setting(&mut config, key, value, line)?,
+            Table::Preset(index) => set_setting(&mut config.presets[index].1, key, value, line)?,
+            Table::Extensions => {
+                let alias = key;
+                let target = match value {
+                    Value::Text(target)
+                        if SUPPORTED_EXTENSIONS
+                            .iter()
+                            .any(|ext| ext.eq_ignore_ascii_case(target)) =>
+                    {
+                        target
+                    }
+                    _ => return Err(ConfigError::InvalidValue { line }),
+                };
+                if config
+                    .extension_aliases
+                    .iter()
+                    .any(|(known, _)| known.eq_ignore_ascii_case(alias))
+                {
+                    return Err(ConfigError::DuplicateKey { line });
+                }
+                config
+                    .extension_aliases
+                    .push((alias.to_string(), target.to_ascii_lowercase()));
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+/// Set one top-level (or preset) key of `layer`
+fn set_setting(
+    layer: &mut ConfigLayer,
+    key: &str,
+    value: Value<'_>,
+    line: usize,
+) -> Result<(), ConfigError> {
+    let already_set = match (key, value) {
+        ("indent_width", Value::Integer(width)) => {
+            if width == 0 || width > MAX_INDENT_WIDTH as u64 {
+                return Err(ConfigError::InvalidValue { line });
+            }
+            layer.indent_width.replace(width as usize).is_some()
+        }
+        ("indent_tab", Value::Bool(tab)) => layer.indent_tab.replace(tab).is_some(),
+        ("preserve_indent", Value::Bool(preserve)) => {
+            layer.preserve_indent.replace(preserve).is_some()
+        }
+        ("backup", Value::Bool(backup)) => layer.backup.replace(backup).is_some(),
+        ("skip_blank", Value::Bool(skip)) => layer.skip_blank.replace(skip).is_some(),
+        ("style", Value::Text(style)) => {
+            let style = match style {
+                "default" => LineStyle::Default,
+                "c89" => LineStyle::C89,
+                _ => return Err(ConfigError::InvalidValue { line }),
+            };
+            layer.line_style.replace(style).is_some()
+        }
+        (
+            "indent_width" | "indent_tab" | "preserve_indent" | "backup" | "skip_blank" | "style",
+            _,
+        ) => {
+            return Err(ConfigError::InvalidValue { line });
+        }
+        _ => return Err(ConfigError::UnknownKey { line }),
+    };
+    if already_set {
+        return Err(ConfigError::DuplicateKey { line });
+    }
+    Ok(())
+}
+
+/// Name in a `[presets.<name>]` header: a bare key, or a `"string"`
+/// without escapes
+fn preset_name(text: &str) -> Option<&str> {
+    match text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Some(inner) if !inner.is_empty() && !inner.contains(['"', '\\']) => Some(inner),
+        Some(_) => None,
+        None => is_bare_key(text).then_some(text),
+    }
+}
+
+/// Line without a `#` comment (a `#` inside a `"string"` is kept)
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Letters, digits, `_` and `-` (TOML bare keys)
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Integer, boolean or escape-free string
+fn parse_value(text: &str) -> Option<Value<'_>> {
+    match text {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return if inner.contains(['"', '\\']) {
+            None
+        } else {
+            Some(Value::Text(inner))
+        };
+    }
+    if !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()) {
+        return text.parse().ok().map(Value::Integer);
+    }
+    None
+}
+
+/// Find the config that applies to `target` (a file or a directory)
+///
+/// # Returns
+/// * `Some(path)` - First CONFIG_FILE_NAME in the target's directory or
+///   its parents (see the module docs for where the search stops)
+/// * `None` - No config, or the directory cannot be resolved
+pub fn find_project_config(target: &Path) -> Option<PathBuf> {
+    let start = if target.is_dir() {
+        target.to_path_buf()
+    } else {
+        match target.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    };
+    let start = start.canonicalize().ok()?;
+
+    for dir in start.ancestors().take(MAX_CONFIG_SEARCH_DEPTH) {
+        let candidate = dir.join(CONFIG_FILE_NAME);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if dir.join(".git").exists() {
+            return None;
+        }
+    }
+    None
+}
+
+/// Read and parse one config file
+///
+/// # Returns
+/// * `Err(ConfigError::Unreadable)` - Read failed, too large, or not UTF-8
+/// * `Err(ConfigError)` - Otherwise the first invalid line
+pub fn load_project_config(path: &Path) -> Result<ConfigLayer, ConfigError> {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.len() <= MAX_CONFIG_BYTES => {}
+        _ => return Err(ConfigError::Unreadable),
+    }
+    let text = std::fs::read_to_string(path).map_err(|_| ConfigError::Unreadable)?;
+    parse_project_config(&text)
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_project_config() {
+        let config = parse_project_config(
+            "# team defaults\n\
+             indent_width = 2\n\
+             preserve_indent = true   # yaml-style\n\
+             backup = false\n\
+             \n\
+             [extensions]\n\
+             tpl = \"py\"\n\
+             H = \"CPP\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.indent_width, Some(2));
+        assert_eq!(config.preserve_indent, Some(true));
+        assert_eq!(config.backup, Some(false));
+        assert_eq!(
+            config.extension_aliases,
+            vec![
+                ("tpl".to_string(), "py".to_string()),
+                ("H".to_string(), "cpp".to_string())
+            ]
+        );
+
+        let mut options = ToggleOptions::default();
+        config.apply(&mut options);
+        assert_eq!(options.indent_width, 2);
+        assert!(options.preserve_indent && options.no_backup);
+
+        assert_eq!(parse_project_config(""), Ok(ConfigLayer::default()));
+        for (text, error) in [
+            ("indent_width", ConfigError::Malformed { line: 1 }),
+            ("\n[extensions", ConfigError::Malformed { line: 2 }),
+            ("indent = 2", ConfigError::UnknownKey { line: 1 }),
+            ("[aliases]", ConfigError::UnknownKey { line: 1 }),
+            ("indent_width = 0", ConfigError::InvalidValue { line: 1 }),
+            ("indent_width = 17", ConfigError::InvalidValue { line: 1 }),
+            ("backup = \"no\"", ConfigError::InvalidValue { line: 1 }),
+            (
+                "[extensions]\ntpl = \"tpl\"",
+                ConfigError::InvalidValue { line: 2 },
+            ),
+            (
+                "backup = true\nbackup = false",
+                ConfigError::DuplicateKey { line: 2 },
+            ),
+            (
+                "[extensions]\na = \"py\"\nA = \"sh\"",
+                ConfigError::DuplicateKey { line: 3 },
+            ),
+        ] {
+            assert_eq!(parse_project_config(text), Err(error), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_presets() {
+        for (name, settings) in BUILTIN_PRESETS {
+            assert!(parse_project_config(settings).is_ok(), "{}", name);
+        }
+        let kr = find_preset("k&r", None).unwrap();
+        assert_eq!(kr.line_style, Some(LineStyle::C89));
+        assert_eq!(kr.skip_blank, Some(true));
+        assert_eq!(find_preset("gofmt", None).unwrap().indent_tab, Some(true));
+        assert_eq!(find_preset("gnu", None), None);
+
+        // A project table of the same name wins; presets are not applied
+        let config = parse_project_config(
+            "indent_width = 2\n\
+             [presets.\"k&r\"]\n\
+             indent_width = 8\n\
+             [presets.team]\n\
+             skip_blank = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            find_preset("k&r", Some(&config)).unwrap().indent_width,
+            Some(8)
+        );
+        assert_eq!(
+            find_preset("team", Some(&config)).unwrap().skip_blank,
+            Some(true)
+        );
+        let mut options = ToggleOptions::default();
+        config.apply(&mut options);
+        assert_eq!(options.indent_width, 2);
+        assert!(!options.skip_blank);
+
+        for (text, error) in [
+            ("[presets.]", ConfigError::Malformed { line: 1 }),
+            ("[presets.\"a\\b\"]", ConfigError::Malformed { line: 1 }),
+            (
+                "[presets.a]\n[presets.a]",
+                ConfigError::DuplicateKey { line: 2 },
+            ),
+            (
+                "[presets.a]\nstyle = \"ansi\"",
+                ConfigError::InvalidValue { line: 2 },
+            ),
+            (
+                "[presets.a]\nindent = 2",
+                ConfigError::UnknownKey { line: 2 },
+            ),
+        ] {
+            assert_eq!(parse_project_config(text), Err(error), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_env_config_precedence() {
+        let env = |pairs: &'static [(&'static str, &'static str)]| {
+            env_config(move |name| {
+                pairs
+                    .iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+
+        let layer = env(&[
+            ("TOGGLE_COMMENT_INDENT_WIDTH", "8"),
+            ("TOGGLE_COMMENT_NO_BACKUP", "1"),
+            ("TOGGLE_COMMENT_PRESERVE_INDENT", ""),
+            ("TOGGLE_COMMENT_BACKUP_DIR", "src"),
+        ])
+        .unwrap();
+        assert_eq!(layer.indent_width, Some(8));
+        assert_eq!(layer.backup, Some(false));
+        assert_eq!(layer.preserve_indent, None);
+        assert_eq!(layer.backup_dir, Some(PathBuf::from("src")));
+
+        // Environment over project config over defaults
+        let project = ConfigLayer {
+            indent_width: Some(2),
+            preserve_indent: Some(true),
+            ..ConfigLayer::default()
+        };
+        let options = resolve_options(Some(&project), &layer);
+        assert_eq!(options.indent_width, 8);
+        assert!(options.preserve_indent && options.no_backup);
+        assert_eq!(
+            resolve_options(None, &ConfigLayer::default()),
+            ToggleOptions::default()
+        );
+
+        for (pairs, name) in [
+            (
+                &[("TOGGLE_COMMENT_INDENT_WIDTH", "0")] as &'static [_],
+                "TOGGLE_COMMENT_INDENT_WIDTH",
+            ),
+            (
+                &[("TOGGLE_COMMENT_NO_BACKUP", "yes")],
+                "TOGGLE_COMMENT_NO_BACKUP",
+            ),
+            (
+                &[("TOGGLE_COMMENT_BACKUP_DIR", "no_such_dir")],
+                "TOGGLE_COMMENT_BACKUP_DIR",
+            ),
+        ] {
+            assert_eq!(env(pairs), Err(ConfigError::InvalidEnv { name }));
+        }
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let root = PathBuf::from("test_config_discovery");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::create_dir_all(root.join("repo/.git")).unwrap();
+        std::fs::create_dir_all(root.join("repo/src")).unwrap();
+        std::fs::write(root.join(CONFIG_FILE_NAME), "indent_width = 2\n").unwrap();
+
+        // Found two directories up, from a file that does not exist yet
+        let found = find_project_config(&root.join("a/b/x.py")).unwrap();
+        assert_eq!(found, root.canonicalize().unwrap().join(CONFIG_FILE_NAME));
+        assert_eq!(load_project_config(&found).unwrap().indent_width, Some(2));
+
+        // Not past a repository root
+        assert_eq!(find_project_config(&root.join("repo/src/x.py")), None);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,488 @@
+//! # ffi.rs
+//!
+//! C interface to `toggle_comment_indent_module` (cargo feature `ffi`)
+//!
+//! Editors written in C (or Lua via a C module) link the library instead of
+//! spawning the binary. Every function takes a NUL-terminated UTF-8 path and
+//! zero-indexed line numbers, and returns an `int`:
+//!
+//! - `0` on success
+//! - otherwise one of the `TC_ERR_*` codes - the same numbers the CLI uses
+//!   as exit codes, so both integrations read errors identically
+//!
+//! No panic crosses the boundary: each call runs under `catch_unwind` and a
+//! caught panic becomes `TC_ERR_INTERNAL`. The header `include/toggle_comment.h`
+//! is generated from the tables in this file (`c_header()`); a test fails when
+//! it is out of date.
+//!
+//! ## Build
+//! ```text
+//! cargo build --release --features ffi
+//! # target/release/libtoggle_comment_indent.{so,dylib,a} + include/toggle_comment.h
+//! ```
+
+use crate::toggle_comment_indent_module::{
+    ExitCode, ToggleCommentError, ToggleIndentError, indent_line_bytewise, indent_range_bytewise,
+    request_cancel, toggle_basic_singleline_comment_bytewise, toggle_block_comment_bytewise,
+    toggle_range_basic_comments_bytewise, toggle_range_rust_docstring_bytewise,
+    toggle_rust_docstring_singleline_comment_bytewise, unindent_line_bytewise,
+    unindent_range_bytewise,
+};
+use std::ffi::{CStr, c_char, c_int};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+// ============================================================================
+// ERROR CODES (the CLI exit codes, see ExitCode)
+// ============================================================================
+
+/// Success
+pub const TC_OK: c_int = ExitCode::Success.code();
+/// Null or non-UTF-8 path, or an invalid line number
+pub const TC_ERR_INVALID_ARGUMENT: c_int = ExitCode::InvalidArgument.code();
+/// File not found
+pub const TC_ERR_FILE_NOT_FOUND: c_int = ExitCode::FileNotFound.code();
+/// File has no extension
+pub const TC_ERR_NO_EXTENSION: c_int = ExitCode::NoExtension.code();
+/// Plain `.json` (no comment syntax), or `///` on a file that is not `.rs`
+pub const TC_ERR_UNSUPPORTED: c_int = ExitCode::Unsupported.code();
+/// Line (or cell, search text, block end) not found
+pub const TC_ERR_NOT_FOUND: c_int = ExitCode::NotFound.code();
+/// I/O error
+pub const TC_ERR_IO: c_int = ExitCode::Io.code();
+/// Path error
+pub const TC_ERR_PATH: c_int = ExitCode::Path.code();
+/// Reserved: never returned (lines of any length are streamed, not buffered)
+pub const TC_ERR_LINE_TOO_LONG: c_int = ExitCode::LineTooLong.code();
+/// Too many lines
+pub const TC_ERR_TOO_MANY_LINES: c_int = ExitCode::TooManyLines.code();
+/// Permission denied
+pub const TC_ERR_PERMISSION_DENIED: c_int = ExitCode::PermissionDenied.code();
+/// Incomplete write (original unchanged)
+pub const TC_ERR_INCOMPLETE_WRITE: c_int = ExitCode::IncompleteWrite.code();
+/// Target file is read-only
+pub const TC_ERR_READ_ONLY: c_int = ExitCode::ReadOnly.code();
+/// File larger than the size limit
+pub const TC_ERR_FILE_TOO_LARGE: c_int = ExitCode::FileTooLarge.code();
+/// Not a notebook, or the cell is not a code cell
+pub const TC_ERR_NOTEBOOK: c_int = ExitCode::Notebook.code();
+/// Line is in a `toggle_comment: off` ... `on` region
+pub const TC_ERR_PROTECTED_REGION: c_int = ExitCode::ProtectedRegion.code();
+/// Backup does not match its checksum
+pub const TC_ERR_BACKUP_INTEGRITY: c_int = ExitCode::BackupIntegrity.code();
+/// The file changed on disk during the operation (not overwritten)
+pub const TC_ERR_CONCURRENT_MODIFICATION: c_int = ExitCode::ConcurrentModification.code();
+/// Target is a FIFO, device node or socket
+pub const TC_ERR_NOT_REGULAR_FILE: c_int = ExitCode::NotRegularFile.code();
+/// Target is one of toggle_comment's own backups or temp files
+pub const TC_ERR_OWN_ARTIFACT: c_int = ExitCode::OwnArtifact.code();
+/// Not valid UTF-8 (utf8_strict)
+pub const TC_ERR_INVALID_UTF8: c_int = ExitCode::InvalidUtf8.code();
+/// A panic was caught at the boundary (a bug; please report)
+pub const TC_ERR_INTERNAL: c_int = ExitCode::Internal.code();
+/// Cancelled before the original was replaced (original unchanged)
+pub const TC_ERR_CANCELLED: c_int = ExitCode::Cancelled.code();
+
+/// `(name, value, description)` of every error code, for the header and
+/// `tc_error_message()`
+const ERROR_CODES: &[(&str, c_int, &CStr)] = &[
+    ("TC_OK", TC_OK, c"Success"),
+    (
+        "TC_ERR_INVALID_ARGUMENT",
+        TC_ERR_INVALID_ARGUMENT,
+        c"Invalid argument",
+    ),
+    (
+        "TC_ERR_FILE_NOT_FOUND",
+        TC_ERR_FILE_NOT_FOUND,
+        c"File not found",
+    ),
+    (
+        "TC_ERR_NO_EXTENSION",
+        TC_ERR_NO_EXTENSION,
+        c"No file extension",
+    ),
+    (
+        "TC_ERR_UNSUPPORTED",
+        TC_ERR_UNSUPPORTED,
+        c"Unsupported extension (plain .json)",
+    ),
+    ("TC_ERR_NOT_FOUND", TC_ERR_NOT_FOUND, c"Line not found"),
+    ("TC_ERR_IO", TC_ERR_IO, c"I/O error"),
+    ("TC_ERR_PATH", TC_ERR_PATH, c"Path error"),
+    (
+        "TC_ERR_LINE_TOO_LONG",
+        TC_ERR_LINE_TOO_LONG,
+        c"Line too long (joins, splits and style conversions)",
+    ),
+    (
+        "TC_ERR_TOO_MANY_LINES",
+        TC_ERR_TOO_MANY_LINES,
+        c"Too many lines",
+    ),
+    (
+        "TC_ERR_PERMISSION_DENIED",
+        TC_ERR_PERMISSION_DENIED,
+        c"Permission denied",
+    ),
+    (
+        "TC_ERR_INCOMPLETE_WRITE",
+        TC_ERR_INCOMPLETE_WRITE,
+        c"Incomplete write (original unchanged)",
+    ),
+    (
+        "TC_ERR_READ_ONLY",
+        TC_ERR_READ_ONLY,
+        c"Target file is read-only",
+    ),
+    (
+        "TC_ERR_FILE_TOO_LARGE",
+        TC_ERR_FILE_TOO_LARGE,
+        c"File too large",
+    ),
+    (
+        "TC_ERR_NOTEBOOK",
+        TC_ERR_NOTEBOOK,
+        c"Not a notebook, or not a code cell",
+    ),
+    (
+        "TC_ERR_PROTECTED_REGION",
+        TC_ERR_PROTECTED_REGION,
+        c"Line is in a protected region",
+    ),
+    (
+        "TC_ERR_BACKUP_INTEGRITY",
+        TC_ERR_BACKUP_INTEGRITY,
+        c"Backup does not match its checksum",
+    ),
+    (
+        "TC_ERR_CONCURRENT_MODIFICATION",
+        TC_ERR_CONCURRENT_MODIFICATION,
+        c"File changed on disk during the operation",
+    ),
+    (
+        "TC_ERR_NOT_REGULAR_FILE",
+        TC_ERR_NOT_REGULAR_FILE,
+        c"Not a regular file",
+    ),
+    (
+        "TC_ERR_OWN_ARTIFACT",
+        TC_ERR_OWN_ARTIFACT,
+        c"Target is a toggle_comment backup or temp file",
+    ),
+    (
+        "TC_ERR_INVALID_UTF8",
+        TC_ERR_INVALID_UTF8,
+        c"Not valid UTF-8",
+    ),
+    ("TC_ERR_INTERNAL", TC_ERR_INTERNAL, c"Internal error"),
+    (
+        "TC_ERR_CANCELLED",
+        TC_ERR_CANCELLED,
+        c"Cancelled (original unchanged)",
+    ),
+];
+
+/// `(doc comment, prototype)` of every exported function, for the header
+const FUNCTIONS: &[(&str, &str)] = &[
+    (
+        "Toggle the extension's line comment on one line",
+        "int tc_toggle_line(const char *path, size_t line);",
+    ),
+    (
+        "Toggle a Rust doc comment (///) on one line",
+        "int tc_toggle_docstring_line(const char *path, size_t line);",
+    ),
+    (
+        "Toggle line comments on start..=end (each line by its own state)",
+        "int tc_toggle_range(const char *path, size_t start, size_t end);",
+    ),
+    (
+        "Toggle Rust doc comments on start..=end",
+        "int tc_toggle_docstring_range(const char *path, size_t start, size_t end);",
+    ),
+    (
+        "Toggle block comment markers around start..=end",
+        "int tc_toggle_block(const char *path, size_t start, size_t end);",
+    ),
+    (
+        "Add 4 spaces at the start of one line",
+        "int tc_indent_line(const char *path, size_t line);",
+    ),
+    (
+        "Remove up to 4 leading spaces from one line",
+        "int tc_unindent_line(const char *path, size_t line);",
+    ),
+    (
+        "Indent start..=end",
+        "int tc_indent_range(const char *path, size_t start, size_t end);",
+    ),
+    (
+        "Unindent start..=end",
+        "int tc_unindent_range(const char *path, size_t start, size_t end);",
+    ),
+    (
+        "Static, NUL-terminated description of a code (never free it)",
+        "const char *tc_error_message(int code);",
+    ),
+    (
+        "Stop running and later operations before they replace a file (TC_ERR_CANCELLED); async-signal-safe",
+        "void tc_request_cancel(void);",
+    ),
+];
+
+/// Map a comment error to its stable code (`ToggleCommentError::exit_code()`)
+pub fn comment_error_code(error: ToggleCommentError) -> c_int {
+    error.exit_code().code()
+}
+
+/// Map an indent error to its stable code (`ToggleIndentError::exit_code()`)
+pub fn indent_error_code(error: ToggleIndentError) -> c_int {
+    error.exit_code().code()
+}
+
+/// Convert a C path, run `operation` under `catch_unwind`, map the result
+///
+/// # Safety
+/// `path` must be null or point to a NUL-terminated string valid for the call
+unsafe fn run_with_path<F>(path: *const c_char, operation: F) -> c_int
+where
+    F: FnOnce(&str) -> c_int,
+{
+    if path.is_null() {
+        return TC_ERR_INVALID_ARGUMENT;
+    }
+    // SAFETY: non-null, NUL-terminated per the caller contract
+    let path = match unsafe { CStr::from_ptr(path) }.to_str() {
+        Ok(p) => p,
+        Err(_) => return TC_ERR_INVALID_ARGUMENT,
+    };
+
+    catch_unwind(AssertUnwindSafe(|| operation(path))).unwrap_or(TC_ERR_INTERNAL)
+}
+
+fn comment_code(result: Result<(), ToggleCommentError>) -> c_int {
+    match result {
+        Ok(()) => TC_OK,
+        Err(e) => comment_error_code(e),
+    }
+}
+
+fn indent_code(result: Result<(), ToggleIndentError>) -> c_int {
+    match result {
+        Ok(()) => TC_OK,
+        Err(e) => indent_error_code(e),
+    }
+}
+
+/// Toggle the extension's line comment on one line
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string valid for the call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tc_toggle_line(path: *const c_char, line: usize) -> c_int {
+    unsafe {
+        run_with_path(path, |p| {
+            comment_code(toggle_basic_singleline_comment_bytewise(p, line))
+        })
+    }
+}
+
+/// Toggle a Rust doc comment (`///`) on one line
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string valid for the call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tc_toggle_docstring_line(path: *const c_char, line: usize) -> c_int {
+    unsafe {
+        run_with_path(path, |p| {
+            comment_code(toggle_rust_docstring_singleline_comment_bytewise(p, line))
+        })
+    }
+}
+
+/// Toggle line comments on `start..=end`
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string valid for the call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tc_toggle_range(path: *const c_char, start: usize, end: usize) -> c_int {
+    unsafe {
+        run_with_path(path, |p| {
+            comment_code(toggle_range_basic_comments_bytewise(p, start, end))
+        })
+    }
+}
+
+/// Toggle Rust doc comments on `start..=end`
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string valid for the call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tc_toggle_docstring_range(
+    path: *const c_char,
+    start: usize,
+    end: usize,
+) -> c_int {
+    unsafe {
+        run_with_path(path, |p| {
+            comment_code(toggle_range_rust_docstring_bytewise(p, start, end))
+        })
+    }
+}
+
+/// Toggle block comment markers around `start..=end`
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string valid for the call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tc_toggle_block(path: *const c_char, start: usize, end: usize) -> c_int {
+    unsafe {
+        run_with_path(path, |p| {
+            comment_code(toggle_block_comment_bytewise(p, start, end))
+        })
+    }
+}
+
+/// Add 4 spaces at the start of one line
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string valid for the call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tc_indent_line(path: *const c_char, line: usize) -> c_int {
+    unsafe { run_with_path(path, |p| indent_code(indent_line_bytewise(p, line))) }
+}
+
+/// Remove up to 4 leading spaces from one line
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string valid for the call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tc_unindent_line(path: *const c_char, line: usize) -> c_int {
+    unsafe { run_with_path(path, |p| indent_code(unindent_line_bytewise(p, line))) }
+}
+
+/// Indent `start..=end`
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string valid for the call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tc_indent_range(path: *const c_char, start: usize, end: usize) -> c_int {
+    unsafe { run_with_path(path, |p| indent_code(indent_range_bytewise(p, start, end))) }
+}
+
+/// Unindent `start..=end`
+///
+/// # Safety
+/// `path` must be null or a NUL-terminated string valid for the call
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tc_unindent_range(path: *const c_char, start: usize, end: usize) -> c_int {
+    unsafe {
+        run_with_path(path, |p| {
+            indent_code(unindent_range_bytewise(p, start, end))
+        })
+    }
+}
+
+/// Static, NUL-terminated description of a code (unknown codes included)
+#[unsafe(no_mangle)]
+pub extern "C" fn tc_error_message(code: c_int) -> *const c_char {
+    let message = ERROR_CODES
+        .iter()
+        .find(|(_, value, _)| *value == code)
+        .map_or(c"Unknown error code", |(_, _, description)| *description);
+    message.as_ptr()
+}
+
+/// Ask operations to stop (see `request_cancel()`); callable from a signal handler
+#[unsafe(no_mangle)]
+pub extern "C" fn tc_request_cancel() {
+    request_cancel();
+}
+
+/// Render `include/toggle_comment.h` from the tables above
+pub fn c_header() -> String {
+    let mut header = String::new();
+    header.push_str(
+        "/* toggle_comment.h - generated from src/ffi.rs by c_header(); do not edit */\n",
+    );
+    header.push_str("#ifndef TOGGLE_COMMENT_H\n#define TOGGLE_COMMENT_H\n\n");
+    header.push_str("#include <stddef.h>\n\n");
+    header.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+    header.push_str("/* Return codes (same numbers as the CLI exit codes) */\n");
+    for (name, value, description) in ERROR_CODES {
+        header.push_str(&format!(
+            "#define {} {} /* {} */\n",
+            name,
+            value,
+            description.to_string_lossy()
+        ));
+    }
+    header.push_str("\n/* Paths: NUL-terminated UTF-8. Lines: zero-indexed. */\n");
+    for (doc, prototype) in FUNCTIONS {
+        header.push_str(&format!("\n/* {} */\n{}\n", doc, prototype));
+    }
+    header.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n#endif /* TOGGLE_COMMENT_H */\n");
+    header
+}
+
+#[cfg(test)]
+mod ffi_tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_header_is_current() {
+        let header_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("include/toggle_comment.h");
+        let generated = c_header();
+
+        // Regenerate: TC_REGENERATE_HEADER=1 cargo test --features ffi
+        if std::env::var_os("TC_REGENERATE_HEADER").is_some() {
+            std::fs::write(&header_path, &generated).unwrap();
+        }
+        let shipped = std::fs::read_to_string(&header_path).unwrap_or_default();
+        assert_eq!(
+            shipped, generated,
+            "include/toggle_comment.h is out of date"
+        );
+
+        // Messages come from the same table; unknown codes still get one
+        // SAFETY: tc_error_message returns a static NUL-terminated string
+        let message = unsafe { CStr::from_ptr(tc_error_message(TC_ERR_READ_ONLY)) };
+        assert_eq!(message, c"Target file is read-only");
+        let message = unsafe { CStr::from_ptr(tc_error_message(-7)) };
+        assert_eq!(message, c"Unknown error code");
+
+        // Every exit code but TimedOut (a C caller sees TC_ERR_CANCELLED)
+        for code in ExitCode::ALL {
+            let listed = ERROR_CODES
+                .iter()
+                .any(|(_, value, _)| *value == code.code());
+            assert_eq!(listed, code != ExitCode::TimedOut, "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn test_ffi_calls() {
+        let path = "test_ffi_toggle.py";
+        std::fs::write(path, "a\nb\n").unwrap();
+        let c_path = c"test_ffi_toggle.py";
+
+        // SAFETY: valid NUL-terminated path / null pointer
+        unsafe {
+            assert_eq!(tc_toggle_line(c_path.as_ptr(), 1), TC_OK);
+            assert_eq!(tc_toggle_line(c_path.as_ptr(), 9), TC_ERR_NOT_FOUND);
+            assert_eq!(tc_indent_line(c_path.as_ptr(), 0), TC_OK);
+            assert_eq!(tc_toggle_line(std::ptr::null(), 0), TC_ERR_INVALID_ARGUMENT);
+            assert_eq!(
+                tc_toggle_line(c"missing_ffi.py".as_ptr(), 0),
+                TC_ERR_FILE_NOT_FOUND
+            );
+        }
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "    a\n# b\n");
+
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file("backup_toggle_comment_test_ffi_toggle.py");
+        let _ = std::fs::remove_file("backup_toggle_comment_test_ffi_toggle.py.sha");
+    }
+}
@@ -0,0 +1,42 @@
+//! # toggle_comment_indent
+//!
+//! Library target for `toggle_comment_indent_module.rs`.
+//!
+//! The module is self-contained and can still be copied into another project
+//! as-is; this target exposes the same module to Rust callers (and to the
+//! `toggle_comment` command-line binary in `main.rs`) as a normal crate.
+//!
+//! File operations need the default `fs` feature; without it only the
+//! in-memory buffer API (`toggle_lines_in_buffer`) is built, e.g. for wasm.
+//!
+//! `cli` is the declarative mode table parser of the binary, `porcelain`
+//! defines its `--porcelain` record format, `completions` its shell
+//! completion scripts (`--completions`), and
+//! `serve` its JSON-RPC command server (`--serve`). `patch` runs an operation
+//! on a preview copy and prints the planned changes (`--emit-sed`,
+//! `--emit-patch`) and applies such patches (`--apply-patch`). `tui` is the
+//! interactive line picker behind `--tui`. `config` finds and reads the
+//! per-project `.toggle_comment.toml` defaults.
+//!
+//! With the `ffi` feature, `ffi` exposes the main operations as `extern "C"`
+//! functions (header: `include/toggle_comment.h`).
+
+pub mod cli;
+pub mod completions;
+pub mod porcelain;
+pub mod toggle_comment_indent_module;
+
+#[cfg(feature = "fs")]
+pub mod config;
+
+#[cfg(feature = "fs")]
+pub mod patch;
+
+#[cfg(feature = "fs")]
+pub mod serve;
+
+#[cfg(feature = "fs")]
+pub mod tui;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
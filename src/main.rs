@@ -19,6 +19,11 @@
 //! toggle_comment --block <file_path> <start_line> <end_line>
 //! ```
 //!
+//! ## Perl POD block toggle (insert/remove =pod / =cut at column 0, .pl / .pm)
+//! ```text
+//! toggle_comment --pod <file_path> <start_line> <end_line>
+//! ```
+//!
 //! ## Batch toggle - basic comments
 //! ```text
 //! toggle_comment --list-basic <file_path> <line1> <line2> ... <lineN>
@@ -28,36 +33,433 @@
 //! ```text
 //! toggle_comment --list-docstring <file_path> <line1> <line2> ... <lineN>
 //! ```
+//!
+//! ## Batch toggle - several inclusive ranges, one backup and one pass (N-M or N; $ = last line)
+//! ```text
+//! toggle_comment --ranges <file_path> <range1> <range2> ... <rangeN>
+//! ```
+//!
+//! ## Batch toggle - line numbers from stdin (one per line)
+//! ```text
+//! toggle_comment --list-basic <file_path> --stdin-lines [max_lines]
+//! toggle_comment --list-docstring <file_path> --stdin-lines [max_lines]
+//! ```
+//!
+//! ## Stdin content - toggle one line of a document piped through (no file, no backup)
+//! ```text
+//! toggle_comment --stdin-content --line <line_number> (--ext <ext> | --custom-flag <flag>)
+//! ```
+//!
+//! ## Jupyter notebook - toggle `# ` on a line of a code cell
+//! ```text
+//! toggle_comment --notebook <file.ipynb> <cell_index> <line_in_cell>
+//! ```
+//!
+//! ## Content-anchored toggle - first (or nth, zero-indexed) line containing a literal
+//! ```text
+//! toggle_comment --at-line-containing <literal> <file_path> [--nth <k>]
+//! ```
+//!
+//! ## Brace block - comment out the whole `{ ... }` block starting at a line
+//! ```text
+//! toggle_comment --block-from <file_path> <line_number>
+//! ```
+//!
+//! ## Python suite - comment out a `def` / `class` line and its indented suite
+//! ```text
+//! toggle_comment --suite-from <file.py> <line_number>
+//! ```
+//!
+//! ## Isolate - comment every line outside a range (--undo-isolate undoes it)
+//! ```text
+//! toggle_comment --isolate <file_path> <start_line> <end_line>
+//! toggle_comment --undo-isolate <file_path> <start_line> <end_line>
+//! ```
+//!
+//! ## Rust attribute - toggle an attribute line above an item (`#[cfg(test)]`, `#[ignore]`)
+//! ```text
+//! toggle_comment --attribute <file.rs> <line_number> <attribute>
+//! ```
+//!
+//! ## Whole lines - insert a line, or remove one holding the expected text
+//! ```text
+//! toggle_comment --insert-line <file_path> <line_number> <content>
+//! toggle_comment --remove-line <file_path> <line_number> <expected_content>
+//! toggle_comment --duplicate <file_path> <line_number>
+//! toggle_comment --move <file_path> <from_line> <to_line>
+//! toggle_comment --join <file_path> <line_number>
+//! toggle_comment --split <file_path> <line_number> <byte_col>
+//! ```
+//!
+//! ## Convert style - line comment to one-line block comment, or back
+//! ```text
+//! toggle_comment --convert-style <file_path> <line_number> --to block|line
+//! toggle_comment --convert-style-file <file_path> --to line|block
+//! ```
+//!
+//! ## Strip comments - remove every full-line comment (in place, or into --output)
+//! ```text
+//! toggle_comment --strip-comments <file_path> [--output <path>]
+//! ```
+//!
+//! ## LSP range - toggle basic comments on the lines an LSP Range covers
+//! ```text
+//! toggle_comment --lsp-range <file_path> <startLine> <startChar> <endLine> <endChar>
+//! ```
+//!
+//! ## Detect - print line endings, final newline, indentation and comment flag (no edit)
+//! ```text
+//! toggle_comment --detect <file_path>
+//! ```
+//!
+//! ## Column - print the byte column where a toggle of the line puts the flag (no edit)
+//! ```text
+//! toggle_comment --column <file_path> <line_number>
+//! ```
+//!
+//! ## Relative - print anchor_line + offset (+N / -N), checked against the file (no edit)
+//! ```text
+//! toggle_comment --relative <file_path> <anchor_line> <offset>
+//! ```
+//!
+//! ## Lint comments - report runs of commented-out lines (dead code blocks, no edit)
+//! ```text
+//! toggle_comment --lint-comments <file_path> [max_run]
+//! ```
+//!
+//! ## Query range - comment state per line: C commented, . code, B blank (no edit)
+//! ```text
+//! toggle_comment --query-range <file_path> <start_line> <end_line> [--json]
+//! ```
+//!
+//! ## Query lines - state, indentation and flag of many lines / ranges at once (no edit)
+//! ```text
+//! toggle_comment --query-lines <file_path> <line|start-end>...
+//! ```
+//!
+//! ## Inspect - length, leading spaces / tabs, flag and CRLF of one line (no edit)
+//! ```text
+//! toggle_comment --inspect <file_path> <line_number>
+//! ```
+//!
+//! ## Grep output - toggle every `file:line:` entry (grep -n / rg -n) read from stdin
+//! ```text
+//! rg -n "unwrap()" | toggle_comment --grep-format [--comment | --uncomment]
+//! ```
+//!
+//! ## Serve - JSON-RPC command server on stdin/stdout (Content-Length framing)
+//! ```text
+//! toggle_comment --serve
+//! ```
+//!
+//! ## TUI - pick lines in the terminal, queue comment / indent edits, apply on exit
+//! ```text
+//! toggle_comment --tui <file_path>
+//! ```
+//!
+//! ## Apply patch - apply a `--emit-patch` diff (comment / indent changes only)
+//! ```text
+//! toggle_comment --apply-patch <patch_file>
+//! ```
+//!
+//! ## Verify only - check that a file differs from its backup only in comments / indentation
+//! ```text
+//! toggle_comment --verify-only <file_path>
+//! ```
+//!
+//! ## Undo - restore the last backup, verified against its `.sha` checksum
+//! ```text
+//! toggle_comment --undo <file_path>
+//! ```
+//!
+//! ## Undo session - restore all files a `--grep-format` run changed
+//! ```text
+//! toggle_comment --undo-session .toggle_comment_backup_<ts>
+//! ```
+//!
+//! ## Limits - bounds compiled into this binary
+//! ```text
+//! toggle_comment --limits
+//! ```
+//!
+//! ## Capabilities - modes (with their argument forms), options, extensions and limits as key: value lines
+//! ```text
+//! toggle_comment --capabilities
+//! ```
+//!
+//! ## Completions - shell completion script from the same mode / option tables
+//! ```text
+//! toggle_comment --completions bash|zsh|fish
+//! ```
+//!
+//! ## Clean temp - remove temp files left behind by crashed runs
+//! ```text
+//! toggle_comment --clean-temp [dir] [--dry-run]
+//! ```
+//!
+//! ## Line addresses (any line-number argument, except notebook / LSP modes)
+//! ```text
+//! $      last line            toggle_comment main.rs '$'
+//! N,M    two arguments N M    toggle_comment --indent-range main.rs 10,$
+//! %      whole file (0 $)     toggle_comment --toggle-range-comment-basic main.rs %
+//! ```
+//!
+//! ## Global options (any position, any mode)
+//! ```text
+//! --force-readonly           clear a read-only attribute for the edit, restore it afterwards
+//! --force                    edit a file named like this tool's own backup or temp files
+//! --allow-any-extension      let the /// (Rust doc comment) modes edit files that are not .rs
+//! --allow-heredoc            let comment toggles of .sh / .bash files edit here-doc bodies
+//! --max-file-size <bytes>    refuse larger files (default 512 MB)
+//! --temp-dir <dir>           write intermediate files there (e.g. /dev/shm), rename into place
+//!                            (backups become hard links instead of copies)
+//! --durability none|flush|fsync  no fsync / fsync the temp (default) / also the
+//!                            replaced file or its directory
+//! --no-size-limit            disable the file size check
+//! --preserve-indent          put the flag after leading spaces; indent added block markers
+//! --relaxed-removal          uncomment flags without (or with extra) spaces; not reversible
+//! --trace / --trace-json    report each stage (backup, write, verify, replace) on stderr
+//!                            (warnings are always printed there; JSON with --trace-json)
+//! --normalize-eol lf|crlf    convert all line endings during a comment toggle
+//! --ensure-final-newline     append a missing final newline during a comment toggle
+//! --strip-final-newline      remove the final newline during a comment toggle
+//! --trim-trailing            strip trailing spaces/tabs from the edited lines only
+//! --style c89|default        c89: line toggles in .c/.h wrap lines as /* line */
+//! --doc-style doxygen|qt|jsdoc  line toggles write ///< / //!< (jsdoc: //), block
+//!                            toggles /** */ / /*! */ / /** */ (/* */ languages)
+//! --eol-audit                report each edited line's ending before/after on stderr
+//! --progress                 percentage on stderr while streaming large files
+//! --timeout <seconds>        stop cleanly when an operation runs longer (exit 124)
+//! --comment / --uncomment    line toggles only add / only remove the flag
+//! --porcelain                versioned tab-separated records on stdout (see porcelain.rs)
+//! --emit-sed                 edit nothing; print the changes as a sed script (see patch.rs)
+//! --emit-patch               edit nothing; print the changes as a unified diff (git apply)
+//! --preview                  page the changes as a unified diff and ask before writing
+//! --no-config                ignore .toggle_comment.toml
+//! --preset <name>            python-black, k&r, google, gofmt, or [presets.<name>] of the
+//!                            config: indent, preserve-indent, skip-blank and style at once
+//! --skip-blank               line toggles leave empty lines as they are
+//! --utf8-strict              refuse a file that is not valid UTF-8 (exit 20)
+//! --indent-width <n>         spaces per indent / unindent step (default 4, at most 16;
+//!                            Makefiles always use one tab)
+//! --indent-tab               indent / unindent one tab instead of spaces (gofmt)
+//! --no-backup                write no backup_toggle_comment_* copy
+//! --backup-dir <dir>         write backups (and look for --undo's) in <dir>
+//! ```
+//!
+//! ## Project config (see config.rs)
+//! The nearest `.toggle_comment.toml` in the target's directory or above
+//! (not past a `.git` directory) sets defaults; flags still override them:
+//! ```text
+//! indent_width = 2       preserve_indent = true       backup = false
+//! skip_blank = true      style = "c89"                indent_tab = false
+//! [extensions]
+//! tpl = "py"
+//! ```
+//!
+//! ## Environment (see config.rs)
+//! ```text
+//! TOGGLE_COMMENT_INDENT_WIDTH  TOGGLE_COMMENT_PRESERVE_INDENT
+//! TOGGLE_COMMENT_NO_BACKUP     TOGGLE_COMMENT_BACKUP_DIR
+//! ```
+//! Precedence: flags > environment > project config > built-in defaults.
 
 use std::env;
+use std::fmt::Display;
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::Path;
 use std::process;
-mod toggle_comment_indent_module;
-use toggle_comment_indent_module::{
-    ToggleCommentError, ToggleIndentError, indent_line_bytewise, indent_range_bytewise,
-    toggle_basic_singleline_comment_bytewise, toggle_block_comment_bytewise,
-    toggle_range_basic_comments_bytewise, toggle_range_rust_docstring_bytewise,
-    toggle_rust_docstring_singleline_comment_bytewise, unindent_line_bytewise,
-    unindent_range_bytewise,
+use std::time::Duration;
+use toggle_comment_indent::cli::{Arg, ArgKind, BASIC_MODE, Mode, ModeArgs, ParseError, find_mode};
+use toggle_comment_indent::completions::{Shell, completion_script};
+use toggle_comment_indent::config::{
+    BUILTIN_PRESETS, CONFIG_FILE_NAME, ConfigLayer, env_config, find_preset, find_project_config,
+    load_project_config, resolve_options,
 };
+use toggle_comment_indent::patch::{PatchError, PreviewCopy, apply_patch, verify_against_backup};
+use toggle_comment_indent::porcelain::{PORCELAIN_VERSION, format_record};
+use toggle_comment_indent::serve::serve;
+use toggle_comment_indent::toggle_comment_indent_module::{
+    BackupSession, CommentFlag, CommentStyle, DEFAULT_MAX_COMMENTED_RUN,
+    DEFAULT_MAX_FILE_SIZE_BYTES, DEFAULT_MAX_STREAMED_LINES, DocStyle, Durability, EolMode,
+    ExitCode, FinalNewline, LineStyle, LineToggleDirection, MAX_BATCH_LINES, MAX_INDENT_WIDTH,
+    MAX_RANGES, MAX_TOTAL_BATCH_LINES, PROGRESS_INTERVAL_MILLIS, PROGRESS_MIN_FILE_BYTES,
+    RemovalMode, SUPPORTED_EXTENSIONS, ToggleCommentError, ToggleIndentError, ToggleOptions,
+    TraceFormat, cancel_requested, clear_readonly, comment_column_in_file, comment_states_in_range,
+    convert_comment_style_file_with_options, convert_comment_style_with_options, detect_file_style,
+    display_path, duplicate_line_with_options, extension_syntax, find_commented_runs,
+    find_line_containing, find_orphan_temp_files, indent_line_bytewise_with_options,
+    indent_range_bytewise_with_options, insert_line_with_options, inspect_line, inspect_lines,
+    is_own_artifact, join_line_with_options, last_line_index, limits, move_line_with_options,
+    remove_line_with_options, restore_backup, restore_permissions, set_deadline,
+    split_line_with_options, strip_line_comments_with_options, take_warnings, timed_out,
+    toggle_basic_singleline_comment_bytewise_with_options,
+    toggle_block_comment_bytewise_with_options, toggle_brace_block_bytewise_with_options,
+    toggle_isolate_range_bytewise_with_options, toggle_lines_in_buffer,
+    toggle_lines_in_buffer_with_flag, toggle_multiple_basic_comments_from_reader_with_options,
+    toggle_multiple_basic_comments_with_options,
+    toggle_multiple_singleline_docstrings_from_reader_with_options,
+    toggle_multiple_singleline_docstrings_with_options,
+    toggle_notebook_cell_line_bytewise_with_options, toggle_pod_block_bytewise_with_options,
+    toggle_python_suite_bytewise_with_options, toggle_range_basic_comments_bytewise_with_options,
+    toggle_range_rust_docstring_bytewise_with_options, toggle_ranges_basic_comments_with_options,
+    toggle_rust_attribute_bytewise_with_options,
+    toggle_rust_docstring_singleline_comment_bytewise_with_options,
+    undo_isolate_range_bytewise_with_options, undo_session, unindent_line_bytewise_with_options,
+    unindent_range_bytewise_with_options, write_warnings,
+};
+use toggle_comment_indent::tui::{TuiEdit, run_tui};
+
+/// Process exit status for an exit code (`Cancelled` after the --timeout
+/// budget ran out is `TimedOut`)
+fn exit_status(code: ExitCode) -> i32 {
+    if code == ExitCode::Cancelled && timed_out() {
+        ExitCode::TimedOut.code()
+    } else {
+        code.code()
+    }
+}
+
+/// Exit code for `Cancelled`: a timeout or an interrupt
+fn cancelled_exit_code() -> i32 {
+    exit_status(ExitCode::Cancelled)
+}
+
+// ============================================================================
+// INTERRUPT HANDLING
+// ============================================================================
+//
+// std has no signal API and the module itself stays free of unsafe code, so
+// this is the one place outside ffi.rs that declares foreign functions: the
+// handler registration. The handler only calls `request_cancel()` (one
+// atomic swap, async-signal-safe); the running operation then stops at its
+// next byte, removes its temp file and leaves the original untouched. A
+// second interrupt exits at once (e.g. while waiting on stdin).
+// ============================================================================
+
+#[cfg(unix)]
+mod interrupt {
+    use std::ffi::c_int;
+    use toggle_comment_indent::toggle_comment_indent_module::request_cancel;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn _exit(status: c_int) -> !;
+    }
+
+    extern "C" fn on_signal(_signum: c_int) {
+        if request_cancel() {
+            // SAFETY: _exit is async-signal-safe and takes no pointers
+            unsafe { _exit(super::ExitCode::Cancelled.code()) }
+        }
+    }
+
+    /// Route SIGINT and SIGTERM to `request_cancel()`
+    pub fn install() {
+        // SAFETY: `on_signal` has the C handler signature and only performs
+        // async-signal-safe work
+        unsafe {
+            signal(SIGINT, on_signal);
+            signal(SIGTERM, on_signal);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod interrupt {
+    use toggle_comment_indent::toggle_comment_indent_module::request_cancel;
 
-/// Maximum number of lines that can be toggled in batch mode
-/// Prevents unbounded memory usage while still being practical
-const MAX_BATCH_LINES: usize = 512;
+    unsafe extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    /// Returns TRUE (handled) for the first event; the second falls through
+    /// to the default handler, which ends the process
+    extern "system" fn on_console_event(_event: u32) -> i32 {
+        if request_cancel() { 0 } else { 1 }
+    }
+
+    /// Route Ctrl-C, Ctrl-Break and console close to `request_cancel()`
+    pub fn install() {
+        // SAFETY: `on_console_event` has the HandlerRoutine signature
+        unsafe {
+            SetConsoleCtrlHandler(on_console_event, 1);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod interrupt {
+    /// No signals on this target: interrupts keep their default behaviour
+    pub fn install() {}
+}
+
+/// Size and raw mode of the terminal on stdin for `--tui`, through `stty`
+#[cfg(unix)]
+mod terminal {
+    use std::process::{Command, Stdio};
+
+    /// Run stty on the terminal; its stdout if it succeeded
+    fn stty(args: &[&str]) -> Option<String> {
+        let output = Command::new("stty")
+            .args(args)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8(output.stdout).ok()
+    }
+
+    /// `(rows, columns)`; None when unknown (some ptys report `0 0`)
+    pub fn size() -> Option<(usize, usize)> {
+        let size = stty(&["size"])?;
+        let mut parts = size.split_whitespace().map(|part| part.parse().ok());
+        let (rows, columns): (usize, usize) = (parts.next()??, parts.next()??);
+        (rows > 0 && columns > 0).then_some((rows, columns))
+    }
+
+    /// Switch to raw mode without echo; returns the settings to restore
+    pub fn enter_raw() -> Option<String> {
+        let saved = stty(&["-g"])?.trim().to_string();
+        stty(&["raw", "-echo"])?;
+        Some(saved)
+    }
+
+    pub fn restore(saved: &str) {
+        let _ = stty(&[saved]);
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    /// No stty on this target: `--tui` is refused
+    pub fn size() -> Option<(usize, usize)> {
+        None
+    }
+
+    pub fn enter_raw() -> Option<String> {
+        None
+    }
+
+    pub fn restore(_saved: &str) {}
+}
 
 /// Print comprehensive usage information and exit
 fn print_usage() {
     eprintln!("toggle_comment - Toggle comments in source code files");
     eprintln!();
     eprintln!("USAGE:");
-    eprintln!("  toggle_comment <file_path> <line_number>");
-    eprintln!("  toggle_comment --rust-doc-string <file_path> <line_number>");
-    eprintln!("  toggle_comment --block <file_path> <start_line> <end_line>");
-    eprintln!("  toggle_comment --list-basic <file_path> <line1> <line2> ...");
-    eprintln!("  toggle_comment --list-docstring <file_path> <line1> <line2> ...");
-    eprintln!("  toggle_comment --indent <file_path> <line_number>");
-    eprintln!("  toggle_comment --unindent <file_path> <line_number>");
-    eprintln!("  toggle_comment --indent-range <file_path> <start_line> <end_line>");
-    eprintln!("  toggle_comment --unindent-range <file_path> <start_line> <end_line>");
+    for mode in MODES {
+        for line in mode.usage("toggle_comment") {
+            eprintln!("  {}", line);
+        }
+    }
     eprintln!();
 
     eprintln!("MODES:");
@@ -72,14 +474,58 @@ fn print_usage() {
     eprintln!("    Toggles block comments around a range of lines");
     eprintln!("    Inserts /* before start_line and */ after end_line (or removes them)");
     eprintln!("    For Python: uses \"\"\" instead");
+    eprintln!(
+        "    start_line == end_line wraps one line; repeating it on the wrapped line unwraps it"
+    );
+    eprintln!("    start_line and end_line may be given in either order");
+    eprintln!();
+    eprintln!("  --pod:");
+    eprintln!("    --block for Perl documentation (.pl / .pm files): inserts =pod before");
+    eprintln!("    start_line and =cut after end_line at column 0 (or removes them)");
+    eprintln!("    Refuses a range holding =pod / =cut, or starting inside a POD block");
     eprintln!();
     eprintln!("  --list-basic:");
     eprintln!("    Toggle basic comments on multiple lines in one operation");
-    eprintln!("    Maximum {} lines per batch", MAX_BATCH_LINES);
+    eprintln!(
+        "    {} lines per pass, one backup for the whole list",
+        MAX_BATCH_LINES
+    );
     eprintln!();
     eprintln!("  --list-docstring:");
     eprintln!("    Toggle /// comments on multiple lines in one operation");
-    eprintln!("    Maximum {} lines per batch", MAX_BATCH_LINES);
+    eprintln!(
+        "    {} lines per pass, one backup for the whole list",
+        MAX_BATCH_LINES
+    );
+    eprintln!();
+    eprintln!("  --ranges:");
+    eprintln!("    Toggle basic comments on every line of several inclusive ranges (N-M,");
+    eprintln!(
+        "    or N; $ is the last line) in one pass and one backup; up to {} ranges,",
+        MAX_RANGES
+    );
+    eprintln!("    which must not overlap (exit 1)");
+    eprintln!();
+    eprintln!("  --stdin-content:");
+    eprintln!("    Read a whole document from stdin, toggle one line, print it on stdout;");
+    eprintln!(
+        "    the flag comes from --ext <ext> (e.g. py) or --custom-flag (// # /// <!-- /* ///< //!<)"
+    );
+    eprintln!(
+        "    No temp files or backups; input is limited to {} MB (and --max-file-size)",
+        MAX_STDIN_CONTENT_BYTES / (1024 * 1024)
+    );
+    eprintln!();
+    eprintln!("  --stdin-lines [max_lines]:");
+    eprintln!("    For --list-basic/--list-docstring: read line numbers from stdin");
+    eprintln!(
+        "    One number per line, applied in chunks of {}",
+        MAX_BATCH_LINES
+    );
+    eprintln!(
+        "    Accepts up to max_lines numbers (default {}, ceiling {})",
+        DEFAULT_MAX_STREAMED_LINES, MAX_TOTAL_BATCH_LINES
+    );
     eprintln!();
     eprintln!("  --indent:");
     eprintln!("    Add 4 spaces to the start of a line");
@@ -92,6 +538,262 @@ fn print_usage() {
     eprintln!("  --unindent-range:");
     eprintln!("    Remove up to 4 spaces from multiple lines (inclusive range)");
     eprintln!();
+    eprintln!("  --notebook:");
+    eprintln!("    Toggle # on one source line of a Jupyter notebook code cell");
+    eprintln!("    (cell and line zero-indexed; JSON layout otherwise untouched)");
+    eprintln!();
+    eprintln!("  --at-line-containing:");
+    eprintln!("    Toggle the first line containing the literal text (basic mode)");
+    eprintln!("    --nth k picks the k-th matching line instead (zero-indexed)");
+    eprintln!();
+    eprintln!("  --block-from:");
+    eprintln!("    Find the end of the {{ ... }} block starting at line_number (brace depth)");
+    eprintln!("    and toggle /* */ around it (// per line for zig); same line again undoes it");
+    eprintln!();
+    eprintln!("  --suite-from:");
+    eprintln!("    Python: comment a def/class line and its indented suite with # ,");
+    eprintln!("    keeping indentation; same line again undoes it exactly");
+    eprintln!();
+    eprintln!("  --isolate:");
+    eprintln!("    Comment every non-blank line outside start_line..end_line (comments get a");
+    eprintln!("    second flag); no-op when none of them is code");
+    eprintln!();
+    eprintln!("  --undo-isolate:");
+    eprintln!("    Undo --isolate with the same range: one flag off every non-blank line");
+    eprintln!("    outside it, only when all of them are commented");
+    eprintln!();
+    eprintln!("  --attribute:");
+    eprintln!("    Rust: insert the attribute line (cfg(test), ignore, ...) directly above the");
+    eprintln!("    item at line_number, indented like it; removes it instead when the");
+    eprintln!("    attribute lines above the item already hold it");
+    eprintln!();
+    eprintln!("  --insert-line / --remove-line:");
+    eprintln!("    Insert content as line line_number (the line count appends), ending like");
+    eprintln!("    the file's lines; remove line line_number only if it is exactly");
+    eprintln!("    expected_content (else exit 5, nothing changed). Any file type");
+    eprintln!();
+    eprintln!("  --duplicate / --move:");
+    eprintln!("    Copy a line directly below itself; move a line so it becomes to_line");
+    eprintln!("    (the lines between shift by one). Any file type");
+    eprintln!();
+    eprintln!("  --join / --split:");
+    eprintln!("    Join a line with the next (one space between; a repeated comment marker");
+    eprintln!("    is dropped); split a line at byte_col, the new line getting the line's");
+    eprintln!("    indent and comment marker. Results over MAX_LINE_LENGTH: exit 8");
+    eprintln!();
+    eprintln!("  --convert-style:");
+    eprintln!("    Turn a line comment into a one-line block comment (// x -> /* x */,");
+    eprintln!("    # x -> \"\"\" x \"\"\" in Python) or back. Not a comment of the other style,");
+    eprintln!("    or text that would change meaning: exit 5; no block comments: exit 4");
+    eprintln!();
+    eprintln!("  --convert-style-file:");
+    eprintln!("    Convert every comment alone on its lines in a /* */ file (block comments");
+    eprintln!("    become // lines, or the reverse); others, and strings, stay as they are");
+    eprintln!();
+    eprintln!("  --strip-comments:");
+    eprintln!("    Remove every line that is only a comment (flag after optional spaces/tabs);");
+    eprintln!("    everything else is kept byte for byte. --output <path> writes the result");
+    eprintln!("    there instead and leaves the file (and its backup) alone");
+    eprintln!();
+    eprintln!("  --lsp-range:");
+    eprintln!("    Toggle basic comments on every line an LSP Range touches (zero-based;");
+    eprintln!("    an end at character 0 of a later line excludes that line)");
+    eprintln!();
+    eprintln!("  --detect:");
+    eprintln!("    Print line_ending (lf/crlf/mixed/none), final_newline, indent");
+    eprintln!("    (spaces/tabs/mixed/none) and comment_flag as key: value lines; no edit");
+    eprintln!();
+    eprintln!("  --column:");
+    eprintln!("    Print the byte column where toggling line_number inserts/removes the flag");
+    eprintln!("    (0, or after leading spaces with --preserve-indent / yaml); no edit");
+    eprintln!();
+    eprintln!("  --relative:");
+    eprintln!("    Print anchor_line + offset (+N, -N or N; anchor may be $) if that line");
+    eprintln!("    exists, else exit 5; no edit");
+    eprintln!();
+    eprintln!("  --lint-comments [max_run]:");
+    eprintln!(
+        "    Print <file>:<line>: for every run of more than max_run (default {})",
+        DEFAULT_MAX_COMMENTED_RUN
+    );
+    eprintln!("    consecutive commented lines, one-based for CI annotations; no edit");
+    eprintln!();
+    eprintln!("  --query-range:");
+    eprintln!("    Print one symbol per line of the range: C commented, . code, B blank");
+    eprintln!("    (as a toggle sees it; --json: [\"C\",\".\"]); stops at end of file; no edit");
+    eprintln!();
+    eprintln!("  --query-lines:");
+    eprintln!("    Print <line>: <state> <leading_whitespace> <flag_kind> for every line of");
+    eprintln!("    the lines / N-M ranges ($ is the last line; up to 128, any order), once");
+    eprintln!("    each in file order; a range stops at end of file; no edit");
+    eprintln!();
+    eprintln!("  --inspect:");
+    eprintln!("    Print byte_len, leading_spaces, leading_tabs, has_flag, flag_kind and");
+    eprintln!("    ends_with_crlf of one line as key: value lines; no edit");
+    eprintln!();
+
+    eprintln!("  --grep-format:");
+    eprintln!("    Read file:line: lines (grep -n / rg -n output, one-based) from stdin and");
+    eprintln!("    toggle them, one batch per file; other lines are skipped. Originals are");
+    eprintln!("    also bundled in .toggle_comment_backup_<ts>/ (see --undo-session)");
+    eprintln!();
+    eprintln!("  --serve:");
+    eprintln!("    JSON-RPC 2.0 server on stdin/stdout with LSP framing (Content-Length);");
+    eprintln!("    methods toggleComment, indentRange, queryState, shutdown; exit stops it");
+    eprintln!();
+    eprintln!("  --tui:");
+    eprintln!("    Show the file with line numbers; j/k move, v selects, c queues a comment");
+    eprintln!("    toggle, > / < indent / unindent, u undoes; q applies the queue on exit,");
+    eprintln!("    Q discards it (Unix terminals, via stty)");
+    eprintln!();
+    eprintln!("  --apply-patch:");
+    eprintln!("    Apply a unified diff from --emit-patch to the file it names (backup,");
+    eprintln!("    atomic replace); context is checked, only comment/indent changes allowed");
+    eprintln!();
+    eprintln!("  --verify-only:");
+    eprintln!("    Compare the file with backup_toggle_comment_<name> (checksum verified)");
+    eprintln!("    and print each run of changed lines (zero-indexed first-last); exit 16");
+    eprintln!("    if anything beyond comment flags / indentation changed; no edit");
+    eprintln!();
+    eprintln!("  --undo:");
+    eprintln!("    Restore the file from backup_toggle_comment_<name> in the working");
+    eprintln!("    directory (or --backup-dir), after checking it against its .sha");
+    eprintln!("    checksum (exit 16 if not)");
+    eprintln!();
+    eprintln!("  --undo-session:");
+    eprintln!("    Restore every file of a .toggle_comment_backup_<ts>/ bundle (written by");
+    eprintln!("    --grep-format); all backups are verified first, else nothing is restored");
+    eprintln!();
+    eprintln!("  --limits:");
+    eprintln!("    Print the bounds compiled into this binary (name: value per line),");
+    eprintln!("    e.g. max_batch_lines, max_search_text_bytes, default_max_file_size_bytes");
+    eprintln!();
+    eprintln!("  --capabilities:");
+    eprintln!("    Print version, porcelain_version, then one mode / option / extension");
+    eprintln!("    line per entry and the --limits values (key: value; --porcelain: records)");
+    eprintln!();
+    eprintln!("  --completions:");
+    eprintln!("    Print a bash, zsh or fish completion script for every mode and global");
+    eprintln!("    option (option values too, e.g. --style c89|default)");
+    eprintln!();
+    eprintln!("  --clean-temp:");
+    eprintln!("    Remove temp_toggle_* / temp_block_* / preview files in [dir] (default .)");
+    eprintln!("    whose process is no longer running; --dry-run only lists them (needs /proc)");
+    eprintln!();
+
+    eprintln!("GLOBAL OPTIONS (any position):");
+    eprintln!("  --no-config:");
+    eprintln!("    Ignore .toggle_comment.toml. Otherwise the nearest one in the target's");
+    eprintln!("    directory or above (not past a .git directory; the working directory");
+    eprintln!("    for modes without a target) sets indent_width, preserve_indent, backup");
+    eprintln!("    and [extensions] aliases; flags override it, errors exit 1");
+    eprintln!("  --preset <name>:");
+    eprintln!("    Set indent_width (or indent_tab), preserve_indent, skip_blank and style:");
+    eprintln!(
+        "    {} (shipped), or a [presets.<name>] table",
+        BUILTIN_PRESETS.map(|(name, _)| name).join(", ")
+    );
+    eprintln!("    of .toggle_comment.toml; other flags override it");
+    eprintln!("  --skip-blank:");
+    eprintln!("    Line toggles copy empty lines unchanged instead of commenting them");
+    eprintln!("  --utf8-strict:");
+    eprintln!("    Refuse a file that is not valid UTF-8 (exit 20) instead of passing");
+    eprintln!("    its bytes through unchanged");
+    eprintln!("  --indent-width <n>:");
+    eprintln!(
+        "    Spaces indent adds and unindent removes (default 4, 1..={})",
+        MAX_INDENT_WIDTH
+    );
+    eprintln!("    Makefiles (.mk, Makefile, GNUmakefile) always use one tab");
+    eprintln!("  --indent-tab:");
+    eprintln!("    Indent adds and unindent removes one tab instead of spaces (gofmt)");
+    eprintln!("  --no-backup:");
+    eprintln!("    Write no backup_toggle_comment_<name> copy (--undo then has none)");
+    eprintln!("  --backup-dir <dir>:");
+    eprintln!("    Write backups in <dir> instead of the working directory; --undo");
+    eprintln!("    looks there too");
+    eprintln!("  --force-readonly:");
+    eprintln!("    Edit a read-only file: clear the attribute, restore it afterwards");
+    eprintln!("  --force:");
+    eprintln!("    Edit a file named like a backup_toggle_comment_* / temp_toggle_* artifact");
+    eprintln!("  --allow-any-extension:");
+    eprintln!("    Let the Rust doc comment (///) modes edit files that are not .rs");
+    eprintln!("  --allow-heredoc:");
+    eprintln!("    Let comment toggles of .sh / .bash files edit the lines of a <<EOF");
+    eprintln!("    here-doc (otherwise refused as data, exit 15)");
+    eprintln!("  --temp-dir <dir>:");
+    eprintln!("    Write intermediate files in <dir> (e.g. /dev/shm) instead of the working");
+    eprintln!("    directory; the result is renamed over the target (staged next to it");
+    eprintln!("    when <dir> is on another filesystem). Backups stay in the working dir,");
+    eprintln!("    as hard links to the original where the filesystem allows");
+    eprintln!("  --durability none|flush|fsync:");
+    eprintln!("    none: no fsync (fastest); flush: fsync the temp file before the replace");
+    eprintln!("    (default); fsync: also fsync the replaced file, or its directory after");
+    eprintln!("    the rename (maximum safety, e.g. for CI)");
+    eprintln!("  --max-file-size <bytes>:");
+    eprintln!(
+        "    Refuse files larger than this (default {} bytes)",
+        DEFAULT_MAX_FILE_SIZE_BYTES
+    );
+    eprintln!("  --no-size-limit:");
+    eprintln!("    Disable the file size check");
+    eprintln!("  --preserve-indent:");
+    eprintln!("    Put the flag after leading spaces (always on for yaml/yml); block mode");
+    eprintln!("    indents the markers it adds like the first selected line");
+    eprintln!("  --relaxed-removal:");
+    eprintln!("    Also uncomment '//x' and '//   x' (toggling back gives '// x')");
+    eprintln!("  --trace / --trace-json:");
+    eprintln!("    Report each stage with its duration on stderr (plain text / JSON lines)");
+    eprintln!("  --normalize-eol lf|crlf:");
+    eprintln!("    Convert every line ending while toggling comments (not indent/block/notebook)");
+    eprintln!("  --ensure-final-newline / --strip-final-newline:");
+    eprintln!("    Add a missing / remove the last line ending while toggling comments");
+    eprintln!("  --trim-trailing:");
+    eprintln!("    Strip trailing spaces/tabs from toggled / unindented lines only");
+    eprintln!("  --style c89|default:");
+    eprintln!("    c89: line toggles in .c / .h files write and remove /* line */ instead");
+    eprintln!("    of // line (K&R / C89 code); default: each extension's usual flag");
+    eprintln!("  --doc-style doxygen|qt|jsdoc:");
+    eprintln!("    Documentation markers in // and /* */ languages: line toggles write");
+    eprintln!("    ///< (doxygen) or //!< (qt), jsdoc keeps //; block toggles write /** */");
+    eprintln!("    (doxygen, jsdoc) or /*! */ (qt). Other languages are unaffected");
+    eprintln!("  --eol-audit:");
+    eprintln!("    Comment toggles report each edited or converted line's ending on stderr");
+    eprintln!("    (line=N before=crlf|lf|none after=...; JSON with --trace-json)");
+    eprintln!("  --timeout <seconds>:");
+    eprintln!("    Stop cleanly if still running after <seconds> (exit 124, original unchanged)");
+    eprintln!("  --progress:");
+    eprintln!(
+        "    Files of {} MB or more: print the percentage done on stderr while",
+        PROGRESS_MIN_FILE_BYTES / (1024 * 1024)
+    );
+    eprintln!(
+        "    streaming, at most every {} ms (JSON with --trace-json)",
+        PROGRESS_INTERVAL_MILLIS
+    );
+    eprintln!("  --comment / --uncomment:");
+    eprintln!("    Line toggles only add the flag (a commented line gets a second one, so");
+    eprintln!("    --uncomment restores it) / only remove it; blank lines are untouched");
+    eprintln!("  --porcelain:");
+    eprintln!("    Print stable tab-separated records (version, kind, mode, file, fields)");
+    eprintln!("    on stdout instead of messages, for scripts and language bindings");
+    eprintln!("  --emit-sed:");
+    eprintln!("    Edit nothing; print the planned changes as a sed script for sed -i -f");
+    eprintln!("    (not for read-only queries, --serve, --grep-format)");
+    eprintln!("  --emit-patch:");
+    eprintln!("    Same, as a unified diff for git apply / patch -p1 (3 lines of context)");
+    eprintln!("  --preview:");
+    eprintln!("    Show that diff first (a page at a time on a terminal) and write only if");
+    eprintln!("    answered y; refused with exit 17 if the file changed in the meantime");
+    eprintln!();
+
+    eprintln!("LINE ADDRESSES (in place of line numbers):");
+    eprintln!("  $            - Last line of the file");
+    eprintln!("  N,M          - Same as the two arguments N M (e.g. 10,$)");
+    eprintln!("  %            - Whole file, same as 0 $");
+    eprintln!("  +N / -N      - N lines after / before the line argument before it");
+    eprintln!("                 (e.g. 10 +3 or 10,+3; must stay inside the file)");
+    eprintln!();
 
     eprintln!("ARGUMENTS:");
     eprintln!("  file_path    - Path to source code file");
@@ -102,625 +804,3527 @@ fn print_usage() {
 
     eprintln!("EXAMPLES:");
     eprintln!("  toggle_comment hello_world.py 5");
-    eprintln!("  toggle_comment --rust-doc-string hello_world.py 10");
+    eprintln!("  toggle_comment --rust-doc-string hello_world.rs 10");
     eprintln!("  toggle_comment --block hello_world.rs 5 15");
+    eprintln!("  toggle_comment --pod hello_world.pl 5 15");
     eprintln!("  toggle_comment --list-basic hello_world.py 1 10 12");
-    eprintln!("  toggle_comment --list-docstring hello_world.toml 1 2 3");
+    eprintln!("  toggle_comment --list-docstring hello_world.rs 1 2 3");
+    eprintln!("  toggle_comment --ranges hello_world.py 3-9 14-20 40-41");
+    eprintln!("  cut -d: -f1 lines.txt | toggle_comment --list-basic hello_world.py --stdin-lines");
+    eprintln!("  printf 'a = 1\\nb = 2\\n' | toggle_comment --stdin-content --line 1 --ext py");
     eprintln!("  toggle_comment --indent hello_world.py 10");
     eprintln!("  toggle_comment --unindent hello_world.py 10");
     eprintln!("  toggle_comment --indent-range hello_world.py 10 12");
     eprintln!("  toggle_comment --unindent-range hello_world.py 10 12");
+    eprintln!("  toggle_comment --force-readonly hello_world.py 5");
+    eprintln!("  toggle_comment --at-line-containing \"debug_dump(\" main.rs --nth 1");
+    eprintln!("  toggle_comment --block-from hello_world.rs 40");
+    eprintln!("  toggle_comment --suite-from hello_world.py 12");
+    eprintln!("  toggle_comment --isolate hello_world.rs 40 60");
+    eprintln!("  toggle_comment --undo-isolate hello_world.rs 40 60");
+    eprintln!("  toggle_comment --attribute hello_world.rs 42 ignore");
+    eprintln!("  toggle_comment --insert-line hello_world.rs 0 \"// Generated - do not edit\"");
+    eprintln!("  toggle_comment --remove-line hello_world.rs 0 \"// Generated - do not edit\"");
+    eprintln!("  toggle_comment --duplicate hello_world.rs 12");
+    eprintln!("  toggle_comment --move hello_world.rs 12 3");
+    eprintln!("  toggle_comment --join hello_world.rs 12");
+    eprintln!("  toggle_comment --split hello_world.rs 12 80");
+    eprintln!("  toggle_comment --convert-style hello_world.rs 12 --to block");
+    eprintln!("  toggle_comment --convert-style-file hello_world.rs --to line");
+    eprintln!("  toggle_comment --strip-comments hello_world.rs --output repro.rs");
+    eprintln!("  toggle_comment --lsp-range hello_world.rs 4 0 9 0");
+    eprintln!("  toggle_comment --detect hello_world.rs");
+    eprintln!("  rg -n \"unwrap()\" | toggle_comment --grep-format --comment");
+    eprintln!("  toggle_comment --tui hello_world.rs");
+    eprintln!("  toggle_comment --column --preserve-indent hello_world.py 12");
+    eprintln!("  toggle_comment --relative hello_world.py 12 -3");
+    eprintln!("  toggle_comment --toggle-range-comment-basic hello_world.py 12 +3");
+    eprintln!("  toggle_comment --lint-comments hello_world.rs 3");
+    eprintln!("  toggle_comment --query-range hello_world.rs 40 80");
+    eprintln!("  toggle_comment --query-lines hello_world.rs 12 40-80");
+    eprintln!("  toggle_comment --inspect hello_world.rs 12");
+    eprintln!("  toggle_comment --emit-sed --block hello_world.rs 5 15 > change.sed");
+    eprintln!("  toggle_comment --emit-patch hello_world.py 5 > change.patch");
+    eprintln!("  toggle_comment --apply-patch change.patch");
+    eprintln!("  toggle_comment --preview --toggle-range-comment-basic hello_world.rs 40 400");
+    eprintln!("  toggle_comment --verify-only hello_world.py");
+    eprintln!("  toggle_comment --undo hello_world.py");
+    eprintln!("  toggle_comment --temp-dir /dev/shm big_generated.rs 5");
+    eprintln!("  toggle_comment --durability fsync src/main.rs 5");
+    eprintln!("  toggle_comment --clean-temp /dev/shm --dry-run");
     eprintln!();
 
     eprintln!("SUPPORTED EXTENSIONS:");
-    eprintln!("  //  : rs, c, cpp, js, ts, java, go, swift");
+    eprintln!("  //  : rs, c, cpp, js, ts, java, go, swift,");
+    eprintln!("        cs, kt, kts, scala, dart, php, zig, v, d");
+    eprintln!("        jsonc, json5 (plain json has no comments: exit 4)");
+    eprintln!("        (php: '# ' lines are also uncommented)");
+    eprintln!("        (c, h with --style c89: /* */ on one line)");
     eprintln!("  #   : py, sh, toml, yaml, rb, pl, r");
+    eprintln!("  <!-- -->: md, markdown");
     eprintln!();
 
     eprintln!("EXIT CODES:");
-    eprintln!("  0 - Success");
-    eprintln!("  1 - Invalid arguments");
-    eprintln!("  2 - File not found");
-    eprintln!("  3 - No extension");
-    eprintln!("  4 - Unsupported extension");
-    eprintln!("  5 - Line not found");
-    eprintln!("  6 - I/O error");
-    eprintln!("  7 - Path error");
-    eprintln!("  8 - Line too long");
-}
-
-/// Execute range toggle - basic comments
-fn execute_range_toggle_basic(file_path: &str, start_line: usize, end_line: usize) -> i32 {
-    match toggle_range_basic_comments_bytewise(file_path, start_line, end_line) {
-        Ok(()) => {
-            println!(
-                "Successfully toggled comment range (lines {}-{})",
-                start_line, end_line
-            );
-            0
-        }
-        Err(e) => {
-            eprintln!("Error toggling range {}: {}", file_path, e);
-            error_to_exit_code(e)
+    for code in ExitCode::ALL {
+        if code != ExitCode::Internal {
+            eprintln!("  {} - {}", code.code(), code.description());
         }
     }
 }
 
-/// Execute range toggle - rust docstrings
-fn execute_range_toggle_docstring(file_path: &str, start_line: usize, end_line: usize) -> i32 {
-    match toggle_range_rust_docstring_bytewise(file_path, start_line, end_line) {
-        Ok(()) => {
-            println!(
-                "Successfully toggled docstring range (lines {}-{})",
-                start_line, end_line
-            );
-            0
+/// Set once a write to stdout found the reader gone (see `Stdout`)
+static STDOUT_CLOSED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// stdout for results and listings, where a reader that went away early
+/// (`--capabilities | head -5`) is not an error
+///
+/// The first `BrokenPipe` marks stdout closed; that write and every later
+/// one are dropped, so the operation finishes quietly instead of panicking
+/// in `println!` (an edit still restores a read-only attribute). Other
+/// write errors are returned.
+struct Stdout;
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if STDOUT_CLOSED.load(std::sync::atomic::Ordering::Relaxed) {
+            return Ok(buf.len());
         }
-        Err(e) => {
-            eprintln!("Error toggling docstring range {}: {}", file_path, e);
-            error_to_exit_code(e)
+        match std::io::stdout().lock().write(buf) {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                STDOUT_CLOSED.store(true, std::sync::atomic::Ordering::Relaxed);
+                Ok(buf.len())
+            }
+            written => written,
         }
     }
-}
 
-/// Parse a line number argument, returning error on invalid input
-///
-/// # Arguments
-/// * `arg` - String slice to parse
-/// * `arg_name` - Name of argument for error messages
-///
-/// # Returns
-/// * `Ok(usize)` - Successfully parsed line number
-/// * `Err(())` - Parse failed (error already printed to stderr)
-fn parse_line_number(arg: &str, arg_name: &str) -> Result<usize, ()> {
-    match arg.parse::<usize>() {
-        Ok(n) => Ok(n),
-        Err(_) => {
-            eprintln!("Error: {} must be a valid integer", arg_name);
-            eprintln!();
-            Err(())
+    fn flush(&mut self) -> std::io::Result<()> {
+        if STDOUT_CLOSED.load(std::sync::atomic::Ordering::Relaxed) {
+            return Ok(());
+        }
+        match std::io::stdout().lock().flush() {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                STDOUT_CLOSED.store(true, std::sync::atomic::Ordering::Relaxed);
+                Ok(())
+            }
+            flushed => flushed,
         }
     }
 }
 
-// /// Parse multiple line number arguments into a fixed-size array
-// ///
-// /// # Arguments
-// /// * `args` - Slice of string arguments to parse
-// ///
-// /// # Returns
-// /// * `Ok((count, array))` - Successfully parsed line numbers
-// /// * `Err(())` - Parse failed or too many lines
-// ///
-// /// # Safety
-// /// - Bounded to MAX_BATCH_LINES (512)
-// /// - Pre-allocated fixed array on stack
-// fn parse_line_list(args: &[String]) -> Result<(usize, [usize; MAX_BATCH_LINES]), ()> {
-//     // Check bounds
-//     if args.is_empty() {
-//         eprintln!("Error: No line numbers provided");
-//         return Err(());
-//     }
-
-//     if args.len() > MAX_BATCH_LINES {
-//         eprintln!("Error: Too many lines (max {})", MAX_BATCH_LINES);
-//         return Err(());
-//     }
-
-//     // Pre-allocate fixed array
-//     let mut line_array: [usize; MAX_BATCH_LINES] = [0; MAX_BATCH_LINES];
-//     let count = args.len();
-
-//     // Parse each line number
-//     for (i, arg) in args.iter().enumerate() {
-//         match arg.parse::<usize>() {
-//             Ok(n) => line_array[i] = n,
-//             Err(_) => {
-//                 eprintln!("Error: Invalid line number: {}", arg);
-//                 return Err(());
-//             }
-//         }
-//     }
-
-//     Ok((count, line_array))
-// }
-
-/// Convert ToggleCommentError to exit code
-///
-/// # Arguments
-/// * `error` - The error to convert
+/// Print one line on stdout (`println!` without its panic, see `Stdout`)
 ///
-/// # Returns
-/// * Exit code (2-10)
-fn error_to_exit_code(error: ToggleCommentError) -> i32 {
-    match error {
-        ToggleCommentError::FileNotFound => 2,
-        ToggleCommentError::NoExtension => 3,
-        ToggleCommentError::LineNotFound { .. } => 5,
-        ToggleCommentError::IoError(_) => 6,
-        ToggleCommentError::PathError => 7,
-    }
+/// The result is already final when it is printed, so a failed write is
+/// not reported.
+fn print_line(line: impl Display) {
+    let _ = writeln!(Stdout, "{}", line);
 }
 
-/// Convert ToggleIndentError to exit code
-///
-/// # Arguments
-/// * `error` - The error to convert
-///
-/// # Returns
-/// * Exit code (2-10, same mapping as ToggleCommentError where applicable)
-fn indent_error_to_exit_code(error: ToggleIndentError) -> i32 {
-    match error {
-        ToggleIndentError::FileNotFound => 2,
-        ToggleIndentError::LineNotFound { .. } => 5,
-        ToggleIndentError::IoError(_) => 6,
-        ToggleIndentError::PathError => 7,
-    }
+/// Where operation results go: human messages, or porcelain records
+/// (`--porcelain`, format and fields in `porcelain.rs`)
+struct Report<'a> {
+    porcelain: bool,
+    /// Mode flag without `--` (`toggle` in basic mode)
+    mode: &'a str,
+    /// --emit-sed / --emit-patch: `(preview path, real path)`; success output is suppressed
+    /// and the preview path is reported as the real one
+    preview: Option<(&'a str, &'a str)>,
+    /// Format of the warning lines on stderr (JSON with --trace-json)
+    trace: TraceFormat,
 }
 
-/// Execute indent on a single line
-fn execute_indent(file_path: &str, line_number: usize) -> i32 {
-    match indent_line_bytewise(file_path, line_number) {
-        Ok(()) => {
-            println!("Successfully indented line {}", line_number);
-            0
-        }
-        Err(e) => {
-            eprintln!("Error indenting {}: {}", file_path, e);
-            indent_error_to_exit_code(e)
+impl Report<'_> {
+    /// Path to show for `file` (the real file when running on a preview)
+    fn shown<'f>(&'f self, file: &'f str) -> &'f str {
+        match self.preview {
+            Some((preview, real)) if preview == file => real,
+            _ => file,
         }
     }
-}
 
-/// Execute unindent on a single line
-fn execute_unindent(file_path: &str, line_number: usize) -> i32 {
-    match unindent_line_bytewise(file_path, line_number) {
+    /// Print the warnings the operation recorded (`take_warnings()`) on stderr
+    fn warnings(&self) {
+        let warnings = take_warnings();
+        let mut stderr = std::io::stderr().lock();
+        let _ = write_warnings(&mut stderr, self.trace, self.mode, &warnings);
+    }
+
+    /// Print one porcelain record on stdout
+    fn record(&self, kind: &str, file: &str, fields: &[&str]) {
+        if let Some(line) = format_record(kind, self.mode, self.shown(file), fields) {
+            print_line(line);
+        }
+    }
+
+    /// Operation succeeded: message, or an `ok` record. Returns exit code 0
+    fn ok(
+        &self,
+        file: &str,
+        message: &str,
+        span: Option<(usize, usize)>,
+        count: Option<usize>,
+    ) -> i32 {
+        self.warnings();
+        if self.preview.is_some() {
+            return 0;
+        }
+        if self.porcelain {
+            let (first, last) = match span {
+                Some((first, last)) => (first.to_string(), last.to_string()),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            let count = count.map_or("-".to_string(), |n| n.to_string());
+            self.record("ok", file, &[&first, &last, &count]);
+        } else {
+            print_line(message);
+        }
+        0
+    }
+
+    /// Nothing to do for this file type: message, or an `unchanged` record.
+    /// Returns exit code 0
+    fn unchanged(&self, file: &str, message: &str) -> i32 {
+        self.warnings();
+        if self.preview.is_some() {
+            return 0;
+        }
+        if self.porcelain {
+            self.record("unchanged", file, &["unsupported"]);
+        } else {
+            print_line(message);
+        }
+        0
+    }
+
+    /// One answer of a read-only query: message, or a `value` record
+    fn value(&self, file: &str, key: &str, value: &str, message: &str) {
+        if self.porcelain {
+            self.record("value", file, &[key, value]);
+        } else {
+            print_line(message);
+        }
+    }
+
+    /// Operation failed: `<context> <path>: <error>` on stderr, or an `error`
+    /// record on stdout. Returns `exit_code`
+    fn error(&self, file: &str, context: &str, error: impl Display, exit_code: i32) -> i32 {
+        self.warnings();
+        if self.porcelain {
+            self.record("error", file, &[&exit_code.to_string(), &error.to_string()]);
+        } else {
+            let shown = display_path(Path::new(self.shown(file)));
+            eprintln!("{} {}: {}", context, shown, error);
+        }
+        exit_code
+    }
+}
+
+/// Output of --emit-sed / --emit-patch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmitFormat {
+    /// sed script (`sed -i -f`)
+    Sed,
+
+    /// Unified diff (`git apply`, `patch -p1`)
+    Patch,
+}
+
+/// Run the mode on a preview copy and print its changes (sed script or patch)
+///
+/// The target is only read. Nothing is printed on failure but the error.
+fn execute_emit(
+    mode: &Mode<Run>,
+    args: &[String],
+    target_index: usize,
+    format: EmitFormat,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let target_path = args[target_index].as_str();
+    let copy = match run_on_preview(mode, args, target_index, options, report) {
+        Ok(copy) => copy,
+        Err(exit_code) => return exit_code,
+    };
+
+    let written = match format {
+        EmitFormat::Sed => copy.write_sed_script(&mut Stdout, target_path),
+        EmitFormat::Patch => copy.write_unified_diff(&mut Stdout, target_path),
+    };
+    match written {
+        Ok(_) => 0,
+        Err(e) => report.error(
+            target_path,
+            "Error writing planned changes for",
+            e,
+            patch_error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Page the mode's changes as a unified diff and ask whether to write them
+///
+/// # Returns
+/// * `Ok(())` - Confirmed and the target is unchanged since: run the mode on it
+/// * `Err(exit_code)` - Declined (0), nothing to change (0) or failed
+fn execute_preview(
+    mode: &Mode<Run>,
+    args: &[String],
+    target_index: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> Result<(), i32> {
+    let target_path = args[target_index].as_str();
+    let copy = run_on_preview(mode, args, target_index, options, report)?;
+
+    let page_lines = if std::io::stdout().is_terminal() {
+        terminal::size()
+            .map_or(24, |(rows, _)| rows)
+            .saturating_sub(1)
+            .max(1)
+    } else {
+        usize::MAX
+    };
+    let stdout = std::io::stdout();
+    let mut pager = Pager {
+        output: stdout.lock(),
+        page_lines,
+        lines: 0,
+        skipping: false,
+    };
+    let changed = match copy.write_unified_diff(&mut pager, target_path) {
+        Ok(changed) => changed,
+        Err(e) => {
+            return Err(report.error(
+                target_path,
+                "Error writing planned changes for",
+                e,
+                patch_error_to_exit_code(e),
+            ));
+        }
+    };
+    drop(pager);
+    if !changed {
+        return Err(report.ok(target_path, "Nothing to change", None, Some(0)));
+    }
+
+    if !ask(&format!(
+        "Write these changes to {}?",
+        display_path(Path::new(target_path))
+    )) {
+        return Err(report.ok(target_path, "Not written (declined)", None, Some(0)));
+    }
+    copy.check_original_unchanged().map_err(|e| {
+        report.error(
+            target_path,
+            "Error: preview is out of date for",
+            e,
+            error_to_exit_code(e),
+        )
+    })
+}
+
+/// Run the mode on a scratch copy of its target (for --emit-* and --preview)
+///
+/// # Returns
+/// * `Ok(copy)` - The operation succeeded on the copy; diff it against the original
+/// * `Err(exit_code)` - Not previewable or failed (error already reported)
+fn run_on_preview(
+    mode: &Mode<Run>,
+    args: &[String],
+    target_index: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> Result<PreviewCopy, i32> {
+    let target_path = args[target_index].as_str();
+    // Queries edit nothing; --undo restores a backup the preview does not have
+    let Run::Edit(run) = mode.handler else {
+        eprintln!(
+            "Error: --emit-sed / --emit-patch / --preview need a mode that edits one file (not an undo)"
+        );
+        return Err(1);
+    };
+
+    // The copy has a scratch name; check the real target's name instead
+    if !options.allow_own_artifacts && is_own_artifact(Path::new(target_path)) {
+        let e = ToggleCommentError::OwnArtifact;
+        return Err(report.error(
+            target_path,
+            "Error preparing preview of",
+            e,
+            error_to_exit_code(e),
+        ));
+    }
+    // PreviewCopy removes the scratch backup from the working directory
+    let preview_options = ToggleOptions {
+        allow_own_artifacts: true,
+        backup_dir: None,
+        ..options.clone()
+    };
+
+    let copy = match PreviewCopy::create(target_path) {
+        Ok(copy) => copy,
+        Err(e) => {
+            return Err(report.error(
+                target_path,
+                "Error preparing preview of",
+                e,
+                error_to_exit_code(e),
+            ));
+        }
+    };
+
+    let mut preview_args = args.to_vec();
+    preview_args[target_index] = copy.path().to_string();
+    let preview_report = Report {
+        porcelain: report.porcelain,
+        mode: report.mode,
+        preview: Some((copy.path(), target_path)),
+        trace: report.trace,
+    };
+
+    let exit_code = run_mode(mode, run, &preview_args, &preview_options, &preview_report);
+    if exit_code != 0 {
+        return Err(exit_code);
+    }
+    Ok(copy)
+}
+
+/// Output paged on a terminal: after each screen, waits for Enter (`q`
+/// skips the rest)
+struct Pager<W: Write> {
+    output: W,
+    /// Lines per page (`usize::MAX`: no paging)
+    page_lines: usize,
+    /// Lines written on the current page
+    lines: usize,
+    /// `q` was answered: drop the rest
+    skipping: bool,
+}
+
+impl<W: Write> Write for Pager<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.skipping {
+            return Ok(buf.len());
+        }
+        for (index, &byte) in buf.iter().enumerate() {
+            if byte != b'\n' {
+                continue;
+            }
+            self.lines += 1;
+            if self.lines >= self.page_lines {
+                // Write through the end of the page, then wait
+                self.output.write_all(&buf[..=index])?;
+                self.output.flush()?;
+                self.lines = 0;
+                eprint!("-- more (Enter: next page, q: skip to the question) --");
+                self.skipping = read_answer().is_none_or(|answer| answer == "q");
+                return Ok(index + 1);
+            }
+        }
+        self.output.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// One trimmed line from stdin (None at end of input or on error)
+fn read_answer() -> Option<String> {
+    let mut line = String::new();
+    match std::io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_string()),
+    }
+}
+
+/// Ask a yes/no question on stderr; only `y` / `yes` is a yes
+fn ask(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    read_answer().is_some_and(|answer| {
+        answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")
+    })
+}
+
+/// Exit code for a patch error (pipeline errors as for any operation)
+fn patch_error_to_exit_code(error: PatchError) -> i32 {
+    match error {
+        PatchError::Toggle(e) => error_to_exit_code(e),
+        PatchError::ContextMismatch { .. } => ExitCode::NotFound.code(),
+        PatchError::UnexpectedChange { .. } => ExitCode::BackupIntegrity.code(),
+        PatchError::FinalNewline
+        | PatchError::Malformed { .. }
+        | PatchError::NotCommentChange { .. } => ExitCode::InvalidArgument.code(),
+    }
+}
+
+/// Apply a patch written by --emit-patch (comment / indent changes only)
+fn execute_apply_patch(patch_path: &str, options: &ToggleOptions, report: &Report) -> i32 {
+    match apply_patch(patch_path, options) {
+        Ok(applied) => report.ok(
+            &applied.target,
+            &format!(
+                "Applied {} hunk(s) of {} to {}",
+                applied.hunks, patch_path, applied.target
+            ),
+            None,
+            Some(applied.lines),
+        ),
+        Err(e) => report.error(
+            patch_path,
+            "Error applying patch",
+            e,
+            patch_error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Range arguments in ascending order (the library accepts either order)
+fn ordered_range(first: usize, second: usize) -> (usize, usize) {
+    (first.min(second), first.max(second))
+}
+
+/// Execute multi-range toggle - basic comments, one pass
+fn execute_ranges_toggle(
+    file_path: &str,
+    ranges: &[(usize, usize)],
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match toggle_ranges_basic_comments_with_options(file_path, ranges, options) {
         Ok(()) => {
-            println!("Successfully unindented line {}", line_number);
-            0
+            let lines: usize = ranges
+                .iter()
+                .map(|&(start, end)| start.abs_diff(end) + 1)
+                .sum();
+            report.ok(
+                file_path,
+                &format!(
+                    "Successfully toggled {} ranges ({} lines)",
+                    ranges.len(),
+                    lines
+                ),
+                None,
+                Some(lines),
+            )
+        }
+        Err(e) => report.error(file_path, "Error toggling ranges", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute range toggle - basic comments
+fn execute_range_toggle_basic(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (start_line, end_line) = ordered_range(start_line, end_line);
+    match toggle_range_basic_comments_bytewise_with_options(
+        file_path, start_line, end_line, options,
+    ) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!(
+                "Successfully toggled comment range (lines {}-{})",
+                start_line, end_line
+            ),
+            Some((start_line, end_line)),
+            Some(end_line - start_line + 1),
+        ),
+        Err(e) => report.error(file_path, "Error toggling range", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute range toggle - rust docstrings
+fn execute_range_toggle_docstring(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (start_line, end_line) = ordered_range(start_line, end_line);
+    match toggle_range_rust_docstring_bytewise_with_options(
+        file_path, start_line, end_line, options,
+    ) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!(
+                "Successfully toggled docstring range (lines {}-{})",
+                start_line, end_line
+            ),
+            Some((start_line, end_line)),
+            Some(end_line - start_line + 1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error toggling docstring range",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Map an LSP Range onto an inclusive line span
+///
+/// LSP positions are zero-based like ours, but the range end is exclusive:
+/// a selection ending at character 0 of a later line (the usual result of
+/// selecting whole lines) does not include that line. Otherwise character
+/// offsets are ignored - any touched line is toggled whole.
+///
+/// # Returns
+/// * `Some((start_line, end_line))` - Inclusive
+/// * `None` - End line before start line
+fn lsp_range_to_lines(
+    start_line: usize,
+    end_line: usize,
+    end_char: usize,
+) -> Option<(usize, usize)> {
+    if end_line < start_line {
+        return None;
+    }
+
+    if end_char == 0 && end_line > start_line {
+        Some((start_line, end_line - 1))
+    } else {
+        Some((start_line, end_line))
+    }
+}
+
+/// Every global option (`split_global_options()`) with the value it takes
+/// (`""`: none, `a|b`: one of these words, `<...>`: free, `<dir>`: a
+/// directory), listed by `--capabilities` and completed by `--completions`
+const GLOBAL_OPTIONS: [(&str, &str); 35] = [
+    ("--force-readonly", ""),
+    ("--force", ""),
+    ("--allow-any-extension", ""),
+    ("--allow-heredoc", ""),
+    ("--porcelain", ""),
+    ("--emit-sed", ""),
+    ("--emit-patch", ""),
+    ("--preview", ""),
+    ("--no-config", ""),
+    ("--preset", "<name>"),
+    ("--skip-blank", ""),
+    ("--utf8-strict", ""),
+    ("--no-size-limit", ""),
+    ("--preserve-indent", ""),
+    ("--relaxed-removal", ""),
+    ("--trace", ""),
+    ("--trace-json", ""),
+    ("--trim-trailing", ""),
+    ("--no-backup", ""),
+    ("--backup-dir", "<dir>"),
+    ("--indent-width", "<n>"),
+    ("--indent-tab", ""),
+    ("--style", "c89|default"),
+    ("--doc-style", "doxygen|qt|jsdoc"),
+    ("--eol-audit", ""),
+    ("--progress", ""),
+    ("--comment", ""),
+    ("--uncomment", ""),
+    ("--ensure-final-newline", ""),
+    ("--strip-final-newline", ""),
+    ("--normalize-eol", "lf|crlf"),
+    ("--max-file-size", "<bytes>"),
+    ("--timeout", "<seconds>"),
+    ("--durability", "none|flush|fsync"),
+    ("--temp-dir", "<dir>"),
+];
+
+/// `--capabilities` entries, in output order (keys repeat for lists)
+///
+/// - `version`, `porcelain_version`
+/// - `mode` (MODES), each followed by one `usage` per argument form
+///   (`--block <file_path> <start_line> <end_line>`)
+/// - `option` (GLOBAL_OPTIONS)
+/// - `exit_code`: `<code> <name> <description>` (ExitCode::ALL)
+/// - `extension`: `<ext> line=<flag>[,<suffix>] [removes=<flag>]
+///   [block=<start>,<end>] [indent=keep]`
+/// - every `--limits` entry
+fn capabilities() -> Vec<(&'static str, String)> {
+    let mut entries = vec![
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("porcelain_version", PORCELAIN_VERSION.to_string()),
+    ];
+    for mode in MODES {
+        entries.push(("mode", mode.name.to_string()));
+        entries.extend(mode.synopses().into_iter().map(|usage| ("usage", usage)));
+    }
+    entries.extend(
+        GLOBAL_OPTIONS
+            .iter()
+            .map(|(option, _)| ("option", option.to_string())),
+    );
+    entries.extend(ExitCode::ALL.iter().map(|code| {
+        (
+            "exit_code",
+            format!("{} {} {}", code.code(), code.name(), code.description()),
+        )
+    }));
+    for extension in SUPPORTED_EXTENSIONS {
+        let Some(syntax) = extension_syntax(extension) else {
+            continue;
+        };
+        let mut value = format!("{} line={}", extension, syntax.line_flag);
+        if !syntax.line_suffix.is_empty() {
+            value.push_str(&format!(",{}", syntax.line_suffix));
+        }
+        if let Some(flag) = syntax.removal_only_flag {
+            value.push_str(&format!(" removes={}", flag));
         }
+        if let Some((start, end)) = syntax.block {
+            value.push_str(&format!(" block={},{}", start, end));
+        }
+        if syntax.preserves_indent {
+            value.push_str(" indent=keep");
+        }
+        if syntax.indent_tab {
+            value.push_str(" indent=tab");
+        }
+        entries.push(("extension", value));
+    }
+    entries.extend(
+        limits()
+            .entries()
+            .iter()
+            .map(|(key, value)| (*key, value.to_string())),
+    );
+    entries
+}
+
+/// Resolve symbolic line addresses before dispatch
+///
+/// In the line number positions of a mode (`Mode::line_after_target()`;
+/// text and other number arguments pass through as given):
+/// - `$` - last line of the file (`last_line_index()`)
+/// - `%` - whole file, expands to two arguments `0 $`
+/// - `N,M` - two arguments `N M` (either side may be `$`), e.g. `10,$`
+/// - `+N` / `-N` - N lines after / before the line argument before it (after
+///   `$` is resolved), e.g. `10 +3`, `10,+3`; checked against the file
+///
+/// `%` and `N,M` only expand where two line numbers follow each other. The
+/// file is only read when a `$`, `+N` or `-N` is present.
+///
+/// # Returns
+/// * `Ok(args)` - Arguments with addresses replaced by numbers
+/// * `Err(exit_code)` - The file could not be read, or a relative line is
+///   outside it (5) or has no line before it (1); error already reported
+fn resolve_line_addresses(
+    args: Vec<String>,
+    mode: &Mode<Run>,
+    report: &Report,
+) -> Result<Vec<String>, i32> {
+    let target_index = match mode.target_index(&args) {
+        Some(target_index) if mode.takes_lines() => target_index,
+        _ => return Ok(args),
+    };
+
+    // Split `%` / `N,M` in line positions, remembering where the lines are
+    let mut resolved: Vec<String> = Vec::with_capacity(args.len() + 1);
+    let mut line_indices: Vec<usize> = Vec::with_capacity(args.len() + 1);
+    resolved.extend(args[..=target_index].iter().cloned());
+    let mut position: usize = 0;
+    let mut rest = args[target_index + 1..].iter();
+    while let Some(arg) = rest.next() {
+        if let Some(spec) = mode.flagged_arg(arg) {
+            resolved.push(arg.clone());
+            if spec.kind != ArgKind::Switch
+                && let Some(value) = rest.next()
+            {
+                resolved.push(value.clone());
+            }
+            continue;
+        }
+        if !mode.line_after_target(position) {
+            resolved.push(arg.clone());
+            position += 1;
+            continue;
+        }
+        let pair = if !mode.line_after_target(position + 1) {
+            None
+        } else if arg == "%" {
+            Some(("0", "$"))
+        } else {
+            arg.split_once(',')
+        };
+        match pair {
+            Some((start, end)) => {
+                line_indices.extend([resolved.len(), resolved.len() + 1]);
+                resolved.push(start.to_string());
+                resolved.push(end.to_string());
+                position += 2;
+            }
+            None => {
+                line_indices.push(resolved.len());
+                resolved.push(arg.clone());
+                position += 1;
+            }
+        }
+    }
+
+    let needs_last_line = line_indices
+        .iter()
+        .any(|&index| resolved[index] == "$" || is_line_offset(&resolved[index]));
+    if !needs_last_line {
+        return Ok(resolved);
+    }
+
+    let file_path = &args[target_index];
+    let last_line = match last_line_index(file_path) {
+        Ok(line) => line,
         Err(e) => {
-            eprintln!("Error unindenting {}: {}", file_path, e);
-            indent_error_to_exit_code(e)
+            return Err(report.error(file_path, "Error reading", e, error_to_exit_code(e)));
+        }
+    };
+    for (nth, &index) in line_indices.iter().enumerate() {
+        if resolved[index] == "$" {
+            resolved[index] = last_line.to_string();
+        } else if is_line_offset(&resolved[index]) {
+            let previous = nth.checked_sub(1).map(|before| line_indices[before]);
+            let anchor = match previous.map(|before| resolved[before].parse::<usize>()) {
+                Some(Ok(anchor)) => anchor,
+                _ => {
+                    eprintln!(
+                        "Error: {} must follow a line number (e.g. 10 {})",
+                        resolved[index], resolved[index]
+                    );
+                    return Err(1);
+                }
+            };
+            match offset_line(anchor, &resolved[index], last_line) {
+                Some(Ok(line)) => resolved[index] = line.to_string(),
+                Some(Err(message)) => {
+                    return Err(report.error(
+                        file_path,
+                        "Error resolving line in",
+                        message,
+                        ExitCode::NotFound.code(),
+                    ));
+                }
+                None => return Err(1),
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// `+N` / `-N` line argument
+fn is_line_offset(arg: &str) -> bool {
+    arg.len() > 1
+        && (arg.starts_with('+') || arg.starts_with('-'))
+        && arg[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Resolve an offset (`+N`, `-N` or `N`) against `anchor`, inside the file
+///
+/// # Returns
+/// * `Some(Ok(line))` - `anchor + offset`, at most `last_line`
+/// * `Some(Err(message))` - Before line 0 or after the last line
+/// * `None` - Not an offset (error already printed)
+fn offset_line(anchor: usize, offset: &str, last_line: usize) -> Option<Result<usize, String>> {
+    let (backwards, digits) = match offset.as_bytes().first() {
+        Some(b'-') => (true, &offset[1..]),
+        Some(b'+') => (false, &offset[1..]),
+        _ => (false, offset),
+    };
+    let Ok(distance) = digits.parse::<usize>() else {
+        eprintln!("Error: offset must be +N, -N or N (got {})", offset);
+        return None;
+    };
+
+    let line = if backwards {
+        anchor.checked_sub(distance)
+    } else {
+        anchor.checked_add(distance)
+    };
+    Some(match line {
+        Some(line) if line <= last_line => Ok(line),
+        Some(line) => Err(format!(
+            "line {} ({}{}) not found (last line is {})",
+            line, anchor, offset, last_line
+        )),
+        None => Err(format!("{}{} is before line 0", anchor, offset)),
+    })
+}
+
+/// Parse a line number argument, returning error on invalid input
+///
+/// # Arguments
+/// * `arg` - String slice to parse
+/// * `arg_name` - Name of argument for error messages
+///
+/// # Returns
+/// * `Ok(usize)` - Successfully parsed line number
+/// * `Err(())` - Parse failed (error already printed to stderr)
+fn parse_line_number(arg: &str, arg_name: &str) -> Result<usize, ()> {
+    match arg.parse::<usize>() {
+        Ok(n) => Ok(n),
+        Err(_) => {
+            eprintln!("Error: {} must be a valid integer", arg_name);
+            eprintln!();
+            Err(())
+        }
+    }
+}
+
+/// Parse `--ranges` arguments: `N-M` (inclusive, either order) or `N`
+///
+/// `$` on either side is the last line; the file is only read when one is present.
+///
+/// # Returns
+/// * `Ok(ranges)` - One `(start, end)` per argument
+/// * `Err(exit_code)` - Malformed range (1) or `$` unreadable (error already printed)
+fn parse_range_list(
+    file_path: &str,
+    args: &[&str],
+    report: &Report,
+) -> Result<Vec<(usize, usize)>, i32> {
+    let last_line = if args.iter().any(|arg| arg.contains('$')) {
+        match last_line_index(file_path) {
+            Ok(line) => Some(line),
+            Err(e) => {
+                return Err(report.error(file_path, "Error reading", e, error_to_exit_code(e)));
+            }
+        }
+    } else {
+        None
+    };
+    let parse_end = |end: &str| match (end, last_line) {
+        ("$", Some(line)) => Some(line),
+        _ => end.parse::<usize>().ok(),
+    };
+
+    let mut ranges = Vec::with_capacity(args.len());
+    for &arg in args {
+        let (start, end) = arg.split_once('-').unwrap_or((arg, arg));
+        match (parse_end(start), parse_end(end)) {
+            (Some(start), Some(end)) => ranges.push((start, end)),
+            _ => {
+                eprintln!("Error: range must be N-M or N (got {})", arg);
+                eprintln!();
+                return Err(1);
+            }
+        }
+    }
+    Ok(ranges)
+}
+
+/// Check the line numbers of a batch toggle
+///
+/// # Arguments
+/// * `lines` - Line numbers from the command line (parsed already)
+///
+/// # Returns
+/// * `Ok(lines)` - At most MAX_TOTAL_BATCH_LINES lines
+/// * `Err(())` - Too many lines (error already printed)
+fn checked_line_list(lines: Vec<usize>) -> Result<Vec<usize>, ()> {
+    if lines.len() > MAX_TOTAL_BATCH_LINES {
+        eprintln!("Error: Too many lines (max {})", MAX_TOTAL_BATCH_LINES);
+        return Err(());
+    }
+    Ok(lines)
+}
+
+/// Convert ToggleCommentError to exit code (`ToggleCommentError::exit_code()`)
+fn error_to_exit_code(error: ToggleCommentError) -> i32 {
+    exit_status(error.exit_code())
+}
+
+/// Convert ToggleIndentError to exit code (`ToggleIndentError::exit_code()`)
+fn indent_error_to_exit_code(error: ToggleIndentError) -> i32 {
+    exit_status(error.exit_code())
+}
+
+/// Execute indent on a single line
+fn execute_indent(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match indent_line_bytewise_with_options(file_path, line_number, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!("Successfully indented line {}", line_number),
+            Some((line_number, line_number)),
+            Some(1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error indenting",
+            e,
+            indent_error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute unindent on a single line
+fn execute_unindent(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match unindent_line_bytewise_with_options(file_path, line_number, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!("Successfully unindented line {}", line_number),
+            Some((line_number, line_number)),
+            Some(1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error unindenting",
+            e,
+            indent_error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute basic single-line comment toggle
+fn execute_basic_toggle(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match toggle_basic_singleline_comment_bytewise_with_options(file_path, line_number, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!("Successfully toggled comment on line {}", line_number),
+            Some((line_number, line_number)),
+            Some(1),
+        ),
+        Err(e) => report.error(file_path, "Error toggling", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute Rust docstring single-line comment toggle
+fn execute_docstring_toggle(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match toggle_rust_docstring_singleline_comment_bytewise_with_options(
+        file_path,
+        line_number,
+        options,
+    ) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!("Successfully toggled docstring on line {}", line_number),
+            Some((line_number, line_number)),
+            Some(1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error toggling docstring",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute notebook code-cell line toggle
+fn execute_notebook_toggle(
+    file_path: &str,
+    cell_index: usize,
+    line_in_cell: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match toggle_notebook_cell_line_bytewise_with_options(
+        file_path,
+        cell_index,
+        line_in_cell,
+        options,
+    ) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!(
+                "Successfully toggled comment on cell {} line {}",
+                cell_index, line_in_cell
+            ),
+            None,
+            Some(1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error toggling notebook",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute basic toggle on the line found by content
+fn execute_content_toggle(
+    file_path: &str,
+    needle: &str,
+    occurrence: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match find_line_containing(file_path, needle, occurrence) {
+        Ok(line_number) => execute_basic_toggle(file_path, line_number, options, report),
+        Err(e) => report.error(file_path, "Error searching", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute brace-block toggle
+fn execute_brace_block_toggle(
+    file_path: &str,
+    start_line: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match toggle_brace_block_bytewise_with_options(file_path, start_line, options) {
+        Ok(Some(end_line)) => report.ok(
+            file_path,
+            &format!(
+                "Successfully toggled block comment on lines {}-{}",
+                start_line, end_line
+            ),
+            Some((start_line, end_line)),
+            Some(end_line - start_line + 1),
+        ),
+        Ok(None) => report.unchanged(
+            file_path,
+            "No brace blocks for this file type (file unchanged)",
+        ),
+        Err(e) => report.error(file_path, "Error toggling block", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute Python suite toggle
+fn execute_suite_toggle(
+    file_path: &str,
+    start_line: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match toggle_python_suite_bytewise_with_options(file_path, start_line, options) {
+        Ok(Some(end_line)) => report.ok(
+            file_path,
+            &format!(
+                "Successfully toggled suite comment on lines {}-{}",
+                start_line, end_line
+            ),
+            Some((start_line, end_line)),
+            Some(end_line - start_line + 1),
+        ),
+        Ok(None) => report.unchanged(
+            file_path,
+            "Suite mode is for .py files only (file unchanged)",
+        ),
+        Err(e) => report.error(file_path, "Error toggling suite", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute range isolation (or, with `undo`, its undo)
+fn execute_isolate(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    undo: bool,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (start_line, end_line) = ordered_range(start_line, end_line);
+    let result = if undo {
+        undo_isolate_range_bytewise_with_options(file_path, start_line, end_line, options)
+    } else {
+        toggle_isolate_range_bytewise_with_options(file_path, start_line, end_line, options)
+    };
+    match result {
+        Ok(Some(direction)) => {
+            let message = if direction == LineToggleDirection::Add {
+                format!(
+                    "Successfully isolated lines {}-{} (everything else commented)",
+                    start_line, end_line
+                )
+            } else {
+                format!(
+                    "Successfully undid the isolation of lines {}-{}",
+                    start_line, end_line
+                )
+            };
+            report.ok(
+                file_path,
+                &message,
+                Some((start_line, end_line)),
+                Some(end_line - start_line + 1),
+            )
+        }
+        Ok(None) if undo => report.unchanged(
+            file_path,
+            "Nothing to undo: unsupported file type, or code outside the range (not isolated)",
+        ),
+        Ok(None) => report.unchanged(
+            file_path,
+            "Nothing to isolate: unsupported file type, or no code outside the range",
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error isolating range in",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute a Rust attribute line toggle
+fn execute_attribute(
+    file_path: &str,
+    line_number: usize,
+    attribute: &str,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match toggle_rust_attribute_bytewise_with_options(file_path, line_number, attribute, options) {
+        Ok(LineToggleDirection::Remove) => report.ok(
+            file_path,
+            &format!(
+                "Successfully removed attribute {} above line {}",
+                attribute.trim(),
+                line_number
+            ),
+            None,
+            Some(1),
+        ),
+        Ok(_) => report.ok(
+            file_path,
+            &format!(
+                "Successfully added attribute {} at line {}",
+                attribute.trim(),
+                line_number
+            ),
+            Some((line_number, line_number)),
+            Some(1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error toggling attribute in",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute a whole-line insert, or a removal checked against `content`
+fn execute_line_edit(
+    file_path: &str,
+    line_number: usize,
+    content: &str,
+    insert: bool,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (result, message, context) = if insert {
+        (
+            insert_line_with_options(file_path, line_number, content, options),
+            format!("Successfully inserted line {}", line_number),
+            "Error inserting line in",
+        )
+    } else {
+        (
+            remove_line_with_options(file_path, line_number, content, options),
+            format!("Successfully removed line {}", line_number),
+            "Error removing line from",
+        )
+    };
+    match result {
+        Ok(()) => report.ok(
+            file_path,
+            &message,
+            insert.then_some((line_number, line_number)),
+            Some(1),
+        ),
+        Err(e) => report.error(file_path, context, e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute a line duplicate (`to_line` is None) or move
+fn execute_duplicate_or_move(
+    file_path: &str,
+    line_number: usize,
+    to_line: Option<usize>,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (result, message, span, context) = match to_line {
+        None => (
+            duplicate_line_with_options(file_path, line_number, options),
+            format!("Successfully duplicated line {}", line_number),
+            (line_number + 1, line_number + 1),
+            "Error duplicating line in",
+        ),
+        Some(to_line) => (
+            move_line_with_options(file_path, line_number, to_line, options),
+            format!("Successfully moved line {} to {}", line_number, to_line),
+            (to_line, to_line),
+            "Error moving line in",
+        ),
+    };
+    match result {
+        Ok(()) => report.ok(file_path, &message, Some(span), Some(1)),
+        Err(e) => report.error(file_path, context, e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute a line join (`column` is None) or split
+fn execute_join_or_split(
+    file_path: &str,
+    line_number: usize,
+    column: Option<usize>,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (result, message, span, context) = match column {
+        None => (
+            join_line_with_options(file_path, line_number, options),
+            format!(
+                "Successfully joined lines {} and {}",
+                line_number,
+                line_number + 1
+            ),
+            (line_number, line_number),
+            "Error joining lines in",
+        ),
+        Some(column) => (
+            split_line_with_options(file_path, line_number, column, options),
+            format!("Successfully split line {} at byte {}", line_number, column),
+            (line_number, line_number + 1),
+            "Error splitting line in",
+        ),
+    };
+    match result {
+        Ok(()) => report.ok(file_path, &message, Some(span), Some(1)),
+        Err(e) => report.error(file_path, context, e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute a comment style conversion
+fn execute_convert_style(
+    file_path: &str,
+    line_number: usize,
+    to: CommentStyle,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match convert_comment_style_with_options(file_path, line_number, to, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!(
+                "Successfully converted line {} to a {} comment",
+                line_number,
+                if to == CommentStyle::Block {
+                    "block"
+                } else {
+                    "line"
+                }
+            ),
+            Some((line_number, line_number)),
+            Some(1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error converting comment style in",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute a file-wide comment style conversion
+fn execute_convert_style_file(
+    file_path: &str,
+    to: CommentStyle,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match convert_comment_style_file_with_options(file_path, to, options) {
+        Ok(converted) => report.ok(
+            file_path,
+            &format!("Successfully converted {} comments", converted),
+            None,
+            Some(converted),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error converting comment style in",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute comment stripping (in place, or into `output`)
+fn execute_strip_comments(
+    file_path: &str,
+    output: Option<&str>,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match strip_line_comments_with_options(file_path, output.map(Path::new), options) {
+        Ok(Some(lines)) => report.ok(
+            file_path,
+            &format!(
+                "Successfully removed {} comment lines{}",
+                lines,
+                output.map_or(String::new(), |path| format!(" (written to {})", path))
+            ),
+            None,
+            Some(lines),
+        ),
+        Ok(None) => report.unchanged(
+            file_path,
+            "Unsupported file type for comment stripping (file unchanged)",
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error stripping comments from",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute file style detection (prints key: value lines on stdout)
+fn execute_detect(file_path: &str, report: &Report) -> i32 {
+    match detect_file_style(file_path) {
+        Ok(style) => {
+            let values = [
+                ("line_ending", style.line_ending.name()),
+                (
+                    "final_newline",
+                    if style.final_newline { "yes" } else { "no" },
+                ),
+                ("indent", style.indent.name()),
+                ("comment_flag", style.comment_flag.unwrap_or("none")),
+            ];
+            for (key, value) in values {
+                report.value(file_path, key, value, &format!("{}: {}", key, value));
+            }
+            0
+        }
+        Err(e) => report.error(
+            file_path,
+            "Error detecting style of",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute relative line query (prints anchor + offset on stdout)
+fn execute_relative(file_path: &str, anchor: &str, offset: &str, report: &Report) -> i32 {
+    let last_line = match last_line_index(file_path) {
+        Ok(line) => line,
+        Err(e) => return report.error(file_path, "Error reading", e, error_to_exit_code(e)),
+    };
+    let anchor = if anchor == "$" {
+        last_line
+    } else {
+        match parse_line_number(anchor, "anchor_line") {
+            Ok(n) => n,
+            Err(_) => return 1,
+        }
+    };
+
+    match offset_line(anchor, offset, last_line) {
+        Some(Ok(line)) => {
+            let value = line.to_string();
+            report.value(file_path, "line", &value, &value);
+            0
+        }
+        Some(Err(message)) => report.error(
+            file_path,
+            "Error resolving line in",
+            message,
+            ExitCode::NotFound.code(),
+        ),
+        None => 1,
+    }
+}
+
+/// Execute comment column query (prints the column on stdout)
+fn execute_column_query(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match comment_column_in_file(file_path, line_number, options) {
+        Ok(column) => {
+            let value = match column {
+                Some(column) => column.to_string(),
+                None => "none".to_string(),
+            };
+            report.value(file_path, "column", &value, &value);
+            0
+        }
+        Err(e) => report.error(
+            file_path,
+            "Error querying column in",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute comment state query for a range (one symbol per line, or JSON)
+fn execute_query_range(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    json: bool,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let mut symbols = String::new();
+    match comment_states_in_range(file_path, start_line, end_line, options, |state| {
+        symbols.push(state.symbol())
+    }) {
+        Ok(Some(_)) => {
+            let shown = if json {
+                let quoted: Vec<String> = symbols.chars().map(|c| format!("\"{}\"", c)).collect();
+                format!("[{}]", quoted.join(","))
+            } else {
+                symbols.clone()
+            };
+            report.value(file_path, "states", &symbols, &shown);
+            0
+        }
+        Ok(None) => {
+            let shown = if json { "null" } else { "none" };
+            report.value(file_path, "states", "none", shown);
+            0
+        }
+        Err(e) => report.error(
+            file_path,
+            "Error querying comment states in",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute a batch query: one `line` record per line of the ranges
+fn execute_query_lines(
+    file_path: &str,
+    ranges: &[(usize, usize)],
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match inspect_lines(file_path, ranges, options, |line, info| {
+        let line = line.to_string();
+        let state = info.comment_state().symbol().to_string();
+        let leading = (info.leading_spaces + info.leading_tabs).to_string();
+        let flag = info.flag_kind.unwrap_or("none");
+        if report.porcelain {
+            report.record("line", file_path, &[&line, &state, &leading, flag]);
+        } else {
+            print_line(format_args!("{}: {} {} {}", line, state, leading, flag));
+        }
+    }) {
+        Ok(_) => 0,
+        Err(e) => report.error(
+            file_path,
+            "Error querying lines in",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute comment lint: one `<file>:<line>:` line per run of commented lines
+///
+/// Text output is one-based (grep / CI annotation style); porcelain records
+/// are zero-indexed like every other record (`value commented_run first-last`).
+fn execute_lint_comments(
+    file_path: &str,
+    max_run: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let shown_path = display_path(Path::new(file_path));
+    match find_commented_runs(file_path, max_run, options, |first, last| {
+        let message = format!(
+            "{}:{}: {} consecutive commented-out lines (lines {}-{})",
+            shown_path,
+            first + 1,
+            last - first + 1,
+            first + 1,
+            last + 1
+        );
+        report.value(
+            file_path,
+            "commented_run",
+            &format!("{}-{}", first, last),
+            &message,
+        );
+    }) {
+        Ok(_) => 0,
+        Err(e) => report.error(
+            file_path,
+            "Error linting comments in",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute --verify-only: list what changed since the backup, checking that
+/// it is only comments and indentation
+fn execute_verify_only(file_path: &str, options: &ToggleOptions, report: &Report) -> i32 {
+    let result = verify_against_backup(file_path, options, &mut |run| {
+        if run.lines == 0 {
+            report.value(
+                file_path,
+                "removed_before",
+                &run.line.to_string(),
+                &format!(
+                    "Before line {}: {} block marker line(s) removed",
+                    run.line, run.backup_lines
+                ),
+            );
+        } else {
+            let last = run.line + run.lines - 1;
+            report.value(
+                file_path,
+                "changed",
+                &format!("{}-{}", run.line, last),
+                &format!(
+                    "Lines {}-{}: comments / indentation only ({} line(s) in the backup)",
+                    run.line, last, run.backup_lines
+                ),
+            );
+        }
+    });
+    match result {
+        Ok(runs) => report.ok(
+            file_path,
+            &format!(
+                "Verified {}: {} changed run(s) since the backup, comments and indentation only",
+                file_path, runs
+            ),
+            None,
+            Some(runs),
+        ),
+        Err(e) => report.error(file_path, "Error verifying", e, patch_error_to_exit_code(e)),
+    }
+}
+
+/// Execute undo: restore the file from its backup (checksum verified first)
+fn execute_undo(file_path: &str, options: &ToggleOptions, report: &Report) -> i32 {
+    match restore_backup(Path::new(file_path), options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!("Restored {} from its backup (checksum verified)", file_path),
+            None,
+            None,
+        ),
+        Err(e) => report.error(file_path, "Error restoring", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute session undo: restore every file of a session bundle
+fn execute_undo_session(session_dir: &str, options: &ToggleOptions, report: &Report) -> i32 {
+    match undo_session(Path::new(session_dir), options) {
+        Ok(count) => report.ok(
+            session_dir,
+            &format!("Restored {} file(s) from {}", count, session_dir),
+            None,
+            Some(count),
+        ),
+        Err(e) => report.error(
+            session_dir,
+            "Error restoring session",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute --clean-temp: remove (or with --dry-run list) orphaned temp files
+///
+/// # Arguments
+/// * `dir` - Directory to clean (`.` if none given)
+/// * `dry_run` - Only list the files
+///
+/// # Returns
+/// * Exit code (0, or 2 / 6 / 10 if the directory could not be read or a
+///   file not removed)
+fn execute_clean_temp(dir: &str, dry_run: bool, report: &Report) -> i32 {
+    let orphans = match find_orphan_temp_files(Path::new(dir)) {
+        Ok(orphans) => orphans,
+        Err(e) => return report.error(dir, "Error reading", &e, io_error_exit_code(&e)),
+    };
+
+    let mut exit_code = 0;
+    let mut removed: usize = 0;
+    for orphan in &orphans {
+        let shown = display_path(&orphan.path);
+        if dry_run {
+            report.value(
+                dir,
+                "orphan",
+                &shown,
+                &format!("Would remove {} (pid {})", shown, orphan.pid),
+            );
+            continue;
+        }
+        match std::fs::remove_file(&orphan.path) {
+            Ok(()) => {
+                removed += 1;
+                report.value(dir, "removed", &shown, &format!("Removed {}", shown));
+            }
+            Err(e) => {
+                let code = report.error(&shown, "Error removing", &e, io_error_exit_code(&e));
+                if exit_code == 0 {
+                    exit_code = code;
+                }
+            }
+        }
+    }
+
+    let summary = if dry_run {
+        format!("{} orphaned temp file(s) found (dry run)", orphans.len())
+    } else {
+        format!("Removed {} orphaned temp file(s)", removed)
+    };
+    let count = if dry_run { orphans.len() } else { removed };
+    let ok_code = report.ok(dir, &summary, None, Some(count));
+    if exit_code == 0 { ok_code } else { exit_code }
+}
+
+/// Exit code for a std::io error outside the library (2 missing, 10 permission, else 6)
+fn io_error_exit_code(error: &std::io::Error) -> i32 {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => ExitCode::FileNotFound.code(),
+        std::io::ErrorKind::PermissionDenied => ExitCode::PermissionDenied.code(),
+        _ => ExitCode::Io.code(),
+    }
+}
+
+/// Execute line inspection (prints key: value lines on stdout)
+fn execute_inspect(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match inspect_line(file_path, line_number, options) {
+        Ok(info) => {
+            let yes_no = |flag: bool| if flag { "yes" } else { "no" }.to_string();
+            let values = [
+                ("byte_len", info.byte_len.to_string()),
+                ("leading_spaces", info.leading_spaces.to_string()),
+                ("leading_tabs", info.leading_tabs.to_string()),
+                ("has_flag", yes_no(info.has_flag)),
+                ("flag_kind", info.flag_kind.unwrap_or("none").to_string()),
+                ("ends_with_crlf", yes_no(info.ends_with_crlf)),
+            ];
+            for (key, value) in values {
+                report.value(file_path, key, &value, &format!("{}: {}", key, value));
+            }
+            0
         }
+        Err(e) => report.error(file_path, "Error inspecting", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute block comment toggle
+fn execute_block_toggle(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (start_line, end_line) = ordered_range(start_line, end_line);
+    match toggle_block_comment_bytewise_with_options(file_path, start_line, end_line, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!(
+                "Successfully toggled block comment (lines {}-{})",
+                start_line, end_line
+            ),
+            Some((start_line, end_line)),
+            Some(end_line - start_line + 1),
+        ),
+        Err(e) => report.error(file_path, "Error toggling block", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute Perl POD block toggle
+fn execute_pod_toggle(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (start_line, end_line) = ordered_range(start_line, end_line);
+    match toggle_pod_block_bytewise_with_options(file_path, start_line, end_line, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!(
+                "Successfully toggled POD block (lines {}-{})",
+                start_line, end_line
+            ),
+            Some((start_line, end_line)),
+            Some(end_line - start_line + 1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error toggling POD block",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute the interactive picker, then apply its queued edits in order
+///
+/// Stops at the first failed edit; the ones before it stay applied.
+fn execute_tui(file_path: &str, options: &ToggleOptions, report: &Report) -> i32 {
+    if !(std::io::stdin().is_terminal() && std::io::stdout().is_terminal()) {
+        eprintln!("Error: --tui needs a terminal on stdin and stdout");
+        return 1;
+    }
+    let (rows, columns) = terminal::size().unwrap_or((24, 80));
+    let Some(saved) = terminal::enter_raw() else {
+        eprintln!("Error: --tui cannot switch the terminal to raw mode (needs stty)");
+        return 1;
+    };
+
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let picked = run_tui(
+        &mut stdin.lock(),
+        &mut stdout.lock(),
+        file_path,
+        rows,
+        columns,
+    );
+    terminal::restore(&saved);
+
+    let edits = match picked {
+        Ok(Some(edits)) => edits,
+        Ok(None) => return report.ok(file_path, "Discarded queued edits", None, Some(0)),
+        Err(e) => return report.error(file_path, "Error reading", e, error_to_exit_code(e)),
+    };
+    if edits.is_empty() {
+        return report.ok(file_path, "No edits queued", None, Some(0));
+    }
+
+    for queued in edits {
+        let exit_code = match queued.edit {
+            TuiEdit::Comment => {
+                execute_range_toggle_basic(file_path, queued.start, queued.end, options, report)
+            }
+            TuiEdit::Indent => {
+                execute_indent_range(file_path, queued.start, queued.end, options, report)
+            }
+            TuiEdit::Unindent => {
+                execute_unindent_range(file_path, queued.start, queued.end, options, report)
+            }
+        };
+        if exit_code != 0 {
+            return exit_code;
+        }
+    }
+    0
+}
+
+/// Execute indent on a range of lines
+fn execute_indent_range(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (start_line, end_line) = ordered_range(start_line, end_line);
+    match indent_range_bytewise_with_options(file_path, start_line, end_line, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!("Successfully indented lines {} to {}", start_line, end_line),
+            Some((start_line, end_line)),
+            Some(end_line - start_line + 1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error indenting range",
+            e,
+            indent_error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute unindent on a range of lines
+fn execute_unindent_range(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let (start_line, end_line) = ordered_range(start_line, end_line);
+    match unindent_range_bytewise_with_options(file_path, start_line, end_line, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!(
+                "Successfully unindented lines {} to {}",
+                start_line, end_line
+            ),
+            Some((start_line, end_line)),
+            Some(end_line - start_line + 1),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error unindenting range",
+            e,
+            indent_error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute batch toggle - basic comments
+fn execute_batch_toggle_standard(
+    file_path: &str,
+    lines: &[usize],
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let count = lines.len();
+
+    match toggle_multiple_basic_comments_with_options(file_path, lines, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!("Successfully toggled {} lines", count),
+            None,
+            Some(count),
+        ),
+        Err(e) => report.error(file_path, "Error batch toggling", e, error_to_exit_code(e)),
+    }
+}
+
+/// Execute batch toggle - docstrings
+fn execute_batch_toggle_docstring(
+    file_path: &str,
+    lines: &[usize],
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let count = lines.len();
+
+    match toggle_multiple_singleline_docstrings_with_options(file_path, lines, options) {
+        Ok(()) => report.ok(
+            file_path,
+            &format!("Successfully toggled {} docstrings", count),
+            None,
+            Some(count),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error batch toggling docstrings",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Execute batch toggle with line numbers streamed from stdin
+///
+/// # Arguments
+/// * `file_path` - Path to source file
+/// * `max_lines` - Upper bound on line numbers accepted from stdin
+/// * `docstring` - Toggle `///` instead of the extension's basic flag
+fn execute_batch_toggle_stdin(
+    file_path: &str,
+    max_lines: usize,
+    docstring: bool,
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+
+    let result = if docstring {
+        toggle_multiple_singleline_docstrings_from_reader_with_options(
+            file_path, &mut input, max_lines, options,
+        )
+    } else {
+        toggle_multiple_basic_comments_from_reader_with_options(
+            file_path, &mut input, max_lines, options,
+        )
+    };
+
+    match result {
+        Ok(count) => report.ok(
+            file_path,
+            &format!("Successfully toggled {} lines from stdin", count),
+            None,
+            Some(count),
+        ),
+        Err(e) => report.error(
+            file_path,
+            "Error batch toggling from stdin",
+            e,
+            error_to_exit_code(e),
+        ),
+    }
+}
+
+/// Longest grep line prefix searched for `file:line:` (rest of the line skipped)
+const MAX_GREP_PREFIX_BYTES: usize = 4096;
+
+/// Split a `grep -n` style line into its file and one-based line number
+///
+/// The file is everything before the first `:<digits>:`, so paths holding
+/// other colons (`C:\src\a.rs:12:...`) still parse.
+///
+/// # Returns
+/// * `Some((file, line))` - `line` as printed by grep (one-based)
+/// * `None` - Not a `file:line:` match line (context line, `--` separator)
+fn parse_grep_entry(line: &str) -> Option<(&str, usize)> {
+    for (colon, _) in line.match_indices(':') {
+        let rest = &line[colon + 1..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if colon > 0 && digits > 0 && rest.as_bytes().get(digits) == Some(&b':') {
+            return Some((&line[..colon], rest[..digits].parse().ok()?));
+        }
+    }
+    None
+}
+
+/// Read `file:line:` entries from grep / ripgrep `-n` output, grouped by file
+///
+/// Files keep the order of their first entry; grep's one-based numbers are
+/// converted to zero-indexed. Lines not in that form are skipped.
+///
+/// # Returns
+/// * `Ok(groups)` - At least one entry
+/// * `Err(())` - Read failed, no entries, line number 0, or more than
+///   MAX_TOTAL_BATCH_LINES entries (error already printed)
+fn read_grep_entries<R: BufRead>(input: &mut R) -> Result<Vec<(String, Vec<usize>)>, ()> {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    let mut entries: usize = 0;
+
+    // Each iteration consumes one input line; the entry bound stops runaway input
+    loop {
+        let mut prefix: Vec<u8> = Vec::new();
+        let read = match input
+            .by_ref()
+            .take(MAX_GREP_PREFIX_BYTES as u64)
+            .read_until(b'\n', &mut prefix)
+        {
+            Ok(n) => n,
+            Err(e) => {
+                eprintln!("Error: reading grep output from stdin: {}", e);
+                return Err(());
+            }
+        };
+        if read == 0 {
+            break;
+        }
+
+        // Long match line: the prefix is enough, skip to the next line
+        if prefix.last() != Some(&b'\n') {
+            let mut rest: Vec<u8> = Vec::new();
+            if input.read_until(b'\n', &mut rest).is_err() {
+                eprintln!("Error: reading grep output from stdin");
+                return Err(());
+            }
+        }
+
+        let text = String::from_utf8_lossy(&prefix);
+        let (file, line) = match parse_grep_entry(&text) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        if line == 0 {
+            eprintln!("Error: grep line numbers start at 1 (got {}:0:)", file);
+            return Err(());
+        }
+
+        entries += 1;
+        if entries > MAX_TOTAL_BATCH_LINES {
+            eprintln!("Error: Too many lines (max {})", MAX_TOTAL_BATCH_LINES);
+            return Err(());
+        }
+
+        match groups.iter_mut().find(|(name, _)| name == file) {
+            Some((_, lines)) => lines.push(line - 1),
+            None => groups.push((file.to_string(), vec![line - 1])),
+        }
+    }
+
+    if groups.is_empty() {
+        eprintln!("Error: no file:line: entries on stdin (use grep -n / rg -n)");
+        return Err(());
+    }
+
+    Ok(groups)
+}
+
+/// Execute batch toggles from grep output on stdin: one batch per file
+///
+/// Every file is attempted; the exit code is the first failure's (0 if none).
+fn execute_grep_toggle(options: &ToggleOptions, report: &Report) -> i32 {
+    let stdin = std::io::stdin();
+    let groups = match read_grep_entries(&mut stdin.lock()) {
+        Ok(groups) => groups,
+        Err(_) => return 1,
+    };
+
+    // All originals also go into one session bundle (--undo-session), made
+    // when the first file is backed up
+    let mut session: Option<BackupSession> = None;
+    let mut exit_code = 0;
+    for (file_path, lines) in &groups {
+        if cancel_requested() {
+            if timed_out() {
+                eprintln!("Timed out: remaining files not toggled");
+            } else {
+                eprintln!("Cancelled: remaining files not toggled");
+            }
+            if exit_code == 0 {
+                exit_code = cancelled_exit_code();
+            }
+            break;
+        }
+        let recorded = match session.as_mut() {
+            Some(session) => session.add(Path::new(file_path)),
+            None => BackupSession::create_in(Path::new(".")).and_then(|mut created| {
+                created.add(Path::new(file_path))?;
+                session = Some(created);
+                Ok(())
+            }),
+        };
+        let code = match recorded {
+            Ok(()) => execute_batch_toggle_standard(file_path, lines, options, report),
+            Err(e) => report.error(file_path, "Error backing up", e, error_to_exit_code(e)),
+        };
+        if exit_code == 0 {
+            exit_code = code;
+        }
+    }
+
+    if let Some(session) = &session {
+        eprintln!(
+            "Session backup: {} ({} files; restore with --undo-session)",
+            display_path(session.dir()),
+            session.files()
+        );
+    }
+    exit_code
+}
+
+/// Largest document `--stdin-content` reads (it is held in memory twice)
+const MAX_STDIN_CONTENT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How `--stdin-content` picks the comment flag
+enum StdinContentFlag {
+    /// --ext <ext>: the flag a file with this extension uses
+    Extension(String),
+
+    /// --custom-flag <flag>: this flag
+    Flag(CommentFlag),
+}
+
+/// Execute `--stdin-content`: toggle one line of stdin, print the result
+///
+/// # Arguments
+/// * `line_number` - Line to toggle (`--line`)
+/// * `flag` - From `--ext` or `--custom-flag`
+///
+/// # Returns
+/// * Exit code: 0 (also for an unsupported extension, content printed
+///   unchanged), 13 input too large, or the operation's error code
+fn execute_stdin_content(
+    line_number: usize,
+    flag: StdinContentFlag,
+    options: &ToggleOptions,
+) -> i32 {
+    // Bounded read: one byte over the limit is enough to refuse
+    let max_bytes = options
+        .max_file_size
+        .map_or(MAX_STDIN_CONTENT_BYTES, |max| {
+            max.min(MAX_STDIN_CONTENT_BYTES)
+        });
+    let mut content: Vec<u8> = Vec::new();
+    if let Err(e) = std::io::stdin()
+        .lock()
+        .take(max_bytes + 1)
+        .read_to_end(&mut content)
+    {
+        eprintln!("Error: reading stdin: {}", e);
+        return ExitCode::Io.code();
+    }
+    if content.len() as u64 > max_bytes {
+        eprintln!("Error: stdin content is larger than {} bytes", max_bytes);
+        return ExitCode::FileTooLarge.code();
     }
-}
 
-/// Execute basic single-line comment toggle
-fn execute_basic_toggle(file_path: &str, line_number: usize) -> i32 {
-    match toggle_basic_singleline_comment_bytewise(file_path, line_number) {
-        Ok(()) => {
-            println!("Successfully toggled comment on line {}", line_number);
-            0
+    // Toggle into memory first: a failed toggle prints nothing on stdout
+    let mut toggled: Vec<u8> = Vec::with_capacity(content.len() + 16);
+    let result = match &flag {
+        StdinContentFlag::Extension(extension) => {
+            toggle_lines_in_buffer(&content, extension, &[line_number], options, &mut toggled)
         }
-        Err(e) => {
-            eprintln!("Error toggling {}: {}", file_path, e);
-            error_to_exit_code(e)
+        StdinContentFlag::Flag(flag) => {
+            toggle_lines_in_buffer_with_flag(&content, *flag, &[line_number], options, &mut toggled)
+                .map(|()| true)
         }
-    }
-}
+    };
 
-/// Execute Rust docstring single-line comment toggle
-fn execute_docstring_toggle(file_path: &str, line_number: usize) -> i32 {
-    match toggle_rust_docstring_singleline_comment_bytewise(file_path, line_number) {
-        Ok(()) => {
-            println!("Successfully toggled docstring on line {}", line_number);
-            0
-        }
+    let output: &[u8] = match result {
+        Ok(true) => &toggled,
+        Ok(false) => &content,
         Err(e) => {
-            eprintln!("Error toggling docstring {}: {}", file_path, e);
-            error_to_exit_code(e)
+            eprintln!("Error toggling stdin content: {}", e);
+            return error_to_exit_code(e);
         }
+    };
+
+    if Stdout
+        .write_all(output)
+        .and_then(|()| Stdout.flush())
+        .is_err()
+    {
+        return ExitCode::Io.code();
     }
+    0
 }
 
-/// Execute block comment toggle
-fn execute_block_toggle(file_path: &str, start_line: usize, end_line: usize) -> i32 {
-    match toggle_block_comment_bytewise(file_path, start_line, end_line) {
-        Ok(()) => {
-            println!(
-                "Successfully toggled block comment (lines {}-{})",
-                start_line, end_line
-            );
-            0
-        }
-        Err(e) => {
-            eprintln!("Error toggling block {}: {}", file_path, e);
-            error_to_exit_code(e)
-        }
+/// Check the optional `[max_lines]` argument following `--stdin-lines`
+///
+/// # Returns
+/// * `Ok(max_lines)` - The value, or DEFAULT_MAX_STREAMED_LINES when absent
+/// * `Err(())` - Above MAX_TOTAL_BATCH_LINES (error already printed)
+fn checked_stdin_max_lines(max_lines: Option<usize>) -> Result<usize, ()> {
+    let max_lines = max_lines.unwrap_or(DEFAULT_MAX_STREAMED_LINES);
+
+    if max_lines > MAX_TOTAL_BATCH_LINES {
+        eprintln!("Error: max_lines must be at most {}", MAX_TOTAL_BATCH_LINES);
+        eprintln!();
+        return Err(());
     }
+
+    Ok(max_lines)
 }
 
-/// Execute indent on a range of lines
-fn execute_indent_range(file_path: &str, start_line: usize, end_line: usize) -> i32 {
-    match indent_range_bytewise(file_path, start_line, end_line) {
-        Ok(()) => {
-            println!("Successfully indented lines {} to {}", start_line, end_line);
-            0
-        }
+/// Options accepted anywhere on the command line, for every mode
+#[derive(Debug, Default)]
+struct GlobalOptions {
+    /// --force-readonly: clear a read-only attribute for the edit, restore after
+    force_readonly: bool,
+
+    /// --porcelain: versioned tab-separated records instead of messages
+    porcelain: bool,
+
+    /// --emit-sed / --emit-patch: print the planned changes, edit nothing
+    emit: Option<EmitFormat>,
+
+    /// --preview: page the planned changes and write only when confirmed
+    preview: bool,
+
+    /// --timeout <seconds>: stop operations still running after this long
+    timeout: Option<Duration>,
+
+    /// --no-config: skip the .toggle_comment.toml search
+    no_config: bool,
+
+    /// --preset <name>: settings bundle applied under the other flags
+    preset: Option<String>,
+
+    /// Options passed to every library operation
+    /// (--max-file-size <bytes>, --no-size-limit, --temp-dir <dir>, ...)
+    toggle: ToggleOptions,
+}
+
+/// Library options below the flags: defaults, project config, environment,
+/// then the --preset
+///
+/// The config search starts at the target (first argument after the mode
+/// flag, or first in basic mode; the literal comes first for
+/// --at-line-containing), or the working directory for modes without one.
+///
+/// # Returns
+/// * `Ok(options)` - Layered options (see `resolve_options()`)
+/// * `Err(1)` - Config, environment variable or preset invalid (error printed)
+fn base_options(args: &[String], options: &GlobalOptions) -> Result<ToggleOptions, i32> {
+    let env = match env_config(|name| env::var(name).ok()) {
+        Ok(layer) => layer,
         Err(e) => {
-            eprintln!("Error indenting range {}: {}", file_path, e);
-            indent_error_to_exit_code(e)
+            eprintln!("Error: {}", e);
+            return Err(1);
         }
-    }
-}
+    };
+    let project = project_config(args, options)?;
+    let mut base = resolve_options(project.as_ref(), &env);
 
-/// Execute unindent on a range of lines
-fn execute_unindent_range(file_path: &str, start_line: usize, end_line: usize) -> i32 {
-    match unindent_range_bytewise(file_path, start_line, end_line) {
-        Ok(()) => {
-            println!(
-                "Successfully unindented lines {} to {}",
-                start_line, end_line
-            );
-            0
+    if let Some(name) = &options.preset {
+        match find_preset(name, project.as_ref()) {
+            Some(preset) => preset.apply(&mut base),
+            None => {
+                let shipped: Vec<&str> = BUILTIN_PRESETS.iter().map(|(name, _)| *name).collect();
+                eprintln!(
+                    "Error: unknown preset {} (shipped: {}; more as [presets.<name>] in {})",
+                    name,
+                    shipped.join(", "),
+                    CONFIG_FILE_NAME
+                );
+                return Err(1);
+            }
         }
-        Err(e) => {
-            eprintln!("Error unindenting range {}: {}", file_path, e);
-            indent_error_to_exit_code(e)
-        }
-    }
-}
-
-// /// Execute batch toggle - basic comments
-// fn execute_batch_toggle_standard(
-//     file_path: &str,
-//     count: usize,
-//     lines: &[usize; MAX_BATCH_LINES],
-// ) -> i32 {
-//     // Use only the valid portion of array
-//     let line_slice = &lines[..count];
-
-//     match toggle_multiple_basic_comments(file_path, line_slice) {
-//         Ok(()) => {
-//             println!("Successfully toggled {} lines", count);
-//             0
-//         }
-//         Err(e) => {
-//             eprintln!("Error batch toggling {}: {}", file_path, e);
-//             error_to_exit_code(e)
-//         }
-//     }
-// }
-
-// /// Execute batch toggle - docstrings
-// fn execute_batch_toggle_docstring(
-//     file_path: &str,
-//     count: usize,
-//     lines: &[usize; MAX_BATCH_LINES],
-// ) -> i32 {
-//     // Use only the valid portion of array
-//     let line_slice = &lines[..count];
-
-//     match toggle_multiple_singline_docstrings(file_path, line_slice) {
-//         Ok(()) => {
-//             println!("Successfully toggled {} docstrings", count);
-//             0
-//         }
-//         Err(e) => {
-//             eprintln!("Error batch toggling docstrings {}: {}", file_path, e);
-//             error_to_exit_code(e)
-//         }
-//     }
-// }
+    }
+    Ok(base)
+}
 
-fn main() {
-    // Collect command line arguments
-    let args: Vec<String> = env::args().collect();
+/// The project config that applies to this run (see `base_options()`)
+///
+/// # Returns
+/// * `Ok(None)` - --no-config, or no config found
+/// * `Err(1)` - Config found but invalid (error printed)
+fn project_config(args: &[String], options: &GlobalOptions) -> Result<Option<ConfigLayer>, i32> {
+    if options.no_config {
+        return Ok(None);
+    }
+    let target = args
+        .get(1)
+        .and_then(|arg| find_mode(MODES, arg))
+        .and_then(|mode| mode.target_index(args));
+    let start = target.map_or(Path::new("."), |index| Path::new(args[index].as_str()));
 
-    // Minimum: program name + at least 2 args
-    if args.len() < 3 {
-        eprintln!("Error: Invalid number of arguments");
-        eprintln!();
-        print_usage();
-        process::exit(1);
+    let path = match find_project_config(start) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    match load_project_config(&path) {
+        Ok(config) => Ok(Some(config)),
+        Err(e) => {
+            eprintln!("Error in {}: {}", display_path(&path), e);
+            Err(1)
+        }
     }
+}
 
-    // Determine mode based on first argument
-    let exit_code = if args[1].starts_with("--") {
-        // Flag-based mode
-        let flag = &args[1];
+/// Split global options out of the argument list
+///
+/// # Arguments
+/// * `base` - Library options the flags are applied to (defaults, or
+///   `base_options()`)
+///
+/// # Returns
+/// * `Ok((args, options))` - Remaining arguments (program name first) and parsed options
+/// * `Err(())` - Invalid option value (error already printed)
+fn split_global_options(
+    raw_args: Vec<String>,
+    base: ToggleOptions,
+) -> Result<(Vec<String>, GlobalOptions), ()> {
+    let mut options = GlobalOptions {
+        toggle: base,
+        ..GlobalOptions::default()
+    };
+    let mut args: Vec<String> = Vec::with_capacity(raw_args.len());
+    let mut raw_iter = raw_args.into_iter();
 
-        match flag.as_str() {
-            "--rust-doc-string" => {
-                // Expect: --rust-doc-string <file> <line>
-                if args.len() != 4 {
-                    eprintln!("Error: --rust-doc-string requires <file_path> <line_number>");
-                    eprintln!();
-                    print_usage();
-                    process::exit(1);
+    while let Some(arg) = raw_iter.next() {
+        match arg.as_str() {
+            "--force-readonly" => options.force_readonly = true,
+            "--force" => options.toggle.allow_own_artifacts = true,
+            "--allow-any-extension" => options.toggle.allow_any_extension = true,
+            "--allow-heredoc" => options.toggle.allow_heredoc = true,
+            "--porcelain" => options.porcelain = true,
+            "--emit-sed" => options.emit = Some(EmitFormat::Sed),
+            "--emit-patch" => options.emit = Some(EmitFormat::Patch),
+            "--preview" => options.preview = true,
+            "--no-config" => options.no_config = true,
+            "--preset" => match raw_iter.next() {
+                Some(name) => options.preset = Some(name),
+                None => {
+                    eprintln!("Error: --preset requires <name>");
+                    return Err(());
                 }
-
-                let file_path = &args[2];
-                let line_number = match parse_line_number(&args[3], "line_number") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
+            },
+            "--skip-blank" => options.toggle.skip_blank = true,
+            "--indent-tab" => options.toggle.indent_tab = true,
+            "--utf8-strict" => options.toggle.utf8_strict = true,
+            "--no-size-limit" => options.toggle.max_file_size = None,
+            "--preserve-indent" => options.toggle.preserve_indent = true,
+            "--relaxed-removal" => options.toggle.removal = RemovalMode::Relaxed,
+            "--trace" => options.toggle.trace = TraceFormat::Text,
+            "--trace-json" => options.toggle.trace = TraceFormat::Json,
+            "--trim-trailing" => options.toggle.trim_trailing = true,
+            "--no-backup" => options.toggle.no_backup = true,
+            "--backup-dir" => match raw_iter.next() {
+                Some(dir) if Path::new(&dir).is_dir() => {
+                    options.toggle.backup_dir = Some(dir.into());
+                }
+                Some(dir) => {
+                    eprintln!("Error: --backup-dir {} is not a directory", dir);
+                    return Err(());
+                }
+                None => {
+                    eprintln!("Error: --backup-dir requires <dir>");
+                    return Err(());
+                }
+            },
+            "--indent-width" => match raw_iter.next().map(|v| v.parse::<usize>()) {
+                Some(Ok(width)) if (1..=MAX_INDENT_WIDTH).contains(&width) => {
+                    options.toggle.indent_width = width;
+                }
+                _ => {
+                    eprintln!("Error: --indent-width requires 1..={}", MAX_INDENT_WIDTH);
+                    return Err(());
+                }
+            },
+            "--style" => {
+                options.toggle.line_style = match raw_iter.next().as_deref() {
+                    Some("c89") => LineStyle::C89,
+                    Some("default") => LineStyle::Default,
+                    _ => {
+                        eprintln!("Error: --style requires c89 or default");
+                        return Err(());
                     }
                 };
-
-                execute_docstring_toggle(file_path, line_number)
             }
-
-            "--block" => {
-                // Expect: --block <file> <start_line> <end_line>
-                if args.len() != 5 {
-                    eprintln!("Error: --block requires <file_path> <start_line> <end_line>");
-                    eprintln!();
-                    print_usage();
-                    process::exit(1);
+            "--doc-style" => match raw_iter.next().as_deref().and_then(DocStyle::from_name) {
+                Some(style) => options.toggle.doc_style = Some(style),
+                None => {
+                    eprintln!("Error: --doc-style requires doxygen, qt or jsdoc");
+                    return Err(());
                 }
-
-                let file_path = &args[2];
-                let start_line = match parse_line_number(&args[3], "start_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
+            },
+            "--eol-audit" => options.toggle.eol_audit = true,
+            "--progress" => options.toggle.progress = true,
+            "--comment" => options.toggle.direction = LineToggleDirection::Add,
+            "--uncomment" => options.toggle.direction = LineToggleDirection::Remove,
+            "--ensure-final-newline" => options.toggle.final_newline = FinalNewline::Ensure,
+            "--strip-final-newline" => options.toggle.final_newline = FinalNewline::Strip,
+            "--normalize-eol" => {
+                options.toggle.eol = match raw_iter.next().as_deref() {
+                    Some("lf") => EolMode::Lf,
+                    Some("crlf") => EolMode::Crlf,
+                    _ => {
+                        eprintln!("Error: --normalize-eol requires lf or crlf");
+                        return Err(());
                     }
                 };
-                let end_line = match parse_line_number(&args[4], "end_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
+            }
+            "--max-file-size" => {
+                let value = match raw_iter.next() {
+                    Some(v) => v,
+                    None => {
+                        eprintln!("Error: --max-file-size requires <bytes>");
+                        return Err(());
                     }
                 };
-
-                // Validate line order
-                if start_line >= end_line {
-                    eprintln!("Error: start_line must be less than end_line");
-                    process::exit(1);
-                }
-
-                execute_block_toggle(file_path, start_line, end_line)
-            }
-
-            // "--list-basic" => {
-            //     // Expect: --list-basic <file> <line1> <line2> ...
-            //     if args.len() < 4 {
-            //         eprintln!("Error: --list-basic requires <file_path> <line1> [line2] ...");
-            //         eprintln!();
-            //         print_usage();
-            //         process::exit(1);
-            //     }
-
-            //     let file_path = &args[2];
-            //     let line_args = &args[3..];
-
-            //     let (count, line_array) = match parse_line_list(line_args) {
-            //         Ok(result) => result,
-            //         Err(_) => {
-            //             print_usage();
-            //             process::exit(1);
-            //         }
-            //     };
-
-            //     execute_batch_toggle_standard(file_path, count, &line_array)
-            // }
-
-            // "--list-docstring" => {
-            //     // Expect: --list-docstring <file> <line1> <line2> ...
-            //     if args.len() < 4 {
-            //         eprintln!("Error: --list-docstring requires <file_path> <line1> [line2] ...");
-            //         eprintln!();
-            //         print_usage();
-            //         process::exit(1);
-            //     }
-
-            //     let file_path = &args[2];
-            //     let line_args = &args[3..];
-
-            //     let (count, line_array) = match parse_line_list(line_args) {
-            //         Ok(result) => result,
-            //         Err(_) => {
-            //             print_usage();
-            //             process::exit(1);
-            //         }
-            //     };
-
-            //     execute_batch_toggle_docstring(file_path, count, &line_array)
-            // }
-            "--indent" => {
-                // Expect: --indent <file> <line>
-                if args.len() != 4 {
-                    eprintln!("Error: --indent requires <file_path> <line_number>");
-                    eprintln!();
-                    print_usage();
-                    process::exit(1);
-                }
-
-                let file_path = &args[2];
-                let line_number = match parse_line_number(&args[3], "line_number") {
-                    Ok(n) => n,
+                match value.parse::<u64>() {
+                    Ok(bytes) => options.toggle.max_file_size = Some(bytes),
                     Err(_) => {
-                        print_usage();
-                        process::exit(1);
+                        eprintln!("Error: --max-file-size must be a valid integer");
+                        return Err(());
                     }
-                };
-
-                execute_indent(file_path, line_number)
+                }
             }
-
-            "--unindent" => {
-                // Expect: --unindent <file> <line>
-                if args.len() != 4 {
-                    eprintln!("Error: --unindent requires <file_path> <line_number>");
-                    eprintln!();
-                    print_usage();
-                    process::exit(1);
+            "--timeout" => match raw_iter.next().map(|v| v.parse::<u64>()) {
+                Some(Ok(seconds)) if seconds > 0 => {
+                    options.timeout = Some(Duration::from_secs(seconds));
                 }
-
-                let file_path = &args[2];
-                let line_number = match parse_line_number(&args[3], "line_number") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
+                _ => {
+                    eprintln!("Error: --timeout requires <seconds> (a positive integer)");
+                    return Err(());
+                }
+            },
+            "--durability" => {
+                options.toggle.durability = match raw_iter.next().as_deref() {
+                    Some("none") => Durability::None,
+                    Some("flush") => Durability::Flush,
+                    Some("fsync") => Durability::Fsync,
+                    _ => {
+                        eprintln!("Error: --durability requires none, flush or fsync");
+                        return Err(());
                     }
                 };
-
-                execute_unindent(file_path, line_number)
             }
-            "--indent-range" => {
-                // Expect: --indent-range <file> <start_line> <end_line>
-                if args.len() != 5 {
-                    eprintln!("Error: --indent-range requires <file_path> <start_line> <end_line>");
-                    eprintln!();
-                    print_usage();
-                    process::exit(1);
+            "--temp-dir" => match raw_iter.next() {
+                Some(dir) if Path::new(&dir).is_dir() => {
+                    options.toggle.temp_dir = Some(dir.into());
+                }
+                Some(dir) => {
+                    eprintln!("Error: --temp-dir {} is not a directory", dir);
+                    return Err(());
                 }
+                None => {
+                    eprintln!("Error: --temp-dir requires <dir>");
+                    return Err(());
+                }
+            },
+            _ => args.push(arg),
+        }
+    }
 
-                let file_path = &args[2];
-                let start_line = match parse_line_number(&args[3], "start_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
-                    }
-                };
-                let end_line = match parse_line_number(&args[4], "end_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
-                    }
-                };
+    if options.preview && options.emit.is_some() {
+        eprintln!("Error: --preview and --emit-sed / --emit-patch exclude each other");
+        return Err(());
+    }
 
-                // Validate line order
-                if start_line > end_line {
-                    eprintln!("Error: start_line must be less than or equal to end_line");
-                    process::exit(1);
-                }
+    Ok((args, options))
+}
 
-                execute_indent_range(file_path, start_line, end_line)
-            }
+/// Runs a mode on its target file: checked arguments, library options, reporter
+type FileHandler = fn(&ModeArgs, &ToggleOptions, &Report) -> i32;
 
-            "--unindent-range" => {
-                // Expect: --unindent-range <file> <start_line> <end_line>
-                if args.len() != 5 {
-                    eprintln!(
-                        "Error: --unindent-range requires <file_path> <start_line> <end_line>"
-                    );
-                    eprintln!();
-                    print_usage();
-                    process::exit(1);
-                }
+/// Runs a mode without a target file (global options as given)
+type CommandHandler = fn(&ModeArgs, &GlobalOptions, &Report) -> i32;
 
-                let file_path = &args[2];
-                let start_line = match parse_line_number(&args[3], "start_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
-                    }
-                };
-                let end_line = match parse_line_number(&args[4], "end_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
-                    }
-                };
+/// What runs a mode of MODES
+#[derive(Debug, Clone, Copy)]
+enum Run {
+    /// Edits its target; --emit-* / --preview run it on a preview copy
+    Edit(FileHandler),
 
-                // Validate line order
-                if start_line > end_line {
-                    eprintln!("Error: start_line must be less than or equal to end_line");
-                    process::exit(1);
-                }
+    /// Reads its target (or restores its backup): nothing to preview
+    Query(FileHandler),
 
-                execute_unindent_range(file_path, start_line, end_line)
-            }
-            "--toggle-range-comment-basic" => {
-                // Expect: --toggle-range-comment-basic <file> <start_line> <end_line>
-                if args.len() != 5 {
-                    eprintln!(
-                        "Error: --toggle-range-comment-basic requires <file_path> <start_line> <end_line>"
-                    );
-                    eprintln!();
-                    print_usage();
-                    process::exit(1);
-                }
+    /// No target file; runs before the target is looked at (no --emit-*,
+    /// --preview; --force-readonly has no effect)
+    Command(CommandHandler),
+}
 
-                let file_path = &args[2];
-                let start_line = match parse_line_number(&args[3], "start_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
-                    }
-                };
-                let end_line = match parse_line_number(&args[4], "end_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
-                    }
-                };
+/// `<file_path>`: the target of most modes
+const FILE: Arg = Arg::new("file_path", ArgKind::Target);
+const LINE: Arg = Arg::new("line_number", ArgKind::Line);
+const START_LINE: Arg = Arg::new("start_line", ArgKind::Line);
+const END_LINE: Arg = Arg::new("end_line", ArgKind::Line);
 
-                // Note: No validation needed - function auto-sorts and validates
-                execute_range_toggle_basic(file_path, start_line, end_line)
-            }
+/// `<file_path> <line_number>`
+const FILE_LINE: &[Arg] = &[FILE, LINE];
 
-            "--toggle-range-rust-docstring" => {
-                // Expect: --toggle-range-rust-docstring <file> <start_line> <end_line>
-                if args.len() != 5 {
-                    eprintln!(
-                        "Error: --toggle-range-rust-docstring requires <file_path> <start_line> <end_line>"
-                    );
-                    eprintln!();
-                    print_usage();
-                    process::exit(1);
-                }
+/// `<file_path> <start_line> <end_line>`
+const FILE_RANGE: &[Arg] = &[FILE, START_LINE, END_LINE];
 
-                let file_path = &args[2];
-                let start_line = match parse_line_number(&args[3], "start_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
-                    }
-                };
-                let end_line = match parse_line_number(&args[4], "end_line") {
-                    Ok(n) => n,
-                    Err(_) => {
-                        print_usage();
-                        process::exit(1);
-                    }
-                };
+/// `<file_path> --stdin-lines [max_lines]`, then `<file_path> <line_number>...`
+const LIST_FORMS: &[&[Arg]] = &[
+    &[
+        FILE,
+        Arg::switch("--stdin-lines").required(),
+        Arg::new("max_lines", ArgKind::Number).optional(),
+    ],
+    &[FILE, LINE.repeated()],
+];
 
-                // Note: No validation needed - function auto-sorts and validates
-                execute_range_toggle_docstring(file_path, start_line, end_line)
+/// Every mode: arguments, dispatch, USAGE lines, `--capabilities` and
+/// `--completions` (in this order; porcelain records use the names)
+const MODES: &[Mode<Run>] = &[
+    Mode {
+        name: BASIC_MODE,
+        forms: &[FILE_LINE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_basic_toggle(
+                args.text("file_path"),
+                args.number("line_number"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "rust-doc-string",
+        forms: &[FILE_LINE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_docstring_toggle(
+                args.text("file_path"),
+                args.number("line_number"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "block",
+        forms: &[FILE_RANGE],
+        stdin: None,
+        // Either order selects the same lines
+        handler: Run::Edit(|args, options, report| {
+            execute_block_toggle(
+                args.text("file_path"),
+                args.number("start_line"),
+                args.number("end_line"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "pod",
+        forms: &[FILE_RANGE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_pod_toggle(
+                args.text("file_path"),
+                args.number("start_line"),
+                args.number("end_line"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "list-basic",
+        forms: LIST_FORMS,
+        stdin: None,
+        handler: Run::Edit(|args, options, report| run_list(args, false, options, report)),
+    },
+    Mode {
+        name: "list-docstring",
+        forms: LIST_FORMS,
+        stdin: None,
+        handler: Run::Edit(|args, options, report| run_list(args, true, options, report)),
+    },
+    Mode {
+        name: "ranges",
+        forms: &[&[FILE, Arg::new("start-end", ArgKind::Text).repeated()]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            let file_path = args.text("file_path");
+            match parse_range_list(file_path, &args.all("start-end"), report) {
+                Ok(ranges) => execute_ranges_toggle(file_path, &ranges, options, report),
+                Err(exit_code) => exit_code,
             }
-            _ => {
-                eprintln!("Error: Unknown flag: {}", flag);
-                eprintln!();
+        }),
+    },
+    Mode {
+        name: "stdin-content",
+        forms: &[
+            &[
+                Arg::flagged("--line", "line_number", ArgKind::Number),
+                Arg::flagged("--ext", "ext", ArgKind::Text),
+            ],
+            &[
+                Arg::flagged("--line", "line_number", ArgKind::Number),
+                Arg::flagged("--custom-flag", "flag", ArgKind::Text),
+            ],
+        ],
+        stdin: None,
+        handler: Run::Command(run_stdin_content),
+    },
+    Mode {
+        name: "indent",
+        forms: &[FILE_LINE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_indent(
+                args.text("file_path"),
+                args.number("line_number"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "unindent",
+        forms: &[FILE_LINE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_unindent(
+                args.text("file_path"),
+                args.number("line_number"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "indent-range",
+        forms: &[FILE_RANGE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| match increasing_range(args) {
+            Some((start_line, end_line)) => execute_indent_range(
+                args.text("file_path"),
+                start_line,
+                end_line,
+                options,
+                report,
+            ),
+            None => 1,
+        }),
+    },
+    Mode {
+        name: "unindent-range",
+        forms: &[FILE_RANGE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| match increasing_range(args) {
+            Some((start_line, end_line)) => execute_unindent_range(
+                args.text("file_path"),
+                start_line,
+                end_line,
+                options,
+                report,
+            ),
+            None => 1,
+        }),
+    },
+    Mode {
+        name: "toggle-range-comment-basic",
+        forms: &[FILE_RANGE],
+        stdin: None,
+        // The operation sorts and checks the range itself
+        handler: Run::Edit(|args, options, report| {
+            execute_range_toggle_basic(
+                args.text("file_path"),
+                args.number("start_line"),
+                args.number("end_line"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "toggle-range-rust-docstring",
+        forms: &[FILE_RANGE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_range_toggle_docstring(
+                args.text("file_path"),
+                args.number("start_line"),
+                args.number("end_line"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "notebook",
+        forms: &[&[
+            Arg::new("file.ipynb", ArgKind::Target),
+            Arg::new("cell_index", ArgKind::Number),
+            Arg::new("line_in_cell", ArgKind::Number),
+        ]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_notebook_toggle(
+                args.text("file.ipynb"),
+                args.number("cell_index"),
+                args.number("line_in_cell"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "at-line-containing",
+        forms: &[&[
+            Arg::new("literal", ArgKind::Text),
+            FILE,
+            Arg::flagged("--nth", "k", ArgKind::Number).optional(),
+        ]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_content_toggle(
+                args.text("file_path"),
+                args.text("literal"),
+                args.number_opt("k").unwrap_or(0),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "block-from",
+        forms: &[FILE_LINE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_brace_block_toggle(
+                args.text("file_path"),
+                args.number("line_number"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "suite-from",
+        forms: &[&[Arg::new("file.py", ArgKind::Target), LINE]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_suite_toggle(
+                args.text("file.py"),
+                args.number("line_number"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "isolate",
+        forms: &[FILE_RANGE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_isolate(
+                args.text("file_path"),
+                args.number("start_line"),
+                args.number("end_line"),
+                false,
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "undo-isolate",
+        forms: &[FILE_RANGE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_isolate(
+                args.text("file_path"),
+                args.number("start_line"),
+                args.number("end_line"),
+                true,
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "attribute",
+        forms: &[&[
+            Arg::new("file.rs", ArgKind::Target),
+            LINE,
+            Arg::new("attribute", ArgKind::Text),
+        ]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_attribute(
+                args.text("file.rs"),
+                args.number("line_number"),
+                args.text("attribute"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "insert-line",
+        forms: &[&[FILE, LINE, Arg::new("content", ArgKind::Text)]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_line_edit(
+                args.text("file_path"),
+                args.number("line_number"),
+                args.text("content"),
+                true,
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "remove-line",
+        forms: &[&[FILE, LINE, Arg::new("expected_content", ArgKind::Text)]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_line_edit(
+                args.text("file_path"),
+                args.number("line_number"),
+                args.text("expected_content"),
+                false,
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "duplicate",
+        forms: &[FILE_LINE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_duplicate_or_move(
+                args.text("file_path"),
+                args.number("line_number"),
+                None,
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "move",
+        forms: &[&[
+            FILE,
+            Arg::new("from_line", ArgKind::Line),
+            Arg::new("to_line", ArgKind::Line),
+        ]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_duplicate_or_move(
+                args.text("file_path"),
+                args.number("from_line"),
+                args.number_opt("to_line"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "join",
+        forms: &[FILE_LINE],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_join_or_split(
+                args.text("file_path"),
+                args.number("line_number"),
+                None,
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "split",
+        forms: &[&[FILE, LINE, Arg::new("byte_col", ArgKind::Number)]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_join_or_split(
+                args.text("file_path"),
+                args.number("line_number"),
+                args.number_opt("byte_col"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "convert-style",
+        forms: &[&[
+            FILE,
+            LINE,
+            Arg::flagged("--to", "to", ArgKind::Choice("block|line")),
+        ]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_convert_style(
+                args.text("file_path"),
+                args.number("line_number"),
+                comment_style(args.text("to")),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "convert-style-file",
+        forms: &[&[
+            FILE,
+            Arg::flagged("--to", "to", ArgKind::Choice("line|block")),
+        ]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            execute_convert_style_file(
+                args.text("file_path"),
+                comment_style(args.text("to")),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "strip-comments",
+        forms: &[&[
+            FILE,
+            Arg::flagged("--output", "path", ArgKind::Text).optional(),
+        ]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            // A preview copy is diffed in place; an output file would be missed
+            if args.has("path") && report.preview.is_some() {
+                eprintln!("Error: --emit-sed / --emit-patch / --preview cannot preview --output");
+                return 1;
+            }
+            execute_strip_comments(args.text("file_path"), args.get("path"), options, report)
+        }),
+    },
+    Mode {
+        name: "lsp-range",
+        forms: &[&[
+            FILE,
+            Arg::new("startLine", ArgKind::Number),
+            Arg::new("startChar", ArgKind::Number),
+            Arg::new("endLine", ArgKind::Number),
+            Arg::new("endChar", ArgKind::Number),
+        ]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            match lsp_range_to_lines(
+                args.number("startLine"),
+                args.number("endLine"),
+                args.number("endChar"),
+            ) {
+                Some((start_line, end_line)) => execute_range_toggle_basic(
+                    args.text("file_path"),
+                    start_line,
+                    end_line,
+                    options,
+                    report,
+                ),
+                None => {
+                    eprintln!("Error: LSP range end must not be before its start");
+                    1
+                }
+            }
+        }),
+    },
+    Mode {
+        name: "detect",
+        forms: &[&[FILE]],
+        stdin: None,
+        handler: Run::Query(|args, _, report| execute_detect(args.text("file_path"), report)),
+    },
+    Mode {
+        name: "serve",
+        forms: &[&[]],
+        stdin: None,
+        handler: Run::Command(|_, options, _| {
+            let stdin = std::io::stdin();
+            let stdout = std::io::stdout();
+            match serve(&mut stdin.lock(), &mut stdout.lock(), &options.toggle) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("Error serving: {}", e);
+                    ExitCode::Io.code()
+                }
+            }
+        }),
+    },
+    Mode {
+        name: "tui",
+        forms: &[&[FILE]],
+        stdin: None,
+        handler: Run::Edit(|args, options, report| {
+            if report.preview.is_some() {
+                eprintln!("Error: --emit-sed / --emit-patch / --preview cannot preview --tui");
+                return 1;
+            }
+            execute_tui(args.text("file_path"), options, report)
+        }),
+    },
+    Mode {
+        name: "grep-format",
+        forms: &[&[]],
+        stdin: Some("<grep -n output>"),
+        handler: Run::Command(|_, options, report| execute_grep_toggle(&options.toggle, report)),
+    },
+    Mode {
+        name: "apply-patch",
+        forms: &[&[Arg::new("patch_file", ArgKind::Text)]],
+        stdin: None,
+        // The target is named inside the patch
+        handler: Run::Command(|args, options, report| {
+            if options.force_readonly {
+                eprintln!("Error: --apply-patch cannot use --force-readonly");
+                return 1;
+            }
+            execute_apply_patch(args.text("patch_file"), &options.toggle, report)
+        }),
+    },
+    Mode {
+        name: "column",
+        forms: &[FILE_LINE],
+        stdin: None,
+        handler: Run::Query(|args, options, report| {
+            execute_column_query(
+                args.text("file_path"),
+                args.number("line_number"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "relative",
+        forms: &[&[
+            FILE,
+            Arg::new("anchor_line", ArgKind::Text),
+            Arg::new("offset", ArgKind::Text),
+        ]],
+        stdin: None,
+        handler: Run::Query(|args, _, report| {
+            execute_relative(
+                args.text("file_path"),
+                args.text("anchor_line"),
+                args.text("offset"),
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "lint-comments",
+        forms: &[&[FILE, Arg::new("max_run", ArgKind::Number).optional()]],
+        stdin: None,
+        handler: Run::Query(|args, options, report| {
+            execute_lint_comments(
+                args.text("file_path"),
+                args.number_opt("max_run")
+                    .unwrap_or(DEFAULT_MAX_COMMENTED_RUN),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "query-range",
+        forms: &[&[FILE, START_LINE, END_LINE, Arg::switch("--json")]],
+        stdin: None,
+        handler: Run::Query(|args, options, report| {
+            execute_query_range(
+                args.text("file_path"),
+                args.number("start_line"),
+                args.number("end_line"),
+                args.has("--json"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "query-lines",
+        forms: &[&[FILE, Arg::new("line|start-end", ArgKind::Text).repeated()]],
+        stdin: None,
+        handler: Run::Query(|args, options, report| {
+            let file_path = args.text("file_path");
+            match parse_range_list(file_path, &args.all("line|start-end"), report) {
+                Ok(ranges) => execute_query_lines(file_path, &ranges, options, report),
+                Err(exit_code) => exit_code,
+            }
+        }),
+    },
+    Mode {
+        name: "inspect",
+        forms: &[FILE_LINE],
+        stdin: None,
+        handler: Run::Query(|args, options, report| {
+            execute_inspect(
+                args.text("file_path"),
+                args.number("line_number"),
+                options,
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "verify-only",
+        forms: &[&[FILE]],
+        stdin: None,
+        handler: Run::Query(|args, options, report| {
+            execute_verify_only(args.text("file_path"), options, report)
+        }),
+    },
+    Mode {
+        name: "undo",
+        forms: &[&[FILE]],
+        stdin: None,
+        handler: Run::Query(|args, options, report| {
+            execute_undo(args.text("file_path"), options, report)
+        }),
+    },
+    Mode {
+        name: "undo-session",
+        forms: &[&[Arg::new("session_dir", ArgKind::Text)]],
+        stdin: None,
+        handler: Run::Command(|args, options, report| {
+            execute_undo_session(args.text("session_dir"), &options.toggle, report)
+        }),
+    },
+    Mode {
+        name: "clean-temp",
+        forms: &[&[
+            Arg::new("dir", ArgKind::Text).optional(),
+            Arg::switch("--dry-run"),
+        ]],
+        stdin: None,
+        handler: Run::Command(|args, _, report| {
+            execute_clean_temp(
+                args.get("dir").unwrap_or("."),
+                args.has("--dry-run"),
+                report,
+            )
+        }),
+    },
+    Mode {
+        name: "limits",
+        forms: &[&[]],
+        stdin: None,
+        // Compiled-in bounds, for wrappers to pre-validate requests
+        handler: Run::Command(|_, _, report| {
+            for (key, value) in limits().entries() {
+                let value = value.to_string();
+                report.value("-", key, &value, &format!("{}: {}", key, value));
+            }
+            0
+        }),
+    },
+    Mode {
+        name: "capabilities",
+        forms: &[&[]],
+        stdin: None,
+        // What this binary supports, for editor plugins to feature-detect
+        // instead of parsing the usage text
+        handler: Run::Command(|_, _, report| {
+            for (key, value) in capabilities() {
+                report.value("-", key, &value, &format!("{}: {}", key, value));
+            }
+            0
+        }),
+    },
+    Mode {
+        name: "completions",
+        forms: &[&[Arg::new("shell", ArgKind::Choice("bash|zsh|fish"))]],
+        stdin: None,
+        handler: Run::Command(|args, _, _| {
+            let Some(shell) = Shell::from_name(args.text("shell")) else {
+                return 1;
+            };
+            let modes: Vec<&str> = MODES.iter().map(|mode| mode.name).collect();
+            let script = completion_script(shell, env!("CARGO_BIN_NAME"), &modes, &GLOBAL_OPTIONS);
+            match Stdout.write_all(script.as_bytes()) {
+                Ok(()) => 0,
+                Err(_) => ExitCode::Io.code(),
+            }
+        }),
+    },
+];
+
+/// `block` / `line` (a `--to` value, checked by the parser)
+fn comment_style(to: &str) -> CommentStyle {
+    if to == "block" {
+        CommentStyle::Block
+    } else {
+        CommentStyle::Line
+    }
+}
+
+/// `start_line` and `end_line` of a range mode that needs them in order
+///
+/// # Returns
+/// * `None` - start_line after end_line (error already printed)
+fn increasing_range(args: &ModeArgs) -> Option<(usize, usize)> {
+    let (start_line, end_line) = (args.number("start_line"), args.number("end_line"));
+    if start_line > end_line {
+        eprintln!("Error: start_line must be less than or equal to end_line");
+        return None;
+    }
+    Some((start_line, end_line))
+}
+
+/// `--list-basic` / `--list-docstring`, line numbers as arguments or on stdin
+fn run_list(args: &ModeArgs, docstring: bool, options: &ToggleOptions, report: &Report) -> i32 {
+    let file_path = args.text("file_path");
+
+    if args.has("--stdin-lines") {
+        let max_lines = match checked_stdin_max_lines(args.number_opt("max_lines")) {
+            Ok(n) => n,
+            Err(_) => {
                 print_usage();
-                process::exit(1);
+                return 1;
             }
+        };
+        return execute_batch_toggle_stdin(file_path, max_lines, docstring, options, report);
+    }
+
+    let line_list = match checked_line_list(args.numbers("line_number")) {
+        Ok(lines) => lines,
+        Err(_) => {
+            print_usage();
+            return 1;
         }
+    };
+    if docstring {
+        execute_batch_toggle_docstring(file_path, &line_list, options, report)
     } else {
-        // Basic mode: <file> <line>
-        if args.len() != 3 {
-            eprintln!("Error: Basic mode requires <file_path> <line_number>");
+        execute_batch_toggle_standard(file_path, &line_list, options, report)
+    }
+}
+
+/// `--stdin-content`: the flag from `--ext` or `--custom-flag`
+fn run_stdin_content(args: &ModeArgs, options: &GlobalOptions, _: &Report) -> i32 {
+    if options.porcelain {
+        eprintln!("Error: --stdin-content prints the content itself (no --porcelain)");
+        return 1;
+    }
+    let flag = match args.get("ext") {
+        Some(extension) => StdinContentFlag::Extension(extension.to_string()),
+        None => match CommentFlag::from_flag(args.text("flag")) {
+            Some(flag) => StdinContentFlag::Flag(flag),
+            None => {
+                eprintln!("Error: --custom-flag must be one of // # /// <!-- /* ///< //!<");
+                return 1;
+            }
+        },
+    };
+    execute_stdin_content(args.number("line_number"), flag, &options.toggle)
+}
+
+/// Print an argument error and the usage text
+///
+/// # Returns
+/// * Exit code 1
+fn usage_error(error: &ParseError) -> i32 {
+    eprintln!("Error: {}", error);
+    eprintln!();
+    print_usage();
+    1
+}
+
+/// Check mode arguments and run the operation on the target
+///
+/// # Arguments
+/// * `mode` - Mode selected by `args[1]`
+/// * `run` - Its handler
+/// * `args` - Arguments without global options (program name first)
+/// * `options` - Library options from the global flags
+/// * `report` - Human messages or porcelain records
+///
+/// # Returns
+/// * Exit code
+fn run_mode(
+    mode: &Mode<Run>,
+    run: FileHandler,
+    args: &[String],
+    options: &ToggleOptions,
+    report: &Report,
+) -> i32 {
+    match mode.parse(args) {
+        Ok(mode_args) => run(&mode_args, options, report),
+        Err(e) => usage_error(&e),
+    }
+}
+
+fn main() {
+    // Collect command line arguments
+    let raw_args: Vec<String> = env::args().collect();
+    let (args, options) = match split_global_options(raw_args.clone(), ToggleOptions::default()) {
+        Ok(result) => result,
+        Err(_) => {
             eprintln!();
             print_usage();
             process::exit(1);
         }
+    };
 
-        let file_path = &args[1];
-        let line_number = match parse_line_number(&args[2], "line_number") {
-            Ok(n) => n,
-            Err(_) => {
-                print_usage();
+    // .toggle_comment.toml and TOGGLE_COMMENT_* variables: defaults under
+    // the flags, so the flags are parsed again on top of them
+    let (args, options) = match base_options(&args, &options) {
+        Ok(base) => match split_global_options(raw_args, base) {
+            Ok(result) => result,
+            Err(_) => process::exit(1),
+        },
+        Err(exit_code) => process::exit(exit_code),
+    };
+
+    let Some(mode) = args.get(1).and_then(|arg| find_mode(MODES, arg)) else {
+        match args.get(1) {
+            Some(flag) => eprintln!("Error: Unknown flag: {}", flag),
+            None => eprintln!("Error: Invalid number of arguments"),
+        }
+        eprintln!();
+        print_usage();
+        process::exit(1);
+    };
+    let report = Report {
+        porcelain: options.porcelain,
+        mode: mode.name,
+        preview: None,
+        trace: options.toggle.trace,
+    };
+
+    // Ctrl-C / SIGTERM from here on cancel the operation cleanly; --timeout
+    // budgets everything below (--serve keeps the default: it only ever
+    // edits between requests)
+    if mode.name != "serve" {
+        interrupt::install();
+        if options.timeout.is_some() {
+            set_deadline(options.timeout);
+        }
+    }
+
+    let run = match mode.handler {
+        Run::Edit(run) | Run::Query(run) => run,
+        Run::Command(run) => {
+            // --emit-sed / --emit-patch / --preview rewrite the target argument
+            if options.emit.is_some() || options.preview {
+                eprintln!(
+                    "Error: --emit-sed / --emit-patch / --preview need a mode that edits one file"
+                );
                 process::exit(1);
             }
-        };
+            match mode.parse(&args) {
+                Ok(mode_args) => process::exit(run(&mode_args, &options, &report)),
+                Err(e) => process::exit(usage_error(&e)),
+            }
+        }
+    };
+
+    // $ / % / N,M line addresses
+    let args = match resolve_line_addresses(args, mode, &report) {
+        Ok(args) => args,
+        Err(exit_code) => process::exit(exit_code),
+    };
+
+    // Checked before the target is touched; run_mode() checks them again
+    // (also on preview copies)
+    if let Err(e) = mode.parse(&args) {
+        process::exit(usage_error(&e));
+    }
+    let Some(target_index) = mode.target_index(&args) else {
+        eprintln!("Error: --{} has no target file", mode.name);
+        process::exit(1);
+    };
+    let target_path = &args[target_index];
+
+    // --emit-sed / --emit-patch: run on a preview copy and print the difference instead
+    if let Some(format) = options.emit {
+        process::exit(execute_emit(
+            mode,
+            &args,
+            target_index,
+            format,
+            &options.toggle,
+            &report,
+        ));
+    }
 
-        execute_basic_toggle(file_path, line_number)
+    // --preview: the same on a preview copy, paged; the mode then runs below
+    // once confirmed
+    if options.preview
+        && let Err(exit_code) = execute_preview(mode, &args, target_index, &options.toggle, &report)
+    {
+        process::exit(exit_code);
+    }
+
+    // --force-readonly: make the target writable for this run only
+    let saved_permissions = if options.force_readonly {
+        match clear_readonly(target_path) {
+            Ok(permissions) => permissions,
+            Err(e) => {
+                process::exit(report.error(
+                    target_path,
+                    "Error clearing read-only attribute on",
+                    e,
+                    error_to_exit_code(e),
+                ));
+            }
+        }
+    } else {
+        None
     };
 
+    let mut exit_code = run_mode(mode, run, &args, &options.toggle, &report);
+
+    // Restore the read-only attribute whether or not the operation succeeded
+    if let Some(permissions) = saved_permissions
+        && let Err(e) = restore_permissions(target_path, permissions)
+    {
+        let restore_code = report.error(
+            target_path,
+            "Error restoring read-only attribute on",
+            e,
+            error_to_exit_code(e),
+        );
+        if exit_code == 0 {
+            exit_code = restore_code;
+        }
+    }
+
     // Exit with appropriate code
     process::exit(exit_code);
 }
+
+#[cfg(test)]
+mod line_address_tests {
+    use super::*;
+
+    fn resolve(list: &[&str]) -> Result<Vec<String>, i32> {
+        let args: Vec<String> = std::iter::once("tc")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect();
+        let mode = find_mode(MODES, &args[1]).unwrap();
+        let report = Report {
+            porcelain: false,
+            mode: mode.name,
+            preview: None,
+            trace: ToggleOptions::default().trace,
+        };
+        resolve_line_addresses(args, mode, &report).map(|args| args[1..].to_vec())
+    }
+
+    #[test]
+    fn test_line_addresses() {
+        let path = "test_main_line_addresses.py";
+        std::fs::write(path, "a\nb\nc\nd\n").unwrap();
+
+        // Line positions: $, %, N,M and offsets
+        assert_eq!(resolve(&[path, "$"]).unwrap(), [path, "3"]);
+        assert_eq!(
+            resolve(&["--block", path, "%"]).unwrap(),
+            ["--block", path, "0", "3"]
+        );
+        assert_eq!(
+            resolve(&["--indent-range", path, "1,$"]).unwrap(),
+            ["--indent-range", path, "1", "3"]
+        );
+        assert_eq!(
+            resolve(&["--block", path, "1,+2"]).unwrap(),
+            ["--block", path, "1", "3"]
+        );
+        assert_eq!(
+            resolve(&["--list-basic", path, "0", "+1", "$"]).unwrap(),
+            ["--list-basic", path, "0", "1", "3"]
+        );
+        assert_eq!(
+            resolve(&["--query-range", path, "--json", "%"]).unwrap(),
+            ["--query-range", path, "--json", "0", "3"]
+        );
+        assert_eq!(
+            resolve(&["--convert-style", path, "$", "--to", "block"]).unwrap(),
+            ["--convert-style", path, "3", "--to", "block"]
+        );
+        assert_eq!(resolve(&["--block", path, "2,+5"]), Err(5));
+        assert_eq!(resolve(&[path, "+1"]), Err(1));
+
+        // Text and number arguments are passed through as given
+        for list in [
+            &["--attribute", path, "$", "#[cfg(any(unix, windows))]"][..],
+            &["--insert-line", path, "$", "foo(a, b)"],
+            &["--insert-line", path, "1", "$"],
+            &["--insert-line", path, "1", "-1"],
+            &["--remove-line", path, "1", "%"],
+            &["--split", path, "1", "$"],
+        ] {
+            let resolved = resolve(list).unwrap();
+            assert_eq!(resolved[3..], list[3..]);
+            assert_eq!(resolved.len(), list.len());
+        }
+        assert_eq!(resolve(&["--insert-line", path, "$", "x"]).unwrap()[2], "3");
+        assert_eq!(
+            resolve(&["--insert-line", path, "1,2", "x"]).unwrap(),
+            ["--insert-line", path, "1,2", "x"]
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,1523 @@
+//! # patch.rs
+//!
+//! Planned changes without editing: preview copies and change scripts
+//!
+//! Some teams must route every modification through their own tooling
+//! (deployment systems that only accept sed scripts, review workflows). With
+//! `--emit-sed` / `--emit-patch` the CLI runs the requested operation on a
+//! scratch copy of the file and prints the difference as a sed script or a
+//! unified diff instead of changing the file.
+//! `--apply-patch` applies such a patch later, after review. `--preview`
+//! shows the same diff and runs the operation for real once confirmed.
+//! `--verify-only` compares a file with its backup afterwards and confirms
+//! that only comments and indentation changed.
+//!
+//! ## Approach
+//! - `PreviewCopy` copies the target to the system temp directory (same file
+//!   name, so the extension still selects the comment flag). The operation
+//!   runs on the copy through the normal pipeline; its backup and the copy
+//!   are removed when the `PreviewCopy` is dropped. The original is only read.
+//! - `diff_lines()` compares original and result in one streaming pass.
+//!   Lines are compared with their line endings. At a mismatch it looks one
+//!   line ahead on each side: that is enough for what this tool does (lines
+//!   changed in place, block marker lines inserted or removed) and always
+//!   yields a correct script, though not always a minimal one elsewhere.
+//!   Unchanged lines are never held; a run of changed lines is held until
+//!   the next unchanged line.
+//!
+//! ## sed Scripts
+//! sed addresses are input line numbers (one-based), so the commands do not
+//! shift each other. Run with `sed -i -f script.sed <file>`. Changes to the
+//! final newline cannot be expressed in sed and are refused.
+//!
+//! ## Unified Diffs
+//! `diff -u` layout: `--- a/<path>` / `+++ b/<path>`, hunks with three lines
+//! of context (changes at most six unchanged lines apart share a hunk) and
+//! `\ No newline at end of file` after a last line without line ending, so
+//! `git apply` and `patch -p1` take it as is.
+
+use crate::toggle_comment_indent_module::{
+    FileSnapshot, IoOperation, StageTrace, TempFileGuard, TimedIo, ToggleCommentError,
+    ToggleOptions, TraceStage, backup_checksum_path, backup_matches_checksum,
+    backup_origin_matches, backup_path_for, clear_readonly, exceeds_size_limit,
+    first_invalid_utf8_line, is_own_artifact, is_readonly_target, is_special_file,
+    remove_temp_file, replace_original, replacement_size_is_plausible, safe_name_component,
+    temp_file_path,
+};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Buffer size for reading the original and the preview
+const PREVIEW_BUFFER_SIZE: usize = 8192;
+
+/// Upper bound on lines compared (same order as the library's scan bound)
+const MAX_DIFF_LINES: u64 = 1_000_000_000;
+
+/// Why planned changes could not be produced or applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchError {
+    /// Reading, writing, backup or replace failed (or a pipeline check, e.g.
+    /// `ReadOnlyTarget`, refused the target)
+    Toggle(ToggleCommentError),
+
+    /// The change adds or removes the final newline, which sed cannot express
+    FinalNewline,
+
+    /// Patch line (one-based) is not valid here: bad header or hunk
+    /// header, hunks out of order, or a second file
+    Malformed { patch_line: usize },
+
+    /// The hunk starting at this patch line changes more than comment flags,
+    /// block markers, indentation, trailing spaces or line endings
+    NotCommentChange { patch_line: usize },
+
+    /// A context or removed line does not match the file (one-based line)
+    ContextMismatch { line: usize },
+
+    /// The file differs from its backup by more than comment flags, block
+    /// markers, indentation, trailing spaces or line endings; `line` is the
+    /// first such line of the file (zero-indexed)
+    UnexpectedChange { line: usize },
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::Toggle(e) => write!(f, "{}", e),
+            PatchError::FinalNewline => {
+                write!(f, "Change to the final newline cannot be written as sed")
+            }
+            PatchError::Malformed { patch_line } => {
+                write!(
+                    f,
+                    "Patch line {} is not a valid unified diff line here",
+                    patch_line
+                )
+            }
+            PatchError::NotCommentChange { patch_line } => write!(
+                f,
+                "Hunk at patch line {} changes more than comments or indentation",
+                patch_line
+            ),
+            PatchError::ContextMismatch { line } => {
+                write!(f, "Line {} of the file does not match the patch", line)
+            }
+            PatchError::UnexpectedChange { line } => write!(
+                f,
+                "Line {} differs from the backup by more than comments or indentation",
+                line
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl From<ToggleCommentError> for PatchError {
+    fn from(e: ToggleCommentError) -> Self {
+        PatchError::Toggle(e)
+    }
+}
+
+// ============================================================================
+// PREVIEW COPY
+// ============================================================================
+
+/// Scratch copy of a file to run an operation on instead of the original
+///
+/// Removed on drop, together with the backup the operation makes of it.
+#[derive(Debug)]
+pub struct PreviewCopy {
+    original: PathBuf,
+    path: PathBuf,
+    backup: PathBuf,
+    /// The original when it was copied
+    snapshot: FileSnapshot,
+}
+
+impl PreviewCopy {
+    /// Copy `file_path` to the temp directory (made writable)
+    ///
+    /// # Returns
+    /// * `Ok(copy)` - Run the operation on `copy.path()`
+    /// * `Err(FileNotFound)` / `Err(PathError)` / `Err(IoError)` - Could not copy
+    pub fn create(file_path: &str) -> Result<PreviewCopy, ToggleCommentError> {
+        let original = match Path::new(file_path).canonicalize() {
+            Ok(p) => p,
+            Err(e) => {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    return Err(ToggleCommentError::FileNotFound);
+                }
+                return Err(ToggleCommentError::PathError);
+            }
+        };
+
+        // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+        if is_special_file(&original) {
+            return Err(ToggleCommentError::NotRegularFile);
+        }
+
+        let filename = match original.file_name() {
+            Some(name) => safe_name_component(name),
+            None => return Err(ToggleCommentError::PathError),
+        };
+
+        let snapshot = match FileSnapshot::of(&original) {
+            Ok(snapshot) => snapshot,
+            Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+        };
+
+        let scratch_name = format!("toggle_comment_preview_{}_{}", std::process::id(), filename);
+        let copy = PreviewCopy {
+            original,
+            snapshot,
+            path: std::env::temp_dir().join(&scratch_name),
+            // The pipelines write their backup to the working directory
+            backup: PathBuf::from(format!("backup_toggle_comment_{}", scratch_name)),
+        };
+
+        if let Err(e) = std::fs::copy(&copy.original, &copy.path) {
+            return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create));
+        }
+        // A read-only original must not make the preview fail
+        clear_readonly(copy.path())?;
+
+        Ok(copy)
+    }
+
+    /// Path of the scratch copy
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+
+    /// Check that the original is still as it was copied (size, modification
+    /// time, inode), before the previewed operation runs on it for real
+    ///
+    /// # Returns
+    /// * `Ok(())` - Unchanged
+    /// * `Err(ConcurrentModification)` - Saved, replaced or removed since
+    pub fn check_original_unchanged(&self) -> Result<(), ToggleCommentError> {
+        match FileSnapshot::of(&self.original) {
+            Ok(now) if now == self.snapshot => Ok(()),
+            _ => Err(ToggleCommentError::ConcurrentModification),
+        }
+    }
+
+    /// Write the difference between the original and the copy as a sed script
+    ///
+    /// # Arguments
+    /// * `output` - Receives the script
+    /// * `display_name` - File name for the usage comment
+    ///
+    /// # Returns
+    /// * `Ok(changed)` - Script written; `false` when nothing changed (the
+    ///   script then holds only the comment)
+    /// * `Err(FinalNewline)` - The final newline changed (nothing useful written)
+    /// * `Err(Toggle(_))` - Read or write failed
+    pub fn write_sed_script<W: Write>(
+        &self,
+        output: &mut W,
+        display_name: &str,
+    ) -> Result<bool, PatchError> {
+        let write_error = |_| ToggleCommentError::IoError(IoOperation::Write);
+        writeln!(
+            output,
+            "# toggle_comment planned changes: sed -i -f <this file> {}",
+            display_name
+        )
+        .map_err(write_error)?;
+
+        let mut sed = SedScript {
+            output,
+            changed: false,
+        };
+        self.diff(&mut |event| sed.event(event))?;
+        Ok(sed.changed)
+    }
+
+    /// Write the difference between the original and the copy as a unified diff
+    ///
+    /// # Arguments
+    /// * `output` - Receives the patch (`git apply` / `patch -p1` format)
+    /// * `display_name` - Path for the `--- a/` / `+++ b/` headers
+    ///
+    /// # Returns
+    /// * `Ok(changed)` - Patch written; `false` (and nothing written) when
+    ///   nothing changed
+    /// * `Err(Toggle(_))` - Read or write failed
+    pub fn write_unified_diff<W: Write>(
+        &self,
+        output: &mut W,
+        display_name: &str,
+    ) -> Result<bool, PatchError> {
+        let mut diff = UnifiedDiff::new(output, display_name);
+        self.diff(&mut |event| diff.event(event))?;
+        diff.finish()
+    }
+
+    /// Stream the comparison of original and copy into `sink`
+    fn diff(
+        &self,
+        sink: &mut dyn FnMut(DiffEvent) -> Result<(), PatchError>,
+    ) -> Result<(), PatchError> {
+        let open = |path: &Path| match File::open(path) {
+            Ok(f) => Ok(BufReader::with_capacity(PREVIEW_BUFFER_SIZE, f)),
+            Err(e) => Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+        };
+        diff_lines(&mut open(&self.original)?, &mut open(&self.path)?, sink)
+    }
+}
+
+impl Drop for PreviewCopy {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_file(&self.backup);
+        let _ = std::fs::remove_file(backup_checksum_path(&self.backup));
+    }
+}
+
+// ============================================================================
+// STREAMING LINE DIFF
+// ============================================================================
+
+/// One step of the comparison
+#[derive(Debug, PartialEq, Eq)]
+enum DiffEvent<'a> {
+    /// Line present in both (original line number, zero-indexed)
+    Same { old_line: usize, text: &'a [u8] },
+
+    /// Original lines `old_start..old_start + old.len()` become `new`
+    /// (either side may be empty)
+    Change {
+        old_start: usize,
+        old: &'a [Vec<u8>],
+        new: &'a [Vec<u8>],
+    },
+}
+
+/// One line with its ending, or `None` at end of input
+fn read_diff_line<R: BufRead>(reader: &mut R) -> Result<Option<Vec<u8>>, ToggleCommentError> {
+    let mut line: Vec<u8> = Vec::new();
+    match reader.read_until(b'\n', &mut line) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(line)),
+        Err(_) => Err(ToggleCommentError::IoError(IoOperation::Read)),
+    }
+}
+
+/// Two-line window over one side of the comparison
+struct DiffSide<'r, R: BufRead> {
+    reader: &'r mut R,
+    current: Option<Vec<u8>>,
+    next: Option<Vec<u8>>,
+    line: usize,
+}
+
+impl<'r, R: BufRead> DiffSide<'r, R> {
+    fn new(reader: &'r mut R) -> Result<Self, ToggleCommentError> {
+        let current = read_diff_line(reader)?;
+        let next = read_diff_line(reader)?;
+        Ok(DiffSide {
+            reader,
+            current,
+            next,
+            line: 0,
+        })
+    }
+
+    /// Move to the next line; returns the line left behind
+    fn advance(&mut self) -> Result<Option<Vec<u8>>, ToggleCommentError> {
+        let passed = self.current.take();
+        self.current = self.next.take();
+        self.next = read_diff_line(self.reader)?;
+        self.line += 1;
+        Ok(passed)
+    }
+}
+
+/// Compare two line streams, reporting unchanged lines and change runs
+fn diff_lines<A: BufRead, B: BufRead>(
+    old: &mut A,
+    new: &mut B,
+    sink: &mut dyn FnMut(DiffEvent) -> Result<(), PatchError>,
+) -> Result<(), PatchError> {
+    let mut old = DiffSide::new(old)?;
+    let mut new = DiffSide::new(new)?;
+    let mut removed: Vec<Vec<u8>> = Vec::new();
+    let mut added: Vec<Vec<u8>> = Vec::new();
+    let mut run_start: usize = 0;
+
+    for _ in 0..MAX_DIFF_LINES {
+        let same = match (&old.current, &new.current) {
+            (None, None) => break,
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        };
+
+        if same {
+            if !removed.is_empty() || !added.is_empty() {
+                sink(DiffEvent::Change {
+                    old_start: run_start,
+                    old: &removed,
+                    new: &added,
+                })?;
+                removed.clear();
+                added.clear();
+            }
+            let line = old.line;
+            let text = old.advance()?.unwrap_or_default();
+            new.advance()?;
+            sink(DiffEvent::Same {
+                old_line: line,
+                text: &text,
+            })?;
+            continue;
+        }
+
+        if removed.is_empty() && added.is_empty() {
+            run_start = old.line;
+        }
+
+        // One line of lookahead: inserted line, removed line, or replaced line
+        let inserted = old.current.is_none()
+            || (new.next.is_some() && old.current == new.next && old.next != new.current);
+        let deleted = new.current.is_none() || (old.next.is_some() && old.next == new.current);
+
+        if inserted {
+            added.extend(new.advance()?);
+        } else if deleted {
+            removed.extend(old.advance()?);
+        } else {
+            removed.extend(old.advance()?);
+            added.extend(new.advance()?);
+        }
+    }
+
+    if !removed.is_empty() || !added.is_empty() {
+        sink(DiffEvent::Change {
+            old_start: run_start,
+            old: &removed,
+            new: &added,
+        })?;
+    }
+    Ok(())
+}
+
+// ============================================================================
+// SED RENDERER
+// ============================================================================
+
+/// Turns change runs into sed commands
+struct SedScript<'w, W: Write> {
+    output: &'w mut W,
+    changed: bool,
+}
+
+impl<W: Write> SedScript<'_, W> {
+    fn event(&mut self, event: DiffEvent) -> Result<(), PatchError> {
+        let (old_start, old, new) = match event {
+            DiffEvent::Same { .. } => return Ok(()),
+            DiffEvent::Change {
+                old_start,
+                old,
+                new,
+            } => (old_start, old, new),
+        };
+        self.changed = true;
+
+        // sed always ends its output lines with a newline: a change of the
+        // final newline has no sed equivalent
+        let ends_open = |lines: &[Vec<u8>]| lines.last().is_some_and(|l| !l.ends_with(b"\n"));
+        if ends_open(old) || ends_open(new) {
+            return Err(PatchError::FinalNewline);
+        }
+
+        let first = old_start + 1; // sed counts from 1
+        let command = match (old.len(), new.len()) {
+            (0, _) if old_start == 0 => "1i\\".to_string(),
+            (0, _) => format!("{}a\\", old_start), // after the previous line
+            (1, 0) => format!("{}d", first),
+            (count, 0) => format!("{},{}d", first, first + count - 1),
+            (1, _) => format!("{}c\\", first),
+            (count, _) => format!("{},{}c\\", first, first + count - 1),
+        };
+
+        let write_error = |_| ToggleCommentError::IoError(IoOperation::Write);
+        writeln!(self.output, "{}", command).map_err(write_error)?;
+        for (index, line) in new.iter().enumerate() {
+            let text = String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(line));
+            // Backslashes are escapes in sed text; every line but the last
+            // continues the text with a trailing backslash
+            let escaped = text.replace('\\', "\\\\");
+            let continuation = if index + 1 < new.len() { "\\" } else { "" };
+            writeln!(self.output, "{}{}", escaped, continuation).map_err(write_error)?;
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// UNIFIED DIFF RENDERER
+// ============================================================================
+
+/// Unchanged lines shown around each change (the `diff -u` default)
+const DIFF_CONTEXT_LINES: usize = 3;
+
+/// Marker git and patch expect after a line without a line ending
+const NO_NEWLINE_MARKER: &[u8] = b"\\ No newline at end of file\n";
+
+/// Turns change runs into unified diff hunks
+///
+/// A hunk is held until more than twice the context of unchanged lines
+/// follows its last change (then the next change starts a new hunk).
+struct UnifiedDiff<'w, W: Write> {
+    output: &'w mut W,
+    display_name: &'w str,
+    headers_written: bool,
+    /// Unchanged lines before the next hunk (at most DIFF_CONTEXT_LINES)
+    before: VecDeque<Vec<u8>>,
+    /// Open hunk: `(prefix, line)` entries
+    hunk: Vec<(u8, Vec<u8>)>,
+    /// Zero-indexed first line of the open hunk in the original / result
+    hunk_old_start: usize,
+    hunk_new_start: usize,
+    /// Unchanged lines at the end of the open hunk
+    trailing_same: usize,
+    /// Zero-indexed line of the result the next event starts at
+    new_line: usize,
+}
+
+impl<'w, W: Write> UnifiedDiff<'w, W> {
+    fn new(output: &'w mut W, display_name: &'w str) -> Self {
+        UnifiedDiff {
+            output,
+            display_name,
+            headers_written: false,
+            before: VecDeque::with_capacity(DIFF_CONTEXT_LINES + 1),
+            hunk: Vec::new(),
+            hunk_old_start: 0,
+            hunk_new_start: 0,
+            trailing_same: 0,
+            new_line: 0,
+        }
+    }
+
+    fn event(&mut self, event: DiffEvent) -> Result<(), PatchError> {
+        match event {
+            DiffEvent::Same { text, .. } => {
+                self.new_line += 1;
+                if self.hunk.is_empty() {
+                    self.before.push_back(text.to_vec());
+                    if self.before.len() > DIFF_CONTEXT_LINES {
+                        self.before.pop_front();
+                    }
+                    return Ok(());
+                }
+                self.hunk.push((b' ', text.to_vec()));
+                self.trailing_same += 1;
+                if self.trailing_same > 2 * DIFF_CONTEXT_LINES {
+                    self.close_hunk()?;
+                }
+            }
+            DiffEvent::Change {
+                old_start,
+                old,
+                new,
+            } => {
+                if self.hunk.is_empty() {
+                    self.hunk_old_start = old_start - self.before.len();
+                    self.hunk_new_start = self.new_line - self.before.len();
+                    for line in self.before.drain(..) {
+                        self.hunk.push((b' ', line));
+                    }
+                }
+                self.hunk
+                    .extend(old.iter().map(|line| (b'-', line.clone())));
+                self.hunk
+                    .extend(new.iter().map(|line| (b'+', line.clone())));
+                self.trailing_same = 0;
+                self.new_line += new.len();
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the open hunk with DIFF_CONTEXT_LINES of trailing context; the
+    /// unchanged lines after that become the next hunk's leading context
+    fn close_hunk(&mut self) -> Result<(), PatchError> {
+        let surplus = self.trailing_same.saturating_sub(DIFF_CONTEXT_LINES);
+        let kept = self.hunk.len() - surplus;
+        let after: Vec<(u8, Vec<u8>)> = self.hunk.drain(kept..).collect();
+        self.trailing_same = 0;
+
+        let write_error = |_| ToggleCommentError::IoError(IoOperation::Write);
+        if !self.headers_written {
+            let name = self.display_name.trim_start_matches("./");
+            write!(self.output, "--- a/{}\n+++ b/{}\n", name, name).map_err(write_error)?;
+            self.headers_written = true;
+        }
+
+        let old_count = self.hunk.iter().filter(|(p, _)| *p != b'+').count();
+        let new_count = self.hunk.iter().filter(|(p, _)| *p != b'-').count();
+        writeln!(
+            self.output,
+            "@@ -{} +{} @@",
+            hunk_range(self.hunk_old_start, old_count),
+            hunk_range(self.hunk_new_start, new_count)
+        )
+        .map_err(write_error)?;
+
+        for (prefix, line) in self.hunk.drain(..) {
+            self.output.write_all(&[prefix]).map_err(write_error)?;
+            self.output.write_all(&line).map_err(write_error)?;
+            if !line.ends_with(b"\n") {
+                self.output.write_all(b"\n").map_err(write_error)?;
+                self.output
+                    .write_all(NO_NEWLINE_MARKER)
+                    .map_err(write_error)?;
+            }
+        }
+
+        let skip = after.len().saturating_sub(DIFF_CONTEXT_LINES);
+        self.before = after.into_iter().skip(skip).map(|(_, line)| line).collect();
+        Ok(())
+    }
+
+    /// Write the last hunk; returns whether anything changed
+    fn finish(mut self) -> Result<bool, PatchError> {
+        if !self.hunk.is_empty() {
+            self.close_hunk()?;
+        }
+        Ok(self.headers_written)
+    }
+}
+
+/// `start,count` of a hunk header (one-based; an empty side names the line
+/// before it, and a count of 1 is left out, as `diff -u` does)
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+// ============================================================================
+// APPLYING A PATCH
+// ============================================================================
+//
+// `apply_patch()` reads a unified diff for one file (as `--emit-patch`
+// writes it) and applies it with the same pipeline as the other operations:
+// checks, backup, temp file, size check, replace. Every context and removed
+// line is compared with the file while the temp file is written; any
+// mismatch leaves the original untouched.
+//
+// Only changes this tool makes are accepted: within each run of removed and
+// added lines, the lines must be equal after stripping indentation, comment
+// flags (`//`, `///`, `#`, `<!-- -->`, repeated), trailing spaces and line
+// endings, apart from block marker lines (`/*`, `*/`, `"""`, `<!--`, `-->`)
+// that are inserted or removed.
+
+/// Longest patch line read (a source line plus prefix)
+const MAX_PATCH_LINE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Most comment flags stripped from one line when comparing
+const MAX_STRIPPED_FLAGS: usize = 8;
+
+/// Lines that are only a block comment marker
+const BLOCK_MARKER_LINES: [&[u8]; 5] = [b"/*", b"*/", b"\"\"\"", b"<!--", b"-->"];
+
+/// Result of `apply_patch()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedPatch {
+    /// Patched file, as named in the patch (`+++ b/<target>`)
+    pub target: String,
+
+    /// Hunks applied
+    pub hunks: usize,
+
+    /// Lines added or replaced (the `+` lines)
+    pub lines: usize,
+}
+
+/// Patch file lines with one line of push-back
+struct PatchLines<R: BufRead> {
+    reader: R,
+    pushed_back: Option<Vec<u8>>,
+    /// One-based number of the line last returned
+    line_number: usize,
+}
+
+impl<R: BufRead> PatchLines<R> {
+    fn next(&mut self) -> Result<Option<Vec<u8>>, PatchError> {
+        if let Some(line) = self.pushed_back.take() {
+            self.line_number += 1;
+            return Ok(Some(line));
+        }
+        let mut line: Vec<u8> = Vec::new();
+        match (&mut self.reader)
+            .take(MAX_PATCH_LINE_BYTES)
+            .read_until(b'\n', &mut line)
+        {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                self.line_number += 1;
+                if line.len() as u64 == MAX_PATCH_LINE_BYTES && !line.ends_with(b"\n") {
+                    return Err(PatchError::Malformed {
+                        patch_line: self.line_number,
+                    });
+                }
+                Ok(Some(line))
+            }
+            Err(_) => Err(ToggleCommentError::IoError(IoOperation::Read).into()),
+        }
+    }
+
+    fn push_back(&mut self, line: Vec<u8>) {
+        self.line_number -= 1;
+        self.pushed_back = Some(line);
+    }
+
+    fn malformed(&self) -> PatchError {
+        PatchError::Malformed {
+            patch_line: self.line_number,
+        }
+    }
+}
+
+/// Path of a `--- ` / `+++ ` header line without `a/` / `b/` and timestamp
+fn header_path<'l>(line: &'l [u8], marker: &[u8], side: &[u8]) -> Option<&'l [u8]> {
+    let rest = line.strip_prefix(marker)?;
+    let rest = rest.strip_suffix(b"\n").unwrap_or(rest);
+    let rest = rest.strip_suffix(b"\r").unwrap_or(rest);
+    let path = match rest.iter().position(|&b| b == b'\t') {
+        Some(tab) => &rest[..tab],
+        None => rest,
+    };
+    Some(path.strip_prefix(side).unwrap_or(path))
+}
+
+/// Parse `@@ -a[,b] +c[,d] @@...` into `(a, b, c, d)`
+fn parse_hunk_header(line: &[u8]) -> Option<(usize, usize, usize, usize)> {
+    let text = std::str::from_utf8(line).ok()?;
+    let mut parts = text.strip_prefix("@@ -")?.splitn(2, " @@");
+    let mut ranges = parts.next()?.split(" +");
+    parts.next()?;
+
+    let range = |r: &str| -> Option<(usize, usize)> {
+        match r.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((r.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = range(ranges.next()?)?;
+    let (new_start, new_count) = range(ranges.next()?)?;
+    if ranges.next().is_some() {
+        return None;
+    }
+    Some((old_start, old_count, new_start, new_count))
+}
+
+/// Read one hunk body: `(prefix, line)` entries, `\ No newline` applied
+fn read_hunk_body<R: BufRead>(
+    lines: &mut PatchLines<R>,
+    old_count: usize,
+    new_count: usize,
+) -> Result<Vec<(u8, Vec<u8>)>, PatchError> {
+    let mut body: Vec<(u8, Vec<u8>)> = Vec::new();
+    let (mut old_left, mut new_left) = (old_count, new_count);
+
+    loop {
+        let line = match lines.next()? {
+            Some(line) => line,
+            None if old_left == 0 && new_left == 0 => break,
+            None => return Err(lines.malformed()),
+        };
+        if line.starts_with(b"\\") {
+            // Marker for the previous line: no line ending in the file
+            match body.last_mut() {
+                Some((_, text)) if text.ends_with(b"\n") => {
+                    text.pop();
+                }
+                _ => return Err(lines.malformed()),
+            }
+            continue;
+        }
+        if old_left == 0 && new_left == 0 {
+            lines.push_back(line);
+            break;
+        }
+
+        // An empty line is a blank context line (trailing space stripped)
+        let (prefix, text) = match line.split_first() {
+            Some((&b'\n', _)) => (b' ', line.clone()),
+            Some((&prefix, text)) => (prefix, text.to_vec()),
+            None => return Err(lines.malformed()),
+        };
+        match prefix {
+            b' ' if old_left > 0 && new_left > 0 => {
+                old_left -= 1;
+                new_left -= 1;
+            }
+            b'-' if old_left > 0 => old_left -= 1,
+            b'+' if new_left > 0 => new_left -= 1,
+            _ => return Err(lines.malformed()),
+        }
+        body.push((prefix, text));
+    }
+    Ok(body)
+}
+
+/// Line content with indentation, comment flags, trailing spaces and line
+/// ending removed; `None` for a block marker line
+fn comment_neutral(line: &[u8]) -> Option<&[u8]> {
+    fn trim(text: &[u8]) -> &[u8] {
+        let is_blank = |b: &u8| matches!(b, b' ' | b'\t' | b'\r' | b'\n');
+        let start = text.iter().position(|b| !is_blank(b)).unwrap_or(text.len());
+        let end = text
+            .iter()
+            .rposition(|b| !is_blank(b))
+            .map_or(start, |i| i + 1);
+        &text[start..end.max(start)]
+    }
+
+    let mut text = trim(line);
+    if BLOCK_MARKER_LINES.contains(&text) {
+        return None;
+    }
+    for _ in 0..MAX_STRIPPED_FLAGS {
+        let stripped = if let Some(rest) = text.strip_prefix(b"<!--") {
+            rest.strip_suffix(b"-->").unwrap_or(rest)
+        } else if let Some(rest) = text.strip_prefix(b"///") {
+            rest
+        } else if let Some(rest) = text.strip_prefix(b"//") {
+            rest
+        } else if let Some(rest) = text.strip_prefix(b"#") {
+            rest
+        } else {
+            break;
+        };
+        text = trim(stripped);
+    }
+    Some(text)
+}
+
+/// Check every run of removed/added lines in a hunk body
+fn is_comment_change(body: &[(u8, Vec<u8>)]) -> bool {
+    body.split(|(prefix, _)| *prefix == b' ').all(|run| {
+        let side = |wanted: u8| {
+            run.iter()
+                .filter(move |(prefix, _)| *prefix == wanted)
+                .filter_map(|(_, line)| comment_neutral(line))
+        };
+        side(b'-').eq(side(b'+'))
+    })
+}
+
+/// Apply the hunks from `patch` to `original`, writing the result to `output`
+///
+/// # Returns
+/// * `Ok((hunks, added_bytes, removed_bytes, lines))` - Everything matched
+/// * `Err(PatchError)` - Malformed patch, non-comment change or mismatch
+fn apply_hunks<P: BufRead, R: BufRead, W: Write>(
+    lines: &mut PatchLines<P>,
+    original: &mut R,
+    output: &mut W,
+) -> Result<(usize, u64, u64, usize), PatchError> {
+    let write_error = |_| PatchError::Toggle(ToggleCommentError::IoError(IoOperation::Write));
+    let (mut hunks, mut added, mut removed, mut changed_lines) = (0usize, 0u64, 0u64, 0usize);
+    let mut old_line: usize = 0; // zero-indexed next original line
+
+    while let Some(line) = lines.next()? {
+        if line.starts_with(b"--- ") || line.starts_with(b"diff ") {
+            return Err(lines.malformed()); // one file per patch
+        }
+        if !line.starts_with(b"@@ ") {
+            continue; // trailer / comment lines
+        }
+
+        let header_line = lines.line_number;
+        let (old_start, old_count, _, new_count) =
+            parse_hunk_header(&line).ok_or(lines.malformed())?;
+        let body = read_hunk_body(lines, old_count, new_count)?;
+        if !is_comment_change(&body) {
+            return Err(PatchError::NotCommentChange {
+                patch_line: header_line,
+            });
+        }
+
+        // An empty old side names the line after which to insert
+        let first = if old_count == 0 {
+            old_start
+        } else {
+            old_start.saturating_sub(1)
+        };
+        if first < old_line || (old_count > 0 && old_start == 0) {
+            return Err(PatchError::Malformed {
+                patch_line: header_line,
+            });
+        }
+
+        // Unchanged lines before the hunk
+        while old_line < first {
+            match read_diff_line(original)? {
+                Some(text) => output.write_all(&text).map_err(write_error)?,
+                None => return Err(PatchError::ContextMismatch { line: old_line + 1 }),
+            }
+            old_line += 1;
+        }
+
+        for (prefix, text) in &body {
+            if *prefix != b'+' {
+                if read_diff_line(original)?.as_ref() != Some(text) {
+                    return Err(PatchError::ContextMismatch { line: old_line + 1 });
+                }
+                old_line += 1;
+            }
+            match prefix {
+                b' ' => output.write_all(text).map_err(write_error)?,
+                b'+' => {
+                    output.write_all(text).map_err(write_error)?;
+                    added += text.len() as u64;
+                    changed_lines += 1;
+                }
+                _ => removed += text.len() as u64,
+            }
+        }
+        hunks += 1;
+    }
+
+    if hunks == 0 {
+        return Err(lines.malformed());
+    }
+
+    // Rest of the file
+    while let Some(text) = read_diff_line(original)? {
+        output.write_all(&text).map_err(write_error)?;
+    }
+    Ok((hunks, added, removed, changed_lines))
+}
+
+/// Apply a unified diff (as written by `--emit-patch`) to the file it names
+///
+/// # Arguments
+/// * `patch_path` - Patch file; the target is its `+++ b/<path>`, relative
+///   to the working directory
+/// * `options` - Size limit and trace settings
+///
+/// # Returns
+/// * `Ok(AppliedPatch)` - Target replaced (backup in the working directory)
+/// * `Err(Malformed / NotCommentChange / ContextMismatch)` - Original untouched
+/// * `Err(Toggle(_))` - Same errors as the other operations
+///
+/// # Example
+/// ```no_run
+/// # use toggle_comment_indent::patch::apply_patch;
+/// # use toggle_comment_indent::toggle_comment_indent_module::ToggleOptions;
+/// let applied = apply_patch("change.patch", &ToggleOptions::default())?;
+/// println!("{} hunks applied to {}", applied.hunks, applied.target);
+/// # Ok::<(), toggle_comment_indent::patch::PatchError>(())
+/// ```
+pub fn apply_patch(patch_path: &str, options: &ToggleOptions) -> Result<AppliedPatch, PatchError> {
+    let patch_file = match File::open(patch_path) {
+        Ok(f) => f,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound.into());
+            }
+            return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open).into());
+        }
+    };
+    let mut lines = PatchLines {
+        reader: BufReader::with_capacity(PREVIEW_BUFFER_SIZE, patch_file),
+        pushed_back: None,
+        line_number: 0,
+    };
+
+    // File headers (anything before `--- `, e.g. `diff --git`, is skipped)
+    let old_header = loop {
+        match lines.next()? {
+            Some(line) if line.starts_with(b"--- ") => break line,
+            Some(_) => continue,
+            None => return Err(lines.malformed()),
+        }
+    };
+    let new_header = lines.next()?.ok_or(lines.malformed())?;
+    let target = match (
+        header_path(&old_header, b"--- ", b"a/"),
+        header_path(&new_header, b"+++ ", b"b/"),
+    ) {
+        // Same file on both sides: no creation, deletion or rename
+        (Some(old), Some(new)) if old == new && new != b"/dev/null" => {
+            String::from_utf8_lossy(new).to_string()
+        }
+        _ => return Err(lines.malformed()),
+    };
+
+    // Same checks as every other operation, before any backup is made
+    let absolute_path = match Path::new(&target).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound.into());
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open).into());
+            }
+            return Err(ToggleCommentError::PathError.into());
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile.into());
+    }
+
+    // Refuse this crate's own backups and temp files (editing one chains backups)
+    if !options.allow_own_artifacts && is_own_artifact(&absolute_path) {
+        return Err(ToggleCommentError::OwnArtifact.into());
+    }
+    if is_readonly_target(&absolute_path) {
+        return Err(ToggleCommentError::ReadOnlyTarget.into());
+    }
+    if let Some((size, max)) = exceeds_size_limit(&absolute_path, options) {
+        return Err(ToggleCommentError::FileTooLarge { size, max }.into());
+    }
+    if let Some(line) = first_invalid_utf8_line(&absolute_path, options) {
+        return Err(ToggleCommentError::InvalidUtf8 { line }.into());
+    }
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError.into()),
+    };
+
+    let backup_path = backup_path_for(&filename, options);
+    let temp_path = temp_file_path(
+        options.temp_dir.as_deref(),
+        &format!("temp_toggle_patch_{}_{}", std::process::id(), filename),
+    );
+    let _remove_temp = TempFileGuard(&temp_path);
+    let trace = StageTrace::new(options, "apply-patch");
+
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup).into()),
+    };
+
+    let written = trace.run_streaming(&absolute_path, &temp_path, || {
+        write_patched_temp(&mut lines, &absolute_path, &temp_path)
+    });
+    let (hunks, added, removed, changed_lines) = match written {
+        Ok(counts) => counts,
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+    };
+
+    if !trace.check(TraceStage::Verify, || {
+        replacement_size_is_plausible(&absolute_path, &temp_path, removed, added)
+    }) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(ToggleCommentError::IncompleteWrite.into());
+    }
+
+    if let Err(e) = trace.run(TraceStage::Replace, || {
+        replace_original(
+            &temp_path,
+            &absolute_path,
+            options.temp_dir.as_deref(),
+            options.durability,
+            &snapshot,
+        )
+    }) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace).into());
+    }
+    if remove_temp_file(&temp_path).is_err() {
+        #[cfg(debug_assertions)]
+        eprintln!("Warning: Failed to clean up temp file");
+    }
+
+    Ok(AppliedPatch {
+        target,
+        hunks,
+        lines: changed_lines,
+    })
+}
+
+/// Temp write stage of `apply_patch()`: original + hunks into `temp_path`
+fn write_patched_temp<P: BufRead>(
+    lines: &mut PatchLines<P>,
+    absolute_path: &Path,
+    temp_path: &Path,
+) -> Result<(usize, u64, u64, usize), PatchError> {
+    let source_file = match File::open(absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open).into()),
+    };
+    let temp_file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(temp_path)
+    {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create).into()),
+    };
+
+    let mut reader = BufReader::with_capacity(PREVIEW_BUFFER_SIZE, TimedIo(source_file));
+    let mut writer = BufWriter::with_capacity(PREVIEW_BUFFER_SIZE, TimedIo(temp_file));
+    let counts = apply_hunks(lines, &mut reader, &mut writer)?;
+
+    if writer.flush().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Flush).into());
+    }
+    Ok(counts)
+}
+
+// ============================================================================
+// VERIFYING A FILE AGAINST ITS BACKUP
+// ============================================================================
+//
+// `verify_against_backup()` answers "what exactly did the last operation
+// change?": it compares the file with its `backup_toggle_comment_<name>`
+// (checksum verified first) using the same streaming diff, and accepts each
+// run of differing lines only under the `--apply-patch` rule above. Nothing
+// is written.
+
+/// One run of lines in which the file differs from its backup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangedRun {
+    /// First line of the run in the file (zero-indexed); for a run with no
+    /// lines left in the file, the line that now follows the removed ones
+    pub line: usize,
+
+    /// Lines of the file in the run (0: backup lines were only removed)
+    pub lines: usize,
+
+    /// Lines of the backup the run replaces (0: lines were only inserted)
+    pub backup_lines: usize,
+}
+
+/// First line of `new` (offset in the run) that is not `old` apart from
+/// comment flags, indentation, trailing spaces, line endings and block
+/// marker lines
+///
+/// # Returns
+/// * `None` - The run is a pure comment / indentation change
+/// * `Some(offset)` - First differing line; `new.len()` when only backup
+///   lines are left over (the line that now follows the run)
+fn first_non_comment_line(old: &[Vec<u8>], new: &[Vec<u8>]) -> Option<usize> {
+    let mut old_lines = old.iter().filter_map(|line| comment_neutral(line));
+    for (offset, line) in new.iter().enumerate() {
+        let Some(neutral) = comment_neutral(line) else {
+            continue;
+        };
+        if old_lines.next() != Some(neutral) {
+            return Some(offset);
+        }
+    }
+    old_lines.next().map(|_| new.len())
+}
+
+/// Compare a file with its backup and check that only comments and
+/// indentation differ
+///
+/// # Arguments
+/// * `file_path` - File to check; its backup is found as `--undo` finds it
+///   (working directory or `options.backup_dir`)
+/// * `options` - `backup_dir` is honored
+/// * `changed` - Called for every verified run, in file order
+///
+/// # Returns
+/// * `Ok(runs)` - Every difference is a comment / indentation change
+///   (0: file and backup are identical)
+/// * `Err(UnexpectedChange)` - A run changes more; runs before it were reported
+/// * `Err(Toggle(FileNotFound))` - File or backup does not exist
+/// * `Err(Toggle(BackupIntegrity))` - Sidecar missing, malformed, or not
+///   matching, or the backup was taken from another file with the same name
+/// * `Err(Toggle(_))` - Reading failed
+pub fn verify_against_backup(
+    file_path: &str,
+    options: &ToggleOptions,
+    changed: &mut dyn FnMut(ChangedRun),
+) -> Result<usize, PatchError> {
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound.into());
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open).into());
+            }
+            return Err(ToggleCommentError::PathError.into());
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile.into());
+    }
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError.into()),
+    };
+    let backup_path = backup_path_for(&filename, options);
+    if !backup_path.is_file() {
+        return Err(ToggleCommentError::FileNotFound.into());
+    }
+    if !backup_matches_checksum(&backup_path)
+        || !backup_origin_matches(&backup_path, &absolute_path)
+    {
+        return Err(ToggleCommentError::BackupIntegrity.into());
+    }
+
+    let open = |path: &Path| match File::open(path) {
+        Ok(f) => Ok(BufReader::with_capacity(PREVIEW_BUFFER_SIZE, f)),
+        Err(e) => Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut runs: usize = 0;
+    let mut new_line: usize = 0;
+    diff_lines(
+        &mut open(&backup_path)?,
+        &mut open(&absolute_path)?,
+        &mut |event| {
+            match event {
+                DiffEvent::Same { .. } => new_line += 1,
+                DiffEvent::Change { old, new, .. } => {
+                    if let Some(offset) = first_non_comment_line(old, new) {
+                        return Err(PatchError::UnexpectedChange {
+                            line: new_line + offset,
+                        });
+                    }
+                    changed(ChangedRun {
+                        line: new_line,
+                        lines: new.len(),
+                        backup_lines: old.len(),
+                    });
+                    runs += 1;
+                    new_line += new.len();
+                }
+            }
+            Ok(())
+        },
+    )?;
+    Ok(runs)
+}
+
+#[cfg(test)]
+mod patch_tests {
+    use super::*;
+    use crate::toggle_comment_indent_module::toggle_basic_singleline_comment_bytewise;
+
+    fn sed_for(old: &str, new: &str) -> Result<String, PatchError> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut sed = SedScript {
+            output: &mut output,
+            changed: false,
+        };
+        diff_lines(&mut old.as_bytes(), &mut new.as_bytes(), &mut |e| {
+            sed.event(e)
+        })?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_diff_to_sed() {
+        // Lines changed in place
+        assert_eq!(sed_for("a\nb\nc\n", "a\n# b\nc\n").unwrap(), "2c\\\n# b\n");
+        assert_eq!(
+            sed_for("a\nb\nc\n", "# a\n# b\nc\n").unwrap(),
+            "1,2c\\\n# a\\\n# b\n"
+        );
+        // Block markers inserted / removed
+        assert_eq!(
+            sed_for("a\nb\nc\n", "a\n/*\nb\n*/\nc\n").unwrap(),
+            "1a\\\n/*\n2a\\\n*/\n"
+        );
+        assert_eq!(sed_for("/*\na\n*/\n", "a\n").unwrap(), "1d\n3d\n");
+        assert_eq!(sed_for("a\n", "/*\na\n").unwrap(), "1i\\\n/*\n");
+        // Backslashes escaped, nothing for identical input
+        assert_eq!(sed_for("x\n", "// a\\b\n").unwrap(), "1c\\\n// a\\\\b\n");
+        assert_eq!(sed_for("a\nb\n", "a\nb\n").unwrap(), "");
+        // The final newline cannot be changed with sed
+        assert_eq!(sed_for("a\n", "a"), Err(PatchError::FinalNewline));
+    }
+
+    fn patch_for(old: &str, new: &str) -> String {
+        let mut output: Vec<u8> = Vec::new();
+        let mut diff = UnifiedDiff::new(&mut output, "./src/a.rs");
+        diff_lines(&mut old.as_bytes(), &mut new.as_bytes(), &mut |e| {
+            diff.event(e)
+        })
+        .unwrap();
+        diff.finish().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_diff_to_unified_patch() {
+        let old: String = (1..=20).map(|n| format!("l{}\n", n)).collect();
+
+        // Changes far apart: two hunks with three lines of context each
+        let new = old.replace("l2\n", "// l2\n").replace("l15\n", "// l15\n");
+        assert_eq!(
+            patch_for(&old, &new),
+            "--- a/src/a.rs\n+++ b/src/a.rs\n\
+             @@ -1,5 +1,5 @@\n l1\n-l2\n+// l2\n l3\n l4\n l5\n\
+             @@ -12,7 +12,7 @@\n l12\n l13\n l14\n-l15\n+// l15\n l16\n l17\n l18\n"
+        );
+
+        // Changes six lines apart share one hunk; insertions shift the result side
+        let new = old.replace("l3\n", "/*\nl3\n").replace("l8\n", "l8\n*/\n");
+        assert_eq!(
+            patch_for(&old, &new),
+            "--- a/src/a.rs\n+++ b/src/a.rs\n\
+             @@ -1,11 +1,13 @@\n l1\n l2\n+/*\n l3\n l4\n l5\n l6\n l7\n l8\n+*/\n l9\n l10\n l11\n"
+        );
+
+        // Missing final newline, and nothing for identical input
+        assert_eq!(
+            patch_for("a\nb", "a\n# b"),
+            "--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+# b\n\\ No newline at end of file\n"
+        );
+        assert_eq!(
+            patch_for("", "x\n"),
+            "--- a/src/a.rs\n+++ b/src/a.rs\n@@ -0,0 +1 @@\n+x\n"
+        );
+        assert_eq!(patch_for(&old, &old), "");
+    }
+
+    fn apply_to(patch: &str, original: &str) -> Result<String, PatchError> {
+        let mut lines = PatchLines {
+            reader: patch.as_bytes(),
+            pushed_back: None,
+            line_number: 2, // after the file headers
+        };
+        let mut output: Vec<u8> = Vec::new();
+        apply_hunks(&mut lines, &mut original.as_bytes(), &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_apply_hunks() {
+        let original: String = (1..=20).map(|n| format!("l{}\n", n)).collect();
+
+        // Round trip of emitted patches: line toggles, block markers, no final newline
+        for (old, new) in [
+            (
+                original.clone(),
+                original
+                    .replace("l2\n", "# l2\n")
+                    .replace("l15\n", "  // l15\n"),
+            ),
+            (
+                original.clone(),
+                original
+                    .replace("l3\n", "/*\nl3\n")
+                    .replace("l8\n", "l8\n*/\n"),
+            ),
+            ("a\nb".to_string(), "a\n# b\n".to_string()),
+        ] {
+            let patch = patch_for(&old, &new);
+            let hunks = patch.split_once("+++ b/src/a.rs\n").unwrap().1;
+            assert_eq!(apply_to(hunks, &old).as_deref(), Ok(new.as_str()));
+        }
+
+        // Changed code is refused, and context must match the file
+        let hunk = "@@ -2 +2 @@\n-l2\n+// l2\n";
+        assert_eq!(
+            apply_to("@@ -2 +2 @@\n-l2\n+l2 = 0\n", &original),
+            Err(PatchError::NotCommentChange { patch_line: 3 })
+        );
+        assert_eq!(
+            apply_to(hunk, &original.replace("l2\n", "x\n")),
+            Err(PatchError::ContextMismatch { line: 2 })
+        );
+        assert_eq!(
+            apply_to(hunk, "l1\n"),
+            Err(PatchError::ContextMismatch { line: 2 })
+        );
+
+        // Bad hunk header, short body, hunks out of order, second file
+        assert_eq!(
+            apply_to("@@ -x +2 @@\n", &original),
+            Err(PatchError::Malformed { patch_line: 3 })
+        );
+        assert_eq!(
+            apply_to("@@ -2,2 +2,2 @@\n-l2\n", &original),
+            Err(PatchError::Malformed { patch_line: 4 })
+        );
+        assert_eq!(
+            apply_to(&format!("{}@@ -1 +1 @@\n-l1\n+# l1\n", hunk), &original),
+            Err(PatchError::Malformed { patch_line: 6 })
+        );
+        assert_eq!(
+            apply_to(&format!("{}--- a/b.rs\n", hunk), &original),
+            Err(PatchError::Malformed { patch_line: 6 })
+        );
+        assert_eq!(
+            apply_to("", &original),
+            Err(PatchError::Malformed { patch_line: 2 })
+        );
+    }
+
+    #[test]
+    fn test_apply_patch_file() {
+        let path = "test_patch_apply.rs";
+        let patch_path = "test_patch_apply.patch";
+        let backup = PathBuf::from("backup_toggle_comment_test_patch_apply.rs");
+        std::fs::write(path, "fn a() {}\nfn b() {}\n").unwrap();
+        std::fs::write(
+            patch_path,
+            "diff --git a/test_patch_apply.rs b/test_patch_apply.rs\n--- a/test_patch_apply.rs\n+++ b/test_patch_apply.rs\n@@ -1,2 +1,2 @@\n fn a() {}\n-fn b() {}\n+// fn b() {}\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            apply_patch(patch_path, &ToggleOptions::default()),
+            Ok(AppliedPatch {
+                target: path.to_string(),
+                hunks: 1,
+                lines: 1
+            })
+        );
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "fn a() {}\n// fn b() {}\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&backup).unwrap(),
+            "fn a() {}\nfn b() {}\n"
+        );
+
+        // Applying again: the context no longer matches, the file is untouched
+        assert_eq!(
+            apply_patch(patch_path, &ToggleOptions::default()),
+            Err(PatchError::ContextMismatch { line: 2 })
+        );
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "fn a() {}\n// fn b() {}\n"
+        );
+
+        for file in [
+            Path::new(path),
+            Path::new(patch_path),
+            &backup,
+            &backup_checksum_path(&backup),
+        ] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn test_verify_against_backup() {
+        let path = "test_patch_verify.py";
+        let backup = PathBuf::from("backup_toggle_comment_test_patch_verify.py");
+        let options = ToggleOptions::default();
+        std::fs::write(path, "a\nb\nc\nd\n").unwrap();
+
+        // No backup yet
+        assert_eq!(
+            verify_against_backup(path, &options, &mut |_| {}),
+            Err(PatchError::Toggle(ToggleCommentError::FileNotFound))
+        );
+
+        // Two toggles and an indent: three lines in two runs
+        toggle_basic_singleline_comment_bytewise(path, 1).unwrap();
+        std::fs::write(path, "a\n# b\n    c\n#   d\n").unwrap();
+        let mut runs: Vec<ChangedRun> = Vec::new();
+        assert_eq!(
+            verify_against_backup(path, &options, &mut |run| runs.push(run)),
+            Ok(1)
+        );
+        assert_eq!(
+            runs,
+            vec![ChangedRun {
+                line: 1,
+                lines: 3,
+                backup_lines: 3
+            }]
+        );
+
+        // Code changed as well: the run starts at the toggle on line 1, the
+        // first line that is not a comment change is line 2
+        std::fs::write(path, "a\n# b\nc = 1\nd\n").unwrap();
+        assert_eq!(
+            verify_against_backup(path, &options, &mut |_| {}),
+            Err(PatchError::UnexpectedChange { line: 2 })
+        );
+
+        // A longer run: toggles and an indent ahead of the edit on line 3,
+        // and a removed line at the end of a run
+        std::fs::write(path, "# a\n# b\n    c\nd = 1\n").unwrap();
+        assert_eq!(
+            verify_against_backup(path, &options, &mut |_| {}),
+            Err(PatchError::UnexpectedChange { line: 3 })
+        );
+        std::fs::write(path, "# a\n# b\nc\n").unwrap();
+        assert_eq!(
+            verify_against_backup(path, &options, &mut |_| {}),
+            Err(PatchError::UnexpectedChange { line: 3 })
+        );
+
+        // Identical file, then a backup taken from another file of that name
+        std::fs::write(path, "a\nb\nc\nd\n").unwrap();
+        assert_eq!(verify_against_backup(path, &options, &mut |_| {}), Ok(0));
+        let sidecar = backup_checksum_path(&backup);
+        let recorded = std::fs::read_to_string(&sidecar).unwrap();
+        let (checksum, _) = recorded.split_once('\n').unwrap();
+        std::fs::write(&sidecar, format!("{checksum}\norigin /elsewhere/{path}\n")).unwrap();
+        assert_eq!(
+            verify_against_backup(path, &options, &mut |_| {}),
+            Err(PatchError::Toggle(ToggleCommentError::BackupIntegrity))
+        );
+        std::fs::write(&sidecar, recorded).unwrap();
+
+        // A tampered backup
+        std::fs::write(&backup, "x\n").unwrap();
+        assert_eq!(
+            verify_against_backup(path, &options, &mut |_| {}),
+            Err(PatchError::Toggle(ToggleCommentError::BackupIntegrity))
+        );
+
+        for file in [Path::new(path), &backup, &backup_checksum_path(&backup)] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn test_preview_copy_leaves_original() {
+        let path = "test_patch_preview.py";
+        std::fs::write(path, "a\nb\n").unwrap();
+
+        let copy = PreviewCopy::create(path).unwrap();
+        assert!(copy.path().ends_with("test_patch_preview.py"));
+        std::fs::write(copy.path(), "a\n# b\n").unwrap();
+
+        let mut script: Vec<u8> = Vec::new();
+        assert_eq!(copy.write_sed_script(&mut script, path), Ok(true));
+        assert_eq!(
+            String::from_utf8(script).unwrap(),
+            "# toggle_comment planned changes: sed -i -f <this file> test_patch_preview.py\n2c\\\n# b\n"
+        );
+
+        assert_eq!(copy.check_original_unchanged(), Ok(()));
+        std::fs::write(path, "a\nb\nc\n").unwrap();
+        assert_eq!(
+            copy.check_original_unchanged(),
+            Err(ToggleCommentError::ConcurrentModification)
+        );
+
+        let scratch = PathBuf::from(copy.path());
+        drop(copy);
+        assert!(!scratch.exists());
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "a\nb\nc\n");
+        let _ = std::fs::remove_file(path);
+    }
+}
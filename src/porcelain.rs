@@ -0,0 +1,211 @@
+//! # porcelain.rs
+//!
+//! Machine-readable CLI output (`--porcelain`) for language bindings
+//!
+//! With `--porcelain` every operation that runs prints exactly one record per
+//! result on stdout (one per key for queries) instead of the human message:
+//!
+//! ```text
+//! <version> TAB <kind> TAB <mode> TAB <file> TAB <field>...
+//! 1 ok toggle src/main.rs 4 4 1
+//! 1 error block src/lib.rs 5 Line 90 not found (file has 12 lines)
+//! (columns shown space-separated here; the CLI separates them with tabs)
+//! ```
+//!
+//! - `version` is PORCELAIN_VERSION; columns are only ever appended within a
+//!   version, anything else bumps it
+//! - `mode` is the mode flag without `--` (`toggle` for basic mode)
+//! - Absent numeric fields are `-`
+//! - Tab, newline, carriage return and backslash inside a field are written
+//!   as `\t`, `\n`, `\r`, `\\`
+//! - Usage errors (exit code 1 before any operation runs) print no record;
+//!   the exit code is authoritative either way
+//!
+//! `RECORD_KINDS` is the single source of truth: the CLI formats records
+//! from it and `python_wrapper()` generates `bindings/python/toggle_comment.py`
+//! from it; a test fails when the shipped wrapper is out of date.
+
+/// Porcelain protocol version (first column of every record)
+pub const PORCELAIN_VERSION: u32 = 1;
+
+/// One record kind: `(kind, fields after <file>, description)`
+pub type RecordKind = (&'static str, &'static [&'static str], &'static str);
+
+/// Every record the CLI prints with `--porcelain`
+pub const RECORD_KINDS: &[RecordKind] = &[
+    (
+        "ok",
+        &["first_line", "last_line", "count"],
+        "Operation done: zero-indexed line span (or -) and number of lines",
+    ),
+    (
+        "unchanged",
+        &["reason"],
+        "Nothing to do for this file type; reason is always unsupported",
+    ),
+    (
+        "value",
+        &["key", "value"],
+        "One answer of a read-only query (--detect, --column, --relative, --query-range, --lint-comments, --inspect, --verify-only)",
+    ),
+    (
+        "line",
+        &["line", "state", "leading_whitespace", "flag_kind"],
+        "One line of --query-lines: zero-indexed line, C / . / B, leading spaces and tabs, flag or none",
+    ),
+    (
+        "error",
+        &["exit_code", "message"],
+        "Operation failed: the process exit code and a human message",
+    ),
+];
+
+/// Escape one field (tab, newline, carriage return, backslash)
+pub fn escape_field(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format a record line (no trailing newline)
+///
+/// # Arguments
+/// * `kind` - One of RECORD_KINDS
+/// * `mode` - Mode name (`toggle`, `block`, ...)
+/// * `file` - Target path as given on the command line
+/// * `fields` - Kind-specific fields, in RECORD_KINDS order
+///
+/// # Returns
+/// * `None` - Unknown kind or wrong field count (a CLI bug; caller prints nothing)
+pub fn format_record(kind: &str, mode: &str, file: &str, fields: &[&str]) -> Option<String> {
+    let (_, names, _) = RECORD_KINDS.iter().find(|(name, _, _)| *name == kind)?;
+    if names.len() != fields.len() {
+        return None;
+    }
+
+    let mut line = format!(
+        "{}\t{}\t{}\t{}",
+        PORCELAIN_VERSION,
+        kind,
+        mode,
+        escape_field(file)
+    );
+    for field in fields {
+        line.push('\t');
+        line.push_str(&escape_field(field));
+    }
+    Some(line)
+}
+
+/// Generate the reference Python wrapper (`bindings/python/toggle_comment.py`)
+///
+/// Standard library only (subprocess); one namedtuple per record kind.
+pub fn python_wrapper() -> String {
+    let mut py = String::new();
+    py.push_str(
+        "# toggle_comment.py - generated from src/porcelain.rs by python_wrapper(); do not edit\n",
+    );
+    py.push_str(
+        "\"\"\"Run toggle_comment_indent with --porcelain and parse its records.\"\"\"\n\n",
+    );
+    py.push_str("import subprocess\nfrom collections import namedtuple\n\n");
+    py.push_str(&format!("PORCELAIN_VERSION = {}\n\n", PORCELAIN_VERSION));
+
+    for (kind, fields, description) in RECORD_KINDS {
+        py.push_str(&format!("# {}\n", description));
+        let mut columns = vec!["'mode'".to_string(), "'file'".to_string()];
+        columns.extend(fields.iter().map(|f| format!("'{}'", f)));
+        py.push_str(&format!(
+            "{} = namedtuple('{}', [{}])\n",
+            class_name(kind),
+            class_name(kind),
+            columns.join(", ")
+        ));
+    }
+
+    py.push_str("\nRECORD_KINDS = {\n");
+    for (kind, _, _) in RECORD_KINDS {
+        py.push_str(&format!("    '{}': {},\n", kind, class_name(kind)));
+    }
+    py.push_str("}\n\n");
+
+    py.push_str(
+        "_UNESCAPE = {'t': '\\t', 'n': '\\n', 'r': '\\r', '\\\\': '\\\\'}\n\n\n\
+def _unescape(field):\n\
+\x20   out, chars = [], iter(field)\n\
+\x20   for c in chars:\n\
+\x20       out.append(_UNESCAPE.get(next(chars, ''), '') if c == '\\\\' else c)\n\
+\x20   return ''.join(out)\n\n\n\
+def parse_record(line):\n\
+\x20   \"\"\"Parse one porcelain line into its namedtuple (ValueError if malformed).\"\"\"\n\
+\x20   parts = [_unescape(p) for p in line.rstrip('\\n').split('\\t')]\n\
+\x20   if len(parts) < 4 or parts[0] != str(PORCELAIN_VERSION):\n\
+\x20       raise ValueError('unsupported porcelain record: %r' % line)\n\
+\x20   kind = RECORD_KINDS.get(parts[1])\n\
+\x20   if kind is None or len(parts) - 2 != len(kind._fields):\n\
+\x20       raise ValueError('unsupported porcelain record: %r' % line)\n\
+\x20   return kind(*parts[2:])\n\n\n\
+def run(*args, binary='toggle_comment_indent'):\n\
+\x20   \"\"\"Run one command; returns (exit_code, [records]).\"\"\"\n\
+\x20   done = subprocess.run([binary, '--porcelain', *map(str, args)],\n\
+\x20                         capture_output=True, text=True)\n\
+\x20   records = [parse_record(l) for l in done.stdout.splitlines() if l]\n\
+\x20   return done.returncode, records\n",
+    );
+    py
+}
+
+/// Python class name for a record kind (`ok` -> `Ok`)
+fn class_name(kind: &str) -> String {
+    let mut chars = kind.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod porcelain_tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_format_record() {
+        assert_eq!(
+            format_record("ok", "toggle", "a b.rs", &["4", "4", "1"]).as_deref(),
+            Some("1\tok\ttoggle\ta b.rs\t4\t4\t1")
+        );
+        assert_eq!(
+            format_record("error", "block", "x\ty.rs", &["5", "two\nlines \\"]).as_deref(),
+            Some("1\terror\tblock\tx\\ty.rs\t5\ttwo\\nlines \\\\")
+        );
+        // Field count is checked against RECORD_KINDS
+        assert_eq!(format_record("ok", "toggle", "a.rs", &["4"]), None);
+        assert_eq!(format_record("done", "toggle", "a.rs", &[]), None);
+    }
+
+    #[test]
+    fn test_python_wrapper_is_current() {
+        let wrapper_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("bindings/python/toggle_comment.py");
+        let generated = python_wrapper();
+
+        // Regenerate: TC_REGENERATE_BINDINGS=1 cargo test
+        if std::env::var_os("TC_REGENERATE_BINDINGS").is_some() {
+            std::fs::write(&wrapper_path, &generated).unwrap();
+        }
+        let shipped = std::fs::read_to_string(&wrapper_path).unwrap_or_default();
+        assert_eq!(
+            shipped, generated,
+            "bindings/python/toggle_comment.py is out of date"
+        );
+    }
+}
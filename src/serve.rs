@@ -0,0 +1,816 @@
+//! # serve.rs
+//!
+//! Command server for editors (`--serve`): JSON-RPC 2.0 over stdin/stdout
+//!
+//! Editors (or a minimal LSP `workspace/executeCommand` bridge) spawn the
+//! binary once and send requests instead of starting a process per toggle.
+//! Messages use LSP framing:
+//!
+//! ```text
+//! Content-Length: 63\r\n
+//! \r\n
+//! {"jsonrpc":"2.0","id":1,"method":"toggleComment","params":{...}}
+//! ```
+//!
+//! ## Methods
+//! - `toggleComment` `{file, lines: [n, ...], docstring?: bool}` ->
+//!   `{"toggled": count}` (basic flag from the extension, `///` with docstring)
+//! - `indentRange` `{file, start, end, unindent?: bool}` -> `{"lines": count}`
+//! - `queryState` `{file, line?}` -> `{lineEnding, finalNewline, indent,
+//!   commentFlag, column?}` (read-only, same answers as `--detect` / `--column`)
+//! - `shutdown` -> `null`; the `exit` notification (or end of input) stops the server
+//!
+//! Lines are zero-indexed. A failed operation answers with error code -32000
+//! and the library error as message; the file is left as the operation
+//! leaves it (unchanged unless the error says otherwise). A result object
+//! gets `"warnings": [{"kind", "message"}, ...]` (and `"warningsDropped": n`
+//! beyond MAX_WARNINGS) when the operation recorded any (see `Warning`).
+//!
+//! ## Policy
+//! - Standard library only: a small bounded JSON reader/writer lives here
+//!   (no recursion; nesting is tracked on an explicit stack)
+//! - Messages over MAX_RPC_MESSAGE_BYTES, header lines over
+//!   MAX_RPC_HEADER_BYTES and JSON nested deeper than MAX_JSON_DEPTH are refused
+//! - One request at a time, answered in order; notifications get no answer
+
+use crate::toggle_comment_indent_module::{
+    ToggleOptions, Warnings, comment_column_in_file, detect_file_style,
+    indent_range_bytewise_with_options, take_warnings, toggle_multiple_basic_comments_with_options,
+    toggle_multiple_singleline_docstrings_with_options, unindent_range_bytewise_with_options,
+};
+use std::io::{BufRead, Read, Write};
+
+/// Largest accepted message body (Content-Length)
+pub const MAX_RPC_MESSAGE_BYTES: usize = 1024 * 1024;
+
+/// Largest accepted header line, including CRLF
+const MAX_RPC_HEADER_BYTES: usize = 256;
+
+/// Deepest accepted array/object nesting (also the capacity of the explicit
+/// stacks the JSON reader and writer use instead of recursion)
+const MAX_JSON_DEPTH: usize = 32;
+
+/// Most header lines before the blank line
+const MAX_RPC_HEADER_LINES: usize = 16;
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// Operation error (implementation-defined server error range)
+const OPERATION_FAILED: i32 = -32000;
+
+// ============================================================================
+// JSON VALUE (bounded reader / writer)
+// ============================================================================
+
+/// Parsed JSON value (object keys kept in input order)
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Member of an object by key
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Non-negative integer that fits a line number exactly
+    fn as_line(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n < 9_007_199_254_740_992.0 => {
+                Some(*n as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Array or object still being read: its children so far (an object also
+/// holds the key of the member being read)
+enum OpenContainer {
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>, String),
+}
+
+/// JSON reader over a complete message body
+struct JsonReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl JsonReader<'_> {
+    /// Parse a whole document (trailing whitespace only)
+    fn parse_document(bytes: &[u8]) -> Option<Json> {
+        let mut reader = JsonReader { bytes, position: 0 };
+        let value = reader.parse_value()?;
+        reader.skip_whitespace();
+        if reader.position == bytes.len() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    fn expect_literal(&mut self, literal: &[u8], value: Json) -> Option<Json> {
+        if self.bytes[self.position..].starts_with(literal) {
+            self.position += literal.len();
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Parse one value; nested arrays/objects use an explicit stack of open
+    /// containers (no recursion) holding at most MAX_JSON_DEPTH entries
+    fn parse_value(&mut self) -> Option<Json> {
+        let mut stack: Vec<OpenContainer> = Vec::with_capacity(MAX_JSON_DEPTH);
+
+        loop {
+            // One value: a scalar, an empty container, or the start of a container
+            self.skip_whitespace();
+            let mut value = match *self.bytes.get(self.position)? {
+                open @ (b'[' | b'{') => {
+                    if stack.len() == MAX_JSON_DEPTH {
+                        return None;
+                    }
+                    self.position += 1;
+                    self.skip_whitespace();
+                    let close = if open == b'[' { b']' } else { b'}' };
+                    if self.bytes.get(self.position) == Some(&close) {
+                        self.position += 1;
+                        if open == b'[' {
+                            Json::Array(Vec::new())
+                        } else {
+                            Json::Object(Vec::new())
+                        }
+                    } else {
+                        stack.push(if open == b'[' {
+                            OpenContainer::Array(Vec::new())
+                        } else {
+                            OpenContainer::Object(Vec::new(), self.parse_key()?)
+                        });
+                        continue;
+                    }
+                }
+                _ => self.parse_scalar()?,
+            };
+
+            // Hand the value to the enclosing containers, closing each that ends here
+            loop {
+                let Some(top) = stack.last_mut() else {
+                    return Some(value);
+                };
+                self.skip_whitespace();
+                let separator = *self.bytes.get(self.position)?;
+                self.position += 1;
+                match top {
+                    OpenContainer::Array(items) => {
+                        items.push(value);
+                        match separator {
+                            b',' => break,
+                            b']' => {}
+                            _ => return None,
+                        }
+                    }
+                    OpenContainer::Object(members, key) => {
+                        members.push((std::mem::take(key), value));
+                        match separator {
+                            b',' => {
+                                *key = self.parse_key()?;
+                                break;
+                            }
+                            b'}' => {}
+                            _ => return None,
+                        }
+                    }
+                }
+                value = match stack.pop()? {
+                    OpenContainer::Array(items) => Json::Array(items),
+                    OpenContainer::Object(members, _) => Json::Object(members),
+                };
+            }
+        }
+    }
+
+    /// Object key and its `:` (a member is expected at the current position)
+    fn parse_key(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        if self.bytes.get(self.position) != Some(&b'"') {
+            return None;
+        }
+        let key = self.parse_string()?;
+        self.skip_whitespace();
+        if self.bytes.get(self.position) != Some(&b':') {
+            return None;
+        }
+        self.position += 1;
+        Some(key)
+    }
+
+    /// Literal, string or number at the current position
+    fn parse_scalar(&mut self) -> Option<Json> {
+        match *self.bytes.get(self.position)? {
+            b'n' => self.expect_literal(b"null", Json::Null),
+            b't' => self.expect_literal(b"true", Json::Bool(true)),
+            b'f' => self.expect_literal(b"false", Json::Bool(false)),
+            b'"' => self.parse_string().map(Json::String),
+            b'-' | b'0'..=b'9' => {
+                let start = self.position;
+                while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+                    self.bytes.get(self.position)
+                {
+                    self.position += 1;
+                }
+                let text = std::str::from_utf8(&self.bytes[start..self.position]).ok()?;
+                text.parse::<f64>().ok().map(Json::Number)
+            }
+            _ => None,
+        }
+    }
+
+    /// Parse a string starting at the opening quote
+    fn parse_string(&mut self) -> Option<String> {
+        self.position += 1; // opening quote
+        let mut out: Vec<u8> = Vec::new();
+
+        loop {
+            let byte = *self.bytes.get(self.position)?;
+            self.position += 1;
+            match byte {
+                b'"' => return String::from_utf8(out).ok(),
+                b'\\' => {
+                    let escape = *self.bytes.get(self.position)?;
+                    self.position += 1;
+                    let decoded = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.parse_unicode_escape()?,
+                        _ => return None,
+                    };
+                    let mut utf8 = [0u8; 4];
+                    out.extend_from_slice(decoded.encode_utf8(&mut utf8).as_bytes());
+                }
+                0x00..=0x1f => return None, // raw control character
+                _ => out.push(byte),
+            }
+        }
+    }
+
+    /// `\uXXXX` (after the `u`), joining a surrogate pair when present
+    fn parse_unicode_escape(&mut self) -> Option<char> {
+        let high = self.parse_hex4()?;
+        if (0xD800..0xDC00).contains(&high) {
+            if !self.bytes[self.position..].starts_with(b"\\u") {
+                return None;
+            }
+            self.position += 2;
+            let low = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return None;
+            }
+            char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+        } else {
+            char::from_u32(high)
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Option<u32> {
+        let digits = self.bytes.get(self.position..self.position + 4)?;
+        self.position += 4;
+        u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    }
+}
+
+/// Array or object still being written: its remaining children, and whether
+/// one has been written yet
+enum PendingContainer<'a> {
+    Array(std::slice::Iter<'a, Json>, bool),
+    Object(std::slice::Iter<'a, (String, Json)>, bool),
+}
+
+/// Append `value` as JSON text
+///
+/// Nesting uses an explicit stack (no recursion). Values written here are
+/// read by `JsonReader` or built by the server, so they nest at most
+/// MAX_JSON_DEPTH deep.
+fn write_json(out: &mut String, value: &Json) {
+    let mut stack: Vec<PendingContainer> = Vec::with_capacity(MAX_JSON_DEPTH);
+    let mut next = Some(value);
+
+    loop {
+        match next.take() {
+            Some(Json::Null) => out.push_str("null"),
+            Some(Json::Bool(b)) => out.push_str(if *b { "true" } else { "false" }),
+            Some(Json::Number(n)) => out.push_str(&n.to_string()),
+            Some(Json::String(s)) => write_json_string(out, s),
+            Some(Json::Array(items)) => {
+                out.push('[');
+                stack.push(PendingContainer::Array(items.iter(), false));
+            }
+            Some(Json::Object(members)) => {
+                out.push('{');
+                stack.push(PendingContainer::Object(members.iter(), false));
+            }
+            None => {}
+        }
+
+        // Next child of the innermost open container, closing those that are done
+        let Some(top) = stack.last_mut() else {
+            return;
+        };
+        match top {
+            PendingContainer::Array(items, started) => match items.next() {
+                Some(item) => {
+                    if *started {
+                        out.push(',');
+                    }
+                    *started = true;
+                    next = Some(item);
+                }
+                None => {
+                    out.push(']');
+                    stack.pop();
+                }
+            },
+            PendingContainer::Object(members, started) => match members.next() {
+                Some((key, item)) => {
+                    if *started {
+                        out.push(',');
+                    }
+                    *started = true;
+                    write_json_string(out, key);
+                    out.push(':');
+                    next = Some(item);
+                }
+                None => {
+                    out.push('}');
+                    stack.pop();
+                }
+            },
+        }
+    }
+}
+
+/// Append `s` as a JSON string literal
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// ============================================================================
+// FRAMING AND DISPATCH
+// ============================================================================
+
+/// Read one framed message body
+///
+/// # Returns
+/// * `Ok(Some(body))` - Complete body
+/// * `Ok(None)` - Clean end of input before a new message
+/// * `Err(_)` - Read failed, or malformed / oversized framing (the stream
+///   cannot be resynchronised, so the server stops)
+fn read_message<R: BufRead>(input: &mut R) -> std::io::Result<Option<Vec<u8>>> {
+    let invalid =
+        |what: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, what.to_string());
+    let mut content_length: Option<usize> = None;
+
+    for header_index in 0..=MAX_RPC_HEADER_LINES {
+        if header_index == MAX_RPC_HEADER_LINES {
+            return Err(invalid("too many header lines"));
+        }
+
+        let mut line: Vec<u8> = Vec::new();
+        let read = input
+            .by_ref()
+            .take(MAX_RPC_HEADER_BYTES as u64)
+            .read_until(b'\n', &mut line)?;
+        if read == 0 {
+            return if header_index == 0 {
+                Ok(None)
+            } else {
+                Err(invalid("end of input inside headers"))
+            };
+        }
+        if line.last() != Some(&b'\n') {
+            return Err(invalid("header line too long"));
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+        if text.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = text.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse::<usize>().ok();
+            if content_length.is_none() {
+                return Err(invalid("bad Content-Length"));
+            }
+        }
+    }
+
+    let length = match content_length {
+        Some(n) if n <= MAX_RPC_MESSAGE_BYTES => n,
+        Some(_) => return Err(invalid("message too large")),
+        None => return Err(invalid("missing Content-Length")),
+    };
+
+    let mut body = vec![0u8; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Write one framed message
+fn write_message<W: Write>(output: &mut W, body: &str) -> std::io::Result<()> {
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Response body for a result or an error
+fn response_body(id: &Json, outcome: Result<Json, (i32, String)>) -> String {
+    let mut body = String::from("{\"jsonrpc\":\"2.0\",\"id\":");
+    write_json(&mut body, id);
+    match outcome {
+        Ok(result) => {
+            body.push_str(",\"result\":");
+            write_json(&mut body, &result);
+        }
+        Err((code, message)) => {
+            body.push_str(&format!(",\"error\":{{\"code\":{},\"message\":", code));
+            write_json_string(&mut body, &message);
+            body.push('}');
+        }
+    }
+    body.push('}');
+    body
+}
+
+/// Run the server until `exit` or end of input
+///
+/// # Arguments
+/// * `input` - Framed requests (stdin)
+/// * `output` - Framed responses (stdout)
+/// * `options` - Library options from the global flags, for every request
+///
+/// # Returns
+/// * `Ok(())` - `exit` received or input ended cleanly
+/// * `Err(_)` - I/O failure or unrecoverable framing error
+pub fn serve<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &ToggleOptions,
+) -> std::io::Result<()> {
+    // One message per iteration; input ends the loop long before this bound
+    for _ in 0..u32::MAX {
+        let body = match read_message(input)? {
+            Some(body) => body,
+            None => return Ok(()),
+        };
+
+        let request = match JsonReader::parse_document(&body) {
+            Some(request) => request,
+            None => {
+                let reply = response_body(&Json::Null, Err((PARSE_ERROR, "Parse error".into())));
+                write_message(output, &reply)?;
+                continue;
+            }
+        };
+
+        let method = request.get("method").and_then(Json::as_str);
+        let id = request.get("id");
+
+        if method == Some("exit") {
+            return Ok(());
+        }
+
+        // Nothing left over from an earlier request
+        let _ = take_warnings();
+        let outcome = match method {
+            Some(method) => dispatch(method, request.get("params"), options),
+            None => Err((INVALID_REQUEST, "Missing method".into())),
+        };
+        let outcome = outcome.map(|result| with_warnings(result, take_warnings()));
+
+        // Notifications (no id) are never answered
+        if let Some(id) = id {
+            write_message(output, &response_body(id, outcome))?;
+        }
+    }
+    Ok(())
+}
+
+/// Add the request's warnings to an object result
+fn with_warnings(result: Json, warnings: Warnings) -> Json {
+    match result {
+        Json::Object(mut fields) if !warnings.list.is_empty() => {
+            let list = warnings
+                .list
+                .iter()
+                .map(|warning| {
+                    Json::Object(vec![
+                        ("kind".into(), Json::String(warning.kind().into())),
+                        ("message".into(), Json::String(warning.to_string())),
+                    ])
+                })
+                .collect();
+            fields.push(("warnings".into(), Json::Array(list)));
+            if warnings.dropped > 0 {
+                fields.push((
+                    "warningsDropped".into(),
+                    Json::Number(warnings.dropped as f64),
+                ));
+            }
+            Json::Object(fields)
+        }
+        other => other,
+    }
+}
+
+/// Run one method
+fn dispatch(
+    method: &str,
+    params: Option<&Json>,
+    options: &ToggleOptions,
+) -> Result<Json, (i32, String)> {
+    let invalid = |what: &str| (INVALID_PARAMS, format!("Invalid params: {}", what));
+    let failed = |error: &dyn std::fmt::Display| (OPERATION_FAILED, error.to_string());
+    let params = params.unwrap_or(&Json::Null);
+    let file = || {
+        params
+            .get("file")
+            .and_then(Json::as_str)
+            .ok_or(invalid("file"))
+    };
+    let flag = |key: &str| matches!(params.get(key), Some(Json::Bool(true)));
+
+    match method {
+        "shutdown" => Ok(Json::Null),
+
+        "toggleComment" => {
+            let file = file()?;
+            let lines: Vec<usize> = match params.get("lines") {
+                Some(Json::Array(items)) => items
+                    .iter()
+                    .map(Json::as_line)
+                    .collect::<Option<Vec<usize>>>()
+                    .ok_or(invalid("lines"))?,
+                _ => return Err(invalid("lines")),
+            };
+            let result = if flag("docstring") {
+                toggle_multiple_singleline_docstrings_with_options(file, &lines, options)
+            } else {
+                toggle_multiple_basic_comments_with_options(file, &lines, options)
+            };
+            result.map_err(|e| failed(&e))?;
+            Ok(Json::Object(vec![(
+                "toggled".into(),
+                Json::Number(lines.len() as f64),
+            )]))
+        }
+
+        "indentRange" => {
+            let file = file()?;
+            let line = |key: &str| params.get(key).and_then(Json::as_line).ok_or(invalid(key));
+            let (start, end) = (line("start")?, line("end")?);
+            if start > end {
+                return Err(invalid("start after end"));
+            }
+            let result = if flag("unindent") {
+                unindent_range_bytewise_with_options(file, start, end, options)
+            } else {
+                indent_range_bytewise_with_options(file, start, end, options)
+            };
+            result.map_err(|e| failed(&e))?;
+            Ok(Json::Object(vec![(
+                "lines".into(),
+                Json::Number((end - start + 1) as f64),
+            )]))
+        }
+
+        "queryState" => {
+            let file = file()?;
+            let style = detect_file_style(file).map_err(|e| failed(&e))?;
+            let text = |s: &str| Json::String(s.to_string());
+            let mut state = vec![
+                ("lineEnding".to_string(), text(style.line_ending.name())),
+                ("finalNewline".to_string(), Json::Bool(style.final_newline)),
+                ("indent".to_string(), text(style.indent.name())),
+                (
+                    "commentFlag".to_string(),
+                    style.comment_flag.map_or(Json::Null, text),
+                ),
+            ];
+            match params.get("line") {
+                None => {}
+                Some(value) => {
+                    let line = value.as_line().ok_or(invalid("line"))?;
+                    let column =
+                        comment_column_in_file(file, line, options).map_err(|e| failed(&e))?;
+                    state.push((
+                        "column".to_string(),
+                        column.map_or(Json::Null, |c| Json::Number(c as f64)),
+                    ));
+                }
+            }
+            Ok(Json::Object(state))
+        }
+
+        _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    }
+}
+
+#[cfg(test)]
+mod serve_tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    /// Run the server over `requests` and return the response bodies
+    fn run(requests: &[&str]) -> Vec<String> {
+        let input: String = requests.iter().map(|r| frame(r)).collect();
+        let mut output: Vec<u8> = Vec::new();
+        serve(
+            &mut input.as_bytes(),
+            &mut output,
+            &ToggleOptions::default(),
+        )
+        .unwrap();
+
+        let text = String::from_utf8(output).unwrap();
+        text.split("Content-Length: ")
+            .filter(|part| !part.is_empty())
+            .map(|part| part.split_once("\r\n\r\n").unwrap().1.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_json_reader() {
+        let value = JsonReader::parse_document(
+            r#" {"a": [1, -2.5e1, true, null], "s": "x\"é\ud83d\ude00\n"} "#.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            value.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Number(-25.0),
+                Json::Bool(true),
+                Json::Null
+            ]))
+        );
+        assert_eq!(
+            value.get("s").and_then(Json::as_str),
+            Some("x\"\u{e9}\u{1F600}\n")
+        );
+
+        let mut out = String::new();
+        write_json(&mut out, &value);
+        assert_eq!(JsonReader::parse_document(out.as_bytes()), Some(value));
+
+        assert_eq!(JsonReader::parse_document(b"{\"a\":1,}"), None);
+        assert_eq!(JsonReader::parse_document(b"[1] 2"), None);
+        assert_eq!(JsonReader::parse_document(b"[1,]"), None);
+        assert_eq!(JsonReader::parse_document(b"{\"a\" 1}"), None);
+        assert_eq!(JsonReader::parse_document("[".repeat(40).as_bytes()), None);
+
+        // Nesting up to MAX_JSON_DEPTH reads and writes back unchanged
+        let nested = format!(
+            "{}{{\"k\":[[],{{}},\"v\"]}}{}",
+            "[".repeat(MAX_JSON_DEPTH - 3),
+            "]".repeat(MAX_JSON_DEPTH - 3)
+        );
+        let value = JsonReader::parse_document(nested.as_bytes()).unwrap();
+        let mut out = String::new();
+        write_json(&mut out, &value);
+        assert_eq!(out, nested);
+        let too_deep = format!("[{}]", nested);
+        assert_eq!(JsonReader::parse_document(too_deep.as_bytes()), None);
+        assert_eq!(Json::Number(2.5).as_line(), None);
+        assert_eq!(Json::Number(-1.0).as_line(), None);
+    }
+
+    #[test]
+    fn test_serve_methods() {
+        let path = "test_serve_methods.py";
+        std::fs::write(path, "a\n    b\n").unwrap();
+
+        let responses = run(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"toggleComment","params":{"file":"test_serve_methods.py","lines":[0]}}"#,
+            r#"{"jsonrpc":"2.0","id":"two","method":"indentRange","params":{"file":"test_serve_methods.py","start":1,"end":1,"unindent":true}}"#,
+            r#"{"jsonrpc":"2.0","method":"toggleComment","params":{"file":"test_serve_methods.py","lines":[1]}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"queryState","params":{"file":"test_serve_methods.py","line":0}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"toggleComment","params":{"file":"test_serve_methods.py","lines":[9]}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"nope"}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"indentRange","params":{"file":"test_serve_methods.py"}}"#,
+            "{not json",
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+            r#"{"jsonrpc":"2.0","id":7,"method":"shutdown"}"#,
+        ]);
+
+        assert_eq!(
+            responses,
+            vec![
+                r#"{"jsonrpc":"2.0","id":1,"result":{"toggled":1}}"#.to_string(),
+                r#"{"jsonrpc":"2.0","id":"two","result":{"lines":1}}"#.to_string(),
+                // The notification ran (line 1 commented) but was not answered
+                r##"{"jsonrpc":"2.0","id":3,"result":{"lineEnding":"lf","finalNewline":true,"indent":"none","commentFlag":"#","column":0}}"##.to_string(),
+                r#"{"jsonrpc":"2.0","id":4,"error":{"code":-32000,"message":"Line 9 not found (file has 3 lines)"}}"#.to_string(),
+                r#"{"jsonrpc":"2.0","id":5,"error":{"code":-32601,"message":"Method not found: nope"}}"#.to_string(),
+                r#"{"jsonrpc":"2.0","id":6,"error":{"code":-32602,"message":"Invalid params: start"}}"#.to_string(),
+                r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"Parse error"}}"#.to_string(),
+            ]
+        );
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "# a\n# b\n");
+
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file("backup_toggle_comment_test_serve_methods.py");
+        let _ = std::fs::remove_file("backup_toggle_comment_test_serve_methods.py.sha");
+    }
+
+    #[test]
+    fn test_serve_warnings() {
+        let path = "test_serve_warnings.py";
+        std::fs::write(path, "a\r\nb\n").unwrap();
+
+        let responses = run(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"toggleComment","params":{"file":"test_serve_warnings.py","lines":[0]}}"#,
+        ]);
+        assert_eq!(
+            responses,
+            vec![
+                r#"{"jsonrpc":"2.0","id":1,"result":{"toggled":1,"warnings":[{"kind":"mixed_line_endings","message":"file mixes line endings (1 LF, 1 CRLF); kept as they are"}]}}"#.to_string()
+            ]
+        );
+
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file("backup_toggle_comment_test_serve_warnings.py");
+        let _ = std::fs::remove_file("backup_toggle_comment_test_serve_warnings.py.sha");
+    }
+
+    #[test]
+    fn test_serve_framing_limits() {
+        let mut output: Vec<u8> = Vec::new();
+        let options = ToggleOptions::default();
+
+        // Oversized message and missing length end the session with an error
+        let oversized = format!("Content-Length: {}\r\n\r\n", MAX_RPC_MESSAGE_BYTES + 1);
+        assert!(serve(&mut oversized.as_bytes(), &mut output, &options).is_err());
+        assert!(
+            serve(
+                &mut "X-Other: 1\r\n\r\n{}".as_bytes(),
+                &mut output,
+                &options
+            )
+            .is_err()
+        );
+        let long_header = format!("Content-Type: {}\r\n", "x".repeat(MAX_RPC_HEADER_BYTES));
+        assert!(serve(&mut long_header.as_bytes(), &mut output, &options).is_err());
+
+        // Empty input is a clean end
+        assert!(serve(&mut "".as_bytes(), &mut output, &options).is_ok());
+        assert!(output.is_empty());
+    }
+}
@@ -10,26 +10,54 @@
 //! - **Single-line toggle**: Toggle basic comments (`//` or `#`) on one line
 //! - **Docstring toggle**: Toggle Rust doc comments (`///`) on one line
 //! - **Block comment toggle**: Add/remove block comment markers around line ranges (`/* */` or `"""`)
-//! - **Batch operations**: Toggle comments on multiple lines in one pass (max 128 lines)
+//! - **Batch operations**: Toggle comments on multiple lines in one operation (128 lines per pass)
+//! - **Notebook cells**: Toggle `# ` on a source line of a Jupyter (`.ipynb`) code cell
+//! - **Content lookup**: Find the line containing a literal text (`find_line_containing()`)
+//! - **Brace blocks**: Comment out the whole `{ ... }` block (function, struct) starting at a line
+//! - **Python suites**: Comment out a `def` / `class` line and its indented suite
 //!
 //! ## Supported Languages & Comment Types
 //!
 //! ### Double-Slash Comments (`//`)
-//! Rust, C, C++, C#, Java, JavaScript, TypeScript, Go, Swift
-//! - Extensions: `rs`, `c`, `cpp`, `cc`, `cxx`, `h`, `hpp`, `js`, `ts`, `java`, `go`, `swift`
+//! Rust, C, C++, C#, Java, JavaScript, TypeScript, Go, Swift, Kotlin, Scala, Dart, PHP, Zig, V, D
+//! - Extensions: `rs`, `c`, `cpp`, `cc`, `cxx`, `h`, `hpp`, `js`, `ts`, `java`, `go`, `swift`,
+//!   `cs`, `kt`, `kts`, `scala`, `dart`, `php`, `zig`, `v`, `d`, `jsonc`, `json5`
+//! - Plain `json` is refused with `JsonNoComments` (JSON has no comment syntax)
+//! - PHP: `# ` at column 0 is also recognised for removal (adding always uses `// `)
 //!
 //! ### Hash Comments (`#`)
-//! Python, Shell, Bash, TOML, YAML, Ruby, Perl, R
-//! - Extensions: `py`, `sh`, `bash`, `toml`, `yaml`, `yml`, `rb`, `pl`, `r`
+//! Python, Shell, Bash, TOML, YAML, Ruby, Perl, R, Make, config dotfiles
+//! - Extensions: `py`, `sh`, `bash`, `toml`, `yaml`, `yml`, `rb`, `pl`, `pm`, `r`, `mk`,
+//!   `conf` (also the extensionless `Makefile`, `makefile` and `GNUmakefile`,
+//!   and dotfiles: `.env`, `.env.*`, `.gitignore`, `.dockerignore`, ...)
+//! - Make: indent / unindent always use one tab (recipes must start with one)
+//! - YAML: `# ` goes after the leading spaces (`  # - item`) so list items stay
+//!   aligned; any extension can opt in with `ToggleOptions::preserve_indent`
 //!
 //! ### Block Comments (`/* */`)
-//! Rust, C, C++, C#, Java, JavaScript, TypeScript, Go, Swift
-//! - Supported for same languages as `//`
+//! Rust, C, C++, C#, Java, JavaScript, TypeScript, Go, Swift, Kotlin, Scala, Dart, PHP, V, D,
+//! JSONC, JSON5
+//! - Supported for same languages as `//`, except Zig (no block comments)
 //!
 //! ### Block Comments (`""" """`)
 //! Python (triple-quoted strings as docblocks)
 //! - Supported for `.py` files
 //!
+//! ### Block Comments (`=begin =end`)
+//! Ruby (embedded documentation)
+//! - Supported for `.rb` files; markers always at column 0, blocks do not nest
+//!
+//! ### POD Blocks (`=pod =cut`)
+//! Perl (`toggle_pod_block_bytewise()`, next to the `#` line comments)
+//! - Supported for `.pl` / `.pm` files; column 0 and no nesting, as for Ruby
+//!
+//! ### HTML Comments (`<!-- -->`)
+//! Markdown
+//! - Extensions: `md`, `markdown`
+//! - Single-line: `<!-- ` prefix at column 0 plus ` -->` before the line ending
+//!   (removal needs the prefix; a trailing ` -->` is dropped if present)
+//! - Block: `<!--` and `-->` on their own lines
+//!
 //! ### Rust Documentation (`///`)
 //! Rust doc comments (dedicated function)
 //! - Supported for `.rs` files
@@ -47,7 +75,11 @@
 //! Output: "println!(...);"
 //! ```
 //!
-//! Pattern detection: `{0+ spaces}{flag}{1 space}{content}`
+//! Pattern detection: `{0+ spaces}{flag}{1 space|end of line}{content}`; a bare `//`, `#`
+//! or `///` (blank doc line) also counts as commented and toggles to an empty line
+//!
+//! Removal is strict by default (exactly `{flag}{1 space}`, so toggling twice gives
+//! back the same bytes); `RemovalMode::Relaxed` also uncomments `//x` and `//   x`.
 //!
 //! ### Block Comment Toggle
 //! Automatically detects whether to add or remove markers:
@@ -75,32 +107,99 @@
 //! ```
 //!
 //! ### Batch Operations
-//! Toggle comments on multiple lines with single backup and file pass:
-//! - Max 128 lines per operation
+//! Toggle comments on multiple lines with single backup and single replace:
+//! - 128 lines per pass through the file; larger lists take several passes
+//! - Max 1,000,000 lines per operation
 //! - Input order doesn't matter (automatically sorted)
 //! - Duplicate lines handled automatically
 //! - More efficient than repeated single-line calls
+//! - Line numbers can also be streamed from a reader (e.g. stdin), applied in
+//!   chunks of 128 with one backup; original replaced only after all chunks
+//!
+//! ### In-Memory Buffer (no filesystem)
+//! `toggle_lines_in_buffer()` toggles lines of a byte slice into any writer
+//! with the same engine. Every file operation is compiled only with the
+//! `fs` cargo feature (on by default); without it the module builds for
+//! targets such as `wasm32-wasip1`. When copying this file into another
+//! project, declare an `fs` feature (enabled) or the file operations vanish.
+//!
+//! `process_toggle()` runs it on any `BufRead` / `Write` pair (sockets,
+//! compressed streams, cursors); the file operations are the layer around it
+//! that adds the backup, temp file and atomic replace.
+//!
+//! ### Comment State and Line Queries (read-only)
+//! `comment_states_in_range()` reports, for every line of a range in one
+//! pass, whether a toggle would remove the flag (`Commented`), add it
+//! (`Code`), or the line is `Blank`, for editor gutters.
+//!
+//! `inspect_line()` describes one line the same way: length, leading
+//! spaces / tabs, flag found, CRLF ending.
 //!
 //! ## Safety & Reliability Features
 //!
 //! ### Memory Safety
 //! - **No heap allocation during processing**: Fixed pre-allocated buffers only
 //! - **Bounded operations**: All loops have upper limits to prevent hangs
-//! - **Line length limits**: Rejects lines exceeding 1MB (MAX_LINE_LENGTH)
-//! - **Batch size limits**: Max 128 lines per batch operation (MAX_BATCH_LINES)
+//! - **No line length limit for toggles, indents and copies**: Lines stream through byte
+//!   by byte, so a long line (minified code, an embedded blob) is never an error there,
+//!   whether or not it is one of the edited lines; the few modes that build a line
+//!   are listed under Limitations
+//! - **Batch size limits**: 128 lines per pass (MAX_BATCH_LINES), 1,000,000 per operation (MAX_TOTAL_BATCH_LINES)
+//! - **Constant memory across calls**: No operation holds a line buffer; the
+//!   only heap use per file pass is its `BufReader` / `BufWriter`
+//!   (IO_BUFFER_SIZE each), freed on return, and the largest fixed stack
+//!   array is the held comment of file-wide style conversion
+//!   (MAX_CONVERTED_COMMENT_BYTES). Thousands of calls in one process
+//!   (`--serve`, a library session) run at the same ceiling as one, so there
+//!   is no buffer pool and no scratch-buffer parameter
 //!
 //! ### File Safety
 //! - **Atomic operations**: Original file only replaced on complete success
-//! - **Single backup**: Creates `backup_toggle_comment_{filename}` before modifications
-//! - **Temp files**: Uses process-ID in temp filename to avoid collisions
-//! - **Preserve file endings**: Maintains original line endings (LF, CRLF, or none)
+//! - **File size limit**: Files above `ToggleOptions::max_file_size` (default 512 MB) are refused
+//!   with `FileTooLarge` before any backup
+//! - **Read-only targets**: Detected before any backup (`ReadOnlyTarget`); `clear_readonly()` /
+//!   `restore_permissions()` allow an explicit override
+//! - **Special files**: FIFOs, device nodes and sockets are refused (`NotRegularFile`) instead
+//!   of blocking in a read
+//! - **Own artifacts**: Backups, sidecars and temp files this crate writes are refused as
+//!   targets (`OwnArtifact`) unless `ToggleOptions::allow_own_artifacts` is set
+//! - **Partial-write detection**: Temp files are size-checked (original ± edit) and, unless
+//!   `Durability::None`, fsynced before replacing; a mismatch returns `IncompleteWrite`
+//! - **Concurrent saves**: A file whose size, mtime or inode changed since its backup is not
+//!   overwritten (`ConcurrentModification`)
+//! - **Single backup**: Creates `backup_toggle_comment_{filename}` before modifications, with a
+//!   `.sha` checksum sidecar; `restore_backup()` refuses a backup that does not match it
+//! - **Session bundles**: Multi-file operations keep all originals in one
+//!   `.toggle_comment_backup_<ts>/` with a manifest (`BackupSession`, `undo_session()`)
+//! - **Limits**: `limits()` reports the compiled-in bounds (batch sizes, search
+//!   text, file size, loop bound) for wrappers to pre-validate requests
+//! - **Temp files**: Uses process-ID in temp filename to avoid collisions; files of
+//!   processes no longer running are listed by `find_orphan_temp_files()`
+//! - **Windows names**: Backup/temp names are sanitized (reserved device names like
+//!   `aux`, invalid characters); verbatim `\\?\` prefixes are stripped for display
+//! - **Preserve file endings**: Maintains original line endings (LF, CRLF, or none);
+//!   `ToggleOptions::eol_audit` reports the ending of each edited line before and after
+//! - **Protected regions**: Lines between `# toggle_comment: off` and `# toggle_comment: on`
+//!   (any supported flag) are refused with `ProtectedRegion`, sentinels included
+//!   (ranges are checked as a whole before any line is edited);
+//!   so are shell here-doc bodies for comment toggles of `.sh` / `.bash` files
 //!
 //! ### Error Handling
 //! - **All errors returned as `Result`**: No panics in production code
 //! - **Specific error types**: `ToggleCommentError` enum provides detailed failure reasons
 //! - **I/O operation tracking**: Errors specify which operation failed (open, read, write, etc.)
+//! - **Permission denied**: Reported as its own variant (`PermissionDenied(stage)`), not as I/O error
 //! - **Recoverable**: Failed operations leave backups intact; original file untouched
 //!
+//! ### API Stability
+//! - Public names are spelled out in full (`toggle_multiple_singleline_docstrings`); a
+//!   misspelled name that shipped stays for one release as a `#[deprecated]` wrapper
+//!   (DEPRECATED NAMES section), then is removed
+//! - `CommentFlag` is public (for `toggle_comment_in_line()`); its variants are part of
+//!   the API, its byte helpers are not
+//! - Line-flag operations also have uniform names, `toggle_{comment,docstring}_{line,lines,
+//!   lines_from_reader,range}` (CONSISTENT NAMES section), next to the descriptive ones
+//!
 //! ## Usage Examples
 //!
 //! ### Toggle Single Line
@@ -209,10 +308,16 @@
 //! ## Limitations & Edge Cases
 //!
 //! ### Limitations
-//! - **Max file line length**: 1,000,000 bytes per line (rejects longer lines)
-//! - **Max batch lines**: 128 lines per batch operation
+//! - **Line length, per mode**: comment toggles, indent, block, isolate,
+//!   duplicate and move stream lines of any length (only the whole scan is
+//!   bounded, by MAX_BYTE_ITERATIONS). Bounded are: join / split results,
+//!   MAX_LINE_LENGTH (64 KB, `LineTooLong`); the line a comment style
+//!   conversion rewrites, MAX_LINE_CONTENT_BYTES (1 KB, `LineTooLong`); the
+//!   content given to insert-line / remove-line and attribute toggles,
+//!   MAX_LINE_CONTENT_BYTES (`InvalidLineContent`)
+//! - **Max batch lines**: 128 lines per pass, 1,000,000 per batch operation
 //! - **Extension-based**: Comment type determined by file extension (case-insensitive)
-//! - **Simple pattern matching**: Only detects `{spaces}{flag}{space}` pattern
+//! - **Simple pattern matching**: Only detects `{spaces}{flag}{space|end of line}` pattern
 //! - **Line-based**: Does not modify content within lines, only toggle markers
 //!
 //! ### Supported Edge Cases
@@ -223,6 +328,9 @@
 //! - ✓ Mixed line endings: CRLF (`\r\n`) and LF (`\n`) both preserved
 //! - ✓ Last line of file: Can be toggled like any other line
 //! - ✓ Only whitespace + flag: `    // \n` → `\n` (removes all)
+//! - ✓ UTF-8 content: flags go at column 0 or after ASCII spaces, never
+//!   inside a multi-byte character (tabs and non-ASCII text are content);
+//!   invalid bytes pass through unchanged unless `ToggleOptions::utf8_strict`
 //!
 //! ### Not Supported (By Design)
 //! - ✗ Inline comments: `code // comment` on same line (flag must start line)
@@ -256,7 +364,13 @@
 //! - `LineNotFound { requested, file_lines }`: Target line beyond EOF
 //! - `IoError(operation)`: I/O failure during backup, read, write, etc.
 //! - `PathError`: Filesystem path manipulation failed
-//! - `LineTooLong { line_number, length }`: Line exceeds 1MB limit
+//! - `LineTooLong { line, length, max }`: Only from joins and splits
+//!   (MAX_LINE_LENGTH) and comment style conversions (MAX_LINE_CONTENT_BYTES);
+//!   every other operation, line copies included, streams lines of any length
+//! - `NoStyleConversion` / `NotConvertibleComment { line }`: Comment style
+//!   conversion on a file type with one style / a line it does not apply to
+//! - `InvalidUtf8 { line }`: Invalid UTF-8 with `ToggleOptions::utf8_strict`
+//! - `NestedBlockMarker { line }`: Ruby `=begin` / `=end` or Perl POD block would nest
 //! - `InconsistentBlockMarkers`: Only one block marker found (not both)
 //!
 //! ## Performance Characteristics
@@ -265,6 +379,7 @@
 //! |-----------|-----------------|-------|
 //! | Single-line toggle | O(n) | O(1) |
 //! | Batch toggle (128 lines) | O(n log m) | O(1) |
+//! | Batch toggle (k lines) | O(n × ⌈k/128⌉) | O(1) |
 //! | Block toggle | O(n) | O(1) |
 //!
 //! Where `n` = file lines, `m` = batch size ≤ 128
@@ -693,7 +808,7 @@ fn execute_unindent_range(file_path: &str, start_line: usize, end_line: usize) -
 //     // Use only the valid portion of array
 //     let line_slice = &lines[..count];
 
-//     match toggle_multiple_singline_docstrings(file_path, line_slice) {
+//     match toggle_multiple_singleline_docstrings(file_path, line_slice) {
 //         Ok(()) => {
 //             println!("Successfully toggled {} docstrings", count);
 //             0
@@ -1180,15 +1295,19 @@ Vigilance: We should help support users and developers and the people who depend
 
 */
 
+#![cfg_attr(not(feature = "fs"), allow(dead_code, unused_imports))]
+
+#[cfg(feature = "fs")]
 use std::fs::{File, OpenOptions};
-use std::io::{BufWriter, Read, Seek, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, Write};
 use std::path::{Path, PathBuf};
 
 /// Buffer size for file I/O operations - pre-allocated, stack-friendly size
-const IO_BUFFER_SIZE: usize = 8192;
+pub const IO_BUFFER_SIZE: usize = 8192;
 
-// // Maximum line length we'll process - safety bound
-// const MAX_LINE_LENGTH: usize = 1_000_000; // 64KB per line max
+/// Longest line a join or split may produce (other operations stream lines
+/// of any length)
+pub const MAX_LINE_LENGTH: usize = 64 * 1024;
 
 // ============================================================================
 // ERROR SECTION: ERROR HANDLING SYSTEM (start)
@@ -1212,8 +1331,127 @@ pub enum ToggleCommentError {
     /// I/O operation failed
     IoError(IoOperation),
 
+    /// The OS refused access (file unreadable, directory not writable, etc.)
+    /// at the given stage; kept apart from IoError so scripts can tell
+    /// "no permission" from "missing" or "broken"
+    PermissionDenied(IoOperation),
+
     /// Path conversion or manipulation error
     PathError,
+
+    /// Temp file size is outside the bounds expected from the edit
+    /// (e.g. a silently truncated write on a full disk); original untouched
+    IncompleteWrite,
+
+    /// Target file has the read-only attribute (checked before any backup)
+    ReadOnlyTarget,
+
+    /// File is larger than the configured limit (`ToggleOptions::max_file_size`)
+    FileTooLarge { size: u64, max: u64 },
+
+    /// More line numbers were supplied than the operation allows
+    TooManyLines { max: usize },
+
+    /// A line-number entry read from an input stream was not a valid integer
+    /// (`input_line` is the 1-indexed line of the input stream)
+    InvalidLineNumber { input_line: usize },
+
+    /// Two ranges of a multi-range toggle both contain `line`
+    OverlappingRanges { line: usize },
+
+    /// Plain `.json` was targeted: the format has no comment syntax
+    /// (`.jsonc` / `.json5` are supported instead)
+    JsonNoComments,
+
+    /// Notebook file is not a JSON object with a `cells` array
+    InvalidNotebook,
+
+    /// Notebook cell index is beyond the number of cells
+    CellNotFound { requested: usize, cells: usize },
+
+    /// Notebook cell exists but is not a code cell (markdown / raw)
+    NotCodeCell { cell: usize },
+
+    /// Target line is inside a `toggle_comment: off` ... `on` region
+    ProtectedRegion { line: usize },
+
+    /// Search text for a content lookup is empty or longer than `max` bytes
+    InvalidSearchText { len: usize, max: usize },
+
+    /// Fewer lines contain the search text than the requested occurrence
+    /// (`matches` is how many lines did contain it)
+    TextNotFound { occurrence: usize, matches: usize },
+
+    /// No complete block starts at the given line: for braces a declaration
+    /// without a body, unbalanced braces, or end of file first; for Python a
+    /// start line that is blank, a comment, or inside a string / bracket
+    BlockEndNotFound { start: usize },
+
+    /// Backup checksum sidecar (`.sha`) is missing, malformed, or does not
+    /// match the backup (stale or corrupted), or the backup was taken from
+    /// another file with the same name; nothing was restored
+    BackupIntegrity,
+
+    /// Stopped by `request_cancel()` (CLI: SIGINT / SIGTERM) or the
+    /// `set_deadline()` budget (`timed_out()`) before the original was
+    /// replaced; the temp file is removed, original untouched
+    Cancelled,
+
+    /// The original changed on disk (size, modification time or inode)
+    /// between the backup and the replace, e.g. an editor autosave; it was
+    /// not overwritten
+    ConcurrentModification,
+
+    /// The target is a FIFO, device node or socket, not a regular file
+    NotRegularFile,
+
+    /// The target is one of this crate's own backups, checksum sidecars or
+    /// temp files (see `is_own_artifact()`); editing it would back up the
+    /// backup. Allowed with `ToggleOptions::allow_own_artifacts`
+    OwnArtifact,
+
+    /// A Rust doc comment (`///`) or attribute operation on a file that is
+    /// not `.rs` (e.g. `.toml`, where `///` is not a comment at all). Allowed
+    /// with `ToggleOptions::allow_any_extension`
+    NotRustSource,
+
+    /// Line content to insert is longer than `max` bytes or holds a line
+    /// break (an attribute also must not be empty)
+    InvalidLineContent { len: usize, max: usize },
+
+    /// The line to remove does not hold the expected content (it was left in
+    /// place)
+    LineContentMismatch { line: usize },
+
+    /// A line to convert, or the line a join would produce, is `length`
+    /// bytes, over the operation's `max`
+    LineTooLong {
+        line: usize,
+        length: usize,
+        max: usize,
+    },
+
+    /// A split column is past the end of the line, inside its indentation or
+    /// comment flag, inside a UTF-8 character, or leaves one side empty
+    InvalidSplitColumn { line: usize, column: usize },
+
+    /// The file type does not have both a line and a block comment style
+    /// (`#` without block markers, Markdown's one comment form); file-wide
+    /// conversion also needs `/* */` (Python's `"""` are strings)
+    NoStyleConversion,
+
+    /// `ToggleOptions::utf8_strict`: the file is not valid UTF-8, first at
+    /// (zero-indexed) `line`; checked before any backup
+    InvalidUtf8 { line: usize },
+
+    /// The line is not one comment of the style to convert from, or its text
+    /// would not survive the other style (holds a marker of it, doc comment)
+    NotConvertibleComment { line: usize },
+
+    /// A block ADD with column-0 markers (Ruby `=begin` / `=end`, Perl
+    /// `=pod` / `=cut`; they do not nest) would enclose the marker line
+    /// `line`, or start inside the block `line` opens
+    NestedBlockMarker { line: usize },
 }
 
 /// Specific I/O operations that can fail
@@ -1237,6 +1475,12 @@ pub enum IoOperation {
     /// Flushing write buffer
     Flush,
 
+    /// Syncing written data to disk (fsync)
+    Sync,
+
+    /// Changing file permissions (read-only attribute)
+    Permissions,
+
     /// Replacing original file with modified version
     Replace,
 }
@@ -1257,28 +1501,464 @@ impl std::fmt::Display for ToggleCommentError {
                 )
             }
             ToggleCommentError::IoError(op) => write!(f, "IO error: {:?}", op),
+            ToggleCommentError::PermissionDenied(op) => {
+                write!(f, "Permission denied: {:?}", op)
+            }
             ToggleCommentError::PathError => write!(f, "Path error"),
+            ToggleCommentError::IncompleteWrite => {
+                write!(f, "Incomplete write (original unchanged)")
+            }
+            ToggleCommentError::ReadOnlyTarget => write!(f, "Target file is read-only"),
+            ToggleCommentError::FileTooLarge { size, max } => {
+                write!(f, "File too large ({} bytes, limit {})", size, max)
+            }
+            ToggleCommentError::TooManyLines { max } => {
+                write!(f, "Too many lines requested (max {})", max)
+            }
+            ToggleCommentError::InvalidLineNumber { input_line } => {
+                write!(f, "Invalid line number on input line {}", input_line)
+            }
+            ToggleCommentError::OverlappingRanges { line } => {
+                write!(f, "Ranges overlap at line {}", line)
+            }
+            ToggleCommentError::JsonNoComments => write!(
+                f,
+                "JSON does not support comments (use .jsonc or .json5 for JSON with comments)"
+            ),
+            ToggleCommentError::InvalidNotebook => {
+                write!(
+                    f,
+                    "Not a notebook (expected JSON object with a \"cells\" array)"
+                )
+            }
+            ToggleCommentError::CellNotFound { requested, cells } => {
+                write!(
+                    f,
+                    "Cell {} not found (notebook has {} cells)",
+                    requested, cells
+                )
+            }
+            ToggleCommentError::NotCodeCell { cell } => {
+                write!(f, "Cell {} is not a code cell", cell)
+            }
+            ToggleCommentError::ProtectedRegion { line } => {
+                write!(
+                    f,
+                    "Line {} is in a protected region (toggle_comment: off, or a shell here-doc)",
+                    line
+                )
+            }
+            ToggleCommentError::InvalidSearchText { len, max } => {
+                write!(f, "Search text must be 1 to {} bytes (got {})", max, len)
+            }
+            ToggleCommentError::TextNotFound {
+                occurrence,
+                matches,
+            } => {
+                write!(
+                    f,
+                    "Occurrence {} of search text not found ({} matching lines)",
+                    occurrence, matches
+                )
+            }
+            ToggleCommentError::BlockEndNotFound { start } => {
+                write!(f, "No complete block found from line {}", start)
+            }
+            ToggleCommentError::BackupIntegrity => {
+                write!(
+                    f,
+                    "Backup does not match its checksum or was taken from another file; not restored"
+                )
+            }
+            ToggleCommentError::NotRegularFile => {
+                write!(f, "Not a regular file (FIFO, device node or socket)")
+            }
+            ToggleCommentError::OwnArtifact => {
+                write!(f, "Target is a toggle_comment backup or temp file")
+            }
+            ToggleCommentError::NotRustSource => {
+                write!(f, "Rust doc comments (///) and attributes need a .rs file")
+            }
+            ToggleCommentError::InvalidLineContent { len, max } => {
+                write!(
+                    f,
+                    "Line content must be one line of at most {} bytes (got {} bytes)",
+                    max, len
+                )
+            }
+            ToggleCommentError::LineContentMismatch { line } => {
+                write!(
+                    f,
+                    "Line {} does not hold the expected content (not removed)",
+                    line
+                )
+            }
+            ToggleCommentError::LineTooLong { line, length, max } => {
+                write!(
+                    f,
+                    "Line {} is too long ({} bytes, limit {})",
+                    line, length, max
+                )
+            }
+            ToggleCommentError::InvalidSplitColumn { line, column } => {
+                write!(
+                    f,
+                    "Column {} of line {} is not a split point (text needed on both sides)",
+                    column, line
+                )
+            }
+            ToggleCommentError::NoStyleConversion => {
+                write!(
+                    f,
+                    "This file type does not have both line and block comments"
+                )
+            }
+            ToggleCommentError::NotConvertibleComment { line } => {
+                write!(
+                    f,
+                    "Line {} is not a comment that converts to the other style",
+                    line
+                )
+            }
+            ToggleCommentError::InvalidUtf8 { line } => {
+                write!(f, "Line {} is not valid UTF-8 (--utf8-strict)", line)
+            }
+            ToggleCommentError::NestedBlockMarker { line } => {
+                write!(
+                    f,
+                    "Line {} is a block marker line; these blocks do not nest",
+                    line
+                )
+            }
+            ToggleCommentError::Cancelled if timed_out() => {
+                write!(f, "Timed out (original unchanged)")
+            }
+            ToggleCommentError::Cancelled => write!(f, "Cancelled (original unchanged)"),
+            ToggleCommentError::ConcurrentModification => {
+                write!(
+                    f,
+                    "File changed on disk during the operation (not overwritten)"
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for ToggleCommentError {}
 
+impl ToggleCommentError {
+    /// Classify a failed std::io call at a given stage
+    ///
+    /// `PermissionDenied` keeps its own variant; everything else is `IoError`.
+    /// Only the error kind is inspected (no OS message is kept or shown).
+    pub(crate) fn from_io_error(error: &std::io::Error, operation: IoOperation) -> Self {
+        if is_cancel_error(error) {
+            ToggleCommentError::Cancelled
+        } else if is_concurrent_modification(error) {
+            ToggleCommentError::ConcurrentModification
+        } else if error.kind() == std::io::ErrorKind::PermissionDenied {
+            ToggleCommentError::PermissionDenied(operation)
+        } else {
+            ToggleCommentError::IoError(operation)
+        }
+    }
+}
+
+/// Exit code of the command-line binary for each kind of failure
+///
+/// The one registry of these numbers: the CLI exits with them, the C
+/// interface returns them (`TC_ERR_*`), and `--capabilities` and the usage
+/// text list them. Numbers are never reused or renumbered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum ExitCode {
+    Success = 0,
+    InvalidArgument = 1,
+    FileNotFound = 2,
+    NoExtension = 3,
+    Unsupported = 4,
+    NotFound = 5,
+    Io = 6,
+    Path = 7,
+    LineTooLong = 8,
+    TooManyLines = 9,
+    PermissionDenied = 10,
+    IncompleteWrite = 11,
+    ReadOnly = 12,
+    FileTooLarge = 13,
+    Notebook = 14,
+    ProtectedRegion = 15,
+    BackupIntegrity = 16,
+    ConcurrentModification = 17,
+    NotRegularFile = 18,
+    OwnArtifact = 19,
+    InvalidUtf8 = 20,
+    /// Panic caught at the C interface (the CLI never exits with it)
+    Internal = 99,
+    /// `Cancelled` after the deadline ran out (as timeout(1) reports it)
+    TimedOut = 124,
+    /// `Cancelled` otherwise (128 + SIGINT, as shells report it)
+    Cancelled = 130,
+}
+
+impl ExitCode {
+    /// Every code, in numeric order
+    pub const ALL: [ExitCode; 24] = [
+        ExitCode::Success,
+        ExitCode::InvalidArgument,
+        ExitCode::FileNotFound,
+        ExitCode::NoExtension,
+        ExitCode::Unsupported,
+        ExitCode::NotFound,
+        ExitCode::Io,
+        ExitCode::Path,
+        ExitCode::LineTooLong,
+        ExitCode::TooManyLines,
+        ExitCode::PermissionDenied,
+        ExitCode::IncompleteWrite,
+        ExitCode::ReadOnly,
+        ExitCode::FileTooLarge,
+        ExitCode::Notebook,
+        ExitCode::ProtectedRegion,
+        ExitCode::BackupIntegrity,
+        ExitCode::ConcurrentModification,
+        ExitCode::NotRegularFile,
+        ExitCode::OwnArtifact,
+        ExitCode::InvalidUtf8,
+        ExitCode::Internal,
+        ExitCode::TimedOut,
+        ExitCode::Cancelled,
+    ];
+
+    /// The number (process exit status, `TC_ERR_*` value)
+    pub const fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// Stable snake_case name (`not_found`)
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitCode::Success => "success",
+            ExitCode::InvalidArgument => "invalid_argument",
+            ExitCode::FileNotFound => "file_not_found",
+            ExitCode::NoExtension => "no_extension",
+            ExitCode::Unsupported => "unsupported",
+            ExitCode::NotFound => "not_found",
+            ExitCode::Io => "io",
+            ExitCode::Path => "path",
+            ExitCode::LineTooLong => "line_too_long",
+            ExitCode::TooManyLines => "too_many_lines",
+            ExitCode::PermissionDenied => "permission_denied",
+            ExitCode::IncompleteWrite => "incomplete_write",
+            ExitCode::ReadOnly => "read_only",
+            ExitCode::FileTooLarge => "file_too_large",
+            ExitCode::Notebook => "notebook",
+            ExitCode::ProtectedRegion => "protected_region",
+            ExitCode::BackupIntegrity => "backup_integrity",
+            ExitCode::ConcurrentModification => "concurrent_modification",
+            ExitCode::NotRegularFile => "not_regular_file",
+            ExitCode::OwnArtifact => "own_artifact",
+            ExitCode::InvalidUtf8 => "invalid_utf8",
+            ExitCode::Internal => "internal",
+            ExitCode::TimedOut => "timed_out",
+            ExitCode::Cancelled => "cancelled",
+        }
+    }
+
+    /// One-line meaning, as the usage text shows it
+    pub fn description(self) -> &'static str {
+        match self {
+            ExitCode::Success => "Success",
+            ExitCode::InvalidArgument => "Invalid arguments",
+            ExitCode::FileNotFound => "File not found",
+            ExitCode::NoExtension => "No extension",
+            ExitCode::Unsupported => "Unsupported extension (e.g. plain .json, or /// outside .rs)",
+            ExitCode::NotFound => "Line not found (or search text / block end)",
+            ExitCode::Io => "I/O error",
+            ExitCode::Path => "Path error",
+            ExitCode::LineTooLong => "Line too long (joins, splits and style conversions only)",
+            ExitCode::TooManyLines => "Too many lines",
+            ExitCode::PermissionDenied => "Permission denied",
+            ExitCode::IncompleteWrite => "Incomplete write (original unchanged)",
+            ExitCode::ReadOnly => "Target file is read-only (see --force-readonly)",
+            ExitCode::FileTooLarge => "File too large (see --max-file-size)",
+            ExitCode::Notebook => "Not a notebook, or cell is not a code cell",
+            ExitCode::ProtectedRegion => {
+                "Line is in a protected region (toggle_comment: off ... on, or a here-doc)"
+            }
+            ExitCode::BackupIntegrity => {
+                "Backup does not match its checksum or origin (--undo refused), or other changes (--verify-only)"
+            }
+            ExitCode::ConcurrentModification => {
+                "File changed on disk during the operation (not overwritten)"
+            }
+            ExitCode::NotRegularFile => "Not a regular file (FIFO, device node or socket)",
+            ExitCode::OwnArtifact => "Target is a toggle_comment backup or temp file (see --force)",
+            ExitCode::InvalidUtf8 => "File is not valid UTF-8 (--utf8-strict)",
+            ExitCode::Internal => "Internal error (C interface only)",
+            ExitCode::TimedOut => "Timed out (--timeout; original unchanged)",
+            ExitCode::Cancelled => {
+                "Cancelled by Ctrl-C / SIGTERM (original unchanged; press twice to force)"
+            }
+        }
+    }
+}
+
+impl ToggleCommentError {
+    /// Exit code for this error (`Cancelled` also after a deadline; the
+    /// CLI reports that one as `TimedOut`)
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            ToggleCommentError::FileNotFound => ExitCode::FileNotFound,
+            ToggleCommentError::NoExtension => ExitCode::NoExtension,
+            ToggleCommentError::LineNotFound { .. } => ExitCode::NotFound,
+            ToggleCommentError::IoError(_) => ExitCode::Io,
+            ToggleCommentError::PermissionDenied(_) => ExitCode::PermissionDenied,
+            ToggleCommentError::PathError => ExitCode::Path,
+            ToggleCommentError::IncompleteWrite => ExitCode::IncompleteWrite,
+            ToggleCommentError::ReadOnlyTarget => ExitCode::ReadOnly,
+            ToggleCommentError::FileTooLarge { .. } => ExitCode::FileTooLarge,
+            ToggleCommentError::TooManyLines { .. } => ExitCode::TooManyLines,
+            ToggleCommentError::InvalidLineNumber { .. } => ExitCode::InvalidArgument,
+            ToggleCommentError::OverlappingRanges { .. } => ExitCode::InvalidArgument,
+            ToggleCommentError::JsonNoComments => ExitCode::Unsupported,
+            ToggleCommentError::InvalidNotebook => ExitCode::Notebook,
+            ToggleCommentError::CellNotFound { .. } => ExitCode::NotFound,
+            ToggleCommentError::NotCodeCell { .. } => ExitCode::Notebook,
+            ToggleCommentError::ProtectedRegion { .. } => ExitCode::ProtectedRegion,
+            ToggleCommentError::InvalidSearchText { .. } => ExitCode::InvalidArgument,
+            ToggleCommentError::TextNotFound { .. } => ExitCode::NotFound,
+            ToggleCommentError::BlockEndNotFound { .. } => ExitCode::NotFound,
+            ToggleCommentError::BackupIntegrity => ExitCode::BackupIntegrity,
+            ToggleCommentError::ConcurrentModification => ExitCode::ConcurrentModification,
+            ToggleCommentError::NotRegularFile => ExitCode::NotRegularFile,
+            ToggleCommentError::OwnArtifact => ExitCode::OwnArtifact,
+            ToggleCommentError::InvalidUtf8 { .. } => ExitCode::InvalidUtf8,
+            ToggleCommentError::NotRustSource => ExitCode::Unsupported,
+            ToggleCommentError::InvalidLineContent { .. } => ExitCode::InvalidArgument,
+            ToggleCommentError::LineContentMismatch { .. } => ExitCode::NotFound,
+            ToggleCommentError::LineTooLong { .. } => ExitCode::LineTooLong,
+            ToggleCommentError::InvalidSplitColumn { .. } => ExitCode::NotFound,
+            ToggleCommentError::NoStyleConversion => ExitCode::Unsupported,
+            ToggleCommentError::NotConvertibleComment { .. } => ExitCode::NotFound,
+            ToggleCommentError::NestedBlockMarker { .. } => ExitCode::InvalidArgument,
+            ToggleCommentError::Cancelled => ExitCode::Cancelled,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod exit_code_tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code_registry() {
+        // Numeric order, no number or name twice
+        for pair in ExitCode::ALL.windows(2) {
+            assert!(pair[0].code() < pair[1].code(), "{:?}", pair);
+        }
+        for code in ExitCode::ALL {
+            let same_name = ExitCode::ALL.iter().filter(|c| c.name() == code.name());
+            assert_eq!(same_name.count(), 1, "{:?}", code);
+        }
+
+        // Both error types map shared variants to the same code
+        assert_eq!(
+            ToggleCommentError::InvalidUtf8 { line: 3 }.exit_code(),
+            ToggleIndentError::InvalidUtf8 { line: 3 }.exit_code()
+        );
+        assert_eq!(
+            ToggleIndentError::PermissionDenied(IoOperation::Open)
+                .exit_code()
+                .code(),
+            10
+        );
+        assert_eq!(ToggleCommentError::Cancelled.exit_code().code(), 130);
+    }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod permission_error_tests {
+    use super::*;
+
+    #[test]
+    fn test_from_io_error_classification() {
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let other = std::io::Error::from(std::io::ErrorKind::Other);
+
+        assert_eq!(
+            ToggleCommentError::from_io_error(&denied, IoOperation::Backup),
+            ToggleCommentError::PermissionDenied(IoOperation::Backup)
+        );
+        assert_eq!(
+            ToggleCommentError::from_io_error(&other, IoOperation::Replace),
+            ToggleCommentError::IoError(IoOperation::Replace)
+        );
+        assert_eq!(
+            ToggleIndentError::from_io_error(&denied, IoOperation::Open),
+            ToggleIndentError::PermissionDenied(IoOperation::Open)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_file_is_permission_denied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let test_file = PathBuf::from("test_permission_unreadable.py");
+        std::fs::write(&test_file, "x = 1\n").unwrap();
+        std::fs::set_permissions(&test_file, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Privileged users (e.g. root in CI containers) bypass file modes
+        if File::open(&test_file).is_err() {
+            let result =
+                toggle_basic_singleline_comment_bytewise("test_permission_unreadable.py", 0);
+            assert_eq!(
+                result,
+                Err(ToggleCommentError::PermissionDenied(IoOperation::Open))
+            );
+
+            let indent_result = indent_line_bytewise("test_permission_unreadable.py", 0);
+            assert!(matches!(
+                indent_result,
+                Err(ToggleIndentError::PermissionDenied(_))
+            ));
+        }
+
+        let _ = std::fs::set_permissions(&test_file, std::fs::Permissions::from_mode(0o644));
+        let _ = std::fs::remove_file(&test_file);
+        let _ = std::fs::remove_file("backup_toggle_comment_test_permission_unreadable.py");
+    }
+}
+
 // ============================================================================
 // ERROR SECTION: ERROR HANDLING SYSTEM (end)
 // ============================================================================
 
 /// Comment flag type for different language syntaxes
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum CommentFlag {
-    /// Tripple Slash for Rust-Docstrings
-    TripppleSlash,
+pub enum CommentFlag {
+    /// Triple slash for Rust docstrings
+    TripleSlash,
 
     /// Double-slash comments (Rust, C, C++, JavaScript, etc.)
     DoubleSlash,
 
     /// Hash/pound comments (Python, Shell, TOML, etc.)
     Hash,
+
+    /// HTML comments for Markdown: `<!-- ` prefix AND ` -->` suffix
+    HtmlComment,
+
+    /// C89 comments on one line: `/* ` prefix AND ` */` suffix
+    /// (`.c` / `.h` with `LineStyle::C89`)
+    SlashStar,
+
+    /// Doxygen member documentation (`///<`, `DocStyle::Doxygen`)
+    DoxygenMember,
+
+    /// Qt-style Doxygen member documentation (`//!<`, `DocStyle::Qt`)
+    QtMember,
 }
 
 // impl CommentFlag {
@@ -1301,6 +1981,71 @@ enum CommentFlag {
 //     }
 // }
 
+impl CommentFlag {
+    /// Parse a flag as written (`"///"`, `"//"`, `"#"`, `"<!--"`, `"/*"`,
+    /// `"///<"`, `"//!<"`)
+    ///
+    /// # Returns
+    /// * `None` - Not one of the supported flags
+    pub fn from_flag(flag: &str) -> Option<CommentFlag> {
+        match flag {
+            "///" => Some(CommentFlag::TripleSlash),
+            "//" => Some(CommentFlag::DoubleSlash),
+            "#" => Some(CommentFlag::Hash),
+            "<!--" => Some(CommentFlag::HtmlComment),
+            "/*" => Some(CommentFlag::SlashStar),
+            "///<" => Some(CommentFlag::DoxygenMember),
+            "//!<" => Some(CommentFlag::QtMember),
+            _ => None,
+        }
+    }
+
+    /// Get the byte slice representation of the comment flag
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            CommentFlag::TripleSlash => b"///",
+            CommentFlag::DoubleSlash => b"//",
+            CommentFlag::Hash => b"#",
+            CommentFlag::HtmlComment => b"<!--",
+            CommentFlag::SlashStar => b"/*",
+            CommentFlag::DoxygenMember => b"///<",
+            CommentFlag::QtMember => b"//!<",
+        }
+    }
+
+    /// Closing part written as ` {suffix}` before the line ending
+    /// (empty for flags that only have a prefix)
+    fn suffix_bytes(&self) -> &'static [u8] {
+        match self {
+            CommentFlag::HtmlComment => b"-->",
+            CommentFlag::SlashStar => b"*/",
+            _ => b"",
+        }
+    }
+
+    /// Whether the flag followed directly by the end of the line (`///`, a
+    /// blank doc line; `//` or `#` left by an editor that strips trailing
+    /// spaces) counts as commented; removing it leaves an empty line.
+    /// Flags with a suffix always need their space.
+    fn removable_at_eol(&self) -> bool {
+        self.suffix_bytes().is_empty()
+    }
+
+    /// Get the string representation of the comment flag
+    /// (prefix and suffix for flags that have both)
+    fn as_str(&self) -> &'static str {
+        match self {
+            CommentFlag::TripleSlash => "///",
+            CommentFlag::DoubleSlash => "//",
+            CommentFlag::Hash => "#",
+            CommentFlag::HtmlComment => "<!-- -->",
+            CommentFlag::SlashStar => "/* */",
+            CommentFlag::DoxygenMember => "///<",
+            CommentFlag::QtMember => "//!<",
+        }
+    }
+}
+
 /// Determine comment flag based on file extension
 ///
 /// # Arguments
@@ -1311,3055 +2056,20691 @@ enum CommentFlag {
 /// * `None` if extension is not recognized
 ///
 /// # Supported Extensions
-/// - `//` : rs, c, cpp, cc, cxx, h, hpp, js, ts, java, go, swift
-/// - `#`  : py, sh, bash, toml, yaml, yml, rb, pl, r
+/// - `//` : rs, c, cpp, cc, cxx, h, hpp, js, ts, java, go, swift,
+///   cs, kt, kts, scala, dart, php, zig, v, d, jsonc, json5
+/// - `<!-- ... -->` : md, markdown
+/// - `#`  : py, sh, bash, toml, yaml, yml, rb, pl, pm, r, mk, conf
+///   (`Makefile` resolves to `mk`, `.env` / `.gitignore` and other
+///   dotfiles to `conf`: FILE_NAME_EXTENSIONS)
 fn determine_comment_flag(extension: &str) -> Option<CommentFlag> {
     match extension.to_lowercase().as_str() {
         // Double-slash languages
-        "rs" | "c" | "cpp" | "cc" | "cxx" | "h" | "hpp" | "js" | "ts" | "java" | "go" | "swift" => {
-            Some(CommentFlag::DoubleSlash)
-        }
+        "rs" | "c" | "cpp" | "cc" | "cxx" | "h" | "hpp" | "js" | "ts" | "java" | "go" | "swift"
+        | "cs" | "kt" | "kts" | "scala" | "dart" | "php" | "zig" | "v" | "d" | "jsonc"
+        | "json5" => Some(CommentFlag::DoubleSlash),
+
+        // HTML comment in Markdown
+        "md" | "markdown" => Some(CommentFlag::HtmlComment),
 
         // Hash languages
-        "py" | "sh" | "bash" | "toml" | "yaml" | "yml" | "rb" | "pl" | "r" => {
-            Some(CommentFlag::Hash)
-        }
+        "py" | "sh" | "bash" | "toml" | "yaml" | "yml" | "rb" | "pl" | "pm" | "r" | "mk"
+        | "conf" => Some(CommentFlag::Hash),
 
         // Unknown extension
         _ => None,
     }
 }
 
-// ================
-// Block Party Mode
-// ================
-
-/// Block comment markers for different languages
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct BlockMarkers {
-    start: &'static [u8],
-    end: &'static [u8],
+/// Determine the flag line toggles add for an extension under the options
+///
+/// # Overview
+/// `determine_comment_flag()`, except where `options.line_style` prefers
+/// another flag for the extension:
+///
+/// ```text
+/// style     extensions   flag
+/// C89       c, h         /* */   (K&R / C89 code forbids //)
+/// ```
+///
+/// `options.doc_style` comes first: a `//` extension gets its member
+/// marker (`///<`, `//!<`) where the style has one.
+///
+/// Only the flag that is added and recognised changes; a `// x` line in a
+/// C89 file no longer counts as commented.
+fn determine_line_flag(extension: &str, options: &ToggleOptions) -> Option<CommentFlag> {
+    let flag = determine_comment_flag(extension)?;
+    if flag == CommentFlag::DoubleSlash
+        && let Some(doc_flag) = options.doc_style.and_then(|style| style.line_flag())
+    {
+        return Some(doc_flag);
+    }
+    match options.line_style {
+        LineStyle::C89 if matches!(extension.to_lowercase().as_str(), "c" | "h") => {
+            Some(CommentFlag::SlashStar)
+        }
+        _ => Some(flag),
+    }
 }
 
-/// Determine block comment markers from file extension
+/// Determine a second flag that is removed, but never added, for an extension
 ///
-/// # Arguments
-/// * `extension` - File extension without dot
+/// # Overview
+/// Some languages have two single-line comment flags. Toggling always ADDS
+/// the primary flag (from `determine_comment_flag()`), but a line that
+/// already starts with the alternative `{flag}{1 space}` at column 0 is
+/// treated as commented and the alternative is REMOVED.
 ///
 /// # Returns
-/// * `Some(BlockMarkers)` - Start and end markers for this language
-/// * `None` - Extension not supported for block comments
-fn determine_block_markers(extension: &str) -> Option<BlockMarkers> {
+/// * `Some(CommentFlag)` - Alternative flag accepted for removal
+/// * `None` - Extension has only one flag
+///
+/// # Supported Extensions
+/// - `php` : `#` (primary `//`)
+fn determine_removal_only_flag(extension: &str) -> Option<CommentFlag> {
     match extension.to_lowercase().as_str() {
-        // C-style block comments: /* */
-        "rs" | "c" | "cpp" | "cc" | "cxx" | "h" | "hpp" | "js" | "ts" | "java" | "go" | "swift" => {
-            Some(BlockMarkers {
-                start: b"/*\n",
-                end: b"*/\n",
-            })
-        }
-
-        // Python triple-quote: """ """
-        "py" => Some(BlockMarkers {
-            start: b"\"\"\"\n",
-            end: b"\"\"\"\n",
-        }),
-
-        // Shell/TOML/YAML don't have block comments
+        "php" => Some(CommentFlag::Hash),
         _ => None,
     }
 }
 
-/// Mode for block comment operation
+/// Check for plain JSON, which has no comment syntax at all
+///
+/// # Overview
+/// Unlike other unsupported extensions (silent no-op), `.json` is refused
+/// with `JsonNoComments`: adding `//` would produce a file most JSON parsers
+/// reject. `.jsonc` and `.json5` are in the `//` family.
+fn is_json_without_comments(extension: &str) -> bool {
+    extension.eq_ignore_ascii_case("json")
+}
+
+/// Check for extensions where single-line toggles always preserve indentation
+///
+/// # Overview
+/// YAML structure is carried by indentation, so `# ` goes after the leading
+/// spaces (`  # - item`), keeping the list item aligned with its siblings.
+/// Other extensions get this only with `ToggleOptions::preserve_indent`.
+fn preserves_indent_by_default(extension: &str) -> bool {
+    matches!(extension.to_lowercase().as_str(), "yaml" | "yml")
+}
+
+/// Check for extensions whose indent step is one tab, whatever the width
+///
+/// # Overview
+/// Make recipe lines must start with a tab (spaces are a "missing
+/// separator" error), so indent adds `\t` and unindent removes one leading
+/// `\t` in Makefiles; `ToggleOptions::indent_width` does not apply.
+fn indents_with_tab(extension: &str) -> bool {
+    extension.eq_ignore_ascii_case("mk")
+}
+
+/// File names that resolve to an extension whatever their own (`Makefile`
+/// has none, `.env.local` has `local`), checked before the extension by
+/// `target_extension()`; a trailing `*` matches any rest of the name
+const FILE_NAME_EXTENSIONS: [(&str, &str); 10] = [
+    ("Makefile", "mk"),
+    ("makefile", "mk"),
+    ("GNUmakefile", "mk"),
+    (".env", "conf"),
+    (".env.*", "conf"),
+    (".gitignore", "conf"),
+    (".gitattributes", "conf"),
+    (".dockerignore", "conf"),
+    (".npmignore", "conf"),
+    (".editorconfig", "conf"),
+];
+
+/// Extension FILE_NAME_EXTENSIONS gives a file name
+fn file_name_extension(name: &str) -> Option<&'static str> {
+    FILE_NAME_EXTENSIONS
+        .iter()
+        .find(|(pattern, _)| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == *pattern,
+        })
+        .map(|(_, extension)| *extension)
+}
+
+/// Every extension `determine_comment_flag()` knows (lowercase; matching
+/// ignores case), in its `//`, `<!-- -->`, `#` order
+pub const SUPPORTED_EXTENSIONS: [&str; 37] = [
+    "rs", "c", "cpp", "cc", "cxx", "h", "hpp", "js", "ts", "java", "go", "swift", "cs", "kt",
+    "kts", "scala", "dart", "php", "zig", "v", "d", "jsonc", "json5", "md", "markdown", "py", "sh",
+    "bash", "toml", "yaml", "yml", "rb", "pl", "pm", "r", "mk", "conf",
+];
+
+/// Comment syntax the operations use for one extension (`extension_syntax()`)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum BlockMode {
-    /// Add block markers (markers not present)
-    Add,
-    /// Remove block markers (markers present)
-    Remove,
+pub struct ExtensionSyntax {
+    /// Flag line toggles add (`//`, `#`, `<!--`)
+    pub line_flag: &'static str,
+
+    /// Closing part after the line (`-->`), empty for prefix-only flags
+    pub line_suffix: &'static str,
+
+    /// Second flag that is removed but never added (PHP `#`)
+    pub removal_only_flag: Option<&'static str>,
+
+    /// Block markers (start, end); None: no block comments (e.g. Zig, shell)
+    pub block: Option<(&'static str, &'static str)>,
+
+    /// Line toggles keep the indentation without `--preserve-indent`
+    pub preserves_indent: bool,
+
+    /// Indent / unindent step is one tab, not `indent_width` spaces (Make)
+    pub indent_tab: bool,
+}
+
+/// Comment syntax for an extension, as the operations resolve it
+///
+/// # Returns
+/// * `Some(ExtensionSyntax)` - One of SUPPORTED_EXTENSIONS (any case)
+/// * `None` - Unknown extension, or plain `json` (no comment syntax)
+pub fn extension_syntax(extension: &str) -> Option<ExtensionSyntax> {
+    let flag = determine_comment_flag(extension)?;
+    let text = |bytes: &'static [u8]| std::str::from_utf8(bytes).unwrap_or_default();
+    Some(ExtensionSyntax {
+        line_flag: text(flag.as_bytes()),
+        line_suffix: text(flag.suffix_bytes()),
+        removal_only_flag: determine_removal_only_flag(extension).map(|f| text(f.as_bytes())),
+        block: determine_block_markers(extension).map(|m| (text(m.start), text(m.end))),
+        preserves_indent: preserves_indent_by_default(extension),
+        indent_tab: indents_with_tab(extension),
+    })
 }
 
 // ============================================================================
-// BYTE-WISE TOGGLE OPERATIONS (IMPLEMENTATION)
+// OPERATION OPTIONS
 // ============================================================================
 //
-// ## Architecture
-// This module implements comment toggling using single-byte operations
-// with no heap allocation and no line-loading into memory.
-//
-// Three core operations:
-// 1. find_and_detect_tag_state() - Combined find+detect in one pass
-// 2. write_toggled_file_bytewise() - Three-part byte-by-byte copy with toggle
-// 3. toggle_basic_singleline_comment() - Refactored wrapper using bytewise ops
-//
-// ## Memory Usage
-// - Stack only: single byte buffer [u8; 1]
-// - No Vec, no String, no heap allocations during processing
-// - Total working memory: 1 byte
-//
-// ## Safety
-// - All loops bounded by MAX_BYTE_ITERATIONS
-// - Stops at newline during tag detection
-// - Preserves CRLF vs LF line endings
-// - Returns Ok(None) for line not found (not an error)
+// Every public operation has two entry points:
+// - `name(...)`              - default options (unchanged behaviour)
+// - `name_with_options(...)` - same operation, caller-provided `ToggleOptions`
 //
+// Options are a small Copy struct passed by reference: no global state, each
+// call says exactly what it wants. Checks driven by options run right after
+// the path is resolved, before any backup or temp file is made.
 // ============================================================================
 
-/// Maximum bytes to read before safety abort
-/// Allows ~1GB files while preventing infinite loops
-const MAX_BYTE_ITERATIONS: u64 = 1_000_000_000;
+/// Default upper bound on the size of a file an operation will process
+pub const DEFAULT_MAX_FILE_SIZE_BYTES: u64 = 512 * 1024 * 1024;
 
-/// Combined operation: Find line start position AND detect tag state
+/// How much a single-line uncomment removes
 ///
-/// # Overview
-/// Performs find+detect in a single file pass. After finding target line,
-/// checks COLUMN 0 for comment tag pattern. No indentation handling.
+/// # Reversibility
+/// Adding always writes `{flag}{1 space}`. `Strict` removes exactly that, so
+/// any line toggled twice is byte-identical to the original. `Relaxed` also
+/// cleans up hand-written comments, at the cost of that guarantee:
 ///
-/// # Detection Rule (SIMPLE)
-/// Read first bytes of line starting at column 0:
-/// - "// " → HAS_TAG
-/// - "# "  → HAS_TAG
-/// - "/// " → HAS_TAG
-/// - Anything else → NO_TAG
+/// ```text
+/// line        Strict         Relaxed
+/// "// x"      "x"            "x"
+/// "//  x"     " x"           "x"           (toggled back: "// x")
+/// "//x"       "// //x" (add) "x"           (toggled back: "// x")
+/// "//"        ""             ""            (toggled back: "// ")
+/// ```
 ///
-/// No space skipping. No indentation detection. Column 0 only.
-fn find_and_detect_tag_state(
-    file_path: &str,
-    target_line: usize,
-    comment_flag: CommentFlag,
-) -> Result<Option<(u64, bool)>, ToggleCommentError> {
-    // Open file for reading
-    let mut file = match File::open(file_path) {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Open)),
-    };
+/// A flag at the end of the line (`removable_at_eol()`) is removed in both
+/// modes; toggled back it gets the usual space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalMode {
+    /// Only `{flag}{1 space}` counts as commented and is removed (default)
+    Strict,
 
-    let mut byte_bucket: [u8; 1] = [0u8; 1];
-    let mut byte_position: u64 = 0;
-    let mut current_line: usize = 0;
-    let line_start_pos: u64;
+    /// A line starting with the flag counts as commented; the flag and up to
+    /// MAX_RELAXED_SPACES following spaces are removed. Not round-trip safe.
+    Relaxed,
+}
 
-    // ===========================================
-    // PHASE 1: Find target line start position
-    // ===========================================
+/// Spaces after the flag that `RemovalMode::Relaxed` removes at most
+/// (longer runs are kept beyond this, as likely alignment)
+pub const MAX_RELAXED_SPACES: usize = 8;
 
-    if target_line == 0 {
-        line_start_pos = 0;
-    } else {
-        loop {
-            if byte_position >= MAX_BYTE_ITERATIONS {
-                return Err(ToggleCommentError::IoError(IoOperation::Read));
-            }
+/// Line endings written by the line engine while it rewrites a file
+///
+/// `Lf` / `Crlf` convert every line ending of the file (not just the toggled
+/// lines) in the same pass as the toggle. A lone `\r` is not a line ending
+/// and is copied as-is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EolMode {
+    /// Copy line endings unchanged (default)
+    Keep,
 
-            let bytes_read = match file.read(&mut byte_bucket) {
-                Ok(n) => n,
-                Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-            };
+    /// Write every line ending as `\n`
+    Lf,
 
-            if bytes_read == 0 {
-                return Ok(None); // Line not found
-            }
+    /// Write every line ending as `\r\n`
+    Crlf,
+}
 
-            if byte_bucket[0] == b'\n' {
-                current_line += 1;
-                if current_line == target_line {
-                    line_start_pos = byte_position + 1;
-                    break;
-                }
-            }
+/// Trailing spaces/tabs held back by `TrailingWhitespaceTrimmer` at most
+/// (a longer run keeps its first bytes and is only trimmed by this many)
+const MAX_TRIMMED_WHITESPACE_BYTES: usize = 256;
 
-            byte_position += 1;
-        }
-    }
+/// What the line engine does with the end of the file while it rewrites it
+///
+/// Independent of the toggle itself: the toggled lines are the same either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinalNewline {
+    /// Leave the end of the file as it is (default)
+    Keep,
 
-    // ===========================================
-    // PHASE 2: Detect tag at column 0 ONLY
-    // ===========================================
+    /// Append a line ending if the (non-empty) file does not end with one
+    Ensure,
 
-    // Seek to line start
-    if let Err(_) = file.seek(std::io::SeekFrom::Start(line_start_pos)) {
-        return Err(ToggleCommentError::IoError(IoOperation::Read));
-    }
+    /// Remove the file's last line ending, if it ends with one
+    Strip,
+}
 
-    // Read first byte at column 0
-    let bytes_read = match file.read(&mut byte_bucket) {
-        Ok(n) => n,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-    };
+/// How far a finished temp file is pushed to disk before it replaces the
+/// original (`replace_original()`)
+///
+/// Intermediate passes are never synced, only the file that is swapped in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// No fsync: the temp is only flushed to the OS. Fastest; a crash soon
+    /// after can lose the edit or, on some filesystems, leave the target empty
+    None,
 
-    // EOF or empty line
-    if bytes_read == 0 || byte_bucket[0] == b'\n' {
-        return Ok(Some((line_start_pos, false)));
+    /// fsync the temp file before the replace (default)
+    Flush,
+
+    /// Also fsync the result of the replace: the target after a copy in
+    /// place, or its directory after the rename (Unix), so the new name
+    /// survives a crash too
+    Fsync,
+}
+
+/// Per-extension preference for the flag line toggles use
+/// (`determine_line_flag()`)
+///
+/// Block, notebook and doc comment modes are not affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineStyle {
+    /// Each extension's usual flag (default)
+    Default,
+
+    /// `.c` / `.h`: wrap each line as `/* line */` instead of `// line`.
+    /// A line that already holds a `*/` would end the comment early, so
+    /// such lines should be block-toggled instead
+    C89,
+}
+
+/// Documentation marker set for `/* */` languages (C, C++, JS, ...)
+///
+/// ```text
+/// style     line toggles   block toggles
+/// Doxygen   ///<           /** ... */
+/// Qt        //!<           /*! ... */
+/// JsDoc     // (unchanged) /** ... */
+/// ```
+///
+/// Line markers apply to `//` extensions only, block markers to `/* */`
+/// ones; other extensions keep their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocStyle {
+    /// Javadoc-style Doxygen
+    Doxygen,
+
+    /// Qt-style Doxygen
+    Qt,
+
+    /// JSDoc / TSDoc
+    JsDoc,
+}
+
+impl DocStyle {
+    /// Parse a style name (`doxygen`, `qt`, `jsdoc`)
+    pub fn from_name(name: &str) -> Option<DocStyle> {
+        match name {
+            "doxygen" => Some(DocStyle::Doxygen),
+            "qt" => Some(DocStyle::Qt),
+            "jsdoc" => Some(DocStyle::JsDoc),
+            _ => None,
+        }
     }
 
-    // Check if first byte matches start of tag
-    let has_tag = match comment_flag {
-        CommentFlag::Hash => {
-            // Pattern: "# " at column 0
-            if byte_bucket[0] != b'#' {
-                false
-            } else {
-                // Read next byte - should be space
-                match file.read(&mut byte_bucket) {
-                    Ok(0) => false,
-                    Ok(_) => byte_bucket[0] == b' ',
-                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-                }
-            }
+    /// Flag line toggles add instead of `//` (`None`: keep `//`)
+    fn line_flag(&self) -> Option<CommentFlag> {
+        match self {
+            DocStyle::Doxygen => Some(CommentFlag::DoxygenMember),
+            DocStyle::Qt => Some(CommentFlag::QtMember),
+            DocStyle::JsDoc => None,
         }
+    }
 
-        CommentFlag::DoubleSlash => {
-            // Pattern: "// " at column 0
-            if byte_bucket[0] != b'/' {
-                false
-            } else {
-                // Read second '/'
-                match file.read(&mut byte_bucket) {
-                    Ok(0) => false,
-                    Ok(_) => {
-                        if byte_bucket[0] != b'/' {
-                            false
-                        } else {
-                            // Read space
-                            match file.read(&mut byte_bucket) {
-                                Ok(0) => false,
-                                Ok(_) => byte_bucket[0] == b' ',
-                                Err(_) => {
-                                    return Err(ToggleCommentError::IoError(IoOperation::Read));
-                                }
-                            }
-                        }
-                    }
-                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-                }
-            }
+    /// Markers block toggles write instead of `/*` `*/`
+    fn block_markers(&self) -> BlockMarkers {
+        match self {
+            DocStyle::Doxygen | DocStyle::JsDoc => BlockMarkers {
+                start: b"/**",
+                end: b"*/",
+            },
+            DocStyle::Qt => BlockMarkers {
+                start: b"/*!",
+                end: b"*/",
+            },
         }
+    }
+}
 
-        CommentFlag::TripppleSlash => {
-            // Pattern: "/// " at column 0
-            if byte_bucket[0] != b'/' {
-                false
-            } else {
-                // Read second '/'
-                match file.read(&mut byte_bucket) {
-                    Ok(0) => false,
-                    Ok(_) => {
-                        if byte_bucket[0] != b'/' {
-                            false
-                        } else {
-                            // Read third '/'
-                            match file.read(&mut byte_bucket) {
-                                Ok(0) => false,
-                                Ok(_) => {
-                                    if byte_bucket[0] != b'/' {
-                                        false
-                                    } else {
-                                        // Read space
-                                        match file.read(&mut byte_bucket) {
-                                            Ok(0) => false,
-                                            Ok(_) => byte_bucket[0] == b' ',
-                                            Err(_) => {
-                                                return Err(ToggleCommentError::IoError(
-                                                    IoOperation::Read,
-                                                ));
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(_) => {
-                                    return Err(ToggleCommentError::IoError(IoOperation::Read));
-                                }
-                            }
-                        }
-                    }
-                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-                }
-            }
+/// Caller-adjustable settings shared by all operations
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::{ToggleOptions, indent_line_bytewise_with_options};
+///
+/// // Allow a 2 GB file
+/// let options = ToggleOptions {
+///     max_file_size: Some(2 * 1024 * 1024 * 1024),
+///     ..ToggleOptions::default()
+/// };
+/// indent_line_bytewise_with_options("./big_generated.rs", 10, &options)?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToggleOptions {
+    /// Largest file (bytes) to process; `None` disables the check
+    /// Default: `Some(DEFAULT_MAX_FILE_SIZE_BYTES)` (512 MB)
+    pub max_file_size: Option<u64>,
+
+    /// Single-line toggles put the flag after the line's leading spaces
+    /// (`  - a` <-> `  # - a`) instead of at column 0. Any indent depth
+    /// works (no scan window; generated JSX often has 100+ spaces).
+    /// Always on for `.yaml` / `.yml`. Block toggles indent the marker
+    /// lines they add like the first selected line. Default: `false`
+    pub preserve_indent: bool,
+
+    /// Single-line and batch toggles: what counts as commented and what an
+    /// uncomment removes (block / notebook / suite modes stay strict).
+    /// Default: `RemovalMode::Strict`
+    pub removal: RemovalMode,
+
+    /// Report each pipeline stage (backup, temp write, verify, replace) with
+    /// its outcome and duration on stderr. Default: `TraceFormat::Off`
+    pub trace: TraceFormat,
+
+    /// Line comment toggles (single, range, batch, suite) also convert the
+    /// file's line endings; indent, block and notebook modes keep them.
+    /// Default: `EolMode::Keep`
+    pub eol: EolMode,
+
+    /// Line comment toggles also add or remove the final newline (same
+    /// modes as `eol`). Default: `FinalNewline::Keep`
+    pub final_newline: FinalNewline,
+
+    /// Line comment toggles and unindent strip trailing spaces/tabs from the
+    /// lines they edit (other lines untouched). A blank line commented this
+    /// way becomes `#`, which no longer toggles back. Default: `false`
+    pub trim_trailing: bool,
+
+    /// Line comment toggles (single, range, batch) copy empty lines (after
+    /// the leading spaces with `preserve_indent`) unchanged instead of
+    /// commenting them, as `LineToggleDirection::Add` always does.
+    /// Default: `false`
+    pub skip_blank: bool,
+
+    /// Line comment toggles (single, range, batch) only add (`Add`: an
+    /// already commented line gets a second flag, so `Remove` restores it
+    /// exactly) or only remove (`Remove`) instead of toggling. Suites choose
+    /// their own direction. Default: `LineToggleDirection::Toggle`
+    pub direction: LineToggleDirection,
+
+    /// Line comment toggles report, on stderr, the line ending of every
+    /// edited line before and after (and of every line whose ending is
+    /// converted), in the `trace` format (text when tracing is off), so CI
+    /// can prove no ending was flipped. Lines are zero-indexed; batches
+    /// beyond MAX_BATCH_LINES report once per pass, later passes seeing the
+    /// endings the earlier ones wrote. Default: `false`
+    pub eol_audit: bool,
+
+    /// Directory for the intermediate (temp) files, e.g. a tmpfs like
+    /// `/dev/shm`; `None` keeps them in the working directory. The finished
+    /// file is renamed over the target, or, across filesystems, copied next
+    /// to the target first and renamed from there (see `replace_original`).
+    /// Backups stay in the working directory; as the original's inode is
+    /// never written again, they are hard links where the filesystem allows
+    /// (no copy of the file). Default: `None`
+    pub temp_dir: Option<PathBuf>,
+
+    /// fsync policy for the replace step. Default: `Durability::Flush`
+    pub durability: Durability,
+
+    /// Streaming passes over files of at least PROGRESS_MIN_FILE_BYTES
+    /// report their percentage on stderr every PROGRESS_INTERVAL_MILLIS
+    /// (JSON lines with `TraceFormat::Json`). Default: `false`
+    pub progress: bool,
+
+    /// Edit targets that look like this crate's own backups, sidecars or
+    /// temp files (`is_own_artifact()`) instead of refusing them with
+    /// `OwnArtifact`. Default: `false`
+    pub allow_own_artifacts: bool,
+
+    /// Rust doc comment (`///`) operations also edit files that are not
+    /// `.rs` instead of refusing them with `NotRustSource`. Default: `false`
+    pub allow_any_extension: bool,
+
+    /// Comment toggles of shell files also edit here-doc bodies instead of
+    /// refusing them with `ProtectedRegion` (see Shell here-docs). Default: `false`
+    pub allow_heredoc: bool,
+
+    /// Flag preference for line toggles (`.c` / `.h` as `/* */` with
+    /// `LineStyle::C89`). Default: `LineStyle::Default`
+    pub line_style: LineStyle,
+
+    /// Line and block toggles write this documentation marker set
+    /// (`///<` / `/** */`, ...) instead of plain comments. Default: `None`
+    pub doc_style: Option<DocStyle>,
+
+    /// Spaces indent adds and unindent removes (at most) per line; Makefiles
+    /// always use one tab (`ExtensionSyntax::indent_tab`). Default: 4
+    pub indent_width: usize,
+
+    /// Indent adds and unindent removes one tab instead of `indent_width`
+    /// spaces, in every file, and `preserve_indent` toggles count leading
+    /// tabs as indentation (gofmt). Default: `false`
+    pub indent_tab: bool,
+
+    /// Skip the `backup_toggle_comment_<name>` copy (and its sidecar): the
+    /// original is still snapshotted, so concurrent modification is still
+    /// refused, but `--undo` has nothing new to restore. Default: `false`
+    pub no_backup: bool,
+
+    /// Directory for `backup_toggle_comment_<name>` backups (and `--undo`)
+    /// instead of the working directory. Default: `None`
+    pub backup_dir: Option<PathBuf>,
+
+    /// `(alias, extension)` pairs, both without the dot: file operations
+    /// treat a `.alias` file as `.extension` (matching ignores case; e.g.
+    /// `("tpl", "py")`). Default: empty
+    pub extension_aliases: Vec<(String, String)>,
+
+    /// Refuse (`InvalidUtf8`, before any backup) a target that is not valid
+    /// UTF-8 instead of passing its bytes through. Costs one extra read of
+    /// the file. Default: `false`
+    pub utf8_strict: bool,
+}
+
+impl Default for ToggleOptions {
+    fn default() -> Self {
+        ToggleOptions {
+            max_file_size: Some(DEFAULT_MAX_FILE_SIZE_BYTES),
+            preserve_indent: false,
+            removal: RemovalMode::Strict,
+            trace: TraceFormat::Off,
+            eol: EolMode::Keep,
+            final_newline: FinalNewline::Keep,
+            trim_trailing: false,
+            skip_blank: false,
+            direction: LineToggleDirection::Toggle,
+            eol_audit: false,
+            temp_dir: None,
+            durability: Durability::Flush,
+            progress: false,
+            allow_own_artifacts: false,
+            allow_any_extension: false,
+            allow_heredoc: false,
+            line_style: LineStyle::Default,
+            doc_style: None,
+            indent_width: INDENT_SPACES,
+            indent_tab: false,
+            no_backup: false,
+            backup_dir: None,
+            extension_aliases: Vec::new(),
+            utf8_strict: false,
         }
+    }
+}
+
+/// Check a resolved target against `options.max_file_size`
+///
+/// # Returns
+/// * `Some((size, max))` - File is larger than allowed
+/// * `None` - Within limit, no limit set, or size unreadable (left for the
+///   operation itself to report)
+#[cfg(feature = "fs")]
+pub(crate) fn exceeds_size_limit(path: &Path, options: &ToggleOptions) -> Option<(u64, u64)> {
+    let max = options.max_file_size?;
+    let size = match std::fs::metadata(path) {
+        Ok(m) => m.len(),
+        Err(_) => return None,
     };
 
-    Ok(Some((line_start_pos, has_tag)))
+    if size > max { Some((size, max)) } else { None }
 }
 
-/// Write file with one line toggled using byte-by-byte operations
+/// Check a resolved target against `options.utf8_strict`
 ///
-/// # Overview
-/// Copies source file to destination with single line modified.
-/// All tag operations at COLUMN 0 only. No indentation handling.
+/// # Returns
+/// * `Some(line)` - Zero-indexed line of the first invalid (or truncated)
+///   UTF-8 sequence
+/// * `None` - Valid, check disabled, or file unreadable (left for the
+///   operation itself to report)
+#[cfg(feature = "fs")]
+pub(crate) fn first_invalid_utf8_line(path: &Path, options: &ToggleOptions) -> Option<usize> {
+    if !options.utf8_strict {
+        return None;
+    }
+    let file = File::open(path).ok()?;
+    first_invalid_utf8_line_in(&mut TimedIo(file)).ok()?
+}
+
+/// First line (zero-indexed) holding invalid UTF-8, streamed in
+/// IO_BUFFER_SIZE chunks (a sequence split across chunks is carried over)
+fn first_invalid_utf8_line_in<R: Read>(reader: &mut R) -> std::io::Result<Option<usize>> {
+    // Up to 3 bytes of an incomplete sequence carried in front of each chunk
+    let mut buffer = [0u8; IO_BUFFER_SIZE + 3];
+    let mut carried: usize = 0;
+    let mut line: usize = 0;
+
+    for _ in 0..MAX_BYTE_ITERATIONS {
+        let read = reader.read(&mut buffer[carried..carried + IO_BUFFER_SIZE])?;
+        if read == 0 {
+            // A sequence cut off by the end of the file is invalid too
+            return Ok((carried > 0).then_some(line));
+        }
+        let filled = carried + read;
+        let valid = match std::str::from_utf8(&buffer[..filled]) {
+            Ok(_) => filled,
+            Err(e) if e.error_len().is_some() => {
+                line += count_newlines(&buffer[..e.valid_up_to()]);
+                return Ok(Some(line));
+            }
+            Err(e) => e.valid_up_to(),
+        };
+        line += count_newlines(&buffer[..valid]);
+        buffer.copy_within(valid..filled, 0);
+        carried = filled - valid;
+    }
+    Err(std::io::Error::other("UTF-8 check iteration limit"))
+}
+
+/// Number of `\n` bytes in `bytes`
+fn count_newlines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&b| b == b'\n').count()
+}
+
+/// Extension of a resolved target (no dot), after `options.extension_aliases`
 ///
-/// # Add Mode (has_tag = false)
-/// Write tag at column 0, then copy rest of line
+/// A name in FILE_NAME_EXTENSIONS (`Makefile`, `.env`) counts as its
+/// extension there.
 ///
-/// # Remove Mode (has_tag = true)
-/// Skip tag bytes at column 0, then copy rest of line
-fn write_toggled_file_bytewise(
-    source_path: &Path,
-    dest_path: &Path,
-    target_line: usize,
-    line_start_pos: u64,
-    has_tag: bool,
-    comment_flag: CommentFlag,
-) -> Result<(), ToggleCommentError> {
-    let mut source_file = match File::open(source_path) {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Open)),
+/// # Returns
+/// * `None` - The path has no extension
+#[cfg(feature = "fs")]
+fn target_extension(path: &Path, options: &ToggleOptions) -> Option<String> {
+    let by_name = path
+        .file_name()
+        .and_then(|name| file_name_extension(&name.to_string_lossy()));
+    let extension = match by_name {
+        Some(extension) => extension.to_string(),
+        None => path.extension()?.to_string_lossy().to_string(),
     };
+    Some(
+        options
+            .extension_aliases
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(&extension))
+            .map_or(extension, |(_, target)| target.clone()),
+    )
+}
 
-    let dest_file = match OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(dest_path)
-    {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Create)),
-    };
+#[cfg(all(test, feature = "fs"))]
+mod operation_options_tests {
+    use super::*;
 
-    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, dest_file);
-    let mut byte_bucket: [u8; 1] = [0u8; 1];
-    let mut byte_position: u64 = 0;
+    #[test]
+    fn test_file_too_large_refused_before_backup() {
+        let content = "line 0\nline 1\n";
+        let test_file = create_test_file("test_options_size_limit.py", content);
+        let backup_path = PathBuf::from("backup_toggle_comment_test_options_size_limit.py");
+        let _ = std::fs::remove_file(&backup_path);
 
-    // ===========================================
-    // PART A: Copy bytes before target line
-    // ===========================================
+        let options = ToggleOptions {
+            max_file_size: Some(4),
+            ..ToggleOptions::default()
+        };
+        let path = test_file.to_str().unwrap();
 
-    while byte_position < line_start_pos {
-        if byte_position >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
-        }
+        assert_eq!(
+            toggle_basic_singleline_comment_bytewise_with_options(path, 0, &options),
+            Err(ToggleCommentError::FileTooLarge { size: 14, max: 4 })
+        );
+        assert_eq!(
+            indent_range_bytewise_with_options(path, 0, 1, &options),
+            Err(ToggleIndentError::FileTooLarge { size: 14, max: 4 })
+        );
+        assert!(matches!(
+            toggle_multiple_basic_comments_with_options(path, &[0, 1], &options),
+            Err(ToggleCommentError::FileTooLarge { .. })
+        ));
+        assert!(!backup_path.exists());
+        assert_eq!(read_file_content(&test_file), content);
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        // No limit
+        let unlimited = ToggleOptions {
+            max_file_size: None,
+            ..ToggleOptions::default()
         };
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &unlimited).is_ok());
+        assert_eq!(read_file_content(&test_file), "# line 0\nline 1\n");
 
-        if bytes_read == 0 {
-            return Err(ToggleCommentError::LineNotFound {
-                requested: target_line,
-                file_lines: 0,
-            });
-        }
-
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleCommentError::IoError(IoOperation::Write));
-        }
-
-        byte_position += 1;
+        cleanup_files(&[&test_file, &backup_path]);
     }
 
-    // ===========================================
-    // PART B: Toggle target line at column 0
-    // ===========================================
+    #[test]
+    fn test_default_options() {
+        assert_eq!(
+            ToggleOptions::default().max_file_size,
+            Some(DEFAULT_MAX_FILE_SIZE_BYTES)
+        );
+        assert!(!ToggleOptions::default().preserve_indent);
+        assert_eq!(ToggleOptions::default().removal, RemovalMode::Strict);
+    }
 
-    if has_tag {
-        // REMOVE MODE: Skip tag bytes at column 0
-        let bytes_to_skip = match comment_flag {
-            CommentFlag::Hash => 2,          // "# "
-            CommentFlag::DoubleSlash => 3,   // "// "
-            CommentFlag::TripppleSlash => 4, // "/// "
+    #[test]
+    fn test_relaxed_removal_option() {
+        let original = "//  spaced
+//tight
+// plain
+#x
+";
+        let test_file = create_test_file("test_options_relaxed.rs", original);
+        let path = test_file.to_str().unwrap();
+        let relaxed = ToggleOptions {
+            removal: RemovalMode::Relaxed,
+            ..ToggleOptions::default()
         };
 
-        // Skip the tag bytes
-        for _ in 0..bytes_to_skip {
-            if let Err(_) = source_file.read(&mut byte_bucket) {
-                return Err(ToggleCommentError::IoError(IoOperation::Read));
-            }
-        }
+        // Strict: double-spaced keeps a space, "//x" is not a comment
+        assert!(toggle_basic_singleline_comment_bytewise(path, 0).is_ok());
+        assert!(toggle_basic_singleline_comment_bytewise(path, 1).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            " spaced\n// //tight\n// plain\n#x\n"
+        );
+        assert!(toggle_basic_singleline_comment_bytewise(path, 0).is_ok());
+        assert!(toggle_basic_singleline_comment_bytewise(path, 1).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "//  spaced\n//tight\n// plain\n#x\n"
+        );
 
-        // Copy rest of line
-        loop {
-            let bytes_read = match source_file.read(&mut byte_bucket) {
-                Ok(n) => n,
-                Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-            };
+        // Relaxed: flag and its spaces go, with or without the space
+        assert!(toggle_multiple_basic_comments_with_options(path, &[0, 1, 2], &relaxed).is_ok());
+        assert_eq!(read_file_content(&test_file), "spaced\ntight\nplain\n#x\n");
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &relaxed).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "// spaced\ntight\nplain\n#x\n"
+        );
 
-            if bytes_read == 0 {
-                break; // EOF
-            }
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_relaxed.rs"),
+        ]);
+    }
 
-            if let Err(_) = writer.write_all(&byte_bucket) {
-                return Err(ToggleCommentError::IoError(IoOperation::Write));
-            }
+    #[test]
+    fn test_normalize_eol_option() {
+        let test_file = create_test_file("test_options_eol.py", "a\r\nb\nc\r\n");
+        let path = test_file.to_str().unwrap();
+        let to_lf = ToggleOptions {
+            eol: EolMode::Lf,
+            ..ToggleOptions::default()
+        };
+        let to_crlf = ToggleOptions {
+            eol: EolMode::Crlf,
+            ..ToggleOptions::default()
+        };
 
-            if byte_bucket[0] == b'\n' {
-                break; // End of line
-            }
-        }
-    } else {
-        // ADD MODE: Write tag at column 0, then copy rest of line
-        match comment_flag {
-            CommentFlag::Hash => {
-                if let Err(_) = writer.write_all(b"#") {
-                    return Err(ToggleCommentError::IoError(IoOperation::Write));
-                }
-                if let Err(_) = writer.write_all(b" ") {
-                    return Err(ToggleCommentError::IoError(IoOperation::Write));
-                }
-            }
-            CommentFlag::DoubleSlash => {
-                if let Err(_) = writer.write_all(b"//") {
-                    return Err(ToggleCommentError::IoError(IoOperation::Write));
-                }
-                if let Err(_) = writer.write_all(b" ") {
-                    return Err(ToggleCommentError::IoError(IoOperation::Write));
-                }
-            }
-            CommentFlag::TripppleSlash => {
-                if let Err(_) = writer.write_all(b"///") {
-                    return Err(ToggleCommentError::IoError(IoOperation::Write));
-                }
-                if let Err(_) = writer.write_all(b" ") {
-                    return Err(ToggleCommentError::IoError(IoOperation::Write));
-                }
-            }
-        }
+        // Whole file converted in the same rewrite as the toggle
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &to_lf).is_ok());
+        assert_eq!(read_file_content(&test_file), "a\n# b\nc\n");
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &to_crlf).is_ok());
+        assert_eq!(read_file_content(&test_file), "a\r\nb\r\nc\r\n");
 
-        // Copy rest of line
-        loop {
-            let bytes_read = match source_file.read(&mut byte_bucket) {
-                Ok(n) => n,
-                Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-            };
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_eol.py"),
+        ]);
+    }
 
-            if bytes_read == 0 {
-                break; // EOF
-            }
+    #[test]
+    fn test_final_newline_options() {
+        let test_file = create_test_file("test_options_final_newline.sh", "a\nb");
+        let path = test_file.to_str().unwrap();
+        let ensure = ToggleOptions {
+            final_newline: FinalNewline::Ensure,
+            ..ToggleOptions::default()
+        };
+        let strip = ToggleOptions {
+            final_newline: FinalNewline::Strip,
+            ..ToggleOptions::default()
+        };
 
-            if let Err(_) = writer.write_all(&byte_bucket) {
-                return Err(ToggleCommentError::IoError(IoOperation::Write));
-            }
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &ensure).is_ok());
+        assert_eq!(read_file_content(&test_file), "# a\nb\n");
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &ensure).is_ok());
+        assert_eq!(read_file_content(&test_file), "a\nb\n");
 
-            if byte_bucket[0] == b'\n' {
-                break; // End of line
-            }
-        }
+        // Several passes (more than MAX_BATCH_LINES lines): stripped once only
+        let lines: Vec<usize> = (0..=MAX_BATCH_LINES).collect();
+        let content = "x\n".repeat(MAX_BATCH_LINES + 1) + "\n";
+        std::fs::write(&test_file, &content).unwrap();
+        assert!(toggle_multiple_basic_comments_with_options(path, &lines, &strip).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "# x\n".repeat(MAX_BATCH_LINES + 1)
+        );
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_final_newline.sh"),
+        ]);
     }
 
-    // ===========================================
-    // PART C: Copy rest of file
-    // ===========================================
+    #[test]
+    fn test_trim_trailing_option() {
+        let test_file = create_test_file("test_options_trim.py", "//x  \n    y \t\r\nz  \n");
+        let path = test_file.to_str().unwrap();
+        let trim = ToggleOptions {
+            trim_trailing: true,
+            ..ToggleOptions::default()
+        };
 
-    let mut remaining_bytes: u64 = 0;
-    loop {
-        if remaining_bytes >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
-        }
+        // Edited lines only: line 2 keeps its trailing spaces
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &trim).is_ok());
+        assert!(unindent_line_bytewise_with_options(path, 1, &trim).is_ok());
+        assert_eq!(read_file_content(&test_file), "# //x\ny\r\nz  \n");
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-        };
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_trim.py"),
+        ]);
+    }
 
-        if bytes_read == 0 {
-            break;
-        }
+    #[test]
+    fn test_direction_option() {
+        let original = "a\n# b\n\n";
+        let test_file = create_test_file("test_options_direction.py", original);
+        let path = test_file.to_str().unwrap();
+        let add = ToggleOptions {
+            direction: LineToggleDirection::Add,
+            ..ToggleOptions::default()
+        };
+        let remove = ToggleOptions {
+            direction: LineToggleDirection::Remove,
+            ..ToggleOptions::default()
+        };
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleCommentError::IoError(IoOperation::Write));
-        }
+        // Add: every line gets a flag (blank lines untouched), Remove undoes it
+        assert!(toggle_multiple_basic_comments_with_options(path, &[0, 1, 2], &add).is_ok());
+        assert_eq!(read_file_content(&test_file), "# a\n# # b\n\n");
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &remove).is_ok());
+        assert!(toggle_range_basic_comments_bytewise_with_options(path, 0, 0, &remove).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
 
-        remaining_bytes += 1;
-    }
+        // Remove leaves uncommented lines alone
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &remove).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
 
-    if let Err(_) = writer.flush() {
-        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_direction.py"),
+        ]);
     }
 
-    Ok(())
-}
-
-// ============================================================================
-// REFACTORED WRAPPER - Uses Bytewise Operations
-// ============================================================================
+    #[test]
+    fn test_utf8_strict_option() {
+        let test_file = PathBuf::from("./tests/test_options_utf8_strict.py");
+        std::fs::create_dir_all("./tests").unwrap();
+        std::fs::write(&test_file, b"a\n\xe9t\xe9\n").unwrap();
+        let path = test_file.to_str().unwrap();
+        let strict = ToggleOptions {
+            utf8_strict: true,
+            ..ToggleOptions::default()
+        };
 
-/// Toggle comment on a specific line using byte-wise operations (REFACTORED)
-///
-/// # Overview
-/// **This function has been refactored to use byte-wise operations with no
-/// line buffering or heap allocation.** The public API remains unchanged for
-/// backward compatibility.
-///
-/// Previous implementation loaded entire lines into Vec<u8>. New implementation
-/// uses single-byte buffer and separate find/detect/write operations.
-///
-/// # New Implementation
-/// 1. Calls `find_and_detect_tag_state()` - finds line and detects tag in one pass
-/// 2. Handles `Ok(None)` gracefully (line not found - not treated as error initially)
-/// 3. Calls `write_toggled_file_bytewise()` - performs byte-wise toggle
-/// 4. Preserves existing backup/temp file behavior
-///
-/// # Arguments
-/// * `file_path` - Path to the source file
-/// * `row_line_zeroindex` - Zero-indexed line number to toggle
-///
-/// # Returns
-/// * `Ok(())` - Comment toggled successfully
-/// * `Err(ToggleCommentError)` - Specific error code
-///
-/// # Example
-/// ```no_run
-/// use toggle_comment_indent_module::toggle_basic_singleline_comment;
-///
-/// match toggle_basic_singleline_comment("./src/main.rs", 5) {
-///     Ok(()) => println!("Line 5 toggled"),
-///     Err(e) => eprintln!("Failed: {:?}", e),
-/// }
-/// ```
-///
-/// # Memory Usage
-/// - Previous: Up to MAX_LINE_LENGTH (10KB) per line
-/// - New: 1 byte working buffer
-/// - Reduction: ~10,000x less memory
-///
-/// # Performance
-/// - Previous: Read entire line into Vec
-/// - New: Single-byte operations, may be slower but more memory-safe
-/// - Trade-off: Safety and bounded memory over raw speed
-//
-// NOTE: This is the refactored version using bytewise operations
-pub fn toggle_basic_singleline_comment_bytewise(
-    file_path: &str,
-    row_line_zeroindex: usize,
-) -> Result<(), ToggleCommentError> {
-    // Convert to absolute path
-    let absolute_path = match Path::new(file_path).canonicalize() {
-        Ok(p) => p,
-        Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                return Err(ToggleCommentError::FileNotFound);
-            }
-            return Err(ToggleCommentError::PathError);
-        }
-    };
+        // Refused before any backup, file untouched
+        assert_eq!(
+            toggle_basic_singleline_comment_bytewise_with_options(path, 0, &strict),
+            Err(ToggleCommentError::InvalidUtf8 { line: 1 })
+        );
+        assert_eq!(
+            indent_line_bytewise_with_options(path, 0, &strict),
+            Err(ToggleIndentError::InvalidUtf8 { line: 1 })
+        );
+        assert_eq!(std::fs::read(&test_file).unwrap(), b"a\n\xe9t\xe9\n");
 
-    // Extract and validate file extension
-    let extension = match absolute_path.extension() {
-        Some(ext) => ext.to_string_lossy().to_string(),
-        None => return Err(ToggleCommentError::NoExtension),
-    };
+        // Without it the bytes pass through
+        assert!(toggle_basic_singleline_comment_bytewise(path, 1).is_ok());
+        assert_eq!(std::fs::read(&test_file).unwrap(), b"a\n# \xe9t\xe9\n");
 
-    // Determine comment flag from extension
-    let comment_flag = match determine_comment_flag(&extension) {
-        Some(flag) => flag,
-        None => return Ok(()), // Unsupported extension - no-op
-    };
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_utf8_strict.py"),
+        ]);
+    }
 
-    // ==================================================
-    // NEW: Combined find and detect in single pass
-    // ==================================================
-    let (line_start_pos, has_tag) =
-        match find_and_detect_tag_state(file_path, row_line_zeroindex, comment_flag)? {
-            Some((pos, tag_state)) => (pos, tag_state),
-            None => {
-                // Line not found - return appropriate error
-                return Err(ToggleCommentError::LineNotFound {
-                    requested: row_line_zeroindex,
-                    file_lines: 0, // Unknown in bytewise mode
-                });
-            }
+    #[test]
+    fn test_skip_blank_option() {
+        let original = "a\n\n    \n# b\n";
+        let test_file = create_test_file("test_options_skip_blank.py", original);
+        let path = test_file.to_str().unwrap();
+        let skip = ToggleOptions {
+            skip_blank: true,
+            ..ToggleOptions::default()
         };
 
-    // Get filename for backup naming
-    let filename = match absolute_path.file_name() {
-        Some(name) => name.to_string_lossy().to_string(),
-        None => return Err(ToggleCommentError::PathError),
-    };
+        // The empty line stays; the space-only one is not empty
+        assert!(toggle_range_basic_comments_bytewise_with_options(path, 0, 3, &skip).is_ok());
+        assert_eq!(read_file_content(&test_file), "# a\n\n#     \nb\n");
 
-    // Create backup path in CWD
-    let backup_filename = format!("backup_toggle_comment_{}", filename);
-    let backup_path = PathBuf::from(&backup_filename);
+        // Single-line toggles of an empty line take the engine and keep it
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &skip).is_ok());
+        assert_eq!(read_file_content(&test_file), "# a\n\n#     \nb\n");
 
-    // Create backup copy of original file
-    if let Err(_) = std::fs::copy(&absolute_path, &backup_path) {
-        return Err(ToggleCommentError::IoError(IoOperation::Backup));
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_skip_blank.py"),
+        ]);
     }
 
-    // Create working temp file in CWD
-    let temp_filename = format!("temp_toggle_bytewise_{}_{}", std::process::id(), filename);
-    let temp_path = PathBuf::from(&temp_filename);
+    #[test]
+    fn test_yaml_preserves_indent_by_default() {
+        let original = "items:\n  - a\n  - b\n";
+        let test_file = create_test_file("test_options_indent.yaml", original);
+        let path = test_file.to_str().unwrap();
 
-    // ==================================================
-    // NEW: Byte-wise write operation
-    // ==================================================
-    let process_result = write_toggled_file_bytewise(
-        &absolute_path,
-        &temp_path,
-        row_line_zeroindex,
-        line_start_pos,
-        has_tag,
-        comment_flag,
-    );
-
-    // Handle processing result (same as before)
-    match process_result {
-        Ok(()) => {
-            // Success: replace original with temp file
-            if let Err(_) = std::fs::copy(&temp_path, &absolute_path) {
-                let _ = std::fs::remove_file(&temp_path);
-                return Err(ToggleCommentError::IoError(IoOperation::Replace));
-            }
-
-            // Clean up temp file
-            if let Err(_) = std::fs::remove_file(&temp_path) {
-                #[cfg(debug_assertions)]
-                eprintln!("Warning: Failed to clean up temp file");
-            }
+        assert!(toggle_basic_singleline_comment_bytewise(path, 1).is_ok());
+        assert_eq!(read_file_content(&test_file), "items:\n  # - a\n  - b\n");
+        assert!(toggle_basic_singleline_comment_bytewise(path, 1).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
 
-            Ok(())
-        }
-        Err(e) => {
-            let _ = std::fs::remove_file(&temp_path);
-            Err(e)
-        }
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_indent.yaml"),
+        ]);
     }
-}
-
-// ============================================================================
-// TESTS - Byte-Wise Operations
-// ============================================================================
-
-#[cfg(test)]
-mod bytewise_tests {
-    use super::*;
-
-    // ========================================
-    // find_and_detect_tag_state() Tests
-    // ========================================
 
     #[test]
-    fn test_find_and_detect_line_0() {
-        let content = "code\nmore\n";
-        let test_file = create_test_file("test_bytewise_find_0.rs", content);
+    fn test_preserve_indent_option() {
+        let original = "fn main() {\n    run();\n}\n";
+        let test_file = create_test_file("test_options_indent.rs", original);
+        let path = test_file.to_str().unwrap();
+        let options = ToggleOptions {
+            preserve_indent: true,
+            ..ToggleOptions::default()
+        };
 
-        let result =
-            find_and_detect_tag_state(test_file.to_str().unwrap(), 0, CommentFlag::DoubleSlash);
+        // Column 0 without the option
+        assert!(toggle_basic_singleline_comment_bytewise(path, 1).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "fn main() {\n//     run();\n}\n"
+        );
+        assert!(toggle_basic_singleline_comment_bytewise(path, 1).is_ok());
 
-        assert!(result.is_ok());
-        let found = result.unwrap();
-        assert!(found.is_some());
-        let (pos, has_tag) = found.unwrap();
-        assert_eq!(pos, 0); // Line 0 starts at position 0
-        assert_eq!(has_tag, false); // "code" has no "//" tag
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &options).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "fn main() {\n    // run();\n}\n"
+        );
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &options).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
 
-        cleanup_files(&[&test_file]);
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_indent.rs"),
+        ]);
     }
 
     #[test]
-    fn test_find_and_detect_line_1() {
-        let content = "line 0\n// line 1\nline 2\n";
-        let test_file = create_test_file("test_bytewise_find_1.rs", content);
-
-        let result =
-            find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::DoubleSlash);
+    fn test_preserve_indent_deep_indent() {
+        // Generated JSX nests far deeper than a short scan window would reach
+        let indent = " ".repeat(150);
+        let original = format!("<App>\n{}<Row />\n{}// <Cell />\n", indent, indent);
+        let test_file = create_test_file("test_options_deep_indent.js", &original);
+        let path = test_file.to_str().unwrap();
+        let options = ToggleOptions {
+            preserve_indent: true,
+            ..ToggleOptions::default()
+        };
 
-        assert!(result.is_ok());
-        let found = result.unwrap();
-        assert!(found.is_some());
-        let (pos, has_tag) = found.unwrap();
-        assert_eq!(pos, 7); // After "line 0\n"
-        assert_eq!(has_tag, true); // Has "// " pattern
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 2, &options).is_ok());
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &options).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            format!("<App>\n{}// <Row />\n{}<Cell />\n", indent, indent)
+        );
+        assert_eq!(comment_column_in_file(path, 1, &options), Ok(Some(150)));
 
-        cleanup_files(&[&test_file]);
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_deep_indent.js"),
+        ]);
     }
-
     #[test]
-    fn test_find_and_detect_line_not_found() {
-        let content = "line 0\nline 1\n";
-        let test_file = create_test_file("test_bytewise_notfound.rs", content);
+    fn test_c89_line_style() {
+        let original = "int a;\nint b;\n/* int c; */\n";
+        let test_file = create_test_file("test_options_c89.c", original);
+        let path = test_file.to_str().unwrap();
+        let c89 = ToggleOptions {
+            line_style: LineStyle::C89,
+            ..ToggleOptions::default()
+        };
 
-        let result =
-            find_and_detect_tag_state(test_file.to_str().unwrap(), 10, CommentFlag::DoubleSlash);
+        // Single line (column-0 path) and range (line engine) both wrap
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &c89).is_ok());
+        assert!(toggle_range_basic_comments_bytewise_with_options(path, 1, 2, &c89).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "/* int a; */\n/* int b; */\nint c;\n"
+        );
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &c89).is_ok());
+        assert!(toggle_range_basic_comments_bytewise_with_options(path, 1, 2, &c89).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
 
-        assert!(result.is_ok());
-        let found = result.unwrap();
-        assert!(found.is_none()); // Line 10 doesn't exist - Ok(None)
+        // Other C-family extensions keep //
+        assert_eq!(
+            determine_line_flag("cpp", &c89),
+            Some(CommentFlag::DoubleSlash)
+        );
+        assert_eq!(determine_line_flag("H", &c89), Some(CommentFlag::SlashStar));
+        assert_eq!(
+            determine_line_flag("c", &ToggleOptions::default()),
+            Some(CommentFlag::DoubleSlash)
+        );
 
-        cleanup_files(&[&test_file]);
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_c89.c"),
+        ]);
     }
 
     #[test]
-    fn test_find_and_detect_no_space_after_tag() {
-        let content = "line 0\n//code\nline 2\n";
-        let test_file = create_test_file("test_bytewise_nospace.rs", content);
-
-        let result =
-            find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::DoubleSlash);
+    fn test_doc_style_markers() {
+        let original = "int a;\nint b;\n";
+        let test_file = create_test_file("test_options_doc_style.cpp", original);
+        let path = test_file.to_str().unwrap();
+        let doxygen = ToggleOptions {
+            doc_style: Some(DocStyle::Doxygen),
+            ..ToggleOptions::default()
+        };
+        let qt = ToggleOptions {
+            doc_style: Some(DocStyle::Qt),
+            ..ToggleOptions::default()
+        };
 
-        assert!(result.is_ok());
-        let found = result.unwrap();
-        assert!(found.is_some());
-        let (_pos, has_tag) = found.unwrap();
-        assert_eq!(has_tag, false); // "//code" doesn't match "// " pattern
+        // Line toggles: member markers, both ways
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &doxygen).is_ok());
+        assert!(toggle_range_basic_comments_bytewise_with_options(path, 1, 1, &qt).is_ok());
+        assert_eq!(read_file_content(&test_file), "///< int a;\n//!< int b;\n");
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &doxygen).is_ok());
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &qt).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
+
+        // Block toggles: /*! ... */ and back
+        assert!(toggle_block_comment_bytewise_with_options(path, 0, 1, &qt).is_ok());
+        assert_eq!(read_file_content(&test_file), "/*!\nint a;\nint b;\n*/\n");
+        assert!(toggle_block_comment_bytewise_with_options(path, 0, 3, &qt).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
+
+        // JSDoc keeps // for lines; non-/* */ languages keep their markers
+        let jsdoc = ToggleOptions {
+            doc_style: Some(DocStyle::JsDoc),
+            ..ToggleOptions::default()
+        };
+        assert_eq!(
+            determine_line_flag("js", &jsdoc),
+            Some(CommentFlag::DoubleSlash)
+        );
+        assert_eq!(
+            determine_block_markers_with("ts", &jsdoc).map(|m| m.start),
+            Some(b"/**" as &[u8])
+        );
+        assert_eq!(determine_line_flag("py", &doxygen), Some(CommentFlag::Hash));
+        assert_eq!(
+            determine_block_markers_with("py", &doxygen),
+            determine_block_markers("py")
+        );
 
-        cleanup_files(&[&test_file]);
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_options_doc_style.cpp"),
+        ]);
     }
 
     #[test]
-    fn test_find_and_detect_empty_line() {
-        let content = "line 0\n\nline 2\n";
-        let test_file = create_test_file("test_bytewise_empty.rs", content);
+    fn test_indent_width_no_backup_and_aliases() {
+        let original = "      a\nb\n";
+        let test_file = create_test_file("test_options_project.tpl", original);
+        let path = test_file.to_str().unwrap();
+        let backup_path = PathBuf::from("backup_toggle_comment_test_options_project.tpl");
+        let _ = std::fs::remove_file(&backup_path);
+        let options = ToggleOptions {
+            indent_width: 2,
+            no_backup: true,
+            extension_aliases: vec![("TPL".to_string(), "py".to_string())],
+            ..ToggleOptions::default()
+        };
 
-        let result =
-            find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::DoubleSlash);
+        assert!(indent_line_bytewise_with_options(path, 1, &options).is_ok());
+        assert!(unindent_line_bytewise_with_options(path, 0, &options).is_ok());
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &options).is_ok());
+        assert_eq!(read_file_content(&test_file), "    a\n#   b\n");
+        assert!(!backup_path.exists());
 
-        assert!(result.is_ok());
-        let found = result.unwrap();
-        assert!(found.is_some());
-        let (_pos, has_tag) = found.unwrap();
-        assert_eq!(has_tag, false); // Empty line has no tag
+        // Without the alias .tpl is unsupported (no-op)
+        let plain = ToggleOptions {
+            no_backup: true,
+            ..ToggleOptions::default()
+        };
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &plain).is_ok());
+        assert_eq!(read_file_content(&test_file), "    a\n#   b\n");
 
-        cleanup_files(&[&test_file]);
+        cleanup_files(&[&test_file, &backup_path]);
     }
+}
 
-    #[test]
-    fn test_find_and_detect_hash_comment() {
-        let content = "line 0\n# code\nline 2\n";
-        let test_file = create_test_file("test_bytewise_hash.py", content);
+// ============================================================================
+// STAGE TRACE (opt-in diagnostics)
+// ============================================================================
+//
+// When automation reports a failure, the error says what went wrong but not
+// at which stage or after how long. With `ToggleOptions::trace` set, every
+// pipeline stage writes one line to stderr:
+//
+//     toggle_comment trace: op=toggle_basic stage=backup status=ok micros=84
+//     {"op":"toggle_basic","stage":"backup","status":"ok","micros":84}
+//
+// The temp_write line also splits out the time spent inside file reads and
+// writes (`read_micros=`, `write_micros=`), so a slow network filesystem
+// shows up as such rather than as a slow edit. Only the calls on the `File`
+// under each I/O buffer are timed (`TimedIo`), one clock read per call.
+//
+// Operations go through `StageTrace` for each stage instead of printing
+// themselves. With `TraceFormat::Off` (the default) no clock is read and
+// nothing is written. Only static names and numbers are emitted: no paths,
+// no file content, so the JSON needs no escaping.
+//
+// Progress (`ToggleOptions::progress`) rides on the same reporter: during
+// each streaming pass over a large file a watcher thread prints how far the
+// temp file has grown relative to the original, in the trace's format:
+//
+//     toggle_comment progress: op=batch 40% (8388608 of 20971520 bytes)
+//     {"op":"batch","progress":40,"bytes":8388608,"total":20971520}
+//
+// Multi-pass operations (batches beyond MAX_BATCH_LINES, two-step blocks)
+// count from 0% to 100% once per pass.
+// ============================================================================
 
-        let result = find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::Hash);
+/// Smallest file that gets progress lines (smaller passes finish too fast)
+pub const PROGRESS_MIN_FILE_BYTES: u64 = 16 * 1024 * 1024;
 
-        assert!(result.is_ok());
-        let found = result.unwrap();
-        assert!(found.is_some());
-        let (_pos, has_tag) = found.unwrap();
-        assert_eq!(has_tag, true); // "# " pattern found
+/// Shortest time between two progress lines of one pass
+pub const PROGRESS_INTERVAL_MILLIS: u64 = 500;
 
-        cleanup_files(&[&test_file]);
-    }
+/// Output format of the stage trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// No trace (default)
+    Off,
 
-    #[test]
-    fn test_find_and_detect_triple_slash() {
-        let content = "line 0\n/// docs\nline 2\n";
-        let test_file = create_test_file("test_bytewise_triple.rs", content);
+    /// `toggle_comment trace: op=... stage=... status=... micros=...`
+    Text,
 
-        let result =
-            find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::TripppleSlash);
+    /// One JSON object per line
+    Json,
+}
 
-        assert!(result.is_ok());
-        let found = result.unwrap();
-        assert!(found.is_some());
-        let (_pos, has_tag) = found.unwrap();
-        assert_eq!(has_tag, true); // "/// " pattern found
+/// Pipeline stage reported by the trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TraceStage {
+    /// Copying the original to `backup_toggle_comment_*`
+    Backup,
 
-        cleanup_files(&[&test_file]);
-    }
+    /// Streaming the edited content into a temp file
+    TempWrite,
 
-    // ========================================
-    // write_toggled_file_bytewise() Tests
-    // ========================================
+    /// Size check of the temp file against the original
+    Verify,
 
-    #[test]
-    fn test_write_bytewise_add_tag() {
-        let content = "line 0\ncode\nline 2\n";
-        let test_file = create_test_file("test_bytewise_write_add.rs", content);
+    /// Copying the temp file over the original
+    Replace,
+}
 
-        let temp_file = PathBuf::from("temp_test_bytewise_add.rs");
+impl TraceStage {
+    fn name(self) -> &'static str {
+        match self {
+            TraceStage::Backup => "backup",
+            TraceStage::TempWrite => "temp_write",
+            TraceStage::Verify => "verify",
+            TraceStage::Replace => "replace",
+        }
+    }
+}
 
-        let result = write_toggled_file_bytewise(
-            &test_file,
-            &temp_file,
-            1,
-            7,     // Position after "line 0\n"
-            false, // has_tag = false (ADD mode)
-            CommentFlag::DoubleSlash,
-        );
+/// Stage reporter for one operation (Copy, no state beyond its settings)
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StageTrace {
+    format: TraceFormat,
 
-        assert!(result.is_ok());
+    /// Operation name shown as `op=`
+    operation: &'static str,
 
-        let new_content = read_file_content(&temp_file);
-        assert_eq!(new_content, "line 0\n// code\nline 2\n");
+    /// `ToggleOptions::progress`
+    progress: bool,
 
-        cleanup_files(&[&test_file, &temp_file]);
+    /// Backups may be hard links (`ToggleOptions::temp_dir` set, see
+    /// `create_backup()`)
+    link_backup: bool,
+
+    /// `ToggleOptions::no_backup`: only snapshot the original
+    no_backup: bool,
+}
+
+impl StageTrace {
+    pub(crate) fn new(options: &ToggleOptions, operation: &'static str) -> Self {
+        StageTrace {
+            format: options.trace,
+            operation,
+            progress: options.progress,
+            link_backup: options.temp_dir.is_some(),
+            no_backup: options.no_backup,
+        }
     }
 
-    #[test]
-    fn test_write_bytewise_remove_tag() {
-        let content = "line 0\n// code\nline 2\n";
-        let test_file = create_test_file("test_bytewise_write_remove.rs", content);
+    /// Run a streaming pass (`TempWrite` from `source` into `dest`)
+    ///
+    /// With `progress` on and `source` at least PROGRESS_MIN_FILE_BYTES, a
+    /// watcher thread reports on stderr, at most every
+    /// PROGRESS_INTERVAL_MILLIS, how much of `source` size has reached `dest`
+    /// (passes write as they read), and 100% when the pass succeeds.
+    pub(crate) fn run_streaming<T, E>(
+        &self,
+        source: &Path,
+        dest: &Path,
+        action: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let total = match std::fs::metadata(source) {
+            Ok(m) if self.progress && m.len() >= PROGRESS_MIN_FILE_BYTES => m.len(),
+            _ => return self.run_temp_write(action),
+        };
 
-        let temp_file = PathBuf::from("temp_test_bytewise_remove.rs");
+        let done = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let watcher = scope.spawn(|| {
+                let mut last_percent: u64 = 0;
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    std::thread::park_timeout(std::time::Duration::from_millis(
+                        PROGRESS_INTERVAL_MILLIS,
+                    ));
+                    let written = std::fs::metadata(dest).map_or(0, |m| m.len());
+                    // 100% is only reported once the pass is done
+                    let percent = (written.saturating_mul(100) / total).min(99);
+                    if percent > last_percent && !done.load(std::sync::atomic::Ordering::Relaxed) {
+                        last_percent = percent;
+                        self.emit_progress(percent, written, total);
+                    }
+                }
+            });
+            self.run_temp_write(|| {
+                let result = action();
+                done.store(true, std::sync::atomic::Ordering::Relaxed);
+                watcher.thread().unpark();
+                let _ = watcher.join();
+                if result.is_ok() {
+                    self.emit_progress(100, total, total);
+                }
+                result
+            })
+        })
+    }
 
-        let result = write_toggled_file_bytewise(
-            &test_file,
-            &temp_file,
-            1,
-            7,    // Position after "line 0\n"
-            true, // has_tag = true (REMOVE mode)
-            CommentFlag::DoubleSlash,
+    /// Print one progress line (JSON with `TraceFormat::Json`, text otherwise)
+    fn emit_progress(&self, percent: u64, bytes: u64, total: u64) {
+        let mut stderr = std::io::stderr().lock();
+        let _ = write_progress_line(
+            &mut stderr,
+            self.format,
+            self.operation,
+            percent,
+            bytes,
+            total,
         );
+    }
 
-        assert!(result.is_ok());
-
-        let new_content = read_file_content(&temp_file);
-        assert_eq!(new_content, "line 0\ncode\nline 2\n");
+    /// Run the TempWrite stage, also reporting its file read / write time
+    fn run_temp_write<T, E>(&self, action: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        if self.format == TraceFormat::Off {
+            return action();
+        }
+        let previous = IO_TIMES.replace(Some(IoTimes::default()));
+        let started = std::time::Instant::now();
+        let result = action();
+        let micros = started.elapsed().as_micros();
+        let io = IO_TIMES.replace(previous);
+        self.emit(TraceStage::TempWrite, result.is_ok(), micros, io);
+        result
+    }
 
-        cleanup_files(&[&test_file, &temp_file]);
+    /// Run a stage, reporting whether it returned `Ok` and how long it took
+    pub(crate) fn run<T, E>(
+        &self,
+        stage: TraceStage,
+        action: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        if self.format == TraceFormat::Off {
+            return action();
+        }
+        let started = std::time::Instant::now();
+        let result = action();
+        self.emit(stage, result.is_ok(), started.elapsed().as_micros(), None);
+        result
     }
 
-    #[test]
-    fn test_write_bytewise_preserves_other_lines() {
-        let content = "line 0\nline 1\nline 2\nline 3\n";
-        let test_file = create_test_file("test_bytewise_preserve.rs", content);
+    /// Run the Backup stage (`create_backup()`)
+    ///
+    /// Records `Warning::BackupReplaced` when the backup it overwrites was
+    /// taken from a different file. With
+    /// `no_backup` the stage is skipped and only the snapshot is taken.
+    #[cfg(feature = "fs")]
+    pub(crate) fn backup(
+        &self,
+        original_path: &Path,
+        backup_path: &Path,
+    ) -> std::io::Result<FileSnapshot> {
+        if self.no_backup {
+            return FileSnapshot::of(original_path);
+        }
+        let previous = recorded_backup_origin(backup_path);
+        let snapshot = self.run(TraceStage::Backup, || {
+            create_backup(original_path, backup_path, self.link_backup)
+        })?;
+        if let Some(previous) = previous
+            && backup_origin(original_path) != Some(previous.as_str())
+        {
+            record_warning(Warning::BackupReplaced {
+                backup: backup_path.to_string_lossy().into_owned(),
+                previous_origin: previous,
+            });
+        }
+        Ok(snapshot)
+    }
 
-        let temp_file = PathBuf::from("temp_test_bytewise_preserve.rs");
+    /// Run a check stage, reporting its outcome and how long it took
+    pub(crate) fn check(&self, stage: TraceStage, action: impl FnOnce() -> bool) -> bool {
+        if self.format == TraceFormat::Off {
+            return action();
+        }
+        let started = std::time::Instant::now();
+        let passed = action();
+        self.emit(stage, passed, started.elapsed().as_micros(), None);
+        passed
+    }
 
-        let result = write_toggled_file_bytewise(
-            &test_file,
-            &temp_file,
-            1,
-            7,     // After "line 0\n"
-            false, // ADD mode
-            CommentFlag::DoubleSlash,
+    /// Write one trace line to stderr (write errors are ignored)
+    fn emit(&self, stage: TraceStage, ok: bool, micros: u128, io: Option<IoTimes>) {
+        let mut stderr = std::io::stderr().lock();
+        let _ = write_trace_line(
+            &mut stderr,
+            self.format,
+            self.operation,
+            stage,
+            ok,
+            micros,
+            io,
         );
+    }
+}
 
-        assert!(result.is_ok());
-
-        let new_content = read_file_content(&temp_file);
-        assert_eq!(new_content, "line 0\n// line 1\nline 2\nline 3\n");
-
-        cleanup_files(&[&test_file, &temp_file]);
+/// Format one progress line
+fn write_progress_line<W: Write>(
+    writer: &mut W,
+    format: TraceFormat,
+    operation: &str,
+    percent: u64,
+    bytes: u64,
+    total: u64,
+) -> std::io::Result<()> {
+    match format {
+        TraceFormat::Json => writeln!(
+            writer,
+            "{{\"op\":\"{}\",\"progress\":{},\"bytes\":{},\"total\":{}}}",
+            operation, percent, bytes, total
+        ),
+        TraceFormat::Off | TraceFormat::Text => writeln!(
+            writer,
+            "toggle_comment progress: op={} {}% ({} of {} bytes)",
+            operation, percent, bytes, total
+        ),
     }
+}
 
-    #[test]
-    fn test_write_bytewise_empty_line_add() {
-        let content = "line 0\n\nline 2\n";
-        let test_file = create_test_file("test_bytewise_empty_add.rs", content);
+// ============================================================================
+// WARNINGS
+// ============================================================================
+// Non-fatal conditions an operation meets on the way (it still succeeds, or
+// fails for its own reason) are recorded here instead of printed: callers
+// drain them with `take_warnings()` after the operation and decide. The CLI
+// prints them on stderr with `write_warning_line()` (JSON lines with
+// --trace-json), `--serve` adds them to the response.
+//
+// Per thread, like the I/O timing, so concurrent operations keep their own;
+// at most MAX_WARNINGS are kept until drained, later ones only counted.
+// ============================================================================
 
-        let temp_file = PathBuf::from("temp_test_bytewise_empty_add.rs");
+/// Most warnings kept per thread between two `take_warnings()` calls
+pub const MAX_WARNINGS: usize = 32;
+
+/// Non-fatal condition met by an operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The backup this operation overwrote was taken from another file
+    /// (same basename): that file's backup is gone
+    BackupReplaced {
+        backup: String,
+        previous_origin: String,
+    },
+
+    /// A temp file could not be removed (left behind; see --clean-temp)
+    TempCleanupFailed { temp: String },
+
+    /// A line comment toggle on the line engine (ranges, lists, batches;
+    /// single lines with engine options) found both `\n` and `\r\n`
+    /// endings (kept as they were; `EolMode` converts them)
+    MixedLineEndings { lf: u64, crlf: u64 },
+}
 
-        let result = write_toggled_file_bytewise(
-            &test_file,
-            &temp_file,
-            1,
-            7,     // After "line 0\n"
-            false, // ADD mode
-            CommentFlag::DoubleSlash,
-        );
+impl Warning {
+    /// Stable name (`backup_replaced`, `temp_cleanup_failed`, `mixed_line_endings`)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Warning::BackupReplaced { .. } => "backup_replaced",
+            Warning::TempCleanupFailed { .. } => "temp_cleanup_failed",
+            Warning::MixedLineEndings { .. } => "mixed_line_endings",
+        }
+    }
+}
 
-        assert!(result.is_ok());
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::BackupReplaced {
+                backup,
+                previous_origin,
+            } => write!(
+                f,
+                "{} held the backup of {}; it now holds this file's",
+                backup, previous_origin
+            ),
+            Warning::TempCleanupFailed { temp } => {
+                write!(f, "could not remove temp file {}", temp)
+            }
+            Warning::MixedLineEndings { lf, crlf } => write!(
+                f,
+                "file mixes line endings ({} LF, {} CRLF); kept as they are",
+                lf, crlf
+            ),
+        }
+    }
+}
 
-        let new_content = read_file_content(&temp_file);
-        assert_eq!(new_content, "line 0\n// \nline 2\n"); // Tag added to empty line
+/// Warnings recorded on this thread since the last `take_warnings()`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Warnings {
+    /// In the order they were met, at most MAX_WARNINGS
+    pub list: Vec<Warning>,
 
-        cleanup_files(&[&test_file, &temp_file]);
-    }
+    /// Further warnings, counted but not kept
+    pub dropped: usize,
+}
 
-    #[test]
-    fn test_write_bytewise_crlf_preservation() {
-        let content = "line 0\r\ncode\r\nline 2\r\n";
-        let test_file = create_test_file("test_bytewise_crlf.rs", content);
+std::thread_local! {
+    static WARNINGS: std::cell::RefCell<Warnings> = const {
+        std::cell::RefCell::new(Warnings { list: Vec::new(), dropped: 0 })
+    };
+}
 
-        let temp_file = PathBuf::from("temp_test_bytewise_crlf.rs");
+/// Record a warning for this thread's caller
+pub(crate) fn record_warning(warning: Warning) {
+    WARNINGS.with(|warnings| {
+        let mut warnings = warnings.borrow_mut();
+        if warnings.list.len() < MAX_WARNINGS {
+            warnings.list.push(warning);
+        } else {
+            warnings.dropped = warnings.dropped.saturating_add(1);
+        }
+    });
+}
 
-        let result = write_toggled_file_bytewise(
-            &test_file,
-            &temp_file,
-            1,
-            8,     // After "line 0\r\n"
-            false, // ADD mode
-            CommentFlag::DoubleSlash,
-        );
+/// Drain the warnings recorded on this thread (see `Warning`)
+pub fn take_warnings() -> Warnings {
+    WARNINGS.with(|warnings| std::mem::take(&mut *warnings.borrow_mut()))
+}
 
-        assert!(result.is_ok());
+/// Format drained warnings, one line each, then one for the dropped count
+/// (`Off` formats as `Text`)
+pub fn write_warnings<W: Write>(
+    writer: &mut W,
+    format: TraceFormat,
+    operation: &str,
+    warnings: &Warnings,
+) -> std::io::Result<()> {
+    for warning in &warnings.list {
+        write_warning_line(writer, format, operation, warning)?;
+    }
+    match (warnings.dropped, format) {
+        (0, _) => Ok(()),
+        (dropped, TraceFormat::Json) => writeln!(
+            writer,
+            "{{\"op\":\"{}\",\"warning\":\"dropped\",\"count\":{}}}",
+            json_escaped(operation),
+            dropped
+        ),
+        (dropped, _) => writeln!(
+            writer,
+            "toggle_comment warning: op={} {} more warnings not shown",
+            operation, dropped
+        ),
+    }
+}
 
-        let new_content = read_file_content(&temp_file);
-        // CRLF should be preserved
-        assert_eq!(new_content, "line 0\r\n// code\r\nline 2\r\n");
+/// Format one warning line
+fn write_warning_line<W: Write>(
+    writer: &mut W,
+    format: TraceFormat,
+    operation: &str,
+    warning: &Warning,
+) -> std::io::Result<()> {
+    if format != TraceFormat::Json {
+        return writeln!(
+            writer,
+            "toggle_comment warning: op={} {}",
+            operation, warning
+        );
+    }
+    write!(
+        writer,
+        "{{\"op\":\"{}\",\"warning\":\"{}\"",
+        json_escaped(operation),
+        warning.kind()
+    )?;
+    match warning {
+        Warning::BackupReplaced {
+            backup,
+            previous_origin,
+        } => write!(
+            writer,
+            ",\"backup\":\"{}\",\"previous_origin\":\"{}\"",
+            json_escaped(backup),
+            json_escaped(previous_origin)
+        )?,
+        Warning::TempCleanupFailed { temp } => {
+            write!(writer, ",\"temp\":\"{}\"", json_escaped(temp))?
+        }
+        Warning::MixedLineEndings { lf, crlf } => {
+            write!(writer, ",\"lf\":{},\"crlf\":{}", lf, crlf)?
+        }
+    }
+    writeln!(writer, "}}")
+}
 
-        cleanup_files(&[&test_file, &temp_file]);
+/// JSON string content (quote, backslash and control characters escaped)
+fn json_escaped(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if u32::from(c) < 0x20 => escaped.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => escaped.push(c),
+        }
     }
+    escaped
+}
 
-    // ========================================
-    // Integration Tests - Full Toggle
-    // ========================================
+/// Format one trace line
+fn write_trace_line<W: Write>(
+    writer: &mut W,
+    format: TraceFormat,
+    operation: &str,
+    stage: TraceStage,
+    ok: bool,
+    micros: u128,
+    io: Option<IoTimes>,
+) -> std::io::Result<()> {
+    let status = if ok { "ok" } else { "failed" };
+    match format {
+        TraceFormat::Off => Ok(()),
+        TraceFormat::Text => {
+            write!(
+                writer,
+                "toggle_comment trace: op={} stage={} status={} micros={}",
+                operation,
+                stage.name(),
+                status,
+                micros
+            )?;
+            if let Some(io) = io {
+                write!(
+                    writer,
+                    " read_micros={} write_micros={}",
+                    io.read_nanos / 1000,
+                    io.write_nanos / 1000
+                )?;
+            }
+            writeln!(writer)
+        }
+        TraceFormat::Json => {
+            write!(
+                writer,
+                "{{\"op\":\"{}\",\"stage\":\"{}\",\"status\":\"{}\",\"micros\":{}",
+                operation,
+                stage.name(),
+                status,
+                micros
+            )?;
+            if let Some(io) = io {
+                write!(
+                    writer,
+                    ",\"read_micros\":{},\"write_micros\":{}",
+                    io.read_nanos / 1000,
+                    io.write_nanos / 1000
+                )?;
+            }
+            writeln!(writer, "}}")
+        }
+    }
+}
 
-    #[test]
-    fn test_bytewise_toggle_full_add() {
-        let content = "fn main() {}\n";
-        let test_file = create_test_file("test_bytewise_full_add.rs", content);
+/// Time spent inside file reads and writes during one TempWrite stage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct IoTimes {
+    read_nanos: u64,
+    write_nanos: u64,
+}
 
-        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+std::thread_local! {
+    /// Counters of the traced TempWrite stage running on this thread (None:
+    /// not timing). Per thread, so concurrent operations keep their own
+    static IO_TIMES: std::cell::Cell<Option<IoTimes>> = const { std::cell::Cell::new(None) };
+}
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "// fn main() {}\n");
+/// Add one timed call to this thread's counters
+fn record_io_time(started: std::time::Instant, is_write: bool) {
+    let nanos = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+    IO_TIMES.with(|times| {
+        if let Some(mut io) = times.get() {
+            if is_write {
+                io.write_nanos = io.write_nanos.saturating_add(nanos);
+            } else {
+                io.read_nanos = io.read_nanos.saturating_add(nanos);
+            }
+            times.set(Some(io));
+        }
+    });
+}
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_bytewise_full_add.rs"),
-        ]);
+/// File under a streaming pass's I/O buffer, timing each call while a
+/// traced TempWrite stage runs (otherwise only a thread-local check)
+#[derive(Debug)]
+pub(crate) struct TimedIo<T>(pub(crate) T);
+
+impl<T: Read> Read for TimedIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if IO_TIMES.get().is_none() {
+            return self.0.read(buf);
+        }
+        let started = std::time::Instant::now();
+        let result = self.0.read(buf);
+        record_io_time(started, false);
+        result
     }
+}
 
-    #[test]
-    fn test_bytewise_toggle_full_remove() {
-        let content = "// fn main() {}\n";
-        let test_file = create_test_file("test_bytewise_full_remove.rs", content);
+impl<T: Write> Write for TimedIo<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if IO_TIMES.get().is_none() {
+            return self.0.write(buf);
+        }
+        let started = std::time::Instant::now();
+        let result = self.0.write(buf);
+        record_io_time(started, true);
+        result
+    }
 
-        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "fn main() {}\n");
+#[cfg(all(test, feature = "fs"))]
+mod stage_trace_tests {
+    use super::*;
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_bytewise_full_remove.rs"),
-        ]);
+    #[test]
+    fn test_progress_line_formats() {
+        let mut text: Vec<u8> = Vec::new();
+        write_progress_line(&mut text, TraceFormat::Off, "batch", 40, 8, 20).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "toggle_comment progress: op=batch 40% (8 of 20 bytes)\n"
+        );
+
+        let mut json: Vec<u8> = Vec::new();
+        write_progress_line(&mut json, TraceFormat::Json, "indent", 100, 20, 20).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"op\":\"indent\",\"progress\":100,\"bytes\":20,\"total\":20}\n"
+        );
     }
 
     #[test]
-    fn test_bytewise_toggle_roundtrip() {
-        let original = "code\n";
-        let test_file = create_test_file("test_bytewise_roundtrip.rs", original);
+    fn test_trace_line_formats() {
+        let mut text: Vec<u8> = Vec::new();
+        write_trace_line(
+            &mut text,
+            TraceFormat::Text,
+            "indent",
+            TraceStage::Verify,
+            false,
+            7,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "toggle_comment trace: op=indent stage=verify status=failed micros=7\n"
+        );
 
-        // Toggle on
-        let result1 = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result1.is_ok());
+        let mut json: Vec<u8> = Vec::new();
+        write_trace_line(
+            &mut json,
+            TraceFormat::Json,
+            "batch",
+            TraceStage::TempWrite,
+            true,
+            12,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"op\":\"batch\",\"stage\":\"temp_write\",\"status\":\"ok\",\"micros\":12}\n"
+        );
 
-        let content1 = read_file_content(&test_file);
-        assert_eq!(content1, "// code\n");
+        // TempWrite with its file read / write time
+        let io = Some(IoTimes {
+            read_nanos: 5_999,
+            write_nanos: 2_000,
+        });
+        let mut text: Vec<u8> = Vec::new();
+        write_trace_line(
+            &mut text,
+            TraceFormat::Text,
+            "batch",
+            TraceStage::TempWrite,
+            true,
+            12,
+            io,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "toggle_comment trace: op=batch stage=temp_write status=ok micros=12 read_micros=5 write_micros=2\n"
+        );
+        let mut json: Vec<u8> = Vec::new();
+        write_trace_line(
+            &mut json,
+            TraceFormat::Json,
+            "batch",
+            TraceStage::TempWrite,
+            true,
+            12,
+            io,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"op\":\"batch\",\"stage\":\"temp_write\",\"status\":\"ok\",\"micros\":12,\"read_micros\":5,\"write_micros\":2}\n"
+        );
 
-        // Toggle off
-        let result2 = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result2.is_ok());
+        let mut off: Vec<u8> = Vec::new();
+        write_trace_line(
+            &mut off,
+            TraceFormat::Off,
+            "batch",
+            TraceStage::Backup,
+            true,
+            1,
+            None,
+        )
+        .unwrap();
+        assert!(off.is_empty());
+    }
 
-        let content2 = read_file_content(&test_file);
-        assert_eq!(content2, original);
+    #[test]
+    fn test_traced_operation_unchanged() {
+        let test_file = create_test_file("test_stage_trace.py", "x = 1\n");
+        let path = test_file.to_str().unwrap();
+        let options = ToggleOptions {
+            trace: TraceFormat::Json,
+            ..ToggleOptions::default()
+        };
+
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 0, &options).is_ok());
+        assert!(indent_line_bytewise_with_options(path, 0, &options).is_ok());
+        assert_eq!(read_file_content(&test_file), "    # x = 1\n");
+
+        let trace = StageTrace::new(&options, "test");
+        assert_eq!(trace.run(TraceStage::Replace, || Err::<(), u8>(3)), Err(3));
+        assert!(!trace.check(TraceStage::Verify, || false));
 
         cleanup_files(&[
             &test_file,
-            &PathBuf::from("backup_toggle_comment_test_bytewise_roundtrip.rs"),
+            &PathBuf::from("backup_toggle_comment_test_stage_trace.py"),
         ]);
     }
+}
 
-    #[test]
-    fn test_bytewise_toggle_middle_line() {
-        let content = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_bytewise_middle.rs", content);
+// ============================================================================
+// PLATFORM PATH HELPERS (Windows quirks)
+// ============================================================================
+//
+// Two Windows-only problems, handled in one place so every operation agrees:
+//
+// 1. `canonicalize()` returns verbatim paths (`\\?\C:\src\main.rs`,
+//    `\\?\UNC\server\share\x.py`). They work for file I/O, but are ugly and
+//    confusing in messages. `display_path()` strips the prefix for display.
+//
+// 2. Backup and temp files are named from the source filename. Windows
+//    reserves device names (CON, PRN, AUX, NUL, COM1-9, LPT1-9; any
+//    extension, any case) and rejects `< > : " / \ | ? *`, control bytes, and
+//    trailing dots/spaces. `safe_name_component()` sanitizes the filename
+//    before it is used in a backup/temp name.
+//
+// On other platforms both helpers are identity operations: generated names
+// stay exactly `backup_toggle_comment_{filename}`.
+//
+// The sanitizing functions themselves are plain string functions, compiled
+// and tested on every platform.
+// ============================================================================
 
-        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 1);
-        assert!(result.is_ok());
+/// Device names reserved by Windows, regardless of extension or case
+const WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "line 0\n// line 1\nline 2\n");
+/// Strip a Windows verbatim prefix from a path string
+///
+/// # Overview
+/// - `\\?\UNC\server\share\x` → `\\server\share\x`
+/// - `\\?\C:\x` → `C:\x`
+/// - anything else is returned unchanged
+fn strip_verbatim_prefix(path_str: &str) -> String {
+    if let Some(rest) = path_str.strip_prefix(r"\\?\UNC\") {
+        return format!(r"\\{}", rest);
+    }
+    if let Some(rest) = path_str.strip_prefix(r"\\?\") {
+        return rest.to_string();
+    }
+    path_str.to_string()
+}
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_bytewise_middle.rs"),
-        ]);
+/// Check whether a filename is a Windows reserved device name
+///
+/// # Overview
+/// Windows treats `aux`, `AUX.rs`, `aux.tar.gz` (and `aux .rs`) alike:
+/// the part before the first dot, with trailing spaces ignored, is compared
+/// case-insensitively against the reserved list.
+fn is_windows_reserved_name(name: &str) -> bool {
+    let stem = match name.find('.') {
+        Some(dot) => &name[..dot],
+        None => name,
+    };
+    let stem = stem.trim_end_matches(' ');
+
+    WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+/// Sanitize a filename for use inside a generated name on Windows
+///
+/// # Overview
+/// - Characters Windows rejects (`< > : " / \ | ? *`, control chars) → `_`
+/// - Trailing dots and spaces → removed
+/// - Reserved device stem (`aux.rs`) → `_` appended to stem (`aux_.rs`)
+/// - Empty result → `_`
+fn sanitize_windows_name_component(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    while sanitized.ends_with('.') || sanitized.ends_with(' ') {
+        sanitized.pop();
     }
 
-    #[test]
-    fn test_bytewise_toggle_last_line() {
-        let content = "line 0\nline 1\nline 2";
-        let test_file = create_test_file("test_bytewise_last.rs", content);
+    if is_windows_reserved_name(&sanitized) {
+        let stem_end = sanitized.find('.').unwrap_or(sanitized.len());
+        sanitized.insert(stem_end, '_');
+    }
 
-        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 2);
-        assert!(result.is_ok());
+    if sanitized.is_empty() {
+        sanitized.push('_');
+    }
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "line 0\nline 1\n// line 2");
+    sanitized
+}
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_bytewise_last.rs"),
-        ]);
+/// Path as shown to users (verbatim prefix stripped on Windows)
+///
+/// # Arguments
+/// * `path` - Any path, canonicalized or as typed by the user
+///
+/// # Returns
+/// * Display string; on non-Windows platforms identical to `path.display()`
+pub fn display_path(path: &Path) -> String {
+    let path_str = path.to_string_lossy();
+    if cfg!(windows) {
+        strip_verbatim_prefix(&path_str)
+    } else {
+        path_str.to_string()
+    }
+}
+
+/// Filename component to embed in backup and temp file names
+///
+/// # Arguments
+/// * `name` - Source filename (from `Path::file_name()`)
+///
+/// # Returns
+/// * Sanitized name on Windows, lossy-UTF-8 name unchanged elsewhere
+pub(crate) fn safe_name_component(name: &std::ffi::OsStr) -> String {
+    let name_str = name.to_string_lossy();
+    if cfg!(windows) {
+        sanitize_windows_name_component(&name_str)
+    } else {
+        name_str.to_string()
     }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod platform_path_tests {
+    use super::*;
 
     #[test]
-    fn test_bytewise_toggle_python() {
-        let content = "print('hello')\n";
-        let test_file = create_test_file("test_bytewise_python.py", content);
+    fn test_strip_verbatim_prefix() {
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\C:\src\main.rs"),
+            r"C:\src\main.rs"
+        );
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\UNC\server\share\x.py"),
+            r"\\server\share\x.py"
+        );
+        assert_eq!(strip_verbatim_prefix("/home/user/x.py"), "/home/user/x.py");
+    }
 
-        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+    #[test]
+    fn test_windows_reserved_names() {
+        assert!(is_windows_reserved_name("aux"));
+        assert!(is_windows_reserved_name("AUX.rs"));
+        assert!(is_windows_reserved_name("Com1.tar.gz"));
+        assert!(is_windows_reserved_name("nul .py"));
+        assert!(!is_windows_reserved_name("auxiliary.rs"));
+        assert!(!is_windows_reserved_name("com10.rs"));
+        assert!(!is_windows_reserved_name("main.rs"));
+    }
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "# print('hello')\n");
+    #[test]
+    fn test_sanitize_windows_name_component() {
+        assert_eq!(sanitize_windows_name_component("main.rs"), "main.rs");
+        assert_eq!(sanitize_windows_name_component("aux.rs"), "aux_.rs");
+        assert_eq!(sanitize_windows_name_component("CON"), "CON_");
+        assert_eq!(sanitize_windows_name_component("a:b?.py"), "a_b_.py");
+        assert_eq!(sanitize_windows_name_component("x.py. "), "x.py");
+        assert_eq!(sanitize_windows_name_component("..."), "_");
+    }
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_bytewise_python.py"),
-        ]);
+    #[cfg(not(windows))]
+    #[test]
+    fn test_non_windows_helpers_are_identity() {
+        let path = Path::new("/tmp/aux.rs");
+        assert_eq!(display_path(path), "/tmp/aux.rs");
+        assert_eq!(
+            safe_name_component(std::ffi::OsStr::new("aux.rs")),
+            "aux.rs"
+        );
     }
 
+    #[cfg(windows)]
     #[test]
-    fn test_bytewise_line_not_found_error() {
-        let content = "line 0\nline 1\n";
-        let test_file = create_test_file("test_bytewise_notfound_err.rs", content);
+    fn test_windows_canonical_display_and_names() {
+        let test_file = PathBuf::from("test_platform_display.rs");
+        std::fs::write(&test_file, "x\n").unwrap();
 
-        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 10);
-        assert!(matches!(
-            result,
-            Err(ToggleCommentError::LineNotFound { .. })
-        ));
+        let canonical = test_file.canonicalize().unwrap();
+        assert!(!display_path(&canonical).starts_with(r"\\?\"));
+        assert_eq!(
+            safe_name_component(std::ffi::OsStr::new("aux.rs")),
+            "aux_.rs"
+        );
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_bytewise_notfound_err.rs"),
-        ]);
+        let _ = std::fs::remove_file(&test_file);
     }
 }
 
 // ============================================================================
-// TESTS
+// LIMITS (introspection for wrappers)
+// ============================================================================
+//
+// `limits()` returns the bounds compiled into this build, so wrappers can
+// check a request before running it instead of keeping copies of the
+// constants (CLI: --limits). There is no separate scan window limit, and
+// no line length limit outside joins, splits, style conversions and given
+// line content (MAX_LINE_LENGTH, MAX_LINE_CONTENT_BYTES): lines are streamed
+// byte by byte, and every scan or copy loop is bounded by MAX_BYTE_ITERATIONS.
 // ============================================================================
-#[cfg(test)]
-/// Helper: create a temporary test file with given content
-fn create_test_file(filename: &str, content: &str) -> PathBuf {
-    use std::io::Write;
-    // Create tests directory if it doesn't exist
-    let tests_dir = PathBuf::from("./tests");
-    std::fs::create_dir_all(&tests_dir).expect("Failed to create tests directory");
 
-    // Create file path within tests directory
-    let path = tests_dir.join(filename);
-    let mut file = File::create(&path).expect("Failed to create test file");
-    file.write_all(content.as_bytes())
-        .expect("Failed to write test file");
-    path
-}
-#[cfg(test)]
-/// Helper: read file content as string
-fn read_file_content(path: &Path) -> String {
-    std::fs::read_to_string(path).expect("Failed to read file")
-}
-#[cfg(test)]
-/// Helper: cleanup test files
-fn cleanup_files(paths: &[&Path]) {
-    for path in paths {
-        let _ = std::fs::remove_file(path);
-    }
-}
+/// Bounds compiled into this build (see `limits()`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Line numbers toggled in one pass through the file (MAX_BATCH_LINES)
+    pub max_batch_lines: usize,
 
-// =================
-// Indent / Unindent
-// =================
-/*
+    /// Line numbers in one batch operation, all passes (MAX_TOTAL_BATCH_LINES)
+    pub max_total_batch_lines: usize,
 
-The standard
-indent/unindent functionality
--- ctrl+brackets is standard --
-is simpliar to but simpler than
-comment-toggling
--- ctrl + / is standard --
+    /// Ranges in one multi-range toggle (MAX_RANGES)
+    pub max_ranges: usize,
 
-Two functionalities:
-- single line
-- range of lines
+    /// Default bound on line numbers read from a stream (DEFAULT_MAX_STREAMED_LINES)
+    pub default_max_streamed_lines: usize,
 
- */
-// ============================================================================
-// INDENT/UNINDENT FUNCTIONALITY
-// ============================================================================
+    /// Longest search text for `find_line_containing()` (MAX_SEARCH_TEXT_BYTES)
+    pub max_search_text_bytes: usize,
 
-/// Number of spaces to add/remove for indent/unindent operations
-const INDENT_SPACES: usize = 4;
+    /// Default `ToggleOptions::max_file_size` (DEFAULT_MAX_FILE_SIZE_BYTES)
+    pub default_max_file_size_bytes: u64,
 
-// ============================================================================
-// ERROR SECTION: ERROR HANDLING SYSTEM (start)
-// ============================================================================
+    /// Bytes one scan or copy loop reads at most, so also the longest line
+    /// (MAX_BYTE_ITERATIONS)
+    pub max_byte_iterations: u64,
 
-/// Errors that can occur during indent/unindent operations
-///
-/// All variants are Copy - no heap allocation, no string storage.
-/// Reuses same design pattern as ToggleCommentError for consistency.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ToggleIndentError {
-    /// The specified file was not found
-    FileNotFound,
+    /// Buffer size of buffered file I/O (IO_BUFFER_SIZE)
+    pub io_buffer_size: usize,
 
-    /// The requested line index exceeds the file's line count
-    LineNotFound { requested: usize, file_lines: usize },
+    /// Spaces after the flag that `RemovalMode::Relaxed` removes (MAX_RELAXED_SPACES)
+    pub max_relaxed_spaces: usize,
 
-    /// I/O operation failed
-    IoError(IoOperation),
+    /// Files one backup session records (MAX_SESSION_FILES)
+    pub max_session_files: usize,
 
-    /// Path conversion or manipulation error
-    PathError,
+    /// Widest `ToggleOptions::indent_width` a config may set (MAX_INDENT_WIDTH)
+    pub max_indent_width: usize,
 }
 
-impl std::fmt::Display for ToggleIndentError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ToggleIndentError::FileNotFound => write!(f, "File not found"),
-            ToggleIndentError::LineNotFound {
-                requested,
-                file_lines,
-            } => {
-                write!(
-                    f,
-                    "Line {} not found (file has {} lines)",
-                    requested, file_lines
-                )
-            }
-            ToggleIndentError::IoError(op) => write!(f, "IO error: {:?}", op),
-            ToggleIndentError::PathError => write!(f, "Path error"),
-        }
+impl Limits {
+    /// `(name, value)` of every field, in declaration order (CLI output keys)
+    pub fn entries(&self) -> [(&'static str, u64); 11] {
+        [
+            ("max_batch_lines", self.max_batch_lines as u64),
+            ("max_total_batch_lines", self.max_total_batch_lines as u64),
+            ("max_ranges", self.max_ranges as u64),
+            (
+                "default_max_streamed_lines",
+                self.default_max_streamed_lines as u64,
+            ),
+            ("max_search_text_bytes", self.max_search_text_bytes as u64),
+            (
+                "default_max_file_size_bytes",
+                self.default_max_file_size_bytes,
+            ),
+            ("max_byte_iterations", self.max_byte_iterations),
+            ("io_buffer_size", self.io_buffer_size as u64),
+            ("max_relaxed_spaces", self.max_relaxed_spaces as u64),
+            ("max_session_files", self.max_session_files as u64),
+            ("max_indent_width", self.max_indent_width as u64),
+        ]
     }
 }
 
-impl std::error::Error for ToggleIndentError {}
+/// Bounds compiled into this build
+///
+/// # Example
+/// ```rust
+/// use toggle_comment_indent::toggle_comment_indent_module::limits;
+///
+/// let lines: Vec<usize> = (0..10).collect();
+/// assert!(lines.len() <= limits().max_total_batch_lines);
+/// ```
+pub const fn limits() -> Limits {
+    Limits {
+        max_batch_lines: MAX_BATCH_LINES,
+        max_total_batch_lines: MAX_TOTAL_BATCH_LINES,
+        max_ranges: MAX_RANGES,
+        default_max_streamed_lines: DEFAULT_MAX_STREAMED_LINES,
+        max_search_text_bytes: MAX_SEARCH_TEXT_BYTES,
+        default_max_file_size_bytes: DEFAULT_MAX_FILE_SIZE_BYTES,
+        max_byte_iterations: MAX_BYTE_ITERATIONS,
+        io_buffer_size: IO_BUFFER_SIZE,
+        max_relaxed_spaces: MAX_RELAXED_SPACES,
+        max_session_files: MAX_SESSION_FILES,
+        max_indent_width: MAX_INDENT_WIDTH,
+    }
+}
 
 // ============================================================================
-// ERROR SECTION: ERROR HANDLING SYSTEM (end)
-// ============================================================================
-
+// CANCELLATION (interrupt without leaving temp files behind)
 // ============================================================================
-// PHASE 2: INDENT/UNINDENT - BYTEWISE OPERATIONS
+//
+// `request_cancel()` sets one process-wide flag; it is only an atomic store,
+// so a signal handler may call it (the CLI installs one for SIGINT / SIGTERM).
+// Every bounded byte loop checks the flag and returns `Cancelled`, and
+// `replace_original()` checks it once more, so an operation stopped at any
+// point leaves the original untouched. Temp files are removed by
+// `TempFileGuard` on every return path, including early `?` errors.
+//
+// `set_deadline()` adds a time budget on the same path: once the monotonic
+// clock passes it, `cancel_requested()` reports true (the clock is read every
+// DEADLINE_POLL_INTERVAL polls, not per byte) and `timed_out()` tells the
+// caller why. Unlike a cancel, the next `set_deadline()` clears it.
 // ============================================================================
 
-/// Helper: Find line start position (extracted for reuse)
-///
-/// # Overview
-/// Reads file byte-by-byte counting newlines until target line is found.
-/// Returns byte offset where target line begins.
+/// Set by `request_cancel()`; never cleared by the library
+static CANCEL_REQUESTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Ask running and later operations to stop before replacing any file
 ///
-/// # Arguments
-/// * `file_path` - Path to source file
-/// * `target_line` - Zero-indexed line number to find
+/// Safe to call from a signal handler (a single atomic swap).
 ///
 /// # Returns
-/// * `Ok(Some(position))` - Line found at byte offset
-/// * `Ok(None)` - Line not found (file has fewer lines)
-/// * `Err(ToggleIndentError)` - File operation failed
-///
-/// # Example
-/// ```text
-/// File:  "line 0\nline 1\nline 2\n"
-///
-/// find_line_start_position(path, 0) → Ok(Some(0))
-/// find_line_start_position(path, 1) → Ok(Some(7))
-/// find_line_start_position(path, 2) → Ok(Some(14))
-/// find_line_start_position(path, 10) → Ok(None)
-/// ```
-pub fn find_line_start_position(
-    file_path: &str,
-    target_line: usize,
-) -> Result<Option<u64>, ToggleIndentError> {
-    let mut file = match File::open(file_path) {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Open)),
-    };
+/// * `true` - Cancellation had already been requested (e.g. a second Ctrl-C)
+pub fn request_cancel() -> bool {
+    CANCEL_REQUESTED.swap(true, std::sync::atomic::Ordering::SeqCst)
+}
 
-    // Special case: line 0 always starts at position 0
-    if target_line == 0 {
-        return Ok(Some(0));
+/// Whether `request_cancel()` has been called or the deadline has passed
+pub fn cancel_requested() -> bool {
+    if CANCEL_REQUESTED.load(std::sync::atomic::Ordering::Relaxed) {
+        return true;
     }
+    DEADLINE_ARMED.load(std::sync::atomic::Ordering::Relaxed) && deadline_passed()
+}
 
-    let mut byte_bucket: [u8; 1] = [0u8; 1];
-    let mut byte_position: u64 = 0;
-    let mut current_line: usize = 0;
-
-    loop {
-        // Safety check
-        if byte_position >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleIndentError::IoError(IoOperation::Read));
-        }
+/// Polls of `cancel_requested()` between two reads of the clock
+const DEADLINE_POLL_INTERVAL: usize = 4096;
 
-        let bytes_read = match file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
-        };
+/// Deadline of `set_deadline()`; read only while DEADLINE_ARMED
+static DEADLINE: std::sync::Mutex<Option<std::time::Instant>> = std::sync::Mutex::new(None);
 
-        if bytes_read == 0 {
-            return Ok(None); // Line not found
-        }
+/// Whether a deadline is set (keeps the poll a single load otherwise)
+static DEADLINE_ARMED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
 
-        if byte_bucket[0] == b'\n' {
-            current_line += 1;
-            if current_line == target_line {
-                return Ok(Some(byte_position + 1));
-            }
-        }
+/// Polls since the deadline was set
+static DEADLINE_POLLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
 
-        byte_position += 1;
-    }
-}
+/// Set once the deadline has passed; cleared by `set_deadline()`
+static TIMED_OUT: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
 
-/// Write file with 4 spaces added at start of target line
+/// Give running and later operations a time budget (CLI: --timeout)
 ///
-/// # Overview
-/// Copies source to dest, inserting 4 spaces at column 0 of target line.
-/// Simple three-part copy: before, modified line, after.
+/// When it runs out they stop as if cancelled (`Cancelled`, temp file
+/// removed, original untouched) and `timed_out()` returns true.
 ///
 /// # Arguments
-/// * `source_path` - Original file
-/// * `dest_path` - Temp file for output
-/// * `line_start_pos` - Byte offset where target line begins
-///
-/// # Returns
-/// * `Ok(())` - File written successfully
-/// * `Err(ToggleIndentError)` - Write operation failed
-///
-/// # Memory
-/// - 1 byte buffer only
-/// - No heap allocation
-pub fn write_indented_file_bytewise(
-    source_path: &Path,
-    dest_path: &Path,
-    line_start_pos: u64,
-) -> Result<(), ToggleIndentError> {
-    let mut source_file = match File::open(source_path) {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Open)),
-    };
+/// * `budget` - Time from now; `None` removes the deadline
+pub fn set_deadline(budget: Option<std::time::Duration>) {
+    let deadline = budget.and_then(|b| std::time::Instant::now().checked_add(b));
+    if let Ok(mut slot) = DEADLINE.lock() {
+        *slot = deadline;
+    }
+    TIMED_OUT.store(false, std::sync::atomic::Ordering::SeqCst);
+    DEADLINE_POLLS.store(0, std::sync::atomic::Ordering::SeqCst);
+    DEADLINE_ARMED.store(deadline.is_some(), std::sync::atomic::Ordering::SeqCst);
+}
 
-    let dest_file = match OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(dest_path)
-    {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Create)),
+/// Whether the `set_deadline()` budget ran out
+pub fn timed_out() -> bool {
+    TIMED_OUT.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Check the clock every DEADLINE_POLL_INTERVAL polls; true once it passed
+fn deadline_passed() -> bool {
+    if TIMED_OUT.load(std::sync::atomic::Ordering::Relaxed) {
+        return true;
+    }
+    let polls = DEADLINE_POLLS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    if !polls.is_multiple_of(DEADLINE_POLL_INTERVAL) {
+        return false;
+    }
+    let expired = match DEADLINE.lock() {
+        Ok(slot) => slot.is_some_and(|deadline| std::time::Instant::now() >= deadline),
+        Err(_) => false,
     };
+    if expired {
+        TIMED_OUT.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+    expired
+}
 
-    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, dest_file);
-    let mut byte_bucket: [u8; 1] = [0u8; 1];
-    let mut byte_position: u64 = 0;
+/// io::Error returned by `replace_original()` once cancellation is requested
+#[cfg(feature = "fs")]
+fn cancel_error() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Interrupted, "cancelled")
+}
 
-    // PART A: Copy before target line
-    while byte_position < line_start_pos {
-        if byte_position >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleIndentError::IoError(IoOperation::Read));
-        }
+/// Whether an io::Error is the cancellation from `replace_original()`
+fn is_cancel_error(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::Interrupted && cancel_requested()
+}
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
-        };
+/// Removes a temp file when dropped (after success it is already gone)
+#[cfg(feature = "fs")]
+pub(crate) struct TempFileGuard<'a>(pub(crate) &'a Path);
 
-        if bytes_read == 0 {
-            return Err(ToggleIndentError::IoError(IoOperation::Read));
+#[cfg(feature = "fs")]
+impl Drop for TempFileGuard<'_> {
+    fn drop(&mut self) {
+        if remove_temp_file(self.0).is_err() {
+            record_warning(Warning::TempCleanupFailed {
+                temp: self.0.to_string_lossy().into_owned(),
+            });
         }
+    }
+}
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleIndentError::IoError(IoOperation::Write));
+#[cfg(all(test, feature = "fs"))]
+mod cancellation_tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_file_guard_removes_on_early_return() {
+        fn fails_midway(temp_path: &Path) -> Result<(), ToggleCommentError> {
+            let _remove_temp = TempFileGuard(temp_path);
+            std::fs::write(temp_path, "partial").unwrap();
+            Err(ToggleCommentError::IoError(IoOperation::Write))
         }
 
-        byte_position += 1;
-    }
+        let temp_path = PathBuf::from(format!("temp_guard_test_{}.rs", std::process::id()));
+        assert!(fails_midway(&temp_path).is_err());
+        assert!(!temp_path.exists());
 
-    // PART B: Add 4 spaces at column 0, then copy rest of line
-    if let Err(_) = writer.write_all(b"    ") {
-        return Err(ToggleIndentError::IoError(IoOperation::Write));
+        // Nothing to remove is fine too
+        drop(TempFileGuard(&temp_path));
     }
 
-    // Copy rest of line
-    loop {
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
-        };
+    #[test]
+    fn test_cancel_error_needs_request() {
+        // An OS-level Interrupted is only a cancellation once one was requested
+        // (the flag is process-wide, so tests never set it)
+        let interrupted = std::io::Error::new(std::io::ErrorKind::Interrupted, "eintr");
+        assert!(!cancel_requested());
+        assert_eq!(
+            ToggleCommentError::from_io_error(&interrupted, IoOperation::Replace),
+            ToggleCommentError::IoError(IoOperation::Replace)
+        );
+    }
 
-        if bytes_read == 0 {
-            break; // EOF
+    #[test]
+    fn test_deadline_not_reached() {
+        // A budget far in the future never trips (an expiring one would
+        // cancel the other tests: the deadline is process-wide)
+        set_deadline(Some(std::time::Duration::from_secs(3600)));
+        for _ in 0..(2 * DEADLINE_POLL_INTERVAL) {
+            assert!(!cancel_requested());
         }
+        assert!(!timed_out());
+        set_deadline(None);
+        assert!(!cancel_requested());
+    }
+}
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleIndentError::IoError(IoOperation::Write));
-        }
+// ============================================================================
+// REPLACE SAFETY CHECK (partial-write detection)
+// ============================================================================
+//
+// The original is replaced by copying a finished temp file over it. If the
+// disk fills up while the temp is written, some platforms still report a
+// successful flush, and a truncated temp would then replace the original.
+//
+// Each operation knows how much it can change the file size (a line toggle
+// adds or removes at most "<!-- " + " -->"), so before every replace the temp size is
+// checked against the original size ± that bound. `replace_original()` then
+// fsyncs the temp as `ToggleOptions::durability` says (by default, always)
+// before it replaces anything.
+//
+// The Backup stage also records the original's size, modification time and
+// (Unix) inode as a `FileSnapshot`; `replace_original()` compares the file
+// against it first and refuses with `ConcurrentModification` when it differs,
+// so a save made by someone else while the temp was written is never
+// overwritten. Each replace returns the new snapshot for a following one.
+// ============================================================================
 
-        if byte_bucket[0] == b'\n' {
-            break; // End of line
-        }
+/// Size, modification time and (Unix) inode of a file at one moment
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileSnapshot {
+    len: u64,
+    modified: Option<std::time::SystemTime>,
+    inode: u64,
+}
+
+#[cfg(feature = "fs")]
+impl FileSnapshot {
+    /// Snapshot of `path` now
+    pub(crate) fn of(path: &Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Ok(FileSnapshot {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            inode,
+        })
     }
 
-    // PART C: Copy rest of file
-    let mut remaining_bytes: u64 = 0;
-    loop {
-        if remaining_bytes >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleIndentError::IoError(IoOperation::Read));
+    /// `Ok` if `path` still matches the snapshot, else the
+    /// ConcurrentModification io::Error
+    fn check(&self, path: &Path) -> std::io::Result<()> {
+        if FileSnapshot::of(path)? == *self {
+            Ok(())
+        } else {
+            Err(std::io::Error::other(ConcurrentModification))
         }
+    }
+}
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
-        };
-
-        if bytes_read == 0 {
-            break;
-        }
+/// Payload of the io::Error for a file that changed under an operation
+#[derive(Debug)]
+struct ConcurrentModification;
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleIndentError::IoError(IoOperation::Write));
+impl std::fmt::Display for ConcurrentModification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "file changed on disk")
+    }
+}
+
+impl std::error::Error for ConcurrentModification {}
+
+/// Whether an io::Error is a `FileSnapshot::check()` mismatch
+fn is_concurrent_modification(error: &std::io::Error) -> bool {
+    error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<ConcurrentModification>())
+}
+
+/// Where an operation's temp file goes
+///
+/// # Arguments
+/// * `temp_dir` - `ToggleOptions::temp_dir`
+/// * `name` - Temp file name (`temp_<op>_<pid>_<filename>`)
+///
+/// # Returns
+/// * `temp_dir/name`, or `name` in the working directory
+#[cfg(feature = "fs")]
+pub(crate) fn temp_file_path(temp_dir: Option<&Path>, name: &str) -> PathBuf {
+    match temp_dir {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+/// Replace the original with a finished (size-checked) temp file
+///
+/// # Overview
+/// - No temp dir: the temp is copied over the original in place (the
+///   original's inode, permissions and links are kept), as always
+/// - Temp dir: the temp gets the original's permissions and is renamed
+///   over it; when that fails (e.g. tmpfs and target on different
+///   filesystems), it is first copied to `.temp_toggle_replace_<pid>_<name>`
+///   in the target's directory and renamed from there, so the target is
+///   still swapped in one step
+///
+/// # Arguments
+/// * `durability` - `ToggleOptions::durability`: whether the temp is synced
+///   first and the replaced target (or its directory) after
+/// * `expected` - Snapshot of the original from the backup (or the previous
+///   replace); a different file on disk is not overwritten
+///
+/// # Returns
+/// * `Ok(snapshot)` - Original replaced; snapshot of the new content
+/// * `Err(io::Error)` - Cancelled, original changed since `expected`, or
+///   sync, copy, permission or rename failed (a staged copy is removed; the
+///   original is untouched unless only the final sync failed)
+#[cfg(feature = "fs")]
+pub(crate) fn replace_original(
+    temp_path: &Path,
+    original_path: &Path,
+    temp_dir: Option<&Path>,
+    durability: Durability,
+    expected: &FileSnapshot,
+) -> std::io::Result<FileSnapshot> {
+    if cancel_requested() {
+        return Err(cancel_error());
+    }
+    expected.check(original_path)?;
+    // fsync: flush alone may succeed while data is still only in OS buffers
+    if durability != Durability::None {
+        sync_file(temp_path)?;
+    }
+    if temp_dir.is_none() {
+        std::fs::copy(temp_path, original_path)?;
+        if durability == Durability::Fsync {
+            sync_file(original_path)?;
         }
+        return FileSnapshot::of(original_path);
+    }
 
-        remaining_bytes += 1;
+    let permissions = std::fs::metadata(original_path)?.permissions();
+    std::fs::set_permissions(temp_path, permissions.clone())?;
+    if std::fs::rename(temp_path, original_path).is_ok() {
+        if durability == Durability::Fsync {
+            sync_parent_dir(original_path)?;
+        }
+        return FileSnapshot::of(original_path);
     }
 
-    if let Err(_) = writer.flush() {
-        return Err(ToggleIndentError::IoError(IoOperation::Flush));
+    // Different filesystem: stage next to the target, then rename
+    let (parent, name) = match (original_path.parent(), original_path.file_name()) {
+        (Some(parent), Some(name)) => (parent, safe_name_component(name)),
+        _ => return Err(std::io::Error::other("target has no parent directory")),
+    };
+    let staged_path = parent.join(format!(
+        ".temp_toggle_replace_{}_{}",
+        std::process::id(),
+        name
+    ));
+    let staged = std::fs::copy(temp_path, &staged_path)
+        .and_then(|_| match durability {
+            Durability::None => Ok(()),
+            _ => sync_file(&staged_path),
+        })
+        .and_then(|_| std::fs::set_permissions(&staged_path, permissions))
+        .and_then(|_| std::fs::rename(&staged_path, original_path));
+    if let Err(e) = staged {
+        let _ = std::fs::remove_file(&staged_path);
+        return Err(e);
+    }
+    let _ = std::fs::remove_file(temp_path);
+    if durability == Durability::Fsync {
+        sync_parent_dir(original_path)?;
     }
+    FileSnapshot::of(original_path)
+}
+
+/// fsync a file's data and metadata
+#[cfg(feature = "fs")]
+fn sync_file(path: &Path) -> std::io::Result<()> {
+    OpenOptions::new().write(true).open(path)?.sync_all()
+}
 
+/// fsync the directory holding `path`, so a rename into it is on disk
+/// (Unix; elsewhere directories cannot be opened this way and this is a no-op)
+#[cfg(feature = "fs")]
+fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all()?,
+        _ => File::open(".")?.sync_all()?,
+    }
+    #[cfg(not(unix))]
+    let _ = path;
     Ok(())
 }
 
-/// Write file with up to 4 spaces removed from start of target line
-///
-/// # Overview
-/// Copies source to dest, removing up to 4 spaces from column 0 of target line.
-/// If line has fewer than 4 spaces, removes only what's there.
+/// Remove a temp file after the replace (one renamed over the original is
+/// already gone, which counts as removed)
+#[cfg(feature = "fs")]
+pub(crate) fn remove_temp_file(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+/// Largest size change one line toggle can make: "<!-- " prefix + " -->" suffix
+const MAX_TOGGLE_EDIT_BYTES: u64 = 9;
+
+/// Check that a finished temp file is plausibly complete before replacing
 ///
 /// # Arguments
-/// * `source_path` - Original file
-/// * `dest_path` - Temp file for output
-/// * `line_start_pos` - Byte offset where target line begins
+/// * `original_path` - File about to be replaced
+/// * `temp_path` - Finished output of the operation
+/// * `max_shrink` - Most bytes the operation can remove
+/// * `max_growth` - Most bytes the operation can add
 ///
 /// # Returns
-/// * `Ok(())` - File written successfully
-/// * `Err(ToggleIndentError)` - Write operation failed
-///
-/// # Memory
-/// - 1 byte buffer only
-/// - No heap allocation
-pub fn write_unindented_file_bytewise(
-    source_path: &Path,
-    dest_path: &Path,
-    line_start_pos: u64,
-) -> Result<(), ToggleIndentError> {
-    let mut source_file = match File::open(source_path) {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Open)),
+/// * `true` - `original - max_shrink <= temp <= original + max_growth`
+/// * `false` - Outside bounds, or sizes could not be read
+#[cfg(feature = "fs")]
+pub(crate) fn replacement_size_is_plausible(
+    original_path: &Path,
+    temp_path: &Path,
+    max_shrink: u64,
+    max_growth: u64,
+) -> bool {
+    let original_len = match std::fs::metadata(original_path) {
+        Ok(m) => m.len(),
+        Err(_) => return false,
     };
-
-    let dest_file = match OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(dest_path)
-    {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Create)),
+    let temp_len = match std::fs::metadata(temp_path) {
+        Ok(m) => m.len(),
+        Err(_) => return false,
     };
 
-    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, dest_file);
-    let mut byte_bucket: [u8; 1] = [0u8; 1];
-    let mut byte_position: u64 = 0;
+    temp_len >= original_len.saturating_sub(max_shrink)
+        && temp_len <= original_len.saturating_add(max_growth)
+}
 
-    // PART A: Copy before target line
-    while byte_position < line_start_pos {
-        if byte_position >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleIndentError::IoError(IoOperation::Read));
-        }
+#[cfg(all(test, feature = "fs"))]
+mod replace_safety_tests {
+    use super::*;
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
-        };
+    #[test]
+    fn test_replacement_size_bounds() {
+        let original = PathBuf::from("test_replace_safety_original.rs");
+        let temp = PathBuf::from("test_replace_safety_temp.rs");
+        std::fs::write(&original, "0123456789").unwrap();
 
-        if bytes_read == 0 {
-            return Err(ToggleIndentError::IoError(IoOperation::Read));
-        }
+        // Within ± bound
+        std::fs::write(&temp, "// 0123456789").unwrap();
+        assert!(replacement_size_is_plausible(&original, &temp, 4, 4));
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleIndentError::IoError(IoOperation::Write));
-        }
+        // Truncated temp (e.g. disk full)
+        std::fs::write(&temp, "0123").unwrap();
+        assert!(!replacement_size_is_plausible(&original, &temp, 4, 4));
 
-        byte_position += 1;
+        // Growth not allowed
+        std::fs::write(&temp, "0123456789x").unwrap();
+        assert!(!replacement_size_is_plausible(&original, &temp, 4, 0));
+
+        // Missing temp
+        let _ = std::fs::remove_file(&temp);
+        assert!(!replacement_size_is_plausible(&original, &temp, 4, 4));
+
+        let _ = std::fs::remove_file(&original);
     }
 
-    // PART B: Skip up to 4 spaces at column 0, then copy rest of line
-    let mut spaces_skipped: usize = 0;
-    loop {
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
+    #[test]
+    fn test_temp_dir_replace() {
+        let temp_dir = PathBuf::from("test_replace_safety_temp_dir");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir(&temp_dir).unwrap();
+        let test_file = PathBuf::from("test_replace_safety_temp_dir.py");
+        std::fs::write(&test_file, "a\nb\n").unwrap();
+        let options = ToggleOptions {
+            temp_dir: Some(temp_dir.clone()),
+            ..ToggleOptions::default()
         };
 
-        if bytes_read == 0 {
-            break; // EOF
-        }
-
-        // If it's a space and we haven't skipped 4 yet, skip it
-        if byte_bucket[0] == b' ' && spaces_skipped < INDENT_SPACES {
-            spaces_skipped += 1;
-            continue; // Skip this space, don't write it
-        }
+        toggle_basic_singleline_comment_bytewise_with_options(
+            "test_replace_safety_temp_dir.py",
+            1,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&test_file).unwrap(), "a\n# b\n");
+        // The temp was renamed over the target: nothing left behind
+        assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
 
-        // Not a space, or already skipped 4 - write rest of line
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleIndentError::IoError(IoOperation::Write));
-        }
+        assert_eq!(temp_file_path(None, "t"), PathBuf::from("t"));
+        assert_eq!(temp_file_path(Some(&temp_dir), "t"), temp_dir.join("t"));
+        assert!(remove_temp_file(&temp_dir.join("missing")).is_ok());
 
-        if byte_bucket[0] == b'\n' {
-            break; // End of line
-        }
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_replace_safety_temp_dir.py"),
+        ]);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
 
-        // Copy rest of line
-        loop {
-            let bytes_read = match source_file.read(&mut byte_bucket) {
-                Ok(n) => n,
-                Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
-            };
+    #[test]
+    fn test_concurrent_modification_refused() {
+        let original = PathBuf::from("test_replace_safety_concurrent.rs");
+        let backup = PathBuf::from("test_replace_safety_concurrent_backup.rs");
+        let temp = PathBuf::from("test_replace_safety_concurrent_temp.rs");
+        std::fs::write(&original, "a\n").unwrap();
+        let snapshot = create_backup(&original, &backup, false).unwrap();
+        std::fs::write(&temp, "// a\n").unwrap();
+
+        // Someone else saves between the backup and the replace
+        std::fs::write(&original, "a\nb\n").unwrap();
+        let e = replace_original(&temp, &original, None, Durability::Flush, &snapshot).unwrap_err();
+        assert_eq!(
+            ToggleCommentError::from_io_error(&e, IoOperation::Replace),
+            ToggleCommentError::ConcurrentModification
+        );
+        assert_eq!(std::fs::read_to_string(&original).unwrap(), "a\nb\n");
 
-            if bytes_read == 0 {
-                break; // EOF
-            }
+        // Unchanged since the snapshot: replaced, new snapshot for the next step
+        let current = FileSnapshot::of(&original).unwrap();
+        let replaced =
+            replace_original(&temp, &original, None, Durability::Flush, &current).unwrap();
+        assert_eq!(std::fs::read_to_string(&original).unwrap(), "// a\n");
+        assert_eq!(replaced, FileSnapshot::of(&original).unwrap());
 
-            if let Err(_) = writer.write_all(&byte_bucket) {
-                return Err(ToggleIndentError::IoError(IoOperation::Write));
-            }
+        cleanup_files(&[&original, &backup, &backup_checksum_path(&backup), &temp]);
+    }
 
-            if byte_bucket[0] == b'\n' {
-                break; // End of line
-            }
+    #[test]
+    fn test_replace_durability() {
+        let temp_dir = PathBuf::from("test_replace_safety_durability_dir");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir(&temp_dir).unwrap();
+        let original = PathBuf::from("test_replace_safety_durability.rs");
+        let temp = temp_dir.join("temp.rs");
+        std::fs::write(&original, "a\n").unwrap();
+
+        // Same result with every policy, copied in place or renamed
+        for (durability, dir) in [
+            (Durability::None, None),
+            (Durability::Flush, None),
+            (Durability::Fsync, None),
+            (Durability::None, Some(temp_dir.as_path())),
+            (Durability::Fsync, Some(temp_dir.as_path())),
+        ] {
+            std::fs::write(&temp, format!("{:?}\n", durability)).unwrap();
+            let current = FileSnapshot::of(&original).unwrap();
+            replace_original(&temp, &original, dir, durability, &current).unwrap();
+            assert_eq!(
+                std::fs::read_to_string(&original).unwrap(),
+                format!("{:?}\n", durability)
+            );
         }
 
-        break;
+        assert!(sync_parent_dir(&std::fs::canonicalize(&original).unwrap()).is_ok());
+        assert!(sync_parent_dir(&original).is_ok());
+        assert!(sync_file(&temp_dir.join("missing")).is_err());
+
+        cleanup_files(&[&original]);
+        let _ = std::fs::remove_dir_all(&temp_dir);
     }
+}
 
-    // PART C: Copy rest of file
-    let mut remaining_bytes: u64 = 0;
-    loop {
-        if remaining_bytes >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleIndentError::IoError(IoOperation::Read));
-        }
+// ============================================================================
+// BACKUP INTEGRITY
+// ============================================================================
+//
+// Every backup `backup_toggle_comment_{filename}` gets a sidecar
+// `backup_toggle_comment_{filename}.sha` holding a checksum of the original
+// it was copied from: `fnv1a64 <16 hex digits>` and a newline. FNV-1a is not
+// a cryptographic hash; it only has to notice a backup that was truncated,
+// edited, or left over from an earlier edit whose sidecar was rewritten.
+//
+// A second line `origin <absolute path>` records which file the backup was
+// taken from (left out for paths that are not UTF-8 or contain a line
+// break). Backups are named by basename only, so editing `src/lib.rs` and
+// then `tests/lib.rs` reuses one backup: the Backup stage then warns on
+// stderr (`StageTrace::backup()`, in the trace format) that the backup of
+// the first file was overwritten.
+//
+// `restore_backup()` (CLI: --undo) refuses with `BackupIntegrity` when the
+// sidecar is missing, malformed, or does not match the backup, or when its
+// origin line names another file, and only then copies the backup over the
+// target.
+// ============================================================================
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
-        };
+/// Suffix of the checksum sidecar next to each backup
+pub const BACKUP_CHECKSUM_SUFFIX: &str = ".sha";
 
-        if bytes_read == 0 {
-            break;
-        }
+/// Checksum name written in the sidecar
+const BACKUP_CHECKSUM_ALGORITHM: &str = "fnv1a64";
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleIndentError::IoError(IoOperation::Write));
+/// Start of the sidecar's second line (the original's absolute path)
+const BACKUP_ORIGIN_PREFIX: &str = "origin ";
+
+const FNV1A64_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV1A64_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a (64-bit) of everything a reader yields
+///
+/// # Returns
+/// * `Ok(hash)` - Input read to the end (at most MAX_BYTE_ITERATIONS bytes)
+/// * `Err(ToggleCommentError)` - Read failed or input too long
+fn fnv1a64_in<R: Read>(reader: &mut R) -> Result<u64, ToggleCommentError> {
+    let mut hash = FNV1A64_OFFSET_BASIS;
+    let mut bytes_read: u64 = 0;
+    while let Some(byte) = read_single_byte(reader)? {
+        bytes_read += 1;
+        if bytes_read > MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
         }
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(FNV1A64_PRIME);
+    }
+    Ok(hash)
+}
 
-        remaining_bytes += 1;
+/// FNV-1a (64-bit) of a file's content
+#[cfg(feature = "fs")]
+fn fnv1a64_of_file(path: &Path) -> std::io::Result<u64> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, file);
+    fnv1a64_in(&mut reader).map_err(|_| std::io::Error::other("checksum read failed"))
+}
+
+/// Sidecar path of a backup (`{backup}.sha`)
+#[cfg(feature = "fs")]
+pub(crate) fn backup_checksum_path(backup_path: &Path) -> PathBuf {
+    let mut name = backup_path.as_os_str().to_os_string();
+    name.push(BACKUP_CHECKSUM_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Sidecar content for a checksum and, when recordable, the original's path
+fn format_backup_checksum(hash: u64, origin: Option<&str>) -> String {
+    let mut content = format!("{} {:016x}\n", BACKUP_CHECKSUM_ALGORITHM, hash);
+    if let Some(origin) = origin {
+        content.push_str(BACKUP_ORIGIN_PREFIX);
+        content.push_str(origin);
+        content.push('\n');
     }
+    content
+}
 
-    if let Err(_) = writer.flush() {
-        return Err(ToggleIndentError::IoError(IoOperation::Flush));
+/// Parse sidecar content
+///
+/// # Returns
+/// * `None` - Other algorithm, wrong digit count, or extra content (other
+///   than one origin line)
+fn parse_backup_checksum(content: &str) -> Option<u64> {
+    let (first, rest) = content.split_once('\n')?;
+    if !rest.is_empty() && parse_backup_origin(content).is_none() {
+        return None;
     }
+    let digits = first
+        .strip_prefix(BACKUP_CHECKSUM_ALGORITHM)?
+        .strip_prefix(' ')?;
+    if digits.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(digits, 16).ok()
+}
 
-    Ok(())
+/// Origin line of sidecar content
+///
+/// # Returns
+/// * `None` - No origin line (older sidecar) or malformed content
+fn parse_backup_origin(content: &str) -> Option<&str> {
+    let (_, rest) = content.split_once('\n')?;
+    let origin = rest
+        .strip_prefix(BACKUP_ORIGIN_PREFIX)?
+        .strip_suffix('\n')?;
+    if origin.is_empty() || origin.contains(['\n', '\r']) {
+        return None;
+    }
+    Some(origin)
 }
 
-/// Add 4 spaces to the start of a specific line (bytewise)
+/// Path as recorded in a sidecar (`None`: not UTF-8 or has a line break)
+#[cfg(feature = "fs")]
+fn backup_origin(original_path: &Path) -> Option<&str> {
+    original_path
+        .to_str()
+        .filter(|path| !path.is_empty() && !path.contains(['\n', '\r']))
+}
+
+/// Original an existing backup was taken from, as its sidecar records it
+#[cfg(feature = "fs")]
+fn recorded_backup_origin(backup_path: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(backup_checksum_path(backup_path)).ok()?;
+    parse_backup_origin(&content).map(str::to_string)
+}
+
+/// Copy (or hard-link) the original to its backup and write the checksum
+/// sidecar
 ///
-/// # Overview
-/// Bytewise implementation - adds exactly 4 spaces at column 0 of target line.
-/// No heap allocation, single byte buffer.
+/// Runs as the Backup stage of every operation.
 ///
 /// # Arguments
-/// * `file_path` - Path to the source file
-/// * `line_number` - Zero-indexed line number to indent
+/// * `link` - Try a hard link first (Unix only; copy when linking fails,
+///   e.g. across filesystems). Only safe when the replace renames a new
+///   file over the original (`ToggleOptions::temp_dir` set): a copy in place
+///   would write through the link into the backup. The link costs nothing
+///   per byte; the checksum still reads the original once
 ///
 /// # Returns
-/// * `Ok(())` - Line indented successfully
-/// * `Err(ToggleIndentError)` - Specific error code
+/// * `Ok(snapshot)` - Backup and sidecar written; snapshot of the original
+///   they were taken from (for `replace_original()`)
+/// * `Err(io::Error)` - Copy, checksum read, or sidecar write failed, or the
+///   original changed while it was copied
+#[cfg(feature = "fs")]
+pub(crate) fn create_backup(
+    original_path: &Path,
+    backup_path: &Path,
+    link: bool,
+) -> std::io::Result<FileSnapshot> {
+    let snapshot = FileSnapshot::of(original_path)?;
+    if !(link && cfg!(unix) && link_backup(original_path, backup_path)) {
+        std::fs::copy(original_path, backup_path)?;
+    }
+    let hash = fnv1a64_of_file(original_path)?;
+    std::fs::write(
+        backup_checksum_path(backup_path),
+        format_backup_checksum(hash, backup_origin(original_path)),
+    )?;
+    snapshot.check(original_path)?;
+    Ok(snapshot)
+}
+
+/// Replace `backup_path` with a hard link to the original
 ///
-/// # Example
-/// ```no_run
-/// use toggle_comment_indent_module::indent_line_bytewise;
+/// # Returns
+/// * `false` - No link made (filesystem without hard links, other
+///   filesystem, no permission); the caller copies instead
+#[cfg(feature = "fs")]
+fn link_backup(original_path: &Path, backup_path: &Path) -> bool {
+    match std::fs::remove_file(backup_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return false,
+        _ => {}
+    }
+    std::fs::hard_link(original_path, backup_path).is_ok()
+}
+
+/// Whether two paths name the same file (Unix: device and inode)
+#[cfg(feature = "fs")]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (std::fs::metadata(a), std::fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        false
+    }
+}
+
+/// Check a backup against its sidecar
 ///
-/// match indent_line_bytewise("./src/main.rs", 5) {
-///     Ok(()) => println!("Line 5 indented"),
-///     Err(e) => eprintln!("Failed: {:?}", e),
-/// }
-/// ```
+/// # Returns
+/// * `true` - Sidecar well-formed and equal to the backup's checksum
+/// * `false` - Sidecar missing or malformed, backup unreadable, or mismatch
+#[cfg(feature = "fs")]
+pub(crate) fn backup_matches_checksum(backup_path: &Path) -> bool {
+    let recorded = std::fs::read_to_string(backup_checksum_path(backup_path))
+        .ok()
+        .and_then(|content| parse_backup_checksum(&content));
+    match (recorded, fnv1a64_of_file(backup_path)) {
+        (Some(recorded), Ok(actual)) => recorded == actual,
+        _ => false,
+    }
+}
+
+/// Check that a backup was taken from `target` (its canonical path)
 ///
-/// # Behavior
-/// ```text
-/// Before: "code"
-/// After:  "    code"
+/// # Returns
+/// * `true` - The sidecar's origin line resolves to `target`, or the sidecar
+///   has none (older sidecar, or a path that could not be recorded)
+/// * `false` - Sidecar unreadable, or the backup was taken from another file
+#[cfg(feature = "fs")]
+pub(crate) fn backup_origin_matches(backup_path: &Path, target: &Path) -> bool {
+    let Ok(content) = std::fs::read_to_string(backup_checksum_path(backup_path)) else {
+        return false;
+    };
+    match parse_backup_origin(&content) {
+        None => true,
+        Some(origin) => {
+            let origin = Path::new(origin);
+            origin == target || origin.canonicalize().is_ok_and(|p| p == target)
+        }
+    }
+}
+
+/// Restore a file from its backup after verifying the backup's checksum
+/// and origin
 ///
-/// Before: "  code"  (already indented 2)
-/// After:  "      code"  (now indented 6)
-/// ```
-pub fn indent_line_bytewise(file_path: &str, line_number: usize) -> Result<(), ToggleIndentError> {
-    // Convert to absolute path
-    let absolute_path = match Path::new(file_path).canonicalize() {
+/// # Arguments
+/// * `file_path` - File to restore; its backup is
+///   `backup_toggle_comment_{filename}` in the working directory (or
+///   `options.backup_dir`)
+/// * `options` - `trace` is honored (op `undo`, stages verify and replace)
+///
+/// # Returns
+/// * `Ok(())` - Target now holds the backup content (backup and sidecar kept)
+/// * `Err(FileNotFound)` - Target or backup does not exist
+/// * `Err(BackupIntegrity)` - Sidecar missing, malformed, or not matching,
+///   or the backup was taken from another file with the same name
+/// * `Err(ReadOnlyTarget | NotRegularFile | OwnArtifact | FileTooLarge | InvalidUtf8)` -
+///   Target refused as by any edit (`check_edit_target()`)
+/// * `Err(ToggleCommentError)` - Reading or replacing failed
+#[cfg(feature = "fs")]
+pub fn restore_backup(file_path: &Path, options: &ToggleOptions) -> Result<(), ToggleCommentError> {
+    let absolute_path = match file_path.canonicalize() {
         Ok(p) => p,
-        Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                return Err(ToggleIndentError::FileNotFound);
-            }
-            return Err(ToggleIndentError::PathError);
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(ToggleCommentError::FileNotFound);
         }
-    };
-
-    // Find line start position
-    let line_start_pos = match find_line_start_position(file_path, line_number)? {
-        Some(pos) => pos,
-        None => {
-            return Err(ToggleIndentError::LineNotFound {
-                requested: line_number,
-                file_lines: 0,
-            });
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
         }
+        Err(_) => return Err(ToggleCommentError::PathError),
     };
 
-    // Get filename for backup
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before the replace)
+    check_edit_target(&absolute_path, options)?;
+
     let filename = match absolute_path.file_name() {
-        Some(name) => name.to_string_lossy().to_string(),
-        None => return Err(ToggleIndentError::PathError),
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
     };
+    let backup_path = backup_path_for(&filename, options);
+    if !backup_path.is_file() {
+        return Err(ToggleCommentError::FileNotFound);
+    }
 
-    // Create backup
-    let backup_filename = format!("backup_toggle_comment_{}", filename);
-    let backup_path = PathBuf::from(&backup_filename);
+    let trace = StageTrace::new(options, "undo");
 
-    if let Err(_) = std::fs::copy(&absolute_path, &backup_path) {
-        return Err(ToggleIndentError::IoError(IoOperation::Backup));
+    if !trace.check(TraceStage::Verify, || {
+        backup_matches_checksum(&backup_path) && backup_origin_matches(&backup_path, &absolute_path)
+    }) {
+        return Err(ToggleCommentError::BackupIntegrity);
     }
 
-    // Create temp file
-    let temp_filename = format!("temp_indent_bytewise_{}_{}", std::process::id(), filename);
-    let temp_path = PathBuf::from(&temp_filename);
+    // A backup still linked to the target (the operation stopped before its
+    // replace) already holds the content; copying it onto itself truncates it
+    if is_same_file(&backup_path, &absolute_path) {
+        return Ok(());
+    }
 
-    // Write indented file
-    let process_result = write_indented_file_bytewise(&absolute_path, &temp_path, line_start_pos);
+    if let Err(e) = trace.run(TraceStage::Replace, || {
+        std::fs::copy(&backup_path, &absolute_path)?;
+        match options.durability {
+            Durability::Fsync => sync_file(&absolute_path),
+            _ => Ok(()),
+        }
+    }) {
+        return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
+    }
 
-    // Handle result
-    match process_result {
-        Ok(()) => {
-            if let Err(_) = std::fs::copy(&temp_path, &absolute_path) {
-                let _ = std::fs::remove_file(&temp_path);
-                return Err(ToggleIndentError::IoError(IoOperation::Replace));
-            }
+    Ok(())
+}
 
-            if let Err(_) = std::fs::remove_file(&temp_path) {
-                #[cfg(debug_assertions)]
-                eprintln!("Warning: Failed to clean up temp file");
-            }
+#[cfg(all(test, feature = "fs"))]
+mod backup_integrity_tests {
+    use super::*;
 
-            Ok(())
-        }
-        Err(e) => {
-            let _ = std::fs::remove_file(&temp_path);
-            Err(e)
-        }
+    #[test]
+    fn test_backup_checksum_format() {
+        // FNV-1a reference values
+        let mut empty: &[u8] = b"";
+        assert_eq!(fnv1a64_in(&mut empty), Ok(0xcbf2_9ce4_8422_2325));
+        let mut a: &[u8] = b"a";
+        assert_eq!(fnv1a64_in(&mut a), Ok(0xaf63_dc4c_8601_ec8c));
+
+        let line = format_backup_checksum(0xaf63_dc4c_8601_ec8c, None);
+        assert_eq!(line, "fnv1a64 af63dc4c8601ec8c\n");
+        assert_eq!(parse_backup_checksum(&line), Some(0xaf63_dc4c_8601_ec8c));
+        assert_eq!(parse_backup_origin(&line), None);
+
+        let with_origin = format_backup_checksum(0xaf63_dc4c_8601_ec8c, Some("/p/src/lib.rs"));
+        assert_eq!(
+            with_origin,
+            "fnv1a64 af63dc4c8601ec8c\norigin /p/src/lib.rs\n"
+        );
+        assert_eq!(
+            parse_backup_checksum(&with_origin),
+            Some(0xaf63_dc4c_8601_ec8c)
+        );
+        assert_eq!(parse_backup_origin(&with_origin), Some("/p/src/lib.rs"));
+        assert_eq!(
+            parse_backup_checksum("fnv1a64 af63dc4c8601ec8c\nextra\n"),
+            None
+        );
+        assert_eq!(parse_backup_checksum("fnv1a64 af63dc4c8601ec8c"), None);
+        assert_eq!(parse_backup_checksum("crc32 8601ec8c\n"), None);
+        assert_eq!(parse_backup_checksum("fnv1a64 af63\n"), None);
     }
-}
 
-/// Remove up to 4 spaces from the start of a specific line (bytewise)
-///
-/// # Overview
-/// Bytewise implementation - removes up to 4 spaces from column 0.
-/// If line has fewer than 4 spaces, removes only what's there.
-/// No heap allocation, single byte buffer.
-///
-/// # Arguments
-/// * `file_path` - Path to the source file
-/// * `line_number` - Zero-indexed line number to unindent
-///
-/// # Returns
-/// * `Ok(())` - Line unindented successfully (even if no spaces removed)
-/// * `Err(ToggleIndentError)` - Specific error code
-///
-/// # Example
-/// ```no_run
-/// use toggle_comment_indent_module::unindent_line_bytewise;
-///
-/// match unindent_line_bytewise("./src/main.rs", 5) {
-///     Ok(()) => println!("Line 5 unindented"),
-///     Err(e) => eprintln!("Failed: {:?}", e),
-/// }
-/// ```
-///
-/// # Behavior
-/// ```text
-/// Before: "    code"  (4 spaces)
-/// After:  "code"      (removed 4)
-///
-/// Before: "  code"    (2 spaces)
-/// After:  "code"      (removed 2)
-///
-/// Before: "code"      (0 spaces)
-/// After:  "code"      (removed 0 - no-op)
-/// ```
-pub fn unindent_line_bytewise(
-    file_path: &str,
-    line_number: usize,
-) -> Result<(), ToggleIndentError> {
-    // Convert to absolute path
-    let absolute_path = match Path::new(file_path).canonicalize() {
-        Ok(p) => p,
-        Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                return Err(ToggleIndentError::FileNotFound);
-            }
-            return Err(ToggleIndentError::PathError);
+    #[test]
+    fn test_backup_records_origin() {
+        let dirs = [
+            PathBuf::from("test_backup_origin_src"),
+            PathBuf::from("test_backup_origin_tests"),
+        ];
+        let backup_path = PathBuf::from("backup_toggle_comment_test_backup_origin.py");
+        for dir in &dirs {
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::write(dir.join("test_backup_origin.py"), "a\n").unwrap();
         }
-    };
+        let first = dirs[0].join("test_backup_origin.py");
+        let second = dirs[1].join("test_backup_origin.py");
 
-    // Find line start position
-    let line_start_pos = match find_line_start_position(file_path, line_number)? {
-        Some(pos) => pos,
-        None => {
-            return Err(ToggleIndentError::LineNotFound {
-                requested: line_number,
-                file_lines: 0,
-            });
+        toggle_comment_line(first.to_str().unwrap(), 0).unwrap();
+        let first_origin = first.canonicalize().unwrap();
+        assert_eq!(
+            recorded_backup_origin(&backup_path).as_deref(),
+            first_origin.to_str()
+        );
+
+        // Same basename, other file: the backup (and its origin) is replaced
+        toggle_comment_line(second.to_str().unwrap(), 0).unwrap();
+        let second_origin = second.canonicalize().unwrap();
+        assert_eq!(
+            recorded_backup_origin(&backup_path).as_deref(),
+            second_origin.to_str()
+        );
+        assert!(backup_matches_checksum(&backup_path));
+
+        // Undo of the first file must not copy the second file's backup over it
+        std::fs::write(&first, "edited\n").unwrap();
+        assert_eq!(
+            restore_backup(&first, &ToggleOptions::default()),
+            Err(ToggleCommentError::BackupIntegrity)
+        );
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "edited\n");
+        assert!(backup_origin_matches(&backup_path, &second_origin));
+        assert!(!backup_origin_matches(&backup_path, &first_origin));
+
+        let warnings = take_warnings();
+        assert_eq!(warnings.list.len(), 1);
+        assert_eq!(warnings.list[0].kind(), "backup_replaced");
+
+        let mut json: Vec<u8> = Vec::new();
+        let replaced = Warning::BackupReplaced {
+            backup: "b\"k".to_string(),
+            previous_origin: "/p\\a".to_string(),
+        };
+        write_warning_line(&mut json, TraceFormat::Json, "toggle", &replaced).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"op\":\"toggle\",\"warning\":\"backup_replaced\",\"backup\":\"b\\\"k\",\"previous_origin\":\"/p\\\\a\"}\n"
+        );
+
+        let _ = std::fs::remove_file(&backup_path);
+        let _ = std::fs::remove_file(backup_checksum_path(&backup_path));
+        for dir in &dirs {
+            let _ = std::fs::remove_dir_all(dir);
         }
-    };
+    }
 
-    // Get filename for backup
-    let filename = match absolute_path.file_name() {
-        Some(name) => name.to_string_lossy().to_string(),
-        None => return Err(ToggleIndentError::PathError),
-    };
+    #[test]
+    fn test_restore_backup_verifies_checksum() {
+        let test_file = PathBuf::from("test_backup_integrity_undo.py");
+        let backup_path = PathBuf::from("backup_toggle_comment_test_backup_integrity_undo.py");
+        let checksum_path = backup_checksum_path(&backup_path);
+        let options = ToggleOptions::default();
+        std::fs::write(&test_file, "a\nb\n").unwrap();
+
+        toggle_basic_singleline_comment_bytewise_with_options(
+            "test_backup_integrity_undo.py",
+            1,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&test_file).unwrap(), "a\n# b\n");
+        assert!(checksum_path.exists());
+
+        assert_eq!(restore_backup(&test_file, &options), Ok(()));
+        assert_eq!(std::fs::read_to_string(&test_file).unwrap(), "a\nb\n");
+
+        // Backup changed after its sidecar was written: refused, target untouched
+        std::fs::write(&test_file, "edited\n").unwrap();
+        std::fs::write(&backup_path, "stale\n").unwrap();
+        assert_eq!(
+            restore_backup(&test_file, &options),
+            Err(ToggleCommentError::BackupIntegrity)
+        );
+        let _ = std::fs::remove_file(&checksum_path);
+        assert_eq!(
+            restore_backup(&test_file, &options),
+            Err(ToggleCommentError::BackupIntegrity)
+        );
+        assert_eq!(std::fs::read_to_string(&test_file).unwrap(), "edited\n");
 
-    // Create backup
-    let backup_filename = format!("backup_toggle_comment_{}", filename);
-    let backup_path = PathBuf::from(&backup_filename);
+        let _ = std::fs::remove_file(&backup_path);
+        assert_eq!(
+            restore_backup(&test_file, &options),
+            Err(ToggleCommentError::FileNotFound)
+        );
 
-    if let Err(_) = std::fs::copy(&absolute_path, &backup_path) {
-        return Err(ToggleIndentError::IoError(IoOperation::Backup));
+        let _ = std::fs::remove_file(&test_file);
     }
 
-    // Create temp file
-    let temp_filename = format!("temp_unindent_bytewise_{}_{}", std::process::id(), filename);
-    let temp_path = PathBuf::from(&temp_filename);
+    #[cfg(unix)]
+    #[test]
+    fn test_linked_backup() {
+        use std::os::unix::fs::MetadataExt;
+        let temp_dir = PathBuf::from("test_backup_integrity_link_dir");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir(&temp_dir).unwrap();
+        let test_file = PathBuf::from("test_backup_integrity_link.py");
+        let backup_path = PathBuf::from("backup_toggle_comment_test_backup_integrity_link.py");
+        std::fs::write(&test_file, "a\nb\n").unwrap();
+        std::fs::write(&backup_path, "older\n").unwrap();
+
+        // Linked (the stale backup is replaced), the link counted twice
+        let snapshot = create_backup(&test_file, &backup_path, true).unwrap();
+        assert!(is_same_file(&test_file, &backup_path));
+        assert_eq!(std::fs::metadata(&backup_path).unwrap().nlink(), 2);
+
+        // Stopped before its replace: undo must not copy the file onto itself
+        let options = ToggleOptions {
+            temp_dir: Some(temp_dir.clone()),
+            ..ToggleOptions::default()
+        };
+        assert_eq!(restore_backup(&test_file, &options), Ok(()));
+        assert_eq!(std::fs::read_to_string(&test_file).unwrap(), "a\nb\n");
+        assert_eq!(FileSnapshot::of(&test_file).unwrap(), snapshot);
 
-    // Write unindented file
-    let process_result = write_unindented_file_bytewise(&absolute_path, &temp_path, line_start_pos);
+        // The rename leaves the old inode to the backup alone
+        toggle_basic_singleline_comment_bytewise_with_options(
+            "test_backup_integrity_link.py",
+            1,
+            &options,
+        )
+        .unwrap();
+        assert!(!is_same_file(&test_file, &backup_path));
+        assert_eq!(std::fs::read_to_string(&test_file).unwrap(), "a\n# b\n");
+        assert_eq!(std::fs::read_to_string(&backup_path).unwrap(), "a\nb\n");
+        assert_eq!(restore_backup(&test_file, &options), Ok(()));
+        assert_eq!(std::fs::read_to_string(&test_file).unwrap(), "a\nb\n");
+
+        // Without a temp dir the replace copies in place: backups stay copies
+        toggle_basic_singleline_comment_bytewise_with_options(
+            "test_backup_integrity_link.py",
+            1,
+            &ToggleOptions::default(),
+        )
+        .unwrap();
+        assert!(!is_same_file(&test_file, &backup_path));
+        assert_eq!(std::fs::read_to_string(&backup_path).unwrap(), "a\nb\n");
 
-    // Handle result
-    match process_result {
-        Ok(()) => {
-            if let Err(_) = std::fs::copy(&temp_path, &absolute_path) {
-                let _ = std::fs::remove_file(&temp_path);
-                return Err(ToggleIndentError::IoError(IoOperation::Replace));
+        cleanup_files(&[
+            &test_file,
+            &backup_path,
+            &backup_checksum_path(&backup_path),
+        ]);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+}
+
+// ============================================================================
+// SESSION BACKUP BUNDLES (multi-file operations)
+// ============================================================================
+//
+// An operation that edits several files (CLI: --grep-format) also keeps all
+// of their backups together in one directory `.toggle_comment_backup_<ts>/`
+// (`<ts>`: Unix seconds, `-2`, `-3`, ... appended when taken), with:
+//
+//   manifest                first line SESSION_MANIFEST_HEADER, then one
+//                           `<backup name> TAB <absolute original path>` per file
+//   <n>_<filename>          copy of the original, n = position in the manifest
+//   <n>_<filename>.sha      its checksum sidecar (see BACKUP INTEGRITY)
+//
+// `undo_session()` (CLI: --undo-session <dir>) checks every backup against
+// its sidecar, and every original for the read-only attribute, before it
+// restores any of them: either all files are restored or none is touched.
+// Paths containing a tab or line break cannot be recorded (`PathError`).
+// ============================================================================
+
+/// First line of a session manifest
+pub const SESSION_MANIFEST_HEADER: &str = "toggle_comment session 1";
+
+/// Name of the manifest inside a session directory
+pub const SESSION_MANIFEST_NAME: &str = "manifest";
+
+/// Most files one session records (bound for the manifest read by undo)
+pub const MAX_SESSION_FILES: usize = 10_000;
+
+/// Attempts at a free directory name within the same second
+#[cfg(feature = "fs")]
+const MAX_SESSION_DIR_ATTEMPTS: u32 = 100;
+
+/// Backups of one multi-file operation, in one directory with a manifest
+#[cfg(feature = "fs")]
+#[derive(Debug)]
+pub struct BackupSession {
+    dir: PathBuf,
+    files: usize,
+}
+
+#[cfg(feature = "fs")]
+impl BackupSession {
+    /// Create `.toggle_comment_backup_<ts>/` in `parent`, with an empty manifest
+    ///
+    /// # Returns
+    /// * `Ok(BackupSession)` - Directory and manifest header written
+    /// * `Err(ToggleCommentError)` - Directory could not be created
+    pub fn create_in(parent: &Path) -> Result<BackupSession, ToggleCommentError> {
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        for attempt in 1..=MAX_SESSION_DIR_ATTEMPTS {
+            let name = if attempt == 1 {
+                format!("{}{}", SESSION_DIR_PREFIX, seconds)
+            } else {
+                format!("{}{}-{}", SESSION_DIR_PREFIX, seconds, attempt)
+            };
+            let dir = parent.join(name);
+            match std::fs::create_dir(&dir) {
+                Ok(()) => {
+                    let header = format!("{}\n", SESSION_MANIFEST_HEADER);
+                    if let Err(e) = std::fs::write(dir.join(SESSION_MANIFEST_NAME), header) {
+                        return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup));
+                    }
+                    return Ok(BackupSession { dir, files: 0 });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
             }
+        }
+        Err(ToggleCommentError::IoError(IoOperation::Backup))
+    }
 
-            if let Err(_) = std::fs::remove_file(&temp_path) {
-                #[cfg(debug_assertions)]
-                eprintln!("Warning: Failed to clean up temp file");
+    /// Session directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Files recorded so far
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    /// Back up one file into the session (before it is edited)
+    ///
+    /// # Returns
+    /// * `Ok(())` - Backup, sidecar and manifest entry written
+    /// * `Err(FileNotFound)` - File does not exist (nothing recorded)
+    /// * `Err(PathError)` - Path has no filename, a tab or a line break
+    /// * `Err(TooManyLines)` - MAX_SESSION_FILES already recorded
+    /// * `Err(ToggleCommentError)` - Copy or manifest write failed
+    pub fn add(&mut self, file_path: &Path) -> Result<(), ToggleCommentError> {
+        let absolute_path = match file_path.canonicalize() {
+            Ok(p) => p,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ToggleCommentError::FileNotFound);
             }
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            Err(_) => return Err(ToggleCommentError::PathError),
+        };
 
-            Ok(())
+        // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+        if is_special_file(&absolute_path) {
+            return Err(ToggleCommentError::NotRegularFile);
         }
-        Err(e) => {
-            let _ = std::fs::remove_file(&temp_path);
-            Err(e)
+
+        let original = match absolute_path.to_str() {
+            Some(s) if !s.contains(['\t', '\n', '\r']) => s.to_string(),
+            _ => return Err(ToggleCommentError::PathError),
+        };
+        let filename = match absolute_path.file_name() {
+            Some(name) => safe_name_component(name),
+            None => return Err(ToggleCommentError::PathError),
+        };
+        if self.files >= MAX_SESSION_FILES {
+            return Err(ToggleCommentError::TooManyLines {
+                max: MAX_SESSION_FILES,
+            });
         }
+
+        let backup_name = format!("{}_{}", self.files, filename);
+        // Copies: `undo_session()` writes them back over the originals in place
+        if let Err(e) = create_backup(&absolute_path, &self.dir.join(&backup_name), false) {
+            return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup));
+        }
+
+        let appended = OpenOptions::new()
+            .append(true)
+            .open(self.dir.join(SESSION_MANIFEST_NAME))
+            .and_then(|mut manifest| writeln!(manifest, "{}\t{}", backup_name, original));
+        if let Err(e) = appended {
+            return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup));
+        }
+
+        self.files += 1;
+        Ok(())
     }
 }
 
-/// Add 4 spaces to multiple lines using simple loop (bytewise)
-///
-/// # Overview
-/// **Simple implementation:** Calls `indent_line_bytewise()` once for each
-/// line in the range. File opened/closed multiple times - intentional for simplicity.
-///
-/// # Arguments
-/// * `file_path` - Path to the source file
-/// * `start_line` - First line to indent (will be sorted with end_line)
-/// * `end_line` - Last line to indent (will be sorted with start_line)
+/// Parse a session manifest into `(backup name, original path)` entries
 ///
 /// # Returns
-/// * `Ok(())` - All lines indented successfully
-/// * `Err(ToggleIndentError)` - Specific error code
-///
-/// # Example
-/// ```no_run
-/// use toggle_comment_indent_module::indent_range_bytewise;
-///
-/// // Indent lines 5-10 (order doesn't matter)
-/// match indent_range_bytewise("./src/main.rs", 5, 10) {
-///     Ok(()) => println!("Range indented"),
-///     Err(e) => eprintln!("Failed: {:?}", e),
-/// }
-/// ```
-pub fn indent_range_bytewise(
-    file_path: &str,
-    start_line: usize,
-    end_line: usize,
-) -> Result<(), ToggleIndentError> {
-    let (start, end) = sort_range(start_line, end_line);
-
-    // Safety check
-    let range_size = end.saturating_sub(start).saturating_add(1);
-    if range_size > 10000 {
-        #[cfg(debug_assertions)]
-        eprintln!(
-            "Warning: Large range ({} lines) - this will be slow",
-            range_size
-        );
+/// * `None` - Wrong header, an entry without a tab, a backup name with a
+///   path separator, or more than MAX_SESSION_FILES entries
+#[cfg(feature = "fs")]
+fn parse_session_manifest(content: &str) -> Option<Vec<(&str, &str)>> {
+    let mut lines = content.lines();
+    if lines.next()? != SESSION_MANIFEST_HEADER {
+        return None;
     }
 
-    // Simple loop: indent each line independently
-    for line_num in start..=end {
-        indent_line_bytewise(file_path, line_num)?;
+    let mut entries = Vec::new();
+    for line in lines {
+        let (backup_name, original) = line.split_once('\t')?;
+        if backup_name.is_empty()
+            || backup_name.contains(['/', '\\'])
+            || original.is_empty()
+            || entries.len() >= MAX_SESSION_FILES
+        {
+            return None;
+        }
+        entries.push((backup_name, original));
     }
-
-    Ok(())
+    Some(entries)
 }
 
-/// Remove up to 4 spaces from multiple lines using simple loop (bytewise)
-///
-/// # Overview
-/// **Simple implementation:** Calls `unindent_line_bytewise()` once for each
-/// line in the range. File opened/closed multiple times - intentional for simplicity.
+/// Restore every file of a session directory from its backup
 ///
 /// # Arguments
-/// * `file_path` - Path to the source file
-/// * `start_line` - First line to unindent (will be sorted with end_line)
-/// * `end_line` - Last line to unindent (will be sorted with start_line)
+/// * `session_dir` - A `.toggle_comment_backup_<ts>/` directory
+/// * `options` - `trace` is honored (op `undo_session`, stages verify and replace)
 ///
 /// # Returns
-/// * `Ok(())` - All lines unindented successfully
-/// * `Err(ToggleIndentError)` - Specific error code
-///
-/// # Example
-/// ```no_run
-/// use toggle_comment_indent_module::unindent_range_bytewise;
-///
-/// // Unindent lines 5-10 (order doesn't matter)
-/// match unindent_range_bytewise("./src/main.rs", 5, 10) {
-///     Ok(()) => println!("Range unindented"),
-///     Err(e) => eprintln!("Failed: {:?}", e),
-/// }
-/// ```
-pub fn unindent_range_bytewise(
-    file_path: &str,
-    start_line: usize,
-    end_line: usize,
-) -> Result<(), ToggleIndentError> {
-    let (start, end) = sort_range(start_line, end_line);
+/// * `Ok(count)` - All `count` files restored (the session directory is kept)
+/// * `Err(FileNotFound)` - No manifest in `session_dir`
+/// * `Err(BackupIntegrity)` - Manifest malformed, or a backup does not match
+///   its checksum; nothing restored
+/// * `Err(ReadOnlyTarget)` - An original is read-only; nothing restored
+/// * `Err(ToggleCommentError)` - Reading or a replace failed (files before
+///   the failing one are already restored)
+#[cfg(feature = "fs")]
+pub fn undo_session(
+    session_dir: &Path,
+    options: &ToggleOptions,
+) -> Result<usize, ToggleCommentError> {
+    let manifest = match std::fs::read_to_string(session_dir.join(SESSION_MANIFEST_NAME)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(ToggleCommentError::FileNotFound);
+        }
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Read)),
+    };
+    let entries = match parse_session_manifest(&manifest) {
+        Some(entries) => entries,
+        None => return Err(ToggleCommentError::BackupIntegrity),
+    };
 
-    // Safety check
-    let range_size = end.saturating_sub(start).saturating_add(1);
-    if range_size > 10000 {
-        #[cfg(debug_assertions)]
-        eprintln!(
-            "Warning: Large range ({} lines) - this will be slow",
-            range_size
-        );
-    }
+    let trace = StageTrace::new(options, "undo_session");
 
-    // Simple loop: unindent each line independently
-    for line_num in start..=end {
-        unindent_line_bytewise(file_path, line_num)?;
+    // Every backup and target is checked before the first one is restored
+    let verified = trace.check(TraceStage::Verify, || {
+        entries
+            .iter()
+            .all(|(backup_name, _)| backup_matches_checksum(&session_dir.join(backup_name)))
+    });
+    if !verified {
+        return Err(ToggleCommentError::BackupIntegrity);
+    }
+    if entries
+        .iter()
+        .any(|(_, original)| is_readonly_target(Path::new(original)))
+    {
+        return Err(ToggleCommentError::ReadOnlyTarget);
     }
 
-    Ok(())
-}
+    for (backup_name, original) in &entries {
+        if let Err(e) = trace.run(TraceStage::Replace, || {
+            std::fs::copy(session_dir.join(backup_name), original)
+        }) {
+            return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
+        }
+    }
 
-// ============================================================================
-// TESTS - PHASE 2: INDENT/UNINDENT BYTEWISE
-// ============================================================================
+    Ok(entries.len())
+}
 
-#[cfg(test)]
-mod indent_bytewise_tests {
+#[cfg(all(test, feature = "fs"))]
+mod backup_session_tests {
     use super::*;
 
-    // ========================================
-    // Single Line Indent Tests
-    // ========================================
+    #[test]
+    fn test_parse_session_manifest() {
+        let manifest = "toggle_comment session 1\n0_a.py\t/src/a.py\n1_b.rs\t/src/b c.rs\n";
+        assert_eq!(
+            parse_session_manifest(manifest),
+            Some(vec![("0_a.py", "/src/a.py"), ("1_b.rs", "/src/b c.rs")])
+        );
+        assert_eq!(
+            parse_session_manifest("toggle_comment session 1\n"),
+            Some(vec![])
+        );
+        assert_eq!(parse_session_manifest("0_a.py\t/src/a.py\n"), None);
+        assert_eq!(
+            parse_session_manifest("toggle_comment session 1\n0_a.py /src/a.py\n"),
+            None
+        );
+        // A backup name must stay inside the session directory
+        assert_eq!(
+            parse_session_manifest("toggle_comment session 1\n../x\t/src/a.py\n"),
+            None
+        );
+    }
 
     #[test]
-    fn test_indent_bytewise_basic() {
-        let content = "code\n";
-        let test_file = create_test_file("test_indent_bw_basic.txt", content);
+    fn test_undo_session_restores_all_or_nothing() {
+        let parent = PathBuf::from("test_backup_session_parent");
+        let _ = std::fs::remove_dir_all(&parent);
+        std::fs::create_dir(&parent).unwrap();
+        let a = parent.join("a.py");
+        let b = parent.join("b.rs");
+        std::fs::write(&a, "a\n").unwrap();
+        std::fs::write(&b, "b\n").unwrap();
+        let options = ToggleOptions::default();
+
+        let mut session = BackupSession::create_in(&parent).unwrap();
+        session.add(&a).unwrap();
+        session.add(&b).unwrap();
+        assert_eq!(
+            session.add(&parent.join("missing.py")),
+            Err(ToggleCommentError::FileNotFound)
+        );
+        assert_eq!(session.files(), 2);
+        let dir = session.dir().to_path_buf();
+        assert!(
+            dir.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(".toggle_comment_backup_")
+        );
+        assert!(dir.join("1_b.rs.sha").exists());
 
-        let result = indent_line_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+        std::fs::write(&a, "# a\n").unwrap();
+        std::fs::write(&b, "// b\n").unwrap();
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "    code\n");
+        // One corrupted backup: nothing restored
+        std::fs::write(dir.join("1_b.rs"), "x\n").unwrap();
+        assert_eq!(
+            undo_session(&dir, &options),
+            Err(ToggleCommentError::BackupIntegrity)
+        );
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "# a\n");
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_indent_bw_basic.txt"),
-        ]);
+        std::fs::write(dir.join("1_b.rs"), "b\n").unwrap();
+        assert_eq!(undo_session(&dir, &options), Ok(2));
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "a\n");
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "b\n");
+
+        assert_eq!(
+            undo_session(&parent, &options),
+            Err(ToggleCommentError::FileNotFound)
+        );
+
+        let _ = std::fs::remove_dir_all(&parent);
     }
+}
 
-    #[test]
-    fn test_indent_bytewise_already_indented() {
-        let content = "  code\n";
-        let test_file = create_test_file("test_indent_bw_existing.txt", content);
+// ============================================================================
+// ORPHAN TEMP FILES (left behind by a crash or kill -9)
+// ============================================================================
+//
+// Every temp / scratch name embeds the writing process's PID right after a
+// fixed prefix: `<prefix><pid>_<filename>`. `find_orphan_temp_files()` lists
+// those in one directory whose process is no longer running (CLI:
+// --clean-temp [dir] [--dry-run]). Liveness is read from `/proc/<pid>`;
+// where there is no `/proc`, no file counts as orphaned (nothing is removed).
+// A live process that reused the PID also keeps its files: this errs on the
+// side of leaving litter.
+// ============================================================================
 
-        let result = indent_line_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+/// Name prefixes of the temp and scratch files operations write, each
+/// followed by `<pid>_<filename>`
+pub const TEMP_FILE_PREFIXES: &[&str] = &[
+    "temp_toggle_bytewise_",
+    "temp_toggle_docstring_bytewise_",
+    "temp_toggle_batch_a_",
+    "temp_toggle_batch_b_",
+    "temp_toggle_notebook_",
+    "temp_toggle_patch_",
+    "temp_toggle_strip_",
+    "temp_toggle_line_",
+    "temp_toggle_restyle_",
+    "temp_indent_bytewise_",
+    "temp_unindent_bytewise_",
+    "temp_block_1_",
+    "temp_block_2_",
+    ".temp_toggle_replace_",
+    "toggle_comment_preview_",
+    "backup_toggle_comment_toggle_comment_preview_",
+];
+
+/// Most directory entries `find_orphan_temp_files()` looks at
+pub const MAX_CLEAN_TEMP_ENTRIES: usize = 100_000;
+
+/// A temp file whose writing process is gone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanTempFile {
+    /// Path inside the scanned directory
+    pub path: PathBuf,
+
+    /// PID embedded in the name
+    pub pid: u32,
+}
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "      code\n"); // 2 + 4 = 6 spaces
+/// PID embedded in a temp file name (`<prefix><pid>_<filename>`)
+///
+/// # Returns
+/// * `Some(pid)` - Name matches one of TEMP_FILE_PREFIXES
+/// * `None` - Not one of this crate's temp names
+pub fn temp_file_pid(name: &str) -> Option<u32> {
+    let rest = TEMP_FILE_PREFIXES
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))?;
+    let (digits, filename) = rest.split_once('_')?;
+    if digits.is_empty() || filename.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_indent_bw_existing.txt"),
-        ]);
+/// Whether a process is running
+///
+/// # Returns
+/// * `Some(running)` - From `/proc/<pid>`
+/// * `None` - No `/proc` on this system: cannot tell
+#[cfg(feature = "fs")]
+fn process_is_running(pid: u32) -> Option<bool> {
+    let proc_dir = Path::new("/proc");
+    if !proc_dir.join("self").exists() {
+        return None;
     }
+    Some(pid == std::process::id() || proc_dir.join(pid.to_string()).exists())
+}
 
-    #[test]
-    fn test_indent_bytewise_empty_line() {
-        let content = "\n";
-        let test_file = create_test_file("test_indent_bw_empty.txt", content);
+/// List temp files in `dir` left behind by processes that are no longer running
+///
+/// Only regular files (not symlinks) directly in `dir` are considered.
+///
+/// # Returns
+/// * `Ok(orphans)` - Sorted by path; empty when liveness cannot be checked
+/// * `Err(io::Error)` - `dir` could not be read, or it holds more than
+///   MAX_CLEAN_TEMP_ENTRIES entries
+#[cfg(feature = "fs")]
+pub fn find_orphan_temp_files(dir: &Path) -> std::io::Result<Vec<OrphanTempFile>> {
+    let mut orphans: Vec<OrphanTempFile> = Vec::new();
+    for (seen, entry) in std::fs::read_dir(dir)?.enumerate() {
+        if seen >= MAX_CLEAN_TEMP_ENTRIES {
+            return Err(std::io::Error::other("too many directory entries"));
+        }
+        let entry = entry?;
+        let pid = match entry.file_name().to_str().and_then(temp_file_pid) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        match process_is_running(pid) {
+            Some(false) => orphans.push(OrphanTempFile {
+                path: entry.path(),
+                pid,
+            }),
+            Some(true) => {}
+            None => return Ok(Vec::new()),
+        }
+    }
+    orphans.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(orphans)
+}
 
-        let result = indent_line_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+#[cfg(all(test, feature = "fs"))]
+mod orphan_temp_tests {
+    use super::*;
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "    \n");
+    #[test]
+    fn test_temp_file_pid() {
+        assert_eq!(temp_file_pid("temp_toggle_bytewise_123_main.rs"), Some(123));
+        assert_eq!(temp_file_pid("temp_block_2_7_a_b.py"), Some(7));
+        assert_eq!(temp_file_pid(".temp_toggle_replace_42_x.rs"), Some(42));
+        assert_eq!(
+            temp_file_pid("backup_toggle_comment_toggle_comment_preview_9_x.rs.sha"),
+            Some(9)
+        );
+        // Not ours, or no pid / filename
+        assert_eq!(temp_file_pid("backup_toggle_comment_main.rs"), None);
+        assert_eq!(temp_file_pid("temp_toggle_bytewise_main.rs"), None);
+        assert_eq!(temp_file_pid("temp_toggle_bytewise_12_"), None);
+        assert_eq!(temp_file_pid("temp_toggle_bytewise_-1_x.rs"), None);
+        assert_eq!(temp_file_pid("my_temp_toggle_bytewise_1_x.rs"), None);
+    }
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_indent_bw_empty.txt"),
-        ]);
+    #[test]
+    fn test_find_orphan_temp_files() {
+        let dir = std::env::temp_dir().join(format!("tc_orphan_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+
+        // PIDs above the kernel's pid_max (at most 2^22) are never running
+        let dead = dir.join("temp_toggle_bytewise_4999999_a.rs");
+        let alive = dir.join(format!("temp_block_1_{}_a.rs", std::process::id()));
+        let unrelated = dir.join("temp_notes_4999999_a.rs");
+        for path in [&dead, &alive, &unrelated] {
+            std::fs::write(path, "x").unwrap();
+        }
+        std::fs::create_dir(dir.join("temp_toggle_patch_4999999_d")).unwrap();
+
+        let orphans = find_orphan_temp_files(&dir).unwrap();
+        if Path::new("/proc/self").exists() {
+            assert_eq!(
+                orphans,
+                vec![OrphanTempFile {
+                    path: dead.clone(),
+                    pid: 4_999_999
+                }]
+            );
+        } else {
+            assert!(orphans.is_empty());
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
+}
 
-    // ========================================
-    // Single Line Unindent Tests
-    // ========================================
+// ============================================================================
+// READ-ONLY TARGETS
+// ============================================================================
+//
+// Every operation checks the read-only attribute right after resolving the
+// path, before any backup or temp file is made, and fails with
+// `ReadOnlyTarget`. (Without this check the failure only surfaced at the
+// replace step, after a backup had already been written.)
+//
+// Callers that really want to edit a read-only file (CLI: --force-readonly)
+// use `clear_readonly()` first and `restore_permissions()` afterwards,
+// whether the operation succeeded or not. Restoring puts back the exact
+// original permissions.
+//
+// On Unix, clearing only adds the owner write bit (not world-writable).
+//
+// The same resolve step refuses FIFOs, device nodes and sockets with
+// `NotRegularFile` (`is_special_file()`): streaming from a FIFO blocks in the
+// read itself, where no loop bound can stop it. Directories fall through to
+// the open and fail there as before.
+//
+// Operations that edit also refuse this crate's own artifacts with
+// `OwnArtifact` (`is_own_artifact()`): a backup, its `.sha` sidecar, a temp
+// file, or anything inside a session backup directory, usually picked by
+// tab completion. Editing one would make `backup_toggle_comment_backup_...`
+// chains. `ToggleOptions::allow_own_artifacts` (CLI: --force) skips the check.
+//
+// `check_edit_target()` runs all of these, then the size and UTF-8 checks,
+// in one place; every edit entry point calls it right after resolving the
+// path, so a new guard is added there once.
+// ============================================================================
 
-    #[test]
-    fn test_unindent_bytewise_four_spaces() {
-        let content = "    code\n";
-        let test_file = create_test_file("test_unindent_bw_four.txt", content);
+/// Check whether a resolved target file has the read-only attribute
+/// (metadata errors are left for the operation itself to report)
+#[cfg(feature = "fs")]
+pub(crate) fn is_readonly_target(path: &Path) -> bool {
+    match std::fs::metadata(path) {
+        Ok(m) => m.permissions().readonly(),
+        Err(_) => false,
+    }
+}
 
-        let result = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+/// Check whether a resolved target is a FIFO, device node or socket
+/// (anything not a regular file or directory; metadata errors are left for
+/// the operation itself to report)
+#[cfg(feature = "fs")]
+pub(crate) fn is_special_file(path: &Path) -> bool {
+    match std::fs::metadata(path) {
+        Ok(m) => {
+            let file_type = m.file_type();
+            !file_type.is_file() && !file_type.is_dir()
+        }
+        Err(_) => false,
+    }
+}
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "code\n");
+/// Name prefix of every single-file backup (`backup_toggle_comment_{filename}`)
+pub const BACKUP_FILE_PREFIX: &str = "backup_toggle_comment_";
+
+/// Backup of a target whose `safe_name_component()` is `filename`: in
+/// `options.backup_dir`, or the working directory
+#[cfg(feature = "fs")]
+pub(crate) fn backup_path_for(filename: &str, options: &ToggleOptions) -> PathBuf {
+    let name = format!("{}{}", BACKUP_FILE_PREFIX, filename);
+    match &options.backup_dir {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    }
+}
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_unindent_bw_four.txt"),
-        ]);
+/// Name prefix of session backup directories (see SESSION BACKUP BUNDLES)
+pub const SESSION_DIR_PREFIX: &str = ".toggle_comment_backup_";
+
+/// Check whether a path names one of this crate's own artifacts
+///
+/// Looks at names only: a `backup_toggle_comment_*` backup or sidecar, a
+/// temp or scratch file (`<prefix><pid>_<filename>`, see TEMP_FILE_PREFIXES),
+/// or a file whose directory is a `.toggle_comment_backup_*` session.
+pub fn is_own_artifact(path: &Path) -> bool {
+    let name_of = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let name = name_of(path);
+    if name.starts_with(BACKUP_FILE_PREFIX) || temp_file_pid(&name).is_some() {
+        return true;
     }
+    path.parent()
+        .is_some_and(|parent| name_of(parent).starts_with(SESSION_DIR_PREFIX))
+}
 
-    #[test]
-    fn test_unindent_bytewise_two_spaces() {
-        let content = "  code\n";
-        let test_file = create_test_file("test_unindent_bw_two.txt", content);
+/// Check a resolved target before an operation reads it to edit it
+///
+/// In order: not a FIFO, device node or socket; not one of this crate's own
+/// artifacts (unless `options.allow_own_artifacts`); not read-only; then
+/// `check_source_target()`. Every operation that edits a file calls this
+/// right after resolving the path, before reading it or making a backup.
+///
+/// # Returns
+/// * `Ok(())` - The file may be edited
+/// * `Err(NotRegularFile | OwnArtifact | ReadOnlyTarget | FileTooLarge | InvalidUtf8)`
+#[cfg(feature = "fs")]
+pub(crate) fn check_edit_target(
+    absolute_path: &Path,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    // Reading a FIFO can block forever
+    if is_special_file(absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+    // Editing a backup or temp file chains backups
+    if !options.allow_own_artifacts && is_own_artifact(absolute_path) {
+        return Err(ToggleCommentError::OwnArtifact);
+    }
+    if is_readonly_target(absolute_path) {
+        return Err(ToggleCommentError::ReadOnlyTarget);
+    }
+    check_source_target(absolute_path, options)
+}
 
-        let result = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+/// Check a resolved file an operation only reads whole (`--strip-comments
+/// --output`): not a special file, within `options.max_file_size`, and
+/// valid UTF-8 with `options.utf8_strict`
+#[cfg(feature = "fs")]
+pub(crate) fn check_source_target(
+    absolute_path: &Path,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    if is_special_file(absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+    if let Some((size, max)) = exceeds_size_limit(absolute_path, options) {
+        return Err(ToggleCommentError::FileTooLarge { size, max });
+    }
+    match first_invalid_utf8_line(absolute_path, options) {
+        Some(line) => Err(ToggleCommentError::InvalidUtf8 { line }),
+        None => Ok(()),
+    }
+}
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "code\n");
+/// Temporarily make a read-only file writable
+///
+/// # Arguments
+/// * `file_path` - Path to the target file
+///
+/// # Returns
+/// * `Ok(Some(original))` - File was read-only; now writable. Pass `original`
+///   to `restore_permissions()` when done
+/// * `Ok(None)` - File was already writable; nothing changed
+/// * `Err(FileNotFound | PermissionDenied | IoError)` - Could not read or change permissions
+#[cfg(feature = "fs")]
+pub fn clear_readonly(file_path: &str) -> Result<Option<std::fs::Permissions>, ToggleCommentError> {
+    let original = match std::fs::metadata(file_path) {
+        Ok(m) => m.permissions(),
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open));
+        }
+    };
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_unindent_bw_two.txt"),
-        ]);
+    if !original.readonly() {
+        return Ok(None);
     }
 
-    #[test]
-    fn test_unindent_bytewise_no_spaces() {
-        let content = "code\n";
-        let test_file = create_test_file("test_unindent_bw_none.txt", content);
+    let mut writable = original.clone();
 
-        let result = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        writable.set_mode(original.mode() | 0o200); // owner write only
+    }
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "code\n"); // Unchanged
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    {
+        writable.set_readonly(false);
+    }
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_unindent_bw_none.txt"),
-        ]);
+    if let Err(e) = std::fs::set_permissions(file_path, writable) {
+        return Err(ToggleCommentError::from_io_error(
+            &e,
+            IoOperation::Permissions,
+        ));
     }
 
-    #[test]
-    fn test_unindent_bytewise_six_spaces() {
-        let content = "      code\n";
-        let test_file = create_test_file("test_unindent_bw_six.txt", content);
+    Ok(Some(original))
+}
 
-        let result = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result.is_ok());
+/// Put back permissions saved by `clear_readonly()`
+///
+/// # Arguments
+/// * `file_path` - Path to the target file
+/// * `permissions` - Original permissions returned by `clear_readonly()`
+#[cfg(feature = "fs")]
+pub fn restore_permissions(
+    file_path: &str,
+    permissions: std::fs::Permissions,
+) -> Result<(), ToggleCommentError> {
+    if let Err(e) = std::fs::set_permissions(file_path, permissions) {
+        return Err(ToggleCommentError::from_io_error(
+            &e,
+            IoOperation::Permissions,
+        ));
+    }
+    Ok(())
+}
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "  code\n"); // Removed 4, left 2
+#[cfg(all(test, feature = "fs"))]
+mod readonly_target_tests {
+    use super::*;
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_unindent_bw_six.txt"),
-        ]);
+    fn set_readonly_flag(path: &Path, readonly: bool) {
+        let mut permissions = std::fs::metadata(path).unwrap().permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(readonly);
+        std::fs::set_permissions(path, permissions).unwrap();
     }
 
-    // ========================================
-    // Range Tests
-    // ========================================
+    #[test]
+    fn test_readonly_target_fails_before_backup() {
+        let test_file = create_test_file("test_readonly_target.py", "x = 1\n");
+        let backup_path = PathBuf::from("backup_toggle_comment_test_readonly_target.py");
+        let _ = std::fs::remove_file(&backup_path);
+        set_readonly_flag(&test_file, true);
+
+        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
+        assert_eq!(result, Err(ToggleCommentError::ReadOnlyTarget));
+        assert_eq!(
+            indent_line_bytewise(test_file.to_str().unwrap(), 0),
+            Err(ToggleIndentError::ReadOnlyTarget)
+        );
+        assert!(!backup_path.exists());
+        assert_eq!(read_file_content(&test_file), "x = 1\n");
+
+        set_readonly_flag(&test_file, false);
+        cleanup_files(&[&test_file]);
+    }
 
     #[test]
-    fn test_indent_range_bytewise_basic() {
-        let content = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_indent_range_bw.txt", content);
+    fn test_clear_and_restore_readonly() {
+        let test_file = create_test_file("test_readonly_force.py", "x = 1\n");
+        set_readonly_flag(&test_file, true);
 
-        let result = indent_range_bytewise(test_file.to_str().unwrap(), 0, 2);
+        let saved = clear_readonly(test_file.to_str().unwrap()).unwrap();
+        assert!(saved.is_some());
+        assert!(!is_readonly_target(&test_file));
+
+        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
         assert!(result.is_ok());
+        assert_eq!(read_file_content(&test_file), "# x = 1\n");
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "    line 0\n    line 1\n    line 2\n");
+        restore_permissions(test_file.to_str().unwrap(), saved.unwrap()).unwrap();
+        assert!(is_readonly_target(&test_file));
+
+        // Already writable: nothing to restore
+        set_readonly_flag(&test_file, false);
+        assert_eq!(clear_readonly(test_file.to_str().unwrap()), Ok(None));
 
         cleanup_files(&[
             &test_file,
-            &PathBuf::from("backup_toggle_comment_test_indent_range_bw.txt"),
+            &PathBuf::from("backup_toggle_comment_test_readonly_force.py"),
         ]);
     }
 
     #[test]
-    fn test_unindent_range_bytewise_basic() {
-        let content = "    line 0\n    line 1\n    line 2\n";
-        let test_file = create_test_file("test_unindent_range_bw.txt", content);
+    fn test_own_artifacts_refused() {
+        assert!(is_own_artifact(Path::new("backup_toggle_comment_main.rs")));
+        assert!(is_own_artifact(Path::new(
+            "src/backup_toggle_comment_main.rs.sha"
+        )));
+        assert!(is_own_artifact(Path::new(
+            "temp_toggle_bytewise_42_main.rs"
+        )));
+        assert!(is_own_artifact(Path::new(
+            ".toggle_comment_backup_1700000000/0_main.rs"
+        )));
+        assert!(!is_own_artifact(Path::new("temp_toggle_bytewise_main.rs")));
+        assert!(!is_own_artifact(Path::new("src/backup.rs")));
+
+        let test_file = create_test_file("backup_toggle_comment_test_own_artifact.py", "a\n");
+        let path = test_file.to_str().unwrap();
+        assert_eq!(
+            toggle_comment_line(path, 0),
+            Err(ToggleCommentError::OwnArtifact)
+        );
+        assert_eq!(
+            indent_line_bytewise(path, 0),
+            Err(ToggleIndentError::OwnArtifact)
+        );
+        assert!(
+            !Path::new("backup_toggle_comment_backup_toggle_comment_test_own_artifact.py").exists()
+        );
 
-        let result = unindent_range_bytewise(test_file.to_str().unwrap(), 0, 2);
-        assert!(result.is_ok());
+        // Refused before the brace scan, which would fail on the open block
+        let block_file =
+            create_test_file("backup_toggle_comment_test_own_artifact.rs", "fn a() {\n");
+        assert_eq!(
+            toggle_brace_block_bytewise_with_options(
+                block_file.to_str().unwrap(),
+                0,
+                &ToggleOptions::default()
+            ),
+            Err(ToggleCommentError::OwnArtifact)
+        );
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "line 0\nline 1\nline 2\n");
+        let forced = ToggleOptions {
+            allow_own_artifacts: true,
+            ..ToggleOptions::default()
+        };
+        assert_eq!(toggle_comment_line_with_options(path, 0, &forced), Ok(()));
+        assert_eq!(read_file_content(&test_file), "# a\n");
 
         cleanup_files(&[
             &test_file,
-            &PathBuf::from("backup_toggle_comment_test_unindent_range_bw.txt"),
+            &block_file,
+            &PathBuf::from("backup_toggle_comment_backup_toggle_comment_test_own_artifact.py"),
         ]);
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_indent_unindent_roundtrip() {
-        let original = "code\n";
-        let test_file = create_test_file("test_indent_roundtrip_bw.txt", original);
-
-        // Indent
-        let result1 = indent_line_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result1.is_ok());
+    fn test_special_file_refused() {
+        // A device node behind a supported extension
+        let link = PathBuf::from("test_special_file.py");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink("/dev/null", &link).unwrap();
+        assert!(is_special_file(&link));
+        assert!(!is_special_file(Path::new("src")));
 
-        let content1 = read_file_content(&test_file);
-        assert_eq!(content1, "    code\n");
-
-        // Unindent back
-        let result2 = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
-        assert!(result2.is_ok());
-
-        let content2 = read_file_content(&test_file);
-        assert_eq!(content2, original);
+        assert_eq!(
+            toggle_basic_singleline_comment_bytewise("test_special_file.py", 0),
+            Err(ToggleCommentError::NotRegularFile)
+        );
+        assert_eq!(
+            indent_line_bytewise("test_special_file.py", 0),
+            Err(ToggleIndentError::NotRegularFile)
+        );
+        assert!(!PathBuf::from("backup_toggle_comment_null").exists());
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_indent_roundtrip_bw.txt"),
-        ]);
+        cleanup_files(&[&link]);
     }
 }
 
 // ============================================================================
-// RANGE UTILITIES
+// PROTECTED REGIONS (sentinel comments)
+// ============================================================================
+//
+// A file can fence off lines that must never be toggled or indented, e.g. a
+// here-doc or string literal whose lines only look like code/comments:
+//
+//     # toggle_comment: off
+//     cat <<EOF
+//     # part of the here-doc, not a comment
+//     EOF
+//     # toggle_comment: on
+//
+// A sentinel is a line that, after optional leading whitespace, is a comment
+// flag (`#`, `//`, `///`, `<!--`), one space, then `toggle_comment: off` or
+// `toggle_comment: on` (trailing whitespace / ` -->` allowed).
+//
+// An `off` sentinel, the lines after it and the next `on` sentinel are
+// protected; an `off` without `on` protects to end of file. Both sentinels
+// are protected so that no edit can lift (or move) the protection by
+// toggling one of them.
+//
+// `ProtectedRegionTracker` recognises sentinels byte-by-byte inside the pass
+// that already walks the file to the target line(s); only the first
+// SENTINEL_HEAD_BYTES of each line are held. An `off` line is only known
+// once it has been read, so a target is checked when its line ends
+// (`line_protected()`). Protected targets are refused with
+// `ProtectedRegion { line }`: single-line, indent, block and range
+// operations after a scan of all their target lines, before any backup is
+// made; batch operations from within their pass (the original is untouched
+// either way). In shell files the tracker also protects here-doc bodies (see
+// Shell here-docs below).
 // ============================================================================
 
-/// Sort and validate a line range to ensure start ≤ end
-///
-/// # Overview
-/// Universal helper for all range-based operations. Always returns a valid
-/// sorted tuple where the first value is ≤ the second value.
-///
-/// # Arguments
-/// * `from` - First line number (may be larger than `to`)
-/// * `to` - Second line number (may be smaller than `from`)
-///
-/// # Returns
-/// * `(start, end)` - Sorted tuple where start ≤ end
-///
-/// # Behavior
-/// ```text
-/// Input: (5, 10) → Output: (5, 10)  [already sorted]
-/// Input: (10, 5) → Output: (5, 10)  [flipped]
-/// Input: (7, 7)  → Output: (7, 7)   [single line, valid]
-/// ```
-///
-/// # Usage
-/// ```rust
-/// let (start, end) = sort_range(user_input_from, user_input_to);
-/// // Now guaranteed: start <= end
-/// ```
+/// Sentinel text that starts a protected region (after flag + one space)
+const PROTECT_OFF_SENTINEL: &[u8] = b"toggle_comment: off";
+
+/// Sentinel text that ends a protected region (after flag + one space)
+const PROTECT_ON_SENTINEL: &[u8] = b"toggle_comment: on";
+
+/// Bytes kept from the start of each line (after leading whitespace) to
+/// recognise a sentinel: `<!-- toggle_comment: off -->\r` fits
+const SENTINEL_HEAD_BYTES: usize = 40;
+
+/// Streaming detector for `toggle_comment: off` / `on` sentinel lines
 ///
-/// # Safety
-/// - Always succeeds (no Result needed)
-/// - No allocations
-/// - Copy semantics only
-/// - Works for any usize values (no overflow - uses min/max)
-fn sort_range(from: usize, to: usize) -> (usize, usize) {
-    // Use built-in min/max for clarity and safety
-    let start = std::cmp::min(from, to);
-    let end = std::cmp::max(from, to);
+/// Feed every byte of the file in order with `push_byte()` (and `finish()` at
+/// end of input). At any line start `in_region()` tells whether that line
+/// starts inside a region; once the line has ended, `line_protected()` tells
+/// whether it is protected, `off` sentinel included.
+#[derive(Debug, Clone, Copy)]
+struct ProtectedRegionTracker {
+    /// Start of the current line, leading whitespace skipped
+    head: [u8; SENTINEL_HEAD_BYTES],
+    head_len: usize,
 
-    (start, end)
+    /// Head did not fit: the line is too long to be a sentinel
+    head_overflow: bool,
+
+    /// Still inside the line's leading whitespace
+    in_leading_space: bool,
+
+    /// Lines from the next one on are inside a region
+    in_region: bool,
+
+    /// The line being read started inside a region or here-doc body
+    line_start_protected: bool,
+
+    /// The last finished line was protected
+    last_line_protected: bool,
+
+    /// Shell here-doc bodies are protected too (see Shell here-docs)
+    heredocs: Option<HereDocTracker>,
 }
 
-// ============================================================================
-// INDENT/UNINDENT RANGE FUNCTIONS
-// ============================================================================
+impl ProtectedRegionTracker {
+    fn new() -> Self {
+        ProtectedRegionTracker {
+            head: [0u8; SENTINEL_HEAD_BYTES],
+            head_len: 0,
+            head_overflow: false,
+            in_leading_space: true,
+            in_region: false,
+            line_start_protected: false,
+            last_line_protected: false,
+            heredocs: None,
+        }
+    }
 
-// ============================================================================
-// PHASE 1: RANGE TOGGLE - SIMPLE LOOP APPROACH (BYTEWISE)
-// ============================================================================
+    /// Also protect shell here-doc bodies when `enabled`
+    fn with_heredocs(self, enabled: bool) -> Self {
+        ProtectedRegionTracker {
+            heredocs: enabled.then(HereDocTracker::new),
+            ..self
+        }
+    }
 
-/// Toggle Rust documentation comment (///) on a specific line - bytewise version
-///
-/// # Overview
-/// Bytewise implementation of docstring toggle. Identical to
-/// `toggle_basic_singleline_comment_bytewise()` but uses TripleSlash flag.
-///
-/// # Arguments
-/// * `file_path` - Path to the source file
-/// * `row_line_zeroindex` - Zero-indexed line number to toggle
+    /// Whether the line currently being read started inside a region (an
+    /// `off` sentinel line is not, see `line_protected()`)
+    fn in_region(&self) -> bool {
+        self.in_region || self.heredocs.is_some_and(|heredocs| heredocs.in_body)
+    }
+
+    /// Whether the last finished line is protected: it started inside a
+    /// region or here-doc body, or it is an `off` sentinel
+    fn line_protected(&self) -> bool {
+        self.last_line_protected
+    }
+
+    /// End of input: finish a last line that has no `\n`
+    fn finish(&mut self) {
+        self.end_line();
+    }
+
+    /// Observe the next byte of the file
+    fn push_byte(&mut self, byte: u8) {
+        if let Some(heredocs) = self.heredocs.as_mut() {
+            heredocs.push_byte(byte);
+        }
+        if byte == b'\n' {
+            self.end_line();
+            return;
+        }
+
+        if self.in_leading_space && (byte == b' ' || byte == b'\t') {
+            return;
+        }
+        self.in_leading_space = false;
+
+        if self.head_len < SENTINEL_HEAD_BYTES {
+            self.head[self.head_len] = byte;
+            self.head_len += 1;
+        } else {
+            self.head_overflow = true;
+        }
+    }
+
+    /// Classify the finished line; the new state applies from the next line
+    fn end_line(&mut self) {
+        let kind = if self.head_overflow {
+            None
+        } else {
+            sentinel_kind(&self.head[..self.head_len])
+        };
+        self.last_line_protected = self.line_start_protected || kind == Some(true);
+        match kind {
+            Some(true) => self.in_region = true,
+            Some(false) => self.in_region = false,
+            None => {}
+        }
+
+        self.head_len = 0;
+        self.head_overflow = false;
+        self.in_leading_space = true;
+        self.line_start_protected = self.in_region();
+    }
+}
+
+/// Recognise a sentinel line (leading whitespace already removed)
 ///
 /// # Returns
-/// * `Ok(())` - Comment toggled successfully
-/// * `Err(ToggleCommentError)` - Specific error code
+/// * `Some(true)` - `toggle_comment: off`
+/// * `Some(false)` - `toggle_comment: on`
+/// * `None` - Not a sentinel
+fn sentinel_kind(line_head: &[u8]) -> Option<bool> {
+    // Longest flag first: "///" before "//"
+    let flags: [&[u8]; 4] = [b"<!--", b"///", b"//", b"#"];
+    let mut rest: &[u8] = &[];
+    let mut found_flag = false;
+    for flag in flags {
+        if line_head.starts_with(flag) && line_head.get(flag.len()) == Some(&b' ') {
+            rest = &line_head[flag.len() + 1..];
+            found_flag = true;
+            break;
+        }
+    }
+    if !found_flag {
+        return None;
+    }
+
+    let (is_off, tail) = if let Some(tail) = rest.strip_prefix(PROTECT_OFF_SENTINEL) {
+        (true, tail)
+    } else if let Some(tail) = rest.strip_prefix(PROTECT_ON_SENTINEL) {
+        (false, tail)
+    } else {
+        return None;
+    };
+
+    // Allowed after the sentinel: whitespace, an HTML comment close, '\r'
+    let mut tail = tail;
+    while let Some((&last, before)) = tail.split_last() {
+        if last == b' ' || last == b'\t' || last == b'\r' {
+            tail = before;
+        } else {
+            break;
+        }
+    }
+    let tail = tail.strip_suffix(b"-->").unwrap_or(tail);
+    if tail.iter().all(|&b| b == b' ' || b == b'\t') {
+        Some(is_off)
+    } else {
+        None
+    }
+}
+
+/// First protected line in `first..=last` (see PROTECTED REGIONS)
 ///
-/// # Memory Usage
-/// - Single byte buffer [u8; 1]
-/// - No heap allocation during processing
+/// Reads to the end of line `last`, so an `off` sentinel there is seen.
+/// Lines past the end of the input are not reported.
 ///
-/// # Example
-/// ```no_run
-/// use toggle_comment_indent_module::toggle_rust_docstring_singleline_comment_bytewise;
+/// # Arguments
+/// * `heredocs` - Also protect shell here-doc bodies
 ///
-/// match toggle_rust_docstring_singleline_comment_bytewise("./src/lib.rs", 5) {
-///     Ok(()) => println!("Docstring toggled"),
-///     Err(e) => eprintln!("Failed: {:?}", e),
-/// }
-/// ```
-pub fn toggle_rust_docstring_singleline_comment_bytewise(
-    file_path: &str,
-    row_line_zeroindex: usize,
-) -> Result<(), ToggleCommentError> {
-    // Convert to absolute path
-    let absolute_path = match Path::new(file_path).canonicalize() {
-        Ok(p) => p,
-        Err(e) => {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                return Err(ToggleCommentError::FileNotFound);
+/// # Returns
+/// * `Ok(Some(line))` - First protected line of the range
+/// * `Ok(None)` - No line of the range is protected
+/// * `Err(ToggleCommentError)` - Read failed, cancelled, or iteration limit reached
+fn first_protected_line_in<R: Read>(
+    reader: &mut R,
+    first: usize,
+    last: usize,
+    heredocs: bool,
+) -> Result<Option<usize>, ToggleCommentError> {
+    let mut tracker = ProtectedRegionTracker::new().with_heredocs(heredocs);
+    let mut current_line: usize = 0;
+    let mut byte_count: u64 = 0;
+    let mut line_open = false;
+
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if !line_open && cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        tracker.push_byte(byte);
+        line_open = byte != b'\n';
+        if byte == b'\n' {
+            if current_line >= first && tracker.line_protected() {
+                return Ok(Some(current_line));
             }
-            return Err(ToggleCommentError::PathError);
+            if current_line == last {
+                return Ok(None);
+            }
+            current_line += 1;
         }
+    }
+
+    // Last line without a line ending
+    tracker.finish();
+    if line_open && current_line >= first && tracker.line_protected() {
+        return Ok(Some(current_line));
+    }
+    Ok(None)
+}
+
+/// `first_protected_line_in()` over a file
+#[cfg(feature = "fs")]
+fn first_protected_line(
+    file_path: &Path,
+    first: usize,
+    last: usize,
+    heredocs: bool,
+) -> Result<Option<usize>, ToggleCommentError> {
+    let file = match File::open(file_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
     };
+    first_protected_line_in(
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, file),
+        first,
+        last,
+        heredocs,
+    )
+}
 
-    // Use TripleSlash flag (no extension check needed)
-    let comment_flag = CommentFlag::TripppleSlash;
+// ----------------------------------------------------------------------------
+// Shell here-docs
+// ----------------------------------------------------------------------------
+//
+// In `.sh` / `.bash` files the body of a here-doc is data (an embedded config,
+// a generated file), whatever it looks like:
+//
+//     cat > app.conf <<EOF
+//     # this is a config comment, not a shell comment
+//     EOF
+//
+// `HereDocTracker` finds `<<WORD`, `<<-WORD`, `<<'WORD'`, `<<"WORD"` (and
+// `<< WORD`) on code lines, outside quotes, comments and `(( ))`; the lines
+// after it, up to and including the line that is exactly `WORD` (leading
+// tabs stripped for `<<-`), are protected like a `toggle_comment: off`
+// region. Several here-docs opened on one line follow each other. `<<<`
+// here-strings, `<<` shifts and delimiters starting with a digit are
+// ignored. Comment toggles of shell files track them unless
+// `ToggleOptions::allow_heredoc` is set.
+
+/// Longest here-doc delimiter tracked (a longer one is not recognised)
+const MAX_HEREDOC_DELIMITER: usize = 32;
+
+/// Here-docs opened on one line that are tracked (`cmd <<A <<B`)
+const MAX_PENDING_HEREDOCS: usize = 4;
+
+/// One here-doc whose body has not ended yet
+#[derive(Debug, Clone, Copy)]
+struct HereDoc {
+    delimiter: [u8; MAX_HEREDOC_DELIMITER],
+    delimiter_len: usize,
+
+    /// `<<-`: leading tabs of body lines are ignored
+    strip_tabs: bool,
+}
 
-    // Combined find and detect in single pass
-    let (line_start_pos, has_tag) =
-        match find_and_detect_tag_state(file_path, row_line_zeroindex, comment_flag)? {
-            Some((pos, tag_state)) => (pos, tag_state),
-            None => {
-                return Err(ToggleCommentError::LineNotFound {
-                    requested: row_line_zeroindex,
-                    file_lines: 0,
-                });
-            }
-        };
+impl HereDoc {
+    fn empty() -> Self {
+        HereDoc {
+            delimiter: [0u8; MAX_HEREDOC_DELIMITER],
+            delimiter_len: 0,
+            strip_tabs: false,
+        }
+    }
+}
 
-    // Get filename for backup naming
-    let filename = match absolute_path.file_name() {
-        Some(name) => name.to_string_lossy().to_string(),
-        None => return Err(ToggleCommentError::PathError),
-    };
+/// Delimiter being read after `<<`
+#[derive(Debug, Clone, Copy)]
+struct DelimiterScan {
+    doc: HereDoc,
 
-    // Create backup path in CWD
-    let backup_filename = format!("backup_toggle_comment_{}", filename);
-    let backup_path = PathBuf::from(&backup_filename);
+    /// A word character was seen (quotes alone do not end the `<<-` / space part)
+    started: bool,
 
-    // Create backup copy
-    if let Err(_) = std::fs::copy(&absolute_path, &backup_path) {
-        return Err(ToggleCommentError::IoError(IoOperation::Backup));
+    /// Inside `'...'` / `"..."` of the delimiter
+    quote: Option<u8>,
+
+    /// The delimiter does not fit MAX_HEREDOC_DELIMITER
+    overflow: bool,
+}
+
+/// Whether a byte ends an unquoted here-doc delimiter
+fn ends_delimiter(byte: u8) -> bool {
+    matches!(
+        byte,
+        b' ' | b'\t' | b'\r' | b';' | b'|' | b'&' | b'<' | b'>' | b'(' | b')'
+    )
+}
+
+/// Streaming detector for shell here-doc bodies
+///
+/// Fed every byte by `ProtectedRegionTracker`; `in_body` tells whether the
+/// line being read belongs to a here-doc (body or closing delimiter).
+#[derive(Debug, Clone, Copy)]
+struct HereDocTracker {
+    /// Here-docs opened and not closed, in body order
+    pending: [HereDoc; MAX_PENDING_HEREDOCS],
+    pending_len: usize,
+
+    /// The line being read is a body line of `pending[0]`
+    in_body: bool,
+
+    /// Body line: its start (leading tabs skipped for `<<-`)
+    line: [u8; MAX_HEREDOC_DELIMITER + 1],
+    line_len: usize,
+    line_overflow: bool,
+    at_line_start: bool,
+
+    /// Code line: quote, escape, comment and `((` state
+    quote: Option<u8>,
+    escaped: bool,
+    comment: bool,
+    arithmetic: usize,
+    previous: u8,
+    angles: usize,
+    scan: Option<DelimiterScan>,
+}
+
+impl HereDocTracker {
+    fn new() -> Self {
+        HereDocTracker {
+            pending: [HereDoc::empty(); MAX_PENDING_HEREDOCS],
+            pending_len: 0,
+            in_body: false,
+            line: [0u8; MAX_HEREDOC_DELIMITER + 1],
+            line_len: 0,
+            line_overflow: false,
+            at_line_start: true,
+            quote: None,
+            escaped: false,
+            comment: false,
+            arithmetic: 0,
+            previous: b'\n',
+            angles: 0,
+            scan: None,
+        }
     }
 
-    // Create temp file
-    let temp_filename = format!(
-        "temp_toggle_docstring_bytewise_{}_{}",
-        std::process::id(),
-        filename
-    );
-    let temp_path = PathBuf::from(&temp_filename);
+    /// Observe the next byte of the file
+    fn push_byte(&mut self, byte: u8) {
+        if byte == b'\n' {
+            self.end_line();
+        } else if self.in_body {
+            self.push_body_byte(byte);
+        } else {
+            self.push_code_byte(byte);
+        }
+    }
 
-    // Byte-wise write operation
-    let process_result = write_toggled_file_bytewise(
-        &absolute_path,
-        &temp_path,
-        row_line_zeroindex,
-        line_start_pos,
-        has_tag,
-        comment_flag,
-    );
+    fn push_body_byte(&mut self, byte: u8) {
+        if self.at_line_start && byte == b'\t' && self.pending[0].strip_tabs {
+            return;
+        }
+        self.at_line_start = false;
+        if self.line_len < self.line.len() {
+            self.line[self.line_len] = byte;
+            self.line_len += 1;
+        } else {
+            self.line_overflow = true;
+        }
+    }
 
-    // Handle result
-    match process_result {
-        Ok(()) => {
-            // Success: replace original
-            if let Err(_) = std::fs::copy(&temp_path, &absolute_path) {
-                let _ = std::fs::remove_file(&temp_path);
-                return Err(ToggleCommentError::IoError(IoOperation::Replace));
+    fn push_code_byte(&mut self, byte: u8) {
+        if let Some(mut scan) = self.scan.take() {
+            if Self::push_delimiter_byte(&mut scan, byte) {
+                self.scan = Some(scan);
+                return;
             }
+            self.finish_delimiter(scan);
+        }
+        if self.comment {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.previous, byte);
 
-            // Clean up temp
-            if let Err(_) = std::fs::remove_file(&temp_path) {
-                #[cfg(debug_assertions)]
-                eprintln!("Warning: Failed to clean up temp file");
+        if self.escaped {
+            self.escaped = false;
+            return;
+        }
+        if let Some(quote) = self.quote {
+            if byte == quote {
+                self.quote = None;
+            } else if byte == b'\\' && quote == b'"' {
+                self.escaped = true;
             }
+            return;
+        }
 
-            Ok(())
+        if byte != b'<' {
+            if self.angles == 2 && self.arithmetic == 0 {
+                self.angles = 0;
+                let scan = DelimiterScan {
+                    doc: HereDoc::empty(),
+                    started: false,
+                    quote: None,
+                    overflow: false,
+                };
+                self.scan = Some(scan);
+                // The byte after `<<` is the delimiter's first
+                self.previous = previous;
+                self.push_code_byte(byte);
+                return;
+            }
+            self.angles = 0;
         }
-        Err(e) => {
-            let _ = std::fs::remove_file(&temp_path);
-            Err(e)
+        match byte {
+            b'\\' => self.escaped = true,
+            b'\'' | b'"' => self.quote = Some(byte),
+            b'#' if matches!(previous, b'\n' | b' ' | b'\t' | b';' | b'&' | b'|' | b'(') => {
+                self.comment = true;
+            }
+            b'(' if previous == b'(' => self.arithmetic += 1,
+            b')' if previous == b')' => self.arithmetic = self.arithmetic.saturating_sub(1),
+            b'<' => self.angles += 1,
+            _ => {}
+        }
+    }
+
+    /// Feed one byte to the delimiter being read; `false` when it ends it
+    /// (the byte is then code again)
+    fn push_delimiter_byte(scan: &mut DelimiterScan, byte: u8) -> bool {
+        if let Some(quote) = scan.quote {
+            if byte == quote {
+                scan.quote = None;
+            } else {
+                Self::push_delimiter_char(scan, byte);
+            }
+            return true;
+        }
+        if !scan.started {
+            match byte {
+                b'-' if !scan.doc.strip_tabs && scan.doc.delimiter_len == 0 => {
+                    scan.doc.strip_tabs = true;
+                    return true;
+                }
+                b' ' | b'\t' => return true,
+                _ => {}
+            }
+        }
+        match byte {
+            b'\'' | b'"' => {
+                scan.started = true;
+                scan.quote = Some(byte);
+                true
+            }
+            // `<<\EOF` quotes the delimiter; the backslash is not part of it
+            b'\\' => {
+                scan.started = true;
+                true
+            }
+            _ if ends_delimiter(byte) => false,
+            _ => {
+                scan.started = true;
+                Self::push_delimiter_char(scan, byte);
+                true
+            }
+        }
+    }
+
+    fn push_delimiter_char(scan: &mut DelimiterScan, byte: u8) {
+        if scan.doc.delimiter_len < MAX_HEREDOC_DELIMITER {
+            scan.doc.delimiter[scan.doc.delimiter_len] = byte;
+            scan.doc.delimiter_len += 1;
+        } else {
+            scan.overflow = true;
+        }
+    }
+
+    /// Queue a complete delimiter (unless it cannot be a here-doc)
+    fn finish_delimiter(&mut self, scan: DelimiterScan) {
+        let doc = scan.doc;
+        let starts_with_digit = doc.delimiter[..doc.delimiter_len]
+            .first()
+            .is_some_and(u8::is_ascii_digit);
+        if scan.overflow
+            || doc.delimiter_len == 0
+            || starts_with_digit
+            || self.pending_len == MAX_PENDING_HEREDOCS
+        {
+            return;
         }
+        self.pending[self.pending_len] = doc;
+        self.pending_len += 1;
+    }
+
+    /// Classify the finished line; the new state applies from the next line
+    fn end_line(&mut self) {
+        if self.in_body {
+            let mut line = &self.line[..self.line_len];
+            if let Some(rest) = line.strip_suffix(b"\r") {
+                line = rest;
+            }
+            let doc = self.pending[0];
+            if !self.line_overflow && line == &doc.delimiter[..doc.delimiter_len] {
+                self.pending.copy_within(1..self.pending_len, 0);
+                self.pending_len -= 1;
+                self.in_body = self.pending_len > 0;
+            }
+        } else {
+            if let Some(scan) = self.scan.take() {
+                self.finish_delimiter(scan);
+            }
+            self.in_body = self.pending_len > 0;
+        }
+
+        self.line_len = 0;
+        self.line_overflow = false;
+        self.at_line_start = true;
+        self.quote = None;
+        self.escaped = false;
+        self.comment = false;
+        self.arithmetic = 0;
+        self.previous = b'\n';
+        self.angles = 0;
     }
 }
 
-/// Toggle basic comments on a range of lines using simple loop (bytewise)
+/// Reader adapter that feeds every byte read through a `ProtectedRegionTracker`
+///
+/// Used by the batch line engine, which reads one byte at a time, so the
+/// tracker state is exact at every line start.
+struct ProtectedRegionReader<'a, R: Read> {
+    inner: &'a mut R,
+    tracker: ProtectedRegionTracker,
+}
+
+impl<R: Read> Read for ProtectedRegionReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        for &byte in &buf[..bytes_read] {
+            self.tracker.push_byte(byte);
+        }
+        Ok(bytes_read)
+    }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod protected_region_tests {
+    use super::*;
+
+    fn protected_lines(content: &str) -> Vec<usize> {
+        tracked_lines(content, false)
+    }
+
+    fn tracked_lines(content: &str, heredocs: bool) -> Vec<usize> {
+        let mut tracker = ProtectedRegionTracker::new().with_heredocs(heredocs);
+        let mut protected = Vec::new();
+        let mut line = 0;
+        for &byte in content.as_bytes() {
+            tracker.push_byte(byte);
+            if byte == b'\n' {
+                if tracker.line_protected() {
+                    protected.push(line);
+                }
+                line += 1;
+            }
+        }
+        if !content.is_empty() && !content.ends_with('\n') {
+            tracker.finish();
+            if tracker.line_protected() {
+                protected.push(line);
+            }
+        }
+        protected
+    }
+
+    #[test]
+    fn test_sentinel_kind() {
+        assert_eq!(sentinel_kind(b"# toggle_comment: off"), Some(true));
+        assert_eq!(sentinel_kind(b"// toggle_comment: on  \r"), Some(false));
+        assert_eq!(sentinel_kind(b"<!-- toggle_comment: off -->"), Some(true));
+        assert_eq!(sentinel_kind(b"/// toggle_comment: on"), Some(false));
+        assert_eq!(sentinel_kind(b"#toggle_comment: off"), None);
+        assert_eq!(sentinel_kind(b"# toggle_comment: offline"), None);
+        assert_eq!(sentinel_kind(b"x = 1 # toggle_comment: off"), None);
+    }
+
+    #[test]
+    fn test_tracker_regions() {
+        let content =
+            "a\n  # toggle_comment: off\nb\nc\n# toggle_comment: on\nd\n# toggle_comment: off\ne";
+        assert_eq!(protected_lines(content), vec![1, 2, 3, 4, 6, 7]);
+        assert_eq!(protected_lines("# toggle_comment: off"), vec![0]);
+    }
+
+    #[test]
+    fn test_tracker_heredocs() {
+        // Body and closing line; quoted delimiter; <<- strips tabs; two on one line
+        let content = "cat <<EOF\n# a\nEOF\n# b\ncat <<'END' >x\n$y\nEND\r\n\
+                       cat <<-EOF\n\t# c\n\tEOF\ncat <<A <<\"B\"\n1\nA\n2\nB\nz";
+        assert_eq!(
+            tracked_lines(content, true),
+            vec![1, 2, 5, 6, 8, 9, 11, 12, 13, 14]
+        );
+        assert_eq!(tracked_lines(content, false), Vec::<usize>::new());
+
+        // Not here-docs: here-string, shift, quoted or commented <<, digit
+        let content = "x <<< EOF\n((y = 1 << z))\necho \"<<EOF\" # <<EOF\ncat <<2\nEOF\n";
+        assert_eq!(tracked_lines(content, true), Vec::<usize>::new());
+
+        // Unterminated here-doc: protected to the end
+        assert_eq!(tracked_lines("cat <<EOF\na\nb", true), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_protected_lines_refused() {
+        let content =
+            "a = 1\n# toggle_comment: off\nb = 2\n    c = 3\n# toggle_comment: on\nd = 4\n";
+        let test_file = create_test_file("test_protected_region.py", content);
+        let path = test_file.to_str().unwrap();
+        let backup_path = PathBuf::from("backup_toggle_comment_test_protected_region.py");
+        let _ = std::fs::remove_file(&backup_path);
+
+        assert_eq!(
+            toggle_basic_singleline_comment_bytewise(path, 2),
+            Err(ToggleCommentError::ProtectedRegion { line: 2 })
+        );
+        assert_eq!(
+            indent_line_bytewise(path, 3),
+            Err(ToggleIndentError::ProtectedRegion { line: 3 })
+        );
+        assert_eq!(
+            unindent_line_bytewise(path, 4),
+            Err(ToggleIndentError::ProtectedRegion { line: 4 })
+        );
+        assert_eq!(
+            toggle_block_comment_bytewise(path, 0, 5),
+            Err(ToggleCommentError::ProtectedRegion { line: 1 })
+        );
+        assert!(!backup_path.exists());
+        assert_eq!(
+            toggle_multiple_basic_comments(path, &[0, 3]),
+            Err(ToggleCommentError::ProtectedRegion { line: 3 })
+        );
+        assert_eq!(read_file_content(&test_file), content);
+
+        // Outside the region is unaffected
+        assert!(toggle_basic_singleline_comment_bytewise(path, 5).is_ok());
+        assert!(toggle_multiple_basic_comments(path, &[0, 5]).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "# a = 1\n# toggle_comment: off\nb = 2\n    c = 3\n# toggle_comment: on\nd = 4\n"
+        );
+
+        cleanup_files(&[&test_file, &backup_path]);
+    }
+
+    #[test]
+    fn test_sentinel_lines_refused() {
+        let content = "a = 1\n# toggle_comment: off\nb = 2\n# toggle_comment: on\nd = 4\n";
+        let test_file = create_test_file("test_protected_sentinel.py", content);
+        let path = test_file.to_str().unwrap();
+        let backup_path = PathBuf::from("backup_toggle_comment_test_protected_sentinel.py");
+        let _ = std::fs::remove_file(&backup_path);
+
+        for line in [1, 3] {
+            assert_eq!(
+                toggle_basic_singleline_comment_bytewise(path, line),
+                Err(ToggleCommentError::ProtectedRegion { line })
+            );
+            assert_eq!(
+                indent_line_bytewise(path, line),
+                Err(ToggleIndentError::ProtectedRegion { line })
+            );
+        }
+        assert_eq!(
+            toggle_block_comment_bytewise(path, 0, 1),
+            Err(ToggleCommentError::ProtectedRegion { line: 1 })
+        );
+        assert!(!backup_path.exists());
+        assert_eq!(
+            toggle_multiple_basic_comments(path, &[0, 1]),
+            Err(ToggleCommentError::ProtectedRegion { line: 1 })
+        );
+        assert_eq!(read_file_content(&test_file), content);
+
+        cleanup_files(&[&test_file, &backup_path]);
+    }
+
+    #[test]
+    fn test_protected_range_leaves_file_untouched() {
+        let content = "a = 1\nb = 2\n# toggle_comment: off\nc = 3\n# toggle_comment: on\n";
+        let test_file = create_test_file("test_protected_range.py", content);
+        let path = test_file.to_str().unwrap();
+        let backup_path = PathBuf::from("backup_toggle_comment_test_protected_range.py");
+        let _ = std::fs::remove_file(&backup_path);
+
+        assert_eq!(
+            toggle_range_basic_comments_bytewise(path, 0, 4),
+            Err(ToggleCommentError::ProtectedRegion { line: 2 })
+        );
+        assert_eq!(
+            indent_range_bytewise(path, 0, 4),
+            Err(ToggleIndentError::ProtectedRegion { line: 2 })
+        );
+        assert_eq!(
+            unindent_range_bytewise(path, 4, 3),
+            Err(ToggleIndentError::ProtectedRegion { line: 3 })
+        );
+        assert_eq!(std::fs::read(&test_file).unwrap(), content.as_bytes());
+        assert!(!backup_path.exists());
+
+        // Up to the line before the sentinel is fine
+        assert!(toggle_range_basic_comments_bytewise(path, 0, 1).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "# a = 1\n# b = 2\n# toggle_comment: off\nc = 3\n# toggle_comment: on\n"
+        );
+
+        cleanup_files(&[&test_file, &backup_path]);
+    }
+
+    #[test]
+    fn test_heredoc_lines_refused() {
+        let content = "cat > app.conf <<EOF\n# port = 80\nEOF\necho done\n";
+        let test_file = create_test_file("test_protected_heredoc.sh", content);
+        let path = test_file.to_str().unwrap();
+        let backup_path = PathBuf::from("backup_toggle_comment_test_protected_heredoc.sh");
+
+        assert_eq!(
+            toggle_basic_singleline_comment_bytewise(path, 1),
+            Err(ToggleCommentError::ProtectedRegion { line: 1 })
+        );
+        assert_eq!(
+            toggle_multiple_basic_comments(path, &[2, 3]),
+            Err(ToggleCommentError::ProtectedRegion { line: 2 })
+        );
+        assert_eq!(read_file_content(&test_file), content);
+
+        // Code lines still toggle; allow_heredoc edits the body
+        assert!(toggle_basic_singleline_comment_bytewise(path, 3).is_ok());
+        let allow = ToggleOptions {
+            allow_heredoc: true,
+            ..ToggleOptions::default()
+        };
+        assert!(toggle_basic_singleline_comment_bytewise_with_options(path, 1, &allow).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "cat > app.conf <<EOF\nport = 80\nEOF\n# echo done\n"
+        );
+
+        cleanup_files(&[&test_file, &backup_path]);
+    }
+}
+
+// ================
+// Block Party Mode
+// ================
+
+/// Block comment markers for different languages
+///
+/// Marker text only; the line ending is taken from the file being edited
+/// (see `marker_line()`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlockMarkers {
+    start: &'static [u8],
+    end: &'static [u8],
+}
+
+/// Determine block comment markers from file extension
+///
+/// # Arguments
+/// * `extension` - File extension without dot
+///
+/// # Returns
+/// * `Some(BlockMarkers)` - Start and end markers for this language
+/// * `None` - Extension not supported for block comments
+fn determine_block_markers(extension: &str) -> Option<BlockMarkers> {
+    match extension.to_lowercase().as_str() {
+        // C-style block comments: /* */
+        // (Zig has no block comments)
+        "rs" | "c" | "cpp" | "cc" | "cxx" | "h" | "hpp" | "js" | "ts" | "java" | "go" | "swift"
+        | "cs" | "kt" | "kts" | "scala" | "dart" | "php" | "v" | "d" | "jsonc" | "json5" => {
+            Some(BlockMarkers {
+                start: b"/*",
+                end: b"*/",
+            })
+        }
+
+        // Python triple-quote: """ """
+        "py" => Some(BlockMarkers {
+            start: b"\"\"\"",
+            end: b"\"\"\"",
+        }),
+
+        // Markdown: HTML comment on its own lines
+        "md" | "markdown" => Some(BlockMarkers {
+            start: b"<!--",
+            end: b"-->",
+        }),
+
+        // Ruby embedded documentation, column 0 only (`marker_at_column_zero()`)
+        "rb" => Some(BlockMarkers {
+            start: b"=begin",
+            end: b"=end",
+        }),
+
+        // Shell/TOML/YAML don't have block comments
+        _ => None,
+    }
+}
+
+/// Block markers for an extension under the options: `options.doc_style`
+/// replaces `/*` `*/`, other markers stay (see `determine_block_markers()`)
+fn determine_block_markers_with(extension: &str, options: &ToggleOptions) -> Option<BlockMarkers> {
+    let markers = determine_block_markers(extension)?;
+    match options.doc_style {
+        Some(style) if markers.start == b"/*" => Some(style.block_markers()),
+        _ => Some(markers),
+    }
+}
+
+/// Perl POD markers (`toggle_pod_block_bytewise()`) for `pl` / `pm`
+fn determine_pod_markers(extension: &str, _options: &ToggleOptions) -> Option<BlockMarkers> {
+    matches!(extension.to_lowercase().as_str(), "pl" | "pm").then_some(BlockMarkers {
+        start: b"=pod",
+        end: b"=cut",
+    })
+}
+
+/// Check for markers that only count at column 0 and do not nest
+///
+/// # Overview
+/// Ruby's `=begin` / `=end` and Perl's `=pod` / `=cut` must start their
+/// line: an indented `=begin` is code, a syntax error. Such marker lines are never indented (not even with
+/// `preserve_indent`), indented copies are not recognised, and an ADD that
+/// would nest one block in another is refused (`NestedBlockMarker`).
+fn marker_at_column_zero(marker: &[u8]) -> bool {
+    marker.first() == Some(&b'=')
+}
+
+/// Most leading whitespace copied onto an indented marker line
+const MAX_MARKER_INDENT: usize = 120;
+
+/// Longest marker line `marker_line()` builds (indent, marker, `\r\n`)
+const MAX_MARKER_LINE: usize = MAX_MARKER_INDENT + 8;
+
+/// Line ending and indentation new marker lines copy from a selected line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MarkerStyle {
+    /// The line ends in `\r\n`
+    crlf: bool,
+
+    /// Leading spaces/tabs of the line (`indent[..indent_len]`)
+    indent: [u8; MAX_MARKER_INDENT],
+    indent_len: usize,
+}
+
+/// A full marker line: optional indent, `marker`, then `\n` or `\r\n`
+///
+/// # Arguments
+/// * `indented` - Copy `style.indent` before the marker (else column 0)
+///
+/// # Returns
+/// * `(buffer, len)` - The line is `buffer[..len]` (no heap allocation)
+fn marker_line(
+    marker: &[u8],
+    style: &MarkerStyle,
+    indented: bool,
+) -> ([u8; MAX_MARKER_LINE], usize) {
+    let indent: &[u8] = if indented {
+        &style.indent[..style.indent_len]
+    } else {
+        b""
+    };
+    let ending: &[u8] = if style.crlf { b"\r\n" } else { b"\n" };
+    let mut buffer = [0u8; MAX_MARKER_LINE];
+    let mut len: usize = 0;
+    for byte in indent.iter().chain(marker.iter()).chain(ending.iter()) {
+        if len == MAX_MARKER_LINE {
+            break;
+        }
+        buffer[len] = *byte;
+        len += 1;
+    }
+    (buffer, len)
+}
+
+/// Incremental "this line is exactly `marker`" check, fed one byte at a time
+///
+/// Leading spaces/tabs (markers indented by an editor; not for
+/// `marker_at_column_zero()` markers) and the `\r` of a CRLF ending are
+/// allowed; anything else makes the line a non-marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MarkerLineMatch {
+    /// Bytes of the marker matched so far (None: line differs)
+    matched: Option<usize>,
+
+    /// A `\r` followed the marker
+    after_cr: bool,
+}
+
+impl MarkerLineMatch {
+    /// State at the start of a line
+    const LINE_START: MarkerLineMatch = MarkerLineMatch {
+        matched: Some(0),
+        after_cr: false,
+    };
+
+    /// Feed one byte of the line (not its `\n`)
+    fn feed(&mut self, marker: &[u8], byte: u8) {
+        if let Some(count) = self.matched {
+            self.matched = if self.after_cr {
+                None
+            } else if count < marker.len() && marker[count] == byte {
+                Some(count + 1)
+            } else if count == 0
+                && (byte == b' ' || byte == b'\t')
+                && !marker_at_column_zero(marker)
+            {
+                Some(0)
+            } else if count == marker.len() && byte == b'\r' {
+                self.after_cr = true;
+                Some(count)
+            } else {
+                None
+            };
+        }
+    }
+
+    /// The bytes fed so far form a marker line
+    fn is_marker(&self, marker: &[u8]) -> bool {
+        self.matched == Some(marker.len())
+    }
+}
+
+/// Mode for block comment operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockMode {
+    /// Add block markers (markers not present)
+    Add,
+    /// Remove block markers (markers present)
+    Remove,
+}
+
+// ============================================================================
+// BYTE-WISE TOGGLE OPERATIONS (IMPLEMENTATION)
+// ============================================================================
+//
+// ## Architecture
+// This module implements comment toggling using single-byte operations
+// with no heap allocation and no line-loading into memory.
+//
+// Three core operations:
+// 1. find_and_detect_tag_state() - Combined find+detect in one pass
+// 2. write_toggled_file_bytewise() - Three-part byte-by-byte copy with toggle
+// 3. toggle_basic_singleline_comment() - Refactored wrapper using bytewise ops
+//
+// ## Memory Usage
+// - Stack only: single byte buffer [u8; 1]
+// - No Vec, no String, no heap allocations during processing
+// - Total working memory: 1 byte
+//
+// ## Safety
+// - All loops bounded by MAX_BYTE_ITERATIONS
+// - Stops at newline during tag detection
+// - Preserves CRLF vs LF line endings
+// - Returns Ok(None) for line not found (not an error)
+//
+// ============================================================================
+
+/// Maximum bytes to read before safety abort
+/// Allows ~1GB files while preventing infinite loops
+pub const MAX_BYTE_ITERATIONS: u64 = 1_000_000_000;
+
+/// Read what follows a flag matched at column 0
+///
+/// # Returns
+/// * `Ok(true)` - A space, or the end of the line for flags that are
+///   `removable_at_eol()` (`\n`, `\r\n`, end of input): the line is commented
+/// * `Ok(false)` - Anything else
+fn flag_terminator_follows<R: Read>(
+    reader: &mut R,
+    comment_flag: CommentFlag,
+) -> Result<bool, ToggleCommentError> {
+    match read_single_byte(reader)? {
+        Some(b' ') => Ok(true),
+        None | Some(b'\n') => Ok(comment_flag.removable_at_eol()),
+        Some(b'\r') => Ok(comment_flag.removable_at_eol()
+            && matches!(read_single_byte(reader)?, None | Some(b'\n'))),
+        Some(_) => Ok(false),
+    }
+}
+
+/// Combined operation: Find line start position AND detect tag state
+///
+/// # Overview
+/// Performs find+detect in a single file pass. After finding target line,
+/// checks COLUMN 0 for comment tag pattern. No indentation handling.
+///
+/// # Detection Rule (SIMPLE)
+/// Read first bytes of line starting at column 0:
+/// - "// " → HAS_TAG
+/// - "# "  → HAS_TAG
+/// - "/// " → HAS_TAG
+/// - "//", "#", "///" at the end of the line → HAS_TAG (see `removable_at_eol()`)
+/// - "<!-- " → HAS_TAG (the " -->" suffix is handled when writing)
+/// - "/* " → HAS_TAG (likewise " */")
+/// - "///< ", "//!< " → HAS_TAG
+/// - Anything else → NO_TAG
+///
+/// No space skipping. No indentation detection. Column 0 only.
+///
+/// Returns `Err(ProtectedRegion)` if the target is inside a
+/// `toggle_comment: off` region or is one of its sentinels.
+#[cfg(feature = "fs")]
+fn find_and_detect_tag_state(
+    file_path: &str,
+    target_line: usize,
+    comment_flag: CommentFlag,
+) -> Result<Option<(u64, bool)>, ToggleCommentError> {
+    // Open file for reading
+    let mut file = match File::open(file_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    // Sentinels up to the end of the target decide whether it may be edited
+    if let Some(line) = first_protected_line(Path::new(file_path), target_line, target_line, false)?
+    {
+        return Err(ToggleCommentError::ProtectedRegion { line });
+    }
+
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+    let mut byte_position: u64 = 0;
+    let mut current_line: usize = 0;
+    let line_start_pos: u64;
+
+    // ===========================================
+    // PHASE 1: Find target line start position
+    // ===========================================
+
+    if target_line == 0 {
+        line_start_pos = 0;
+    } else {
+        loop {
+            if byte_position >= MAX_BYTE_ITERATIONS {
+                return Err(ToggleCommentError::IoError(IoOperation::Read));
+            }
+            if cancel_requested() {
+                return Err(ToggleCommentError::Cancelled);
+            }
+
+            let bytes_read = match file.read(&mut byte_bucket) {
+                Ok(n) => n,
+                Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+            };
+
+            if bytes_read == 0 {
+                return Ok(None); // Line not found
+            }
+
+            if byte_bucket[0] == b'\n' {
+                current_line += 1;
+                if current_line == target_line {
+                    line_start_pos = byte_position + 1;
+                    break;
+                }
+            }
+
+            byte_position += 1;
+        }
+    }
+
+    // ===========================================
+    // PHASE 2: Detect tag at column 0 ONLY
+    // ===========================================
+
+    // Seek to line start
+    if file.seek(std::io::SeekFrom::Start(line_start_pos)).is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Read));
+    }
+
+    // Read first byte at column 0
+    let bytes_read = match file.read(&mut byte_bucket) {
+        Ok(n) => n,
+        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+    };
+
+    // EOF or empty line
+    if bytes_read == 0 || byte_bucket[0] == b'\n' {
+        return Ok(Some((line_start_pos, false)));
+    }
+
+    // Check if first byte matches start of tag
+    let has_tag = match comment_flag {
+        CommentFlag::Hash => {
+            // Pattern: "# " at column 0
+            if byte_bucket[0] != b'#' {
+                false
+            } else {
+                // Read next byte - should be space (or the end of the line)
+                flag_terminator_follows(&mut file, comment_flag)?
+            }
+        }
+
+        CommentFlag::DoubleSlash => {
+            // Pattern: "// " at column 0
+            if byte_bucket[0] != b'/' {
+                false
+            } else {
+                // Read second '/'
+                match file.read(&mut byte_bucket) {
+                    Ok(0) => false,
+                    Ok(_) => {
+                        if byte_bucket[0] != b'/' {
+                            false
+                        } else {
+                            // Read space (or the end of the line)
+                            flag_terminator_follows(&mut file, comment_flag)?
+                        }
+                    }
+                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+                }
+            }
+        }
+
+        CommentFlag::TripleSlash => {
+            // Pattern: "/// " at column 0
+            if byte_bucket[0] != b'/' {
+                false
+            } else {
+                // Read second '/'
+                match file.read(&mut byte_bucket) {
+                    Ok(0) => false,
+                    Ok(_) => {
+                        if byte_bucket[0] != b'/' {
+                            false
+                        } else {
+                            // Read third '/'
+                            match file.read(&mut byte_bucket) {
+                                Ok(0) => false,
+                                Ok(_) => {
+                                    if byte_bucket[0] != b'/' {
+                                        false
+                                    } else {
+                                        // Read space (or the end of the line)
+                                        flag_terminator_follows(&mut file, comment_flag)?
+                                    }
+                                }
+                                Err(_) => {
+                                    return Err(ToggleCommentError::IoError(IoOperation::Read));
+                                }
+                            }
+                        }
+                    }
+                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+                }
+            }
+        }
+
+        CommentFlag::HtmlComment => {
+            // Pattern: "<!-- " at column 0 (suffix is not required to detect)
+            let mut matched = byte_bucket[0] == b'<';
+            for expected in b"!-- " {
+                if !matched {
+                    break;
+                }
+                matched = match file.read(&mut byte_bucket) {
+                    Ok(0) => false,
+                    Ok(_) => byte_bucket[0] == *expected,
+                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+                };
+            }
+            matched
+        }
+
+        CommentFlag::SlashStar => {
+            // Pattern: "/* " at column 0 (suffix is not required to detect)
+            let mut matched = byte_bucket[0] == b'/';
+            for expected in b"* " {
+                if !matched {
+                    break;
+                }
+                matched = match file.read(&mut byte_bucket) {
+                    Ok(0) => false,
+                    Ok(_) => byte_bucket[0] == *expected,
+                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+                };
+            }
+            matched
+        }
+
+        CommentFlag::DoxygenMember | CommentFlag::QtMember => {
+            // Pattern: "///< " / "//!< " at column 0 (or the flag at the end of the line)
+            let flag_bytes = comment_flag.as_bytes();
+            let mut matched = byte_bucket[0] == flag_bytes[0];
+            for expected in &flag_bytes[1..] {
+                if !matched {
+                    break;
+                }
+                matched = match file.read(&mut byte_bucket) {
+                    Ok(0) => false,
+                    Ok(_) => byte_bucket[0] == *expected,
+                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+                };
+            }
+            matched && flag_terminator_follows(&mut file, comment_flag)?
+        }
+    };
+
+    Ok(Some((line_start_pos, has_tag)))
+}
+
+/// Write file with one line toggled using byte-by-byte operations
+///
+/// # Overview
+/// Copies source file to destination with single line modified.
+/// All tag operations at COLUMN 0 only. No indentation handling.
+///
+/// # Add Mode (has_tag = false)
+/// Write tag at column 0, then copy rest of line
+///
+/// # Remove Mode (has_tag = true)
+/// Skip tag bytes at column 0, then copy rest of line (a flag at the end of
+/// the line has no space to skip; its line ending is kept)
+///
+/// Flags with a suffix (HTML `-->`) also add / drop ` {suffix}` before the
+/// line ending, see `copy_rest_of_line_with_suffix()`.
+#[cfg(feature = "fs")]
+fn write_toggled_file_bytewise(
+    source_path: &Path,
+    dest_path: &Path,
+    target_line: usize,
+    line_start_pos: u64,
+    has_tag: bool,
+    comment_flag: CommentFlag,
+) -> Result<(), ToggleCommentError> {
+    let mut source_file = match File::open(source_path) {
+        Ok(f) => TimedIo(f),
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let dest_file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest_path)
+    {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+    };
+
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, TimedIo(dest_file));
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+    let mut byte_position: u64 = 0;
+
+    // ===========================================
+    // PART A: Copy bytes before target line
+    // ===========================================
+
+    while byte_position < line_start_pos {
+        if byte_position >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            return Err(ToggleCommentError::LineNotFound {
+                requested: target_line,
+                file_lines: 0,
+            });
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        byte_position += 1;
+    }
+
+    // ===========================================
+    // PART B: Toggle target line at column 0
+    // ===========================================
+
+    if has_tag {
+        // REMOVE MODE: Skip tag bytes at column 0
+        // (the space after a prefix-only flag is dropped below, if present)
+        let bytes_to_skip = match comment_flag {
+            CommentFlag::Hash => 1,                                  // "#"
+            CommentFlag::DoubleSlash => 2,                           // "//"
+            CommentFlag::TripleSlash => 3,                           // "///"
+            CommentFlag::HtmlComment => 5,                           // "<!-- "
+            CommentFlag::SlashStar => 3,                             // "/* "
+            CommentFlag::DoxygenMember | CommentFlag::QtMember => 4, // "///<", "//!<"
+        };
+
+        // Skip the tag bytes
+        for _ in 0..bytes_to_skip {
+            if source_file.read(&mut byte_bucket).is_err() {
+                return Err(ToggleCommentError::IoError(IoOperation::Read));
+            }
+        }
+
+        // Copy rest of line (dropping a " -->" style suffix if the flag has one)
+        let suffix = comment_flag.suffix_bytes();
+        if !suffix.is_empty() {
+            copy_rest_of_line_with_suffix(
+                &mut source_file,
+                &mut writer,
+                &[],
+                false,
+                suffix,
+                false,
+            )?;
+        } else {
+            let mut space_pending = true;
+            loop {
+                let bytes_read = match source_file.read(&mut byte_bucket) {
+                    Ok(n) => n,
+                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+                };
+
+                if bytes_read == 0 {
+                    break; // EOF
+                }
+
+                // The flag's space; a flag at the end of the line has none
+                if space_pending {
+                    space_pending = false;
+                    if byte_bucket[0] == b' ' {
+                        continue;
+                    }
+                }
+
+                if writer.write_all(&byte_bucket).is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+
+                if byte_bucket[0] == b'\n' {
+                    break; // End of line
+                }
+            }
+        }
+    } else {
+        // ADD MODE: Write tag at column 0, then copy rest of line
+        match comment_flag {
+            CommentFlag::Hash => {
+                if writer.write_all(b"#").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+                if writer.write_all(b" ").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+            }
+            CommentFlag::DoubleSlash => {
+                if writer.write_all(b"//").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+                if writer.write_all(b" ").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+            }
+            CommentFlag::TripleSlash => {
+                if writer.write_all(b"///").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+                if writer.write_all(b" ").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+            }
+            CommentFlag::HtmlComment => {
+                if writer.write_all(b"<!--").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+                if writer.write_all(b" ").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+            }
+            CommentFlag::SlashStar | CommentFlag::DoxygenMember | CommentFlag::QtMember => {
+                if writer.write_all(comment_flag.as_bytes()).is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+                if writer.write_all(b" ").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+            }
+        }
+
+        // Copy rest of line (adding a " -->" style suffix if the flag has one)
+        let suffix = comment_flag.suffix_bytes();
+        if !suffix.is_empty() {
+            copy_rest_of_line_with_suffix(&mut source_file, &mut writer, &[], false, suffix, true)?;
+        } else {
+            loop {
+                let bytes_read = match source_file.read(&mut byte_bucket) {
+                    Ok(n) => n,
+                    Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+                };
+
+                if bytes_read == 0 {
+                    break; // EOF
+                }
+
+                if writer.write_all(&byte_bucket).is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+
+                if byte_bucket[0] == b'\n' {
+                    break; // End of line
+                }
+            }
+        }
+    }
+
+    // ===========================================
+    // PART C: Copy rest of file
+    // ===========================================
+
+    let mut remaining_bytes: u64 = 0;
+    loop {
+        if remaining_bytes >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        remaining_bytes += 1;
+    }
+
+    if writer.flush().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// REFACTORED WRAPPER - Uses Bytewise Operations
+// ============================================================================
+
+/// Toggle comment on a specific line using byte-wise operations (REFACTORED)
+///
+/// # Overview
+/// **This function has been refactored to use byte-wise operations with no
+/// line buffering or heap allocation.** The public API remains unchanged for
+/// backward compatibility.
+///
+/// Previous implementation loaded entire lines into Vec<u8>. New implementation
+/// uses single-byte buffer and separate find/detect/write operations.
+///
+/// # New Implementation
+/// 1. Calls `find_and_detect_tag_state()` - finds line and detects tag in one pass
+/// 2. Handles `Ok(None)` gracefully (line not found - not treated as error initially)
+/// 3. Calls `write_toggled_file_bytewise()` - performs byte-wise toggle
+/// 4. Preserves existing backup/temp file behavior
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `row_line_zeroindex` - Zero-indexed line number to toggle
+///
+/// # Returns
+/// * `Ok(())` - Comment toggled successfully
+/// * `Err(ToggleCommentError)` - Specific error code
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::toggle_basic_singleline_comment;
+///
+/// match toggle_basic_singleline_comment("./src/main.rs", 5) {
+///     Ok(()) => println!("Line 5 toggled"),
+///     Err(e) => eprintln!("Failed: {:?}", e),
+/// }
+/// ```
+///
+/// # Memory Usage
+/// - Previous: A 10KB buffer per line
+/// - New: 1 byte working buffer
+/// - Reduction: ~10,000x less memory
+///
+/// # Performance
+/// - Previous: Read entire line into Vec
+/// - New: Single-byte operations, may be slower but more memory-safe
+/// - Trade-off: Safety and bounded memory over raw speed
+//
+// NOTE: This is the refactored version using bytewise operations
+#[cfg(feature = "fs")]
+pub fn toggle_basic_singleline_comment_bytewise(
+    file_path: &str,
+    row_line_zeroindex: usize,
+) -> Result<(), ToggleCommentError> {
+    toggle_basic_singleline_comment_bytewise_with_options(
+        file_path,
+        row_line_zeroindex,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_basic_singleline_comment_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_basic_singleline_comment_bytewise_with_options(
+    file_path: &str,
+    row_line_zeroindex: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    // Extract and validate file extension
+    let extension = match target_extension(&absolute_path, options) {
+        Some(ext) => ext,
+        None => return Err(ToggleCommentError::NoExtension),
+    };
+
+    if is_json_without_comments(&extension) {
+        return Err(ToggleCommentError::JsonNoComments);
+    }
+
+    // Determine comment flag from extension
+    let comment_flag = match determine_line_flag(&extension, options) {
+        Some(flag) => flag,
+        None => return Ok(()), // Unsupported extension - no-op
+    };
+
+    // Indent-preserving, relaxed, trimming, blank-skipping, one-direction,
+    // audited, here-doc guarded and EOL / final-newline converting toggles
+    // run on the line engine
+    if options.preserve_indent
+        || preserves_indent_by_default(&extension)
+        || guards_heredocs(&extension, options)
+        || options.removal == RemovalMode::Relaxed
+        || options.eol != EolMode::Keep
+        || options.final_newline != FinalNewline::Keep
+        || options.trim_trailing
+        || options.skip_blank
+        || options.direction != LineToggleDirection::Toggle
+        || options.eol_audit
+    {
+        return toggle_multiple_lines_bytewise(file_path, &[row_line_zeroindex], None, options);
+    }
+
+    // ==================================================
+    // NEW: Combined find and detect in single pass
+    // ==================================================
+    let (line_start_pos, has_tag) =
+        match find_and_detect_tag_state(file_path, row_line_zeroindex, comment_flag)? {
+            Some((pos, tag_state)) => (pos, tag_state),
+            None => {
+                // Line not found - return appropriate error
+                return Err(ToggleCommentError::LineNotFound {
+                    requested: row_line_zeroindex,
+                    file_lines: 0, // Unknown in bytewise mode
+                });
+            }
+        };
+
+    // A line tagged with the removal-only flag (e.g. PHP `# `) is uncommented
+    // with that flag; untagged lines still get the primary flag added.
+    let mut comment_flag = comment_flag;
+    let mut has_tag = has_tag;
+    if !has_tag
+        && let Some(alternate) = determine_removal_only_flag(&extension)
+        && let Some((_, true)) =
+            find_and_detect_tag_state(file_path, row_line_zeroindex, alternate)?
+    {
+        comment_flag = alternate;
+        has_tag = true;
+    }
+
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+
+    // Create backup path in CWD
+    let backup_path = backup_path_for(&filename, options);
+
+    let trace = StageTrace::new(options, "toggle_basic");
+
+    // Create backup copy of original file
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    // Create working temp file (CWD, or options.temp_dir)
+    let temp_filename = format!("temp_toggle_bytewise_{}_{}", std::process::id(), filename);
+    let temp_path = temp_file_path(options.temp_dir.as_deref(), &temp_filename);
+    let _remove_temp = TempFileGuard(&temp_path);
+
+    // ==================================================
+    // NEW: Byte-wise write operation
+    // ==================================================
+    let process_result = trace.run_streaming(&absolute_path, &temp_path, || {
+        write_toggled_file_bytewise(
+            &absolute_path,
+            &temp_path,
+            row_line_zeroindex,
+            line_start_pos,
+            has_tag,
+            comment_flag,
+        )
+    });
+
+    // Handle processing result (same as before)
+    match process_result {
+        Ok(()) => {
+            // Success: check temp looks complete, then replace original
+            if !trace.check(TraceStage::Verify, || {
+                replacement_size_is_plausible(
+                    &absolute_path,
+                    &temp_path,
+                    MAX_TOGGLE_EDIT_BYTES,
+                    MAX_TOGGLE_EDIT_BYTES,
+                )
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleCommentError::IncompleteWrite);
+            }
+            if let Err(e) = trace.run(TraceStage::Replace, || {
+                replace_original(
+                    &temp_path,
+                    &absolute_path,
+                    options.temp_dir.as_deref(),
+                    options.durability,
+                    &snapshot,
+                )
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
+            }
+
+            // Clean up temp file
+            if remove_temp_file(&temp_path).is_err() {
+                #[cfg(debug_assertions)]
+                eprintln!("Warning: Failed to clean up temp file");
+            }
+
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+// ============================================================================
+// TESTS - Byte-Wise Operations
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod bytewise_tests {
+    use super::*;
+
+    // ========================================
+    // Extension Mapping Tests
+    // ========================================
+
+    #[test]
+    fn test_double_slash_family_extensions() {
+        for ext in [
+            "cs", "kt", "kts", "scala", "dart", "php", "zig", "v", "d", "CS",
+        ] {
+            assert_eq!(determine_comment_flag(ext), Some(CommentFlag::DoubleSlash));
+        }
+        for ext in ["cs", "kt", "kts", "scala", "dart", "php", "v", "d"] {
+            assert!(determine_block_markers(ext).is_some());
+        }
+        // Zig has no block comments
+        assert!(determine_block_markers("zig").is_none());
+    }
+
+    #[test]
+    fn test_extension_syntax_table() {
+        for ext in SUPPORTED_EXTENSIONS {
+            assert!(extension_syntax(ext).is_some(), "{}", ext);
+            assert_eq!(ext, ext.to_lowercase());
+        }
+        assert_eq!(
+            extension_syntax("PHP"),
+            Some(ExtensionSyntax {
+                line_flag: "//",
+                line_suffix: "",
+                removal_only_flag: Some("#"),
+                block: Some(("/*", "*/")),
+                preserves_indent: false,
+                indent_tab: false,
+            })
+        );
+        let md = extension_syntax("md").unwrap();
+        assert_eq!((md.line_flag, md.line_suffix), ("<!--", "-->"));
+        assert_eq!(extension_syntax("zig").unwrap().block, None);
+        assert!(extension_syntax("yml").unwrap().preserves_indent);
+        assert!(extension_syntax("mk").unwrap().indent_tab);
+        assert_eq!(extension_syntax("json"), None);
+        assert_eq!(extension_syntax("txt"), None);
+    }
+
+    #[test]
+    fn test_jsonc_toggles_plain_json_refused() {
+        let jsonc_file = create_test_file("test_bytewise_cfg.jsonc", "{\n  \"a\": 1\n}\n");
+        assert!(toggle_basic_singleline_comment_bytewise(jsonc_file.to_str().unwrap(), 1).is_ok());
+        assert_eq!(read_file_content(&jsonc_file), "{\n//   \"a\": 1\n}\n");
+
+        let content = "{\n  \"a\": 1\n}\n";
+        let json_file = create_test_file("test_bytewise_cfg.json", content);
+        let path = json_file.to_str().unwrap();
+        assert_eq!(
+            toggle_basic_singleline_comment_bytewise(path, 1),
+            Err(ToggleCommentError::JsonNoComments)
+        );
+        assert_eq!(
+            toggle_block_comment_bytewise(path, 0, 2),
+            Err(ToggleCommentError::JsonNoComments)
+        );
+        assert_eq!(
+            toggle_multiple_basic_comments(path, &[0, 1]),
+            Err(ToggleCommentError::JsonNoComments)
+        );
+        assert_eq!(read_file_content(&json_file), content);
+        assert!(!PathBuf::from("backup_toggle_comment_test_bytewise_cfg.json").exists());
+
+        cleanup_files(&[
+            &jsonc_file,
+            &json_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_cfg.jsonc"),
+        ]);
+    }
+
+    #[test]
+    fn test_dotfiles_toggle_with_hash() {
+        let dot_dir = PathBuf::from("./tests/dotfiles_bw");
+        std::fs::create_dir_all(&dot_dir).unwrap();
+        let mut cleanup = Vec::new();
+        for name in [".env", ".env.local", ".gitignore", ".editorconfig"] {
+            let path = dot_dir.join(name);
+            std::fs::write(&path, "KEY=1\n").unwrap();
+            toggle_basic_singleline_comment_bytewise(path.to_str().unwrap(), 0).unwrap();
+            assert_eq!(read_file_content(&path), "# KEY=1\n", "{}", name);
+            cleanup.push(path);
+            cleanup.push(PathBuf::from(format!("backup_toggle_comment_{}", name)));
+        }
+        assert_eq!(file_name_extension(".envrc"), None);
+        assert_eq!(file_name_extension("Makefile"), Some("mk"));
+
+        cleanup_files(&cleanup.iter().map(PathBuf::as_path).collect::<Vec<_>>());
+        let _ = std::fs::remove_dir(&dot_dir);
+    }
+
+    #[test]
+    fn test_markdown_html_comment_roundtrip() {
+        // LF, CRLF, empty line, and last line without newline
+        let original = "# Title\ndraft\r\n\nend";
+        let test_file = create_test_file("test_bytewise_html.md", original);
+        let path = test_file.to_str().unwrap();
+
+        for line in 0..4 {
+            assert!(toggle_basic_singleline_comment_bytewise(path, line).is_ok());
+        }
+        assert_eq!(
+            read_file_content(&test_file),
+            "<!-- # Title -->\n<!-- draft -->\r\n<!--  -->\n<!-- end -->"
+        );
+
+        for line in 0..4 {
+            assert!(toggle_basic_singleline_comment_bytewise(path, line).is_ok());
+        }
+        assert_eq!(read_file_content(&test_file), original);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_html.md"),
+        ]);
+    }
+
+    #[test]
+    fn test_markdown_prefix_without_suffix_removed() {
+        // Only the prefix is required; a missing " -->" leaves the rest as-is
+        let test_file = create_test_file("test_bytewise_html_prefix.markdown", "<!-- a\nb\n");
+        assert!(toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0).is_ok());
+        assert_eq!(read_file_content(&test_file), "a\nb\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_html_prefix.markdown"),
+        ]);
+    }
+
+    #[test]
+    fn test_removal_only_flag() {
+        assert_eq!(determine_removal_only_flag("php"), Some(CommentFlag::Hash));
+        assert_eq!(determine_removal_only_flag("rs"), None);
+        assert_eq!(determine_removal_only_flag("py"), None);
+    }
+
+    #[test]
+    fn test_php_hash_comment_removed_slash_added() {
+        let content = "# hash\n$a = 1;\n// slash\n";
+        let test_file = create_test_file("test_bytewise_php_alt.php", content);
+        let path = test_file.to_str().unwrap();
+
+        assert!(toggle_basic_singleline_comment_bytewise(path, 0).is_ok());
+        assert!(toggle_basic_singleline_comment_bytewise(path, 1).is_ok());
+        assert!(toggle_basic_singleline_comment_bytewise(path, 2).is_ok());
+        assert_eq!(read_file_content(&test_file), "hash\n// $a = 1;\nslash\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_php_alt.php"),
+        ]);
+    }
+
+    // ========================================
+    // find_and_detect_tag_state() Tests
+    // ========================================
+
+    #[test]
+    fn test_find_and_detect_line_0() {
+        let content = "code\nmore\n";
+        let test_file = create_test_file("test_bytewise_find_0.rs", content);
+
+        let result =
+            find_and_detect_tag_state(test_file.to_str().unwrap(), 0, CommentFlag::DoubleSlash);
+
+        assert!(result.is_ok());
+        let found = result.unwrap();
+        assert!(found.is_some());
+        let (pos, has_tag) = found.unwrap();
+        assert_eq!(pos, 0); // Line 0 starts at position 0
+        assert!(!has_tag); // "code" has no "//" tag
+
+        cleanup_files(&[&test_file]);
+    }
+
+    #[test]
+    fn test_find_and_detect_line_1() {
+        let content = "line 0\n// line 1\nline 2\n";
+        let test_file = create_test_file("test_bytewise_find_1.rs", content);
+
+        let result =
+            find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::DoubleSlash);
+
+        assert!(result.is_ok());
+        let found = result.unwrap();
+        assert!(found.is_some());
+        let (pos, has_tag) = found.unwrap();
+        assert_eq!(pos, 7); // After "line 0\n"
+        assert!(has_tag); // Has "// " pattern
+
+        cleanup_files(&[&test_file]);
+    }
+
+    #[test]
+    fn test_find_and_detect_line_not_found() {
+        let content = "line 0\nline 1\n";
+        let test_file = create_test_file("test_bytewise_notfound.rs", content);
+
+        let result =
+            find_and_detect_tag_state(test_file.to_str().unwrap(), 10, CommentFlag::DoubleSlash);
+
+        assert!(result.is_ok());
+        let found = result.unwrap();
+        assert!(found.is_none()); // Line 10 doesn't exist - Ok(None)
+
+        cleanup_files(&[&test_file]);
+    }
+
+    #[test]
+    fn test_find_and_detect_no_space_after_tag() {
+        let content = "line 0\n//code\nline 2\n";
+        let test_file = create_test_file("test_bytewise_nospace.rs", content);
+
+        let result =
+            find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::DoubleSlash);
+
+        assert!(result.is_ok());
+        let found = result.unwrap();
+        assert!(found.is_some());
+        let (_pos, has_tag) = found.unwrap();
+        assert!(!has_tag); // "//code" doesn't match "// " pattern
+
+        cleanup_files(&[&test_file]);
+    }
+
+    #[test]
+    fn test_find_and_detect_empty_line() {
+        let content = "line 0\n\nline 2\n";
+        let test_file = create_test_file("test_bytewise_empty.rs", content);
+
+        let result =
+            find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::DoubleSlash);
+
+        assert!(result.is_ok());
+        let found = result.unwrap();
+        assert!(found.is_some());
+        let (_pos, has_tag) = found.unwrap();
+        assert!(!has_tag); // Empty line has no tag
+
+        cleanup_files(&[&test_file]);
+    }
+
+    #[test]
+    fn test_find_and_detect_hash_comment() {
+        let content = "line 0\n# code\nline 2\n";
+        let test_file = create_test_file("test_bytewise_hash.py", content);
+
+        let result = find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::Hash);
+
+        assert!(result.is_ok());
+        let found = result.unwrap();
+        assert!(found.is_some());
+        let (_pos, has_tag) = found.unwrap();
+        assert!(has_tag); // "# " pattern found
+
+        cleanup_files(&[&test_file]);
+    }
+
+    #[test]
+    fn test_find_and_detect_triple_slash() {
+        let content = "line 0\n/// docs\nline 2\n";
+        let test_file = create_test_file("test_bytewise_triple.rs", content);
+
+        let result =
+            find_and_detect_tag_state(test_file.to_str().unwrap(), 1, CommentFlag::TripleSlash);
+
+        assert!(result.is_ok());
+        let found = result.unwrap();
+        assert!(found.is_some());
+        let (_pos, has_tag) = found.unwrap();
+        assert!(has_tag); // "/// " pattern found
+
+        cleanup_files(&[&test_file]);
+    }
+
+    #[test]
+    fn test_triple_slash_blank_doc_line_removed() {
+        let content = "/// a\n///\n///\r\n///x\nfn f() {}\n///";
+        let test_file = create_test_file("test_bytewise_triple_blank.rs", content);
+        let path = test_file.to_str().unwrap();
+
+        // Flag at the end of the line (LF, CRLF, end of input) is commented
+        for (line, expected) in [(1, true), (2, true), (3, false), (5, true)] {
+            let found = find_and_detect_tag_state(path, line, CommentFlag::TripleSlash);
+            assert_eq!(found.unwrap().map(|(_, tag)| tag), Some(expected));
+        }
+
+        for line in [1, 2, 5] {
+            toggle_rust_docstring_singleline_comment_bytewise(path, line).unwrap();
+        }
+        assert_eq!(
+            read_file_content(&test_file),
+            "/// a\n\n\r\n///x\nfn f() {}\n"
+        );
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_triple_blank.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_bare_hash_toggles_off() {
+        let test_file = create_test_file("test_bytewise_bare_hash.py", "x\n#\n");
+        let path = test_file.to_str().unwrap();
+
+        toggle_basic_singleline_comment_bytewise(path, 1).unwrap();
+        assert_eq!(read_file_content(&test_file), "x\n\n");
+        toggle_basic_singleline_comment_bytewise(path, 1).unwrap();
+        assert_eq!(read_file_content(&test_file), "x\n# \n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_bare_hash.py"),
+        ]);
+    }
+
+    // ========================================
+    // write_toggled_file_bytewise() Tests
+    // ========================================
+
+    #[test]
+    fn test_write_bytewise_add_tag() {
+        let content = "line 0\ncode\nline 2\n";
+        let test_file = create_test_file("test_bytewise_write_add.rs", content);
+
+        let temp_file = PathBuf::from("temp_test_bytewise_add.rs");
+
+        let result = write_toggled_file_bytewise(
+            &test_file,
+            &temp_file,
+            1,
+            7,     // Position after "line 0\n"
+            false, // has_tag = false (ADD mode)
+            CommentFlag::DoubleSlash,
+        );
+
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&temp_file);
+        assert_eq!(new_content, "line 0\n// code\nline 2\n");
+
+        cleanup_files(&[&test_file, &temp_file]);
+    }
+
+    #[test]
+    fn test_write_bytewise_remove_tag() {
+        let content = "line 0\n// code\nline 2\n";
+        let test_file = create_test_file("test_bytewise_write_remove.rs", content);
+
+        let temp_file = PathBuf::from("temp_test_bytewise_remove.rs");
+
+        let result = write_toggled_file_bytewise(
+            &test_file,
+            &temp_file,
+            1,
+            7,    // Position after "line 0\n"
+            true, // has_tag = true (REMOVE mode)
+            CommentFlag::DoubleSlash,
+        );
+
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&temp_file);
+        assert_eq!(new_content, "line 0\ncode\nline 2\n");
+
+        cleanup_files(&[&test_file, &temp_file]);
+    }
+
+    #[test]
+    fn test_write_bytewise_preserves_other_lines() {
+        let content = "line 0\nline 1\nline 2\nline 3\n";
+        let test_file = create_test_file("test_bytewise_preserve.rs", content);
+
+        let temp_file = PathBuf::from("temp_test_bytewise_preserve.rs");
+
+        let result = write_toggled_file_bytewise(
+            &test_file,
+            &temp_file,
+            1,
+            7,     // After "line 0\n"
+            false, // ADD mode
+            CommentFlag::DoubleSlash,
+        );
+
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&temp_file);
+        assert_eq!(new_content, "line 0\n// line 1\nline 2\nline 3\n");
+
+        cleanup_files(&[&test_file, &temp_file]);
+    }
+
+    #[test]
+    fn test_write_bytewise_empty_line_add() {
+        let content = "line 0\n\nline 2\n";
+        let test_file = create_test_file("test_bytewise_empty_add.rs", content);
+
+        let temp_file = PathBuf::from("temp_test_bytewise_empty_add.rs");
+
+        let result = write_toggled_file_bytewise(
+            &test_file,
+            &temp_file,
+            1,
+            7,     // After "line 0\n"
+            false, // ADD mode
+            CommentFlag::DoubleSlash,
+        );
+
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&temp_file);
+        assert_eq!(new_content, "line 0\n// \nline 2\n"); // Tag added to empty line
+
+        cleanup_files(&[&test_file, &temp_file]);
+    }
+
+    #[test]
+    fn test_write_bytewise_crlf_preservation() {
+        let content = "line 0\r\ncode\r\nline 2\r\n";
+        let test_file = create_test_file("test_bytewise_crlf.rs", content);
+
+        let temp_file = PathBuf::from("temp_test_bytewise_crlf.rs");
+
+        let result = write_toggled_file_bytewise(
+            &test_file,
+            &temp_file,
+            1,
+            8,     // After "line 0\r\n"
+            false, // ADD mode
+            CommentFlag::DoubleSlash,
+        );
+
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&temp_file);
+        // CRLF should be preserved
+        assert_eq!(new_content, "line 0\r\n// code\r\nline 2\r\n");
+
+        cleanup_files(&[&test_file, &temp_file]);
+    }
+
+    // ========================================
+    // Integration Tests - Full Toggle
+    // ========================================
+
+    #[test]
+    fn test_bytewise_toggle_full_add() {
+        let content = "fn main() {}\n";
+        let test_file = create_test_file("test_bytewise_full_add.rs", content);
+
+        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "// fn main() {}\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_full_add.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_bytewise_toggle_full_remove() {
+        let content = "// fn main() {}\n";
+        let test_file = create_test_file("test_bytewise_full_remove.rs", content);
+
+        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "fn main() {}\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_full_remove.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_bytewise_toggle_roundtrip() {
+        let original = "code\n";
+        let test_file = create_test_file("test_bytewise_roundtrip.rs", original);
+
+        // Toggle on
+        let result1 = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result1.is_ok());
+
+        let content1 = read_file_content(&test_file);
+        assert_eq!(content1, "// code\n");
+
+        // Toggle off
+        let result2 = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result2.is_ok());
+
+        let content2 = read_file_content(&test_file);
+        assert_eq!(content2, original);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_roundtrip.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_bytewise_toggle_middle_line() {
+        let content = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_bytewise_middle.rs", content);
+
+        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 1);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "line 0\n// line 1\nline 2\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_middle.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_bytewise_toggle_last_line() {
+        let content = "line 0\nline 1\nline 2";
+        let test_file = create_test_file("test_bytewise_last.rs", content);
+
+        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 2);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "line 0\nline 1\n// line 2");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_last.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_bytewise_toggle_python() {
+        let content = "print('hello')\n";
+        let test_file = create_test_file("test_bytewise_python.py", content);
+
+        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "# print('hello')\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_python.py"),
+        ]);
+    }
+
+    #[test]
+    fn test_bytewise_line_not_found_error() {
+        let content = "line 0\nline 1\n";
+        let test_file = create_test_file("test_bytewise_notfound_err.rs", content);
+
+        let result = toggle_basic_singleline_comment_bytewise(test_file.to_str().unwrap(), 10);
+        assert!(matches!(
+            result,
+            Err(ToggleCommentError::LineNotFound { .. })
+        ));
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_bytewise_notfound_err.rs"),
+        ]);
+    }
+}
+
+// ============================================================================
+// TESTS
+// ============================================================================
+#[cfg(all(test, feature = "fs"))]
+/// Helper: create a temporary test file with given content
+fn create_test_file(filename: &str, content: &str) -> PathBuf {
+    use std::io::Write;
+    // Create tests directory if it doesn't exist
+    let tests_dir = PathBuf::from("./tests");
+    std::fs::create_dir_all(&tests_dir).expect("Failed to create tests directory");
+
+    // Create file path within tests directory
+    let path = tests_dir.join(filename);
+    let mut file = File::create(&path).expect("Failed to create test file");
+    file.write_all(content.as_bytes())
+        .expect("Failed to write test file");
+    path
+}
+#[cfg(all(test, feature = "fs"))]
+/// Helper: read file content as string
+fn read_file_content(path: &Path) -> String {
+    std::fs::read_to_string(path).expect("Failed to read file")
+}
+#[cfg(all(test, feature = "fs"))]
+/// Helper: cleanup test files (and the checksum sidecar of a backup)
+fn cleanup_files(paths: &[&Path]) {
+    for path in paths {
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(backup_checksum_path(path));
+    }
+}
+
+// =================
+// Indent / Unindent
+// =================
+/*
+
+The standard
+indent/unindent functionality
+-- ctrl+brackets is standard --
+is simpliar to but simpler than
+comment-toggling
+-- ctrl + / is standard --
+
+Two functionalities:
+- single line
+- range of lines
+
+ */
+// ============================================================================
+// INDENT/UNINDENT FUNCTIONALITY
+// ============================================================================
+
+/// Number of spaces to add/remove for indent/unindent operations
+/// (default `ToggleOptions::indent_width`)
+const INDENT_SPACES: usize = 4;
+
+/// Widest indent step a project config or option parser should accept
+pub const MAX_INDENT_WIDTH: usize = 16;
+
+/// What one indent step adds (and unindent removes, at most) at column 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IndentStep {
+    /// `width` spaces (`ToggleOptions::indent_width`)
+    Spaces(usize),
+    /// One tab (`ToggleOptions::indent_tab`; `indents_with_tab()`: Makefiles)
+    Tab,
+}
+
+impl IndentStep {
+    /// Step for a target: a tab under `options.indent_tab` or where its
+    /// language requires one, else the width
+    #[cfg(feature = "fs")]
+    fn for_target(absolute_path: &Path, options: &ToggleOptions) -> IndentStep {
+        if options.indent_tab {
+            return IndentStep::Tab;
+        }
+        match target_extension(absolute_path, options) {
+            Some(extension) if indents_with_tab(&extension) => IndentStep::Tab,
+            _ => IndentStep::Spaces(options.indent_width),
+        }
+    }
+
+    /// Byte this step is made of, and how many of it
+    fn unit(self) -> (u8, usize) {
+        match self {
+            IndentStep::Spaces(width) => (b' ', width),
+            IndentStep::Tab => (b'\t', 1),
+        }
+    }
+}
+
+// ============================================================================
+// ERROR SECTION: ERROR HANDLING SYSTEM (start)
+// ============================================================================
+
+/// Errors that can occur during indent/unindent operations
+///
+/// All variants are Copy - no heap allocation, no string storage.
+/// Reuses same design pattern as ToggleCommentError for consistency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToggleIndentError {
+    /// The specified file was not found
+    FileNotFound,
+
+    /// The requested line index exceeds the file's line count
+    LineNotFound { requested: usize, file_lines: usize },
+
+    /// I/O operation failed
+    IoError(IoOperation),
+
+    /// The OS refused access at the given stage (see ToggleCommentError)
+    PermissionDenied(IoOperation),
+
+    /// Path conversion or manipulation error
+    PathError,
+
+    /// Temp file size is outside the expected bounds (see ToggleCommentError)
+    IncompleteWrite,
+
+    /// Target file has the read-only attribute (checked before any backup)
+    ReadOnlyTarget,
+
+    /// File is larger than the configured limit (`ToggleOptions::max_file_size`)
+    FileTooLarge { size: u64, max: u64 },
+
+    /// Target line is inside a `toggle_comment: off` ... `on` region
+    ProtectedRegion { line: usize },
+
+    /// Stopped by `request_cancel()` or the deadline (see ToggleCommentError)
+    Cancelled,
+
+    /// The original changed on disk before the replace (see ToggleCommentError)
+    ConcurrentModification,
+
+    /// The target is a FIFO, device node or socket
+    NotRegularFile,
+
+    /// The target is one of this crate's own backups or temp files
+    OwnArtifact,
+
+    /// Not valid UTF-8 with `utf8_strict` (see ToggleCommentError)
+    InvalidUtf8 { line: usize },
+}
+
+impl std::fmt::Display for ToggleIndentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToggleIndentError::FileNotFound => write!(f, "File not found"),
+            ToggleIndentError::LineNotFound {
+                requested,
+                file_lines,
+            } => {
+                write!(
+                    f,
+                    "Line {} not found (file has {} lines)",
+                    requested, file_lines
+                )
+            }
+            ToggleIndentError::IoError(op) => write!(f, "IO error: {:?}", op),
+            ToggleIndentError::PermissionDenied(op) => write!(f, "Permission denied: {:?}", op),
+            ToggleIndentError::PathError => write!(f, "Path error"),
+            ToggleIndentError::IncompleteWrite => {
+                write!(f, "Incomplete write (original unchanged)")
+            }
+            ToggleIndentError::ReadOnlyTarget => write!(f, "Target file is read-only"),
+            ToggleIndentError::FileTooLarge { size, max } => {
+                write!(f, "File too large ({} bytes, limit {})", size, max)
+            }
+            ToggleIndentError::ProtectedRegion { line } => {
+                write!(
+                    f,
+                    "Line {} is in a protected region (toggle_comment: off)",
+                    line
+                )
+            }
+            ToggleIndentError::NotRegularFile => {
+                write!(f, "Not a regular file (FIFO, device node or socket)")
+            }
+            ToggleIndentError::OwnArtifact => {
+                write!(f, "Target is a toggle_comment backup or temp file")
+            }
+            ToggleIndentError::InvalidUtf8 { line } => {
+                write!(f, "Line {} is not valid UTF-8 (--utf8-strict)", line)
+            }
+            ToggleIndentError::Cancelled if timed_out() => {
+                write!(f, "Timed out (original unchanged)")
+            }
+            ToggleIndentError::Cancelled => write!(f, "Cancelled (original unchanged)"),
+            ToggleIndentError::ConcurrentModification => {
+                write!(
+                    f,
+                    "File changed on disk during the operation (not overwritten)"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ToggleIndentError {}
+
+impl ToggleIndentError {
+    /// Classify a failed std::io call at a given stage (see ToggleCommentError)
+    fn from_io_error(error: &std::io::Error, operation: IoOperation) -> Self {
+        if is_cancel_error(error) {
+            ToggleIndentError::Cancelled
+        } else if is_concurrent_modification(error) {
+            ToggleIndentError::ConcurrentModification
+        } else if error.kind() == std::io::ErrorKind::PermissionDenied {
+            ToggleIndentError::PermissionDenied(operation)
+        } else {
+            ToggleIndentError::IoError(operation)
+        }
+    }
+
+    /// Exit code for this error (the same as the ToggleCommentError variant
+    /// of the same name)
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            ToggleIndentError::FileNotFound => ExitCode::FileNotFound,
+            ToggleIndentError::LineNotFound { .. } => ExitCode::NotFound,
+            ToggleIndentError::IoError(_) => ExitCode::Io,
+            ToggleIndentError::PermissionDenied(_) => ExitCode::PermissionDenied,
+            ToggleIndentError::PathError => ExitCode::Path,
+            ToggleIndentError::IncompleteWrite => ExitCode::IncompleteWrite,
+            ToggleIndentError::ReadOnlyTarget => ExitCode::ReadOnly,
+            ToggleIndentError::FileTooLarge { .. } => ExitCode::FileTooLarge,
+            ToggleIndentError::ProtectedRegion { .. } => ExitCode::ProtectedRegion,
+            ToggleIndentError::ConcurrentModification => ExitCode::ConcurrentModification,
+            ToggleIndentError::NotRegularFile => ExitCode::NotRegularFile,
+            ToggleIndentError::OwnArtifact => ExitCode::OwnArtifact,
+            ToggleIndentError::InvalidUtf8 { .. } => ExitCode::InvalidUtf8,
+            ToggleIndentError::Cancelled => ExitCode::Cancelled,
+        }
+    }
+}
+
+// ============================================================================
+// ERROR SECTION: ERROR HANDLING SYSTEM (end)
+// ============================================================================
+
+// ============================================================================
+// PHASE 2: INDENT/UNINDENT - BYTEWISE OPERATIONS
+// ============================================================================
+
+/// Helper: Find line start position (extracted for reuse)
+///
+/// # Overview
+/// Reads file byte-by-byte counting newlines until target line is found.
+/// Returns byte offset where target line begins.
+///
+/// # Arguments
+/// * `file_path` - Path to source file
+/// * `target_line` - Zero-indexed line number to find
+///
+/// # Returns
+/// * `Ok(Some(position))` - Line found at byte offset
+/// * `Ok(None)` - Line not found (file has fewer lines)
+/// * `Err(ToggleIndentError)` - File operation failed
+///
+/// # Example
+/// ```text
+/// File:  "line 0\nline 1\nline 2\n"
+///
+/// find_line_start_position(path, 0) → Ok(Some(0))
+/// find_line_start_position(path, 1) → Ok(Some(7))
+/// find_line_start_position(path, 2) → Ok(Some(14))
+/// find_line_start_position(path, 10) → Ok(None)
+/// ```
+#[cfg(feature = "fs")]
+pub fn find_line_start_position(
+    file_path: &str,
+    target_line: usize,
+) -> Result<Option<u64>, ToggleIndentError> {
+    Ok(find_line_start_tracked(file_path, target_line, target_line)?.map(|(pos, _)| pos))
+}
+
+/// Find a line start, noting protected lines passed on the way
+///
+/// # Overview
+/// Same scan as `find_line_start_position()`, with every byte also fed to a
+/// `ProtectedRegionTracker` (see PROTECTED REGIONS). Reads on to the end of
+/// the target line, so an `off` sentinel there counts.
+///
+/// # Returns
+/// * `Ok(Some((position, protected)))` - Line start, and the first protected
+///   line in `protect_from..=target_line` (sentinels included), if any
+/// * `Ok(None)` - Line not found (file has fewer lines)
+/// * `Err(ToggleIndentError)` - File operation failed
+#[cfg(feature = "fs")]
+fn find_line_start_tracked(
+    file_path: &str,
+    target_line: usize,
+    protect_from: usize,
+) -> Result<Option<(u64, Option<usize>)>, ToggleIndentError> {
+    let mut file = match File::open(file_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleIndentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+    let mut byte_position: u64 = 0;
+    let mut current_line: usize = 0;
+    let mut protection = ProtectedRegionTracker::new();
+    let mut first_protected: Option<usize> = None;
+    // Line 0 always starts at position 0
+    let mut line_start: Option<u64> = (target_line == 0).then_some(0);
+
+    loop {
+        // Safety check
+        if byte_position >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleIndentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleIndentError::Cancelled);
+        }
+
+        let bytes_read = match file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            // Line not found, or the target is the last line
+            if line_start.is_some() {
+                protection.finish();
+                if first_protected.is_none()
+                    && current_line >= protect_from
+                    && protection.line_protected()
+                {
+                    first_protected = Some(current_line);
+                }
+            }
+            return Ok(line_start.map(|pos| (pos, first_protected)));
+        }
+
+        protection.push_byte(byte_bucket[0]);
+
+        if byte_bucket[0] == b'\n' {
+            if first_protected.is_none()
+                && current_line >= protect_from
+                && protection.line_protected()
+            {
+                first_protected = Some(current_line);
+            }
+            if current_line == target_line {
+                return Ok(line_start.map(|pos| (pos, first_protected)));
+            }
+            current_line += 1;
+            if current_line == target_line {
+                line_start = Some(byte_position + 1);
+            }
+        }
+
+        byte_position += 1;
+    }
+}
+
+/// Write file with 4 spaces added at start of target line
+///
+/// # Overview
+/// Copies source to dest, inserting 4 spaces at column 0 of target line.
+/// Simple three-part copy: before, modified line, after.
+///
+/// # Arguments
+/// * `source_path` - Original file
+/// * `dest_path` - Temp file for output
+/// * `line_start_pos` - Byte offset where target line begins
+///
+/// # Returns
+/// * `Ok(())` - File written successfully
+/// * `Err(ToggleIndentError)` - Write operation failed
+///
+/// # Memory
+/// - 1 byte buffer only
+/// - No heap allocation
+#[cfg(feature = "fs")]
+pub fn write_indented_file_bytewise(
+    source_path: &Path,
+    dest_path: &Path,
+    line_start_pos: u64,
+) -> Result<(), ToggleIndentError> {
+    write_indented_file_width(
+        source_path,
+        dest_path,
+        line_start_pos,
+        IndentStep::Spaces(INDENT_SPACES),
+    )
+}
+
+/// `write_indented_file_bytewise()` adding `step` instead of 4 spaces
+#[cfg(feature = "fs")]
+fn write_indented_file_width(
+    source_path: &Path,
+    dest_path: &Path,
+    line_start_pos: u64,
+    step: IndentStep,
+) -> Result<(), ToggleIndentError> {
+    let mut source_file = match File::open(source_path) {
+        Ok(f) => TimedIo(f),
+        Err(e) => return Err(ToggleIndentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let dest_file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest_path)
+    {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleIndentError::from_io_error(&e, IoOperation::Create)),
+    };
+
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, TimedIo(dest_file));
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+    let mut byte_position: u64 = 0;
+
+    // PART A: Copy before target line
+    while byte_position < line_start_pos {
+        if byte_position >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleIndentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleIndentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            return Err(ToggleIndentError::IoError(IoOperation::Read));
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleIndentError::IoError(IoOperation::Write));
+        }
+
+        byte_position += 1;
+    }
+
+    // PART B: Add the step (spaces or a tab) at column 0, then copy rest of line
+    let (unit, count) = step.unit();
+    for _ in 0..count {
+        if writer.write_all(&[unit]).is_err() {
+            return Err(ToggleIndentError::IoError(IoOperation::Write));
+        }
+    }
+
+    // Copy rest of line
+    loop {
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            break; // EOF
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleIndentError::IoError(IoOperation::Write));
+        }
+
+        if byte_bucket[0] == b'\n' {
+            break; // End of line
+        }
+    }
+
+    // PART C: Copy rest of file
+    let mut remaining_bytes: u64 = 0;
+    loop {
+        if remaining_bytes >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleIndentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleIndentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleIndentError::IoError(IoOperation::Write));
+        }
+
+        remaining_bytes += 1;
+    }
+
+    if writer.flush().is_err() {
+        return Err(ToggleIndentError::IoError(IoOperation::Flush));
+    }
+
+    Ok(())
+}
+
+/// Write file with up to 4 spaces removed from start of target line
+///
+/// # Overview
+/// Copies source to dest, removing up to 4 spaces from column 0 of target line.
+/// If line has fewer than 4 spaces, removes only what's there.
+///
+/// # Arguments
+/// * `source_path` - Original file
+/// * `dest_path` - Temp file for output
+/// * `line_start_pos` - Byte offset where target line begins
+///
+/// # Returns
+/// * `Ok(())` - File written successfully
+/// * `Err(ToggleIndentError)` - Write operation failed
+///
+/// # Memory
+/// - 1 byte buffer only
+/// - No heap allocation
+#[cfg(feature = "fs")]
+pub fn write_unindented_file_bytewise(
+    source_path: &Path,
+    dest_path: &Path,
+    line_start_pos: u64,
+) -> Result<(), ToggleIndentError> {
+    write_unindented_file_trimmed(
+        source_path,
+        dest_path,
+        line_start_pos,
+        IndentStep::Spaces(INDENT_SPACES),
+        false,
+    )
+    .map(|_| ())
+}
+
+/// `write_unindented_file_bytewise()` removing up to `step` (spaces or a tab),
+/// optionally trimming the target line's trailing spaces/tabs (see
+/// `TrailingWhitespaceTrimmer`)
+///
+/// # Returns
+/// * `Ok(trimmed)` - File written; `trimmed` trailing bytes dropped
+/// * `Err(ToggleIndentError)` - Write operation failed
+#[cfg(feature = "fs")]
+fn write_unindented_file_trimmed(
+    source_path: &Path,
+    dest_path: &Path,
+    line_start_pos: u64,
+    step: IndentStep,
+    trim_trailing: bool,
+) -> Result<u64, ToggleIndentError> {
+    let mut source_file = match File::open(source_path) {
+        Ok(f) => TimedIo(f),
+        Err(e) => return Err(ToggleIndentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let dest_file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest_path)
+    {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleIndentError::from_io_error(&e, IoOperation::Create)),
+    };
+
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, TimedIo(dest_file));
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+    let mut byte_position: u64 = 0;
+
+    // PART A: Copy before target line
+    while byte_position < line_start_pos {
+        if byte_position >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleIndentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleIndentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            return Err(ToggleIndentError::IoError(IoOperation::Read));
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleIndentError::IoError(IoOperation::Write));
+        }
+
+        byte_position += 1;
+    }
+
+    // PART B: Skip up to `width` step bytes at column 0, then copy rest of line
+    let mut line_writer = TrailingWhitespaceTrimmer::new(&mut writer, trim_trailing);
+    let (unit, width) = step.unit();
+    let mut spaces_skipped: usize = 0;
+    loop {
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            break; // EOF
+        }
+
+        // If it's a step byte and we haven't skipped `width` yet, skip it
+        if byte_bucket[0] == unit && spaces_skipped < width {
+            spaces_skipped += 1;
+            continue; // Skip this space, don't write it
+        }
+
+        // Not a space, or already skipped `width` - write rest of line
+        if line_writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleIndentError::IoError(IoOperation::Write));
+        }
+
+        if byte_bucket[0] == b'\n' {
+            break; // End of line
+        }
+
+        // Copy rest of line
+        loop {
+            let bytes_read = match source_file.read(&mut byte_bucket) {
+                Ok(n) => n,
+                Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
+            };
+
+            if bytes_read == 0 {
+                break; // EOF
+            }
+
+            if line_writer.write_all(&byte_bucket).is_err() {
+                return Err(ToggleIndentError::IoError(IoOperation::Write));
+            }
+
+            if byte_bucket[0] == b'\n' {
+                break; // End of line
+            }
+        }
+
+        break;
+    }
+
+    if line_writer.finish().is_err() {
+        return Err(ToggleIndentError::IoError(IoOperation::Write));
+    }
+    let trimmed = line_writer.trimmed;
+
+    // PART C: Copy rest of file
+    let mut remaining_bytes: u64 = 0;
+    loop {
+        if remaining_bytes >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleIndentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleIndentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleIndentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleIndentError::IoError(IoOperation::Write));
+        }
+
+        remaining_bytes += 1;
+    }
+
+    if writer.flush().is_err() {
+        return Err(ToggleIndentError::IoError(IoOperation::Flush));
+    }
+
+    Ok(trimmed)
+}
+
+/// Add 4 spaces to the start of a specific line (bytewise)
+///
+/// # Overview
+/// Bytewise implementation - adds exactly 4 spaces at column 0 of target line.
+/// No heap allocation, single byte buffer.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `line_number` - Zero-indexed line number to indent
+///
+/// # Returns
+/// * `Ok(())` - Line indented successfully
+/// * `Err(ToggleIndentError)` - Specific error code
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::indent_line_bytewise;
+///
+/// match indent_line_bytewise("./src/main.rs", 5) {
+///     Ok(()) => println!("Line 5 indented"),
+///     Err(e) => eprintln!("Failed: {:?}", e),
+/// }
+/// ```
+///
+/// # Behavior
+/// ```text
+/// Before: "code"
+/// After:  "    code"
+///
+/// Before: "  code"  (already indented 2)
+/// After:  "      code"  (now indented 6)
+/// ```
+#[cfg(feature = "fs")]
+pub fn indent_line_bytewise(file_path: &str, line_number: usize) -> Result<(), ToggleIndentError> {
+    indent_line_bytewise_with_options(file_path, line_number, &ToggleOptions::default())
+}
+
+/// Same as `indent_line_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn indent_line_bytewise_with_options(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleIndentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleIndentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleIndentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleIndentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_indent_target(&absolute_path, options)?;
+
+    // Find line start position (refusing protected lines)
+    let line_start_pos = match find_line_start_tracked(file_path, line_number, line_number)? {
+        Some((_, Some(line))) => return Err(ToggleIndentError::ProtectedRegion { line }),
+        Some((pos, None)) => pos,
+        None => {
+            return Err(ToggleIndentError::LineNotFound {
+                requested: line_number,
+                file_lines: 0,
+            });
+        }
+    };
+
+    // Get filename for backup
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleIndentError::PathError),
+    };
+
+    // Create backup
+    let backup_path = backup_path_for(&filename, options);
+
+    let trace = StageTrace::new(options, "indent");
+
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleIndentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    // Create temp file
+    let temp_filename = format!("temp_indent_bytewise_{}_{}", std::process::id(), filename);
+    let temp_path = temp_file_path(options.temp_dir.as_deref(), &temp_filename);
+    let _remove_temp = TempFileGuard(&temp_path);
+
+    // Write indented file
+    let step = IndentStep::for_target(&absolute_path, options);
+    let process_result = trace.run_streaming(&absolute_path, &temp_path, || {
+        write_indented_file_width(&absolute_path, &temp_path, line_start_pos, step)
+    });
+
+    // Handle result
+    match process_result {
+        Ok(()) => {
+            if !trace.check(TraceStage::Verify, || {
+                replacement_size_is_plausible(&absolute_path, &temp_path, 0, step.unit().1 as u64)
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleIndentError::IncompleteWrite);
+            }
+            if let Err(e) = trace.run(TraceStage::Replace, || {
+                replace_original(
+                    &temp_path,
+                    &absolute_path,
+                    options.temp_dir.as_deref(),
+                    options.durability,
+                    &snapshot,
+                )
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleIndentError::from_io_error(&e, IoOperation::Replace));
+            }
+
+            if remove_temp_file(&temp_path).is_err() {
+                #[cfg(debug_assertions)]
+                eprintln!("Warning: Failed to clean up temp file");
+            }
+
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Remove up to 4 spaces from the start of a specific line (bytewise)
+///
+/// # Overview
+/// Bytewise implementation - removes up to 4 spaces from column 0.
+/// If line has fewer than 4 spaces, removes only what's there.
+/// No heap allocation, single byte buffer.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `line_number` - Zero-indexed line number to unindent
+///
+/// # Returns
+/// * `Ok(())` - Line unindented successfully (even if no spaces removed)
+/// * `Err(ToggleIndentError)` - Specific error code
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::unindent_line_bytewise;
+///
+/// match unindent_line_bytewise("./src/main.rs", 5) {
+///     Ok(()) => println!("Line 5 unindented"),
+///     Err(e) => eprintln!("Failed: {:?}", e),
+/// }
+/// ```
+///
+/// # Behavior
+/// ```text
+/// Before: "    code"  (4 spaces)
+/// After:  "code"      (removed 4)
+///
+/// Before: "  code"    (2 spaces)
+/// After:  "code"      (removed 2)
+///
+/// Before: "code"      (0 spaces)
+/// After:  "code"      (removed 0 - no-op)
+/// ```
+#[cfg(feature = "fs")]
+pub fn unindent_line_bytewise(
+    file_path: &str,
+    line_number: usize,
+) -> Result<(), ToggleIndentError> {
+    unindent_line_bytewise_with_options(file_path, line_number, &ToggleOptions::default())
+}
+
+/// Same as `unindent_line_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn unindent_line_bytewise_with_options(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleIndentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleIndentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleIndentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleIndentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_indent_target(&absolute_path, options)?;
+
+    // Find line start position (refusing protected lines)
+    let line_start_pos = match find_line_start_tracked(file_path, line_number, line_number)? {
+        Some((_, Some(line))) => return Err(ToggleIndentError::ProtectedRegion { line }),
+        Some((pos, None)) => pos,
+        None => {
+            return Err(ToggleIndentError::LineNotFound {
+                requested: line_number,
+                file_lines: 0,
+            });
+        }
+    };
+
+    // Get filename for backup
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleIndentError::PathError),
+    };
+
+    // Create backup
+    let backup_path = backup_path_for(&filename, options);
+
+    let trace = StageTrace::new(options, "unindent");
+
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleIndentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    // Create temp file
+    let temp_filename = format!("temp_unindent_bytewise_{}_{}", std::process::id(), filename);
+    let temp_path = temp_file_path(options.temp_dir.as_deref(), &temp_filename);
+    let _remove_temp = TempFileGuard(&temp_path);
+
+    // Write unindented file
+    let step = IndentStep::for_target(&absolute_path, options);
+    let process_result = trace.run_streaming(&absolute_path, &temp_path, || {
+        write_unindented_file_trimmed(
+            &absolute_path,
+            &temp_path,
+            line_start_pos,
+            step,
+            options.trim_trailing,
+        )
+    });
+
+    // Handle result
+    match process_result {
+        Ok(trimmed) => {
+            let max_shrink = step.unit().1 as u64 + trimmed;
+            if !trace.check(TraceStage::Verify, || {
+                replacement_size_is_plausible(&absolute_path, &temp_path, max_shrink, 0)
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleIndentError::IncompleteWrite);
+            }
+            if let Err(e) = trace.run(TraceStage::Replace, || {
+                replace_original(
+                    &temp_path,
+                    &absolute_path,
+                    options.temp_dir.as_deref(),
+                    options.durability,
+                    &snapshot,
+                )
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleIndentError::from_io_error(&e, IoOperation::Replace));
+            }
+
+            if remove_temp_file(&temp_path).is_err() {
+                #[cfg(debug_assertions)]
+                eprintln!("Warning: Failed to clean up temp file");
+            }
+
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Add 4 spaces to multiple lines using simple loop (bytewise)
+///
+/// # Overview
+/// **Simple implementation:** Calls `indent_line_bytewise()` once for each
+/// line in the range. File opened/closed multiple times - intentional for simplicity.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `start_line` - First line to indent (will be sorted with end_line)
+/// * `end_line` - Last line to indent (will be sorted with start_line)
+///
+/// # Returns
+/// * `Ok(())` - All lines indented successfully
+/// * `Err(ToggleIndentError)` - Specific error code
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::indent_range_bytewise;
+///
+/// // Indent lines 5-10 (order doesn't matter)
+/// match indent_range_bytewise("./src/main.rs", 5, 10) {
+///     Ok(()) => println!("Range indented"),
+///     Err(e) => eprintln!("Failed: {:?}", e),
+/// }
+/// ```
+#[cfg(feature = "fs")]
+pub fn indent_range_bytewise(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+) -> Result<(), ToggleIndentError> {
+    indent_range_bytewise_with_options(file_path, start_line, end_line, &ToggleOptions::default())
+}
+
+/// Same as `indent_range_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn indent_range_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleIndentError> {
+    let (start, end) = sort_range(start_line, end_line);
+
+    // Safety check
+    let range_size = end.saturating_sub(start).saturating_add(1);
+    if range_size > 10000 {
+        #[cfg(debug_assertions)]
+        eprintln!(
+            "Warning: Large range ({} lines) - this will be slow",
+            range_size
+        );
+    }
+
+    refuse_protected_indent_range(file_path, start, end)?;
+
+    // Simple loop: indent each line independently
+    for line_num in start..=end {
+        indent_line_bytewise_with_options(file_path, line_num, options)?;
+    }
+
+    Ok(())
+}
+
+/// `check_edit_target()` for the indent operations
+#[cfg(feature = "fs")]
+fn check_indent_target(
+    absolute_path: &Path,
+    options: &ToggleOptions,
+) -> Result<(), ToggleIndentError> {
+    match check_edit_target(absolute_path, options) {
+        Ok(()) => Ok(()),
+        Err(ToggleCommentError::NotRegularFile) => Err(ToggleIndentError::NotRegularFile),
+        Err(ToggleCommentError::OwnArtifact) => Err(ToggleIndentError::OwnArtifact),
+        Err(ToggleCommentError::ReadOnlyTarget) => Err(ToggleIndentError::ReadOnlyTarget),
+        Err(ToggleCommentError::FileTooLarge { size, max }) => {
+            Err(ToggleIndentError::FileTooLarge { size, max })
+        }
+        Err(ToggleCommentError::InvalidUtf8 { line }) => {
+            Err(ToggleIndentError::InvalidUtf8 { line })
+        }
+        Err(_) => Err(ToggleIndentError::IoError(IoOperation::Read)),
+    }
+}
+
+/// Refuse an indent range with a protected line before any line is edited
+///
+/// # Returns
+/// * `Err(ProtectedRegion)` - First protected line of `start..=end`
+/// * `Err(ToggleIndentError)` - Reading the file failed
+#[cfg(feature = "fs")]
+fn refuse_protected_indent_range(
+    file_path: &str,
+    start: usize,
+    end: usize,
+) -> Result<(), ToggleIndentError> {
+    match first_protected_line(Path::new(file_path), start, end, false) {
+        Ok(None) => Ok(()),
+        Ok(Some(line)) => Err(ToggleIndentError::ProtectedRegion { line }),
+        Err(ToggleCommentError::FileNotFound) => Err(ToggleIndentError::FileNotFound),
+        Err(ToggleCommentError::PermissionDenied(op)) => {
+            Err(ToggleIndentError::PermissionDenied(op))
+        }
+        Err(ToggleCommentError::Cancelled) => Err(ToggleIndentError::Cancelled),
+        Err(_) => Err(ToggleIndentError::IoError(IoOperation::Read)),
+    }
+}
+
+/// Remove up to 4 spaces from multiple lines using simple loop (bytewise)
+///
+/// # Overview
+/// **Simple implementation:** Calls `unindent_line_bytewise()` once for each
+/// line in the range. File opened/closed multiple times - intentional for simplicity.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `start_line` - First line to unindent (will be sorted with end_line)
+/// * `end_line` - Last line to unindent (will be sorted with start_line)
+///
+/// # Returns
+/// * `Ok(())` - All lines unindented successfully
+/// * `Err(ToggleIndentError)` - Specific error code
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::unindent_range_bytewise;
+///
+/// // Unindent lines 5-10 (order doesn't matter)
+/// match unindent_range_bytewise("./src/main.rs", 5, 10) {
+///     Ok(()) => println!("Range unindented"),
+///     Err(e) => eprintln!("Failed: {:?}", e),
+/// }
+/// ```
+#[cfg(feature = "fs")]
+pub fn unindent_range_bytewise(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+) -> Result<(), ToggleIndentError> {
+    unindent_range_bytewise_with_options(file_path, start_line, end_line, &ToggleOptions::default())
+}
+
+/// Same as `unindent_range_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn unindent_range_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleIndentError> {
+    let (start, end) = sort_range(start_line, end_line);
+
+    // Safety check
+    let range_size = end.saturating_sub(start).saturating_add(1);
+    if range_size > 10000 {
+        #[cfg(debug_assertions)]
+        eprintln!(
+            "Warning: Large range ({} lines) - this will be slow",
+            range_size
+        );
+    }
+
+    refuse_protected_indent_range(file_path, start, end)?;
+
+    // Simple loop: unindent each line independently
+    for line_num in start..=end {
+        unindent_line_bytewise_with_options(file_path, line_num, options)?;
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// TESTS - PHASE 2: INDENT/UNINDENT BYTEWISE
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod indent_bytewise_tests {
+    use super::*;
+
+    // ========================================
+    // Single Line Indent Tests
+    // ========================================
+
+    #[test]
+    fn test_indent_bytewise_basic() {
+        let content = "code\n";
+        let test_file = create_test_file("test_indent_bw_basic.txt", content);
+
+        let result = indent_line_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "    code\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_indent_bw_basic.txt"),
+        ]);
+    }
+
+    #[test]
+    fn test_indent_bytewise_already_indented() {
+        let content = "  code\n";
+        let test_file = create_test_file("test_indent_bw_existing.txt", content);
+
+        let result = indent_line_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "      code\n"); // 2 + 4 = 6 spaces
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_indent_bw_existing.txt"),
+        ]);
+    }
+
+    #[test]
+    fn test_indent_bytewise_empty_line() {
+        let content = "\n";
+        let test_file = create_test_file("test_indent_bw_empty.txt", content);
+
+        let result = indent_line_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "    \n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_indent_bw_empty.txt"),
+        ]);
+    }
+
+    // ========================================
+    // Single Line Unindent Tests
+    // ========================================
+
+    #[test]
+    fn test_unindent_bytewise_four_spaces() {
+        let content = "    code\n";
+        let test_file = create_test_file("test_unindent_bw_four.txt", content);
+
+        let result = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "code\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_unindent_bw_four.txt"),
+        ]);
+    }
+
+    #[test]
+    fn test_unindent_bytewise_two_spaces() {
+        let content = "  code\n";
+        let test_file = create_test_file("test_unindent_bw_two.txt", content);
+
+        let result = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "code\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_unindent_bw_two.txt"),
+        ]);
+    }
+
+    #[test]
+    fn test_unindent_bytewise_no_spaces() {
+        let content = "code\n";
+        let test_file = create_test_file("test_unindent_bw_none.txt", content);
+
+        let result = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "code\n"); // Unchanged
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_unindent_bw_none.txt"),
+        ]);
+    }
+
+    #[test]
+    fn test_unindent_bytewise_six_spaces() {
+        let content = "      code\n";
+        let test_file = create_test_file("test_unindent_bw_six.txt", content);
+
+        let result = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "  code\n"); // Removed 4, left 2
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_unindent_bw_six.txt"),
+        ]);
+    }
+
+    // ========================================
+    // Range Tests
+    // ========================================
+
+    #[test]
+    fn test_indent_range_bytewise_basic() {
+        let content = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_indent_range_bw.txt", content);
+
+        let result = indent_range_bytewise(test_file.to_str().unwrap(), 0, 2);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "    line 0\n    line 1\n    line 2\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_indent_range_bw.txt"),
+        ]);
+    }
+
+    #[test]
+    fn test_unindent_range_bytewise_basic() {
+        let content = "    line 0\n    line 1\n    line 2\n";
+        let test_file = create_test_file("test_unindent_range_bw.txt", content);
+
+        let result = unindent_range_bytewise(test_file.to_str().unwrap(), 0, 2);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "line 0\nline 1\nline 2\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_unindent_range_bw.txt"),
+        ]);
+    }
+
+    #[test]
+    fn test_indent_unindent_roundtrip() {
+        let original = "code\n";
+        let test_file = create_test_file("test_indent_roundtrip_bw.txt", original);
+
+        // Indent
+        let result1 = indent_line_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result1.is_ok());
+
+        let content1 = read_file_content(&test_file);
+        assert_eq!(content1, "    code\n");
+
+        // Unindent back
+        let result2 = unindent_line_bytewise(test_file.to_str().unwrap(), 0);
+        assert!(result2.is_ok());
+
+        let content2 = read_file_content(&test_file);
+        assert_eq!(content2, original);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_indent_roundtrip_bw.txt"),
+        ]);
+    }
+
+    #[test]
+    fn test_makefile_indents_with_tab() {
+        let options = ToggleOptions {
+            indent_width: 2,
+            ..ToggleOptions::default()
+        };
+        let test_file = create_test_file("test_indent_tab_bw.mk", "all:\n  echo\n");
+        let path = test_file.to_str().unwrap();
+
+        // A tab whatever the width; unindent takes one tab, never spaces
+        indent_line_bytewise_with_options(path, 1, &options).unwrap();
+        assert_eq!(read_file_content(&test_file), "all:\n\t  echo\n");
+        unindent_line_bytewise_with_options(path, 1, &options).unwrap();
+        unindent_line_bytewise_with_options(path, 1, &options).unwrap();
+        assert_eq!(read_file_content(&test_file), "all:\n  echo\n");
+
+        // Extensionless names resolve to `mk`
+        let make_dir = PathBuf::from("./tests/indent_tab_bw");
+        std::fs::create_dir_all(&make_dir).unwrap();
+        let makefile = make_dir.join("GNUmakefile");
+        std::fs::write(&makefile, "all:\necho\n").unwrap();
+        indent_range_bytewise(makefile.to_str().unwrap(), 1, 1).unwrap();
+        assert_eq!(read_file_content(&makefile), "all:\n\techo\n");
+
+        cleanup_files(&[
+            &test_file,
+            &makefile,
+            &PathBuf::from("backup_toggle_comment_test_indent_tab_bw.mk"),
+            &PathBuf::from("backup_toggle_comment_GNUmakefile"),
+        ]);
+        let _ = std::fs::remove_dir(&make_dir);
+    }
+
+    #[test]
+    fn test_indent_tab_option() {
+        // The gofmt preset: tabs to indent, `// ` after the leading tabs
+        let options = ToggleOptions {
+            indent_tab: true,
+            preserve_indent: true,
+            ..ToggleOptions::default()
+        };
+        let test_file = create_test_file("test_indent_tab_option_bw.go", "{\nrun()\n}\n");
+        let path = test_file.to_str().unwrap();
+
+        indent_line_bytewise_with_options(path, 1, &options).unwrap();
+        assert_eq!(read_file_content(&test_file), "{\n\trun()\n}\n");
+        toggle_basic_singleline_comment_bytewise_with_options(path, 1, &options).unwrap();
+        assert_eq!(read_file_content(&test_file), "{\n\t// run()\n}\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_indent_tab_option_bw.go"),
+        ]);
+    }
+}
+
+// ============================================================================
+// RANGE UTILITIES
+// ============================================================================
+
+/// Sort and validate a line range to ensure start ≤ end
+///
+/// # Overview
+/// Universal helper for all range-based operations. Always returns a valid
+/// sorted tuple where the first value is ≤ the second value.
+///
+/// # Arguments
+/// * `from` - First line number (may be larger than `to`)
+/// * `to` - Second line number (may be smaller than `from`)
+///
+/// # Returns
+/// * `(start, end)` - Sorted tuple where start ≤ end
+///
+/// # Behavior
+/// ```text
+/// Input: (5, 10) → Output: (5, 10)  [already sorted]
+/// Input: (10, 5) → Output: (5, 10)  [flipped]
+/// Input: (7, 7)  → Output: (7, 7)   [single line, valid]
+/// ```
+///
+/// # Usage
+/// ```rust
+/// let (start, end) = sort_range(user_input_from, user_input_to);
+/// // Now guaranteed: start <= end
+/// ```
+///
+/// # Safety
+/// - Always succeeds (no Result needed)
+/// - No allocations
+/// - Copy semantics only
+/// - Works for any usize values (no overflow - uses min/max)
+fn sort_range(from: usize, to: usize) -> (usize, usize) {
+    // Use built-in min/max for clarity and safety
+    let start = std::cmp::min(from, to);
+    let end = std::cmp::max(from, to);
+
+    (start, end)
+}
+
+// ============================================================================
+// INDENT/UNINDENT RANGE FUNCTIONS
+// ============================================================================
+
+// ============================================================================
+// PHASE 1: RANGE TOGGLE - SIMPLE LOOP APPROACH (BYTEWISE)
+// ============================================================================
+
+/// Toggle Rust documentation comment (///) on a specific line - bytewise version
+///
+/// # Overview
+/// Bytewise implementation of docstring toggle. Identical to
+/// `toggle_basic_singleline_comment_bytewise()` but uses TripleSlash flag.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `row_line_zeroindex` - Zero-indexed line number to toggle
+///
+/// # Returns
+/// * `Ok(())` - Comment toggled successfully
+/// * `Err(ToggleCommentError)` - Specific error code
+///
+/// # Memory Usage
+/// - Single byte buffer [u8; 1]
+/// - No heap allocation during processing
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::toggle_rust_docstring_singleline_comment_bytewise;
+///
+/// match toggle_rust_docstring_singleline_comment_bytewise("./src/lib.rs", 5) {
+///     Ok(()) => println!("Docstring toggled"),
+///     Err(e) => eprintln!("Failed: {:?}", e),
+/// }
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_rust_docstring_singleline_comment_bytewise(
+    file_path: &str,
+    row_line_zeroindex: usize,
+) -> Result<(), ToggleCommentError> {
+    toggle_rust_docstring_singleline_comment_bytewise_with_options(
+        file_path,
+        row_line_zeroindex,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_rust_docstring_singleline_comment_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_rust_docstring_singleline_comment_bytewise_with_options(
+    file_path: &str,
+    row_line_zeroindex: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    // `///` is only a doc comment in Rust (in `.toml` it is not even a comment)
+    check_docstring_target(&absolute_path, options)?;
+    let comment_flag = CommentFlag::TripleSlash;
+
+    // Indent-preserving, relaxed, trimming, blank-skipping, one-direction,
+    // audited and EOL / final-newline converting toggles run on the line engine
+    if options.preserve_indent
+        || options.removal == RemovalMode::Relaxed
+        || options.eol != EolMode::Keep
+        || options.final_newline != FinalNewline::Keep
+        || options.trim_trailing
+        || options.skip_blank
+        || options.direction != LineToggleDirection::Toggle
+        || options.eol_audit
+    {
+        return toggle_multiple_lines_bytewise(
+            file_path,
+            &[row_line_zeroindex],
+            Some(comment_flag),
+            options,
+        );
+    }
+
+    // Combined find and detect in single pass
+    let (line_start_pos, has_tag) =
+        match find_and_detect_tag_state(file_path, row_line_zeroindex, comment_flag)? {
+            Some((pos, tag_state)) => (pos, tag_state),
+            None => {
+                return Err(ToggleCommentError::LineNotFound {
+                    requested: row_line_zeroindex,
+                    file_lines: 0,
+                });
+            }
+        };
+
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+
+    // Create backup path in CWD
+    let backup_path = backup_path_for(&filename, options);
+
+    let trace = StageTrace::new(options, "toggle_docstring");
+
+    // Create backup copy
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    // Create temp file
+    let temp_filename = format!(
+        "temp_toggle_docstring_bytewise_{}_{}",
+        std::process::id(),
+        filename
+    );
+    let temp_path = temp_file_path(options.temp_dir.as_deref(), &temp_filename);
+    let _remove_temp = TempFileGuard(&temp_path);
+
+    // Byte-wise write operation
+    let process_result = trace.run_streaming(&absolute_path, &temp_path, || {
+        write_toggled_file_bytewise(
+            &absolute_path,
+            &temp_path,
+            row_line_zeroindex,
+            line_start_pos,
+            has_tag,
+            comment_flag,
+        )
+    });
+
+    // Handle result
+    match process_result {
+        Ok(()) => {
+            // Success: check temp looks complete, then replace original
+            if !trace.check(TraceStage::Verify, || {
+                replacement_size_is_plausible(
+                    &absolute_path,
+                    &temp_path,
+                    MAX_TOGGLE_EDIT_BYTES,
+                    MAX_TOGGLE_EDIT_BYTES,
+                )
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleCommentError::IncompleteWrite);
+            }
+            if let Err(e) = trace.run(TraceStage::Replace, || {
+                replace_original(
+                    &temp_path,
+                    &absolute_path,
+                    options.temp_dir.as_deref(),
+                    options.durability,
+                    &snapshot,
+                )
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
+            }
+
+            // Clean up temp
+            if remove_temp_file(&temp_path).is_err() {
+                #[cfg(debug_assertions)]
+                eprintln!("Warning: Failed to clean up temp file");
+            }
+
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Toggle basic comments on a range of lines using simple loop (bytewise)
+///
+/// # Overview
+/// **Simple implementation:** Calls `toggle_basic_singleline_comment_bytewise()`
+/// once for each line in the range. No optimization, no single-pass complexity.
+/// File is opened/closed multiple times - this is intentional for simplicity.
+///
+/// Each line is toggled independently based on its current state.
+/// Range is automatically sorted - argument order doesn't matter.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `start_line` - First line to toggle (will be sorted with end_line)
+/// * `end_line` - Last line to toggle (will be sorted with start_line)
+///
+/// # Returns
+/// * `Ok(())` - All lines in range toggled successfully
+/// * `Err(ToggleCommentError)` - Specific error code
+///
+/// # Algorithm
+/// ```text
+/// for each line in start..=end:
+///     toggle_basic_singleline_comment_bytewise(file, line)
+/// ```
+///
+/// # Memory Usage
+/// - Per line: 1 byte buffer
+/// - Total: 1 byte (reused for each line)
+/// - No accumulation, no line counting needed
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::toggle_range_basic_comments_bytewise;
+///
+/// // Toggle lines 5-10 (both inclusive, order doesn't matter)
+/// match toggle_range_basic_comments_bytewise("./src/main.rs", 5, 10) {
+///     Ok(()) => println!("Range toggled"),
+///     Err(e) => eprintln!("Failed: {:?}", e),
+/// }
+///
+/// // Same result (auto-sorted):
+/// toggle_range_basic_comments_bytewise("./src/main.rs", 10, 5)?;
+/// ```
+///
+/// # Behavior
+/// Each line toggled independently:
+/// ```text
+/// Input range [5-7]:
+/// line 5          →  // line 5
+/// // line 6       →  line 6
+/// line 7          →  // line 7
+/// ```
+///
+/// # Performance Note
+/// This is NOT optimized for performance - it's optimized for:
+/// - Simplicity (easy to understand and maintain)
+/// - Safety (reuses proven single-line bytewise code)
+/// - Memory bounds (constant memory regardless of range size)
+///
+/// For a 100-line range, this opens the file 100 times. That's OK.
+/// Simple is better than clever.
+#[cfg(feature = "fs")]
+pub fn toggle_range_basic_comments_bytewise(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+) -> Result<(), ToggleCommentError> {
+    toggle_range_basic_comments_bytewise_with_options(
+        file_path,
+        start_line,
+        end_line,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_range_basic_comments_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_range_basic_comments_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    // Sort range automatically
+    let (start, end) = sort_range(start_line, end_line);
+
+    // Safety check: reasonable range size
+    // This is just a sanity check, not a hard limit
+    let range_size = end.saturating_sub(start).saturating_add(1);
+    if range_size > 10000 {
+        #[cfg(debug_assertions)]
+        eprintln!(
+            "Warning: Large range ({} lines) - this will be slow",
+            range_size
+        );
+    }
+
+    // Refuse the whole range before the first line is edited: a toggled
+    // sentinel would lift the protection of the lines after it
+    let heredocs = target_extension(Path::new(file_path), options)
+        .is_some_and(|extension| guards_heredocs(&extension, options));
+    if let Some(line) = first_protected_line(Path::new(file_path), start, end, heredocs)? {
+        return Err(ToggleCommentError::ProtectedRegion { line });
+    }
+
+    // Simple loop: toggle each line independently
+    for line_num in start..=end {
+        // If any line fails, propagate the error immediately
+        toggle_basic_singleline_comment_bytewise_with_options(file_path, line_num, options)?;
+    }
+
+    Ok(())
+}
+
+/// Toggle Rust docstrings on a range of lines using simple loop (bytewise)
+///
+/// # Overview
+/// **Simple implementation:** Calls `toggle_rust_docstring_singleline_comment_bytewise()`
+/// once for each line in the range. No optimization, no single-pass complexity.
+/// File is opened/closed multiple times - this is intentional for simplicity.
+///
+/// Each line is toggled independently based on its current state.
+/// Range is automatically sorted - argument order doesn't matter.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `start_line` - First line to toggle (will be sorted with end_line)
+/// * `end_line` - Last line to toggle (will be sorted with start_line)
+///
+/// # Returns
+/// * `Ok(())` - All lines in range toggled successfully
+/// * `Err(ToggleCommentError)` - Specific error code
+///
+/// # Algorithm
+/// ```text
+/// for each line in start..=end:
+///     toggle_rust_docstring_singleline_comment_bytewise(file, line)
+/// ```
+///
+/// # Memory Usage
+/// - Per line: 1 byte buffer
+/// - Total: 1 byte (reused for each line)
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::toggle_range_rust_docstring_bytewise;
+///
+/// // Toggle docstrings on lines 5-10
+/// match toggle_range_rust_docstring_bytewise("./src/lib.rs", 5, 10) {
+///     Ok(()) => println!("Docstrings toggled"),
+///     Err(e) => eprintln!("Failed: {:?}", e),
+/// }
+/// ```
+///
+/// # Note
+/// No file extension validation - works on any file type.
+/// Caller responsible for using on appropriate files.
+#[cfg(feature = "fs")]
+pub fn toggle_range_rust_docstring_bytewise(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+) -> Result<(), ToggleCommentError> {
+    toggle_range_rust_docstring_bytewise_with_options(
+        file_path,
+        start_line,
+        end_line,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_range_rust_docstring_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_range_rust_docstring_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    // Sort range automatically
+    let (start, end) = sort_range(start_line, end_line);
+
+    // Safety check: reasonable range size
+    let range_size = end.saturating_sub(start).saturating_add(1);
+    if range_size > 10000 {
+        #[cfg(debug_assertions)]
+        eprintln!(
+            "Warning: Large range ({} lines) - this will be slow",
+            range_size
+        );
+    }
+
+    // Simple loop: toggle each line independently
+    for line_num in start..=end {
+        toggle_rust_docstring_singleline_comment_bytewise_with_options(
+            file_path, line_num, options,
+        )?;
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// TESTS - PHASE 1: RANGE TOGGLE BYTEWISE
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod range_toggle_bytewise_tests {
+    use super::*;
+
+    // ========================================
+    // Basic Range Toggle Tests
+    // ========================================
+
+    #[test]
+    fn test_range_bytewise_basic_all_off() {
+        let content = "line 0\nline 1\nline 2\nline 3\n";
+        let test_file = create_test_file("test_range_bw_all_off.rs", content);
+
+        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 2);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "line 0\n// line 1\n// line 2\nline 3\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_all_off.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_basic_all_on() {
+        let content = "line 0\n// line 1\n// line 2\nline 3\n";
+        let test_file = create_test_file("test_range_bw_all_on.rs", content);
+
+        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 2);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "line 0\nline 1\nline 2\nline 3\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_all_on.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_basic_mixed() {
+        let content = "line 0\n// line 1\nline 2\n// line 3\nline 4\n";
+        let test_file = create_test_file("test_range_bw_mixed.rs", content);
+
+        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 3);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        // Each line toggled independently
+        assert_eq!(new_content, "line 0\nline 1\n// line 2\nline 3\nline 4\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_mixed.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_reversed_input() {
+        let content = "line 0\nline 1\nline 2\nline 3\n";
+        let test_file = create_test_file("test_range_bw_reversed.rs", content);
+
+        // Reversed input: 2, 1 (should auto-sort to 1, 2)
+        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 2, 1);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "line 0\n// line 1\n// line 2\nline 3\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_reversed.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_single_line() {
+        let content = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_range_bw_single.rs", content);
+
+        // Range of one line (1, 1)
+        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 1);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "line 0\n// line 1\nline 2\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_single.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_preserves_outside() {
+        let content = "line 0\nline 1\nline 2\nline 3\nline 4\n";
+        let test_file = create_test_file("test_range_bw_preserve.rs", content);
+
+        // Toggle only middle lines
+        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 3);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(
+            new_content,
+            "line 0\n// line 1\n// line 2\n// line 3\nline 4\n"
+        );
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_preserve.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_roundtrip() {
+        let original = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_range_bw_roundtrip.rs", original);
+
+        // Toggle on
+        let result1 = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 0, 2);
+        assert!(result1.is_ok());
+
+        let content1 = read_file_content(&test_file);
+        assert_eq!(content1, "// line 0\n// line 1\n// line 2\n");
+
+        // Toggle off
+        let result2 = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 0, 2);
+        assert!(result2.is_ok());
+
+        let content2 = read_file_content(&test_file);
+        assert_eq!(content2, original);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_roundtrip.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_python() {
+        let content = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_range_bw_python.py", content);
+
+        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 0, 1);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "# line 0\n# line 1\nline 2\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_python.py"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_line_not_found() {
+        let content = "line 0\nline 1\n";
+        let test_file = create_test_file("test_range_bw_notfound.rs", content);
+
+        // Range extends beyond file
+        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 0, 10);
+        assert!(matches!(
+            result,
+            Err(ToggleCommentError::LineNotFound { .. })
+        ));
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_notfound.rs"),
+        ]);
+    }
+
+    // ========================================
+    // Docstring Range Toggle Tests
+    // ========================================
+
+    #[test]
+    fn test_range_bytewise_docstring_basic() {
+        let content = "line 0\nline 1\nline 2\nline 3\n";
+        let test_file = create_test_file("test_range_bw_doc.rs", content);
+
+        let result = toggle_range_rust_docstring_bytewise(test_file.to_str().unwrap(), 1, 2);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "line 0\n/// line 1\n/// line 2\nline 3\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_doc.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_docstring_mixed() {
+        let content = "line 0\n/// line 1\nline 2\n/// line 3\n";
+        let test_file = create_test_file("test_range_bw_doc_mixed.rs", content);
+
+        let result = toggle_range_rust_docstring_bytewise(test_file.to_str().unwrap(), 1, 3);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        // Each line toggled independently
+        assert_eq!(new_content, "line 0\nline 1\n/// line 2\nline 3\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_doc_mixed.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_range_bytewise_docstring_roundtrip() {
+        let original = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_range_bw_doc_roundtrip.rs", original);
+
+        // Toggle on
+        let result1 = toggle_range_rust_docstring_bytewise(test_file.to_str().unwrap(), 0, 2);
+        assert!(result1.is_ok());
+
+        let content1 = read_file_content(&test_file);
+        assert_eq!(content1, "/// line 0\n/// line 1\n/// line 2\n");
+
+        // Toggle off
+        let result2 = toggle_range_rust_docstring_bytewise(test_file.to_str().unwrap(), 0, 2);
+        assert!(result2.is_ok());
+
+        let content2 = read_file_content(&test_file);
+        assert_eq!(content2, original);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_range_bw_doc_roundtrip.rs"),
+        ]);
+    }
+}
+
+// ============================================================================
+// PHASE 3: BLOCK COMMENTS - BYTEWISE OPERATIONS
+// ============================================================================
+
+/// Detect if specific line starts with exact byte pattern at column 0
+///
+/// # Overview
+/// Reads first bytes of target line and compares against expected pattern.
+/// Used for block comment marker detection.
+///
+/// # Arguments
+/// * `file_path` - Path to source file
+/// * `line_number` - Zero-indexed line to check
+/// * `pattern` - Exact byte sequence to match (e.g., b"/*\n")
+///
+/// # Returns
+/// * `Ok(true)` - Line starts with exact pattern at column 0
+/// * `Ok(false)` - Line doesn't match pattern
+/// * `Err(ToggleCommentError)` - File operation failed or line not found
+///
+/// # Example
+/// ```text
+/// File line 5: "/*\n"
+/// detect_line_pattern(path, 5, b"/*\n") → Ok(true)
+///
+/// File line 5: "  /*\n"  (indented)
+/// detect_line_pattern(path, 5, b"/*\n") → Ok(false)
+///
+/// File line 5: "/* comment\n"  (has content after)
+/// detect_line_pattern(path, 5, b"/*\n") → Ok(false)
+/// ```
+#[cfg(feature = "fs")]
+pub fn detect_line_pattern(
+    file_path: &str,
+    line_number: usize,
+    pattern: &[u8],
+) -> Result<bool, ToggleCommentError> {
+    // Find line start position
+    let line_start_pos =
+        match find_line_start_position(file_path, line_number).map_err(|e| match e {
+            ToggleIndentError::FileNotFound => ToggleCommentError::FileNotFound,
+            ToggleIndentError::LineNotFound {
+                requested,
+                file_lines,
+            } => ToggleCommentError::LineNotFound {
+                requested,
+                file_lines,
+            },
+            ToggleIndentError::IoError(op) => ToggleCommentError::IoError(op),
+            ToggleIndentError::PermissionDenied(op) => ToggleCommentError::PermissionDenied(op),
+            ToggleIndentError::PathError => ToggleCommentError::PathError,
+            ToggleIndentError::IncompleteWrite => ToggleCommentError::IncompleteWrite,
+            ToggleIndentError::ReadOnlyTarget => ToggleCommentError::ReadOnlyTarget,
+            ToggleIndentError::FileTooLarge { size, max } => {
+                ToggleCommentError::FileTooLarge { size, max }
+            }
+            ToggleIndentError::ProtectedRegion { line } => {
+                ToggleCommentError::ProtectedRegion { line }
+            }
+            ToggleIndentError::Cancelled => ToggleCommentError::Cancelled,
+            ToggleIndentError::ConcurrentModification => ToggleCommentError::ConcurrentModification,
+            ToggleIndentError::NotRegularFile => ToggleCommentError::NotRegularFile,
+            ToggleIndentError::OwnArtifact => ToggleCommentError::OwnArtifact,
+            ToggleIndentError::InvalidUtf8 { line } => ToggleCommentError::InvalidUtf8 { line },
+        })? {
+            Some(pos) => pos,
+            None => return Ok(false), // Line not found = doesn't match
+        };
+
+    let mut file = match File::open(file_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    // Seek to line start
+    if file.seek(std::io::SeekFrom::Start(line_start_pos)).is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Read));
+    }
+
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+
+    // Compare each byte in pattern
+    for &expected_byte in pattern.iter() {
+        let bytes_read = match file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            return Ok(false); // EOF before pattern complete
+        }
+
+        if byte_bucket[0] != expected_byte {
+            return Ok(false); // Mismatch
+        }
+    }
+
+    // All bytes matched
+    Ok(true)
+}
+
+/// Check whether a line is a block marker line
+///
+/// The marker may be indented with spaces/tabs and must end the line with
+/// `\n` or `\r\n` (see `MarkerLineMatch`).
+///
+/// # Arguments
+/// * `marker` - Marker text without indent or line ending (e.g. `b"/*"`)
+///
+/// # Returns
+/// * `Ok(bool)` - Line is (not) a marker line; an unterminated last line is not
+/// * `Err(LineNotFound)` - File has fewer lines
+/// * `Err(ToggleCommentError)` - Open or read failed
+#[cfg(feature = "fs")]
+fn detect_marker_line(
+    file_path: &str,
+    line_number: usize,
+    marker: &[u8],
+) -> Result<bool, ToggleCommentError> {
+    let mut reader = match File::open(file_path) {
+        Ok(f) => BufReader::with_capacity(IO_BUFFER_SIZE, f),
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let mut current_line: usize = 0;
+    let mut byte_count: u64 = 0;
+    let mut state = MarkerLineMatch::LINE_START;
+
+    while let Some(byte) = read_single_byte(&mut reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if byte == b'\n' {
+            if current_line == line_number {
+                return Ok(state.is_marker(marker));
+            }
+            current_line += 1;
+        } else if current_line == line_number {
+            state.feed(marker, byte);
+        }
+    }
+
+    if current_line == line_number {
+        return Ok(false);
+    }
+    Err(ToggleCommentError::LineNotFound {
+        requested: line_number,
+        file_lines: current_line + 1,
+    })
+}
+
+/// Read the line ending and indentation new marker lines copy from a line
+///
+/// An unterminated last line takes the ending of the line before it, so a
+/// marker appended at EOF still matches the rest of the file. Indentation
+/// beyond MAX_MARKER_INDENT bytes is not copied.
+///
+/// # Returns
+/// * `Ok(MarkerStyle)` - Style of the line (an absent line gives the last ending seen)
+/// * `Err(ToggleCommentError)` - Open or read failed
+#[cfg(feature = "fs")]
+fn marker_style_of_line(
+    source_path: &Path,
+    line_number: usize,
+) -> Result<MarkerStyle, ToggleCommentError> {
+    let mut reader = match File::open(source_path) {
+        Ok(f) => BufReader::with_capacity(IO_BUFFER_SIZE, f),
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let mut style = MarkerStyle {
+        crlf: false,
+        indent: [0u8; MAX_MARKER_INDENT],
+        indent_len: 0,
+    };
+    let mut current_line: usize = 0;
+    let mut byte_count: u64 = 0;
+    let mut previous: Option<u8> = None;
+    let mut in_indent = line_number == 0;
+
+    while let Some(byte) = read_single_byte(&mut reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if byte == b'\n' {
+            style.crlf = previous == Some(b'\r');
+            if current_line == line_number {
+                return Ok(style);
+            }
+            current_line += 1;
+            in_indent = current_line == line_number;
+        } else if in_indent {
+            if (byte == b' ' || byte == b'\t') && style.indent_len < MAX_MARKER_INDENT {
+                style.indent[style.indent_len] = byte;
+                style.indent_len += 1;
+            } else {
+                in_indent = false;
+            }
+        }
+        previous = Some(byte);
+    }
+
+    Ok(style)
+}
+
+/// Delete entire line from file (bytewise copy, skip target line)
+///
+/// # Overview
+/// Copies source to dest, skipping all bytes of target line including newline.
+/// Simple three-part copy: before, skip, after.
+///
+/// # Arguments
+/// * `source_path` - Original file
+/// * `dest_path` - Temp file for output
+/// * `line_number` - Zero-indexed line to delete
+///
+/// # Returns
+/// * `Ok(removed_bytes)` - Line deleted; bytes dropped (including its newline)
+/// * `Err(ToggleCommentError)` - Operation failed
+///
+/// # Memory
+/// - 1 byte buffer only
+/// - No heap allocation
+#[cfg(feature = "fs")]
+pub fn delete_line_bytewise(
+    source_path: &Path,
+    dest_path: &Path,
+    line_number: usize,
+) -> Result<u64, ToggleCommentError> {
+    // Find line start position
+    let line_start_pos = match find_line_start_position(
+        source_path.to_str().unwrap_or(""),
+        line_number,
+    )
+    .map_err(|e| match e {
+        ToggleIndentError::FileNotFound => ToggleCommentError::FileNotFound,
+        ToggleIndentError::LineNotFound {
+            requested,
+            file_lines,
+        } => ToggleCommentError::LineNotFound {
+            requested,
+            file_lines,
+        },
+        ToggleIndentError::IoError(op) => ToggleCommentError::IoError(op),
+        ToggleIndentError::PermissionDenied(op) => ToggleCommentError::PermissionDenied(op),
+        ToggleIndentError::PathError => ToggleCommentError::PathError,
+        ToggleIndentError::IncompleteWrite => ToggleCommentError::IncompleteWrite,
+        ToggleIndentError::ReadOnlyTarget => ToggleCommentError::ReadOnlyTarget,
+        ToggleIndentError::FileTooLarge { size, max } => {
+            ToggleCommentError::FileTooLarge { size, max }
+        }
+        ToggleIndentError::ProtectedRegion { line } => ToggleCommentError::ProtectedRegion { line },
+        ToggleIndentError::Cancelled => ToggleCommentError::Cancelled,
+        ToggleIndentError::ConcurrentModification => ToggleCommentError::ConcurrentModification,
+        ToggleIndentError::NotRegularFile => ToggleCommentError::NotRegularFile,
+        ToggleIndentError::OwnArtifact => ToggleCommentError::OwnArtifact,
+        ToggleIndentError::InvalidUtf8 { line } => ToggleCommentError::InvalidUtf8 { line },
+    })? {
+        Some(pos) => pos,
+        None => {
+            return Err(ToggleCommentError::LineNotFound {
+                requested: line_number,
+                file_lines: 0,
+            });
+        }
+    };
+
+    let mut source_file = match File::open(source_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let dest_file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest_path)
+    {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+    };
+
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, dest_file);
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+    let mut byte_position: u64 = 0;
+
+    // PART A: Copy before target line
+    while byte_position < line_start_pos {
+        if byte_position >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        byte_position += 1;
+    }
+
+    // PART B: Skip entire target line (don't write anything)
+    let mut removed_bytes: u64 = 0;
+    loop {
+        if removed_bytes >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            break; // EOF - line had no newline
+        }
+
+        removed_bytes += 1;
+
+        if byte_bucket[0] == b'\n' {
+            break; // Found newline - skip it too, line is deleted
+        }
+    }
+
+    // PART C: Copy rest of file
+    let mut remaining_bytes: u64 = 0;
+    loop {
+        if remaining_bytes >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        remaining_bytes += 1;
+    }
+
+    if writer.flush().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+    }
+
+    // fsync: flush alone may succeed while data is still only in OS buffers
+    if writer.get_ref().sync_all().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Sync));
+    }
+
+    Ok(removed_bytes)
+}
+
+/// Insert new line before target line (bytewise)
+///
+/// # Overview
+/// Copies source to dest, inserting new line content before target line.
+///
+/// # Arguments
+/// * `source_path` - Original file
+/// * `dest_path` - Temp file for output
+/// * `line_number` - Zero-indexed line to insert before
+/// * `content` - Bytes to insert (should include newline)
+///
+/// # Returns
+/// * `Ok(())` - Line inserted successfully
+/// * `Err(ToggleCommentError)` - Operation failed
+#[cfg(feature = "fs")]
+pub fn insert_line_before_bytewise(
+    source_path: &Path,
+    dest_path: &Path,
+    line_number: usize,
+    content: &[u8],
+) -> Result<(), ToggleCommentError> {
+    // Find line start position
+    let line_start_pos = match find_line_start_position(
+        source_path.to_str().unwrap_or(""),
+        line_number,
+    )
+    .map_err(|e| match e {
+        ToggleIndentError::FileNotFound => ToggleCommentError::FileNotFound,
+        ToggleIndentError::LineNotFound {
+            requested,
+            file_lines,
+        } => ToggleCommentError::LineNotFound {
+            requested,
+            file_lines,
+        },
+        ToggleIndentError::IoError(op) => ToggleCommentError::IoError(op),
+        ToggleIndentError::PermissionDenied(op) => ToggleCommentError::PermissionDenied(op),
+        ToggleIndentError::PathError => ToggleCommentError::PathError,
+        ToggleIndentError::IncompleteWrite => ToggleCommentError::IncompleteWrite,
+        ToggleIndentError::ReadOnlyTarget => ToggleCommentError::ReadOnlyTarget,
+        ToggleIndentError::FileTooLarge { size, max } => {
+            ToggleCommentError::FileTooLarge { size, max }
+        }
+        ToggleIndentError::ProtectedRegion { line } => ToggleCommentError::ProtectedRegion { line },
+        ToggleIndentError::Cancelled => ToggleCommentError::Cancelled,
+        ToggleIndentError::ConcurrentModification => ToggleCommentError::ConcurrentModification,
+        ToggleIndentError::NotRegularFile => ToggleCommentError::NotRegularFile,
+        ToggleIndentError::OwnArtifact => ToggleCommentError::OwnArtifact,
+        ToggleIndentError::InvalidUtf8 { line } => ToggleCommentError::InvalidUtf8 { line },
+    })? {
+        Some(pos) => pos,
+        None => {
+            return Err(ToggleCommentError::LineNotFound {
+                requested: line_number,
+                file_lines: 0,
+            });
+        }
+    };
+
+    let mut source_file = match File::open(source_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let dest_file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest_path)
+    {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+    };
+
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, dest_file);
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+    let mut byte_position: u64 = 0;
+
+    // PART A: Copy before target line
+    while byte_position < line_start_pos {
+        if byte_position >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        byte_position += 1;
+    }
+
+    // PART B: Insert new content
+    if writer.write_all(content).is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Write));
+    }
+
+    // PART C: Copy rest of file
+    let mut remaining_bytes: u64 = 0;
+    loop {
+        if remaining_bytes >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        remaining_bytes += 1;
+    }
+
+    if writer.flush().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+    }
+
+    // fsync: flush alone may succeed while data is still only in OS buffers
+    if writer.get_ref().sync_all().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Sync));
+    }
+
+    Ok(())
+}
+
+/// Insert new line after target line (bytewise)
+///
+/// # Overview
+/// Copies source to dest, inserting new line content after target line's newline.
+///
+/// # Arguments
+/// * `source_path` - Original file
+/// * `dest_path` - Temp file for output
+/// * `line_number` - Zero-indexed line to insert after
+/// * `content` - Bytes to insert (should include newline)
+///
+/// # Returns
+/// * `Ok(())` - Line inserted successfully
+/// * `Err(ToggleCommentError)` - Operation failed
+#[cfg(feature = "fs")]
+pub fn insert_line_after_bytewise(
+    source_path: &Path,
+    dest_path: &Path,
+    line_number: usize,
+    content: &[u8],
+) -> Result<(), ToggleCommentError> {
+    // Find line start position
+    let line_start_pos = match find_line_start_position(
+        source_path.to_str().unwrap_or(""),
+        line_number,
+    )
+    .map_err(|e| match e {
+        ToggleIndentError::FileNotFound => ToggleCommentError::FileNotFound,
+        ToggleIndentError::LineNotFound {
+            requested,
+            file_lines,
+        } => ToggleCommentError::LineNotFound {
+            requested,
+            file_lines,
+        },
+        ToggleIndentError::IoError(op) => ToggleCommentError::IoError(op),
+        ToggleIndentError::PermissionDenied(op) => ToggleCommentError::PermissionDenied(op),
+        ToggleIndentError::PathError => ToggleCommentError::PathError,
+        ToggleIndentError::IncompleteWrite => ToggleCommentError::IncompleteWrite,
+        ToggleIndentError::ReadOnlyTarget => ToggleCommentError::ReadOnlyTarget,
+        ToggleIndentError::FileTooLarge { size, max } => {
+            ToggleCommentError::FileTooLarge { size, max }
+        }
+        ToggleIndentError::ProtectedRegion { line } => ToggleCommentError::ProtectedRegion { line },
+        ToggleIndentError::Cancelled => ToggleCommentError::Cancelled,
+        ToggleIndentError::ConcurrentModification => ToggleCommentError::ConcurrentModification,
+        ToggleIndentError::NotRegularFile => ToggleCommentError::NotRegularFile,
+        ToggleIndentError::OwnArtifact => ToggleCommentError::OwnArtifact,
+        ToggleIndentError::InvalidUtf8 { line } => ToggleCommentError::InvalidUtf8 { line },
+    })? {
+        Some(pos) => pos,
+        None => {
+            return Err(ToggleCommentError::LineNotFound {
+                requested: line_number,
+                file_lines: 0,
+            });
+        }
+    };
+
+    let mut source_file = match File::open(source_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let dest_file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest_path)
+    {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+    };
+
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, dest_file);
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+    let mut byte_position: u64 = 0;
+
+    // PART A: Copy up to and including target line
+    while byte_position < line_start_pos {
+        if byte_position >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        byte_position += 1;
+    }
+
+    // PART B: Copy target line until newline
+    loop {
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            // EOF - no newline at end, end the line like `content`, then insert it
+            let ending: &[u8] = if content.ends_with(b"\r\n") {
+                b"\r\n"
+            } else {
+                b"\n"
+            };
+            if writer.write_all(ending).is_err() {
+                return Err(ToggleCommentError::IoError(IoOperation::Write));
+            }
+            if writer.write_all(content).is_err() {
+                return Err(ToggleCommentError::IoError(IoOperation::Write));
+            }
+            break;
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        if byte_bucket[0] == b'\n' {
+            // Found newline - insert after it
+            if writer.write_all(content).is_err() {
+                return Err(ToggleCommentError::IoError(IoOperation::Write));
+            }
+            break;
+        }
+    }
+
+    // PART C: Copy rest of file
+    let mut remaining_bytes: u64 = 0;
+    loop {
+        if remaining_bytes >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        let bytes_read = match source_file.read(&mut byte_bucket) {
+            Ok(n) => n,
+            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+        };
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        if writer.write_all(&byte_bucket).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        remaining_bytes += 1;
+    }
+
+    if writer.flush().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+    }
+
+    // fsync: flush alone may succeed while data is still only in OS buffers
+    if writer.get_ref().sync_all().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Sync));
+    }
+
+    Ok(())
+}
+
+/// Toggle block comment markers around range of lines (bytewise)
+///
+/// # Overview
+/// Adds or removes block comment markers (`/* */` or `"""`) at column 0.
+/// Markers placed on their own lines before/after content.
+///
+/// # Rules
+/// - **Column 0 only** - no indentation handling
+/// - **Single line (start == end)** - ADD mode, unless the line is already
+///   wrapped (marker lines directly above and below): then REMOVE those markers
+/// - **Detection** - if BOTH lines start with markers → REMOVE, else ADD
+/// - **ADD mode** - insert new line before start, new line after end
+/// - **REMOVE mode** - delete start and end line
+/// - **One pass** - detection happens while the file is copied, holding only
+///   the two candidate marker lines (see `write_block_toggle_in()`)
+///
+/// # Arguments
+/// * `file_path` - Path to source file
+/// * `start_line` - First line of content range (zero-indexed)
+/// * `end_line` - Last line of content range (zero-indexed)
+///
+/// # Returns
+/// * `Ok(())` - Block comment toggled successfully
+/// * `Err(ToggleCommentError)` - Specific error code
+///
+/// # Example (Rust - ADD mode)
+/// ```text
+/// Before:
+/// line 5: code line 1
+/// line 6: code line 2
+///
+/// After toggle_block_comment_bytewise(path, 5, 6):
+/// line 5: /*
+/// line 6: code line 1
+/// line 7: code line 2
+/// line 8: */
+/// ```
+///
+/// # Example (Rust - REMOVE mode)
+/// ```text
+/// Before:
+/// line 5: /*
+/// line 6: code line 1
+/// line 7: code line 2
+/// line 8: */
+///
+/// After toggle_block_comment_bytewise(path, 5, 8):
+/// line 5: code line 1
+/// line 6: code line 2
+/// ```
+///
+/// # Example (Python)
+/// ```text
+/// Uses """ markers instead of /* */
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_block_comment_bytewise(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+) -> Result<(), ToggleCommentError> {
+    toggle_block_comment_bytewise_with_options(
+        file_path,
+        start_line,
+        end_line,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_block_comment_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_block_comment_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_marker_block(
+        file_path,
+        start_line,
+        end_line,
+        options,
+        "block",
+        determine_block_markers_with,
+    )
+}
+
+/// Toggle Perl POD markers (`=pod` / `=cut`) around a range of lines
+///
+/// # Overview
+/// `toggle_block_comment_bytewise()` with POD instead of the extension's
+/// block comment: the same ADD / REMOVE detection (both range ends marker
+/// lines: REMOVE; a single line: ADD unless directly wrapped), markers at
+/// column 0 that do not nest (see `marker_at_column_zero()`).
+///
+/// # Arguments
+/// * `file_path` - Path to a `.pl` / `.pm` file (others: no-op, `Ok(())`)
+/// * `start_line` - First line of content range (zero-indexed)
+/// * `end_line` - Last line of content range (zero-indexed)
+///
+/// # Example
+/// ```text
+/// Before:            After toggle_pod_block_bytewise(path, 1, 2):
+/// line 0: use strict;        line 0: use strict;
+/// line 1: old();             line 1: =pod
+/// line 2: older();           line 2: old();
+///                            line 3: older();
+///                            line 4: =cut
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_pod_block_bytewise(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+) -> Result<(), ToggleCommentError> {
+    toggle_pod_block_bytewise_with_options(
+        file_path,
+        start_line,
+        end_line,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_pod_block_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_pod_block_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_marker_block(
+        file_path,
+        start_line,
+        end_line,
+        options,
+        "pod",
+        determine_pod_markers,
+    )
+}
+
+/// Block toggle shared by `--block` and `--pod`: `markers_of` picks the
+/// markers for the target's extension (None: no-op)
+#[cfg(feature = "fs")]
+fn toggle_marker_block(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+    operation: &'static str,
+    markers_of: fn(&str, &ToggleOptions) -> Option<BlockMarkers>,
+) -> Result<(), ToggleCommentError> {
+    // Sort range
+    let (start, end) = sort_range(start_line, end_line);
+
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    // Determine block markers from extension
+    let extension = match target_extension(&absolute_path, options) {
+        Some(ext) => ext,
+        None => return Err(ToggleCommentError::NoExtension),
+    };
+
+    if is_json_without_comments(&extension) {
+        return Err(ToggleCommentError::JsonNoComments);
+    }
+
+    let markers = match markers_of(&extension, options) {
+        Some(m) => m,
+        None => return Ok(()), // Unsupported - no-op
+    };
+
+    // Refuse ranges that reach into a protected region (before any backup is made)
+    if let Some((_, Some(line))) =
+        find_line_start_tracked(file_path, end, start).map_err(|e| match e {
+            ToggleIndentError::FileNotFound => ToggleCommentError::FileNotFound,
+            ToggleIndentError::LineNotFound {
+                requested,
+                file_lines,
+            } => ToggleCommentError::LineNotFound {
+                requested,
+                file_lines,
+            },
+            ToggleIndentError::IoError(op) => ToggleCommentError::IoError(op),
+            ToggleIndentError::PermissionDenied(op) => ToggleCommentError::PermissionDenied(op),
+            ToggleIndentError::PathError => ToggleCommentError::PathError,
+            ToggleIndentError::IncompleteWrite => ToggleCommentError::IncompleteWrite,
+            ToggleIndentError::ReadOnlyTarget => ToggleCommentError::ReadOnlyTarget,
+            ToggleIndentError::FileTooLarge { size, max } => {
+                ToggleCommentError::FileTooLarge { size, max }
+            }
+            ToggleIndentError::ProtectedRegion { line } => {
+                ToggleCommentError::ProtectedRegion { line }
+            }
+            ToggleIndentError::Cancelled => ToggleCommentError::Cancelled,
+            ToggleIndentError::ConcurrentModification => ToggleCommentError::ConcurrentModification,
+            ToggleIndentError::NotRegularFile => ToggleCommentError::NotRegularFile,
+            ToggleIndentError::OwnArtifact => ToggleCommentError::OwnArtifact,
+            ToggleIndentError::InvalidUtf8 { line } => ToggleCommentError::InvalidUtf8 { line },
+        })?
+    {
+        return Err(ToggleCommentError::ProtectedRegion { line });
+    }
+
+    // Get filename
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+
+    let trace = StageTrace::new(options, operation);
+
+    // New marker lines copy the first selected line's ending (no mixed endings)
+    // and, with `preserve_indent`, its indentation (not Ruby's column-0 ones)
+    let style = marker_style_of_line(&absolute_path, start)?;
+    let indented = options.preserve_indent && !marker_at_column_zero(markers.start);
+    let (start_buffer, start_len) = marker_line(markers.start, &style, indented);
+    let (end_buffer, end_len) = marker_line(markers.end, &style, indented);
+
+    // Marker lines REMOVE deletes: the range's own first and last line, or
+    // for a single line the lines directly around it (toggling back off)
+    let wrap = if start != end {
+        Some((start, end))
+    } else {
+        start.checked_sub(1).map(|open| (open, end + 1))
+    };
+    let mut plan = BlockPlan {
+        start,
+        end,
+        wrap,
+        markers,
+        start_marker_line: &start_buffer[..start_len],
+        end_marker_line: &end_buffer[..end_len],
+    };
+
+    // Create backup
+    let backup_path = backup_path_for(&filename, options);
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    let temp_filename = format!("temp_block_1_{}_{}", std::process::id(), filename);
+    let temp_path = temp_file_path(options.temp_dir.as_deref(), &temp_filename);
+    let _remove_temp = TempFileGuard(&temp_path);
+
+    // Opening marker without its closing one: the pass stopped early, ADD instead
+    let mut pass = trace.run_streaming(&absolute_path, &temp_path, || {
+        write_block_toggle_file(&absolute_path, &temp_path, &plan)
+    })?;
+    if pass == BlockPass::NotWrapped {
+        plan.wrap = None;
+        pass = trace.run_streaming(&absolute_path, &temp_path, || {
+            write_block_toggle_file(&absolute_path, &temp_path, &plan)
+        })?;
+    }
+    let (max_shrink, max_growth) = match pass {
+        BlockPass::Added(inserted) => (0, inserted),
+        BlockPass::Removed(removed) => (removed, 0),
+        BlockPass::NotWrapped => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+    };
+
+    // Replace original
+    if !trace.check(TraceStage::Verify, || {
+        replacement_size_is_plausible(&absolute_path, &temp_path, max_shrink, max_growth)
+    }) {
+        return Err(ToggleCommentError::IncompleteWrite);
+    }
+    if let Err(e) = trace.run(TraceStage::Replace, || {
+        replace_original(
+            &temp_path,
+            &absolute_path,
+            options.temp_dir.as_deref(),
+            options.durability,
+            &snapshot,
+        )
+    }) {
+        return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
+    }
+
+    Ok(())
+}
+
+/// What one block toggle pass does (`write_block_toggle_in()`)
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy)]
+struct BlockPlan<'a> {
+    /// Content lines: ADD puts a marker line before `start` and after `end`
+    start: usize,
+    end: usize,
+
+    /// Lines REMOVE deletes when they are the opening and the closing marker
+    /// line (None: always ADD)
+    wrap: Option<(usize, usize)>,
+
+    markers: BlockMarkers,
+
+    /// Marker lines ADD inserts, line ending included
+    start_marker_line: &'a [u8],
+    end_marker_line: &'a [u8],
+}
+
+/// Outcome of one block toggle pass
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockPass {
+    /// Marker lines inserted; bytes written for them
+    Added(u64),
+
+    /// Marker lines deleted; bytes they held
+    Removed(u64),
+
+    /// The opening line of `wrap` is a marker line but the closing one is
+    /// not: the output is incomplete, run again without `wrap`
+    NotWrapped,
+}
+
+/// Column-0 marker lines an ADD must not nest with (`marker_at_column_zero()`)
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy)]
+struct BlockNestingCheck {
+    /// Markers are column-0 ones (otherwise the check does nothing)
+    enabled: bool,
+
+    /// Current line against the opening / closing marker
+    start_match: MarkerLineMatch,
+    end_match: MarkerLineMatch,
+
+    /// Opening marker line of the block the current line is in
+    open: Option<usize>,
+}
+
+#[cfg(feature = "fs")]
+impl BlockNestingCheck {
+    fn new(markers: &BlockMarkers) -> BlockNestingCheck {
+        BlockNestingCheck {
+            enabled: marker_at_column_zero(markers.start),
+            start_match: MarkerLineMatch::LINE_START,
+            end_match: MarkerLineMatch::LINE_START,
+            open: None,
+        }
+    }
+
+    /// Feed one byte of the current line (not its `\n`)
+    fn feed(&mut self, markers: &BlockMarkers, byte: u8) {
+        if self.enabled {
+            self.start_match.feed(markers.start, byte);
+            self.end_match.feed(markers.end, byte);
+        }
+    }
+
+    /// Finish line `line`; `adding` says whether the pass ADDs and the line
+    /// is in the range (`first`: its first line)
+    ///
+    /// # Returns
+    /// * `Err(NestedBlockMarker)` - The range holds a marker line, or starts
+    ///   inside an existing block
+    fn end_line(
+        &mut self,
+        markers: &BlockMarkers,
+        line: usize,
+        adding: bool,
+        first: bool,
+    ) -> Result<(), ToggleCommentError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let opens = self.start_match.is_marker(markers.start);
+        let closes = self.end_match.is_marker(markers.end);
+        if adding
+            && first
+            && let Some(open) = self.open
+        {
+            return Err(ToggleCommentError::NestedBlockMarker { line: open });
+        }
+        if adding && (opens || closes) {
+            return Err(ToggleCommentError::NestedBlockMarker { line });
+        }
+        if opens {
+            self.open = Some(line);
+        } else if closes {
+            self.open = None;
+        }
+        self.start_match = MarkerLineMatch::LINE_START;
+        self.end_match = MarkerLineMatch::LINE_START;
+        Ok(())
+    }
+}
+
+/// Copy `reader` to `writer`, toggling block markers as `plan` says
+///
+/// # Overview
+/// Detection and edit in one pass. The opening line of `plan.wrap` is held
+/// (at most MAX_MARKER_LINE bytes, see `MarkerLineMatch`): a marker line is
+/// dropped and the pass goes on to REMOVE, anything else is written and the
+/// pass ADDs. In REMOVE the closing line is held the same way; when it is
+/// not a marker line the pass stops with `NotWrapped`, the only case that
+/// needs a second pass. Nothing else is buffered.
+///
+/// Column-0 markers (Ruby, POD) are also matched on every line, so an ADD that
+/// would nest blocks fails (see `BlockNestingCheck`).
+///
+/// # Returns
+/// * `Ok(BlockPass)` - Added / Removed (output complete) or NotWrapped
+/// * `Err(LineNotFound)` - Input ended before `plan.end`
+/// * `Err(ProtectedRegion)` - A marker line to delete is protected
+/// * `Err(NestedBlockMarker)` - ADD would nest column-0 marker blocks
+/// * `Err(ToggleCommentError)` - Read / write failed or iteration limit reached
+#[cfg(feature = "fs")]
+fn write_block_toggle_in<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    plan: &BlockPlan,
+) -> Result<BlockPass, ToggleCommentError> {
+    let write = |writer: &mut W, bytes: &[u8]| {
+        writer
+            .write_all(bytes)
+            .map_err(|_| ToggleCommentError::IoError(IoOperation::Write))
+    };
+
+    let (open, close) = match plan.wrap {
+        Some((open, close)) => (Some(open), Some(close)),
+        None => (None, None),
+    };
+    let mut mode: Option<BlockMode> = if plan.wrap.is_some() {
+        None
+    } else {
+        Some(BlockMode::Add)
+    };
+
+    let mut protection = ProtectedRegionTracker::new();
+    let mut current_line: usize = 0;
+    let mut at_line_start = true;
+    let mut byte_count: u64 = 0;
+    let mut inserted: u64 = 0;
+    let mut removed: u64 = 0;
+    let mut start_written = false;
+    let mut closed = false;
+
+    // Candidate marker line being held
+    let mut held = [0u8; MAX_MARKER_LINE];
+    let mut held_len: usize = 0;
+    let mut holding: Option<&[u8]> = None;
+    let mut held_protected = false;
+    let mut marker_match = MarkerLineMatch::LINE_START;
+    let mut nesting = BlockNestingCheck::new(&plan.markers);
+
+    loop {
+        if at_line_start {
+            holding = match mode {
+                None if Some(current_line) == open => Some(plan.markers.start),
+                Some(BlockMode::Remove) if Some(current_line) == close => Some(plan.markers.end),
+                _ => None,
+            };
+            held_len = 0;
+            held_protected = protection.in_region();
+            marker_match = MarkerLineMatch::LINE_START;
+            if mode == Some(BlockMode::Add) && current_line == plan.start && !start_written {
+                write(writer, plan.start_marker_line)?;
+                inserted += plan.start_marker_line.len() as u64;
+                start_written = true;
+            }
+        }
+
+        let byte = match read_single_byte(reader)? {
+            Some(byte) => byte,
+            None => break,
+        };
+
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if at_line_start && cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+        protection.push_byte(byte);
+        if byte != b'\n' {
+            nesting.feed(&plan.markers, byte);
+        }
+
+        match holding {
+            Some(marker) if byte != b'\n' && held_len < held.len() => {
+                marker_match.feed(marker, byte);
+                held[held_len] = byte;
+                held_len += 1;
+            }
+            Some(_) => {
+                // Line complete, or too long for a marker line
+                let is_marker = byte == b'\n' && marker_match.is_marker(holding.unwrap_or(b""));
+                holding = None;
+                if is_marker && held_protected {
+                    return Err(ToggleCommentError::ProtectedRegion { line: current_line });
+                }
+                match mode {
+                    Some(BlockMode::Remove) if !is_marker => return Ok(BlockPass::NotWrapped),
+                    _ if is_marker => {
+                        removed += held_len as u64 + 1;
+                        closed = mode == Some(BlockMode::Remove);
+                        mode = Some(BlockMode::Remove);
+                    }
+                    _ => {
+                        mode = Some(BlockMode::Add);
+                        if current_line == plan.start {
+                            write(writer, plan.start_marker_line)?;
+                            inserted += plan.start_marker_line.len() as u64;
+                            start_written = true;
+                        }
+                        write(writer, &held[..held_len])?;
+                        write(writer, &[byte])?;
+                    }
+                }
+            }
+            None => write(writer, &[byte])?,
+        }
+
+        at_line_start = byte == b'\n';
+        if at_line_start {
+            let in_range = (plan.start..=plan.end).contains(&current_line);
+            nesting.end_line(
+                &plan.markers,
+                current_line,
+                mode == Some(BlockMode::Add) && in_range,
+                current_line == plan.start,
+            )?;
+            if mode == Some(BlockMode::Add) && current_line == plan.end {
+                write(writer, plan.end_marker_line)?;
+                inserted += plan.end_marker_line.len() as u64;
+            }
+            current_line += 1;
+        }
+    }
+
+    // Held last line without a line ending
+    if let Some(marker) = holding {
+        let is_marker = held_len < held.len() && marker_match.is_marker(marker);
+        if is_marker && held_protected {
+            return Err(ToggleCommentError::ProtectedRegion { line: current_line });
+        }
+        match mode {
+            Some(BlockMode::Remove) if !is_marker => return Ok(BlockPass::NotWrapped),
+            _ if is_marker => {
+                removed += held_len as u64;
+                closed = mode == Some(BlockMode::Remove);
+                mode = Some(BlockMode::Remove);
+            }
+            _ => {
+                mode = Some(BlockMode::Add);
+                if current_line == plan.start && !start_written {
+                    write(writer, plan.start_marker_line)?;
+                    inserted += plan.start_marker_line.len() as u64;
+                }
+                write(writer, &held[..held_len])?;
+            }
+        }
+    }
+    if !at_line_start {
+        let in_range = (plan.start..=plan.end).contains(&current_line);
+        nesting.end_line(
+            &plan.markers,
+            current_line,
+            mode == Some(BlockMode::Add) && in_range,
+            current_line == plan.start,
+        )?;
+    }
+
+    match mode {
+        Some(BlockMode::Remove) if closed => Ok(BlockPass::Removed(removed)),
+        Some(BlockMode::Remove) => Ok(BlockPass::NotWrapped),
+        _ if current_line < plan.end || (current_line == plan.end && at_line_start) => {
+            Err(ToggleCommentError::LineNotFound {
+                requested: plan.end,
+                file_lines: current_line + usize::from(!at_line_start),
+            })
+        }
+        _ => {
+            // The last line is unterminated (or the empty position after the
+            // final newline): end it like the marker line, then close
+            if current_line == plan.end {
+                let ending: &[u8] = if plan.end_marker_line.ends_with(b"\r\n") {
+                    b"\r\n"
+                } else {
+                    b"\n"
+                };
+                write(writer, ending)?;
+                write(writer, plan.end_marker_line)?;
+                inserted += (ending.len() + plan.end_marker_line.len()) as u64;
+            }
+            Ok(BlockPass::Added(inserted))
+        }
+    }
+}
+
+/// `write_block_toggle_in()` from `source_path` into a new `dest_path`
+#[cfg(feature = "fs")]
+fn write_block_toggle_file(
+    source_path: &Path,
+    dest_path: &Path,
+    plan: &BlockPlan,
+) -> Result<BlockPass, ToggleCommentError> {
+    let source_file = match File::open(source_path) {
+        Ok(f) => TimedIo(f),
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let dest_file = match File::create(dest_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+    };
+
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, TimedIo(dest_file));
+    let pass = write_block_toggle_in(
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, source_file),
+        &mut writer,
+        plan,
+    )?;
+
+    if writer.flush().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+    }
+
+    Ok(pass)
+}
+
+// ============================================================================
+// TESTS - PHASE 3: BLOCK COMMENTS BYTEWISE
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod block_comment_bytewise_tests {
+    use super::*;
+
+    #[test]
+    fn test_write_block_toggle_in_one_pass() {
+        let markers = BlockMarkers {
+            start: b"/*",
+            end: b"*/",
+        };
+        let run = |input: &str, start, end, wrap| {
+            let plan = BlockPlan {
+                start,
+                end,
+                wrap,
+                markers,
+                start_marker_line: b"/*\n",
+                end_marker_line: b"*/\n",
+            };
+            let mut output = Vec::new();
+            let pass = write_block_toggle_in(&mut input.as_bytes(), &mut output, &plan);
+            (pass, String::from_utf8(output).unwrap())
+        };
+
+        // Add, remove, and the single-line case wrapped by its neighbours
+        assert_eq!(
+            run("a\nb\nc\n", 0, 1, Some((0, 1))),
+            (Ok(BlockPass::Added(6)), "/*\na\nb\n*/\nc\n".to_string())
+        );
+        assert_eq!(
+            run("/*\na\n*/\nc\n", 0, 2, Some((0, 2))),
+            (Ok(BlockPass::Removed(6)), "a\nc\n".to_string())
+        );
+        assert_eq!(
+            run("x\n/*\nb\n*/", 2, 2, Some((1, 3))),
+            (Ok(BlockPass::Removed(5)), "x\nb\n".to_string())
+        );
+
+        // Opening marker without a closing one needs the ADD pass
+        assert_eq!(
+            run("/*\nb\nc\n", 0, 2, Some((0, 2))).0,
+            Ok(BlockPass::NotWrapped)
+        );
+        assert_eq!(
+            run("/*\nb\nc\n", 0, 2, None),
+            (Ok(BlockPass::Added(6)), "/*\n/*\nb\nc\n*/\n".to_string())
+        );
+
+        // Unterminated last line is ended before the closing marker
+        assert_eq!(
+            run("a\nb", 1, 1, Some((0, 2))),
+            (Ok(BlockPass::Added(7)), "a\n/*\nb\n*/\n".to_string())
+        );
+        assert!(matches!(
+            run("a\nb\n", 0, 2, Some((0, 2))).0,
+            Err(ToggleCommentError::LineNotFound { .. })
+        ));
+    }
+
+    // ========================================
+    // Detection Tests
+    // ========================================
+
+    #[test]
+    fn test_detect_line_pattern_match() {
+        let content = "line 0\n/*\nline 2\n";
+        let test_file = create_test_file("test_detect_match.rs", content);
+
+        let result = detect_line_pattern(test_file.to_str().unwrap(), 1, b"/*\n");
+        assert!(result.is_ok());
+        assert!(result.unwrap());
+
+        cleanup_files(&[&test_file]);
+    }
+
+    #[test]
+    fn test_detect_line_pattern_no_match() {
+        let content = "line 0\ncode\nline 2\n";
+        let test_file = create_test_file("test_detect_no_match.rs", content);
+
+        let result = detect_line_pattern(test_file.to_str().unwrap(), 1, b"/*\n");
+        assert!(result.is_ok());
+        assert!(!result.unwrap());
+
+        cleanup_files(&[&test_file]);
+    }
+
+    #[test]
+    fn test_detect_line_pattern_indented() {
+        let content = "line 0\n  /*\nline 2\n";
+        let test_file = create_test_file("test_detect_indented.rs", content);
+
+        // Should NOT match - pattern must be at column 0
+        let result = detect_line_pattern(test_file.to_str().unwrap(), 1, b"/*\n");
+        assert!(result.is_ok());
+        assert!(!result.unwrap());
+
+        cleanup_files(&[&test_file]);
+    }
+
+    // ========================================
+    // Delete Line Tests
+    // ========================================
+
+    #[test]
+    fn test_delete_line_bytewise_middle() {
+        let content = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_delete_middle.txt", content);
+        let temp_file = PathBuf::from("temp_delete_test.txt");
+
+        let result = delete_line_bytewise(&test_file, &temp_file, 1);
+        assert_eq!(result, Ok(7)); // "line 1\n"
+
+        let new_content = read_file_content(&temp_file);
+        assert_eq!(new_content, "line 0\nline 2\n");
+
+        cleanup_files(&[&test_file, &temp_file]);
+    }
+
+    #[test]
+    fn test_delete_line_bytewise_first() {
+        let content = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_delete_first.txt", content);
+        let temp_file = PathBuf::from("temp_delete_first.txt");
+
+        let result = delete_line_bytewise(&test_file, &temp_file, 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&temp_file);
+        assert_eq!(new_content, "line 1\nline 2\n");
+
+        cleanup_files(&[&test_file, &temp_file]);
+    }
+
+    // ========================================
+    // Insert Line Tests
+    // ========================================
+
+    #[test]
+    fn test_insert_line_before_bytewise() {
+        let content = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_insert_before.txt", content);
+        let temp_file = PathBuf::from("temp_insert_before.txt");
+
+        let result = insert_line_before_bytewise(&test_file, &temp_file, 1, b"NEW\n");
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&temp_file);
+        assert_eq!(new_content, "line 0\nNEW\nline 1\nline 2\n");
+
+        cleanup_files(&[&test_file, &temp_file]);
+    }
+
+    #[test]
+    fn test_insert_line_after_bytewise() {
+        let content = "line 0\nline 1\nline 2\n";
+        let test_file = create_test_file("test_insert_after.txt", content);
+        let temp_file = PathBuf::from("temp_insert_after.txt");
+
+        let result = insert_line_after_bytewise(&test_file, &temp_file, 1, b"NEW\n");
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&temp_file);
+        assert_eq!(new_content, "line 0\nline 1\nNEW\nline 2\n");
+
+        cleanup_files(&[&test_file, &temp_file]);
+    }
+
+    // ========================================
+    // Block Comment Toggle Tests
+    // ========================================
+
+    #[test]
+    fn test_block_comment_bytewise_add_rust() {
+        let content = "code line 1\ncode line 2\n";
+        let test_file = create_test_file("test_block_add.rs", content);
+
+        let result = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 1);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "/*\ncode line 1\ncode line 2\n*/\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_add.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comment_bytewise_remove_rust() {
+        let content = "/*\ncode line 1\ncode line 2\n*/\n";
+        let test_file = create_test_file("test_block_remove.rs", content);
+
+        let result = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 3);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "code line 1\ncode line 2\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_remove.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comment_bytewise_single_line() {
+        let content = "code line\n";
+        let test_file = create_test_file("test_block_single.rs", content);
+
+        // Single line always ADD
+        let result = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 0);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "/*\ncode line\n*/\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_single.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comment_bytewise_single_line_roundtrip() {
+        let original = "fn a() {}\ncode line\nfn b() {}\n";
+        let test_file = create_test_file("test_block_single_roundtrip.rs", original);
+        let path = test_file.to_str().unwrap();
+        let wrapped = "fn a() {}\n/*\ncode line\n*/\nfn b() {}\n";
+
+        // Same one-line selection adds, then removes the adjacent markers
+        assert!(toggle_block_comment_bytewise(path, 1, 1).is_ok());
+        assert_eq!(read_file_content(&test_file), wrapped);
+        assert!(toggle_block_comment_bytewise(path, 2, 2).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
+
+        // Selecting the marker lines themselves also removes them, in either order
+        assert!(toggle_block_comment_bytewise(path, 1, 1).is_ok());
+        assert!(toggle_block_comment_bytewise(path, 3, 1).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
+
+        // Last line: no line below, so it is wrapped rather than an error
+        assert!(toggle_block_comment_bytewise(path, 2, 2).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "fn a() {}\ncode line\n/*\nfn b() {}\n*/\n"
+        );
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_single_roundtrip.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comment_bytewise_crlf() {
+        let original = "a();\r\nb();\r\nc();";
+        let test_file = create_test_file("test_block_crlf.rs", original);
+        let path = test_file.to_str().unwrap();
+
+        // Markers copy the file's CRLF endings, including after an unterminated last line
+        assert!(toggle_block_comment_bytewise(path, 1, 2).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "a();\r\n/*\r\nb();\r\nc();\r\n*/\r\n"
+        );
+
+        // CRLF marker lines are recognised for removal
+        assert!(toggle_block_comment_bytewise(path, 1, 4).is_ok());
+        assert_eq!(read_file_content(&test_file), "a();\r\nb();\r\nc();\r\n");
+        assert!(toggle_block_comment_bytewise(path, 0, 0).is_ok());
+        assert!(toggle_block_comment_bytewise(path, 1, 1).is_ok());
+        assert_eq!(read_file_content(&test_file), "a();\r\nb();\r\nc();\r\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_crlf.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comment_bytewise_indented_markers() {
+        let original = "fn f() {\n    a();\n    b();\n}\n";
+        let test_file = create_test_file("test_block_indented.rs", original);
+        let path = test_file.to_str().unwrap();
+        let indented = ToggleOptions {
+            preserve_indent: true,
+            ..ToggleOptions::default()
+        };
+
+        // preserve_indent: markers copy the first line's indentation
+        assert!(toggle_block_comment_bytewise_with_options(path, 1, 2, &indented).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "fn f() {\n    /*\n    a();\n    b();\n    */\n}\n"
+        );
+
+        // Indented markers (also from editors) are recognised for removal
+        assert!(toggle_block_comment_bytewise(path, 1, 4).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
+        assert!(
+            find_marker_line_after(&mut "/*\n\t*/ \n \t*/\r\n".as_bytes(), 0, b"*/")
+                .is_ok_and(|line| line == Some(2))
+        );
+
+        // Default: markers stay at column 0
+        assert!(toggle_block_comment_bytewise(path, 1, 2).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "fn f() {\n/*\n    a();\n    b();\n*/\n}\n"
+        );
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_indented.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comment_bytewise_ruby() {
+        let original = "def f
+  a
+end
+";
+        let test_file = create_test_file("test_block_ruby.rb", original);
+        let path = test_file.to_str().unwrap();
+        let indented = ToggleOptions {
+            preserve_indent: true,
+            ..ToggleOptions::default()
+        };
+
+        // Column 0 even with preserve_indent; removed again by the same range
+        toggle_block_comment_bytewise_with_options(path, 1, 1, &indented).unwrap();
+        assert_eq!(
+            read_file_content(&test_file),
+            "def f\n=begin\n  a\n=end\nend\n"
+        );
+        toggle_block_comment_bytewise(path, 1, 3).unwrap();
+        assert_eq!(read_file_content(&test_file), original);
+
+        // An indented `=begin` is code, not a marker: ADD
+        std::fs::write(&test_file, "  =begin\nx\n").unwrap();
+        toggle_block_comment_bytewise(path, 0, 1).unwrap();
+        assert_eq!(read_file_content(&test_file), "=begin\n  =begin\nx\n=end\n");
+
+        // Blocks do not nest: no range around a marker, none inside a block
+        let nested = "  =begin\nx\n=begin\ny\nz\n=end\n";
+        std::fs::write(&test_file, nested).unwrap();
+        assert_eq!(
+            toggle_block_comment_bytewise(path, 1, 2),
+            Err(ToggleCommentError::NestedBlockMarker { line: 2 })
+        );
+        assert_eq!(
+            toggle_block_comment_bytewise(path, 4, 4),
+            Err(ToggleCommentError::NestedBlockMarker { line: 2 })
+        );
+        assert_eq!(read_file_content(&test_file), nested);
+
+        // No one-line form: `# x` does not convert to `=begin x =end`
+        assert!(comment_styles_of(&test_file).is_none());
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_ruby.rb"),
+        ]);
+    }
+
+    #[test]
+    fn test_pod_block_bytewise() {
+        let original = "use strict;\nold();\nolder();\n";
+        let test_file = create_test_file("test_block_pod.pm", original);
+        let path = test_file.to_str().unwrap();
+
+        toggle_pod_block_bytewise(path, 2, 1).unwrap();
+        assert_eq!(
+            read_file_content(&test_file),
+            "use strict;\n=pod\nold();\nolder();\n=cut\n"
+        );
+        toggle_pod_block_bytewise(path, 1, 4).unwrap();
+        assert_eq!(read_file_content(&test_file), original);
+
+        // `--block` has nothing for Perl; `--pod` nothing for other files
+        toggle_block_comment_bytewise(path, 1, 2).unwrap();
+        assert_eq!(read_file_content(&test_file), original);
+        let ruby_file = create_test_file("test_block_pod.rb", original);
+        toggle_pod_block_bytewise(ruby_file.to_str().unwrap(), 1, 2).unwrap();
+        assert_eq!(read_file_content(&ruby_file), original);
+
+        cleanup_files(&[
+            &test_file,
+            &ruby_file,
+            &PathBuf::from("backup_toggle_comment_test_block_pod.pm"),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comment_bytewise_python() {
+        let content = "code line 1\ncode line 2\n";
+        let test_file = create_test_file("test_block_python.py", content);
+
+        let result = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 1);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "\"\"\"\ncode line 1\ncode line 2\n\"\"\"\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_python.py"),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comment_bytewise_markdown_roundtrip() {
+        let original = "# Title\ndraft 1\ndraft 2\n";
+        let test_file = create_test_file("test_block_markdown.md", original);
+        let path = test_file.to_str().unwrap();
+
+        assert!(toggle_block_comment_bytewise(path, 1, 2).is_ok());
+        assert_eq!(
+            read_file_content(&test_file),
+            "# Title\n<!--\ndraft 1\ndraft 2\n-->\n"
+        );
+
+        assert!(toggle_block_comment_bytewise(path, 1, 4).is_ok());
+        assert_eq!(read_file_content(&test_file), original);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_markdown.md"),
+        ]);
+    }
+
+    #[test]
+    fn test_block_comment_bytewise_roundtrip() {
+        let original = "code line 1\ncode line 2\n";
+        let test_file = create_test_file("test_block_roundtrip.rs", original);
+
+        // Add
+        let result1 = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 1);
+        assert!(result1.is_ok());
+
+        let content1 = read_file_content(&test_file);
+        assert_eq!(content1, "/*\ncode line 1\ncode line 2\n*/\n");
+
+        // Remove
+        let result2 = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 3);
+        assert!(result2.is_ok());
+
+        let content2 = read_file_content(&test_file);
+        assert_eq!(content2, original);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_block_roundtrip.rs"),
+        ]);
+    }
+}
+
+// ============================================================================
+// PHASE 4: BATCH TOGGLE - LIST OF LINES, CHUNKED PASSES (BYTEWISE)
+// ============================================================================
+//
+// ## Project Context
+// Editor integrations and grep-driven scripts need to toggle many scattered
+// lines at once (e.g. every line matching a pattern). Calling the single-line
+// toggle N times costs N backups and N full rewrites, and leaves the backup
+// holding an intermediate state rather than the original.
+//
+// Batch mode instead:
+// - creates ONE backup of the original
+// - toggles each requested line independently, using the same column-0
+//   `{flag}{1 space}` rule as the single-line toggle
+// - copies every other byte unchanged (line endings preserved)
+// - replaces the original ONCE, only after everything succeeded
+//
+// ## Chunked Passes
+// At most MAX_BATCH_LINES line numbers are held (sorted, de-duplicated) in a
+// fixed stack array per pass. Larger selections are processed as several
+// passes over the file. Comment toggles never add or remove lines, so line
+// numbers remain valid from one pass to the next; passes are chained through
+// two temp files (`BatchPassChain`), so a 5,000-line selection is still one
+// invocation, one backup, one replace.
+//
+// - **Slice input**: each pass selects the next MAX_BATCH_LINES smallest
+//   distinct line numbers from the caller's slice (re-scanning the slice,
+//   no copy, no heap). Duplicates are removed across the whole selection.
+// - **Stream input** (e.g. stdin fed by grep): each chunk of MAX_BATCH_LINES
+//   entries read is one pass. Streams cannot be re-scanned, so duplicates are
+//   removed within a chunk only; a line number repeated in two different
+//   chunks is toggled twice (net: unchanged).
+//
+// Total line numbers per operation are bounded by MAX_TOTAL_BATCH_LINES.
+//
+// - **Ranges** (`toggle_ranges_basic_comments`): up to MAX_RANGES inclusive
+//   ranges are sorted in a fixed stack array and checked for overlaps; only
+//   their ends are held, so all of them are one pass whatever their length.
+// ============================================================================
+
+/// Maximum number of lines toggled in one pass through the file
+/// Bounds the fixed stack array used to sort and de-duplicate line numbers
+pub const MAX_BATCH_LINES: usize = 128;
+
+/// Default upper bound on line numbers accepted from a stream (e.g. stdin)
+pub const DEFAULT_MAX_STREAMED_LINES: usize = 4096;
+
+/// Hard ceiling on line numbers in one batch operation (all passes together),
+/// slice or stream, whatever the caller asks
+/// Keeps the pass loop bounded
+pub const MAX_TOTAL_BATCH_LINES: usize = 1_000_000;
+
+/// Maximum number of ranges in one multi-range toggle (one pass)
+pub const MAX_RANGES: usize = 128;
+
+/// Maximum bytes in a single line-number entry of a stream (digits + whitespace)
+/// A usize has at most 20 decimal digits; anything longer is not a line number
+const MAX_LINE_NUMBER_ENTRY_BYTES: usize = 32;
+
+/// Read one byte from a reader
+///
+/// # Returns
+/// * `Ok(Some(byte))` - Byte read
+/// * `Ok(None)` - End of input
+/// * `Err(Cancelled)` - `request_cancel()` was called
+/// * `Err(ToggleCommentError)` - Read failed
+fn read_single_byte<R: Read>(reader: &mut R) -> Result<Option<u8>, ToggleCommentError> {
+    if cancel_requested() {
+        return Err(ToggleCommentError::Cancelled);
+    }
+    let mut byte_bucket: [u8; 1] = [0u8; 1];
+    match reader.read(&mut byte_bucket) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(byte_bucket[0])),
+        Err(_) => Err(ToggleCommentError::IoError(IoOperation::Read)),
+    }
+}
+
+/// Copy the remainder of the current line (newline included) to the writer
+///
+/// # Returns
+/// * `Ok(true)` - Line ended with a newline (more input may follow)
+/// * `Ok(false)` - End of input reached
+/// * `Err(ToggleCommentError)` - Read or write failed
+fn copy_rest_of_line<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<bool, ToggleCommentError> {
+    let mut bytes_copied: u64 = 0;
+    loop {
+        if bytes_copied >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        let byte = match read_single_byte(reader)? {
+            Some(b) => b,
+            None => return Ok(false), // EOF
+        };
+
+        if writer.write_all(&[byte]).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+
+        if byte == b'\n' {
+            return Ok(true); // End of line
+        }
+
+        bytes_copied += 1;
+    }
+}
+
+/// Copy the rest of a line, adding or dropping ` {suffix}` before its ending
+///
+/// # Overview
+/// Used for flags with a closing part (HTML `-->`). The last few bytes of the
+/// line are held in a small stack buffer until the line ending (`\n`, `\r\n`
+/// or EOF) is seen, so the suffix goes before a `\r` and CRLF is preserved.
+///
+/// - **Add** (`add_suffix = true`): ` {suffix}` is written before the ending
+/// - **Drop** (`add_suffix = false`): a trailing ` {suffix}` is removed if
+///   present; a line without it is copied unchanged
+///
+/// # Arguments
+/// * `pending` - Bytes of this line the caller already read (processed first)
+/// * `pending_then_eof` - Input ended right after `pending`
+///
+/// # Returns
+/// * `Ok(true)` - Line ended with a newline (more input may follow)
+/// * `Ok(false)` - End of input reached
+/// * `Err(ToggleCommentError)` - Read or write failed
+fn copy_rest_of_line_with_suffix<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    pending: &[u8],
+    pending_then_eof: bool,
+    suffix: &[u8],
+    add_suffix: bool,
+) -> Result<bool, ToggleCommentError> {
+    // Tail held back: space + suffix + '\r' (" -->\r" is 5 bytes)
+    let mut held: [u8; 8] = [0u8; 8];
+    let hold_capacity = (suffix.len() + 2).min(held.len());
+    let mut held_len: usize = 0;
+    let mut pending_index: usize = 0;
+    let mut bytes_copied: u64 = 0;
+
+    let ends_with_newline = loop {
+        if bytes_copied >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        bytes_copied += 1;
+
+        let next = if pending_index < pending.len() {
+            pending_index += 1;
+            Some(pending[pending_index - 1])
+        } else if pending_then_eof {
+            None
+        } else {
+            read_single_byte(reader)?
+        };
+
+        let byte = match next {
+            Some(b'\n') => break true,
+            None => break false,
+            Some(b) => b,
+        };
+
+        if held_len == hold_capacity {
+            // Oldest held byte can no longer be part of the tail
+            if writer.write_all(&held[..1]).is_err() {
+                return Err(ToggleCommentError::IoError(IoOperation::Write));
+            }
+            held.copy_within(1..held_len, 0);
+            held_len -= 1;
+        }
+        held[held_len] = byte;
+        held_len += 1;
+    };
+
+    // Split the held tail into content and an optional '\r'
+    let (content, carriage_return) = match held[..held_len].split_last() {
+        Some((b'\r', rest)) => (rest, true),
+        _ => (&held[..held_len], false),
+    };
+
+    let mut keep = content.len();
+    if !add_suffix
+        && content.len() > suffix.len()
+        && content.ends_with(suffix)
+        && content[content.len() - suffix.len() - 1] == b' '
+    {
+        keep -= suffix.len() + 1;
+    }
+
+    if writer.write_all(&content[..keep]).is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Write));
+    }
+    if add_suffix {
+        if writer.write_all(b" ").is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+        if writer.write_all(suffix).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+    }
+    if carriage_return && writer.write_all(b"\r").is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Write));
+    }
+    if ends_with_newline && writer.write_all(b"\n").is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Write));
+    }
+
+    Ok(ends_with_newline)
+}
+
+/// How `toggle_line()` adds and detects the comment on one line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineToggleRule {
+    /// Flag to add/remove
+    comment_flag: CommentFlag,
+
+    /// Optional second flag that is removed but never added (PHP `#`)
+    removal_only_flag: Option<CommentFlag>,
+
+    /// Match and insert the flag after the line's leading spaces, not at column 0
+    preserve_indent: bool,
+
+    /// Toggle each line by its own state, or only add / only remove
+    direction: LineToggleDirection,
+
+    /// What counts as commented and what is removed (see `RemovalMode`)
+    removal: RemovalMode,
+
+    /// Line endings written for every line of the file (see `EolMode`)
+    eol: EolMode,
+
+    /// End-of-file newline handling (see `FinalNewline`)
+    final_newline: FinalNewline,
+
+    /// Strip trailing spaces/tabs from each toggled line
+    trim_trailing: bool,
+
+    /// Copy empty lines unchanged (what `Add` always does)
+    skip_blank: bool,
+
+    /// Report line endings of edited / converted lines on stderr in this
+    /// format (`TraceFormat::Off`: no audit)
+    eol_audit: TraceFormat,
+
+    /// Refuse lines inside shell here-doc bodies (see `guards_heredocs()`)
+    heredocs: bool,
+
+    /// With `preserve_indent`, leading tabs are indentation too
+    /// (`ToggleOptions::indent_tab`); otherwise only spaces are
+    indent_tabs: bool,
+}
+
+impl LineToggleRule {
+    /// Largest size change toggling one line can cause
+    fn max_edit_bytes(&self) -> u64 {
+        match self.removal {
+            RemovalMode::Strict => MAX_TOGGLE_EDIT_BYTES,
+            RemovalMode::Relaxed => MAX_TOGGLE_EDIT_BYTES + MAX_RELAXED_SPACES as u64,
+        }
+    }
+}
+
+/// Which way a line comment toggle may change a line
+///
+/// Whole-span operations (Python suites) use `Add` / `Remove` so that a span
+/// containing comment lines is commented uniformly and restored exactly.
+/// Callers choose it for line toggles with `ToggleOptions::direction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineToggleDirection {
+    /// Remove the flag where present, add it elsewhere
+    Toggle,
+
+    /// Always add (a commented line gets a second flag); blank lines are copied unchanged
+    Add,
+
+    /// Only remove; lines without the flag are copied unchanged
+    Remove,
+}
+
+/// Longest `{flag}{space}{lookahead}` that `toggle_line()` writes in one go:
+/// `<!-- ` plus the 5-byte lookahead
+const MAX_ADDED_HEAD: usize = 10;
+
+/// Expected byte at `index` of the `{flag}{1 space}` pattern
+fn pattern_byte(flag_bytes: &[u8], index: usize) -> u8 {
+    if index < flag_bytes.len() {
+        flag_bytes[index]
+    } else {
+        b' '
+    }
+}
+
+/// Toggle the comment flag on the line at the reader's position
+///
+/// # Overview
+/// Same detection rule as `find_and_detect_tag_state()`: the line is commented
+/// when it starts with `{flag}{1 space}` at column 0. Up to 5 bytes are held in
+/// a stack lookahead buffer to decide; nothing else is buffered.
+///
+/// With `rule.preserve_indent` the leading spaces (and tabs, with
+/// `rule.indent_tabs`) are copied first and the same rule applies from the
+/// first other byte (`  # - a` <-> `  - a`, `\t// f()` <-> `\tf()`).
+///
+/// - **Remove**: the `{flag}{space}` bytes are dropped, rest of line copied
+/// - **Add**: `{flag}{space}` is written, then the lookahead, then rest of line
+///
+/// Flags with a suffix (HTML `-->`) also add / drop ` {suffix}` at the end of
+/// the line (the removal-only flag is always prefix-only).
+///
+/// When `rule.removal_only_flag` is given (e.g. PHP `#`), a line starting with
+/// `{removal_only_flag}{space}` is also uncommented; adding always uses
+/// `rule.comment_flag`.
+///
+/// `rule.direction` can restrict the line to one change: `Add` skips the
+/// detection (blank lines are copied as-is), `Remove` copies unflagged lines.
+/// `rule.skip_blank` copies blank lines as-is in every direction.
+///
+/// With `RemovalMode::Relaxed` a line counts as commented once the flag
+/// itself matched; the flag and up to MAX_RELAXED_SPACES spaces are dropped.
+///
+/// # Returns
+/// * `Ok(true)` - Line ended with a newline (more input may follow)
+/// * `Ok(false)` - End of input reached
+/// * `Err(ToggleCommentError)` - Read or write failed
+fn toggle_line<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    rule: LineToggleRule,
+) -> Result<bool, ToggleCommentError> {
+    let comment_flag = rule.comment_flag;
+    let removal_only_flag = rule.removal_only_flag;
+    let flag_bytes = comment_flag.as_bytes();
+    let pattern_len = flag_bytes.len() + 1; // flag + one space
+    let alternate_bytes: &[u8] = match removal_only_flag {
+        Some(flag) => flag.as_bytes(),
+        None => b"",
+    };
+    let alternate_len = alternate_bytes.len() + 1;
+
+    // Longest pattern is "<!-- " (5 bytes); a full match returns before overflow
+    let mut lookahead: [u8; 5] = [0u8; 5];
+    let mut lookahead_len: usize = 0;
+    let mut primary_matches = true;
+    let mut alternate_matches = removal_only_flag.is_some();
+    let mut line_continues = true;
+    let mut line_finished = false;
+
+    // Indent-preserving: copy leading spaces, keep the first other byte (or EOF)
+    let mut first_byte: Option<Option<u8>> = None;
+    if rule.preserve_indent {
+        let mut spaces_copied: u64 = 0;
+        loop {
+            if spaces_copied >= MAX_BYTE_ITERATIONS {
+                return Err(ToggleCommentError::IoError(IoOperation::Read));
+            }
+            match read_single_byte(reader)? {
+                Some(byte) if byte == b' ' || (byte == b'\t' && rule.indent_tabs) => {
+                    if writer.write_all(&[byte]).is_err() {
+                        return Err(ToggleCommentError::IoError(IoOperation::Write));
+                    }
+                    spaces_copied += 1;
+                }
+                other => {
+                    first_byte = Some(other);
+                    break;
+                }
+            }
+        }
+    }
+
+    if rule.direction == LineToggleDirection::Add || rule.skip_blank {
+        // Add: no detection, only a blank line (end of line right away)
+        // stays as it is; skip_blank: a blank line stays, others toggle
+        let next = match first_byte.take() {
+            Some(already_read) => already_read,
+            None => read_single_byte(reader)?,
+        };
+        match next {
+            None => return Ok(false),
+            Some(b'\n') => {
+                if writer.write_all(b"\n").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+                return Ok(true);
+            }
+            Some(b'\r') => {
+                if writer.write_all(b"\r").is_err() {
+                    return Err(ToggleCommentError::IoError(IoOperation::Write));
+                }
+                return copy_rest_of_line(reader, writer);
+            }
+            Some(byte) if rule.direction == LineToggleDirection::Add => {
+                lookahead[0] = byte;
+                lookahead_len = 1;
+                primary_matches = false;
+                alternate_matches = false;
+            }
+            Some(byte) => first_byte = Some(Some(byte)),
+        }
+    }
+
+    while primary_matches || alternate_matches {
+        if rule.removal == RemovalMode::Relaxed {
+            if primary_matches && lookahead_len == flag_bytes.len() {
+                return remove_relaxed_rest(reader, writer, comment_flag.suffix_bytes());
+            }
+            if alternate_matches && lookahead_len == alternate_bytes.len() {
+                return remove_relaxed_rest(reader, writer, b"");
+            }
+        }
+        if primary_matches && lookahead_len == pattern_len {
+            // REMOVE MODE: drop the flag and its space (and suffix), copy the rest
+            let suffix = comment_flag.suffix_bytes();
+            if !suffix.is_empty() {
+                return copy_rest_of_line_with_suffix(reader, writer, &[], false, suffix, false);
+            }
+            return copy_rest_of_line(reader, writer);
+        }
+        if alternate_matches && lookahead_len == alternate_len {
+            // REMOVE MODE (removal-only flag): drop the flag and its space
+            return copy_rest_of_line(reader, writer);
+        }
+
+        // A complete flag followed by the end of the line also counts
+        // (`removable_at_eol()`): only the flag is dropped
+        let at_flag_end = (primary_matches
+            && lookahead_len == flag_bytes.len()
+            && comment_flag.removable_at_eol())
+            || (alternate_matches
+                && lookahead_len == alternate_bytes.len()
+                && removal_only_flag.is_some_and(|flag| flag.removable_at_eol()));
+
+        let next = match first_byte.take() {
+            Some(already_read) => already_read,
+            None => read_single_byte(reader)?,
+        };
+        if at_flag_end {
+            match next {
+                None => return Ok(false),
+                Some(b'\n') => {
+                    if writer.write_all(b"\n").is_err() {
+                        return Err(ToggleCommentError::IoError(IoOperation::Write));
+                    }
+                    return Ok(true);
+                }
+                Some(b'\r') => {
+                    let after = read_single_byte(reader)?;
+                    if matches!(after, None | Some(b'\n')) {
+                        let ending: &[u8] = if after.is_none() { b"\r" } else { b"\r\n" };
+                        if writer.write_all(ending).is_err() {
+                            return Err(ToggleCommentError::IoError(IoOperation::Write));
+                        }
+                        return Ok(after.is_some());
+                    }
+                    // Lone `\r`: not flagged (flags at the end of the line are
+                    // at most 3 bytes, so both bytes fit the lookahead)
+                    lookahead[lookahead_len] = b'\r';
+                    lookahead_len += 1;
+                    if let Some(byte) = after {
+                        lookahead[lookahead_len] = byte;
+                        lookahead_len += 1;
+                    }
+                    break;
+                }
+                Some(_) => {}
+            }
+        }
+        let byte = match next {
+            Some(b) => b,
+            None => {
+                line_continues = false;
+                line_finished = true;
+                break;
+            }
+        };
+
+        lookahead[lookahead_len] = byte;
+        lookahead_len += 1;
+
+        primary_matches = primary_matches && byte == pattern_byte(flag_bytes, lookahead_len - 1);
+        alternate_matches =
+            alternate_matches && byte == pattern_byte(alternate_bytes, lookahead_len - 1);
+
+        // A newline never matches a pattern, so the loop ends here
+        if byte == b'\n' {
+            line_finished = true;
+        }
+    }
+
+    if rule.direction == LineToggleDirection::Remove {
+        // Not flagged: copy what was looked at, then the rest, unchanged
+        if writer.write_all(&lookahead[..lookahead_len]).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+        if line_finished {
+            return Ok(line_continues);
+        }
+        return copy_rest_of_line(reader, writer);
+    }
+
+    // ADD MODE: flag + space, then whatever was looked at, then the rest.
+    // The head is assembled on the stack and written once (in front of the
+    // lookahead unless a suffix writer takes that)
+    let suffix = comment_flag.suffix_bytes();
+    let mut head = [0u8; MAX_ADDED_HEAD];
+    head[..flag_bytes.len()].copy_from_slice(flag_bytes);
+    head[flag_bytes.len()] = b' ';
+    let mut head_len = pattern_len;
+    if suffix.is_empty() {
+        head[head_len..head_len + lookahead_len].copy_from_slice(&lookahead[..lookahead_len]);
+        head_len += lookahead_len;
+    }
+    if writer.write_all(&head[..head_len]).is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Write));
+    }
+
+    if !suffix.is_empty() {
+        // Lookahead goes through the suffix writer so ` {suffix}` lands before the ending
+        let lookahead_then_eof = line_finished && !line_continues;
+        return copy_rest_of_line_with_suffix(
+            reader,
+            writer,
+            &lookahead[..lookahead_len],
+            lookahead_then_eof,
+            suffix,
+            true,
+        );
+    }
+
+    if line_finished {
+        return Ok(line_continues);
+    }
+
+    copy_rest_of_line(reader, writer)
+}
+
+/// Relaxed removal after a matched flag: drop spaces, copy the rest of the line
+///
+/// Up to MAX_RELAXED_SPACES spaces are dropped; a suffix flag also loses its
+/// ` {suffix}` at the end of the line.
+///
+/// # Returns
+/// * `Ok(true)` - Line ended with a newline (more input may follow)
+/// * `Ok(false)` - End of input reached
+/// * `Err(ToggleCommentError)` - Read or write failed
+fn remove_relaxed_rest<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    suffix: &[u8],
+) -> Result<bool, ToggleCommentError> {
+    let mut spaces_dropped: usize = 0;
+    let first = loop {
+        let next = read_single_byte(reader)?;
+        if next == Some(b' ') && spaces_dropped < MAX_RELAXED_SPACES {
+            spaces_dropped += 1;
+            continue;
+        }
+        break next;
+    };
+
+    let byte = match first {
+        Some(b) => b,
+        None => return Ok(false),
+    };
+
+    if !suffix.is_empty() {
+        return copy_rest_of_line_with_suffix(reader, writer, &[byte], false, suffix, false);
+    }
+
+    if writer.write_all(&[byte]).is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Write));
+    }
+    if byte == b'\n' {
+        return Ok(true);
+    }
+    copy_rest_of_line(reader, writer)
+}
+
+/// Writer adapter that rewrites line endings as bytes pass through
+///
+/// # Overview
+/// Sits between the line engine and the temp file, so `toggle_line()` and
+/// the copy helpers keep seeing (and preserving) the original endings, and
+/// the conversion happens on the way out. One pending `\r` is held to tell
+/// `\r\n` from a lone `\r`; `finish()` writes it if the input ended there.
+///
+/// The end of file is handled here too: with `FinalNewline::Strip` the most
+/// recent line ending is held until another byte follows (so the last one is
+/// never written); with `FinalNewline::Ensure`, `finish()` appends an ending
+/// (in the configured style, else the file's last one, else `\n`) when the
+/// output does not already end with one.
+///
+/// With `EolMode::Keep` and `FinalNewline::Keep` every byte is passed
+/// straight through.
+struct EolWriter<'a, W: Write> {
+    inner: &'a mut W,
+    mode: EolMode,
+    final_newline: FinalNewline,
+    pending_carriage_return: bool,
+    /// Ending not written yet (`Some(crlf)`), only with `FinalNewline::Strip`
+    held_ending: Option<bool>,
+    /// Style of the last ending written (`Some(crlf)`)
+    last_ending_crlf: Option<bool>,
+    /// Anything written, and whether the last thing written was an ending
+    wrote_any: bool,
+    ends_with_ending: bool,
+    /// Bytes added or removed by conversions (bound for the size check)
+    size_change: u64,
+    /// Track endings even in Keep/Keep mode (no pass-through)
+    audit: bool,
+    /// Last input ending seen: `Some((input_crlf, output_crlf))`
+    last_line_ending: Option<(bool, bool)>,
+    /// Set by `finish()`: final ending dropped (`Strip`) or added (`Ensure`),
+    /// `Some(crlf)`
+    dropped_final: Option<bool>,
+    added_final: Option<bool>,
+    /// Input endings seen, `\n` and `\r\n` (for `Warning::MixedLineEndings`)
+    lf_endings: u64,
+    crlf_endings: u64,
+    /// Pass-through only: last byte seen was `\r`
+    after_carriage_return: bool,
+}
+
+impl<'a, W: Write> EolWriter<'a, W> {
+    fn new(inner: &'a mut W, mode: EolMode, final_newline: FinalNewline) -> Self {
+        EolWriter {
+            inner,
+            mode,
+            final_newline,
+            pending_carriage_return: false,
+            held_ending: None,
+            last_ending_crlf: None,
+            wrote_any: false,
+            ends_with_ending: false,
+            size_change: 0,
+            audit: false,
+            last_line_ending: None,
+            dropped_final: None,
+            added_final: None,
+            lf_endings: 0,
+            crlf_endings: 0,
+            after_carriage_return: false,
+        }
+    }
+
+    /// `Warning::MixedLineEndings` if the input had both kinds of ending
+    fn mixed_endings(&self) -> Option<Warning> {
+        (self.lf_endings > 0 && self.crlf_endings > 0).then_some(Warning::MixedLineEndings {
+            lf: self.lf_endings,
+            crlf: self.crlf_endings,
+        })
+    }
+
+    /// Write one line ending in the given style
+    fn emit_ending(&mut self, crlf: bool) -> std::io::Result<()> {
+        self.wrote_any = true;
+        self.ends_with_ending = true;
+        self.last_ending_crlf = Some(crlf);
+        if crlf {
+            self.inner.write_all(b"\r\n")
+        } else {
+            self.inner.write_all(b"\n")
+        }
+    }
+
+    /// Write the held ending (if any), something else follows it
+    fn release_held(&mut self) -> std::io::Result<()> {
+        match self.held_ending.take() {
+            Some(crlf) => self.emit_ending(crlf),
+            None => Ok(()),
+        }
+    }
+
+    /// Write a byte that is not part of a line ending
+    fn emit_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.release_held()?;
+        self.wrote_any = true;
+        self.ends_with_ending = false;
+        self.inner.write_all(&[byte])
+    }
+
+    /// Write the configured line ending for one input ending
+    fn write_ending(&mut self, input_was_crlf: bool) -> std::io::Result<()> {
+        if input_was_crlf {
+            self.crlf_endings += 1;
+        } else {
+            self.lf_endings += 1;
+        }
+        let crlf = match self.mode {
+            EolMode::Keep => input_was_crlf,
+            EolMode::Lf => false,
+            EolMode::Crlf => true,
+        };
+        if crlf != input_was_crlf {
+            self.size_change += 1;
+        }
+        self.last_line_ending = Some((input_was_crlf, crlf));
+        if self.final_newline == FinalNewline::Strip {
+            self.release_held()?;
+            self.held_ending = Some(crlf);
+            return Ok(());
+        }
+        self.emit_ending(crlf)
+    }
+
+    /// End of input: write a held `\r`, then apply `final_newline`
+    fn finish(&mut self) -> std::io::Result<()> {
+        if self.pending_carriage_return {
+            self.pending_carriage_return = false;
+            self.emit_byte(b'\r')?;
+        }
+
+        match self.final_newline {
+            FinalNewline::Keep => {}
+            FinalNewline::Strip => {
+                // The held ending is the last one: drop it
+                if let Some(crlf) = self.held_ending.take() {
+                    self.size_change += if crlf { 2 } else { 1 };
+                    self.dropped_final = Some(crlf);
+                }
+            }
+            FinalNewline::Ensure => {
+                if self.wrote_any && !self.ends_with_ending {
+                    let crlf = match self.mode {
+                        EolMode::Keep => self.last_ending_crlf.unwrap_or(false),
+                        EolMode::Lf => false,
+                        EolMode::Crlf => true,
+                    };
+                    self.size_change += if crlf { 2 } else { 1 };
+                    self.added_final = Some(crlf);
+                    self.emit_ending(crlf)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EolWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.mode == EolMode::Keep && self.final_newline == FinalNewline::Keep && !self.audit {
+            // Only counted here (`\r\n` may straddle two calls)
+            for &byte in buf {
+                if byte == b'\n' {
+                    if self.after_carriage_return {
+                        self.crlf_endings += 1;
+                    } else {
+                        self.lf_endings += 1;
+                    }
+                }
+                self.after_carriage_return = byte == b'\r';
+            }
+            self.inner.write_all(buf)?;
+            return Ok(buf.len());
+        }
+
+        for &byte in buf {
+            if self.pending_carriage_return {
+                self.pending_carriage_return = false;
+                if byte == b'\n' {
+                    self.write_ending(true)?;
+                    continue;
+                }
+                // Lone '\r': not a line ending
+                self.emit_byte(b'\r')?;
+            }
+
+            match byte {
+                b'\r' => self.pending_carriage_return = true,
+                b'\n' => self.write_ending(false)?,
+                _ => self.emit_byte(byte)?,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writer adapter that drops trailing spaces/tabs of the line written through it
+///
+/// # Overview
+/// Wrapped around one edited line at a time (a toggled line, an unindented
+/// line), never the whole file. A run of spaces/tabs is held (up to
+/// MAX_TRIMMED_WHITESPACE_BYTES, beyond that the oldest bytes are written
+/// out) until something else follows: content releases it, a line ending
+/// (`\n` or `\r\n`) or `finish()` at end of input drops it. With
+/// `enabled = false` bytes are passed straight through.
+struct TrailingWhitespaceTrimmer<'a, W: Write> {
+    inner: &'a mut W,
+    enabled: bool,
+    held: [u8; MAX_TRIMMED_WHITESPACE_BYTES],
+    held_len: usize,
+    pending_carriage_return: bool,
+    /// Bytes dropped so far
+    trimmed: u64,
+}
+
+impl<'a, W: Write> TrailingWhitespaceTrimmer<'a, W> {
+    fn new(inner: &'a mut W, enabled: bool) -> Self {
+        TrailingWhitespaceTrimmer {
+            inner,
+            enabled,
+            held: [0u8; MAX_TRIMMED_WHITESPACE_BYTES],
+            held_len: 0,
+            pending_carriage_return: false,
+            trimmed: 0,
+        }
+    }
+
+    /// Content follows: the held run was not trailing after all
+    fn release_held(&mut self) -> std::io::Result<()> {
+        let held_len = self.held_len;
+        self.held_len = 0;
+        self.inner.write_all(&self.held[..held_len])
+    }
+
+    /// Line (or input) ended: drop the held run
+    fn drop_held(&mut self) {
+        self.trimmed += self.held_len as u64;
+        self.held_len = 0;
+    }
+
+    /// End of input: a held `\r` is content; a held run is trailing
+    fn finish(&mut self) -> std::io::Result<()> {
+        if self.pending_carriage_return {
+            self.pending_carriage_return = false;
+            self.release_held()?;
+            return self.inner.write_all(b"\r");
+        }
+        self.drop_held();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for TrailingWhitespaceTrimmer<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !self.enabled {
+            return self.inner.write(buf);
+        }
+
+        for &byte in buf {
+            if self.pending_carriage_return {
+                self.pending_carriage_return = false;
+                if byte == b'\n' {
+                    self.drop_held();
+                    self.inner.write_all(b"\r\n")?;
+                    continue;
+                }
+                // Lone '\r': content
+                self.release_held()?;
+                self.inner.write_all(b"\r")?;
+            }
+
+            match byte {
+                b' ' | b'\t' => {
+                    if self.held_len == MAX_TRIMMED_WHITESPACE_BYTES {
+                        self.inner.write_all(&self.held[..1])?;
+                        self.held.copy_within(1.., 0);
+                        self.held_len -= 1;
+                    }
+                    self.held[self.held_len] = byte;
+                    self.held_len += 1;
+                }
+                b'\r' => self.pending_carriage_return = true,
+                b'\n' => {
+                    self.drop_held();
+                    self.inner.write_all(b"\n")?;
+                }
+                _ => {
+                    self.release_held()?;
+                    self.inner.write_all(&[byte])?;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Copy reader to writer, toggling each line listed in `sorted_targets`
+///
+/// # Arguments
+/// * `reader` - Source content, positioned at line 0
+/// * `writer` - Destination
+/// * `sorted_targets` - Zero-indexed lines to toggle: ascending, no duplicates
+/// * `rule` - Flag(s) to add/remove and where (see `LineToggleRule`)
+///
+/// Line endings and the final newline are converted on the way out when
+/// `rule.eol` / `rule.final_newline` ask for it; with `rule.trim_trailing`
+/// each toggled line loses its trailing spaces/tabs. With `rule.eol_audit`
+/// each toggled line, and each line whose ending changed, gets one
+/// `write_eol_audit_line` record on stderr.
+///
+/// # Returns
+/// * `Ok(size_change)` - All target lines toggled, everything else copied
+///   unchanged; conversions and trimming changed the size by at most
+///   `size_change` bytes (0 when all three are off)
+/// * `Err(LineNotFound)` - Input ended before a target line (`file_lines`
+///   counts addressable lines, including the empty position after a final newline)
+/// * `Err(ProtectedRegion)` - A target line is in a `toggle_comment: off` region
+/// * `Err(ToggleCommentError)` - Read, write or flush failed
+fn write_toggled_lines_bytewise<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    sorted_targets: &[usize],
+    rule: LineToggleRule,
+) -> Result<u64, ToggleCommentError> {
+    write_toggled_lines_audited(reader, writer, sorted_targets, rule, &mut std::io::stderr())
+}
+
+/// `write_toggled_lines_bytewise` with audit records going to `audit`
+fn write_toggled_lines_audited<R: Read, W: Write, A: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    sorted_targets: &[usize],
+    rule: LineToggleRule,
+    audit: &mut A,
+) -> Result<u64, ToggleCommentError> {
+    write_toggled_targets(reader, writer, sorted_targets.iter().copied(), rule, audit)
+}
+
+/// `write_toggled_lines_audited` over any ascending, duplicate-free sequence
+/// of target lines (a slice, or the lines of sorted ranges)
+fn write_toggled_targets<R: Read, W: Write, A: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    targets: impl Iterator<Item = usize>,
+    rule: LineToggleRule,
+    audit: &mut A,
+) -> Result<u64, ToggleCommentError> {
+    let mut current_line: usize = 0;
+    let mut targets = targets.peekable();
+    let mut writer = EolWriter::new(writer, rule.eol, rule.final_newline);
+    writer.audit = rule.eol_audit != TraceFormat::Off;
+    let mut trimmed: u64 = 0;
+    // Last line's record is held back: `finish()` may still change its ending
+    let mut audit_record: Option<EolAuditRecord> = None;
+
+    // Sentinel comments are tracked as the lines stream past
+    let mut reader = ProtectedRegionReader {
+        inner: reader,
+        tracker: ProtectedRegionTracker::new().with_heredocs(rule.heredocs),
+    };
+
+    loop {
+        if current_line as u64 >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        let is_target = targets.peek() == Some(&current_line);
+
+        let line_continues = if is_target {
+            targets.next();
+            let mut line_writer = TrailingWhitespaceTrimmer::new(&mut writer, rule.trim_trailing);
+            let line_continues = toggle_line(&mut reader, &mut line_writer, rule)?;
+            if line_writer.finish().is_err() {
+                return Err(ToggleCommentError::IoError(IoOperation::Write));
+            }
+            trimmed += line_writer.trimmed;
+            // Known once the line is read (an `off` sentinel is protected too)
+            if !line_continues {
+                reader.tracker.finish();
+            }
+            if reader.tracker.line_protected() {
+                return Err(ToggleCommentError::ProtectedRegion { line: current_line });
+            }
+            line_continues
+        } else {
+            copy_rest_of_line(&mut reader, &mut writer)?
+        };
+
+        if writer.audit {
+            let ending = writer.last_line_ending.take();
+            let changed = matches!(ending, Some((before, after)) if before != after);
+            if is_target || changed {
+                write_eol_audit_record(audit, rule.eol_audit, audit_record.take());
+                audit_record = Some(EolAuditRecord {
+                    line: current_line,
+                    before: ending.map(|(before, _)| before),
+                    after: ending.map(|(_, after)| after),
+                });
+            }
+        }
+
+        if !line_continues {
+            break;
+        }
+
+        current_line += 1;
+    }
+
+    if let Some(requested) = targets.next() {
+        return Err(ToggleCommentError::LineNotFound {
+            requested,
+            file_lines: current_line + 1,
+        });
+    }
+
+    if writer.finish().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Write));
+    }
+    if writer.flush().is_err() {
+        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+    }
+
+    if writer.audit {
+        // Dropped: ending of the line before the (empty) last one.
+        // Added: ending of the last line
+        let final_change = match (writer.dropped_final, writer.added_final) {
+            (Some(crlf), _) => Some((current_line.saturating_sub(1), Some(crlf), None)),
+            (None, Some(crlf)) => Some((current_line, None, Some(crlf))),
+            (None, None) => None,
+        };
+        if let Some((line, before, after)) = final_change {
+            match audit_record.as_mut() {
+                Some(record) if record.line == line => record.after = after,
+                _ => {
+                    write_eol_audit_record(audit, rule.eol_audit, audit_record.take());
+                    audit_record = Some(EolAuditRecord {
+                        line,
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+        write_eol_audit_record(audit, rule.eol_audit, audit_record);
+    }
+
+    // Converted endings are not mixed any more
+    if rule.eol == EolMode::Keep
+        && let Some(warning) = writer.mixed_endings()
+    {
+        record_warning(warning);
+    }
+
+    Ok(writer.size_change + trimmed)
+}
+
+/// Ending of one line before and after the edit (`Some(crlf)`, `None`: no ending)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EolAuditRecord {
+    line: usize,
+    before: Option<bool>,
+    after: Option<bool>,
+}
+
+/// Audit name of a line ending
+fn ending_name(ending: Option<bool>) -> &'static str {
+    match ending {
+        Some(true) => "crlf",
+        Some(false) => "lf",
+        None => "none",
+    }
+}
+
+/// Write one audit record (nothing for `None`); failures are ignored like
+/// trace lines
+fn write_eol_audit_record<A: Write>(
+    audit: &mut A,
+    format: TraceFormat,
+    record: Option<EolAuditRecord>,
+) {
+    if let Some(record) = record {
+        let _ = write_eol_audit_line(audit, format, record);
+    }
+}
+
+/// Format one audit record (zero-indexed line)
+fn write_eol_audit_line<W: Write>(
+    writer: &mut W,
+    format: TraceFormat,
+    record: EolAuditRecord,
+) -> std::io::Result<()> {
+    match format {
+        TraceFormat::Off => Ok(()),
+        TraceFormat::Text => writeln!(
+            writer,
+            "toggle_comment audit: eol line={} before={} after={}",
+            record.line,
+            ending_name(record.before),
+            ending_name(record.after)
+        ),
+        TraceFormat::Json => writeln!(
+            writer,
+            "{{\"audit\":\"eol\",\"line\":{},\"before\":\"{}\",\"after\":\"{}\"}}",
+            record.line,
+            ending_name(record.before),
+            ending_name(record.after)
+        ),
+    }
+}
+
+/// Run one batch pass from `source_path` into `dest_path`
+///
+/// # Returns
+/// * `Ok(size_change)` - Pass written and synced; bytes changed by conversions
+/// * `Err(ToggleCommentError)` - Open, write or sync failed
+#[cfg(feature = "fs")]
+fn write_batch_pass_bytewise(
+    source_path: &Path,
+    dest_path: &Path,
+    targets: impl Iterator<Item = usize>,
+    rule: LineToggleRule,
+) -> Result<u64, ToggleCommentError> {
+    let source_file = match File::open(source_path) {
+        Ok(f) => TimedIo(f),
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    let dest_file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest_path)
+    {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+    };
+
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, TimedIo(dest_file));
+
+    let size_change = write_toggled_targets(
+        &mut reader,
+        &mut writer,
+        targets,
+        rule,
+        &mut std::io::stderr(),
+    )?;
+
+    Ok(size_change)
+}
+
+/// Sort and de-duplicate line numbers into a fixed stack array
+///
+/// # Returns
+/// * `Ok(count)` - `sorted_buffer[..count]` holds ascending unique line numbers
+/// * `Err(TooManyLines)` - More than MAX_BATCH_LINES supplied
+fn sort_and_dedup_line_numbers(
+    line_numbers: &[usize],
+    sorted_buffer: &mut [usize; MAX_BATCH_LINES],
+) -> Result<usize, ToggleCommentError> {
+    if line_numbers.len() > MAX_BATCH_LINES {
+        return Err(ToggleCommentError::TooManyLines {
+            max: MAX_BATCH_LINES,
+        });
+    }
+
+    let count = line_numbers.len();
+    sorted_buffer[..count].copy_from_slice(line_numbers);
+    sorted_buffer[..count].sort_unstable();
+
+    // In-place de-duplication of the sorted prefix
+    let mut unique_count: usize = 0;
+    for read_index in 0..count {
+        if unique_count == 0 || sorted_buffer[read_index] != sorted_buffer[unique_count - 1] {
+            sorted_buffer[unique_count] = sorted_buffer[read_index];
+            unique_count += 1;
+        }
+    }
+
+    Ok(unique_count)
+}
+
+/// Resolve how a batch operation toggles each line
+///
+/// # Returns
+/// * `Ok(Some(rule))` - Flag to toggle, the optional removal-only flag for the
+///   extension (never used with an override), and whether indent is preserved
+/// * `Ok(None)` - Extension not supported (caller treats as no-op, like single-line)
+/// * `Err(NoExtension)` - No extension and no override given
+/// * `Err(JsonNoComments)` - Plain `.json` and no override given
+/// * `Err(NotRustSource)` - `TripleSlash` override on a file that is not `.rs`
+#[cfg(feature = "fs")]
+fn resolve_batch_toggle_rule(
+    absolute_path: &Path,
+    flag_override: Option<CommentFlag>,
+    options: &ToggleOptions,
+) -> Result<Option<LineToggleRule>, ToggleCommentError> {
+    if let Some(flag) = flag_override {
+        if flag == CommentFlag::TripleSlash {
+            check_docstring_target(absolute_path, options)?;
+        }
+        return Ok(Some(LineToggleRule {
+            comment_flag: flag,
+            removal_only_flag: None,
+            preserve_indent: options.preserve_indent,
+            direction: options.direction,
+            removal: options.removal,
+            eol: options.eol,
+            final_newline: options.final_newline,
+            trim_trailing: options.trim_trailing,
+            skip_blank: options.skip_blank,
+            eol_audit: eol_audit_format(options),
+            heredocs: target_extension(absolute_path, options)
+                .is_some_and(|extension| guards_heredocs(&extension, options)),
+            indent_tabs: options.indent_tab,
+        }));
+    }
+
+    let extension = match target_extension(absolute_path, options) {
+        Some(ext) => ext,
+        None => return Err(ToggleCommentError::NoExtension),
+    };
+
+    resolve_extension_toggle_rule(&extension, options)
+}
+
+/// Refuse a Rust doc comment or attribute target that is not `.rs` (unless
+/// `options.allow_any_extension`)
+#[cfg(feature = "fs")]
+fn check_docstring_target(
+    absolute_path: &Path,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    let is_rust = absolute_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("rs"));
+    if is_rust || options.allow_any_extension {
+        Ok(())
+    } else {
+        Err(ToggleCommentError::NotRustSource)
+    }
+}
+
+/// Resolve the batch toggle rule for an extension (no leading dot)
+///
+/// Shared by the file path (`resolve_batch_toggle_rule`) and the in-memory
+/// buffer API, which has an extension but no path.
+fn resolve_extension_toggle_rule(
+    extension: &str,
+    options: &ToggleOptions,
+) -> Result<Option<LineToggleRule>, ToggleCommentError> {
+    if is_json_without_comments(extension) {
+        return Err(ToggleCommentError::JsonNoComments);
+    }
+
+    Ok(
+        determine_line_flag(extension, options).map(|flag| LineToggleRule {
+            comment_flag: flag,
+            removal_only_flag: determine_removal_only_flag(extension),
+            preserve_indent: options.preserve_indent || preserves_indent_by_default(extension),
+            direction: options.direction,
+            removal: options.removal,
+            eol: options.eol,
+            final_newline: options.final_newline,
+            trim_trailing: options.trim_trailing,
+            skip_blank: options.skip_blank,
+            eol_audit: eol_audit_format(options),
+            heredocs: guards_heredocs(extension, options),
+            indent_tabs: options.indent_tab,
+        }),
+    )
+}
+
+/// Whether comment toggles of a file with this extension refuse here-doc
+/// bodies: shell scripts, unless `options.allow_heredoc`
+fn guards_heredocs(extension: &str, options: &ToggleOptions) -> bool {
+    !options.allow_heredoc && matches!(extension.to_lowercase().as_str(), "sh" | "bash")
+}
+
+/// Audit record format for `options.eol_audit`: the trace format, text when
+/// tracing is off
+fn eol_audit_format(options: &ToggleOptions) -> TraceFormat {
+    match (options.eol_audit, options.trace) {
+        (false, _) => TraceFormat::Off,
+        (true, TraceFormat::Off) => TraceFormat::Text,
+        (true, format) => format,
+    }
+}
+
+/// Select the next pass of line numbers from an unsorted slice
+///
+/// # Overview
+/// Collects the (up to) MAX_BATCH_LINES smallest distinct values in
+/// `line_numbers` that are strictly greater than `after` into `selected`,
+/// ascending. Calling repeatedly with `after` = last value selected walks the
+/// whole selection in sorted order without copying or sorting the slice.
+///
+/// # Arguments
+/// * `line_numbers` - Caller's line numbers (any order, duplicates allowed)
+/// * `after` - Exclusive lower bound (`None` for the first pass)
+/// * `selected` - Output buffer
+///
+/// # Returns
+/// * Number of values written to `selected` (0 when the selection is exhausted)
+///
+/// # Cost
+/// O(n × MAX_BATCH_LINES) per pass, no allocation
+fn select_next_batch_after(
+    line_numbers: &[usize],
+    after: Option<usize>,
+    selected: &mut [usize; MAX_BATCH_LINES],
+) -> usize {
+    let mut count: usize = 0;
+
+    for &candidate in line_numbers.iter() {
+        if let Some(bound) = after
+            && candidate <= bound
+        {
+            continue;
+        }
+
+        // Full and candidate is not smaller than the current largest: skip
+        if count == MAX_BATCH_LINES && candidate >= selected[count - 1] {
+            continue;
+        }
+
+        // Find sorted insertion point
+        let mut insert_at: usize = count;
+        while insert_at > 0 && selected[insert_at - 1] > candidate {
+            insert_at -= 1;
+        }
+
+        // Duplicate of an already selected value
+        if insert_at > 0 && selected[insert_at - 1] == candidate {
+            continue;
+        }
+
+        // Shift larger values up (dropping the largest when full)
+        let mut shift_from: usize = if count == MAX_BATCH_LINES {
+            count - 1
+        } else {
+            count
+        };
+        while shift_from > insert_at {
+            selected[shift_from] = selected[shift_from - 1];
+            shift_from -= 1;
+        }
+        selected[insert_at] = candidate;
+
+        if count < MAX_BATCH_LINES {
+            count += 1;
+        }
+    }
+
+    count
+}
+
+/// Chain of batch passes through two alternating temp files
+///
+/// # Overview
+/// The first pass reads the original file; each later pass reads the previous
+/// pass's output. `finish()` copies the latest output over the original, so
+/// the original is only ever replaced once, after every pass succeeded.
+///
+/// The temp files are removed when the chain is dropped, on success or on
+/// any error (`abandon()` removes them right away).
+#[cfg(feature = "fs")]
+struct BatchPassChain {
+    absolute_path: PathBuf,
+    temp_a_path: PathBuf,
+    temp_b_path: PathBuf,
+    passes_done: usize,
+    latest_is_a: bool,
+    /// Largest size change the passes so far can cause (per-line bound x lines,
+    /// plus line ending conversions)
+    max_change: u64,
+
+    /// Stage reporting for the passes and the final replace
+    trace: StageTrace,
+
+    /// `ToggleOptions::temp_dir` (the final replace renames when set)
+    temp_dir: Option<PathBuf>,
+
+    /// `ToggleOptions::durability` of the final replace
+    durability: Durability,
+
+    /// Snapshot of the original from the backup (checked by the final replace)
+    original: Option<FileSnapshot>,
+}
+
+#[cfg(feature = "fs")]
+impl BatchPassChain {
+    /// Set up temp file names in `temp_dir` or CWD (nothing is created yet)
+    ///
+    /// `original` is the backup's snapshot; the stream toggle, which backs up
+    /// only once a first chunk arrived, sets it later.
+    fn new(
+        absolute_path: &Path,
+        filename: &str,
+        options: &ToggleOptions,
+        trace: StageTrace,
+        original: Option<FileSnapshot>,
+    ) -> Self {
+        let temp_dir = options.temp_dir.as_deref();
+        BatchPassChain {
+            absolute_path: absolute_path.to_path_buf(),
+            temp_a_path: temp_file_path(
+                temp_dir,
+                &format!("temp_toggle_batch_a_{}_{}", std::process::id(), filename),
+            ),
+            temp_b_path: temp_file_path(
+                temp_dir,
+                &format!("temp_toggle_batch_b_{}_{}", std::process::id(), filename),
+            ),
+            temp_dir: temp_dir.map(Path::to_path_buf),
+            durability: options.durability,
+            passes_done: 0,
+            latest_is_a: false,
+            max_change: 0,
+            trace,
+            original,
+        }
+    }
+
+    /// Run one pass toggling `sorted_targets` (ascending, no duplicates)
+    fn run_pass(
+        &mut self,
+        sorted_targets: &[usize],
+        rule: LineToggleRule,
+    ) -> Result<(), ToggleCommentError> {
+        self.run_pass_over(sorted_targets.iter().copied(), sorted_targets.len(), rule)
+    }
+
+    /// Run one pass toggling every line of `sorted_ranges` (inclusive,
+    /// ascending, disjoint; `line_count` lines in all)
+    fn run_range_pass(
+        &mut self,
+        sorted_ranges: &[(usize, usize)],
+        line_count: usize,
+        rule: LineToggleRule,
+    ) -> Result<(), ToggleCommentError> {
+        let targets = sorted_ranges.iter().flat_map(|&(start, end)| start..=end);
+        self.run_pass_over(targets, line_count, rule)
+    }
+
+    /// Run one pass toggling `targets` (ascending, no duplicates, `target_count` of them)
+    fn run_pass_over(
+        &mut self,
+        targets: impl Iterator<Item = usize>,
+        target_count: usize,
+        rule: LineToggleRule,
+    ) -> Result<(), ToggleCommentError> {
+        let (source_path, dest_path, dest_is_a) = if self.passes_done == 0 {
+            (&self.absolute_path, &self.temp_a_path, true)
+        } else if self.latest_is_a {
+            (&self.temp_a_path, &self.temp_b_path, false)
+        } else {
+            (&self.temp_b_path, &self.temp_a_path, true)
+        };
+
+        // The final newline is fixed once, on the first pass (stripping again
+        // on a later pass would remove a second line ending)
+        let mut rule = rule;
+        if self.passes_done > 0 {
+            rule.final_newline = FinalNewline::Keep;
+        }
+
+        let size_change = self.trace.run_streaming(source_path, dest_path, || {
+            write_batch_pass_bytewise(source_path, dest_path, targets, rule)
+        })?;
+
+        self.latest_is_a = dest_is_a;
+        self.passes_done += 1;
+        self.max_change = self
+            .max_change
+            .saturating_add((target_count as u64).saturating_mul(rule.max_edit_bytes()))
+            .saturating_add(size_change);
+        Ok(())
+    }
+
+    /// Size-check, then replace the original with the latest pass output and clean up
+    /// No-op when no pass ran
+    fn finish(self) -> Result<(), ToggleCommentError> {
+        if self.passes_done == 0 {
+            return Ok(());
+        }
+
+        let latest_path = if self.latest_is_a {
+            &self.temp_a_path
+        } else {
+            &self.temp_b_path
+        };
+
+        let max_change = self.max_change;
+        if !self.trace.check(TraceStage::Verify, || {
+            replacement_size_is_plausible(&self.absolute_path, latest_path, max_change, max_change)
+        }) {
+            self.abandon();
+            return Err(ToggleCommentError::IncompleteWrite);
+        }
+
+        // Passes only run after the backup took its snapshot
+        let Some(original) = self.original else {
+            self.abandon();
+            return Err(ToggleCommentError::IoError(IoOperation::Backup));
+        };
+        if let Err(e) = self.trace.run(TraceStage::Replace, || {
+            replace_original(
+                latest_path,
+                &self.absolute_path,
+                self.temp_dir.as_deref(),
+                self.durability,
+                &original,
+            )
+        }) {
+            self.abandon();
+            return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
+        }
+
+        // Clean up temp files (temp_b does not exist after a single pass)
+        if remove_temp_file(&self.temp_a_path).is_err() {
+            #[cfg(debug_assertions)]
+            eprintln!("Warning: Failed to clean up temp file");
+        }
+        let _ = std::fs::remove_file(&self.temp_b_path);
+
+        Ok(())
+    }
+
+    /// Remove any temp files; original is left untouched
+    fn abandon(&self) {
+        let _ = std::fs::remove_file(&self.temp_a_path);
+        let _ = std::fs::remove_file(&self.temp_b_path);
+    }
+}
+
+#[cfg(feature = "fs")]
+impl Drop for BatchPassChain {
+    fn drop(&mut self) {
+        self.abandon();
+    }
+}
+
+/// Shared implementation for the slice-based batch toggles
+#[cfg(feature = "fs")]
+fn toggle_multiple_lines_bytewise(
+    file_path: &str,
+    line_numbers: &[usize],
+    flag_override: Option<CommentFlag>,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    if line_numbers.len() > MAX_TOTAL_BATCH_LINES {
+        return Err(ToggleCommentError::TooManyLines {
+            max: MAX_TOTAL_BATCH_LINES,
+        });
+    }
+
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    let rule = match resolve_batch_toggle_rule(&absolute_path, flag_override, options)? {
+        Some(rule) => rule,
+        None => return Ok(()), // Unsupported extension - no-op
+    };
+
+    if line_numbers.is_empty() {
+        return Ok(()); // Nothing requested
+    }
+
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+
+    // Create backup path in CWD
+    let backup_path = backup_path_for(&filename, options);
+
+    let trace = StageTrace::new(options, "batch");
+
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    let mut chain = BatchPassChain::new(&absolute_path, &filename, options, trace, Some(snapshot));
+    let mut selected: [usize; MAX_BATCH_LINES] = [0; MAX_BATCH_LINES];
+    let mut last_selected: Option<usize> = None;
+
+    // Each pass consumes at least one distinct value: bounded by slice length
+    for _ in 0..line_numbers.len() {
+        let count = select_next_batch_after(line_numbers, last_selected, &mut selected);
+        if count == 0 {
+            break; // Selection exhausted
+        }
+
+        if let Err(e) = chain.run_pass(&selected[..count], rule) {
+            chain.abandon();
+            return Err(e);
+        }
+
+        last_selected = Some(selected[count - 1]);
+    }
+
+    chain.finish()
+}
+
+/// Toggle basic comments (`//` or `#`) on a list of lines in one operation
+///
+/// # Overview
+/// Comment flag is auto-detected from the file extension, as in
+/// `toggle_basic_singleline_comment_bytewise()`. Each listed line is toggled
+/// independently based on its own current state.
+///
+/// One backup, one replace. Up to MAX_BATCH_LINES lines are handled per pass
+/// through the file; larger lists simply take more passes.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `line_numbers` - Zero-indexed lines to toggle (any order, duplicates ignored)
+///
+/// # Returns
+/// * `Ok(())` - All lines toggled (or unsupported extension: no-op)
+/// * `Err(TooManyLines)` - More than MAX_TOTAL_BATCH_LINES line numbers
+/// * `Err(LineNotFound)` - A line number is beyond the end of file (file untouched)
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::toggle_multiple_basic_comments;
+///
+/// // Toggle lines 5, 10, 15, 20 in one pass
+/// let lines = [5, 10, 15, 20];
+/// toggle_multiple_basic_comments("./script.py", &lines)?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_multiple_basic_comments(
+    file_path: &str,
+    line_numbers: &[usize],
+) -> Result<(), ToggleCommentError> {
+    toggle_multiple_basic_comments_with_options(file_path, line_numbers, &ToggleOptions::default())
+}
+
+/// Same as `toggle_multiple_basic_comments()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_multiple_basic_comments_with_options(
+    file_path: &str,
+    line_numbers: &[usize],
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_multiple_lines_bytewise(file_path, line_numbers, None, options)
+}
+
+/// Toggle Rust docstrings (`///`) on a list of lines in one operation
+///
+/// # Overview
+/// Same as `toggle_multiple_basic_comments()` but always uses `///`.
+/// No file extension validation (same as the single-line docstring toggle).
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `line_numbers` - Zero-indexed lines to toggle (any order, duplicates ignored)
+///
+/// # Returns
+/// * `Ok(())` - All lines toggled
+/// * `Err(ToggleCommentError)` - Specific error code
+#[cfg(feature = "fs")]
+pub fn toggle_multiple_singleline_docstrings(
+    file_path: &str,
+    line_numbers: &[usize],
+) -> Result<(), ToggleCommentError> {
+    toggle_multiple_singleline_docstrings_with_options(
+        file_path,
+        line_numbers,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_multiple_singleline_docstrings()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_multiple_singleline_docstrings_with_options(
+    file_path: &str,
+    line_numbers: &[usize],
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_multiple_lines_bytewise(
+        file_path,
+        line_numbers,
+        Some(CommentFlag::TripleSlash),
+        options,
+    )
+}
+
+/// Toggle basic comments on several inclusive line ranges in one operation
+///
+/// # Overview
+/// Each line of each range is toggled as by `toggle_range_basic_comments_bytewise()`,
+/// but all ranges are done in one pass through the file: one backup, one
+/// replace. Each range may be given in either order; ranges may be listed in
+/// any order, but must not share a line.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `ranges` - Zero-indexed inclusive `(start, end)` ranges
+///
+/// # Returns
+/// * `Ok(())` - All lines toggled (or unsupported extension: no-op)
+/// * `Err(OverlappingRanges)` - Two ranges share a line (file untouched)
+/// * `Err(TooManyLines)` - More than MAX_RANGES ranges, or more than
+///   MAX_TOTAL_BATCH_LINES lines in all (file untouched)
+/// * `Err(LineNotFound)` - A range reaches beyond the end of file (file untouched);
+///   `requested` is that range's end line
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::toggle_ranges_basic_comments;
+///
+/// // Lines 3-9, 14-20 and 40-41, one backup and one rewrite
+/// toggle_ranges_basic_comments("./src/main.rs", &[(3, 9), (14, 20), (40, 41)])?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_ranges_basic_comments(
+    file_path: &str,
+    ranges: &[(usize, usize)],
+) -> Result<(), ToggleCommentError> {
+    toggle_ranges_basic_comments_with_options(file_path, ranges, &ToggleOptions::default())
+}
+
+/// Same as `toggle_ranges_basic_comments()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_ranges_basic_comments_with_options(
+    file_path: &str,
+    ranges: &[(usize, usize)],
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    let mut sorted: [(usize, usize); MAX_RANGES] = [(0, 0); MAX_RANGES];
+    let (count, line_count) = sort_disjoint_ranges(ranges, &mut sorted)?;
+
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    let rule = match resolve_batch_toggle_rule(&absolute_path, None, options)? {
+        Some(rule) => rule,
+        None => return Ok(()), // Unsupported extension - no-op
+    };
+
+    if count == 0 {
+        return Ok(()); // Nothing requested
+    }
+
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+    let backup_path = backup_path_for(&filename, options);
+
+    let trace = StageTrace::new(options, "ranges");
+
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    let mut chain = BatchPassChain::new(&absolute_path, &filename, options, trace, Some(snapshot));
+    if let Err(e) = chain.run_range_pass(&sorted[..count], line_count, rule) {
+        chain.abandon();
+        // Name the end of the range that runs past the file and the lines the
+        // file has (as `--block` / `--isolate` do), not the first line missing
+        if let ToggleCommentError::LineNotFound { requested, .. } = e {
+            let end = sorted[..count]
+                .iter()
+                .find(|&&(_, end)| end >= requested)
+                .map_or(requested, |&(_, end)| end);
+            let file_lines = last_line_index(file_path).map_or(requested, |last| last + 1);
+            return Err(ToggleCommentError::LineNotFound {
+                requested: end,
+                file_lines,
+            });
+        }
+        return Err(e);
+    }
+    chain.finish()
+}
+
+/// Sort inclusive ranges by start into a fixed stack array
+///
+/// # Returns
+/// * `Ok((count, lines))` - `sorted[..count]` holds the ranges (start <= end),
+///   ascending and disjoint; `lines` is the number of lines they cover
+/// * `Err(TooManyLines)` - More than MAX_RANGES ranges or MAX_TOTAL_BATCH_LINES lines
+/// * `Err(OverlappingRanges)` - Two ranges share a line
+fn sort_disjoint_ranges(
+    ranges: &[(usize, usize)],
+    sorted: &mut [(usize, usize); MAX_RANGES],
+) -> Result<(usize, usize), ToggleCommentError> {
+    if ranges.len() > MAX_RANGES {
+        return Err(ToggleCommentError::TooManyLines { max: MAX_RANGES });
+    }
+
+    let count = ranges.len();
+    for (slot, &(start, end)) in sorted.iter_mut().zip(ranges) {
+        *slot = sort_range(start, end);
+    }
+    sorted[..count].sort_unstable();
+
+    let mut lines: usize = 0;
+    for index in 0..count {
+        let (start, end) = sorted[index];
+        if index > 0 && start <= sorted[index - 1].1 {
+            return Err(ToggleCommentError::OverlappingRanges { line: start });
+        }
+        lines = lines.saturating_add(end - start + 1);
+    }
+    if lines > MAX_TOTAL_BATCH_LINES {
+        return Err(ToggleCommentError::TooManyLines {
+            max: MAX_TOTAL_BATCH_LINES,
+        });
+    }
+
+    Ok((count, lines))
+}
+
+/// Parse the next line-number entry from a stream
+///
+/// # Format
+/// One zero-indexed line number per line. Leading/trailing spaces, tabs and
+/// `\r` are allowed; blank lines are skipped. Anything else is an error.
+///
+/// # Arguments
+/// * `reader` - Stream of newline-separated line numbers
+/// * `input_line` - Running 1-indexed input line counter (updated)
+///
+/// # Returns
+/// * `Ok(Some(n))` - Next line number
+/// * `Ok(None)` - End of stream
+/// * `Err(InvalidLineNumber)` - Entry is not a valid integer (or too long)
+fn read_next_line_number<R: Read>(
+    reader: &mut R,
+    input_line: &mut usize,
+) -> Result<Option<usize>, ToggleCommentError> {
+    let mut skipped_bytes: u64 = 0;
+
+    loop {
+        if skipped_bytes >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        *input_line += 1;
+
+        let mut value: usize = 0;
+        let mut digit_count: usize = 0;
+        let mut entry_bytes: usize = 0;
+        let mut trailing_whitespace = false;
+        let mut stream_ended = false;
+
+        loop {
+            let byte = match read_single_byte(reader)? {
+                Some(b) => b,
+                None => {
+                    stream_ended = true;
+                    break;
+                }
+            };
+
+            if byte == b'\n' {
+                break;
+            }
+
+            entry_bytes += 1;
+            skipped_bytes += 1;
+            if entry_bytes > MAX_LINE_NUMBER_ENTRY_BYTES {
+                return Err(ToggleCommentError::InvalidLineNumber {
+                    input_line: *input_line,
+                });
+            }
+
+            match byte {
+                b'0'..=b'9' => {
+                    if trailing_whitespace {
+                        // e.g. "12 34"
+                        return Err(ToggleCommentError::InvalidLineNumber {
+                            input_line: *input_line,
+                        });
+                    }
+                    value = match value
+                        .checked_mul(10)
+                        .and_then(|v| v.checked_add((byte - b'0') as usize))
+                    {
+                        Some(v) => v,
+                        None => {
+                            return Err(ToggleCommentError::InvalidLineNumber {
+                                input_line: *input_line,
+                            });
+                        }
+                    };
+                    digit_count += 1;
+                }
+                b' ' | b'\t' | b'\r' => {
+                    if digit_count > 0 {
+                        trailing_whitespace = true;
+                    }
+                }
+                _ => {
+                    return Err(ToggleCommentError::InvalidLineNumber {
+                        input_line: *input_line,
+                    });
+                }
+            }
+        }
+
+        if digit_count > 0 {
+            return Ok(Some(value));
+        }
+
+        if stream_ended {
+            return Ok(None);
+        }
+
+        // Blank line - skip
+    }
+}
+
+/// Shared implementation for the stream-based batch toggles
+#[cfg(feature = "fs")]
+fn toggle_lines_from_reader_bytewise<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+    flag_override: Option<CommentFlag>,
+    options: &ToggleOptions,
+) -> Result<usize, ToggleCommentError> {
+    if max_lines > MAX_TOTAL_BATCH_LINES {
+        return Err(ToggleCommentError::TooManyLines {
+            max: MAX_TOTAL_BATCH_LINES,
+        });
+    }
+
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    let rule = match resolve_batch_toggle_rule(&absolute_path, flag_override, options)? {
+        Some(rule) => rule,
+        None => return Ok(0), // Unsupported extension - no-op
+    };
+
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+
+    let backup_path = backup_path_for(&filename, options);
+
+    let trace = StageTrace::new(options, "batch_stream");
+    let mut chain = BatchPassChain::new(&absolute_path, &filename, options, trace, None);
+    let mut chunk: [usize; MAX_BATCH_LINES] = [0; MAX_BATCH_LINES];
+    let mut sorted_chunk: [usize; MAX_BATCH_LINES] = [0; MAX_BATCH_LINES];
+    let mut input_line: usize = 0;
+    let mut total_lines: usize = 0;
+    let mut toggled_lines: usize = 0;
+
+    // Upper bound: one chunk per MAX_BATCH_LINES entries, plus the final empty read
+    let max_chunks = MAX_TOTAL_BATCH_LINES / MAX_BATCH_LINES + 2;
+
+    for _ in 0..max_chunks {
+        // Fill next chunk
+        let mut chunk_len: usize = 0;
+        while chunk_len < MAX_BATCH_LINES {
+            let next = match read_next_line_number(reader, &mut input_line) {
+                Ok(n) => n,
+                Err(e) => {
+                    chain.abandon();
+                    return Err(e);
+                }
+            };
+            match next {
+                Some(line_number) => {
+                    chunk[chunk_len] = line_number;
+                    chunk_len += 1;
+                }
+                None => break,
+            }
+        }
+
+        if chunk_len == 0 {
+            break; // Stream exhausted
+        }
+
+        total_lines += chunk_len;
+        if total_lines > max_lines {
+            chain.abandon();
+            return Err(ToggleCommentError::TooManyLines { max: max_lines });
+        }
+
+        let target_count = sort_and_dedup_line_numbers(&chunk[..chunk_len], &mut sorted_chunk)?;
+        toggled_lines += target_count;
+
+        // Backup once, before the first pass
+        if total_lines == chunk_len {
+            match trace.backup(&absolute_path, &backup_path) {
+                Ok(snapshot) => chain.original = Some(snapshot),
+                Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+            }
+        }
+
+        if let Err(e) = chain.run_pass(&sorted_chunk[..target_count], rule) {
+            chain.abandon();
+            return Err(e);
+        }
+    }
+
+    chain.finish()?;
+
+    Ok(toggled_lines)
+}
+
+/// Toggle basic comments on line numbers read from a stream (e.g. stdin)
+///
+/// # Overview
+/// Reads newline-separated zero-indexed line numbers (see
+/// `read_next_line_number()` for the format) and applies them in chunks of
+/// MAX_BATCH_LINES, one file pass per chunk. One backup of the original is
+/// made; the original is replaced only after every chunk succeeded.
+///
+/// # Arguments
+/// * `file_path` - Path to the source file
+/// * `reader` - Stream of line numbers
+/// * `max_lines` - Upper bound on entries accepted (≤ MAX_TOTAL_BATCH_LINES)
+///
+/// # Returns
+/// * `Ok(count)` - Number of lines toggled (an entry repeated within a chunk
+///   counts once)
+/// * `Err(TooManyLines)` - Stream held more than `max_lines` entries (file untouched)
+/// * `Err(InvalidLineNumber)` - Malformed entry (file untouched)
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// use toggle_comment_indent_module::toggle_multiple_basic_comments_from_reader;
+///
+/// let stdin = std::io::stdin();
+/// let mut input = stdin.lock();
+/// toggle_multiple_basic_comments_from_reader("./script.py", &mut input, 4096)?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_multiple_basic_comments_from_reader<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+) -> Result<usize, ToggleCommentError> {
+    toggle_multiple_basic_comments_from_reader_with_options(
+        file_path,
+        reader,
+        max_lines,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_multiple_basic_comments_from_reader()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_multiple_basic_comments_from_reader_with_options<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+    options: &ToggleOptions,
+) -> Result<usize, ToggleCommentError> {
+    toggle_lines_from_reader_bytewise(file_path, reader, max_lines, None, options)
+}
+
+/// Toggle Rust docstrings (`///`) on line numbers read from a stream
+///
+/// Same as `toggle_multiple_basic_comments_from_reader()` but always uses `///`.
+#[cfg(feature = "fs")]
+pub fn toggle_multiple_singleline_docstrings_from_reader<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+) -> Result<usize, ToggleCommentError> {
+    toggle_multiple_singleline_docstrings_from_reader_with_options(
+        file_path,
+        reader,
+        max_lines,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_multiple_singleline_docstrings_from_reader()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_multiple_singleline_docstrings_from_reader_with_options<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+    options: &ToggleOptions,
+) -> Result<usize, ToggleCommentError> {
+    toggle_lines_from_reader_bytewise(
+        file_path,
+        reader,
+        max_lines,
+        Some(CommentFlag::TripleSlash),
+        options,
+    )
+}
+
+// ============================================================================
+// CONSISTENT NAMES
+// ============================================================================
+//
+// One scheme for every line-flag family, `toggle_<family>_<target>`:
+//
+//   family      line                    lines / lines_from_reader   range
+//   comment     toggle_comment_line     toggle_comment_lines        toggle_comment_range
+//   docstring   toggle_docstring_line   toggle_docstring_lines      toggle_docstring_range
+//
+// each with a `_with_options` form. They are the same operations as the
+// older descriptive names (which stay): a line toggles one line, lines is
+// the batch (one backup, one replace), range toggles `start..=end` in either
+// order.
+// ============================================================================
+
+/// Toggle the extension's line comment on one line (`toggle_basic_singleline_comment_bytewise()`)
+#[cfg(feature = "fs")]
+pub fn toggle_comment_line(file_path: &str, line: usize) -> Result<(), ToggleCommentError> {
+    toggle_basic_singleline_comment_bytewise_with_options(
+        file_path,
+        line,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_comment_line()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_comment_line_with_options(
+    file_path: &str,
+    line: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_basic_singleline_comment_bytewise_with_options(file_path, line, options)
+}
+
+/// Toggle the extension's line comment on a list of lines in one pass (`toggle_multiple_basic_comments()`)
+#[cfg(feature = "fs")]
+pub fn toggle_comment_lines(file_path: &str, lines: &[usize]) -> Result<(), ToggleCommentError> {
+    toggle_multiple_basic_comments_with_options(file_path, lines, &ToggleOptions::default())
+}
+
+/// Same as `toggle_comment_lines()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_comment_lines_with_options(
+    file_path: &str,
+    lines: &[usize],
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_multiple_basic_comments_with_options(file_path, lines, options)
+}
+
+/// Toggle the extension's line comment on line numbers read from a stream (`toggle_multiple_basic_comments_from_reader()`)
+#[cfg(feature = "fs")]
+pub fn toggle_comment_lines_from_reader<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+) -> Result<usize, ToggleCommentError> {
+    toggle_multiple_basic_comments_from_reader_with_options(
+        file_path,
+        reader,
+        max_lines,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_comment_lines_from_reader()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_comment_lines_from_reader_with_options<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+    options: &ToggleOptions,
+) -> Result<usize, ToggleCommentError> {
+    toggle_multiple_basic_comments_from_reader_with_options(file_path, reader, max_lines, options)
+}
+
+/// Toggle the extension's line comment on lines `start..=end`, in either order (`toggle_range_basic_comments_bytewise()`)
+#[cfg(feature = "fs")]
+pub fn toggle_comment_range(
+    file_path: &str,
+    start: usize,
+    end: usize,
+) -> Result<(), ToggleCommentError> {
+    toggle_range_basic_comments_bytewise_with_options(
+        file_path,
+        start,
+        end,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_comment_range()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_comment_range_with_options(
+    file_path: &str,
+    start: usize,
+    end: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_range_basic_comments_bytewise_with_options(file_path, start, end, options)
+}
+
+/// Toggle a Rust docstring (`///`) on one line (`toggle_rust_docstring_singleline_comment_bytewise()`)
+#[cfg(feature = "fs")]
+pub fn toggle_docstring_line(file_path: &str, line: usize) -> Result<(), ToggleCommentError> {
+    toggle_rust_docstring_singleline_comment_bytewise_with_options(
+        file_path,
+        line,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_docstring_line()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_docstring_line_with_options(
+    file_path: &str,
+    line: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_rust_docstring_singleline_comment_bytewise_with_options(file_path, line, options)
+}
+
+/// Toggle a Rust docstring (`///`) on a list of lines in one pass (`toggle_multiple_singleline_docstrings()`)
+#[cfg(feature = "fs")]
+pub fn toggle_docstring_lines(file_path: &str, lines: &[usize]) -> Result<(), ToggleCommentError> {
+    toggle_multiple_singleline_docstrings_with_options(file_path, lines, &ToggleOptions::default())
+}
+
+/// Same as `toggle_docstring_lines()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_docstring_lines_with_options(
+    file_path: &str,
+    lines: &[usize],
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_multiple_singleline_docstrings_with_options(file_path, lines, options)
+}
+
+/// Toggle a Rust docstring (`///`) on line numbers read from a stream (`toggle_multiple_singleline_docstrings_from_reader()`)
+#[cfg(feature = "fs")]
+pub fn toggle_docstring_lines_from_reader<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+) -> Result<usize, ToggleCommentError> {
+    toggle_multiple_singleline_docstrings_from_reader_with_options(
+        file_path,
+        reader,
+        max_lines,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_docstring_lines_from_reader()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_docstring_lines_from_reader_with_options<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+    options: &ToggleOptions,
+) -> Result<usize, ToggleCommentError> {
+    toggle_multiple_singleline_docstrings_from_reader_with_options(
+        file_path, reader, max_lines, options,
+    )
+}
+
+/// Toggle a Rust docstring (`///`) on lines `start..=end`, in either order (`toggle_range_rust_docstring_bytewise()`)
+#[cfg(feature = "fs")]
+pub fn toggle_docstring_range(
+    file_path: &str,
+    start: usize,
+    end: usize,
+) -> Result<(), ToggleCommentError> {
+    toggle_range_rust_docstring_bytewise_with_options(
+        file_path,
+        start,
+        end,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_docstring_range()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_docstring_range_with_options(
+    file_path: &str,
+    start: usize,
+    end: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_range_rust_docstring_bytewise_with_options(file_path, start, end, options)
+}
+
+// ============================================================================
+// DEPRECATED NAMES
+// ============================================================================
+//
+// Misspelled public names are kept as thin deprecated wrappers for one
+// release after their rename, then removed. Nothing new is added here.
+// ============================================================================
+
+/// Renamed to `toggle_multiple_singleline_docstrings()`
+#[cfg(feature = "fs")]
+#[deprecated(note = "renamed to toggle_multiple_singleline_docstrings")]
+pub fn toggle_multiple_singline_docstrings(
+    file_path: &str,
+    line_numbers: &[usize],
+) -> Result<(), ToggleCommentError> {
+    toggle_multiple_singleline_docstrings(file_path, line_numbers)
+}
+
+/// Renamed to `toggle_multiple_singleline_docstrings_with_options()`
+#[cfg(feature = "fs")]
+#[deprecated(note = "renamed to toggle_multiple_singleline_docstrings_with_options")]
+pub fn toggle_multiple_singline_docstrings_with_options(
+    file_path: &str,
+    line_numbers: &[usize],
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    toggle_multiple_singleline_docstrings_with_options(file_path, line_numbers, options)
+}
+
+/// Renamed to `toggle_multiple_singleline_docstrings_from_reader()`
+#[cfg(feature = "fs")]
+#[deprecated(note = "renamed to toggle_multiple_singleline_docstrings_from_reader")]
+pub fn toggle_multiple_singline_docstrings_from_reader<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+) -> Result<usize, ToggleCommentError> {
+    toggle_multiple_singleline_docstrings_from_reader(file_path, reader, max_lines)
+}
+
+/// Renamed to `toggle_multiple_singleline_docstrings_from_reader_with_options()`
+#[cfg(feature = "fs")]
+#[deprecated(note = "renamed to toggle_multiple_singleline_docstrings_from_reader_with_options")]
+pub fn toggle_multiple_singline_docstrings_from_reader_with_options<R: Read>(
+    file_path: &str,
+    reader: &mut R,
+    max_lines: usize,
+    options: &ToggleOptions,
+) -> Result<usize, ToggleCommentError> {
+    toggle_multiple_singleline_docstrings_from_reader_with_options(
+        file_path, reader, max_lines, options,
+    )
+}
+
+// ============================================================================
+// TESTS - PHASE 4: BATCH TOGGLE BYTEWISE
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod batch_toggle_bytewise_tests {
+    use super::*;
+
+    #[test]
+    fn test_long_lines_are_not_an_error() {
+        // 2 MB line, edited or not: streamed, never rejected
+        let long = "x".repeat(2 * 1024 * 1024);
+        let test_file = create_test_file("test_long_lines.py", &format!("a\n{}\nb\n", long));
+        let path = test_file.to_str().unwrap();
+
+        toggle_comment_lines(path, &[0, 2]).unwrap();
+        toggle_comment_line(path, 1).unwrap();
+        assert_eq!(
+            read_file_content(&test_file),
+            format!("# a\n# {}\n# b\n", long)
+        );
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_long_lines.py"),
+        ]);
+    }
+
+    #[test]
+    fn test_consistent_names() {
+        let test_file = create_test_file("test_consistent_names.rs", "a\nb\nc\nd\n");
+        let path = test_file.to_str().unwrap();
+
+        toggle_comment_line(path, 0).unwrap();
+        toggle_comment_range(path, 2, 1).unwrap();
+        assert_eq!(read_file_content(&test_file), "// a\n// b\n// c\nd\n");
+
+        toggle_docstring_lines(path, &[3]).unwrap();
+        toggle_docstring_lines_from_reader(path, &mut "3\n".as_bytes(), 10).unwrap();
+        toggle_docstring_range(path, 3, 3).unwrap();
+        toggle_comment_lines(path, &[0, 1, 2]).unwrap();
+        assert_eq!(read_file_content(&test_file), "a\nb\nc\n/// d\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_consistent_names.rs"),
+        ]);
+    }
+
+    // ========================================
+    // Line Engine Tests (in-memory)
+    // ========================================
+
+    fn column_zero_rule(flag: CommentFlag) -> LineToggleRule {
+        LineToggleRule {
+            comment_flag: flag,
+            removal_only_flag: None,
+            preserve_indent: false,
+            direction: LineToggleDirection::Toggle,
+            removal: RemovalMode::Strict,
+            eol: EolMode::Keep,
+            final_newline: FinalNewline::Keep,
+            trim_trailing: false,
+            skip_blank: false,
+            eol_audit: TraceFormat::Off,
+            heredocs: false,
+            indent_tabs: false,
+        }
+    }
+
+    fn toggle_with_rule(content: &str, targets: &[usize], rule: LineToggleRule) -> String {
+        let mut reader = std::io::Cursor::new(content.as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        write_toggled_lines_bytewise(&mut reader, &mut output, targets, rule).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn toggle_in_memory(content: &str, targets: &[usize], flag: CommentFlag) -> String {
+        toggle_with_rule(content, targets, column_zero_rule(flag))
+    }
+
+    #[test]
+    fn test_engine_add_and_remove() {
+        let result = toggle_in_memory("a\n// b\nc\n", &[0, 1], CommentFlag::DoubleSlash);
+        assert_eq!(result, "// a\nb\nc\n");
+    }
+
+    #[test]
+    fn test_engine_short_lines_and_crlf() {
+        // Lines shorter than the pattern, and CRLF endings (a bare flag is
+        // at the end of its line: removed)
+        let result = toggle_in_memory("/\r\n\n#\r\n", &[0, 1, 2], CommentFlag::Hash);
+        assert_eq!(result, "# /\r\n# \n\r\n");
+    }
+
+    #[test]
+    fn test_engine_partial_flag_not_removed() {
+        // "//x" is not the "// " pattern: flag is added
+        let result = toggle_in_memory("//x\n", &[0], CommentFlag::DoubleSlash);
+        assert_eq!(result, "// //x\n");
+    }
+
+    #[test]
+    fn test_engine_triple_slash_at_end_of_line() {
+        // Blank doc lines lose the flag; "///\r" without "\n" is content
+        let result = toggle_in_memory(
+            "///\n///\r\n///\rx\n/// a\n///",
+            &[0, 1, 2, 3, 4],
+            CommentFlag::TripleSlash,
+        );
+        assert_eq!(result, "\n\r\n/// ///\rx\na\n");
+    }
+
+    #[test]
+    fn test_engine_bare_flag_at_end_of_line() {
+        // "{spaces}{flag}" then the line ending: the flag goes, indent stays
+        let indented = LineToggleRule {
+            preserve_indent: true,
+            ..column_zero_rule(CommentFlag::DoubleSlash)
+        };
+        let result = toggle_with_rule("    //\n//\n    //x\n", &[0, 1, 2], indented);
+        assert_eq!(result, "    \n\n    // //x\n");
+
+        // The removal-only flag too (PHP `#`)
+        let php = LineToggleRule {
+            removal_only_flag: Some(CommentFlag::Hash),
+            ..column_zero_rule(CommentFlag::DoubleSlash)
+        };
+        assert_eq!(toggle_with_rule("#\r\n#", &[0, 1], php), "\r\n");
+    }
+
+    #[test]
+    fn test_engine_removal_only_flag() {
+        // "# " is removed, everything else gets "// "
+        let rule = LineToggleRule {
+            removal_only_flag: Some(CommentFlag::Hash),
+            ..column_zero_rule(CommentFlag::DoubleSlash)
+        };
+        let result = toggle_with_rule("# a\n#b\n// c\nd", &[0, 1, 2, 3], rule);
+        assert_eq!(result, "a\n// #b\nc\n// d");
+    }
+
+    #[test]
+    fn test_engine_preserve_indent() {
+        let rule = LineToggleRule {
+            preserve_indent: true,
+            ..column_zero_rule(CommentFlag::Hash)
+        };
+        let result = toggle_with_rule(
+            "list:\n  - a\n  # - b\n    \n# c\n  ",
+            &[1, 2, 3, 4, 5],
+            rule,
+        );
+        assert_eq!(result, "list:\n  # - a\n  - b\n    # \nc\n  # ");
+
+        // 100+ spaces: detection and removal reach the flag all the same
+        let deep = " ".repeat(120);
+        let original = format!("{}# a\n{}b\n", deep, " ".repeat(300));
+        let toggled = toggle_with_rule(&original, &[0, 1], rule);
+        assert_eq!(toggled, format!("{}a\n{}# b\n", deep, " ".repeat(300)));
+        assert_eq!(toggle_with_rule(&toggled, &[0, 1], rule), original);
+    }
+
+    #[test]
+    fn test_engine_add_and_remove_directions() {
+        let add = LineToggleRule {
+            preserve_indent: true,
+            direction: LineToggleDirection::Add,
+            ..column_zero_rule(CommentFlag::Hash)
+        };
+        let remove = LineToggleRule {
+            direction: LineToggleDirection::Remove,
+            ..add
+        };
+        let original = "def f():\n    # note\n\n    \r\n    x = 1";
+        let commented = toggle_with_rule(original, &[0, 1, 2, 3, 4], add);
+        assert_eq!(commented, "# def f():\n    # # note\n\n    \r\n    # x = 1");
+        assert_eq!(
+            toggle_with_rule(&commented, &[0, 1, 2, 3, 4], remove),
+            original
+        );
+        assert_eq!(toggle_with_rule("a\n# b\n", &[0, 1], remove), "a\nb\n");
+    }
+
+    #[test]
+    fn test_engine_relaxed_removal() {
+        let relaxed = LineToggleRule {
+            removal: RemovalMode::Relaxed,
+            ..column_zero_rule(CommentFlag::DoubleSlash)
+        };
+        let result = toggle_with_rule(
+            "//x\n//   y\r\n//\n//          z\na",
+            &[0, 1, 2, 3, 4],
+            relaxed,
+        );
+        assert_eq!(result, "x\ny\r\n\n  z\n// a");
+
+        let html = LineToggleRule {
+            removal: RemovalMode::Relaxed,
+            ..column_zero_rule(CommentFlag::HtmlComment)
+        };
+        assert_eq!(toggle_with_rule("<!--note -->\n", &[0], html), "note\n");
+    }
+
+    #[test]
+    fn test_engine_html_comment_suffix() {
+        let result = toggle_in_memory(
+            "<!-- a -->\r\nb\n<\n<!-- c -->",
+            &[0, 1, 2, 3],
+            CommentFlag::HtmlComment,
+        );
+        assert_eq!(result, "a\r\n<!-- b -->\n<!-- < -->\nc");
+    }
+
+    #[test]
+    fn test_engine_eol_normalization() {
+        let to_lf = LineToggleRule {
+            eol: EolMode::Lf,
+            ..column_zero_rule(CommentFlag::HtmlComment)
+        };
+        let to_crlf = LineToggleRule {
+            eol: EolMode::Crlf,
+            ..to_lf
+        };
+        // Suffix still lands before the ending; a lone '\r' is kept, also at EOF
+        let content = "a\r\nb\nx\ry\r\n\r";
+        assert_eq!(
+            toggle_with_rule(content, &[0], to_lf),
+            "<!-- a -->\nb\nx\ry\n\r"
+        );
+        assert_eq!(
+            toggle_with_rule(content, &[1], to_crlf),
+            "a\r\n<!-- b -->\r\nx\ry\r\n\r"
+        );
+
+        let mut reader = std::io::Cursor::new(content.as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(
+            write_toggled_lines_bytewise(&mut reader, &mut output, &[], to_lf),
+            Ok(2)
+        );
+    }
+
+    #[test]
+    fn test_engine_final_newline() {
+        let ensure = LineToggleRule {
+            final_newline: FinalNewline::Ensure,
+            ..column_zero_rule(CommentFlag::Hash)
+        };
+        let strip = LineToggleRule {
+            final_newline: FinalNewline::Strip,
+            ..ensure
+        };
+        // Ensure: style of the file's last ending; nothing for an empty file
+        assert_eq!(toggle_with_rule("a\r\nb", &[1], ensure), "a\r\n# b\r\n");
+        assert_eq!(toggle_with_rule("a\n", &[0], ensure), "# a\n");
+        assert_eq!(toggle_with_rule("", &[], ensure), "");
+        assert_eq!(
+            toggle_with_rule(
+                "a",
+                &[],
+                LineToggleRule {
+                    eol: EolMode::Crlf,
+                    ..ensure
+                }
+            ),
+            "a\r\n"
+        );
+        // Strip: only the last ending goes; a lone '\r' is content
+        assert_eq!(toggle_with_rule("a\n\r\n", &[0], strip), "# a\n");
+        assert_eq!(toggle_with_rule("a\r", &[], strip), "a\r");
+        assert_eq!(toggle_with_rule("a\n\nb", &[], strip), "a\n\nb");
+    }
+
+    fn audit_records(content: &str, targets: &[usize], rule: LineToggleRule) -> String {
+        let mut reader = std::io::Cursor::new(content.as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let mut audit: Vec<u8> = Vec::new();
+        write_toggled_lines_audited(&mut reader, &mut output, targets, rule, &mut audit).unwrap();
+        String::from_utf8(audit).unwrap()
+    }
+
+    #[test]
+    fn test_engine_eol_audit() {
+        let audit = LineToggleRule {
+            eol_audit: TraceFormat::Text,
+            ..column_zero_rule(CommentFlag::Hash)
+        };
+        // Targets always, other lines only when their ending changed
+        assert_eq!(
+            audit_records("a\r\nb\r\nc", &[0, 2], audit),
+            "toggle_comment audit: eol line=0 before=crlf after=crlf\n\
+             toggle_comment audit: eol line=2 before=none after=none\n"
+        );
+        assert_eq!(
+            audit_records(
+                "a\r\nb\r\nc\n",
+                &[0],
+                LineToggleRule {
+                    eol: EolMode::Lf,
+                    ..audit
+                }
+            ),
+            "toggle_comment audit: eol line=0 before=crlf after=lf\n\
+             toggle_comment audit: eol line=1 before=crlf after=lf\n"
+        );
+        // Final newline: added to the last line, dropped from the one before
+        // the empty position after it
+        assert_eq!(
+            audit_records(
+                "a\nb",
+                &[1],
+                LineToggleRule {
+                    final_newline: FinalNewline::Ensure,
+                    ..audit
+                }
+            ),
+            "toggle_comment audit: eol line=1 before=none after=lf\n"
+        );
+        assert_eq!(
+            audit_records(
+                "a\r\nb\r\n",
+                &[0],
+                LineToggleRule {
+                    final_newline: FinalNewline::Strip,
+                    ..audit
+                }
+            ),
+            "toggle_comment audit: eol line=0 before=crlf after=crlf\n\
+             toggle_comment audit: eol line=1 before=crlf after=none\n"
+        );
+        // Off: nothing recorded
+        assert_eq!(
+            audit_records("a\r\n", &[0], column_zero_rule(CommentFlag::Hash)),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_eol_audit_line_json() {
+        let mut json: Vec<u8> = Vec::new();
+        write_eol_audit_line(
+            &mut json,
+            TraceFormat::Json,
+            EolAuditRecord {
+                line: 4,
+                before: Some(true),
+                after: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"audit\":\"eol\",\"line\":4,\"before\":\"crlf\",\"after\":\"none\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_engine_trim_trailing() {
+        let trim = LineToggleRule {
+            trim_trailing: true,
+            ..column_zero_rule(CommentFlag::HtmlComment)
+        };
+        // Inner blanks and a lone '\r' stay; untouched line 3 keeps its blanks
+        assert_eq!(
+            toggle_with_rule("<!-- a  b \t -->\r\nc\r \n \t\nd  ", &[0, 1, 2], trim),
+            "a  b\r\n<!-- c\r  -->\n<!--  \t -->\nd  "
+        );
+        let hash = LineToggleRule {
+            trim_trailing: true,
+            ..column_zero_rule(CommentFlag::Hash)
+        };
+        assert_eq!(toggle_with_rule("# x  \n\n  ", &[0, 1, 2], hash), "x\n#\n#");
+    }
+
+    #[test]
+    fn test_engine_mixed_line_endings_warning() {
+        let _ = take_warnings();
+        let rule = column_zero_rule(CommentFlag::Hash);
+        assert_eq!(
+            toggle_with_rule("a\r\nb\nc\r\n", &[1], rule),
+            "a\r\n# b\nc\r\n"
+        );
+        assert_eq!(
+            take_warnings().list,
+            vec![Warning::MixedLineEndings { lf: 1, crlf: 2 }]
+        );
+
+        // Uniform, or converted: nothing to warn about
+        toggle_with_rule("a\r\nb\r\n", &[1], rule);
+        let to_lf = LineToggleRule {
+            eol: EolMode::Lf,
+            ..rule
+        };
+        toggle_with_rule("a\r\nb\n", &[1], to_lf);
+        assert_eq!(take_warnings(), Warnings::default());
+
+        // Bounded: later warnings are only counted
+        for _ in 0..MAX_WARNINGS + 2 {
+            toggle_with_rule("a\r\nb\n", &[1], rule);
+        }
+        let warnings = take_warnings();
+        assert_eq!((warnings.list.len(), warnings.dropped), (MAX_WARNINGS, 2));
+        let mut text: Vec<u8> = Vec::new();
+        let short = Warnings {
+            list: warnings.list[..1].to_vec(),
+            dropped: 2,
+        };
+        write_warnings(&mut text, TraceFormat::Off, "toggle", &short).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "toggle_comment warning: op=toggle file mixes line endings (1 LF, 1 CRLF); kept as they are\n\
+             toggle_comment warning: op=toggle 2 more warnings not shown\n"
+        );
+    }
+
+    #[test]
+    fn test_engine_utf8_content() {
+        // Multi-byte text right after the indentation, tabs, CRLF: the flag
+        // lands at column 0 or after the spaces, the bytes after it unchanged
+        let content = "\u{e9}t\u{e9}\n    \u{65e5}\u{672c}\r\n\t  \u{1F600}\n  \u{301}x\n";
+        let targets = [0, 1, 2, 3];
+        let indented = LineToggleRule {
+            preserve_indent: true,
+            ..column_zero_rule(CommentFlag::Hash)
+        };
+        for (rule, expected) in [
+            (
+                column_zero_rule(CommentFlag::Hash),
+                "# \u{e9}t\u{e9}\n#     \u{65e5}\u{672c}\r\n# \t  \u{1F600}\n#   \u{301}x\n",
+            ),
+            (
+                indented,
+                "# \u{e9}t\u{e9}\n    # \u{65e5}\u{672c}\r\n# \t  \u{1F600}\n  # \u{301}x\n",
+            ),
+        ] {
+            let toggled = toggle_with_rule(content, &targets, rule);
+            assert_eq!(toggled, expected);
+            assert_eq!(toggle_with_rule(&toggled, &targets, rule), content);
+        }
+    }
+
+    #[test]
+    fn test_first_invalid_utf8_line() {
+        let check = |bytes: &[u8]| first_invalid_utf8_line_in(&mut &bytes[..]).unwrap();
+        assert_eq!(check("a\n\u{e9}\n".as_bytes()), None);
+        assert_eq!(check(b"a\nb\n\xff\n"), Some(2));
+        assert_eq!(check(b"a\n\xc3"), Some(1)); // cut off at end of file
+        assert_eq!(check(b"\xed\xa0\x80\n"), Some(0)); // surrogate
+
+        // A character split across the read chunks is still valid
+        let mut split = vec![b'a'; IO_BUFFER_SIZE - 1];
+        split.extend_from_slice("\u{1F600}\n\n".as_bytes());
+        assert_eq!(check(&split), None);
+        split.push(0x80);
+        assert_eq!(check(&split), Some(2));
+    }
+
+    #[test]
+    fn test_engine_docstring_no_trailing_newline() {
+        let result = toggle_in_memory("a\n/// b", &[1], CommentFlag::TripleSlash);
+        assert_eq!(result, "a\nb");
+    }
+
+    #[test]
+    fn test_engine_line_not_found() {
+        let mut reader = std::io::Cursor::new("a\nb".as_bytes());
+        let mut output: Vec<u8> = Vec::new();
+        let result = write_toggled_lines_bytewise(
+            &mut reader,
+            &mut output,
+            &[5],
+            column_zero_rule(CommentFlag::Hash),
+        );
+        assert_eq!(
+            result,
+            Err(ToggleCommentError::LineNotFound {
+                requested: 5,
+                file_lines: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_sort_and_dedup() {
+        let mut buffer: [usize; MAX_BATCH_LINES] = [0; MAX_BATCH_LINES];
+        let count = sort_and_dedup_line_numbers(&[9, 3, 3, 1, 9], &mut buffer).unwrap();
+        assert_eq!(&buffer[..count], &[1, 3, 9]);
+
+        let too_many = [0usize; MAX_BATCH_LINES + 1];
+        assert!(matches!(
+            sort_and_dedup_line_numbers(&too_many, &mut buffer),
+            Err(ToggleCommentError::TooManyLines { .. })
+        ));
+    }
+
+    #[test]
+    fn test_select_next_batch_after() {
+        // 300 distinct values, reversed, with duplicates
+        let mut numbers: Vec<usize> = (0..300).rev().collect();
+        numbers.extend_from_slice(&[5, 200, 299]);
+
+        let mut buffer: [usize; MAX_BATCH_LINES] = [0; MAX_BATCH_LINES];
+        let first = select_next_batch_after(&numbers, None, &mut buffer);
+        assert_eq!(first, MAX_BATCH_LINES);
+        assert_eq!(buffer[0], 0);
+        assert_eq!(buffer[first - 1], 127);
+
+        let second = select_next_batch_after(&numbers, Some(127), &mut buffer);
+        assert_eq!(second, MAX_BATCH_LINES);
+        assert_eq!(buffer[0], 128);
+
+        let third = select_next_batch_after(&numbers, Some(255), &mut buffer);
+        assert_eq!(third, 44);
+        assert_eq!(buffer[third - 1], 299);
+
+        assert_eq!(select_next_batch_after(&numbers, Some(299), &mut buffer), 0);
+    }
+
+    // ========================================
+    // Stream Parsing Tests
+    // ========================================
+
+    #[test]
+    fn test_read_line_numbers_format() {
+        let mut reader = std::io::Cursor::new(" 3\n\n12 \r\n\t7".as_bytes());
+        let mut input_line: usize = 0;
+        assert_eq!(
+            read_next_line_number(&mut reader, &mut input_line),
+            Ok(Some(3))
+        );
+        assert_eq!(
+            read_next_line_number(&mut reader, &mut input_line),
+            Ok(Some(12))
+        );
+        assert_eq!(
+            read_next_line_number(&mut reader, &mut input_line),
+            Ok(Some(7))
+        );
+        assert_eq!(
+            read_next_line_number(&mut reader, &mut input_line),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_read_line_numbers_invalid() {
+        let mut reader = std::io::Cursor::new("1\n2 3\n".as_bytes());
+        let mut input_line: usize = 0;
+        assert_eq!(
+            read_next_line_number(&mut reader, &mut input_line),
+            Ok(Some(1))
+        );
+        assert_eq!(
+            read_next_line_number(&mut reader, &mut input_line),
+            Err(ToggleCommentError::InvalidLineNumber { input_line: 2 })
+        );
+    }
+
+    // ========================================
+    // File-Level Tests
+    // ========================================
+
+    #[test]
+    fn test_batch_basic_unsorted_duplicates() {
+        let content = "line 0\nline 1\n// line 2\nline 3\n";
+        let test_file = create_test_file("test_batch_bw_basic.rs", content);
+
+        let result = toggle_multiple_basic_comments(test_file.to_str().unwrap(), &[2, 0, 2]);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "// line 0\nline 1\nline 2\nline 3\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_batch_bw_basic.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_batch_line_not_found_leaves_file() {
+        let content = "line 0\nline 1\n";
+        let test_file = create_test_file("test_batch_bw_notfound.py", content);
+
+        let result = toggle_multiple_basic_comments(test_file.to_str().unwrap(), &[0, 10]);
+        assert!(matches!(
+            result,
+            Err(ToggleCommentError::LineNotFound { requested: 10, .. })
+        ));
+        assert_eq!(read_file_content(&test_file), content);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_batch_bw_notfound.py"),
+        ]);
+    }
+
+    #[test]
+    fn test_batch_ranges() {
+        let content = "a\nb\n# c\nd\ne\nf\n";
+        let test_file = create_test_file("test_batch_bw_ranges.py", content);
+        let path = test_file.to_str().unwrap();
+
+        // Any order, reversed ranges; a 300-line range would be one pass too
+        toggle_ranges_basic_comments(path, &[(5, 5), (2, 0)]).unwrap();
+        assert_eq!(read_file_content(&test_file), "# a\n# b\nc\nd\ne\n# f\n");
+
+        assert_eq!(
+            toggle_ranges_basic_comments(path, &[(0, 2), (4, 3), (2, 2)]),
+            Err(ToggleCommentError::OverlappingRanges { line: 2 })
+        );
+        assert_eq!(
+            toggle_ranges_basic_comments(path, &[(0, 0), (4, 9)]),
+            Err(ToggleCommentError::LineNotFound {
+                requested: 9,
+                file_lines: 6
+            })
+        );
+        assert_eq!(
+            toggle_ranges_basic_comments(path, &[(1, 9)])
+                .unwrap_err()
+                .to_string(),
+            "Line 9 not found (file has 6 lines)"
+        );
+        assert_eq!(
+            toggle_ranges_basic_comments(path, &[(0, 0); MAX_RANGES + 1]),
+            Err(ToggleCommentError::TooManyLines { max: MAX_RANGES })
+        );
+        assert_eq!(
+            toggle_ranges_basic_comments(path, &[(0, MAX_TOTAL_BATCH_LINES)]),
+            Err(ToggleCommentError::TooManyLines {
+                max: MAX_TOTAL_BATCH_LINES
+            })
+        );
+        assert_eq!(read_file_content(&test_file), "# a\n# b\nc\nd\ne\n# f\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_batch_bw_ranges.py"),
+        ]);
+    }
+
+    #[test]
+    fn test_batch_docstring() {
+        let content = "line 0\n/// line 1\n";
+        let test_file = create_test_file("test_batch_bw_doc.rs", content);
+
+        let result = toggle_multiple_singleline_docstrings(test_file.to_str().unwrap(), &[0, 1]);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert_eq!(new_content, "/// line 0\nline 1\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_batch_bw_doc.rs"),
+        ]);
+    }
+
+    #[test]
+    fn test_docstring_needs_rust_file() {
+        let test_file = create_test_file("test_docstring_needs_rust.toml", "a = 1\n");
+        let path = test_file.to_str().unwrap();
+
+        assert_eq!(
+            toggle_docstring_line(path, 0),
+            Err(ToggleCommentError::NotRustSource)
+        );
+        assert_eq!(
+            toggle_docstring_lines(path, &[0]),
+            Err(ToggleCommentError::NotRustSource)
+        );
+        assert_eq!(
+            toggle_docstring_lines_from_reader(path, &mut "0\n".as_bytes(), 10),
+            Err(ToggleCommentError::NotRustSource)
+        );
+        assert_eq!(read_file_content(&test_file), "a = 1\n");
+        assert!(!Path::new("backup_toggle_comment_test_docstring_needs_rust.toml").exists());
+
+        let any = ToggleOptions {
+            allow_any_extension: true,
+            ..ToggleOptions::default()
+        };
+        assert_eq!(toggle_docstring_line_with_options(path, 0, &any), Ok(()));
+        assert_eq!(read_file_content(&test_file), "/// a = 1\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_docstring_needs_rust.toml"),
+        ]);
+    }
+
+    #[test]
+    fn test_batch_many_lines_single_backup() {
+        // 5,000 lines in one call -> several passes, one backup
+        let mut content = String::new();
+        for i in 0..5000 {
+            content.push_str(&format!("line {}\n", i));
+        }
+        let test_file = create_test_file("test_batch_bw_many.py", &content);
+
+        // Every line, reversed, plus one duplicate (still toggled once)
+        let mut numbers: Vec<usize> = (0..5000).rev().collect();
+        numbers.push(4321);
+
+        let result = toggle_multiple_basic_comments(test_file.to_str().unwrap(), &numbers);
+        assert!(result.is_ok());
+
+        let new_content = read_file_content(&test_file);
+        assert!(new_content.starts_with("# line 0\n"));
+        assert!(new_content.contains("\n# line 4321\n"));
+        assert!(new_content.ends_with("# line 4999\n"));
+        assert!(!new_content.contains("\nline "));
+
+        let backup_path = PathBuf::from("backup_toggle_comment_test_batch_bw_many.py");
+        assert_eq!(read_file_content(&backup_path), content);
+
+        cleanup_files(&[&test_file, &backup_path]);
+    }
+
+    #[test]
+    fn test_batch_many_lines_not_found_leaves_file() {
+        // Out-of-range line lands in a later pass: original still untouched
+        let mut content = String::new();
+        for i in 0..200 {
+            content.push_str(&format!("line {}\n", i));
+        }
+        let test_file = create_test_file("test_batch_bw_many_notfound.py", &content);
+
+        let mut numbers: Vec<usize> = (0..200).collect();
+        numbers.push(999);
+
+        let result = toggle_multiple_basic_comments(test_file.to_str().unwrap(), &numbers);
+        assert!(matches!(
+            result,
+            Err(ToggleCommentError::LineNotFound { requested: 999, .. })
+        ));
+        assert_eq!(read_file_content(&test_file), content);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_batch_bw_many_notfound.py"),
+        ]);
+    }
+
+    #[test]
+    fn test_stream_multiple_chunks() {
+        // 300 lines -> three chunks of MAX_BATCH_LINES
+        let mut content = String::new();
+        let mut numbers = String::new();
+        for i in 0..300 {
+            content.push_str(&format!("line {}\n", i));
+            numbers.push_str(&format!("{}\n", i));
+        }
+        let test_file = create_test_file("test_batch_bw_stream.py", &content);
+
+        let mut reader = std::io::Cursor::new(numbers.into_bytes());
+        let result = toggle_multiple_basic_comments_from_reader(
+            test_file.to_str().unwrap(),
+            &mut reader,
+            DEFAULT_MAX_STREAMED_LINES,
+        );
+        assert_eq!(result, Ok(300));
+
+        // Repeated entries toggle (and count) once
+        let mut reader = std::io::Cursor::new("1\n1\n3\n1\n".as_bytes());
+        let result = toggle_multiple_basic_comments_from_reader(
+            test_file.to_str().unwrap(),
+            &mut reader,
+            DEFAULT_MAX_STREAMED_LINES,
+        );
+        assert_eq!(result, Ok(2));
+        toggle_multiple_basic_comments(test_file.to_str().unwrap(), &[1, 3]).unwrap();
+
+        let new_content = read_file_content(&test_file);
+        assert!(new_content.starts_with("# line 0\n# line 1\n"));
+        assert!(new_content.ends_with("# line 299\n"));
+        assert!(!new_content.contains("\nline "));
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_batch_bw_stream.py"),
+        ]);
+    }
+
+    #[test]
+    fn test_stream_over_limit_leaves_file() {
+        let content = "a\nb\nc\n";
+        let test_file = create_test_file("test_batch_bw_stream_limit.py", content);
+
+        let mut reader = std::io::Cursor::new("0\n1\n2\n".as_bytes());
+        let result =
+            toggle_multiple_basic_comments_from_reader(test_file.to_str().unwrap(), &mut reader, 2);
+        assert_eq!(result, Err(ToggleCommentError::TooManyLines { max: 2 }));
+        assert_eq!(read_file_content(&test_file), content);
+
+        cleanup_files(&[&test_file]);
+    }
+}
+
+// ============================================================================
+// PHASE 5: JUPYTER NOTEBOOK CELLS (BYTEWISE)
+// ============================================================================
+//
+// ## Project Context
+// A notebook (`.ipynb`) is one JSON document; its code lives in string values:
+//
+//     {"cells": [{"cell_type": "code", "source": ["import os\n", "x = 1\n"]}]}
+//
+// Toggling line N of the file would edit JSON syntax. Notebook mode instead
+// addresses (cell index, line within cell) and toggles `# ` at the start of
+// that source line, inside the JSON string.
+//
+// ## Approach
+// 1. A minimal streaming JSON scanner (`NotebookScanner`: forward-only, one
+//    byte of lookahead, no tree, no heap) walks `cells[cell]`, checks that
+//    `cell_type` is "code", and finds the byte offset where the requested
+//    source line starts, plus whether it starts with `# `.
+// 2. The single-line writer (`write_toggled_file_bytewise()`) inserts or drops
+//    the two bytes `# ` at that offset. Both are plain ASCII, so the JSON
+//    string stays valid; every other byte of the file is copied unchanged.
+//
+// `source` may be an array of strings (one per line, nbformat 4) or a single
+// string (lines separated by `\n` escapes); both are handled. Only values on
+// the path to the target are inspected; everything else is skipped by bracket
+// depth. Each scanner step consumes input, so scanning is bounded by
+// MAX_BYTE_ITERATIONS (and by `ToggleOptions::max_file_size` up front).
+// ============================================================================
+
+/// Longest object key / short string value the scanner compares ("cell_type")
+const MAX_NOTEBOOK_TOKEN_BYTES: usize = 16;
+
+/// Where a notebook source line starts in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct NotebookLineLocation {
+    /// Byte offset of the first content byte of the line
+    line_start_pos: u64,
+
+    /// Line starts with `# `
+    has_tag: bool,
+}
+
+/// Minimal forward-only JSON scanner over a byte reader
+///
+/// Syntax is only checked as far as needed to find the target; end of input
+/// anywhere inside the document is `InvalidNotebook`.
+struct NotebookScanner<R: Read> {
+    reader: R,
+
+    /// Bytes consumed so far (= file offset of the next byte)
+    position: u64,
+
+    /// One byte of lookahead (read, not yet consumed)
+    peeked: Option<u8>,
+}
+
+impl<R: Read> NotebookScanner<R> {
+    fn new(reader: R) -> Self {
+        NotebookScanner {
+            reader,
+            position: 0,
+            peeked: None,
+        }
+    }
+
+    /// Look at the next byte without consuming it
+    fn peek_byte(&mut self) -> Result<u8, ToggleCommentError> {
+        if let Some(byte) = self.peeked {
+            return Ok(byte);
+        }
+        if self.position >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        match read_single_byte(&mut self.reader)? {
+            Some(byte) => {
+                self.peeked = Some(byte);
+                Ok(byte)
+            }
+            None => Err(ToggleCommentError::InvalidNotebook),
+        }
+    }
+
+    /// Consume the next byte
+    fn next_byte(&mut self) -> Result<u8, ToggleCommentError> {
+        let byte = self.peek_byte()?;
+        self.peeked = None;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    /// Skip whitespace, then look at the next byte without consuming it
+    fn peek_token_byte(&mut self) -> Result<u8, ToggleCommentError> {
+        loop {
+            let byte = self.peek_byte()?;
+            if !matches!(byte, b' ' | b'\t' | b'\n' | b'\r') {
+                return Ok(byte);
+            }
+            self.next_byte()?;
+        }
+    }
+
+    /// Skip whitespace, then consume the next byte, which must be `expected`
+    fn expect(&mut self, expected: u8) -> Result<(), ToggleCommentError> {
+        if self.peek_token_byte()? != expected {
+            return Err(ToggleCommentError::InvalidNotebook);
+        }
+        self.next_byte()?;
+        Ok(())
+    }
+
+    /// Step to the next element of an object or array
+    ///
+    /// Call right after the opening `{`/`[` (`first = true`) or after the
+    /// previous element.
+    ///
+    /// # Returns
+    /// * `Ok(true)` - Another element follows (`,` consumed if not first)
+    /// * `Ok(false)` - The closing `close` byte was consumed
+    fn next_element(&mut self, close: u8, first: bool) -> Result<bool, ToggleCommentError> {
+        let byte = self.peek_token_byte()?;
+        if byte == close {
+            self.next_byte()?;
+            return Ok(false);
+        }
+        if !first {
+            if byte != b',' {
+                return Err(ToggleCommentError::InvalidNotebook);
+            }
+            self.next_byte()?;
+        }
+        Ok(true)
+    }
+
+    /// Read a string (opening quote already consumed) into `token`
+    ///
+    /// # Returns
+    /// * `Ok(Some(len))` - `token[..len]` holds the raw content
+    /// * `Ok(None)` - Longer than the token buffer or contains escapes
+    ///   (never one of the keys/values the scanner looks for)
+    fn read_short_string(
+        &mut self,
+        token: &mut [u8; MAX_NOTEBOOK_TOKEN_BYTES],
+    ) -> Result<Option<usize>, ToggleCommentError> {
+        let mut len: usize = 0;
+        let mut comparable = true;
+        loop {
+            let byte = self.next_byte()?;
+            match byte {
+                b'"' => return Ok(if comparable { Some(len) } else { None }),
+                b'\\' => {
+                    self.next_byte()?;
+                    comparable = false;
+                }
+                _ if len < token.len() => {
+                    token[len] = byte;
+                    len += 1;
+                }
+                _ => comparable = false,
+            }
+        }
+    }
+
+    /// Skip a string (opening quote already consumed)
+    fn skip_string(&mut self) -> Result<(), ToggleCommentError> {
+        loop {
+            match self.next_byte()? {
+                b'"' => return Ok(()),
+                b'\\' => {
+                    self.next_byte()?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Skip one value of any type (leading whitespace allowed)
+    fn skip_value(&mut self) -> Result<(), ToggleCommentError> {
+        match self.next_token_byte()? {
+            b'"' => self.skip_string(),
+            b'{' | b'[' => {
+                let mut depth: u64 = 1;
+                while depth > 0 {
+                    match self.next_byte()? {
+                        b'"' => self.skip_string()?,
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => depth -= 1,
+                        _ => {}
+                    }
+                }
+                Ok(())
+            }
+            _ => {
+                // Number / true / false / null: runs up to the next delimiter
+                loop {
+                    let byte = self.peek_byte()?;
+                    if matches!(byte, b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r') {
+                        return Ok(());
+                    }
+                    self.next_byte()?;
+                }
+            }
+        }
+    }
+
+    /// Skip whitespace, then consume the next byte
+    fn next_token_byte(&mut self) -> Result<u8, ToggleCommentError> {
+        self.peek_token_byte()?;
+        self.next_byte()
+    }
+
+    /// Read an object key and its `:` (positioned before the key)
+    fn read_key(
+        &mut self,
+        token: &mut [u8; MAX_NOTEBOOK_TOKEN_BYTES],
+    ) -> Result<Option<usize>, ToggleCommentError> {
+        self.expect(b'"')?;
+        let key_len = self.read_short_string(token)?;
+        self.expect(b':')?;
+        Ok(key_len)
+    }
+
+    /// Scan a source string (opening quote consumed) for the start of a line
+    ///
+    /// # Arguments
+    /// * `target_line` - Line to locate within this string (`None`: just skip)
+    /// * `split_on_newlines` - Count `\n` escapes as line breaks (single-string
+    ///   `source`); otherwise the whole string is line 0
+    ///
+    /// # Returns
+    /// * `Ok((location, lines))` - Location if the target line was in this
+    ///   string, and the number of lines the string holds
+    fn scan_source_string(
+        &mut self,
+        target_line: Option<usize>,
+        split_on_newlines: bool,
+    ) -> Result<(Option<NotebookLineLocation>, usize), ToggleCommentError> {
+        let tag = b"# ";
+        let mut line: usize = 0;
+        let mut location: Option<NotebookLineLocation> = None;
+        // Bytes of "# " matched so far at the target line start (None: not checking)
+        let mut tag_matched: Option<usize> = None;
+
+        if target_line == Some(0) {
+            location = Some(NotebookLineLocation {
+                line_start_pos: self.position,
+                has_tag: false,
+            });
+            tag_matched = Some(0);
+        }
+
+        loop {
+            let byte = self.next_byte()?;
+
+            if let Some(matched) = tag_matched {
+                if byte == tag[matched] {
+                    tag_matched = Some(matched + 1);
+                    if matched + 1 == tag.len() {
+                        if let Some(found) = location.as_mut() {
+                            found.has_tag = true;
+                        }
+                        tag_matched = None;
+                    }
+                } else {
+                    tag_matched = None;
+                }
+            }
+
+            match byte {
+                b'"' => return Ok((location, line + 1)),
+                b'\\' => {
+                    let escaped = self.next_byte()?;
+                    if split_on_newlines && escaped == b'n' {
+                        line += 1;
+                        if target_line == Some(line) {
+                            location = Some(NotebookLineLocation {
+                                line_start_pos: self.position,
+                                has_tag: false,
+                            });
+                            tag_matched = Some(0);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Scan a cell's `source` value (positioned before the value)
+    ///
+    /// # Returns
+    /// * `Ok((location, lines))` - Location of `line_in_cell` if present, and
+    ///   the number of source lines in the cell
+    fn scan_source(
+        &mut self,
+        line_in_cell: usize,
+    ) -> Result<(Option<NotebookLineLocation>, usize), ToggleCommentError> {
+        match self.next_token_byte()? {
+            b'[' => {
+                // nbformat 4: one string per line
+                let mut lines: usize = 0;
+                let mut location: Option<NotebookLineLocation> = None;
+                let mut first = true;
+                while self.next_element(b']', first)? {
+                    first = false;
+                    self.expect(b'"')?;
+                    let target = if lines == line_in_cell { Some(0) } else { None };
+                    let (found, _) = self.scan_source_string(target, false)?;
+                    if found.is_some() {
+                        location = found;
+                    }
+                    lines += 1;
+                }
+                Ok((location, lines))
+            }
+            b'"' => self.scan_source_string(Some(line_in_cell), true),
+            _ => Err(ToggleCommentError::InvalidNotebook),
+        }
+    }
+
+    /// Scan the target cell object (positioned before `{`)
+    fn scan_code_cell(
+        &mut self,
+        cell_index: usize,
+        line_in_cell: usize,
+    ) -> Result<NotebookLineLocation, ToggleCommentError> {
+        let mut token: [u8; MAX_NOTEBOOK_TOKEN_BYTES] = [0u8; MAX_NOTEBOOK_TOKEN_BYTES];
+        let mut is_code: Option<bool> = None;
+        let mut source: Option<(Option<NotebookLineLocation>, usize)> = None;
+
+        // Keys may come in any order: collect both, decide at the end
+        self.expect(b'{')?;
+        let mut first = true;
+        while self.next_element(b'}', first)? {
+            first = false;
+            let key_len = self.read_key(&mut token)?;
+
+            if key_len == Some(9) && &token[..9] == b"cell_type" {
+                self.expect(b'"')?;
+                let value_len = self.read_short_string(&mut token)?;
+                is_code = Some(value_len == Some(4) && &token[..4] == b"code");
+            } else if key_len == Some(6) && &token[..6] == b"source" {
+                source = Some(self.scan_source(line_in_cell)?);
+            } else {
+                self.skip_value()?;
+            }
+        }
+
+        match is_code {
+            Some(true) => {}
+            Some(false) => return Err(ToggleCommentError::NotCodeCell { cell: cell_index }),
+            None => return Err(ToggleCommentError::InvalidNotebook),
+        }
+
+        match source {
+            Some((Some(location), _)) => Ok(location),
+            Some((None, lines)) => Err(ToggleCommentError::LineNotFound {
+                requested: line_in_cell,
+                file_lines: lines,
+            }),
+            None => Err(ToggleCommentError::InvalidNotebook),
+        }
+    }
+
+    /// Scan the `cells` array (positioned before `[`) for the target cell
+    fn scan_cells(
+        &mut self,
+        cell_index: usize,
+        line_in_cell: usize,
+    ) -> Result<NotebookLineLocation, ToggleCommentError> {
+        self.expect(b'[')?;
+        let mut cells: usize = 0;
+        let mut first = true;
+        while self.next_element(b']', first)? {
+            first = false;
+            if cells == cell_index {
+                return self.scan_code_cell(cell_index, line_in_cell);
+            }
+            self.skip_value()?;
+            cells += 1;
+        }
+
+        Err(ToggleCommentError::CellNotFound {
+            requested: cell_index,
+            cells,
+        })
+    }
+}
+
+/// Find where source line `line_in_cell` of code cell `cell_index` starts
+///
+/// # Returns
+/// * `Ok(NotebookLineLocation)` - File offset of the line's first content
+///   byte, and whether it starts with `# `
+/// * `Err(CellNotFound)` - Fewer cells than `cell_index + 1`
+/// * `Err(NotCodeCell)` - Target cell is markdown/raw
+/// * `Err(LineNotFound)` - Cell has fewer source lines (`file_lines` = cell lines)
+/// * `Err(InvalidNotebook)` - Not a JSON object with a `cells` array
+fn locate_notebook_source_line<R: Read>(
+    reader: R,
+    cell_index: usize,
+    line_in_cell: usize,
+) -> Result<NotebookLineLocation, ToggleCommentError> {
+    let mut scanner = NotebookScanner::new(reader);
+    let mut token: [u8; MAX_NOTEBOOK_TOKEN_BYTES] = [0u8; MAX_NOTEBOOK_TOKEN_BYTES];
+
+    scanner.expect(b'{')?;
+    let mut first = true;
+    while scanner.next_element(b'}', first)? {
+        first = false;
+        let key_len = scanner.read_key(&mut token)?;
+        if key_len == Some(5) && &token[..5] == b"cells" {
+            return scanner.scan_cells(cell_index, line_in_cell);
+        }
+        scanner.skip_value()?;
+    }
+
+    Err(ToggleCommentError::InvalidNotebook)
+}
+
+/// Toggle `# ` on one source line of a Jupyter notebook code cell
+///
+/// # Overview
+/// The notebook is treated as JSON: `cells[cell_index]` must be a code cell,
+/// and line `line_in_cell` of its `source` is toggled with the usual rule
+/// (`# ` at the start of the line → removed, otherwise added). Only those
+/// two bytes change; the JSON layout, outputs and metadata are untouched.
+///
+/// Same safety pipeline as `toggle_basic_singleline_comment_bytewise()`:
+/// backup, temp file, fsync, size check, replace.
+///
+/// # Arguments
+/// * `file_path` - Path to the `.ipynb` file
+/// * `cell_index` - Zero-indexed cell (all cell types count)
+/// * `line_in_cell` - Zero-indexed line within the cell's source
+///
+/// # Returns
+/// * `Ok(())` - Line toggled
+/// * `Err(CellNotFound)` / `Err(NotCodeCell)` / `Err(LineNotFound)` - Target
+///   not addressable (file untouched, no backup made)
+/// * `Err(InvalidNotebook)` - File is not a notebook JSON document
+/// * `Err(ToggleCommentError)` - Path, permission or I/O failure
+///
+/// # Example
+/// ```no_run
+/// // Comment out the 2nd line of the 4th cell
+/// toggle_notebook_cell_line_bytewise("./analysis.ipynb", 3, 1)?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_notebook_cell_line_bytewise(
+    file_path: &str,
+    cell_index: usize,
+    line_in_cell: usize,
+) -> Result<(), ToggleCommentError> {
+    toggle_notebook_cell_line_bytewise_with_options(
+        file_path,
+        cell_index,
+        line_in_cell,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_notebook_cell_line_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_notebook_cell_line_bytewise_with_options(
+    file_path: &str,
+    cell_index: usize,
+    line_in_cell: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    // Locate the source line inside the JSON document
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let location = locate_notebook_source_line(
+        BufReader::with_capacity(IO_BUFFER_SIZE, source_file),
+        cell_index,
+        line_in_cell,
+    )?;
+
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+
+    // Create backup copy of original file in CWD
+    let backup_path = backup_path_for(&filename, options);
+    let trace = StageTrace::new(options, "notebook");
+
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    // Create working temp file (CWD, or options.temp_dir)
+    let temp_path = temp_file_path(
+        options.temp_dir.as_deref(),
+        &format!("temp_toggle_notebook_{}_{}", std::process::id(), filename),
+    );
+    let _remove_temp = TempFileGuard(&temp_path);
+
+    // `# ` is inserted/dropped at the line start inside the JSON string
+    let process_result = trace.run_streaming(&absolute_path, &temp_path, || {
+        write_toggled_file_bytewise(
+            &absolute_path,
+            &temp_path,
+            line_in_cell,
+            location.line_start_pos,
+            location.has_tag,
+            CommentFlag::Hash,
+        )
+    });
+
+    match process_result {
+        Ok(()) => {
+            if !trace.check(TraceStage::Verify, || {
+                replacement_size_is_plausible(
+                    &absolute_path,
+                    &temp_path,
+                    MAX_TOGGLE_EDIT_BYTES,
+                    MAX_TOGGLE_EDIT_BYTES,
+                )
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleCommentError::IncompleteWrite);
+            }
+            if let Err(e) = trace.run(TraceStage::Replace, || {
+                replace_original(
+                    &temp_path,
+                    &absolute_path,
+                    options.temp_dir.as_deref(),
+                    options.durability,
+                    &snapshot,
+                )
+            }) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
+            }
+
+            if remove_temp_file(&temp_path).is_err() {
+                #[cfg(debug_assertions)]
+                eprintln!("Warning: Failed to clean up temp file");
+            }
+
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+// ============================================================================
+// TESTS - PHASE 5: JUPYTER NOTEBOOK CELLS
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod notebook_cell_tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+ "cells": [
+  {
+   "cell_type": "markdown",
+   "metadata": {},
+   "source": ["# Title\n"]
+  },
+  {
+   "cell_type": "code",
+   "execution_count": null,
+   "metadata": {"tags": ["a]\"}"]},
+   "outputs": [],
+   "source": [
+    "import os\n",
+    "# x = 1\n",
+    "print(\"done\")"
+   ]
+  },
+  {
+   "source": "a = 1\nb = 2",
+   "cell_type": "code"
+  }
+ ],
+ "metadata": {},
+ "nbformat": 4
+}
+"##;
+
+    fn locate(cell: usize, line: usize) -> Result<NotebookLineLocation, ToggleCommentError> {
+        locate_notebook_source_line(std::io::Cursor::new(NOTEBOOK.as_bytes()), cell, line)
+    }
+
+    #[test]
+    fn test_locate_source_lines() {
+        let first = locate(1, 0).unwrap();
+        assert!(!first.has_tag);
+        assert!(NOTEBOOK[first.line_start_pos as usize..].starts_with("import os"));
+
+        assert!(locate(1, 1).unwrap().has_tag);
+
+        // Single-string source, split on \n escapes; keys in any order
+        let second = locate(2, 1).unwrap();
+        assert!(NOTEBOOK[second.line_start_pos as usize..].starts_with("b = 2"));
+    }
+
+    #[test]
+    fn test_locate_errors() {
+        assert_eq!(
+            locate(0, 0),
+            Err(ToggleCommentError::NotCodeCell { cell: 0 })
+        );
+        assert_eq!(
+            locate(5, 0),
+            Err(ToggleCommentError::CellNotFound {
+                requested: 5,
+                cells: 3
+            })
+        );
+        assert_eq!(
+            locate(1, 3),
+            Err(ToggleCommentError::LineNotFound {
+                requested: 3,
+                file_lines: 3
+            })
+        );
+        let not_notebook = locate_notebook_source_line(std::io::Cursor::new(b"[1, 2]"), 0, 0);
+        assert_eq!(not_notebook, Err(ToggleCommentError::InvalidNotebook));
+        let truncated = locate_notebook_source_line(std::io::Cursor::new(b"{\"cells\": [{"), 0, 0);
+        assert_eq!(truncated, Err(ToggleCommentError::InvalidNotebook));
+    }
+
+    #[test]
+    fn test_notebook_toggle_roundtrip() {
+        let test_file = create_test_file("test_notebook_toggle.ipynb", NOTEBOOK);
+        let path = test_file.to_str().unwrap();
+
+        assert!(toggle_notebook_cell_line_bytewise(path, 1, 0).is_ok());
+        assert!(toggle_notebook_cell_line_bytewise(path, 1, 1).is_ok());
+        let toggled = read_file_content(&test_file);
+        assert!(toggled.contains("\"# import os\\n\",\n    \"x = 1\\n\","));
+        assert_eq!(toggled.len(), NOTEBOOK.len());
+
+        assert!(toggle_notebook_cell_line_bytewise(path, 1, 0).is_ok());
+        assert!(toggle_notebook_cell_line_bytewise(path, 1, 1).is_ok());
+        assert_eq!(read_file_content(&test_file), NOTEBOOK);
+
+        // Markdown cell refused, file untouched
+        assert_eq!(
+            toggle_notebook_cell_line_bytewise(path, 0, 0),
+            Err(ToggleCommentError::NotCodeCell { cell: 0 })
+        );
+        assert_eq!(read_file_content(&test_file), NOTEBOOK);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_notebook_toggle.ipynb"),
+        ]);
+    }
+}
+
+// ============================================================================
+// PHASE 6: LINE LOOKUP BY CONTENT
+// ============================================================================
+//
+// ## Project Context
+// Scripts usually find a line with grep, then pass its number here. Between
+// the two steps the file can change, and grep counts lines from 1 while this
+// crate counts from 0. A content lookup resolves the line number in the same
+// process, right before the toggle.
+//
+// ## Approach
+// One streaming pass with a Knuth-Morris-Pratt matcher: the failure table is
+// a fixed array sized for MAX_SEARCH_TEXT_BYTES, the match state resets at
+// every `\n`, so a match never spans lines. The search text is a plain byte
+// literal (no patterns, no case folding). Lines are counted per line, not per
+// match: a line containing the text twice is one occurrence.
+//
+// `last_line_index()` is the same kind of pass, counting line breaks: it
+// resolves the CLI's `$` address (last line) right before the operation.
+// ============================================================================
+
+/// Longest search text accepted by `find_line_containing()`
+pub const MAX_SEARCH_TEXT_BYTES: usize = 256;
+
+/// Find the zero-indexed line number of a line containing `needle`
+///
+/// # Arguments
+/// * `reader` - Input positioned at the start of line 0
+/// * `needle` - Literal bytes (1..=MAX_SEARCH_TEXT_BYTES, already checked)
+/// * `occurrence` - Which matching line to return (0 = first)
+///
+/// # Returns
+/// * `Ok(line)` - Line number of the requested matching line
+/// * `Err(TextNotFound)` - Input ended first
+/// * `Err(ToggleCommentError)` - Read failed
+fn find_line_containing_in<R: Read>(
+    reader: &mut R,
+    needle: &[u8],
+    occurrence: usize,
+) -> Result<usize, ToggleCommentError> {
+    let needle_len = needle.len();
+
+    // Failure table: length of the longest proper prefix that is also a suffix
+    let mut failure = [0usize; MAX_SEARCH_TEXT_BYTES];
+    let mut prefix_len: usize = 0;
+    for index in 1..needle_len {
+        while prefix_len > 0 && needle[index] != needle[prefix_len] {
+            prefix_len = failure[prefix_len - 1];
+        }
+        if needle[index] == needle[prefix_len] {
+            prefix_len += 1;
+        }
+        failure[index] = prefix_len;
+    }
+
+    let mut current_line: usize = 0;
+    let mut matched: usize = 0;
+    let mut line_done = false;
+    let mut matches: usize = 0;
+    let mut byte_count: u64 = 0;
+
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if byte == b'\n' {
+            current_line += 1;
+            matched = 0;
+            line_done = false;
+            continue;
+        }
+
+        // Line already counted: skip to its end
+        if line_done {
+            continue;
+        }
+
+        while matched > 0 && byte != needle[matched] {
+            matched = failure[matched - 1];
+        }
+        if byte == needle[matched] {
+            matched += 1;
+        }
+
+        if matched == needle_len {
+            if matches == occurrence {
+                return Ok(current_line);
+            }
+            matches += 1;
+            matched = 0;
+            line_done = true;
+        }
+    }
+
+    Err(ToggleCommentError::TextNotFound {
+        occurrence,
+        matches,
+    })
+}
+
+/// Find the line containing a literal text, for content-anchored toggles
+///
+/// # Overview
+/// Streams the file once and returns the zero-indexed number of the
+/// `occurrence`-th line (0 = first) that contains `needle`. The result can be
+/// passed straight to any line operation, e.g.
+/// `toggle_basic_singleline_comment_bytewise()`. The file is not modified.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `needle` - Literal text, 1..=MAX_SEARCH_TEXT_BYTES bytes, matched within a line
+/// * `occurrence` - Which matching line to return (zero-indexed)
+///
+/// # Returns
+/// * `Ok(line)` - Zero-indexed line number
+/// * `Err(TextNotFound)` - Fewer than `occurrence + 1` lines contain the text
+/// * `Err(InvalidSearchText)` - Empty or over-long search text
+/// * `Err(ToggleCommentError)` - Path, permission or I/O failure
+///
+/// # Example
+/// ```no_run
+/// let line = find_line_containing("./src/main.rs", "debug_dump(", 0)?;
+/// toggle_basic_singleline_comment_bytewise("./src/main.rs", line)?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn find_line_containing(
+    file_path: &str,
+    needle: &str,
+    occurrence: usize,
+) -> Result<usize, ToggleCommentError> {
+    if needle.is_empty() || needle.len() > MAX_SEARCH_TEXT_BYTES {
+        return Err(ToggleCommentError::InvalidSearchText {
+            len: needle.len(),
+            max: MAX_SEARCH_TEXT_BYTES,
+        });
+    }
+
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    find_line_containing_in(&mut reader, needle.as_bytes(), occurrence)
+}
+
+/// Count line breaks to find the last line (see `last_line_index()`)
+fn last_line_index_in<R: Read>(reader: &mut R) -> Result<usize, ToggleCommentError> {
+    let mut newlines: usize = 0;
+    let mut ends_with_newline = false;
+    let mut byte_count: u64 = 0;
+
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        ends_with_newline = byte == b'\n';
+        if ends_with_newline {
+            newlines += 1;
+        }
+    }
+
+    // A final newline ends the last line; it does not start another one
+    if ends_with_newline {
+        Ok(newlines - 1)
+    } else {
+        Ok(newlines)
+    }
+}
+
+/// Zero-indexed number of the last line (what `$` means on the command line)
+///
+/// The empty position after a final newline is not a line here: for
+/// `"a\nb\n"` the last line is 1. An empty file has line 0 only.
+///
+/// # Returns
+/// * `Ok(line)` - Last line
+/// * `Err(FileNotFound)` / `Err(PermissionDenied)` / `Err(PathError)` - Cannot open
+/// * `Err(IoError)` - Read failed
+#[cfg(feature = "fs")]
+pub fn last_line_index(file_path: &str) -> Result<usize, ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    last_line_index_in(&mut reader)
+}
+
+// ============================================================================
+// TESTS - PHASE 6: LINE LOOKUP BY CONTENT
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod line_lookup_tests {
+    use super::*;
+
+    fn lookup(content: &str, needle: &str, occurrence: usize) -> Result<usize, ToggleCommentError> {
+        find_line_containing_in(
+            &mut std::io::Cursor::new(content.as_bytes()),
+            needle.as_bytes(),
+            occurrence,
+        )
+    }
+
+    #[test]
+    fn test_lookup_by_content() {
+        let content = "fn main() {\n    aab(); aab();\n    aaab();\n}\nxaab";
+        assert_eq!(lookup(content, "aab", 0), Ok(1));
+        // Partial-match fallback ("aaab" restarts inside the prefix)
+        assert_eq!(lookup(content, "aab", 1), Ok(2));
+        assert_eq!(lookup(content, "aab", 2), Ok(4));
+        assert_eq!(
+            lookup(content, "aab", 3),
+            Err(ToggleCommentError::TextNotFound {
+                occurrence: 3,
+                matches: 3
+            })
+        );
+        // Matches never span a newline
+        assert!(lookup(content, "{    aab", 0).is_err());
+        assert_eq!(lookup("a\r\nb\r\n", "b", 0), Ok(1));
+    }
+
+    #[test]
+    fn test_last_line_index() {
+        let last = |content: &str| last_line_index_in(&mut content.as_bytes()).unwrap();
+        assert_eq!(last(""), 0);
+        assert_eq!(last("a"), 0);
+        assert_eq!(last("a\n"), 0);
+        assert_eq!(last("a\r\nb"), 1);
+        assert_eq!(last("a\nb\n\n"), 2);
+        assert_eq!(
+            last_line_index("missing_last_line.py"),
+            Err(ToggleCommentError::FileNotFound)
+        );
+    }
+
+    #[test]
+    fn test_find_line_containing_then_toggle() {
+        let test_file = create_test_file("test_line_lookup.py", "a = 1\nb = 2\nb = 3\n");
+        let path = test_file.to_str().unwrap();
+
+        let line = find_line_containing(path, "b =", 1).unwrap();
+        assert_eq!(line, 2);
+        assert!(toggle_basic_singleline_comment_bytewise(path, line).is_ok());
+        assert_eq!(read_file_content(&test_file), "a = 1\nb = 2\n# b = 3\n");
+
+        assert_eq!(
+            find_line_containing(path, "", 0),
+            Err(ToggleCommentError::InvalidSearchText {
+                len: 0,
+                max: MAX_SEARCH_TEXT_BYTES
+            })
+        );
+        assert_eq!(
+            find_line_containing("missing_line_lookup.py", "a", 0),
+            Err(ToggleCommentError::FileNotFound)
+        );
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_line_lookup.py"),
+        ]);
+    }
+}
+
+// ============================================================================
+// PHASE 7: BRACE-DELIMITED BLOCKS (FUNCTION / STRUCT SPANS)
+// ============================================================================
+//
+// ## Project Context
+// Disabling a whole function means finding its closing brace by eye and
+// passing both line numbers to block mode. Brace-block mode takes only the
+// first line (`fn ... {`, `struct ... {`, or a signature whose `{` follows on
+// a later line) and finds the end itself.
+//
+// ## Approach
+// `BraceBlockScanner` is fed the file from the start line on, one byte at a
+// time. It tracks brace depth and skips braces inside comments and string /
+// char literals; the block ends on the line where depth returns to zero. A
+// `;` outside parentheses before the first `{` means a declaration without a
+// body (`fn f();`, `struct Unit;`): no block. Depth is bounded by
+// MAX_BRACE_DEPTH, the scan by MAX_BYTE_ITERATIONS.
+//
+// The span is then handed to the existing operations: block markers where the
+// language has them (`/* */`), otherwise a per-line `// ` range toggle (Zig).
+// Toggling again from the same line undoes it: a start line holding the
+// opening marker is matched with its closing marker line; a `// ` commented
+// span is rescanned with the `// ` prefixes ignored.
+//
+// Only the lexical basics are known: Rust raw strings (`r#"..."#`) and
+// preprocessor tricks can hide or add braces.
+// ============================================================================
+
+/// Deepest brace nesting followed by the brace scanner
+const MAX_BRACE_DEPTH: usize = 1024;
+
+/// Lexical state of `BraceBlockScanner`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BraceLexState {
+    /// Plain code: braces count
+    Code,
+
+    /// `/` seen in code (comment may start)
+    Slash,
+
+    /// Inside `// ...` up to the newline
+    LineComment,
+
+    /// Inside `/* ... */`
+    BlockComment,
+
+    /// `*` seen inside a block comment (comment may end)
+    BlockCommentStar,
+
+    /// Inside a string delimited by the given quote byte
+    StringLiteral(u8),
+
+    /// Backslash seen inside a string (next byte is escaped)
+    StringEscape(u8),
+
+    /// Rust: `'` seen in code (char literal or lifetime)
+    CharOpen,
+
+    /// Rust: one byte after `'`; a closing `'` next makes it a char literal
+    CharBody(u8),
+
+    /// Rust: `'\` seen (escaped char literal)
+    CharEscape,
+
+    /// Rust: rest of an escaped char literal (`'\u{7B}'`) up to the closing `'`
+    CharTail,
+}
+
+/// Result of feeding one byte to `BraceBlockScanner`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BraceStep {
+    /// Block not finished yet
+    Continue,
+
+    /// Closing brace of the outermost block
+    End,
+
+    /// No block here (body-less declaration, stray `}`, or too deep)
+    NoBlock,
+}
+
+/// Byte-fed brace depth tracker for one block
+#[derive(Debug)]
+struct BraceBlockScanner {
+    state: BraceLexState,
+
+    /// Current `{` nesting
+    depth: usize,
+
+    /// Current `(` nesting (a `;` inside `for (;;)` is not a declaration end)
+    paren_depth: usize,
+
+    /// At least one `{` seen
+    opened: bool,
+
+    /// `'` starts a char literal or lifetime (Rust), not a string
+    rust_chars: bool,
+}
+
+impl BraceBlockScanner {
+    fn new(rust_chars: bool) -> Self {
+        BraceBlockScanner {
+            state: BraceLexState::Code,
+            depth: 0,
+            paren_depth: 0,
+            opened: false,
+            rust_chars,
+        }
+    }
+
+    /// Feed one byte
+    fn push_byte(&mut self, byte: u8) -> BraceStep {
+        match self.state {
+            BraceLexState::Code => self.code_byte(byte),
+            BraceLexState::Slash => match byte {
+                b'/' => {
+                    self.state = BraceLexState::LineComment;
+                    BraceStep::Continue
+                }
+                b'*' => {
+                    self.state = BraceLexState::BlockComment;
+                    BraceStep::Continue
+                }
+                _ => {
+                    self.state = BraceLexState::Code;
+                    self.code_byte(byte)
+                }
+            },
+            BraceLexState::LineComment => {
+                if byte == b'\n' {
+                    self.state = BraceLexState::Code;
+                }
+                BraceStep::Continue
+            }
+            BraceLexState::BlockComment => {
+                if byte == b'*' {
+                    self.state = BraceLexState::BlockCommentStar;
+                }
+                BraceStep::Continue
+            }
+            BraceLexState::BlockCommentStar => {
+                self.state = match byte {
+                    b'/' => BraceLexState::Code,
+                    b'*' => BraceLexState::BlockCommentStar,
+                    _ => BraceLexState::BlockComment,
+                };
+                BraceStep::Continue
+            }
+            BraceLexState::StringLiteral(quote) => {
+                if byte == b'\\' {
+                    self.state = BraceLexState::StringEscape(quote);
+                } else if byte == quote {
+                    self.state = BraceLexState::Code;
+                }
+                BraceStep::Continue
+            }
+            BraceLexState::StringEscape(quote) => {
+                self.state = BraceLexState::StringLiteral(quote);
+                BraceStep::Continue
+            }
+            BraceLexState::CharOpen => {
+                self.state = match byte {
+                    b'\\' => BraceLexState::CharEscape,
+                    b'\n' => BraceLexState::Code,
+                    _ => BraceLexState::CharBody(byte),
+                };
+                BraceStep::Continue
+            }
+            BraceLexState::CharBody(held) => {
+                self.state = BraceLexState::Code;
+                if byte == b'\'' {
+                    // `'x'`: a char literal, `x` is not code
+                    return BraceStep::Continue;
+                }
+                // Lifetime or label (`'a`): both bytes are code
+                match self.code_byte(held) {
+                    BraceStep::Continue => self.push_byte(byte),
+                    step => step,
+                }
+            }
+            BraceLexState::CharEscape => {
+                self.state = BraceLexState::CharTail;
+                BraceStep::Continue
+            }
+            BraceLexState::CharTail => {
+                if byte == b'\'' || byte == b'\n' {
+                    self.state = BraceLexState::Code;
+                }
+                BraceStep::Continue
+            }
+        }
+    }
+
+    /// Handle one byte in the `Code` state
+    fn code_byte(&mut self, byte: u8) -> BraceStep {
+        match byte {
+            b'/' => self.state = BraceLexState::Slash,
+            b'"' | b'`' => self.state = BraceLexState::StringLiteral(byte),
+            b'\'' => {
+                self.state = if self.rust_chars {
+                    BraceLexState::CharOpen
+                } else {
+                    BraceLexState::StringLiteral(b'\'')
+                };
+            }
+            b'(' => self.paren_depth = self.paren_depth.saturating_add(1),
+            b')' => self.paren_depth = self.paren_depth.saturating_sub(1),
+            b'{' => {
+                if self.depth >= MAX_BRACE_DEPTH {
+                    return BraceStep::NoBlock;
+                }
+                self.depth += 1;
+                self.opened = true;
+            }
+            b'}' => {
+                if self.depth == 0 {
+                    return BraceStep::NoBlock;
+                }
+                self.depth -= 1;
+                if self.depth == 0 {
+                    return BraceStep::End;
+                }
+            }
+            b';' if !self.opened && self.paren_depth == 0 => return BraceStep::NoBlock,
+            _ => {}
+        }
+        BraceStep::Continue
+    }
+}
+
+/// Find the last line of the brace block starting at `start_line`
+///
+/// # Arguments
+/// * `reader` - Input positioned at the start of line 0
+/// * `start_line` - Zero-indexed first line of the block
+/// * `rust_chars` - Treat `'` as Rust char literal / lifetime
+/// * `ignore_prefix` - Bytes skipped at the start of each scanned line when
+///   present (`b"// "` to rescan a commented-out span, `b""` for none)
+///
+/// # Returns
+/// * `Ok(line)` - Zero-indexed line holding the closing brace
+/// * `Err(LineNotFound)` - Input has fewer lines than `start_line`
+/// * `Err(BlockEndNotFound)` - No complete block from `start_line`
+/// * `Err(ToggleCommentError)` - Read failed
+fn find_brace_block_end_in<R: Read>(
+    reader: &mut R,
+    start_line: usize,
+    rust_chars: bool,
+    ignore_prefix: &[u8],
+) -> Result<usize, ToggleCommentError> {
+    let mut current_line: usize = 0;
+    let mut byte_count: u64 = 0;
+
+    // Skip to the start line
+    while current_line < start_line {
+        let byte = match read_single_byte(reader)? {
+            Some(b) => b,
+            None => {
+                return Err(ToggleCommentError::LineNotFound {
+                    requested: start_line,
+                    file_lines: current_line + 1,
+                });
+            }
+        };
+
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if byte == b'\n' {
+            current_line += 1;
+        }
+    }
+
+    let mut scanner = BraceBlockScanner::new(rust_chars);
+
+    // Bytes of `ignore_prefix` matched at the current line start (None: past it)
+    let mut prefix_matched: Option<usize> = if ignore_prefix.is_empty() {
+        None
+    } else {
+        Some(0)
+    };
+
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if let Some(matched) = prefix_matched {
+            if byte == ignore_prefix[matched] {
+                prefix_matched = if matched + 1 < ignore_prefix.len() {
+                    Some(matched + 1)
+                } else {
+                    None
+                };
+                continue;
+            }
+
+            // Partial prefix: those bytes were code after all
+            prefix_matched = None;
+            for &held in &ignore_prefix[..matched] {
+                match scanner.push_byte(held) {
+                    BraceStep::Continue => {}
+                    BraceStep::End => return Ok(current_line),
+                    BraceStep::NoBlock => {
+                        return Err(ToggleCommentError::BlockEndNotFound { start: start_line });
+                    }
+                }
+            }
+        }
+
+        match scanner.push_byte(byte) {
+            BraceStep::Continue => {}
+            BraceStep::End => return Ok(current_line),
+            BraceStep::NoBlock => {
+                return Err(ToggleCommentError::BlockEndNotFound { start: start_line });
+            }
+        }
+
+        if byte == b'\n' {
+            current_line += 1;
+            if !ignore_prefix.is_empty() {
+                prefix_matched = Some(0);
+            }
+        }
+    }
+
+    Err(ToggleCommentError::BlockEndNotFound { start: start_line })
+}
+
+/// Find the first line after `from_line` that is exactly `marker`
+///
+/// `marker` excludes indent and line ending (e.g. `b"*/"`); lines match as
+/// in `detect_marker_line()`.
+///
+/// # Returns
+/// * `Ok(Some(line))` - Zero-indexed marker line
+/// * `Ok(None)` - No such line
+/// * `Err(ToggleCommentError)` - Read failed
+fn find_marker_line_after<R: Read>(
+    reader: &mut R,
+    from_line: usize,
+    marker: &[u8],
+) -> Result<Option<usize>, ToggleCommentError> {
+    let mut current_line: usize = 0;
+    let mut byte_count: u64 = 0;
+    let mut state = MarkerLineMatch::LINE_START;
+
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if byte == b'\n' {
+            if current_line > from_line && state.is_marker(marker) {
+                return Ok(Some(current_line));
+            }
+            current_line += 1;
+            state = MarkerLineMatch::LINE_START;
+        } else {
+            state.feed(marker, byte);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find the last line of the `{ ... }` block starting at a line
+///
+/// # Overview
+/// Scans forward from `start_line` tracking brace depth, skipping braces in
+/// comments and string / char literals, and returns the line where the first
+/// block opened on or after `start_line` closes. The file is not modified.
+///
+/// # Arguments
+/// * `file_path` - Path to a C-like source file (the `//` languages)
+/// * `start_line` - Zero-indexed first line (e.g. the `fn` signature)
+///
+/// # Returns
+/// * `Ok(line)` - Zero-indexed line holding the closing brace
+/// * `Err(BlockEndNotFound)` - Body-less declaration, unbalanced braces or EOF
+/// * `Err(LineNotFound)` - File has fewer lines
+/// * `Err(ToggleCommentError)` - Path, permission or I/O failure
+#[cfg(feature = "fs")]
+pub fn find_brace_block_end(
+    file_path: &str,
+    start_line: usize,
+) -> Result<usize, ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let rust_chars = absolute_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("rs"));
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    find_brace_block_end_in(&mut reader, start_line, rust_chars, b"")
+}
+
+/// Toggle a comment over the whole brace block starting at a line
+///
+/// # Overview
+/// Finds the block end (see `find_brace_block_end()`) and toggles the span:
+/// - Languages with block markers: `toggle_block_comment_bytewise()` adds
+///   `/*` / `*/` around the span. If `start_line` already is the opening
+///   marker, the span up to the matching closing marker is uncommented.
+/// - Zig (no block comments): `toggle_range_basic_comments_bytewise()` on
+///   each line. If `start_line` is commented, the span is found with the
+///   `// ` prefixes ignored and uncommented.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `start_line` - Zero-indexed first line of the block
+///
+/// # Returns
+/// * `Ok(Some(end_line))` - Span `start_line..=end_line` toggled (as found
+///   before the edit)
+/// * `Ok(None)` - Extension has no brace blocks (not a `//` language) - no-op
+/// * `Err(BlockEndNotFound)` - No complete block from `start_line` (no backup made)
+/// * `Err(ToggleCommentError)` - As for the block / range operations
+///
+/// # Example
+/// ```no_run
+/// // Line 40 is `fn legacy_parse(input: &str) -> Result<Ast, Error> {`
+/// let end = toggle_brace_block_bytewise("./src/parser.rs", 40)?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_brace_block_bytewise(
+    file_path: &str,
+    start_line: usize,
+) -> Result<Option<usize>, ToggleCommentError> {
+    toggle_brace_block_bytewise_with_options(file_path, start_line, &ToggleOptions::default())
+}
+
+/// Same as `toggle_brace_block_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_brace_block_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    options: &ToggleOptions,
+) -> Result<Option<usize>, ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    let extension = match target_extension(&absolute_path, options) {
+        Some(ext) => ext,
+        None => return Err(ToggleCommentError::NoExtension),
+    };
+
+    if is_json_without_comments(&extension) {
+        return Err(ToggleCommentError::JsonNoComments);
+    }
+
+    // Brace blocks only for the `//` (C-like) languages
+    if determine_comment_flag(&extension) != Some(CommentFlag::DoubleSlash) {
+        return Ok(None); // Unsupported extension - no-op
+    }
+    let rust_chars = extension.eq_ignore_ascii_case("rs");
+
+    let open_source = |path: &Path| match File::open(path) {
+        Ok(f) => Ok(BufReader::with_capacity(IO_BUFFER_SIZE, f)),
+        Err(e) => Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    match determine_block_markers(&extension) {
+        Some(markers) => {
+            let end_line = if detect_marker_line(file_path, start_line, markers.start)? {
+                // Already wrapped: undo up to the closing marker
+                match find_marker_line_after(
+                    &mut open_source(&absolute_path)?,
+                    start_line,
+                    markers.end,
+                )? {
+                    Some(line) => line,
+                    None => return Err(ToggleCommentError::BlockEndNotFound { start: start_line }),
+                }
+            } else {
+                find_brace_block_end_in(
+                    &mut open_source(&absolute_path)?,
+                    start_line,
+                    rust_chars,
+                    b"",
+                )?
+            };
+
+            toggle_block_comment_bytewise_with_options(file_path, start_line, end_line, options)?;
+            Ok(Some(end_line))
+        }
+        None => {
+            // Commented span: find its end as if the `// ` prefixes were absent
+            let commented =
+                match find_and_detect_tag_state(file_path, start_line, CommentFlag::DoubleSlash)? {
+                    Some((_, has_tag)) => has_tag,
+                    None => {
+                        return Err(ToggleCommentError::LineNotFound {
+                            requested: start_line,
+                            file_lines: 0,
+                        });
+                    }
+                };
+            let ignore_prefix: &[u8] = if commented { b"// " } else { b"" };
+
+            let end_line = find_brace_block_end_in(
+                &mut open_source(&absolute_path)?,
+                start_line,
+                rust_chars,
+                ignore_prefix,
+            )?;
+
+            toggle_range_basic_comments_bytewise_with_options(
+                file_path, start_line, end_line, options,
+            )?;
+            Ok(Some(end_line))
+        }
+    }
+}
+
+// ============================================================================
+// TESTS - PHASE 7: BRACE-DELIMITED BLOCKS
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod brace_block_tests {
+    use super::*;
+
+    fn block_end(
+        content: &str,
+        start: usize,
+        rust_chars: bool,
+    ) -> Result<usize, ToggleCommentError> {
+        find_brace_block_end_in(
+            &mut std::io::Cursor::new(content.as_bytes()),
+            start,
+            rust_chars,
+            b"",
+        )
+    }
+
+    #[test]
+    fn test_brace_block_end() {
+        let rust = "use x;\n\nfn parse<'a>(s: &'a str) -> char\n{\n    let open = '{';\n    // }\n    let text = \"}\\\"}\";\n    /* } */ if s.is_empty() { '\\u{7D}' } else { '}' }\n}\nfn next() {}\n";
+        assert_eq!(block_end(rust, 2, true), Ok(8));
+        assert_eq!(block_end(rust, 9, true), Ok(9));
+        // `use x;` has no body
+        assert_eq!(
+            block_end(rust, 0, true),
+            Err(ToggleCommentError::BlockEndNotFound { start: 0 })
+        );
+        assert_eq!(
+            block_end(rust, 20, true),
+            Err(ToggleCommentError::LineNotFound {
+                requested: 20,
+                file_lines: 11
+            })
+        );
+
+        // C-like: `;` inside parentheses, '{' and '}' as quoted literals
+        let c = "for (i = 0;; i++) {\n  s = '{';\n}\nint f(void);\n";
+        assert_eq!(block_end(c, 0, false), Ok(2));
+        assert!(block_end(c, 3, false).is_err());
+        // Truncated block
+        assert!(block_end("fn f() {\n  {\n}\n", 0, true).is_err());
+    }
+
+    #[test]
+    fn test_brace_block_ignores_comment_prefix() {
+        let commented = "// fn f() {\n//     g();\n// }\n// fn h() {}\n";
+        let end = find_brace_block_end_in(
+            &mut std::io::Cursor::new(commented.as_bytes()),
+            0,
+            false,
+            b"// ",
+        );
+        assert_eq!(end, Ok(2));
+    }
+
+    #[test]
+    fn test_toggle_brace_block_roundtrip() {
+        let original = "fn keep() {}\n\nfn legacy(x: u8) -> u8 {\n    if x > 0 { x } else { 0 }\n}\n\nfn tail() {}\n";
+        let test_file = create_test_file("test_brace_block.rs", original);
+        let path = test_file.to_str().unwrap();
+
+        assert_eq!(toggle_brace_block_bytewise(path, 2), Ok(Some(4)));
+        assert_eq!(
+            read_file_content(&test_file),
+            "fn keep() {}\n\n/*\nfn legacy(x: u8) -> u8 {\n    if x > 0 { x } else { 0 }\n}\n*/\n\nfn tail() {}\n"
+        );
+
+        assert_eq!(toggle_brace_block_bytewise(path, 2), Ok(Some(6)));
+        assert_eq!(read_file_content(&test_file), original);
+
+        // Zig: per-line `// `, undone by toggling the same line again
+        let zig_original = "fn add(a: i32) i32 {\n    return a;\n}\n";
+        let zig_file = create_test_file("test_brace_block.zig", zig_original);
+        let zig_path = zig_file.to_str().unwrap();
+
+        assert_eq!(toggle_brace_block_bytewise(zig_path, 0), Ok(Some(2)));
+        assert_eq!(
+            read_file_content(&zig_file),
+            "// fn add(a: i32) i32 {\n//     return a;\n// }\n"
+        );
+        assert_eq!(toggle_brace_block_bytewise(zig_path, 0), Ok(Some(2)));
+        assert_eq!(read_file_content(&zig_file), zig_original);
+
+        cleanup_files(&[
+            &test_file,
+            &zig_file,
+            &PathBuf::from("backup_toggle_comment_test_brace_block.rs"),
+            &PathBuf::from("backup_toggle_comment_test_brace_block.zig"),
+        ]);
+    }
+}
+
+// ============================================================================
+// PHASE 8: PYTHON SUITES (INDENTATION SCANNER)
+// ============================================================================
+//
+// ## Project Context
+// Python has no braces: a `def` / `class` (or `if`, `for`, ...) owns the
+// lines below it that are indented deeper than its own line. Suite mode takes
+// that first line and comments it and its whole suite in one operation.
+//
+// ## Approach
+// `PythonLineScanner` follows just enough lexical state to know which
+// physical lines start a logical line: strings (including triple-quoted,
+// which may span lines at any indentation), `#` comments, bracket depth and
+// backslash continuations. The suite ends before the first logical line that
+// is code (not blank, not a comment) at the header's indentation or less;
+// trailing blank and shallower comment lines are not part of it. Tabs count
+// to the next multiple of 8, as in the Python tokenizer.
+//
+// The span is commented with the line engine in `Add` direction with the flag
+// after each line's leading spaces: every non-blank line gets exactly one
+// `# `, so comment lines inside the suite become `# # note`. Toggling again
+// from the same line (now `# def ...`) scans with that `# ` ignored, finds
+// the same span, and removes exactly one `# ` per line (`Remove` direction).
+// ============================================================================
+
+/// Lexical state of `PythonLineScanner`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PythonLexState {
+    /// Plain code
+    Code,
+
+    /// Inside `# ...` up to the newline
+    Comment,
+
+    /// `n` (1 or 2) quote bytes seen in code: string, empty string or triple quote
+    QuoteRun(u8, u8),
+
+    /// Inside a single-line string
+    ShortString(u8),
+
+    /// Backslash seen inside a single-line string
+    ShortStringEscape(u8),
+
+    /// Inside a triple-quoted string; count of closing quotes seen so far
+    LongString(u8, u8),
+
+    /// Backslash seen inside a triple-quoted string
+    LongStringEscape(u8),
+}
+
+/// Byte-fed Python tokenizer state (only what line structure needs)
+#[derive(Debug)]
+struct PythonLineScanner {
+    state: PythonLexState,
+
+    /// Open `(`, `[`, `{` (lines inside continue the logical line)
+    bracket_depth: usize,
+
+    /// Last code byte on the line was a backslash
+    backslash_pending: bool,
+
+    /// The physical line just ended continues the logical line
+    continues: bool,
+}
+
+impl PythonLineScanner {
+    fn new() -> Self {
+        PythonLineScanner {
+            state: PythonLexState::Code,
+            bracket_depth: 0,
+            backslash_pending: false,
+            continues: false,
+        }
+    }
+
+    /// Next physical line starts a new logical line
+    fn at_logical_start(&self) -> bool {
+        self.state == PythonLexState::Code && self.bracket_depth == 0 && !self.continues
+    }
+
+    /// Feed one byte
+    fn push_byte(&mut self, byte: u8) {
+        if byte == b'\n' {
+            self.continues = self.state == PythonLexState::Code && self.backslash_pending;
+            self.backslash_pending = false;
+        }
+
+        match self.state {
+            PythonLexState::Code => self.code_byte(byte),
+            PythonLexState::Comment => {
+                if byte == b'\n' {
+                    self.state = PythonLexState::Code;
+                }
+            }
+            PythonLexState::QuoteRun(quote, count) => {
+                if byte == quote {
+                    self.state = if count == 1 {
+                        PythonLexState::QuoteRun(quote, 2)
+                    } else {
+                        PythonLexState::LongString(quote, 0)
+                    };
+                } else if count == 1 {
+                    self.state = PythonLexState::ShortString(quote);
+                    self.push_byte(byte);
+                } else {
+                    // `""`: empty string, back to code
+                    self.state = PythonLexState::Code;
+                    self.push_byte(byte);
+                }
+            }
+            PythonLexState::ShortString(quote) => {
+                if byte == b'\\' {
+                    self.state = PythonLexState::ShortStringEscape(quote);
+                } else if byte == quote || byte == b'\n' {
+                    self.state = PythonLexState::Code;
+                }
+            }
+            PythonLexState::ShortStringEscape(quote) => {
+                self.state = PythonLexState::ShortString(quote);
+            }
+            PythonLexState::LongString(quote, closing) => {
+                self.state = if byte == b'\\' {
+                    PythonLexState::LongStringEscape(quote)
+                } else if byte != quote {
+                    PythonLexState::LongString(quote, 0)
+                } else if closing == 2 {
+                    PythonLexState::Code
+                } else {
+                    PythonLexState::LongString(quote, closing + 1)
+                };
+            }
+            PythonLexState::LongStringEscape(quote) => {
+                self.state = PythonLexState::LongString(quote, 0);
+            }
+        }
+    }
+
+    /// Handle one byte in the `Code` state
+    fn code_byte(&mut self, byte: u8) {
+        match byte {
+            b'#' => self.state = PythonLexState::Comment,
+            b'"' | b'\'' => self.state = PythonLexState::QuoteRun(byte, 1),
+            b'(' | b'[' | b'{' => self.bracket_depth = self.bracket_depth.saturating_add(1),
+            b')' | b']' | b'}' => self.bracket_depth = self.bracket_depth.saturating_sub(1),
+            _ => {}
+        }
+        if byte != b'\r' && byte != b'\n' {
+            self.backslash_pending = byte == b'\\';
+        }
+    }
+}
+
+/// How a physical line starts (after its indentation)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PythonLineKind {
+    /// Only whitespace
+    Blank,
+
+    /// First non-whitespace byte is `#`
+    Comment,
+
+    /// Anything else
+    Code,
+}
+
+/// Position within the head of a physical line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PythonLineHead {
+    /// Leading spaces (where an ignored `# ` can appear)
+    Spaces,
+
+    /// `#` seen at the end of the leading spaces
+    Hash,
+
+    /// Remaining indentation (spaces and tabs)
+    Indent,
+
+    /// First content byte seen
+    Content,
+}
+
+/// Find the last line of the suite headed by `start_line`
+///
+/// # Arguments
+/// * `reader` - Input positioned at the start of line 0
+/// * `start_line` - Zero-indexed header line (`def`, `class`, `if`, ...)
+///
+/// # Returns
+/// * `Ok((end_line, commented))` - Last line of the suite; `commented` when
+///   the header starts with `# ` after its leading spaces (the span was then
+///   scanned with one such `# ` per line ignored)
+/// * `Err(LineNotFound)` - Input has fewer lines than `start_line`
+/// * `Err(BlockEndNotFound)` - Header is blank, a comment, or not a logical line start
+/// * `Err(ToggleCommentError)` - Read failed
+fn find_python_suite_end_in<R: Read>(
+    reader: &mut R,
+    start_line: usize,
+) -> Result<(usize, bool), ToggleCommentError> {
+    let mut scanner = PythonLineScanner::new();
+    let mut current_line: usize = 0;
+    let mut byte_count: u64 = 0;
+
+    // Lines before the header still matter: they may open strings or brackets
+    while current_line < start_line {
+        let byte = match read_single_byte(reader)? {
+            Some(b) => b,
+            None => {
+                return Err(ToggleCommentError::LineNotFound {
+                    requested: start_line,
+                    file_lines: current_line + 1,
+                });
+            }
+        };
+
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        scanner.push_byte(byte);
+        if byte == b'\n' {
+            current_line += 1;
+        }
+    }
+
+    if !scanner.at_logical_start() {
+        return Err(ToggleCommentError::BlockEndNotFound { start: start_line });
+    }
+
+    let mut header_seen = false;
+    let mut header_indent: usize = 0;
+    let mut commented = false;
+    let mut last_line = start_line;
+
+    // State of the current physical line
+    let mut head = PythonLineHead::Spaces;
+    let mut indent: usize = 0;
+    let mut logical_start = true;
+    let mut line_in_span = true;
+    let mut line_has_content = false;
+
+    loop {
+        let byte = match read_single_byte(reader)? {
+            Some(b) => b,
+            None => {
+                if !header_seen {
+                    return Err(ToggleCommentError::BlockEndNotFound { start: start_line });
+                }
+                if line_in_span && line_has_content {
+                    last_line = current_line;
+                }
+                return Ok((last_line, commented));
+            }
+        };
+
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        // Line head: measure indentation, skip the ignored `# `, classify
+        let mut kind: Option<PythonLineKind> = None;
+        match head {
+            PythonLineHead::Spaces => {
+                if byte == b' ' {
+                    indent += 1;
+                    scanner.push_byte(byte);
+                    continue;
+                }
+                if byte == b'#' && (commented || current_line == start_line) {
+                    head = PythonLineHead::Hash;
+                    continue;
+                }
+                head = PythonLineHead::Indent;
+            }
+            PythonLineHead::Hash => {
+                if byte == b' ' {
+                    if current_line == start_line {
+                        commented = true;
+                    }
+                    head = PythonLineHead::Indent;
+                    continue;
+                }
+                // Lone `#`: a comment line after all
+                scanner.push_byte(b'#');
+                kind = Some(PythonLineKind::Comment);
+                head = PythonLineHead::Content;
+            }
+            PythonLineHead::Indent | PythonLineHead::Content => {}
+        }
+
+        if head == PythonLineHead::Indent {
+            if byte == b' ' || byte == b'\t' {
+                indent = if byte == b'\t' {
+                    (indent / 8 + 1) * 8
+                } else {
+                    indent + 1
+                };
+                scanner.push_byte(byte);
+                continue;
+            }
+            kind = Some(match byte {
+                b'\n' | b'\r' => PythonLineKind::Blank,
+                b'#' => PythonLineKind::Comment,
+                _ => PythonLineKind::Code,
+            });
+            head = PythonLineHead::Content;
+        }
+
+        if let Some(kind) = kind {
+            if current_line == start_line {
+                if kind != PythonLineKind::Code {
+                    return Err(ToggleCommentError::BlockEndNotFound { start: start_line });
+                }
+                header_seen = true;
+                header_indent = indent;
+            } else if logical_start {
+                match kind {
+                    PythonLineKind::Blank => line_in_span = false,
+                    PythonLineKind::Comment => line_in_span = indent > header_indent,
+                    PythonLineKind::Code => {
+                        // First code line at the header's level or less: suite over
+                        if indent <= header_indent {
+                            return Ok((last_line, commented));
+                        }
+                        line_in_span = true;
+                    }
+                }
+            }
+            line_has_content = kind != PythonLineKind::Blank;
+        }
+
+        scanner.push_byte(byte);
+
+        if byte == b'\n' {
+            if line_in_span && line_has_content {
+                last_line = current_line;
+            }
+            current_line += 1;
+            logical_start = scanner.at_logical_start();
+            head = PythonLineHead::Spaces;
+            indent = 0;
+            line_has_content = false;
+            // Continuation lines stay in (or out of) the span of their logical line
+            if logical_start {
+                line_in_span = false;
+            }
+        }
+    }
+}
+
+/// Find the last line of the Python suite headed by a line
+///
+/// # Overview
+/// The suite is the header line (with its bracket / backslash continuations)
+/// plus every following line up to, not including, the first code line
+/// indented no deeper than the header. Trailing blank lines and shallower
+/// comment lines are left out. The file is not modified.
+///
+/// # Arguments
+/// * `file_path` - Path to a `.py` file
+/// * `start_line` - Zero-indexed header line (`def`, `class`, `if`, ...)
+///
+/// # Returns
+/// * `Ok(line)` - Zero-indexed last line of the suite (the header line for
+///   a one-line `def f(): pass`)
+/// * `Err(BlockEndNotFound)` - Header is blank, a comment, or inside a string / bracket
+/// * `Err(LineNotFound)` - File has fewer lines
+/// * `Err(ToggleCommentError)` - Path, permission or I/O failure
+#[cfg(feature = "fs")]
+pub fn find_python_suite_end(
+    file_path: &str,
+    start_line: usize,
+) -> Result<usize, ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    let (end_line, _) = find_python_suite_end_in(&mut reader, start_line)?;
+    Ok(end_line)
+}
+
+/// Comment (or uncomment) a Python header line and its whole suite
+///
+/// # Overview
+/// Finds the suite (see `find_python_suite_end()`) and puts `# ` after the
+/// leading spaces of every non-blank line in it, so the indentation is kept
+/// (`    # if x:` / `    #     y()`). Comment lines in the suite get a
+/// second flag. If the header line is already commented that way, the same
+/// span is found again and exactly one `# ` is removed per line, giving back
+/// the original bytes.
+///
+/// One backup of the original; lines go through the batch line engine in
+/// passes of MAX_BATCH_LINES.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `start_line` - Zero-indexed header line
+///
+/// # Returns
+/// * `Ok(Some(end_line))` - Span `start_line..=end_line` toggled
+/// * `Ok(None)` - Not a `.py` file - no-op
+/// * `Err(BlockEndNotFound)` - No suite at `start_line` (no backup made)
+/// * `Err(ProtectedRegion)` - Span reaches into a `toggle_comment: off` region
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// // Line 12 is `def legacy_handler(request):`
+/// toggle_python_suite_bytewise("./app/views.py", 12)?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_python_suite_bytewise(
+    file_path: &str,
+    start_line: usize,
+) -> Result<Option<usize>, ToggleCommentError> {
+    toggle_python_suite_bytewise_with_options(file_path, start_line, &ToggleOptions::default())
+}
+
+/// Same as `toggle_python_suite_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_python_suite_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    options: &ToggleOptions,
+) -> Result<Option<usize>, ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    let extension = match target_extension(&absolute_path, options) {
+        Some(ext) => ext,
+        None => return Err(ToggleCommentError::NoExtension),
+    };
+    if !extension.eq_ignore_ascii_case("py") {
+        return Ok(None); // Unsupported extension - no-op
+    }
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let (end_line, commented) = find_python_suite_end_in(
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, source_file),
+        start_line,
+    )?;
+
+    let rule = LineToggleRule {
+        comment_flag: CommentFlag::Hash,
+        removal_only_flag: None,
+        preserve_indent: true,
+        direction: if commented {
+            LineToggleDirection::Remove
+        } else {
+            LineToggleDirection::Add
+        },
+        // Exact inverse of Add, whatever the options say
+        removal: RemovalMode::Strict,
+        eol: options.eol,
+        final_newline: options.final_newline,
+        trim_trailing: options.trim_trailing,
+        skip_blank: false,
+        eol_audit: eol_audit_format(options),
+        heredocs: false,
+        indent_tabs: false,
+    };
+
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+
+    let backup_path = backup_path_for(&filename, options);
+    let trace = StageTrace::new(options, "suite");
+
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    // Contiguous span: passes of up to MAX_BATCH_LINES consecutive lines
+    let mut chain = BatchPassChain::new(&absolute_path, &filename, options, trace, Some(snapshot));
+    let mut targets: [usize; MAX_BATCH_LINES] = [0; MAX_BATCH_LINES];
+    let mut next_line = start_line;
+
+    while next_line <= end_line {
+        let mut count: usize = 0;
+        while count < MAX_BATCH_LINES && next_line <= end_line {
+            targets[count] = next_line;
+            count += 1;
+            next_line += 1;
+        }
+
+        if let Err(e) = chain.run_pass(&targets[..count], rule) {
+            chain.abandon();
+            return Err(e);
+        }
+    }
+
+    chain.finish()?;
+
+    Ok(Some(end_line))
+}
+
+// ============================================================================
+// TESTS - PHASE 8: PYTHON SUITES
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod python_suite_tests {
+    use super::*;
+
+    fn suite_end(content: &str, start: usize) -> Result<(usize, bool), ToggleCommentError> {
+        find_python_suite_end_in(&mut std::io::Cursor::new(content.as_bytes()), start)
+    }
+
+    const SOURCE: &str = "import os\n\nclass A:\n    def f(\n        self,\n):\n        \"\"\"Doc\n\nend\"\"\"\n        x = [1,\n2]\n# col-0 note\n\n        return x \\\n+ 1\n\n    # trailing note\n\n    def g(self): pass\ny = 1\n";
+
+    #[test]
+    fn test_python_suite_end() {
+        // Continuations and a column-0 comment stay in; the shallower note does not
+        assert_eq!(suite_end(SOURCE, 3), Ok((14, false)));
+        assert_eq!(suite_end(SOURCE, 2), Ok((18, false)));
+        // One-line suite
+        assert_eq!(suite_end(SOURCE, 18), Ok((18, false)));
+        // Blank / continuation lines are not headers
+        assert!(suite_end(SOURCE, 1).is_err());
+        assert!(suite_end(SOURCE, 8).is_err());
+        assert!(suite_end(SOURCE, 10).is_err());
+        // Suite running to end of input (no final newline)
+        assert_eq!(suite_end("if x:\n\ty = 1\n\tz = 2", 0), Ok((2, false)));
+    }
+
+    #[test]
+    fn test_python_suite_roundtrip() {
+        let test_file = create_test_file("test_python_suite.py", SOURCE);
+        let path = test_file.to_str().unwrap();
+
+        assert_eq!(toggle_python_suite_bytewise(path, 3), Ok(Some(14)));
+        let commented = read_file_content(&test_file);
+        assert!(commented.starts_with(
+            "import os\n\nclass A:\n    # def f(\n        # self,\n# ):\n        # \"\"\"Doc\n\n# end\"\"\"\n"
+        ));
+        assert!(
+            commented.contains(
+                "\n# # col-0 note\n\n        # return x \\\n# + 1\n\n    # trailing note\n"
+            )
+        );
+
+        // Same line again: commented header, same span, exact undo
+        assert_eq!(suite_end(&commented, 3), Ok((14, true)));
+        assert_eq!(toggle_python_suite_bytewise(path, 3), Ok(Some(14)));
+        assert_eq!(read_file_content(&test_file), SOURCE);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_python_suite.py"),
+        ]);
+    }
+}
+
+// ============================================================================
+// PHASE 9: FILE STYLE DETECTION (READ-ONLY)
+// ============================================================================
+//
+// ## Project Context
+// Wrappers (formatters, editor plugins) decide which options to pass before
+// calling a toggle: does the file use CRLF, does it end with a newline, is it
+// indented with tabs, which flag would be written. Detection answers that in
+// one streaming pass without modifying the file.
+//
+// ## Approach
+// Per byte, only the previous byte and the current line's leading whitespace
+// kind are held. A line counts towards the indentation style when it starts
+// with a space or tab and has content after it (whitespace-only lines are
+// ignored). The comment flag comes from the extension, as for a toggle.
+// ============================================================================
+
+/// Line ending style found by `detect_file_style()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// No line breaks at all (empty or single-line file)
+    None,
+
+    /// Every line ends with `\n`
+    Lf,
+
+    /// Every line ends with `\r\n`
+    Crlf,
+
+    /// Both styles occur
+    Mixed,
+}
+
+impl LineEnding {
+    /// Lowercase name used in CLI output
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::None => "none",
+            LineEnding::Lf => "lf",
+            LineEnding::Crlf => "crlf",
+            LineEnding::Mixed => "mixed",
+        }
+    }
+}
+
+/// Indentation style found by `detect_file_style()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    /// No indented lines
+    None,
+
+    /// Indented lines start with spaces only
+    Spaces,
+
+    /// Indented lines start with tabs only
+    Tabs,
+
+    /// Some lines start with spaces, others with tabs
+    Mixed,
+}
+
+impl IndentStyle {
+    /// Lowercase name used in CLI output
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndentStyle::None => "none",
+            IndentStyle::Spaces => "spaces",
+            IndentStyle::Tabs => "tabs",
+            IndentStyle::Mixed => "mixed",
+        }
+    }
+}
+
+/// File metadata reported by `detect_file_style()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStyle {
+    /// Dominant line ending (`Mixed` if both occur)
+    pub line_ending: LineEnding,
+
+    /// Last byte of the file is `\n`
+    pub final_newline: bool,
+
+    /// Leading whitespace style of indented lines
+    pub indent: IndentStyle,
+
+    /// Flag a basic toggle would write (`None` for unsupported extensions)
+    pub comment_flag: Option<&'static str>,
+}
+
+/// Scan line endings, final newline and indentation in one pass
+///
+/// # Returns
+/// * `Ok(FileStyle)` - With `comment_flag: None` (filled in by the caller)
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+fn detect_file_style_in<R: Read>(reader: &mut R) -> Result<FileStyle, ToggleCommentError> {
+    let mut lf_count: u64 = 0;
+    let mut crlf_count: u64 = 0;
+    let mut space_lines: u64 = 0;
+    let mut tab_lines: u64 = 0;
+
+    let mut previous: Option<u8> = None;
+    // First byte of the current line if it is a space or tab
+    let mut line_lead: Option<u8> = None;
+    let mut at_line_start = true;
+    let mut in_leading_space = true;
+    let mut byte_count: u64 = 0;
+
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if byte == b'\n' {
+            if previous == Some(b'\r') {
+                crlf_count += 1;
+            } else {
+                lf_count += 1;
+            }
+            line_lead = None;
+            at_line_start = true;
+            in_leading_space = true;
+            previous = Some(byte);
+            continue;
+        }
+
+        if at_line_start {
+            at_line_start = false;
+            if byte == b' ' || byte == b'\t' {
+                line_lead = Some(byte);
+            }
+        }
+
+        // First content byte: the line counts if it was indented
+        if in_leading_space && byte != b' ' && byte != b'\t' && byte != b'\r' {
+            in_leading_space = false;
+            match line_lead {
+                Some(b' ') => space_lines += 1,
+                Some(_) => tab_lines += 1,
+                None => {}
+            }
+        }
+
+        previous = Some(byte);
+    }
+
+    let line_ending = match (lf_count > 0, crlf_count > 0) {
+        (false, false) => LineEnding::None,
+        (true, false) => LineEnding::Lf,
+        (false, true) => LineEnding::Crlf,
+        (true, true) => LineEnding::Mixed,
+    };
+
+    let indent = match (space_lines > 0, tab_lines > 0) {
+        (false, false) => IndentStyle::None,
+        (true, false) => IndentStyle::Spaces,
+        (false, true) => IndentStyle::Tabs,
+        (true, true) => IndentStyle::Mixed,
+    };
+
+    Ok(FileStyle {
+        line_ending,
+        final_newline: previous == Some(b'\n'),
+        indent,
+        comment_flag: None,
+    })
+}
+
+/// Report line endings, final newline, indentation and comment flag of a file
+///
+/// # Overview
+/// Streams the file once; the file is not modified and no backup is made.
+/// Works for any file: the comment flag is `None` when the extension is
+/// unsupported (or plain `.json`), the other fields are still reported.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+///
+/// # Returns
+/// * `Ok(FileStyle)` - Detected metadata
+/// * `Err(ToggleCommentError)` - Path, permission or I/O failure
+///
+/// # Example
+/// ```no_run
+/// let style = detect_file_style("./src/main.rs")?;
+/// if style.line_ending == LineEnding::Crlf { /* ... */ }
+/// ```
+#[cfg(feature = "fs")]
+pub fn detect_file_style(file_path: &str) -> Result<FileStyle, ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    let mut style = detect_file_style_in(&mut reader)?;
+
+    style.comment_flag = absolute_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(determine_comment_flag)
+        .map(|flag| flag.as_str());
+
+    Ok(style)
+}
+
+// ============================================================================
+// TESTS - PHASE 9: FILE STYLE DETECTION
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod file_style_tests {
+    use super::*;
+
+    fn detect(content: &str) -> FileStyle {
+        detect_file_style_in(&mut std::io::Cursor::new(content.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_detect_style_in() {
+        let style = detect("fn a() {\n    b();\n\n}\n");
+        assert_eq!(style.line_ending, LineEnding::Lf);
+        assert!(style.final_newline);
+        assert_eq!(style.indent, IndentStyle::Spaces);
+
+        let style = detect("a\r\n\tb\r\n  \r\nc");
+        assert_eq!(style.line_ending, LineEnding::Crlf);
+        assert!(!style.final_newline);
+        // Whitespace-only line does not count as indented
+        assert_eq!(style.indent, IndentStyle::Tabs);
+
+        let style = detect("a\n\tb\r\n  c\n");
+        assert_eq!(style.line_ending, LineEnding::Mixed);
+        assert_eq!(style.indent, IndentStyle::Mixed);
+
+        let style = detect("");
+        assert_eq!(style.line_ending, LineEnding::None);
+        assert!(!style.final_newline);
+        assert_eq!(style.indent, IndentStyle::None);
+    }
+
+    #[test]
+    fn test_detect_file_style() {
+        let test_file = create_test_file("test_file_style.md", "# Title\r\n\r\n  text\r\n");
+        let path = test_file.to_str().unwrap();
+
+        assert_eq!(
+            detect_file_style(path),
+            Ok(FileStyle {
+                line_ending: LineEnding::Crlf,
+                final_newline: true,
+                indent: IndentStyle::Spaces,
+                comment_flag: Some("<!-- -->"),
+            })
+        );
+        // Detection never writes a backup
+        assert!(!Path::new("backup_toggle_comment_test_file_style.md").exists());
+        assert_eq!(
+            detect_file_style("missing_file_style.rs"),
+            Err(ToggleCommentError::FileNotFound)
+        );
+
+        cleanup_files(&[&test_file]);
+    }
+}
+
+// ============================================================================
+// PHASE 10: COMMENT COLUMN QUERY (EDITOR ALIGNMENT)
+// ============================================================================
+//
+// ## Project Context
+// Editor plugins draw a marker where the flag will appear (or disappear)
+// before running a toggle. Re-implementing the rule in each plugin drifts;
+// this answers it with the same rule `toggle_line()` uses: column 0, or
+// after the leading spaces (spaces only, not tabs, unless
+// `ToggleOptions::indent_tab`) when indentation is preserved
+// (`ToggleOptions::preserve_indent`, always for yaml/yml).
+// ============================================================================
+
+/// Byte column where a line toggle inserts or removes the flag
+///
+/// # Arguments
+/// * `line` - Line content (a line ending, if present, is ignored)
+/// * `preserve_indent` - Same meaning as `ToggleOptions::preserve_indent`
+///
+/// # Returns
+/// * `0` without `preserve_indent`
+/// * Number of leading spaces with `preserve_indent` (a blank line of
+///   spaces gets the flag after them)
+///
+/// # Example
+/// ```no_run
+/// assert_eq!(comment_insertion_column(b"    x = 1", true), 4);
+/// assert_eq!(comment_insertion_column(b"    x = 1", false), 0);
+/// ```
+pub fn comment_insertion_column(line: &[u8], preserve_indent: bool) -> usize {
+    if !preserve_indent {
+        return 0;
+    }
+    line.iter().take_while(|&&byte| byte == b' ').count()
+}
+
+/// Count the leading spaces (and tabs, with `tabs`) of line `line_number`,
+/// streaming
+///
+/// # Returns
+/// * `Ok(spaces)` - Leading spaces of the line
+/// * `Err(LineNotFound)` - Input ended before the line
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+fn leading_spaces_of_line_in<R: Read>(
+    reader: &mut R,
+    line_number: usize,
+    tabs: bool,
+) -> Result<usize, ToggleCommentError> {
+    let mut current_line: usize = 0;
+    let mut spaces: usize = 0;
+    let mut counting = line_number == 0;
+    let mut byte_count: u64 = 0;
+
+    loop {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        let byte = match read_single_byte(reader)? {
+            Some(b) => b,
+            None if counting => return Ok(spaces),
+            None => {
+                return Err(ToggleCommentError::LineNotFound {
+                    requested: line_number,
+                    file_lines: current_line + 1,
+                });
+            }
+        };
+
+        if counting {
+            if byte == b' ' || (byte == b'\t' && tabs) {
+                spaces += 1;
+                continue;
+            }
+            return Ok(spaces);
+        }
+
+        if byte == b'\n' {
+            current_line += 1;
+            counting = current_line == line_number;
+        }
+    }
+}
+
+/// Byte column where a toggle of `line_number` would insert or remove the flag
+///
+/// # Overview
+/// File-level form of `comment_insertion_column()`: resolves the flag and
+/// the indent rule from the extension and `options` exactly as a basic
+/// single-line toggle would, then streams to the line and counts only its
+/// leading spaces. The file is not modified.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `line_number` - Zero-indexed line
+/// * `options` - Only `preserve_indent` and `indent_tab` matter
+///
+/// # Returns
+/// * `Ok(Some(column))` - Byte column of the flag
+/// * `Ok(None)` - Unsupported extension (a toggle would be a no-op)
+/// * `Err(LineNotFound)` / `Err(NoExtension)` / `Err(JsonNoComments)` - As for a toggle
+/// * `Err(ToggleCommentError)` - Path, permission or I/O failure
+#[cfg(feature = "fs")]
+pub fn comment_column_in_file(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+) -> Result<Option<usize>, ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let extension = match target_extension(&absolute_path, options) {
+        Some(ext) => ext,
+        None => return Err(ToggleCommentError::NoExtension),
+    };
+    if is_json_without_comments(&extension) {
+        return Err(ToggleCommentError::JsonNoComments);
+    }
+    if determine_comment_flag(&extension).is_none() {
+        return Ok(None); // Unsupported extension - no-op
+    }
+    let preserve_indent = options.preserve_indent || preserves_indent_by_default(&extension);
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    let spaces = leading_spaces_of_line_in(&mut reader, line_number, options.indent_tab)?;
+    Ok(Some(if preserve_indent { spaces } else { 0 }))
+}
+
+// ============================================================================
+// TESTS - PHASE 10: COMMENT COLUMN QUERY
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod comment_column_tests {
+    use super::*;
+
+    #[test]
+    fn test_comment_insertion_column() {
+        assert_eq!(comment_insertion_column(b"    x = 1\n", true), 4);
+        assert_eq!(comment_insertion_column(b"    x = 1\n", false), 0);
+        // Tabs are not skipped by the engine
+        assert_eq!(comment_insertion_column(b"\t  x", true), 0);
+        assert_eq!(comment_insertion_column(b"   ", true), 3);
+        assert_eq!(comment_insertion_column(b"", true), 0);
+    }
+
+    #[test]
+    fn test_comment_column_in_file() {
+        let test_file = create_test_file("test_comment_column.yaml", "a:\n  - b\n");
+        let path = test_file.to_str().unwrap();
+        let defaults = ToggleOptions::default();
+
+        // yaml always preserves indentation; the column matches the toggle
+        assert_eq!(comment_column_in_file(path, 1, &defaults), Ok(Some(2)));
+        assert_eq!(comment_column_in_file(path, 2, &defaults), Ok(Some(0)));
+        assert_eq!(
+            comment_column_in_file(path, 3, &defaults),
+            Err(ToggleCommentError::LineNotFound {
+                requested: 3,
+                file_lines: 3
+            })
+        );
+        assert!(toggle_basic_singleline_comment_bytewise(path, 1).is_ok());
+        assert_eq!(read_file_content(&test_file), "a:\n  # - b\n");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_comment_column.yaml"),
+        ]);
+    }
+}
+
+// ============================================================================
+// PHASE 11: IN-MEMORY BUFFER API (NO FILESYSTEM)
+// ============================================================================
+//
+// ## Project Context
+// Web-based editors (wasm32-wasip1 and similar) hold the document in memory
+// and have no useful filesystem: no canonicalize, no process id for temp
+// names, no rename-over. This entry point runs the same line engine
+// (`write_toggled_lines_bytewise()`) from a byte slice into any Write, so the
+// result is byte-identical to the file operations.
+//
+// Building with `--no-default-features` drops the `fs` feature and every
+// file operation with it; this section and the engine remain.
+//
+// ## Policy
+// - One pass only: up to MAX_BATCH_LINES lines per call (no temp file to
+//   chain further passes through)
+// - `max_file_size` applies to `content.len()`; `trace` is ignored (there
+//   are no pipeline stages)
+// - Nothing is written for an unsupported extension: the caller keeps the
+//   original buffer
+// - `toggle_comment_in_line()` toggles one `&str` line with a given flag, for
+//   formatters and test generators that have no file or extension at all;
+//   `toggle_lines_in_buffer_with_flag()` does the same for a whole buffer
+// - `process_toggle()` is the stream form underneath: any `BufRead` in, any
+//   `Write` out, lines chosen by a `LineSelection`, the flag by a
+//   `StreamEdit`. The buffer functions are it plus the size check; the file
+//   operations add backup, temp file and replace around the same engine
+//   (`max_file_size` cannot apply to a stream of unknown length)
+// ============================================================================
+
+/// Lines a stream toggle applies to (`process_toggle()`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineSelection<'a> {
+    /// One zero-indexed line
+    Line(usize),
+
+    /// Zero-indexed lines `start..=end` (either order)
+    Range { start: usize, end: usize },
+
+    /// Zero-indexed lines in any order, duplicates ignored
+    Lines(&'a [usize]),
+}
+
+/// Comment flag a stream toggle adds or removes (`process_toggle()`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEdit<'a> {
+    /// The flag of a file extension without the dot (`"rs"`), with its
+    /// removal-only flag and indent default, exactly as for files
+    Extension(&'a str),
+
+    /// This flag, and only it (`CommentFlag::TripleSlash` for Rust doc comments)
+    Flag(CommentFlag),
+}
+
+/// Toggle comments on lines of a stream
+///
+/// # Overview
+/// The line engine of every toggle, from `reader` to `writer`: the selected
+/// lines are toggled, every other byte is copied unchanged. No filesystem is
+/// involved; backups and atomic replacement belong to the file operations
+/// built on it.
+///
+/// # Arguments
+/// * `reader` - Source, positioned at line 0 (buffered: it is read bytewise)
+/// * `writer` - Receives the whole toggled stream
+/// * `selection` - Lines to toggle (at most MAX_BATCH_LINES: one pass)
+/// * `edit` - Flag from an extension, or a given flag
+///
+/// # Returns
+/// * `Ok(true)` - Toggled stream written to `writer`
+/// * `Ok(false)` - Extension not supported: nothing read or written
+/// * `Err(NoExtension)` / `Err(JsonNoComments)` - As for `toggle_lines_in_buffer()`
+/// * `Err(TooManyLines)` - Selection larger than MAX_BATCH_LINES
+/// * `Err(LineNotFound)` / `Err(ProtectedRegion)` - `writer` may hold a
+///   partial stream and should be discarded
+/// * `Err(ToggleCommentError)` - Read or write failed
+///
+/// # Example
+/// ```rust,no_run
+/// let mut input = std::io::Cursor::new(b"a = 1\nb = 2\n".to_vec());
+/// let mut output: Vec<u8> = Vec::new();
+/// process_toggle(
+///     &mut input,
+///     &mut output,
+///     LineSelection::Range { start: 0, end: 1 },
+///     StreamEdit::Extension("py"),
+/// )?;
+/// ```
+pub fn process_toggle<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    selection: LineSelection,
+    edit: StreamEdit,
+) -> Result<bool, ToggleCommentError> {
+    process_toggle_with_options(reader, writer, selection, edit, &ToggleOptions::default())
+}
+
+/// Same as `process_toggle()`, with caller-provided `ToggleOptions`
+/// (`trace` and `max_file_size` ignored)
+pub fn process_toggle_with_options<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    selection: LineSelection,
+    edit: StreamEdit,
+    options: &ToggleOptions,
+) -> Result<bool, ToggleCommentError> {
+    let rule = match stream_edit_rule(edit, options)? {
+        Some(rule) => rule,
+        None => return Ok(false),
+    };
+
+    let mut sorted: [usize; MAX_BATCH_LINES] = [0; MAX_BATCH_LINES];
+    let count = match selection {
+        LineSelection::Line(line) => sort_and_dedup_line_numbers(&[line], &mut sorted)?,
+        LineSelection::Lines(lines) => sort_and_dedup_line_numbers(lines, &mut sorted)?,
+        LineSelection::Range { start, end } => {
+            let (first, last) = (start.min(end), start.max(end));
+            if last - first >= MAX_BATCH_LINES {
+                return Err(ToggleCommentError::TooManyLines {
+                    max: MAX_BATCH_LINES,
+                });
+            }
+            for (slot, line) in sorted.iter_mut().zip(first..=last) {
+                *slot = line;
+            }
+            last - first + 1
+        }
+    };
+
+    write_toggled_lines_bytewise(reader, writer, &sorted[..count], rule)?;
+    Ok(true)
+}
+
+/// Line rule of a `StreamEdit` (`None`: unsupported extension)
+fn stream_edit_rule(
+    edit: StreamEdit,
+    options: &ToggleOptions,
+) -> Result<Option<LineToggleRule>, ToggleCommentError> {
+    match edit {
+        StreamEdit::Extension("") => Err(ToggleCommentError::NoExtension),
+        StreamEdit::Extension(extension) => resolve_extension_toggle_rule(extension, options),
+        StreamEdit::Flag(flag) => Ok(Some(LineToggleRule {
+            comment_flag: flag,
+            removal_only_flag: None,
+            preserve_indent: options.preserve_indent,
+            direction: options.direction,
+            removal: options.removal,
+            eol: options.eol,
+            final_newline: options.final_newline,
+            trim_trailing: options.trim_trailing,
+            skip_blank: options.skip_blank,
+            eol_audit: eol_audit_format(options),
+            heredocs: false,
+            indent_tabs: false,
+        })),
+    }
+}
+
+/// Toggle basic comments on lines of an in-memory buffer
+///
+/// # Arguments
+/// * `content` - Whole document
+/// * `extension` - File extension without the dot (`"rs"`, `"py"`), selects the flag
+/// * `line_numbers` - Zero-indexed lines to toggle (any order, duplicates ignored)
+/// * `options` - Same options as the file operations (`trace` ignored)
+/// * `output` - Receives the whole toggled document
+///
+/// # Returns
+/// * `Ok(true)` - Toggled document written to `output`
+/// * `Ok(false)` - Extension not supported: nothing written
+/// * `Err(NoExtension)` - `extension` is empty
+/// * `Err(JsonNoComments)` - Plain `json`
+/// * `Err(FileTooLarge)` - `content` exceeds `options.max_file_size`
+/// * `Err(TooManyLines)` - More than MAX_BATCH_LINES line numbers
+/// * `Err(LineNotFound)` / `Err(ProtectedRegion)` - As for the file operations;
+///   `output` may hold a partial document and should be discarded
+///
+/// # Example
+/// ```rust,no_run
+/// let mut toggled: Vec<u8> = Vec::new();
+/// let changed = toggle_lines_in_buffer(
+///     b"fn main() {\n    run();\n}\n",
+///     "rs",
+///     &[1],
+///     &ToggleOptions::default(),
+///     &mut toggled,
+/// )?;
+/// ```
+pub fn toggle_lines_in_buffer<W: Write>(
+    content: &[u8],
+    extension: &str,
+    line_numbers: &[usize],
+    options: &ToggleOptions,
+    output: &mut W,
+) -> Result<bool, ToggleCommentError> {
+    toggle_buffer(
+        content,
+        line_numbers,
+        StreamEdit::Extension(extension),
+        options,
+        output,
+    )
+}
+
+/// Toggle a given comment flag on lines of an in-memory buffer
+///
+/// Same as `toggle_lines_in_buffer()` with the flag chosen by the caller
+/// instead of an extension (no removal-only flags, no per-language indent
+/// default), for content whose language is only known to the caller.
+///
+/// # Returns
+/// * `Ok(())` - Toggled document written to `output`
+/// * `Err(FileTooLarge)` / `Err(TooManyLines)` / `Err(LineNotFound)` - As for
+///   `toggle_lines_in_buffer()`
+///
+/// # Example
+/// ```rust,no_run
+/// let mut toggled: Vec<u8> = Vec::new();
+/// toggle_lines_in_buffer_with_flag(
+///     b"a = 1\nb = 2\n",
+///     CommentFlag::Hash,
+///     &[1],
+///     &ToggleOptions::default(),
+///     &mut toggled,
+/// )?;
+/// ```
+pub fn toggle_lines_in_buffer_with_flag<W: Write>(
+    content: &[u8],
+    flag: CommentFlag,
+    line_numbers: &[usize],
+    options: &ToggleOptions,
+    output: &mut W,
+) -> Result<(), ToggleCommentError> {
+    toggle_buffer(
+        content,
+        line_numbers,
+        StreamEdit::Flag(flag),
+        options,
+        output,
+    )?;
+    Ok(())
+}
+
+/// Shared body of the buffer toggles: size check, then the stream toggle
+fn toggle_buffer<W: Write>(
+    content: &[u8],
+    line_numbers: &[usize],
+    edit: StreamEdit,
+    options: &ToggleOptions,
+    output: &mut W,
+) -> Result<bool, ToggleCommentError> {
+    // Extension errors and unsupported extensions come before the size check
+    if stream_edit_rule(edit, options)?.is_none() {
+        return Ok(false);
+    }
+    if let Some(max) = options.max_file_size
+        && content.len() as u64 > max
+    {
+        return Err(ToggleCommentError::FileTooLarge {
+            size: content.len() as u64,
+            max,
+        });
+    }
+
+    let mut reader: &[u8] = content;
+    process_toggle_with_options(
+        &mut reader,
+        output,
+        LineSelection::Lines(line_numbers),
+        edit,
+        options,
+    )
+}
+
+/// Toggle a comment flag on one line of text
+///
+/// Exactly what a file toggle does to the line (`toggle_line()`, default
+/// `ToggleOptions`): `{flag}{space}` (or the bare flag before the line
+/// ending) at column 0 is removed, otherwise `{flag}{space}` is added; HTML
+/// comments also add / drop ` -->` before the line ending.
+///
+/// # Arguments
+/// * `line` - One line, with or without its line ending (anything after the
+///   first `\n` is copied unchanged)
+/// * `flag` - Flag to add or remove
+///
+/// # Returns
+/// * `(toggled, LineToggleDirection::Add)` - The flag was added
+/// * `(toggled, LineToggleDirection::Remove)` - The flag was removed
+/// * `(line, LineToggleDirection::Toggle)` - Nothing changed (only for a line
+///   longer than MAX_BYTE_ITERATIONS bytes)
+///
+/// # Example
+/// ```rust,no_run
+/// let (toggled, direction) = toggle_comment_in_line("    run();", CommentFlag::DoubleSlash);
+/// assert_eq!(toggled, "//     run();");
+/// assert_eq!(direction, LineToggleDirection::Add);
+/// ```
+pub fn toggle_comment_in_line(line: &str, flag: CommentFlag) -> (String, LineToggleDirection) {
+    let options = ToggleOptions::default();
+    let rule = LineToggleRule {
+        comment_flag: flag,
+        removal_only_flag: None,
+        preserve_indent: options.preserve_indent,
+        direction: options.direction,
+        removal: options.removal,
+        eol: options.eol,
+        final_newline: options.final_newline,
+        trim_trailing: options.trim_trailing,
+        skip_blank: options.skip_blank,
+        eol_audit: TraceFormat::Off,
+        heredocs: false,
+        indent_tabs: false,
+    };
+
+    let mut reader: &[u8] = line.as_bytes();
+    let mut toggled: Vec<u8> = Vec::with_capacity(line.len() + MAX_TOGGLE_EDIT_BYTES as usize);
+    if toggle_line(&mut reader, &mut toggled, rule).is_err() {
+        return (line.to_string(), LineToggleDirection::Toggle);
+    }
+    toggled.extend_from_slice(reader);
+
+    // Only ASCII flag bytes are added or removed, so it is still UTF-8
+    let direction = if toggled.len() > line.len() {
+        LineToggleDirection::Add
+    } else {
+        LineToggleDirection::Remove
+    };
+    match String::from_utf8(toggled) {
+        Ok(toggled) => (toggled, direction),
+        Err(_) => (line.to_string(), LineToggleDirection::Toggle),
+    }
+}
+
+// ============================================================================
+// TESTS - PHASE 11: IN-MEMORY BUFFER API
+// ============================================================================
+
+#[cfg(test)]
+mod buffer_api_tests {
+    use super::*;
+
+    fn toggle(content: &str, extension: &str, lines: &[usize]) -> String {
+        let mut output: Vec<u8> = Vec::new();
+        let changed = toggle_lines_in_buffer(
+            content.as_bytes(),
+            extension,
+            lines,
+            &ToggleOptions::default(),
+            &mut output,
+        )
+        .unwrap();
+        assert!(changed);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_toggle_lines_in_buffer_with_flag() {
+        let flag = CommentFlag::from_flag("//").unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        toggle_lines_in_buffer_with_flag(
+            b"a\r\nb",
+            flag,
+            &[1],
+            &ToggleOptions::default(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(output, b"a\r\n// b");
+
+        // Only the four flags parse; a missing line is an error as for files
+        assert_eq!(CommentFlag::from_flag("--"), None);
+        let mut output: Vec<u8> = Vec::new();
+        let result = toggle_lines_in_buffer_with_flag(
+            b"a\n",
+            CommentFlag::Hash,
+            &[3],
+            &ToggleOptions::default(),
+            &mut output,
+        );
+        assert!(matches!(
+            result,
+            Err(ToggleCommentError::LineNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_toggle_comment_in_line() {
+        let cases: &[(&str, CommentFlag, &str, LineToggleDirection)] = &[
+            ("a", CommentFlag::Hash, "# a", LineToggleDirection::Add),
+            ("# a", CommentFlag::Hash, "a", LineToggleDirection::Remove),
+            ("#", CommentFlag::Hash, "", LineToggleDirection::Remove),
+            (
+                "#a\n",
+                CommentFlag::Hash,
+                "# #a\n",
+                LineToggleDirection::Add,
+            ),
+            (
+                "  x();\r\n",
+                CommentFlag::DoubleSlash,
+                "//   x();\r\n",
+                LineToggleDirection::Add,
+            ),
+            (
+                "/// doc",
+                CommentFlag::TripleSlash,
+                "doc",
+                LineToggleDirection::Remove,
+            ),
+            (
+                "note",
+                CommentFlag::HtmlComment,
+                "<!-- note -->",
+                LineToggleDirection::Add,
+            ),
+            (
+                "<!-- note -->\n",
+                CommentFlag::HtmlComment,
+                "note\n",
+                LineToggleDirection::Remove,
+            ),
+            (
+                "a\nb",
+                CommentFlag::Hash,
+                "# a\nb",
+                LineToggleDirection::Add,
+            ),
+            ("é", CommentFlag::Hash, "# é", LineToggleDirection::Add),
+        ];
+        for &(line, flag, expected, direction) in cases {
+            assert_eq!(
+                toggle_comment_in_line(line, flag),
+                (expected.to_string(), direction),
+                "{:?}",
+                line
+            );
+        }
+
+        // Same result as the buffer API for the same flag
+        let mut output: Vec<u8> = Vec::new();
+        toggle_lines_in_buffer(
+            b"  x();\n",
+            "rs",
+            &[0],
+            &ToggleOptions::default(),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            toggle_comment_in_line("  x();\n", CommentFlag::DoubleSlash).0
+        );
+    }
+
+    #[test]
+    fn test_toggle_lines_in_buffer() {
+        assert_eq!(toggle("a\nb\nc\n", "rs", &[2, 0, 2]), "// a\nb\n// c\n");
+        assert_eq!(toggle("// a\nb\n", "rs", &[0]), "a\nb\n");
+        assert_eq!(toggle("  x: 1\n", "yaml", &[0]), "  # x: 1\n");
+        assert_eq!(toggle("a\r\nb", "py", &[1]), "a\r\n# b");
+    }
+
+    #[test]
+    fn test_toggle_lines_in_buffer_errors() {
+        let options = ToggleOptions::default();
+        let mut output: Vec<u8> = Vec::new();
+
+        assert_eq!(
+            toggle_lines_in_buffer(b"a\n", "xyz", &[0], &options, &mut output),
+            Ok(false)
+        );
+        assert!(output.is_empty());
+        assert_eq!(
+            toggle_lines_in_buffer(b"a\n", "", &[0], &options, &mut output),
+            Err(ToggleCommentError::NoExtension)
+        );
+        assert_eq!(
+            toggle_lines_in_buffer(b"{}\n", "json", &[0], &options, &mut output),
+            Err(ToggleCommentError::JsonNoComments)
+        );
+        assert!(matches!(
+            toggle_lines_in_buffer(b"a\n", "rs", &[5], &options, &mut output),
+            Err(ToggleCommentError::LineNotFound { requested: 5, .. })
+        ));
+
+        let small = ToggleOptions {
+            max_file_size: Some(1),
+            ..ToggleOptions::default()
+        };
+        assert_eq!(
+            toggle_lines_in_buffer(b"a\n", "rs", &[0], &small, &mut output),
+            Err(ToggleCommentError::FileTooLarge { size: 2, max: 1 })
+        );
+    }
+
+    #[test]
+    fn test_process_toggle_streams() {
+        let toggle_stream = |content: &[u8], selection, edit| {
+            let mut reader = std::io::Cursor::new(content.to_vec());
+            let mut output: Vec<u8> = Vec::new();
+            process_toggle(&mut reader, &mut output, selection, edit)
+                .map(|changed| (changed, output))
+        };
+
+        // Reversed range; same bytes as the buffer API
+        assert_eq!(
+            toggle_stream(
+                b"a\r\nb\r\nc",
+                LineSelection::Range { start: 2, end: 1 },
+                StreamEdit::Extension("py"),
+            ),
+            Ok((true, b"a\r\n# b\r\n# c".to_vec()))
+        );
+        assert_eq!(
+            toggle_stream(
+                b"/// a\n",
+                LineSelection::Line(0),
+                StreamEdit::Flag(CommentFlag::TripleSlash),
+            ),
+            Ok((true, b"a\n".to_vec()))
+        );
+        assert_eq!(
+            toggle_stream(
+                b"a\n",
+                LineSelection::Lines(&[0]),
+                StreamEdit::Extension("xyz")
+            ),
+            Ok((false, Vec::new()))
+        );
+        assert_eq!(
+            toggle_stream(
+                b"a\n",
+                LineSelection::Range {
+                    start: 0,
+                    end: MAX_BATCH_LINES
+                },
+                StreamEdit::Extension("rs"),
+            ),
+            Err(ToggleCommentError::TooManyLines {
+                max: MAX_BATCH_LINES
+            })
+        );
+    }
+}
+
+// ============================================================================
+// PHASE 12: COMMENT STATE QUERY (EDITOR GUTTER)
+// ============================================================================
+//
+// ## Project Context
+// Editor plugins paint a comment indicator for every visible line. One call
+// per line would reopen and rescan the file each time; this classifies a
+// whole range in one streaming pass, with the rule a toggle uses to decide
+// whether to add or remove the flag (`toggle_line()`): `{flag}{space}` at
+// column 0 (after leading spaces with indent preservation), the removal-only
+// flag too, and the bare flag with `RemovalMode::Relaxed`.
+// ============================================================================
+
+/// Comment state of one line, as a toggle would see it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCommentState {
+    /// A toggle would remove the flag
+    Commented,
+
+    /// A toggle would add the flag
+    Code,
+
+    /// Empty or whitespace-only line (and not commented)
+    Blank,
+}
+
+impl LineCommentState {
+    /// One-character form: `C` commented, `.` code, `B` blank
+    pub fn symbol(self) -> char {
+        match self {
+            LineCommentState::Commented => 'C',
+            LineCommentState::Code => '.',
+            LineCommentState::Blank => 'B',
+        }
+    }
+}
+
+/// `toggle_line()`'s detection rule, fed one byte of a line at a time
+struct FlagMatcher<'r> {
+    rule: &'r LineToggleRule,
+    primary_matches: bool,
+    alternate_matches: bool,
+    matched: usize,
+    in_indent: bool,
+    found: Option<CommentFlag>,
+}
+
+impl<'r> FlagMatcher<'r> {
+    fn new(rule: &'r LineToggleRule) -> Self {
+        FlagMatcher {
+            rule,
+            primary_matches: true,
+            alternate_matches: rule.removal_only_flag.is_some(),
+            matched: 0,
+            in_indent: rule.preserve_indent,
+            found: None,
+        }
+    }
+
+    /// Next byte of the line (line ending excluded)
+    fn feed(&mut self, byte: u8) {
+        if self.found.is_some() || !(self.primary_matches || self.alternate_matches) {
+            return;
+        }
+        if self.in_indent && byte == b' ' {
+            return;
+        }
+        self.in_indent = false;
+
+        let flag_bytes = self.rule.comment_flag.as_bytes();
+        let alternate_bytes: &[u8] = match self.rule.removal_only_flag {
+            Some(flag) => flag.as_bytes(),
+            None => b"",
+        };
+        // Strict: flag + one space; relaxed: the flag alone
+        let extra = if self.rule.removal == RemovalMode::Relaxed {
+            0
+        } else {
+            1
+        };
+
+        self.primary_matches =
+            self.primary_matches && byte == pattern_byte(flag_bytes, self.matched);
+        self.alternate_matches =
+            self.alternate_matches && byte == pattern_byte(alternate_bytes, self.matched);
+        self.matched += 1;
+
+        if self.primary_matches && self.matched == flag_bytes.len() + extra {
+            self.found = Some(self.rule.comment_flag);
+        } else if self.alternate_matches && self.matched == alternate_bytes.len() + extra {
+            self.found = self.rule.removal_only_flag;
+        }
+    }
+
+    /// End of the line: a complete flag with nothing after it counts for
+    /// flags that are `removable_at_eol()`
+    fn finish(&mut self) {
+        if self.found.is_some() {
+            return;
+        }
+        let comment_flag = self.rule.comment_flag;
+        if self.primary_matches
+            && self.matched == comment_flag.as_bytes().len()
+            && comment_flag.removable_at_eol()
+        {
+            self.found = Some(comment_flag);
+        } else if let Some(alternate) = self.rule.removal_only_flag
+            && self.alternate_matches
+            && self.matched == alternate.as_bytes().len()
+            && alternate.removable_at_eol()
+        {
+            self.found = Some(alternate);
+        }
+    }
+}
+
+/// Classify the line at the reader's position and consume it
+///
+/// # Returns
+/// * `Ok(Some(state))` - Line classified; reader is at the next line
+/// * `Ok(None)` - End of input (no line left)
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+fn classify_line_in<R: Read>(
+    reader: &mut R,
+    rule: &LineToggleRule,
+) -> Result<Option<LineCommentState>, ToggleCommentError> {
+    let mut matcher = FlagMatcher::new(rule);
+    let mut blank = true;
+    let mut byte_count: u64 = 0;
+    // A CR is only fed once something other than the newline follows it
+    let mut pending_cr = false;
+
+    loop {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        let byte = match read_single_byte(reader)? {
+            Some(b'\n') => break,
+            Some(b) => b,
+            None if byte_count == 1 => return Ok(None),
+            None => break,
+        };
+        blank = blank && matches!(byte, b' ' | b'\t' | b'\r');
+        if pending_cr {
+            matcher.feed(b'\r');
+        }
+        pending_cr = byte == b'\r';
+        if !pending_cr {
+            matcher.feed(byte);
+        }
+    }
+    matcher.finish();
+
+    Ok(Some(if matcher.found.is_some() {
+        LineCommentState::Commented
+    } else if blank {
+        LineCommentState::Blank
+    } else {
+        LineCommentState::Code
+    }))
+}
+
+/// Classify lines `start_line..=end_line` in one pass
+///
+/// # Returns
+/// * `Ok(count)` - Lines reported (the range is cut at end of input)
+/// * `Err(LineNotFound)` - Input ended before `start_line`
+fn comment_states_in<R: Read>(
+    reader: &mut R,
+    rule: &LineToggleRule,
+    start_line: usize,
+    end_line: usize,
+    report: &mut dyn FnMut(LineCommentState),
+) -> Result<usize, ToggleCommentError> {
+    let mut current_line: usize = 0;
+
+    while current_line <= end_line {
+        let state = match classify_line_in(reader, rule)? {
+            Some(state) => state,
+            None => break,
+        };
+        if current_line >= start_line {
+            report(state);
+        }
+        current_line += 1;
+    }
+
+    if current_line <= start_line {
+        return Err(ToggleCommentError::LineNotFound {
+            requested: start_line,
+            file_lines: current_line,
+        });
+    }
+    Ok(current_line - start_line)
+}
+
+/// Comment state of every line in a range, streaming (read-only)
+///
+/// # Overview
+/// Resolves the flag and options from the extension exactly as a basic
+/// toggle would, then classifies each line of the range in order. Lines
+/// past the end of the file are not reported, so a viewport that reaches
+/// beyond the last line needs no clamping.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `start_line` / `end_line` - Zero-indexed, inclusive (either order)
+/// * `options` - `preserve_indent` and `removal` matter
+/// * `report` - Called once per line, in order
+///
+/// # Returns
+/// * `Ok(Some(count))` - Number of lines reported
+/// * `Ok(None)` - Unsupported extension (nothing reported)
+/// * `Err(LineNotFound)` - The file ends before `start_line`
+/// * `Err(ToggleCommentError)` - Path, extension, permission or I/O failure
+///
+/// # Example
+/// ```no_run
+/// let mut gutter = String::new();
+/// comment_states_in_range("./src/main.rs", 40, 80, &ToggleOptions::default(), |state| {
+///     gutter.push(state.symbol())
+/// })?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn comment_states_in_range<F: FnMut(LineCommentState)>(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+    mut report: F,
+) -> Result<Option<usize>, ToggleCommentError> {
+    let (start_line, end_line) = sort_range(start_line, end_line);
+
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let rule = match resolve_batch_toggle_rule(&absolute_path, None, options)? {
+        Some(rule) => rule,
+        None => return Ok(None), // Unsupported extension - no-op
+    };
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    comment_states_in(&mut reader, &rule, start_line, end_line, &mut report).map(Some)
+}
+
+/// Longest run of commented lines `find_commented_runs()` callers accept by
+/// default (the CLI's `--lint-comments` without `[max_run]`)
+pub const DEFAULT_MAX_COMMENTED_RUN: usize = 5;
+
+/// Report every run of more than `max_run` consecutive commented lines
+///
+/// # Returns
+/// * `Ok(runs)` - Runs reported, as `report(first_line, last_line)` in order
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+fn commented_runs_in<R: Read>(
+    reader: &mut R,
+    rule: &LineToggleRule,
+    max_run: usize,
+    report: &mut dyn FnMut(usize, usize),
+) -> Result<usize, ToggleCommentError> {
+    let mut current_line: usize = 0;
+    let mut run_start: Option<usize> = None;
+    let mut runs: usize = 0;
+
+    let mut close_run = |run_start: &mut Option<usize>, end: usize| {
+        if let Some(first) = run_start.take()
+            && end - first > max_run
+        {
+            report(first, end - 1);
+            runs += 1;
+        }
+    };
+
+    while let Some(state) = classify_line_in(reader, rule)? {
+        if state == LineCommentState::Commented {
+            run_start.get_or_insert(current_line);
+        } else {
+            close_run(&mut run_start, current_line);
+        }
+        current_line += 1;
+    }
+    close_run(&mut run_start, current_line);
+
+    Ok(runs)
+}
+
+/// Find runs of consecutive commented lines (dead code blocks), streaming
+///
+/// # Overview
+/// Classifies every line with the same flag rule a basic toggle uses (see
+/// `comment_states_in_range()`) and reports each run of more than `max_run`
+/// consecutive commented lines. Blank lines end a run. Read-only.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `max_run` - Longest run that is not reported
+/// * `options` - `preserve_indent` and `removal` matter
+/// * `report` - Called as `report(first_line, last_line)` (zero-indexed) per run
+///
+/// # Returns
+/// * `Ok(Some(runs))` - Number of runs reported
+/// * `Ok(None)` - Unsupported extension (nothing reported)
+/// * `Err(ToggleCommentError)` - Path, extension, permission or I/O failure
+///
+/// # Example
+/// ```no_run
+/// find_commented_runs("./src/main.rs", DEFAULT_MAX_COMMENTED_RUN, &ToggleOptions::default(), |first, last| {
+///     println!("src/main.rs:{}: commented-out block", first + 1)
+/// })?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn find_commented_runs<F: FnMut(usize, usize)>(
+    file_path: &str,
+    max_run: usize,
+    options: &ToggleOptions,
+    mut report: F,
+) -> Result<Option<usize>, ToggleCommentError> {
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let rule = match resolve_batch_toggle_rule(&absolute_path, None, options)? {
+        Some(rule) => rule,
+        None => return Ok(None), // Unsupported extension - no-op
+    };
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    commented_runs_in(&mut reader, &rule, max_run, &mut report).map(Some)
+}
+
+// ============================================================================
+// TESTS - PHASE 12: COMMENT STATE QUERY
+// ============================================================================
+
+#[cfg(test)]
+mod comment_state_tests {
+    use super::*;
+
+    fn states(
+        content: &str,
+        extension: &str,
+        options: &ToggleOptions,
+        range: (usize, usize),
+    ) -> String {
+        let rule = resolve_extension_toggle_rule(extension, options)
+            .unwrap()
+            .unwrap();
+        let mut symbols = String::new();
+        let result = comment_states_in(
+            &mut content.as_bytes(),
+            &rule,
+            range.0,
+            range.1,
+            &mut |state| symbols.push(state.symbol()),
+        );
+        match result {
+            Ok(count) => {
+                assert_eq!(count, symbols.len());
+                symbols
+            }
+            Err(e) => format!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_comment_states() {
+        let defaults = ToggleOptions::default();
+        let source = "// a\nb\n\n  \t\n  // c\n//d\n// e";
+
+        // Column 0 rule; indented flags only count with preserve_indent
+        assert_eq!(states(source, "rs", &defaults, (0, 6)), "C.BB..C");
+        let indented = ToggleOptions {
+            preserve_indent: true,
+            ..defaults.clone()
+        };
+        assert_eq!(states(source, "rs", &indented, (0, 6)), "C.BBC.C");
+
+        // A bare flag at the end of the line is commented (LF, CRLF, EOF)
+        assert_eq!(states("//\n//\r\n#\n//", "rs", &defaults, (0, 3)), "CC.C");
+        let relaxed = ToggleOptions {
+            removal: RemovalMode::Relaxed,
+            ..defaults.clone()
+        };
+        assert_eq!(states(source, "rs", &relaxed, (0, 6)), "C.BB.CC");
+
+        // Sub-range, range past the end, removal-only flag, yaml indentation
+        assert_eq!(states(source, "rs", &defaults, (4, 100)), "..C");
+        assert_eq!(states("# a\n$x;\n", "php", &defaults, (0, 1)), "C.");
+        assert_eq!(states("a:\n  # b\n", "yaml", &defaults, (0, 1)), ".C");
+        assert_eq!(
+            states("a\n", "py", &defaults, (1, 2)),
+            "LineNotFound { requested: 1, file_lines: 1 }"
+        );
+    }
+
+    #[test]
+    fn test_commented_runs() {
+        let rule = resolve_extension_toggle_rule("py", &ToggleOptions::default())
+            .unwrap()
+            .unwrap();
+        let runs = |content: &str, max_run: usize| {
+            let mut found: Vec<(usize, usize)> = Vec::new();
+            let count = commented_runs_in(&mut content.as_bytes(), &rule, max_run, &mut |a, b| {
+                found.push((a, b))
+            })
+            .unwrap();
+            assert_eq!(count, found.len());
+            found
+        };
+
+        // Runs longer than max_run, including one at end of input; blanks split runs
+        let source = "# a\n# b\n# c\nx = 1\n# d\n\n# e\n# f\n# g";
+        assert_eq!(runs(source, 2), vec![(0, 2), (6, 8)]);
+        assert_eq!(runs(source, 3), vec![]);
+        assert_eq!(runs(source, 0), vec![(0, 2), (4, 4), (6, 8)]);
+        assert_eq!(runs("", 0), vec![]);
+    }
+}
+
+// ============================================================================
+// PHASE 13: LINE METADATA (INSPECT)
+// ============================================================================
+//
+// ## Project Context
+// Tools built on this crate (formatters, linters, editor plugins) need the
+// same facts the engine uses about one line: its length, its indentation,
+// whether a toggle would see a flag. `inspect_line()` answers from one
+// streaming pass with `FlagMatcher`, so nobody re-implements the rules.
+// ============================================================================
+
+/// Facts about one line, as the toggle engine sees them
+///
+/// `has_flag` / `flag_kind` follow the toggle rule for the file's extension
+/// and the options (column 0, or after leading spaces when indentation is
+/// preserved); for unsupported extensions no flag is ever found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineInfo {
+    /// Bytes of the line without its line ending
+    pub byte_len: usize,
+
+    /// Spaces in the leading whitespace (spaces and tabs, any order)
+    pub leading_spaces: usize,
+
+    /// Tabs in the leading whitespace
+    pub leading_tabs: usize,
+
+    /// A toggle would remove a flag from this line
+    pub has_flag: bool,
+
+    /// The flag found (`//`, `#`, `<!-- -->`, ...), `None` without one
+    pub flag_kind: Option<&'static str>,
+
+    /// Line ends with CRLF (LF, or no ending on the last line, otherwise)
+    pub ends_with_crlf: bool,
+}
+
+impl LineInfo {
+    /// The line's state for a gutter: commented when a toggle would remove a
+    /// flag, blank when it holds only spaces and tabs, code otherwise
+    pub fn comment_state(&self) -> LineCommentState {
+        if self.has_flag {
+            LineCommentState::Commented
+        } else if self.leading_spaces + self.leading_tabs == self.byte_len {
+            LineCommentState::Blank
+        } else {
+            LineCommentState::Code
+        }
+    }
+}
+
+/// Stream to `line_number` and describe it
+///
+/// # Returns
+/// * `Ok(LineInfo)` - Line found (line 0 of an empty file is an empty line)
+/// * `Err(LineNotFound)` - Input ended before the line
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+fn inspect_line_in<R: Read>(
+    reader: &mut R,
+    line_number: usize,
+    rule: Option<&LineToggleRule>,
+) -> Result<LineInfo, ToggleCommentError> {
+    inspect_line_after(reader, 0, line_number, rule)
+}
+
+/// Same as `inspect_line_in()`, for a reader at the start of line `from_line`
+/// (`from_line <= line_number`); the reader is left at the next line
+fn inspect_line_after<R: Read>(
+    reader: &mut R,
+    from_line: usize,
+    line_number: usize,
+    rule: Option<&LineToggleRule>,
+) -> Result<LineInfo, ToggleCommentError> {
+    let mut current_line: usize = from_line;
+    let mut byte_count: u64 = 0;
+
+    // Skip to the line
+    while current_line < line_number {
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        match read_single_byte(reader)? {
+            Some(b'\n') => current_line += 1,
+            Some(_) => {}
+            None => {
+                return Err(ToggleCommentError::LineNotFound {
+                    requested: line_number,
+                    file_lines: current_line + 1,
+                });
+            }
+        }
+    }
+
+    let mut info = LineInfo {
+        byte_len: 0,
+        leading_spaces: 0,
+        leading_tabs: 0,
+        has_flag: false,
+        flag_kind: None,
+        ends_with_crlf: false,
+    };
+    let mut matcher = rule.map(FlagMatcher::new);
+    let mut in_leading = true;
+    let mut pending_cr = false;
+    let mut first = true;
+
+    loop {
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        let byte = match read_single_byte(reader)? {
+            Some(b'\n') => {
+                info.ends_with_crlf = pending_cr;
+                break;
+            }
+            Some(b) => b,
+            // The final newline does not start another line
+            None if first && line_number > 0 => {
+                return Err(ToggleCommentError::LineNotFound {
+                    requested: line_number,
+                    file_lines: line_number,
+                });
+            }
+            None => {
+                // A lone CR at end of input is content
+                if pending_cr {
+                    info.byte_len += 1;
+                }
+                break;
+            }
+        };
+        first = false;
+
+        // A CR only counts as content once something follows it
+        if pending_cr {
+            info.byte_len += 1;
+            in_leading = false;
+            if let Some(matcher) = matcher.as_mut() {
+                matcher.feed(b'\r');
+            }
+        }
+        pending_cr = byte == b'\r';
+        if pending_cr {
+            continue;
+        }
+
+        info.byte_len += 1;
+        if in_leading {
+            match byte {
+                b' ' => info.leading_spaces += 1,
+                b'\t' => info.leading_tabs += 1,
+                _ => in_leading = false,
+            }
+        }
+        if let Some(matcher) = matcher.as_mut() {
+            matcher.feed(byte);
+        }
+    }
+
+    if let Some(matcher) = matcher.as_mut() {
+        matcher.finish();
+    }
+    if let Some(flag) = matcher.and_then(|m| m.found) {
+        info.has_flag = true;
+        info.flag_kind = Some(flag.as_str());
+    }
+    Ok(info)
+}
+
+/// Describe one line: length, indentation, flag, line ending (read-only)
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `line_number` - Zero-indexed line
+/// * `options` - `preserve_indent` and `removal` decide what a flag is
+///
+/// # Returns
+/// * `Ok(LineInfo)` - The line's facts
+/// * `Err(LineNotFound)` - No such line
+/// * `Err(ToggleCommentError)` - Path, extension, permission or I/O failure
+///
+/// # Example
+/// ```no_run
+/// let info = inspect_line("./src/main.rs", 12, &ToggleOptions::default())?;
+/// if info.has_flag { /* draw the "commented" marker */ }
+/// ```
+#[cfg(feature = "fs")]
+pub fn inspect_line(
+    file_path: &str,
+    line_number: usize,
+    options: &ToggleOptions,
+) -> Result<LineInfo, ToggleCommentError> {
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let rule = resolve_batch_toggle_rule(&absolute_path, None, options)?;
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    inspect_line_in(&mut reader, line_number, rule.as_ref())
+}
+
+/// Describe every line of sorted, non-overlapping ranges in one pass
+///
+/// # Returns
+/// * `Ok(count)` - Lines reported; a range is cut at end of input
+/// * `Err(LineNotFound)` - Input ended before the start of a range
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+fn inspect_lines_in<R: Read>(
+    reader: &mut R,
+    ranges: &[(usize, usize)],
+    rule: Option<&LineToggleRule>,
+    report: &mut dyn FnMut(usize, LineInfo),
+) -> Result<usize, ToggleCommentError> {
+    let mut next_line: usize = 0;
+    let mut reported: usize = 0;
+
+    for &(start, end) in ranges {
+        for line in start..=end {
+            match inspect_line_after(reader, next_line, line, rule) {
+                Ok(info) => report(line, info),
+                Err(ToggleCommentError::LineNotFound { .. }) if line > start => break,
+                Err(e) => return Err(e),
+            }
+            reported += 1;
+            next_line = line + 1;
+        }
+    }
+    Ok(reported)
+}
+
+/// Describe many lines at once: state, indentation and flag (read-only)
+///
+/// # Overview
+/// One streaming pass for a whole selection, so an editor refreshing its
+/// gutter needs one call instead of one `inspect_line()` per line. Ranges
+/// may come in any order and overlap; lines are reported once each, in
+/// file order. A range reaching past the last line is cut there.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `ranges` - Inclusive zero-indexed `(start, end)` ranges (either order
+///   within a range); a single line is `(n, n)`; at most MAX_RANGES
+/// * `options` - `preserve_indent` and `removal` decide what a flag is
+/// * `report` - Called as `report(line, info)` per line
+///
+/// # Returns
+/// * `Ok(count)` - Lines reported
+/// * `Err(TooManyLines)` - More than MAX_RANGES ranges
+/// * `Err(LineNotFound)` - The file ends before the start of a range
+/// * `Err(ToggleCommentError)` - Path, extension, permission or I/O failure
+///
+/// # Example
+/// ```no_run
+/// inspect_lines("./src/main.rs", &[(40, 80), (95, 95)], &ToggleOptions::default(), |line, info| {
+///     println!("{} {}", line, info.comment_state().symbol())
+/// })?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn inspect_lines<F: FnMut(usize, LineInfo)>(
+    file_path: &str,
+    ranges: &[(usize, usize)],
+    options: &ToggleOptions,
+    mut report: F,
+) -> Result<usize, ToggleCommentError> {
+    if ranges.len() > MAX_RANGES {
+        return Err(ToggleCommentError::TooManyLines { max: MAX_RANGES });
+    }
+    let merged = merge_ranges(ranges);
+
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse FIFOs, device nodes and sockets (reading one can block forever)
+    if is_special_file(&absolute_path) {
+        return Err(ToggleCommentError::NotRegularFile);
+    }
+
+    let rule = resolve_batch_toggle_rule(&absolute_path, None, options)?;
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    inspect_lines_in(&mut reader, &merged, rule.as_ref(), &mut report)
+}
+
+/// Sort ranges (each put in order first) and join overlapping / adjacent ones
+fn merge_ranges(ranges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut sorted: Vec<(usize, usize)> = ranges
+        .iter()
+        .map(|&(start, end)| sort_range(start, end))
+        .collect();
+    sorted.sort_unstable();
+
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(sorted.len());
+    for (start, end) in sorted {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// ============================================================================
+// TESTS - PHASE 13: LINE METADATA
+// ============================================================================
+
+#[cfg(test)]
+mod inspect_line_tests {
+    use super::*;
+
+    fn inspect(
+        content: &str,
+        line: usize,
+        extension: &str,
+    ) -> Result<LineInfo, ToggleCommentError> {
+        let rule = resolve_extension_toggle_rule(extension, &ToggleOptions::default()).unwrap();
+        inspect_line_in(&mut content.as_bytes(), line, rule.as_ref())
+    }
+
+    #[test]
+    fn test_inspect_line() {
+        let source = "fn a() {}\r\n \t // x\n# y\r";
+
+        let info = inspect(source, 0, "rs").unwrap();
+        assert_eq!(
+            (info.byte_len, info.ends_with_crlf, info.has_flag),
+            (9, true, false)
+        );
+
+        // Mixed indentation; the flag is not at column 0, so a toggle adds one
+        let info = inspect(source, 1, "rs").unwrap();
+        assert_eq!(
+            (info.byte_len, info.leading_spaces, info.leading_tabs),
+            (7, 2, 1)
+        );
+        assert_eq!((info.has_flag, info.flag_kind), (false, None));
+
+        // Lone CR at end of input is content; removal-only flag reported as found
+        let info = inspect(source, 2, "php").unwrap();
+        assert_eq!((info.byte_len, info.ends_with_crlf), (4, false));
+        assert_eq!((info.has_flag, info.flag_kind), (true, Some("#")));
+        assert_eq!(inspect(source, 2, "txt").unwrap().flag_kind, None);
+
+        // Line after the final newline / past the end; empty input has line 0
+        assert_eq!(
+            inspect("a\n", 1, "rs"),
+            Err(ToggleCommentError::LineNotFound {
+                requested: 1,
+                file_lines: 1
+            })
+        );
+        assert!(inspect("a\n", 5, "rs").is_err());
+        assert_eq!(inspect("", 0, "rs").unwrap().byte_len, 0);
+    }
+
+    #[test]
+    fn test_inspect_lines() {
+        let rule = resolve_extension_toggle_rule("py", &ToggleOptions::default()).unwrap();
+        let source = "# a\n    b\n\t\n# c\nd";
+        let inspect_all = |ranges: &[(usize, usize)]| {
+            let mut seen: Vec<(usize, char, usize)> = Vec::new();
+            inspect_lines_in(
+                &mut source.as_bytes(),
+                &merge_ranges(ranges),
+                rule.as_ref(),
+                &mut |line, info| {
+                    seen.push((
+                        line,
+                        info.comment_state().symbol(),
+                        info.leading_spaces + info.leading_tabs,
+                    ))
+                },
+            )
+            .map(|count| {
+                assert_eq!(count, seen.len());
+                seen
+            })
+        };
+
+        // Overlapping and unordered ranges: every line once, in file order
+        assert_eq!(
+            inspect_all(&[(3, 1), (0, 0), (2, 2)]),
+            Ok(vec![(0, 'C', 0), (1, '.', 4), (2, 'B', 1), (3, 'C', 0)])
+        );
+        // A range is cut at end of input; one starting past it is an error
+        assert_eq!(inspect_all(&[(4, 100)]), Ok(vec![(4, '.', 0)]));
+        assert_eq!(
+            inspect_all(&[(1, 1), (7, 8)]),
+            Err(ToggleCommentError::LineNotFound {
+                requested: 7,
+                file_lines: 5
+            })
+        );
+        assert_eq!(merge_ranges(&[(5, 9), (0, 2), (3, 4)]), vec![(0, 9)]);
+    }
+}
+
+// ============================================================================
+// PHASE 14: STRIP LINE COMMENTS
+// ============================================================================
+//
+// ## Project Context
+// Minimal reproductions and code-size measurements want a file without its
+// comments. `strip_line_comments()` drops every full-line comment of the
+// extension's flag (the line starts with the flag after optional spaces and
+// tabs) in one streaming pass; every other byte is copied unchanged. Trailing
+// comments after code and multi-line block comments are not touched.
+// ============================================================================
+
+/// Longest leading whitespace held back while deciding whether a line is a
+/// comment; a line indented further is copied as code
+#[cfg(feature = "fs")]
+const MAX_STRIP_INDENT: usize = 256;
+
+/// Copy `reader` to `writer` without the lines that start with one of `flags`
+/// (after optional spaces / tabs), including their line endings
+///
+/// # Returns
+/// * `Ok((lines, bytes))` - Lines removed and bytes they held
+/// * `Err(ToggleCommentError)` - Read / write failed or iteration limit reached
+#[cfg(feature = "fs")]
+fn strip_comment_lines_in<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    flags: &[&[u8]],
+) -> Result<(usize, u64), ToggleCommentError> {
+    /// Where the current line stands
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum LineState {
+        /// Start of line held back in `pending`
+        Deciding,
+        /// Code: copy to the end of the line
+        Copy,
+        /// Comment: drop to the end of the line
+        Skip,
+    }
+
+    let longest_flag = flags.iter().map(|flag| flag.len()).max().unwrap_or(0);
+    let mut pending = [0u8; MAX_STRIP_INDENT + 8];
+    let mut pending_len: usize = 0;
+    let mut indent_len: usize = 0;
+    let mut state = LineState::Deciding;
+    let mut removed_lines: usize = 0;
+    let mut removed_bytes: u64 = 0;
+    let mut byte_count: u64 = 0;
+
+    let write = |writer: &mut W, bytes: &[u8]| {
+        writer
+            .write_all(bytes)
+            .map_err(|_| ToggleCommentError::IoError(IoOperation::Write))
+    };
+
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        match state {
+            LineState::Copy => write(writer, &[byte])?,
+            LineState::Skip => removed_bytes += 1,
+            LineState::Deciding if byte == b'\n' => {
+                write(writer, &pending[..pending_len])?;
+                write(writer, &[byte])?;
+            }
+            LineState::Deciding => {
+                pending[pending_len] = byte;
+                pending_len += 1;
+                let in_indent = pending_len - 1 == indent_len && (byte == b' ' || byte == b'\t');
+                if in_indent {
+                    indent_len += 1;
+                }
+                let tail = &pending[indent_len..pending_len];
+                let undecided = if in_indent {
+                    indent_len < MAX_STRIP_INDENT
+                } else {
+                    tail.len() < longest_flag && flags.iter().any(|flag| flag.starts_with(tail))
+                };
+
+                if !in_indent && flags.contains(&tail) {
+                    state = LineState::Skip;
+                    removed_lines += 1;
+                    removed_bytes += pending_len as u64;
+                } else if !undecided {
+                    write(writer, &pending[..pending_len])?;
+                    state = LineState::Copy;
+                }
+            }
+        }
+
+        if byte == b'\n' {
+            state = LineState::Deciding;
+            pending_len = 0;
+            indent_len = 0;
+        }
+    }
+
+    if state == LineState::Deciding {
+        write(writer, &pending[..pending_len])?;
+    }
+    Ok((removed_lines, removed_bytes))
+}
+
+/// Remove every full-line comment from a file, in place
 ///
 /// # Overview
-/// **Simple implementation:** Calls `toggle_basic_singleline_comment_bytewise()`
-/// once for each line in the range. No optimization, no single-pass complexity.
-/// File is opened/closed multiple times - this is intentional for simplicity.
+/// A full-line comment starts with the extension's flag (or removal-only
+/// flag, e.g. `#` in PHP) after optional spaces and tabs: `// x`, `//x`,
+/// `    /// doc`, `#`. The line and its line ending are removed; all other
+/// bytes are copied unchanged. Backup, temp file and atomic replace as for
+/// every edit.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+///
+/// # Returns
+/// * `Ok(Some(lines))` - Number of comment lines removed
+/// * `Ok(None)` - Unsupported extension - no-op
+/// * `Err(ToggleCommentError)` - Specific error code
+///
+/// # Example
+/// ```no_run
+/// let removed = strip_line_comments("./repro/main.rs")?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn strip_line_comments(file_path: &str) -> Result<Option<usize>, ToggleCommentError> {
+    strip_line_comments_with_options(file_path, None, &ToggleOptions::default())
+}
+
+/// Same as `strip_line_comments()`, with an optional output file and
+/// caller-provided `ToggleOptions`
+///
+/// # Arguments
+/// * `output` - Write the stripped copy here (created or truncated) and leave
+///   `file_path` untouched (no backup); the same file as `file_path` means
+///   in place
+#[cfg(feature = "fs")]
+pub fn strip_line_comments_with_options(
+    file_path: &str,
+    output: Option<&Path>,
+    options: &ToggleOptions,
+) -> Result<Option<usize>, ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // An output naming the source itself is an in-place edit
+    let output = output.filter(|path| path.canonicalize().ok().as_ref() != Some(&absolute_path));
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made); with an output the source is only read
+    match output {
+        None => check_edit_target(&absolute_path, options)?,
+        Some(_) => check_source_target(&absolute_path, options)?,
+    }
+
+    let extension = match target_extension(&absolute_path, options) {
+        Some(ext) => ext,
+        None => return Err(ToggleCommentError::NoExtension),
+    };
+    if is_json_without_comments(&extension) {
+        return Err(ToggleCommentError::JsonNoComments);
+    }
+    let comment_flag = match determine_comment_flag(&extension) {
+        Some(flag) => flag,
+        None => return Ok(None), // Unsupported extension - no-op
+    };
+    let removal_only = determine_removal_only_flag(&extension).map(|flag| flag.as_bytes());
+    let flags_buffer: [&[u8]; 2] = [comment_flag.as_bytes(), removal_only.unwrap_or(b"")];
+    let flags = if removal_only.is_some() {
+        &flags_buffer[..]
+    } else {
+        &flags_buffer[..1]
+    };
+
+    let open_source = || match File::open(&absolute_path) {
+        Ok(f) => Ok(BufReader::with_capacity(IO_BUFFER_SIZE, TimedIo(f))),
+        Err(e) => Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+
+    // Separate output: the source is only read
+    if let Some(output_path) = output {
+        let output_file = match File::create(output_path) {
+            Ok(f) => f,
+            Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+        };
+        let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, output_file);
+        let (lines, _) = strip_comment_lines_in(&mut open_source()?, &mut writer, flags)?;
+        if writer.flush().is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+        return Ok(Some(lines));
+    }
+
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+
+    let trace = StageTrace::new(options, "strip-comments");
+    let backup_path = backup_path_for(&filename, options);
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    let temp_filename = format!("temp_toggle_strip_{}_{}", std::process::id(), filename);
+    let temp_path = temp_file_path(options.temp_dir.as_deref(), &temp_filename);
+    let _remove_temp = TempFileGuard(&temp_path);
+
+    let (lines, removed_bytes) = trace.run_streaming(&absolute_path, &temp_path, || {
+        let temp_file = match File::create(&temp_path) {
+            Ok(f) => f,
+            Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+        };
+        let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, TimedIo(temp_file));
+        let stripped = strip_comment_lines_in(&mut open_source()?, &mut writer, flags)?;
+        if writer.flush().is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        }
+        Ok(stripped)
+    })?;
+
+    if !trace.check(TraceStage::Verify, || {
+        replacement_size_is_plausible(&absolute_path, &temp_path, removed_bytes, 0)
+    }) {
+        return Err(ToggleCommentError::IncompleteWrite);
+    }
+    if let Err(e) = trace.run(TraceStage::Replace, || {
+        replace_original(
+            &temp_path,
+            &absolute_path,
+            options.temp_dir.as_deref(),
+            options.durability,
+            &snapshot,
+        )
+    }) {
+        return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
+    }
+
+    Ok(Some(lines))
+}
+
+// ============================================================================
+// TESTS - PHASE 14: STRIP LINE COMMENTS
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod strip_comments_tests {
+    use super::*;
+
+    fn strip(content: &str, flags: &[&[u8]]) -> (String, usize) {
+        let mut output: Vec<u8> = Vec::new();
+        let (lines, bytes) =
+            strip_comment_lines_in(&mut content.as_bytes(), &mut output, flags).unwrap();
+        assert_eq!(bytes as usize, content.len() - output.len());
+        (String::from_utf8(output).unwrap(), lines)
+    }
+
+    #[test]
+    fn test_strip_comment_lines() {
+        // Indented, flag-only and CRLF comment lines go; code, blanks and trailing comments stay
+        let source = "// a\nfn f() {\n\t  //b\n    x(); // keep\n\n/ / no\n//\r\n  /\n}\n// end";
+        assert_eq!(
+            strip(source, &[b"//"]),
+            (
+                "fn f() {\n    x(); // keep\n\n/ / no\n  /\n}\n".to_string(),
+                4
+            )
+        );
+
+        // Removal-only flag, partial prefix at end of input, deep indent kept as code
+        assert_eq!(
+            strip("# a\n$x;\n// b\n/", &[b"//", b"#"]),
+            ("$x;\n/".to_string(), 2)
+        );
+        let deep = format!("{}// c\n", " ".repeat(MAX_STRIP_INDENT + 1));
+        assert_eq!(strip(&deep, &[b"//"]), (deep.clone(), 0));
+    }
+
+    #[test]
+    fn test_strip_line_comments_file() {
+        let original = "# setup\nimport os\n    # note\nos.exit(0)\n";
+        let test_file = create_test_file("test_strip_comments.py", original);
+        let path = test_file.to_str().unwrap();
+        let output = PathBuf::from("test_strip_comments_out.py");
+
+        // Separate output: source untouched, no backup
+        let result =
+            strip_line_comments_with_options(path, Some(&output), &ToggleOptions::default());
+        assert_eq!(result, Ok(Some(2)));
+        assert_eq!(read_file_content(&output), "import os\nos.exit(0)\n");
+        assert_eq!(read_file_content(&test_file), original);
+
+        // In place
+        assert_eq!(strip_line_comments(path), Ok(Some(2)));
+        assert_eq!(read_file_content(&test_file), "import os\nos.exit(0)\n");
+
+        cleanup_files(&[
+            &test_file,
+            &output,
+            &PathBuf::from("backup_toggle_comment_test_strip_comments.py"),
+        ]);
+    }
+}
+
+// ============================================================================
+// PHASE 15: ISOLATE A RANGE (COMMENT EVERYTHING ELSE)
+// ============================================================================
+//
+// ## Project Context
+// Bisecting a bug often means running one region on its own. Isolating a
+// range comments every non-blank line outside it with the force-comment
+// rule (`LineToggleDirection::Add`: a commented line gets a second flag), so
+// undoing it with the same range removes exactly one flag per line and gives
+// back the original bytes.
+//
+// A file whose lines outside the range are all commented may be isolated
+// already, or may just start with real comments; the two cannot be told
+// apart. So isolating never removes flags (with no code outside the range it
+// is a no-op), and the undo is its own call (`undo_isolate_range_bytewise()`,
+// CLI: --undo-isolate).
+// ============================================================================
+
+/// Scan a file for `toggle_isolate_range_bytewise()`
+///
+/// # Returns
+/// * `Ok((lines, Some(isolated)))` - Lines in the input; whether every
+///   non-blank line outside `start_line..=end_line` is commented
+/// * `Ok((lines, None))` - No non-blank line outside the range
+/// * `Err(LineNotFound)` - The input ends before `end_line`
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+fn isolation_state_in<R: Read>(
+    reader: &mut R,
+    rule: &LineToggleRule,
+    start_line: usize,
+    end_line: usize,
+) -> Result<(usize, Option<bool>), ToggleCommentError> {
+    let mut current_line: usize = 0;
+    let mut outside_code = false;
+    let mut outside_commented = false;
+
+    while let Some(state) = classify_line_in(reader, rule)? {
+        if current_line < start_line || current_line > end_line {
+            match state {
+                LineCommentState::Commented => outside_commented = true,
+                LineCommentState::Code => outside_code = true,
+                LineCommentState::Blank => {}
+            }
+        }
+        current_line += 1;
+    }
+
+    if current_line <= end_line {
+        return Err(ToggleCommentError::LineNotFound {
+            requested: end_line,
+            file_lines: current_line,
+        });
+    }
+    let isolated = (outside_code || outside_commented).then_some(!outside_code);
+    Ok((current_line, isolated))
+}
+
+/// Comment every line outside a range
+///
+/// # Overview
+/// Non-blank lines before `start_line` and after `end_line` get the
+/// extension's flag (already commented lines a second one); blank lines and
+/// the range itself are untouched. When no line outside the range is code,
+/// nothing is done: the file is already isolated, or holds only comments
+/// there. `undo_isolate_range_bytewise()` with the same range restores the
+/// file byte for byte.
+///
+/// One backup of the original; lines go through the batch line engine in
+/// passes of MAX_BATCH_LINES.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `start_line` / `end_line` - Zero-indexed range to keep, inclusive (either order)
+///
+/// # Returns
+/// * `Ok(Some(LineToggleDirection::Add))` - Lines outside the range commented
+/// * `Ok(None)` - Unsupported extension, or no code line outside the range - no-op
+/// * `Err(LineNotFound)` - File has fewer lines than `end_line + 1`
+/// * `Err(ProtectedRegion)` - A line outside the range is in a protected region
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// toggle_isolate_range_bytewise("./src/main.rs", 40, 60)?; // only 40-60 active
+/// undo_isolate_range_bytewise("./src/main.rs", 40, 60)?; // back to the original
+/// ```
+#[cfg(feature = "fs")]
+pub fn toggle_isolate_range_bytewise(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+) -> Result<Option<LineToggleDirection>, ToggleCommentError> {
+    toggle_isolate_range_bytewise_with_options(
+        file_path,
+        start_line,
+        end_line,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `toggle_isolate_range_bytewise()`, with caller-provided `ToggleOptions`
+///
+/// `options.direction` and `options.removal` are ignored: the exact-inverse
+/// Add / strict Remove pair is what makes `undo_isolate_range_bytewise()` exact.
+#[cfg(feature = "fs")]
+pub fn toggle_isolate_range_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+) -> Result<Option<LineToggleDirection>, ToggleCommentError> {
+    isolate_range_in_direction(
+        file_path,
+        start_line,
+        end_line,
+        LineToggleDirection::Add,
+        options,
+    )
+}
+
+/// Undo `toggle_isolate_range_bytewise()` with the same range
+///
+/// # Overview
+/// Removes one flag from every non-blank line outside the range, but only
+/// when all of them are commented; otherwise the file is not isolated with
+/// this range and nothing is done.
+///
+/// # Returns
+/// * `Ok(Some(LineToggleDirection::Remove))` - Isolation undone
+/// * `Ok(None)` - Unsupported extension, or some non-blank line outside the
+///   range is code (or there is none) - no-op
+/// * `Err(ToggleCommentError)` - As for `toggle_isolate_range_bytewise()`
+#[cfg(feature = "fs")]
+pub fn undo_isolate_range_bytewise(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+) -> Result<Option<LineToggleDirection>, ToggleCommentError> {
+    undo_isolate_range_bytewise_with_options(
+        file_path,
+        start_line,
+        end_line,
+        &ToggleOptions::default(),
+    )
+}
+
+/// Same as `undo_isolate_range_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn undo_isolate_range_bytewise_with_options(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    options: &ToggleOptions,
+) -> Result<Option<LineToggleDirection>, ToggleCommentError> {
+    isolate_range_in_direction(
+        file_path,
+        start_line,
+        end_line,
+        LineToggleDirection::Remove,
+        options,
+    )
+}
+
+/// Isolate (`Add`) or undo the isolation of (`Remove`) a range
+#[cfg(feature = "fs")]
+fn isolate_range_in_direction(
+    file_path: &str,
+    start_line: usize,
+    end_line: usize,
+    direction: LineToggleDirection,
+    options: &ToggleOptions,
+) -> Result<Option<LineToggleDirection>, ToggleCommentError> {
+    let (start_line, end_line) = sort_range(start_line, end_line);
+
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
+
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    let mut rule = match resolve_batch_toggle_rule(&absolute_path, None, options)? {
+        Some(rule) => rule,
+        None => return Ok(None), // Unsupported extension - no-op
+    };
+
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let (line_count, isolated) = isolation_state_in(
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, source_file),
+        &rule,
+        start_line,
+        end_line,
+    )?;
+    // Isolate only with code outside the range; undo only when all of it is commented
+    if isolated != Some(direction == LineToggleDirection::Remove) {
+        return Ok(None);
+    }
+
+    rule.direction = direction;
+    // Exact inverse of Add, whatever the options say
+    rule.removal = RemovalMode::Strict;
+
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
+
+    let backup_path = backup_path_for(&filename, options);
+    let op = match direction {
+        LineToggleDirection::Remove => "undo_isolate",
+        _ => "isolate",
+    };
+    let trace = StageTrace::new(options, op);
+
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    // Lines before and after the range: passes of up to MAX_BATCH_LINES
+    let mut chain = BatchPassChain::new(&absolute_path, &filename, options, trace, Some(snapshot));
+    let mut targets: [usize; MAX_BATCH_LINES] = [0; MAX_BATCH_LINES];
+    let mut count: usize = 0;
+
+    // Passes keep every line's blankness, so the original tells which to skip
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut states = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+
+    for line in 0..line_count {
+        let blank = match classify_line_in(&mut states, &rule) {
+            Ok(state) => state == Some(LineCommentState::Blank),
+            Err(e) => {
+                chain.abandon();
+                return Err(e);
+            }
+        };
+        if blank || (start_line..=end_line).contains(&line) {
+            continue;
+        }
+        targets[count] = line;
+        count += 1;
+
+        if count == MAX_BATCH_LINES {
+            if let Err(e) = chain.run_pass(&targets[..count], rule) {
+                chain.abandon();
+                return Err(e);
+            }
+            count = 0;
+        }
+    }
+    if count > 0
+        && let Err(e) = chain.run_pass(&targets[..count], rule)
+    {
+        chain.abandon();
+        return Err(e);
+    }
+
+    chain.finish()?;
+
+    Ok(Some(direction))
+}
+
+// ============================================================================
+// TESTS - PHASE 15: ISOLATE A RANGE
+// ============================================================================
+
+#[cfg(all(test, feature = "fs"))]
+mod isolate_range_tests {
+    use super::*;
+
+    #[test]
+    fn test_isolate_range_roundtrip() {
+        let original = "import os\n# note\n\ndef f():\n    return 1\n  \nprint(f())\n";
+        let test_file = create_test_file("test_isolate_range.py", original);
+        let path = test_file.to_str().unwrap();
+
+        // Outside lines commented (comments doubled, blanks kept), range untouched
+        assert_eq!(
+            toggle_isolate_range_bytewise(path, 4, 3),
+            Ok(Some(LineToggleDirection::Add))
+        );
+        assert_eq!(
+            read_file_content(&test_file),
+            "# import os\n# # note\n\ndef f():\n    return 1\n  \n# print(f())\n"
+        );
+
+        // Isolating again does not stack; the undo with the same range is exact
+        assert_eq!(toggle_isolate_range_bytewise(path, 3, 4), Ok(None));
+        assert_eq!(
+            undo_isolate_range_bytewise(path, 3, 4),
+            Ok(Some(LineToggleDirection::Remove))
+        );
+        assert_eq!(read_file_content(&test_file), original);
+        assert_eq!(undo_isolate_range_bytewise(path, 3, 4), Ok(None));
+
+        // Whole file selected, or a range past the end
+        assert_eq!(toggle_isolate_range_bytewise(path, 0, 6), Ok(None));
+        assert!(matches!(
+            toggle_isolate_range_bytewise(path, 2, 9),
+            Err(ToggleCommentError::LineNotFound { .. })
+        ));
+        assert_eq!(read_file_content(&test_file), original);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_isolate_range.py"),
+        ]);
+    }
+
+    #[test]
+    fn test_isolate_keeps_real_comments() {
+        let original = "# header
+code
+";
+        let test_file = create_test_file("test_isolate_comments.py", original);
+        let path = test_file.to_str().unwrap();
+
+        // Only comments outside the range: not taken for an isolation to undo
+        assert_eq!(toggle_isolate_range_bytewise(path, 1, 1), Ok(None));
+        assert_eq!(read_file_content(&test_file), original);
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_isolate_comments.py"),
+        ]);
+    }
+}
+
+// ============================================================================
+// PHASE 16: WHOLE-LINE EDITS (INSERT / REMOVE / MOVE, RUST ATTRIBUTE LINES)
+// ============================================================================
+//
+// ## Project Context
+// Some edits add or drop a whole line instead of a flag: `#[cfg(test)]` or
+// `#[ignore]` above a function switches it off without touching its body;
+// banners, region markers and external tools need the same primitive.
+// `write_line_edit_in()` streams the file once, inserting one line before
+// line N (N may be the line count: append), dropping line N with its ending,
+// or both at once (a move); `apply_line_edit()` wraps it in the usual backup /
+// temp file / verify / atomic replace. `insert_line()` / `remove_line()`
+// expose it; removal names the content it expects, so a stale line number
+// fails instead of deleting the wrong line. `duplicate_line()` and
+// `move_line()` first find the line's byte span with one more pass; the
+// edit then streams the copy from a second handle on the file, so lines of
+// any length are copied. A copied last line without a line ending gets the
+// file's.
+//
+// An attribute toggle looks at the run of attribute (`#[`) and `//` comment
+// lines directly above the item line: a line that is exactly the attribute
+// (indentation and a CRLF ending allowed) is removed, otherwise the attribute
+// is inserted directly above the item with the item's indentation and line
+// ending. Multi-line attributes end the run at their first continuation line.
+// ============================================================================
+
+/// Longest line content (without indentation or line ending) a whole-line
+/// edit inserts
+pub const MAX_LINE_CONTENT_BYTES: usize = 1024;
+
+/// Longest line `styled_line()` builds (indent, content, `\r\n`)
+#[cfg(feature = "fs")]
+const MAX_STYLED_LINE: usize = MAX_MARKER_INDENT + MAX_LINE_CONTENT_BYTES + 2;
+
+/// The line a whole-line edit writes
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NewLine<'a> {
+    /// These bytes, line ending included
+    Bytes(&'a [u8]),
+
+    /// Bytes `start..end` of the input itself (a whole line), streamed from
+    /// a second handle, then `ending` (empty unless the line is an
+    /// unterminated last line)
+    Copy {
+        start: u64,
+        end: u64,
+        ending: &'static [u8],
+
+        /// The line as written ends with `\r\n`
+        crlf: bool,
+    },
+}
+
+#[cfg(feature = "fs")]
+impl NewLine<'_> {
+    /// Bytes written for the line
+    fn len(&self) -> u64 {
+        match self {
+            NewLine::Bytes(line) => line.len() as u64,
+            NewLine::Copy {
+                start, end, ending, ..
+            } => end - start + ending.len() as u64,
+        }
+    }
+
+    /// Whether the line as written ends with `\r\n`
+    fn crlf(&self) -> bool {
+        match self {
+            NewLine::Bytes(line) => line.ends_with(b"\r\n"),
+            NewLine::Copy { crlf, .. } => *crlf,
+        }
+    }
+
+    /// Write the line; a `Copy` is read from `source`
+    fn write_to<S: Read + Seek, W: Write>(
+        &self,
+        source: &mut S,
+        writer: &mut W,
+    ) -> Result<(), ToggleCommentError> {
+        let write = |writer: &mut W, bytes: &[u8]| {
+            writer
+                .write_all(bytes)
+                .map_err(|_| ToggleCommentError::IoError(IoOperation::Write))
+        };
+        let (start, end, ending) = match *self {
+            NewLine::Bytes(line) => return write(writer, line),
+            NewLine::Copy {
+                start, end, ending, ..
+            } => (start, end, ending),
+        };
+
+        if source.seek(std::io::SeekFrom::Start(start)).is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        for _ in start..end {
+            match read_single_byte(source)? {
+                Some(byte) => write(writer, &[byte])?,
+                // Input shorter than at the scan: changed in between
+                None => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+            }
+        }
+        write(writer, ending)
+    }
+}
+
+/// One whole-line edit for `write_line_edit_in()`
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineEdit<'a> {
+    /// Write `line` (its ending included) so that it becomes line `index`;
+    /// `index` may be the line count (append)
+    Insert { index: usize, line: NewLine<'a> },
+
+    /// Drop line `index` and its line ending, only if the line (without its
+    /// ending) is `expected`, when given
+    Remove {
+        index: usize,
+        expected: Option<&'a [u8]>,
+    },
+
+    /// Drop line `from` and write `line` (its copy, ending included) so that
+    /// it becomes line `to` of the result
+    Move {
+        from: usize,
+        to: usize,
+        line: NewLine<'a>,
+    },
+
+    /// Replace bytes `start..end` of the file with `insert` (joins, splits)
+    Splice {
+        start: u64,
+        end: u64,
+        insert: &'a [u8],
+    },
+}
+
+/// A full line: optional `style.indent`, `content`, then `\n` or `\r\n`
 ///
-/// Each line is toggled independently based on its current state.
-/// Range is automatically sorted - argument order doesn't matter.
+/// # Returns
+/// * `(buffer, len)` - The line is `buffer[..len]` (no heap allocation)
+#[cfg(feature = "fs")]
+fn styled_line(
+    content: &[u8],
+    style: &MarkerStyle,
+    indented: bool,
+) -> ([u8; MAX_STYLED_LINE], usize) {
+    let indent: &[u8] = if indented {
+        &style.indent[..style.indent_len]
+    } else {
+        b""
+    };
+    let ending: &[u8] = if style.crlf { b"\r\n" } else { b"\n" };
+    let mut buffer = [0u8; MAX_STYLED_LINE];
+    let mut len: usize = 0;
+    for byte in indent.iter().chain(content.iter()).chain(ending.iter()) {
+        if len == MAX_STYLED_LINE {
+            break;
+        }
+        buffer[len] = *byte;
+        len += 1;
+    }
+    (buffer, len)
+}
+
+/// Copy `reader` to `writer` with one whole line inserted, removed or moved
 ///
-/// # Arguments
-/// * `file_path` - Path to the source file
-/// * `start_line` - First line to toggle (will be sorted with end_line)
-/// * `end_line` - Last line to toggle (will be sorted with start_line)
+/// A line is everything up to and including its `\n`; a final line without
+/// one counts. Appending after such a line first ends it like `line` ends.
+/// The lines edited must not be protected (see PROTECTED REGIONS).
+/// `copy_source` is a second handle on the same input, read only for a
+/// `NewLine::Copy`.
 ///
 /// # Returns
-/// * `Ok(())` - All lines in range toggled successfully
-/// * `Err(ToggleCommentError)` - Specific error code
-///
-/// # Algorithm
-/// ```text
-/// for each line in start..=end:
-///     toggle_basic_singleline_comment_bytewise(file, line)
-/// ```
+/// * `Ok((inserted, removed))` - Bytes written for the new line, bytes dropped
+/// * `Err(LineNotFound)` - A position is past the end (insert: past the line count)
+/// * `Err(LineContentMismatch)` - The line to remove is not the expected one
+/// * `Err(ProtectedRegion)` - An edited line is protected
+/// * `Err(ToggleCommentError)` - Read / write failed or iteration limit reached
+#[cfg(feature = "fs")]
+fn write_line_edit_in<R: Read, S: Read + Seek, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    edit: LineEdit,
+    copy_source: &mut S,
+) -> Result<(u64, u64), ToggleCommentError> {
+    let write = |writer: &mut W, bytes: &[u8]| {
+        writer
+            .write_all(bytes)
+            .map_err(|_| ToggleCommentError::IoError(IoOperation::Write))
+    };
+
+    // The new line goes before original line `insert.0`; `remove` is dropped
+    let (insert, remove, expected) = match edit {
+        LineEdit::Insert { index, line } => (Some((index, line)), None, None),
+        LineEdit::Remove { index, expected } => (None, Some(index), expected),
+        LineEdit::Move { from, to, line } => {
+            let before = if from < to { to + 1 } else { to };
+            (Some((before, line)), Some(from), None)
+        }
+        LineEdit::Splice { start, end, insert } => {
+            return write_spliced_in(reader, writer, start, end, insert);
+        }
+    };
+
+    let mut protection = ProtectedRegionTracker::new();
+    let mut current_line: usize = 0;
+    let mut at_line_start = true;
+    let mut insert_pending = insert.is_some();
+    let mut output_mid_line = false;
+    let mut inserted_bytes: u64 = 0;
+    let mut removed_bytes: u64 = 0;
+    let mut byte_count: u64 = 0;
+    // Bytes of the expected content matched on the removed line (None: differs)
+    let mut expected_match: Option<usize> = Some(0);
+    let mut removed_cr = false;
+
+    loop {
+        if at_line_start
+            && let Some((before, line)) = insert
+            && insert_pending
+            && before == current_line
+        {
+            if protection.in_region() {
+                return Err(ToggleCommentError::ProtectedRegion { line: before });
+            }
+            line.write_to(copy_source, writer)?;
+            inserted_bytes = line.len();
+            insert_pending = false;
+        }
+
+        let byte = match read_single_byte(reader)? {
+            Some(byte) => byte,
+            None => break,
+        };
+
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if at_line_start && cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+        protection.push_byte(byte);
+
+        if remove == Some(current_line) {
+            removed_bytes += 1;
+            if let Some(expected) = expected
+                && byte != b'\n'
+            {
+                expected_match = match expected_match {
+                    Some(_) if removed_cr => None,
+                    Some(n) if n < expected.len() && expected[n] == byte => Some(n + 1),
+                    Some(n) if n == expected.len() && byte == b'\r' => {
+                        removed_cr = true;
+                        Some(n)
+                    }
+                    _ => None,
+                };
+            }
+        } else {
+            write(writer, &[byte])?;
+            output_mid_line = byte != b'\n';
+        }
+
+        at_line_start = byte == b'\n';
+        if at_line_start {
+            // Known once the line is read (an `off` sentinel is protected too)
+            if remove == Some(current_line) && protection.line_protected() {
+                return Err(ToggleCommentError::ProtectedRegion { line: current_line });
+            }
+            current_line += 1;
+        }
+    }
+
+    // Last line without a line ending
+    if !at_line_start {
+        protection.finish();
+        if remove == Some(current_line) && protection.line_protected() {
+            return Err(ToggleCommentError::ProtectedRegion { line: current_line });
+        }
+    }
+
+    let file_lines = if at_line_start {
+        current_line
+    } else {
+        current_line + 1
+    };
+    if let Some(index) = remove
+        && index >= file_lines
+    {
+        return Err(ToggleCommentError::LineNotFound {
+            requested: index,
+            file_lines,
+        });
+    }
+    if let Some((before, line)) = insert
+        && insert_pending
+    {
+        if before != file_lines {
+            let requested = match edit {
+                LineEdit::Move { to, .. } => to,
+                _ => before,
+            };
+            return Err(ToggleCommentError::LineNotFound {
+                requested,
+                file_lines,
+            });
+        }
+        if protection.in_region() {
+            return Err(ToggleCommentError::ProtectedRegion { line: before });
+        }
+        if output_mid_line {
+            // Unterminated last line: end it the way the new line ends
+            let ending: &[u8] = if line.crlf() { b"\r\n" } else { b"\n" };
+            write(writer, ending)?;
+            inserted_bytes += ending.len() as u64;
+        }
+        line.write_to(copy_source, writer)?;
+        inserted_bytes += line.len();
+    }
+    if let Some(expected) = expected
+        && expected_match != Some(expected.len())
+    {
+        return Err(ToggleCommentError::LineContentMismatch {
+            line: remove.unwrap_or(0),
+        });
+    }
+    Ok((inserted_bytes, removed_bytes))
+}
+
+/// Copy `reader` to `writer` with bytes `start..end` replaced by `insert`
 ///
-/// # Memory Usage
-/// - Per line: 1 byte buffer
-/// - Total: 1 byte (reused for each line)
-/// - No accumulation, no line counting needed
+/// # Returns
+/// * `Ok((inserted, removed))` - `insert.len()` and `end - start`
+/// * `Err(IoError(Read))` - Input ends before `end` (changed since the scan)
+/// * `Err(ToggleCommentError)` - Read / write failed or iteration limit reached
+#[cfg(feature = "fs")]
+fn write_spliced_in<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    start: u64,
+    end: u64,
+    insert: &[u8],
+) -> Result<(u64, u64), ToggleCommentError> {
+    let write = |writer: &mut W, bytes: &[u8]| {
+        writer
+            .write_all(bytes)
+            .map_err(|_| ToggleCommentError::IoError(IoOperation::Write))
+    };
+
+    let mut offset: u64 = 0;
+    if start == 0 {
+        write(writer, insert)?;
+    }
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        if offset >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        if byte == b'\n' && cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
+        }
+
+        if offset < start || offset >= end {
+            write(writer, &[byte])?;
+        }
+        offset += 1;
+        if offset == start && start > 0 {
+            write(writer, insert)?;
+        }
+    }
+
+    if offset < end {
+        return Err(ToggleCommentError::IoError(IoOperation::Read));
+    }
+    Ok((insert.len() as u64, end - start))
+}
+
+/// Copy line `index` of a file, line ending included
 ///
-/// # Example
-/// ```no_run
-/// use toggle_comment_indent_module::toggle_range_basic_comments_bytewise;
+/// An unterminated last line is given the ending of the line before it, so
+/// the copy can be written anywhere.
 ///
-/// // Toggle lines 5-10 (both inclusive, order doesn't matter)
-/// match toggle_range_basic_comments_bytewise("./src/main.rs", 5, 10) {
-///     Ok(()) => println!("Range toggled"),
-///     Err(e) => eprintln!("Failed: {:?}", e),
-/// }
+/// # Returns
+/// * `Ok((buffer, len))` - The line is `buffer[..len]`
+/// * `Err(LineNotFound)` - File has no line `index`
+/// * `Err(LineTooLong)` - Line longer than MAX_LINE_CONTENT_BYTES
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+#[cfg(feature = "fs")]
+fn copy_of_line(
+    absolute_path: &Path,
+    index: usize,
+) -> Result<([u8; MAX_STYLED_LINE], usize), ToggleCommentError> {
+    let source_file = match File::open(absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+    let mut buffer = [0u8; MAX_STYLED_LINE];
+    let mut len: usize = 0;
+    // Bytes before the `\n`, counted past what fits in `buffer`
+    let mut line_bytes: usize = 0;
+    let mut current_line: usize = 0;
+    let mut byte_count: u64 = 0;
+
+    while let Some(byte) = read_single_byte(&mut reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if current_line == index {
+            if len < MAX_STYLED_LINE {
+                buffer[len] = byte;
+                len += 1;
+            }
+            if byte == b'\n' {
+                break;
+            }
+            line_bytes += 1;
+        } else if byte == b'\n' {
+            current_line += 1;
+        }
+    }
+
+    if current_line < index || len == 0 {
+        return Err(ToggleCommentError::LineNotFound {
+            requested: index,
+            file_lines: current_line + usize::from(len > 0),
+        });
+    }
+    // Content plus the `\r` of a CRLF ending
+    if line_bytes > MAX_LINE_CONTENT_BYTES + 1 {
+        return Err(ToggleCommentError::LineTooLong {
+            line: index,
+            length: line_bytes,
+            max: MAX_LINE_CONTENT_BYTES,
+        });
+    }
+    if buffer[len - 1] != b'\n' {
+        let style = marker_style_of_line(absolute_path, index)?;
+        let ending: &[u8] = if style.crlf { b"\r\n" } else { b"\n" };
+        buffer[len..len + ending.len()].copy_from_slice(ending);
+        len += ending.len();
+    }
+    Ok((buffer, len))
+}
+
+/// Byte span of line `index` of a file, to be copied by the edit itself
 ///
-/// // Same result (auto-sorted):
-/// toggle_range_basic_comments_bytewise("./src/main.rs", 10, 5)?;
-/// ```
+/// Nothing of the line is held, so lines of any length can be copied. An
+/// unterminated last line is given the ending of the line before it, so the
+/// copy can be written anywhere.
 ///
-/// # Behavior
-/// Each line toggled independently:
-/// ```text
-/// Input range [5-7]:
-/// line 5          →  // line 5
-/// // line 6       →  line 6
-/// line 7          →  // line 7
-/// ```
+/// # Returns
+/// * `Ok(NewLine::Copy)` - Span, ending included
+/// * `Err(LineNotFound)` - File has no line `index`
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+#[cfg(feature = "fs")]
+fn copy_of_line_span(
+    absolute_path: &Path,
+    index: usize,
+) -> Result<NewLine<'static>, ToggleCommentError> {
+    let source_file = match File::open(absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut reader = BufReader::with_capacity(IO_BUFFER_SIZE, source_file);
+    let mut offset: u64 = 0;
+    let mut start: u64 = 0;
+    let mut current_line: usize = 0;
+    let mut terminated = false;
+    let mut previous: Option<u8> = None;
+
+    while let Some(byte) = read_single_byte(&mut reader)? {
+        // Safety check
+        if offset >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+        offset += 1;
+
+        if current_line == index {
+            if byte == b'\n' {
+                terminated = true;
+                break;
+            }
+            previous = Some(byte);
+        } else if byte == b'\n' {
+            current_line += 1;
+            start = offset;
+        }
+    }
+
+    if current_line < index || offset == start {
+        return Err(ToggleCommentError::LineNotFound {
+            requested: index,
+            file_lines: current_line + usize::from(offset > start),
+        });
+    }
+    if terminated {
+        return Ok(NewLine::Copy {
+            start,
+            end: offset,
+            ending: b"",
+            crlf: previous == Some(b'\r'),
+        });
+    }
+    let style = marker_style_of_line(absolute_path, index)?;
+    let ending: &'static [u8] = if style.crlf { b"\r\n" } else { b"\n" };
+    Ok(NewLine::Copy {
+        start,
+        end: offset,
+        ending,
+        crlf: style.crlf,
+    })
+}
+
+/// Apply one whole-line edit to a file: backup, temp file, size check, replace
 ///
-/// # Performance Note
-/// This is NOT optimized for performance - it's optimized for:
-/// - Simplicity (easy to understand and maintain)
-/// - Safety (reuses proven single-line bytewise code)
-/// - Memory bounds (constant memory regardless of range size)
+/// The caller has resolved `absolute_path` and done the pre-backup checks
+/// (special file, own artifact, read-only, size limit, protected lines).
 ///
-/// For a 100-line range, this opens the file 100 times. That's OK.
-/// Simple is better than clever.
-pub fn toggle_range_basic_comments_bytewise(
-    file_path: &str,
-    start_line: usize,
-    end_line: usize,
+/// # Returns
+/// * `Ok(())` - Edit applied
+/// * `Err(ToggleCommentError)` - Specific error code; original untouched
+#[cfg(feature = "fs")]
+fn apply_line_edit(
+    absolute_path: &Path,
+    filename: &str,
+    edit: LineEdit,
+    options: &ToggleOptions,
+    trace: StageTrace,
 ) -> Result<(), ToggleCommentError> {
-    // Sort range automatically
-    let (start, end) = sort_range(start_line, end_line);
+    let backup_path = backup_path_for(filename, options);
+    let snapshot = match trace.backup(absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
+    };
+
+    let temp_filename = format!("temp_toggle_line_{}_{}", std::process::id(), filename);
+    let temp_path = temp_file_path(options.temp_dir.as_deref(), &temp_filename);
+    let _remove_temp = TempFileGuard(&temp_path);
+
+    let (inserted_bytes, removed_bytes) = trace.run_streaming(absolute_path, &temp_path, || {
+        let source_file = match File::open(absolute_path) {
+            Ok(f) => f,
+            Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+        };
+        let temp_file = match File::create(&temp_path) {
+            Ok(f) => f,
+            Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+        };
+        let copy_file = match File::open(absolute_path) {
+            Ok(f) => f,
+            Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+        };
+        let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, TimedIo(temp_file));
+        let changed = write_line_edit_in(
+            &mut BufReader::with_capacity(IO_BUFFER_SIZE, TimedIo(source_file)),
+            &mut writer,
+            edit,
+            &mut BufReader::with_capacity(IO_BUFFER_SIZE, copy_file),
+        )?;
+        if writer.flush().is_err() {
+            return Err(ToggleCommentError::IoError(IoOperation::Flush));
+        }
+        Ok(changed)
+    })?;
+
+    if !trace.check(TraceStage::Verify, || {
+        replacement_size_is_plausible(absolute_path, &temp_path, removed_bytes, inserted_bytes)
+    }) {
+        return Err(ToggleCommentError::IncompleteWrite);
+    }
+    if let Err(e) = trace.run(TraceStage::Replace, || {
+        replace_original(
+            &temp_path,
+            absolute_path,
+            options.temp_dir.as_deref(),
+            options.durability,
+            &snapshot,
+        )
+    }) {
+        return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
+    }
+
+    Ok(())
+}
 
-    // Safety check: reasonable range size
-    // This is just a sanity check, not a hard limit
-    let range_size = end.saturating_sub(start).saturating_add(1);
-    if range_size > 10000 {
-        #[cfg(debug_assertions)]
-        eprintln!(
-            "Warning: Large range ({} lines) - this will be slow",
-            range_size
-        );
+/// What `rust_attribute_scan_in()` found above an item line
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RustAttributeScan {
+    /// Line holding exactly the attribute, in the run above the item
+    existing: Option<usize>,
+
+    /// First protected line among `existing` and the item line
+    protected: Option<usize>,
+
+    /// Indentation and line ending of the item line
+    style: MarkerStyle,
+}
+
+/// Scan up to the end of `item_line` for `toggle_rust_attribute_bytewise()`
+///
+/// # Returns
+/// * `Ok(RustAttributeScan)` - Scan result
+/// * `Err(LineNotFound)` - File has no line `item_line`
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+#[cfg(feature = "fs")]
+fn rust_attribute_scan_in<R: Read>(
+    reader: &mut R,
+    item_line: usize,
+    attribute: &[u8],
+) -> Result<RustAttributeScan, ToggleCommentError> {
+    let mut style = MarkerStyle {
+        crlf: false,
+        indent: [0u8; MAX_MARKER_INDENT],
+        indent_len: 0,
+    };
+    let mut protection = ProtectedRegionTracker::new();
+    // Matching attribute line in the current run, and whether it is protected
+    let mut candidate: Option<(usize, bool)> = None;
+    let mut current_line: usize = 0;
+    let mut line_bytes: usize = 0;
+    let mut line_protected = false;
+    let mut line_match = MarkerLineMatch::LINE_START;
+    let mut head = [0u8; 2];
+    let mut head_len: usize = 0;
+    let mut previous: Option<u8> = None;
+    let mut byte_count: u64 = 0;
+
+    let finish = |candidate: Option<(usize, bool)>, item_protected: bool, style| {
+        let existing = candidate.map(|(line, _)| line);
+        let protected = match candidate {
+            Some((line, true)) => Some(line),
+            _ => item_protected.then_some(item_line),
+        };
+        RustAttributeScan {
+            existing,
+            protected,
+            style,
+        }
+    };
+
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
+
+        if line_bytes == 0 {
+            line_protected = protection.in_region();
+        }
+        protection.push_byte(byte);
+        line_bytes += 1;
+
+        if byte == b'\n' {
+            if current_line == item_line {
+                style.crlf = previous == Some(b'\r');
+                return Ok(finish(candidate, line_protected, style));
+            }
+
+            if line_match.is_marker(attribute) {
+                candidate = Some((current_line, line_protected));
+            } else if &head[..head_len] != b"#[" && &head[..head_len] != b"//" {
+                candidate = None; // Code or blank line: the run starts again
+            }
+
+            current_line += 1;
+            line_bytes = 0;
+            line_match = MarkerLineMatch::LINE_START;
+            head_len = 0;
+        } else if current_line == item_line {
+            let in_indent = line_bytes - 1 == style.indent_len;
+            if in_indent && (byte == b' ' || byte == b'\t') && style.indent_len < MAX_MARKER_INDENT
+            {
+                style.indent[style.indent_len] = byte;
+                style.indent_len += 1;
+            }
+        } else {
+            line_match.feed(attribute, byte);
+            let in_indent = head_len == 0 && (byte == b' ' || byte == b'\t');
+            if !in_indent && head_len < head.len() {
+                head[head_len] = byte;
+                head_len += 1;
+            }
+        }
+        previous = Some(byte);
     }
 
-    // Simple loop: toggle each line independently
-    for line_num in start..=end {
-        // If any line fails, propagate the error immediately
-        toggle_basic_singleline_comment_bytewise(file_path, line_num)?;
+    // Last line without a line ending
+    if current_line == item_line && line_bytes > 0 {
+        return Ok(finish(candidate, line_protected, style));
     }
+    Err(ToggleCommentError::LineNotFound {
+        requested: item_line,
+        file_lines: if line_bytes > 0 {
+            current_line + 1
+        } else {
+            current_line
+        },
+    })
+}
 
-    Ok(())
+/// Normalise an attribute argument: `cfg(test)` and `#[cfg(test)]` both give
+/// `#[cfg(test)]` (surrounding whitespace trimmed)
+///
+/// # Returns
+/// * `Ok((buffer, len))` - The attribute is `buffer[..len]`
+/// * `Err(InvalidLineContent)` - Empty, too long, or holds a line break
+fn rust_attribute_text(
+    attribute: &str,
+) -> Result<([u8; MAX_LINE_CONTENT_BYTES], usize), ToggleCommentError> {
+    let trimmed = attribute.trim().as_bytes();
+    let (prefix, body, suffix): (&[u8], &[u8], &[u8]) = if trimmed.starts_with(b"#[") {
+        (b"", trimmed, b"")
+    } else {
+        (b"#[", trimmed, b"]")
+    };
+    let len = prefix.len() + body.len() + suffix.len();
+    let invalid = ToggleCommentError::InvalidLineContent {
+        len: trimmed.len(),
+        max: MAX_LINE_CONTENT_BYTES,
+    };
+    if body.is_empty()
+        || len > MAX_LINE_CONTENT_BYTES
+        || body.contains(&b'\n')
+        || body.contains(&b'\r')
+    {
+        return Err(invalid);
+    }
+
+    let mut buffer = [0u8; MAX_LINE_CONTENT_BYTES];
+    for (slot, byte) in buffer
+        .iter_mut()
+        .zip(prefix.iter().chain(body.iter()).chain(suffix.iter()))
+    {
+        *slot = *byte;
+    }
+    if len < 3 || buffer[len - 1] != b']' {
+        return Err(invalid);
+    }
+    Ok((buffer, len))
 }
 
-/// Toggle Rust docstrings on a range of lines using simple loop (bytewise)
+/// Toggle a Rust attribute line (`#[cfg(test)]`, `#[ignore]`, ...) above an item
 ///
 /// # Overview
-/// **Simple implementation:** Calls `toggle_rust_docstring_singleline_comment_bytewise()`
-/// once for each line in the range. No optimization, no single-pass complexity.
-/// File is opened/closed multiple times - this is intentional for simplicity.
-///
-/// Each line is toggled independently based on its current state.
-/// Range is automatically sorted - argument order doesn't matter.
+/// Looks at the attribute and `//` comment lines directly above `item_line`
+/// (usually a `fn`): when one of them is exactly the attribute it is
+/// removed, otherwise the attribute is inserted directly above the item,
+/// indented like it and with its line ending. Calling this twice restores
+/// the file byte for byte.
 ///
 /// # Arguments
-/// * `file_path` - Path to the source file
-/// * `start_line` - First line to toggle (will be sorted with end_line)
-/// * `end_line` - Last line to toggle (will be sorted with start_line)
+/// * `file_path` - Path to a `.rs` file (relative or absolute)
+/// * `item_line` - Zero-indexed line of the item (`fn`, `mod`, `struct`, ...)
+/// * `attribute` - `cfg(test)` or `#[cfg(test)]` (one line, at most
+///   MAX_LINE_CONTENT_BYTES)
 ///
 /// # Returns
-/// * `Ok(())` - All lines in range toggled successfully
-/// * `Err(ToggleCommentError)` - Specific error code
-///
-/// # Algorithm
-/// ```text
-/// for each line in start..=end:
-///     toggle_rust_docstring_singleline_comment_bytewise(file, line)
-/// ```
-///
-/// # Memory Usage
-/// - Per line: 1 byte buffer
-/// - Total: 1 byte (reused for each line)
+/// * `Ok(LineToggleDirection::Add)` - Attribute line inserted at `item_line`
+/// * `Ok(LineToggleDirection::Remove)` - Attribute line removed
+/// * `Err(NotRustSource)` - Not a `.rs` file (see `ToggleOptions::allow_any_extension`)
+/// * `Err(InvalidLineContent)` - Attribute empty, too long, or multi-line
+/// * `Err(ToggleCommentError)` - Other specific error code
 ///
 /// # Example
 /// ```no_run
-/// use toggle_comment_indent_module::toggle_range_rust_docstring_bytewise;
-///
-/// // Toggle docstrings on lines 5-10
-/// match toggle_range_rust_docstring_bytewise("./src/lib.rs", 5, 10) {
-///     Ok(()) => println!("Docstrings toggled"),
-///     Err(e) => eprintln!("Failed: {:?}", e),
-/// }
+/// toggle_rust_attribute_bytewise("./src/lib.rs", 42, "ignore")?; // adds #[ignore]
+/// toggle_rust_attribute_bytewise("./src/lib.rs", 43, "ignore")?; // removes it again
 /// ```
-///
-/// # Note
-/// No file extension validation - works on any file type.
-/// Caller responsible for using on appropriate files.
-pub fn toggle_range_rust_docstring_bytewise(
+#[cfg(feature = "fs")]
+pub fn toggle_rust_attribute_bytewise(
     file_path: &str,
-    start_line: usize,
-    end_line: usize,
-) -> Result<(), ToggleCommentError> {
-    // Sort range automatically
-    let (start, end) = sort_range(start_line, end_line);
-
-    // Safety check: reasonable range size
-    let range_size = end.saturating_sub(start).saturating_add(1);
-    if range_size > 10000 {
-        #[cfg(debug_assertions)]
-        eprintln!(
-            "Warning: Large range ({} lines) - this will be slow",
-            range_size
-        );
-    }
-
-    // Simple loop: toggle each line independently
-    for line_num in start..=end {
-        toggle_rust_docstring_singleline_comment_bytewise(file_path, line_num)?;
-    }
-
-    Ok(())
+    item_line: usize,
+    attribute: &str,
+) -> Result<LineToggleDirection, ToggleCommentError> {
+    toggle_rust_attribute_bytewise_with_options(
+        file_path,
+        item_line,
+        attribute,
+        &ToggleOptions::default(),
+    )
 }
 
-// ============================================================================
-// TESTS - PHASE 1: RANGE TOGGLE BYTEWISE
-// ============================================================================
-
-#[cfg(test)]
-mod range_toggle_bytewise_tests {
-    use super::*;
-
-    // ========================================
-    // Basic Range Toggle Tests
-    // ========================================
+/// Same as `toggle_rust_attribute_bytewise()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn toggle_rust_attribute_bytewise_with_options(
+    file_path: &str,
+    item_line: usize,
+    attribute: &str,
+    options: &ToggleOptions,
+) -> Result<LineToggleDirection, ToggleCommentError> {
+    let (attribute_buffer, attribute_len) = rust_attribute_text(attribute)?;
+    let attribute = &attribute_buffer[..attribute_len];
 
-    #[test]
-    fn test_range_bytewise_basic_all_off() {
-        let content = "line 0\nline 1\nline 2\nline 3\n";
-        let test_file = create_test_file("test_range_bw_all_off.rs", content);
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
 
-        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 2);
-        assert!(result.is_ok());
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "line 0\n// line 1\n// line 2\nline 3\n");
+    check_docstring_target(&absolute_path, options)?;
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_all_off.rs"),
-        ]);
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let scan = rust_attribute_scan_in(
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, source_file),
+        item_line,
+        attribute,
+    )?;
+    if let Some(line) = scan.protected {
+        return Err(ToggleCommentError::ProtectedRegion { line });
     }
 
-    #[test]
-    fn test_range_bytewise_basic_all_on() {
-        let content = "line 0\n// line 1\n// line 2\nline 3\n";
-        let test_file = create_test_file("test_range_bw_all_on.rs", content);
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
 
-        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 2);
-        assert!(result.is_ok());
+    let trace = StageTrace::new(options, "attribute");
+    let (line_buffer, line_len) = styled_line(attribute, &scan.style, true);
+    let (edit, direction) = match scan.existing {
+        Some(line) => (
+            LineEdit::Remove {
+                index: line,
+                expected: None,
+            },
+            LineToggleDirection::Remove,
+        ),
+        None => (
+            LineEdit::Insert {
+                index: item_line,
+                line: NewLine::Bytes(&line_buffer[..line_len]),
+            },
+            LineToggleDirection::Add,
+        ),
+    };
+    apply_line_edit(&absolute_path, &filename, edit, options, trace)?;
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "line 0\nline 1\nline 2\nline 3\n");
+    Ok(direction)
+}
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_all_on.rs"),
-        ]);
-    }
+/// A public whole-line operation, before its line bytes are known
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WholeLineOp<'a> {
+    /// `insert_line()`: bare content, given the line ending of the line it
+    /// displaces (of the last line when appending)
+    Insert { index: usize, content: &'a [u8] },
 
-    #[test]
-    fn test_range_bytewise_basic_mixed() {
-        let content = "line 0\n// line 1\nline 2\n// line 3\nline 4\n";
-        let test_file = create_test_file("test_range_bw_mixed.rs", content);
+    /// `remove_line()`
+    Remove { index: usize, expected: &'a [u8] },
 
-        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 3);
-        assert!(result.is_ok());
+    /// `duplicate_line()`: a copy inserted above the line
+    Duplicate { index: usize },
 
-        let new_content = read_file_content(&test_file);
-        // Each line toggled independently
-        assert_eq!(new_content, "line 0\nline 1\n// line 2\nline 3\nline 4\n");
+    /// `move_line()`
+    Move { from: usize, to: usize },
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_mixed.rs"),
-        ]);
-    }
+    /// `join_line()`: line `index` and the next one
+    Join { index: usize },
 
-    #[test]
-    fn test_range_bytewise_reversed_input() {
-        let content = "line 0\nline 1\nline 2\nline 3\n";
-        let test_file = create_test_file("test_range_bw_reversed.rs", content);
+    /// `split_line()`
+    Split { index: usize, column: usize },
 
-        // Reversed input: 2, 1 (should auto-sort to 1, 2)
-        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 2, 1);
-        assert!(result.is_ok());
+    /// `convert_comment_style()`
+    Convert { index: usize, to: CommentStyle },
+}
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "line 0\n// line 1\n// line 2\nline 3\n");
+/// Shared body of the public whole-line operations
+///
+/// The edit is first streamed into a sink, so a bad position, a mismatching
+/// line or a protected line fails before any backup is made.
+#[cfg(feature = "fs")]
+fn edit_whole_line(
+    file_path: &str,
+    op: WholeLineOp,
+    options: &ToggleOptions,
+    operation: &'static str,
+) -> Result<(), ToggleCommentError> {
+    // Convert to absolute path
+    let absolute_path = match Path::new(file_path).canonicalize() {
+        Ok(p) => p,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return Err(ToggleCommentError::FileNotFound);
+            }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
+            return Err(ToggleCommentError::PathError);
+        }
+    };
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_reversed.rs"),
-        ]);
-    }
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
+
+    let line_buffer: [u8; MAX_STYLED_LINE];
+    let line_len;
+    let edit = match op {
+        WholeLineOp::Insert { index, content } => {
+            if content.len() > MAX_LINE_CONTENT_BYTES
+                || content.contains(&b'\n')
+                || content.contains(&b'\r')
+            {
+                return Err(ToggleCommentError::InvalidLineContent {
+                    len: content.len(),
+                    max: MAX_LINE_CONTENT_BYTES,
+                });
+            }
+            let style = marker_style_of_line(&absolute_path, index)?;
+            (line_buffer, line_len) = styled_line(content, &style, false);
+            LineEdit::Insert {
+                index,
+                line: NewLine::Bytes(&line_buffer[..line_len]),
+            }
+        }
+        WholeLineOp::Remove { index, expected } => LineEdit::Remove {
+            index,
+            expected: Some(expected),
+        },
+        WholeLineOp::Duplicate { index } => LineEdit::Insert {
+            index,
+            line: copy_of_line_span(&absolute_path, index)?,
+        },
+        WholeLineOp::Move { from, to } => LineEdit::Move {
+            from,
+            to,
+            line: copy_of_line_span(&absolute_path, from)?,
+        },
+        WholeLineOp::Join { index } => {
+            line_len = 1;
+            line_buffer = [b' '; MAX_STYLED_LINE];
+            let (start, end, separated) = join_splice(&absolute_path, index)?;
+            LineEdit::Splice {
+                start,
+                end,
+                insert: &line_buffer[..if separated { line_len } else { 0 }],
+            }
+        }
+        WholeLineOp::Split { index, column } => {
+            let (start, end);
+            (start, end, line_buffer, line_len) = split_splice(&absolute_path, index, column)?;
+            LineEdit::Splice {
+                start,
+                end,
+                insert: &line_buffer[..line_len],
+            }
+        }
+        WholeLineOp::Convert { index, to } => {
+            let (start, end);
+            (start, end, line_buffer, line_len) = convert_splice(&absolute_path, index, to)?;
+            LineEdit::Splice {
+                start,
+                end,
+                insert: &line_buffer[..line_len],
+            }
+        }
+    };
 
-    #[test]
-    fn test_range_bytewise_single_line() {
-        let content = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_range_bw_single.rs", content);
+    // Dry run: position, expected content and protection, before the backup
+    let source_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let copy_file = match File::open(&absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    write_line_edit_in(
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, source_file),
+        &mut std::io::sink(),
+        edit,
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, copy_file),
+    )?;
+
+    // Moving a line onto itself: checked, nothing to write
+    if let WholeLineOp::Move { from, to } = op
+        && from == to
+    {
+        return Ok(());
+    }
 
-        // Range of one line (1, 1)
-        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 1);
-        assert!(result.is_ok());
+    // Get filename for backup naming
+    let filename = match absolute_path.file_name() {
+        Some(name) => safe_name_component(name),
+        None => return Err(ToggleCommentError::PathError),
+    };
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "line 0\n// line 1\nline 2\n");
+    let trace = StageTrace::new(options, operation);
+    apply_line_edit(&absolute_path, &filename, edit, options, trace)?;
+    Ok(())
+}
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_single.rs"),
-        ]);
-    }
+/// Insert a whole line into a file
+///
+/// # Overview
+/// `content` becomes line `index`: the line there and every later line move
+/// down by one; `index` equal to the line count appends. The new line ends
+/// like the line it displaces (`\n` or `\r\n`). Works on any file, with or
+/// without an extension: no comment syntax is involved.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `index` - Zero-indexed line the content becomes
+/// * `content` - Line text without a line ending (at most
+///   MAX_LINE_CONTENT_BYTES; empty inserts a blank line)
+///
+/// # Returns
+/// * `Ok(())` - Line inserted
+/// * `Err(LineNotFound)` - `index` is past the line count
+/// * `Err(InvalidLineContent)` - Content too long or holds a line break
+/// * `Err(ProtectedRegion)` - Line `index` is in a protected region
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// insert_line("./src/main.rs", 0, "// Generated file - do not edit")?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn insert_line(file_path: &str, index: usize, content: &str) -> Result<(), ToggleCommentError> {
+    insert_line_with_options(file_path, index, content, &ToggleOptions::default())
+}
 
-    #[test]
-    fn test_range_bytewise_preserves_outside() {
-        let content = "line 0\nline 1\nline 2\nline 3\nline 4\n";
-        let test_file = create_test_file("test_range_bw_preserve.rs", content);
+/// Same as `insert_line()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn insert_line_with_options(
+    file_path: &str,
+    index: usize,
+    content: &str,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    let op = WholeLineOp::Insert {
+        index,
+        content: content.as_bytes(),
+    };
+    edit_whole_line(file_path, op, options, "insert-line")
+}
 
-        // Toggle only middle lines
-        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 1, 3);
-        assert!(result.is_ok());
+/// Remove a whole line from a file, if it holds the expected content
+///
+/// # Overview
+/// Line `index` and its line ending are removed when the line, without its
+/// ending, is exactly `expected_content` (a CRLF ending is fine); otherwise
+/// nothing changes. Naming the content keeps a stale line number from
+/// deleting the wrong line.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `index` - Zero-indexed line to remove
+/// * `expected_content` - Exact text of that line, without its ending
+///
+/// # Returns
+/// * `Ok(())` - Line removed
+/// * `Err(LineNotFound)` - File has no line `index`
+/// * `Err(LineContentMismatch)` - The line holds something else; not removed
+/// * `Err(ProtectedRegion)` - Line `index` is in a protected region
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// remove_line("./src/main.rs", 0, "// Generated file - do not edit")?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn remove_line(
+    file_path: &str,
+    index: usize,
+    expected_content: &str,
+) -> Result<(), ToggleCommentError> {
+    remove_line_with_options(
+        file_path,
+        index,
+        expected_content,
+        &ToggleOptions::default(),
+    )
+}
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(
-            new_content,
-            "line 0\n// line 1\n// line 2\n// line 3\nline 4\n"
-        );
+/// Same as `remove_line()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn remove_line_with_options(
+    file_path: &str,
+    index: usize,
+    expected_content: &str,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    let op = WholeLineOp::Remove {
+        index,
+        expected: expected_content.as_bytes(),
+    };
+    edit_whole_line(file_path, op, options, "remove-line")
+}
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_preserve.rs"),
-        ]);
-    }
+/// Duplicate a line: an exact copy is inserted directly below it
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `index` - Zero-indexed line to duplicate (any length: the copy is streamed)
+///
+/// # Returns
+/// * `Ok(())` - Line `index + 1` is now a copy of line `index`
+/// * `Err(LineNotFound)` - File has no line `index`
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// duplicate_line("./src/main.rs", 12)?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn duplicate_line(file_path: &str, index: usize) -> Result<(), ToggleCommentError> {
+    duplicate_line_with_options(file_path, index, &ToggleOptions::default())
+}
 
-    #[test]
-    fn test_range_bytewise_roundtrip() {
-        let original = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_range_bw_roundtrip.rs", original);
+/// Same as `duplicate_line()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn duplicate_line_with_options(
+    file_path: &str,
+    index: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    edit_whole_line(
+        file_path,
+        WholeLineOp::Duplicate { index },
+        options,
+        "duplicate",
+    )
+}
 
-        // Toggle on
-        let result1 = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 0, 2);
-        assert!(result1.is_ok());
+/// Move a line so that it becomes line `to`; the lines between shift by one
+///
+/// A moved last line without a line ending gets the file's line ending.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `from` - Zero-indexed line to move (any length: the copy is streamed)
+/// * `to` - Zero-indexed position of the line afterwards
+///
+/// # Returns
+/// * `Ok(())` - Line moved (`from == to`: checked, file untouched)
+/// * `Err(LineNotFound)` - File has no line `from` or `to`
+/// * `Err(ProtectedRegion)` - `from` or the new position is protected
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// move_line("./src/main.rs", 12, 3)?; // line 12 is now line 3
+/// ```
+#[cfg(feature = "fs")]
+pub fn move_line(file_path: &str, from: usize, to: usize) -> Result<(), ToggleCommentError> {
+    move_line_with_options(file_path, from, to, &ToggleOptions::default())
+}
 
-        let content1 = read_file_content(&test_file);
-        assert_eq!(content1, "// line 0\n// line 1\n// line 2\n");
+/// Same as `move_line()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn move_line_with_options(
+    file_path: &str,
+    from: usize,
+    to: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    edit_whole_line(file_path, WholeLineOp::Move { from, to }, options, "move")
+}
 
-        // Toggle off
-        let result2 = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 0, 2);
-        assert!(result2.is_ok());
+// ============================================================================
+// TESTS - PHASE 16: WHOLE-LINE EDITS
+// ============================================================================
 
-        let content2 = read_file_content(&test_file);
-        assert_eq!(content2, original);
+#[cfg(all(test, feature = "fs"))]
+mod line_edit_tests {
+    use super::*;
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_roundtrip.rs"),
-        ]);
+    fn edited(input: &str, edit: LineEdit) -> Result<String, ToggleCommentError> {
+        let mut output = Vec::new();
+        write_line_edit_in(
+            &mut input.as_bytes(),
+            &mut output,
+            edit,
+            &mut std::io::Cursor::new(input.as_bytes()),
+        )?;
+        Ok(String::from_utf8(output).unwrap())
     }
 
     #[test]
-    fn test_range_bytewise_python() {
-        let content = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_range_bw_python.py", content);
+    fn test_write_line_edit_in() {
+        let insert = |index| LineEdit::Insert {
+            index,
+            line: NewLine::Bytes(b"new\n"),
+        };
+        assert_eq!(edited("a\nb\n", insert(1)).as_deref(), Ok("a\nnew\nb\n"));
+        assert_eq!(edited("a\nb\n", insert(2)).as_deref(), Ok("a\nb\nnew\n"));
+        assert_eq!(edited("a\nb", insert(2)).as_deref(), Ok("a\nb\nnew\n"));
+        assert_eq!(edited("", insert(0)).as_deref(), Ok("new\n"));
+        assert_eq!(
+            edited("a\n", insert(2)),
+            Err(ToggleCommentError::LineNotFound {
+                requested: 2,
+                file_lines: 1
+            })
+        );
 
-        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 0, 1);
-        assert!(result.is_ok());
+        let remove = |index, expected: &'static str| LineEdit::Remove {
+            index,
+            expected: Some(expected.as_bytes()),
+        };
+        assert_eq!(
+            edited("a\r\nb\r\nc", remove(1, "b")).as_deref(),
+            Ok("a\r\nc")
+        );
+        assert_eq!(edited("a\nb", remove(1, "b")).as_deref(), Ok("a\n"));
+        assert!(matches!(
+            edited("a\nb\n", remove(2, "")),
+            Err(ToggleCommentError::LineNotFound { .. })
+        ));
+        for stale in ["", "a", "bb"] {
+            assert_eq!(
+                edited("a\nb\r\n", remove(1, stale)),
+                Err(ToggleCommentError::LineContentMismatch { line: 1 })
+            );
+        }
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "# line 0\n# line 1\nline 2\n");
+        // Protected lines are refused
+        let fenced = "a\n// toggle_comment: off\nb\n// toggle_comment: on\n";
+        assert_eq!(
+            edited(fenced, insert(2)),
+            Err(ToggleCommentError::ProtectedRegion { line: 2 })
+        );
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_python.py"),
-        ]);
+        // Moves both ways, to the end, and past it
+        let moved = |from, to| LineEdit::Move {
+            from,
+            to,
+            line: NewLine::Bytes(b"b\n"),
+        };
+        assert_eq!(edited("a\nb\nc\n", moved(1, 0)).as_deref(), Ok("b\na\nc\n"));
+        assert_eq!(edited("a\nb\nc\n", moved(1, 2)).as_deref(), Ok("a\nc\nb\n"));
+        assert_eq!(edited("a\nb\nc", moved(1, 2)).as_deref(), Ok("a\nc\nb\n"));
+        // A copy streamed from the input itself (bytes 2..5: "bb\n")
+        let copied = LineEdit::Move {
+            from: 1,
+            to: 0,
+            line: NewLine::Copy {
+                start: 2,
+                end: 5,
+                ending: b"",
+                crlf: false,
+            },
+        };
+        assert_eq!(edited("a\nbb\nc\n", copied).as_deref(), Ok("bb\na\nc\n"));
+        assert_eq!(
+            edited("a\nb\nc\n", moved(1, 3)),
+            Err(ToggleCommentError::LineNotFound {
+                requested: 3,
+                file_lines: 3
+            })
+        );
     }
 
     #[test]
-    fn test_range_bytewise_line_not_found() {
-        let content = "line 0\nline 1\n";
-        let test_file = create_test_file("test_range_bw_notfound.rs", content);
+    fn test_insert_and_remove_line() {
+        let original = "alpha\r\nbeta\r\n";
+        let test_file = create_test_file("test_insert_remove_line.txt", original);
+        let path = test_file.to_str().unwrap();
+
+        // Line ending copied from the file; appending at the line count works
+        assert_eq!(insert_line(path, 1, "// banner"), Ok(()));
+        assert_eq!(insert_line(path, 3, ""), Ok(()));
+        assert_eq!(
+            read_file_content(&test_file),
+            "alpha\r\n// banner\r\nbeta\r\n\r\n"
+        );
 
-        // Range extends beyond file
-        let result = toggle_range_basic_comments_bytewise(test_file.to_str().unwrap(), 0, 10);
+        // Stale position or content: nothing removed
+        assert_eq!(
+            remove_line(path, 2, "// banner"),
+            Err(ToggleCommentError::LineContentMismatch { line: 2 })
+        );
         assert!(matches!(
-            result,
+            insert_line(path, 9, "x"),
             Err(ToggleCommentError::LineNotFound { .. })
         ));
+        assert!(matches!(
+            insert_line(path, 0, "two\nlines"),
+            Err(ToggleCommentError::InvalidLineContent { .. })
+        ));
+
+        assert_eq!(remove_line(path, 3, ""), Ok(()));
+        assert_eq!(remove_line(path, 1, "// banner"), Ok(()));
+        assert_eq!(read_file_content(&test_file), original);
 
         cleanup_files(&[
             &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_notfound.rs"),
+            &PathBuf::from("backup_toggle_comment_test_insert_remove_line.txt"),
         ]);
     }
 
-    // ========================================
-    // Docstring Range Toggle Tests
-    // ========================================
-
     #[test]
-    fn test_range_bytewise_docstring_basic() {
-        let content = "line 0\nline 1\nline 2\nline 3\n";
-        let test_file = create_test_file("test_range_bw_doc.rs", content);
+    fn test_duplicate_and_move_line() {
+        let test_file = create_test_file("test_duplicate_move_line.txt", "one\r\ntwo\r\nthree");
+        let path = test_file.to_str().unwrap();
 
-        let result = toggle_range_rust_docstring_bytewise(test_file.to_str().unwrap(), 1, 2);
-        assert!(result.is_ok());
+        // The unterminated last line is copied with the file's ending
+        assert_eq!(duplicate_line(path, 2), Ok(()));
+        assert_eq!(
+            read_file_content(&test_file),
+            "one\r\ntwo\r\nthree\r\nthree"
+        );
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "line 0\n/// line 1\n/// line 2\nline 3\n");
+        assert_eq!(move_line(path, 0, 3), Ok(()));
+        assert_eq!(move_line(path, 2, 0), Ok(()));
+        assert_eq!(
+            read_file_content(&test_file),
+            "three\r\ntwo\r\nthree\r\none\r\n"
+        );
+        assert_eq!(move_line(path, 1, 1), Ok(()));
+        assert!(matches!(
+            move_line(path, 0, 4),
+            Err(ToggleCommentError::LineNotFound { .. })
+        ));
+        assert!(matches!(
+            duplicate_line(path, 4),
+            Err(ToggleCommentError::LineNotFound { .. })
+        ));
+        assert_eq!(
+            read_file_content(&test_file),
+            "three\r\ntwo\r\nthree\r\none\r\n"
+        );
 
         cleanup_files(&[
             &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_doc.rs"),
+            &PathBuf::from("backup_toggle_comment_test_duplicate_move_line.txt"),
         ]);
     }
 
     #[test]
-    fn test_range_bytewise_docstring_mixed() {
-        let content = "line 0\n/// line 1\nline 2\n/// line 3\n";
-        let test_file = create_test_file("test_range_bw_doc_mixed.rs", content);
-
-        let result = toggle_range_rust_docstring_bytewise(test_file.to_str().unwrap(), 1, 3);
-        assert!(result.is_ok());
-
-        let new_content = read_file_content(&test_file);
-        // Each line toggled independently
-        assert_eq!(new_content, "line 0\nline 1\n/// line 2\nline 3\n");
+    fn test_duplicate_and_move_long_line() {
+        // Far over MAX_LINE_CONTENT_BYTES and MAX_LINE_LENGTH: streamed, not refused
+        let long = "x".repeat(MAX_LINE_LENGTH + 1);
+        let test_file = create_test_file("test_duplicate_move_long.txt", &format!("a\n{long}"));
+        let path = test_file.to_str().unwrap();
+
+        assert_eq!(duplicate_line(path, 1), Ok(()));
+        assert_eq!(move_line(path, 0, 2), Ok(()));
+        assert_eq!(
+            read_file_content(&test_file),
+            format!("{long}\n{long}\na\n")
+        );
 
         cleanup_files(&[
             &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_doc_mixed.rs"),
+            &PathBuf::from("backup_toggle_comment_test_duplicate_move_long.txt"),
         ]);
     }
 
     #[test]
-    fn test_range_bytewise_docstring_roundtrip() {
-        let original = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_range_bw_doc_roundtrip.rs", original);
-
-        // Toggle on
-        let result1 = toggle_range_rust_docstring_bytewise(test_file.to_str().unwrap(), 0, 2);
-        assert!(result1.is_ok());
-
-        let content1 = read_file_content(&test_file);
-        assert_eq!(content1, "/// line 0\n/// line 1\n/// line 2\n");
-
-        // Toggle off
-        let result2 = toggle_range_rust_docstring_bytewise(test_file.to_str().unwrap(), 0, 2);
-        assert!(result2.is_ok());
-
-        let content2 = read_file_content(&test_file);
-        assert_eq!(content2, original);
-
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_range_bw_doc_roundtrip.rs"),
-        ]);
-    }
-}
+    fn test_toggle_rust_attribute_roundtrip() {
+        let original = "mod tests {\n    /// Slow\n    #[test]\n    fn slow() {}\n}\n";
+        let test_file = create_test_file("test_rust_attribute.rs", original);
+        let path = test_file.to_str().unwrap();
 
-// ============================================================================
-// PHASE 3: BLOCK COMMENTS - BYTEWISE OPERATIONS
-// ============================================================================
+        // Inserted directly above the fn, with its indentation
+        assert_eq!(
+            toggle_rust_attribute_bytewise(path, 3, "ignore"),
+            Ok(LineToggleDirection::Add)
+        );
+        assert_eq!(
+            read_file_content(&test_file),
+            "mod tests {\n    /// Slow\n    #[test]\n    #[ignore]\n    fn slow() {}\n}\n"
+        );
 
-/// Detect if specific line starts with exact byte pattern at column 0
-///
-/// # Overview
-/// Reads first bytes of target line and compares against expected pattern.
-/// Used for block comment marker detection.
-///
-/// # Arguments
-/// * `file_path` - Path to source file
-/// * `line_number` - Zero-indexed line to check
-/// * `pattern` - Exact byte sequence to match (e.g., b"/*\n")
-///
-/// # Returns
-/// * `Ok(true)` - Line starts with exact pattern at column 0
-/// * `Ok(false)` - Line doesn't match pattern
-/// * `Err(ToggleCommentError)` - File operation failed or line not found
-///
-/// # Example
-/// ```text
-/// File line 5: "/*\n"
-/// detect_line_pattern(path, 5, b"/*\n") → Ok(true)
-///
-/// File line 5: "  /*\n"  (indented)
-/// detect_line_pattern(path, 5, b"/*\n") → Ok(false)
-///
-/// File line 5: "/* comment\n"  (has content after)
-/// detect_line_pattern(path, 5, b"/*\n") → Ok(false)
-/// ```
-pub fn detect_line_pattern(
-    file_path: &str,
-    line_number: usize,
-    pattern: &[u8],
-) -> Result<bool, ToggleCommentError> {
-    // Find line start position
-    let line_start_pos =
-        match find_line_start_position(file_path, line_number).map_err(|e| match e {
-            ToggleIndentError::FileNotFound => ToggleCommentError::FileNotFound,
-            ToggleIndentError::LineNotFound {
-                requested,
-                file_lines,
-            } => ToggleCommentError::LineNotFound {
-                requested,
-                file_lines,
-            },
-            ToggleIndentError::IoError(op) => ToggleCommentError::IoError(op),
-            ToggleIndentError::PathError => ToggleCommentError::PathError,
-        })? {
-            Some(pos) => pos,
-            None => return Ok(false), // Line not found = doesn't match
-        };
+        // Found anywhere in the attribute run above the (shifted) fn line
+        assert_eq!(
+            toggle_rust_attribute_bytewise(path, 4, "#[ignore]"),
+            Ok(LineToggleDirection::Remove)
+        );
+        assert_eq!(read_file_content(&test_file), original);
 
-    let mut file = match File::open(file_path) {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Open)),
-    };
+        // Not line-shaped, or not Rust
+        assert!(matches!(
+            toggle_rust_attribute_bytewise(path, 3, "cfg(\ntest)"),
+            Err(ToggleCommentError::InvalidLineContent { .. })
+        ));
+        let toml_file = create_test_file("test_rust_attribute.toml", "a = 1\n");
+        assert_eq!(
+            toggle_rust_attribute_bytewise(toml_file.to_str().unwrap(), 0, "ignore"),
+            Err(ToggleCommentError::NotRustSource)
+        );
 
-    // Seek to line start
-    if let Err(_) = file.seek(std::io::SeekFrom::Start(line_start_pos)) {
-        return Err(ToggleCommentError::IoError(IoOperation::Read));
+        cleanup_files(&[
+            &test_file,
+            &toml_file,
+            &PathBuf::from("backup_toggle_comment_test_rust_attribute.rs"),
+        ]);
     }
+}
 
-    let mut byte_bucket: [u8; 1] = [0u8; 1];
+// ============================================================================
+// PHASE 17: JOIN AND SPLIT LINES (REFLOWING COMMENTS)
+// ============================================================================
+//
+// ## Project Context
+// Wrappers reflow long comments with two primitives. Joining line N with N+1
+// drops N's trailing whitespace and line ending and N+1's indentation, plus
+// N+1's comment marker when it repeats N's (`// a` + `// b` is `// a b`),
+// with one space between. Splitting line N at a byte column drops the
+// whitespace around the column and starts the new line with N's indentation
+// and comment marker (`// a b` at the `b` gives `// a` and `// b`), ending N
+// with its own line ending.
+//
+// A comment marker is the extension's flag after the indentation, extended by
+// repeats of its last byte or `!` (`///`, `//!`, `##`), then one optional
+// space. A pre-scan streams the two lines (or one) keeping only offsets; the
+// edit is then one splice of the file bytes through the whole-line engine.
+// Results are bounded by MAX_LINE_LENGTH.
+// ============================================================================
 
-    // Compare each byte in pattern
-    for &expected_byte in pattern.iter() {
-        let bytes_read = match file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-        };
+/// Longest comment marker kept for a join or split (`///`, `//!`, `##`, ...)
+#[cfg(feature = "fs")]
+const MAX_COMMENT_MARKER: usize = 8;
 
-        if bytes_read == 0 {
-            return Ok(false); // EOF before pattern complete
-        }
+/// Longest text a split inserts: line ending, indent, marker, space
+#[cfg(feature = "fs")]
+const MAX_SPLIT_PREFIX: usize = 2 + MAX_MARKER_INDENT + MAX_COMMENT_MARKER + 1;
 
-        if byte_bucket[0] != expected_byte {
-            return Ok(false); // Mismatch
-        }
-    }
+/// Offsets of one line, as joins and splits need them (relative to `start`)
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct LineShape {
+    /// Byte offset of the line in the file
+    start: u64,
 
-    // All bytes matched
-    Ok(true)
+    /// The line is in a protected region
+    protected: bool,
+
+    /// Leading spaces / tabs
+    indent_len: usize,
+
+    /// Comment marker after the indentation (`marker[..marker_len]`, empty for code)
+    marker: [u8; MAX_COMMENT_MARKER],
+    marker_len: usize,
+
+    /// One space follows the marker
+    marker_space: bool,
+
+    /// Where the text starts (after indent, marker and its space)
+    body_start: usize,
+
+    /// End of the last byte that is not a space, tab or `\r`
+    content_end: usize,
+
+    /// Bytes before the line ending
+    len: usize,
+
+    /// The line ends in `\n` (or `\r\n`)
+    terminated: bool,
+
+    /// Split column only: text end before it, text start from it (None: the
+    /// column is past the end or inside a UTF-8 character)
+    split: Option<(usize, usize)>,
 }
 
-/// Delete entire line from file (bytewise copy, skip target line)
-///
-/// # Overview
-/// Copies source to dest, skipping all bytes of target line including newline.
-/// Simple three-part copy: before, skip, after.
+/// Where a line stands while `line_shapes_in()` reads it
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShapePhase {
+    Indent,
+    Flag(usize),
+    Marker,
+    Body,
+}
+
+/// Read the shapes of `shapes.len()` consecutive lines from `first_line`
 ///
 /// # Arguments
-/// * `source_path` - Original file
-/// * `dest_path` - Temp file for output
-/// * `line_number` - Zero-indexed line to delete
+/// * `flag` - Comment flag of the extension (empty: no markers)
+/// * `column` - Split column of the first line, if any
 ///
 /// # Returns
-/// * `Ok(())` - Line deleted successfully
-/// * `Err(ToggleCommentError)` - Operation failed
-///
-/// # Memory
-/// - 1 byte buffer only
-/// - No heap allocation
-pub fn delete_line_bytewise(
-    source_path: &Path,
-    dest_path: &Path,
-    line_number: usize,
+/// * `Ok(())` - `shapes` filled
+/// * `Err(LineNotFound)` - The file ends before the last requested line
+/// * `Err(ToggleCommentError)` - Read failed or iteration limit reached
+#[cfg(feature = "fs")]
+fn line_shapes_in<R: Read>(
+    reader: &mut R,
+    first_line: usize,
+    shapes: &mut [LineShape],
+    flag: &[u8],
+    column: Option<usize>,
 ) -> Result<(), ToggleCommentError> {
-    // Find line start position
-    let line_start_pos =
-        match find_line_start_position(source_path.to_str().unwrap_or(""), line_number).map_err(
-            |e| match e {
-                ToggleIndentError::FileNotFound => ToggleCommentError::FileNotFound,
-                ToggleIndentError::LineNotFound {
-                    requested,
-                    file_lines,
-                } => ToggleCommentError::LineNotFound {
-                    requested,
-                    file_lines,
-                },
-                ToggleIndentError::IoError(op) => ToggleCommentError::IoError(op),
-                ToggleIndentError::PathError => ToggleCommentError::PathError,
-            },
-        )? {
-            Some(pos) => pos,
-            None => {
-                return Err(ToggleCommentError::LineNotFound {
-                    requested: line_number,
-                    file_lines: 0,
-                });
-            }
-        };
-
-    let mut source_file = match File::open(source_path) {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Open)),
-    };
-
-    let dest_file = match OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(dest_path)
-    {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Create)),
-    };
+    let mut protection = ProtectedRegionTracker::new();
+    let mut offset: u64 = 0;
+    let mut current_line: usize = 0;
+    let mut found: usize = 0;
+    let mut pos: usize = 0;
+    let mut phase = ShapePhase::Indent;
+    let mut body_start: Option<usize> = None;
+    let mut previous_cr = false;
+    let mut shape = LineShape::default();
+
+    // Split column of the first line: text end before it, first text byte from it
+    let split_column = |found: usize| column.filter(|_| found == 0);
+    let mut split_open: Option<usize> = None;
+    let mut split_right: Option<usize> = None;
+    let mut split_boundary = false;
+
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        if offset >= MAX_BYTE_ITERATIONS {
+            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        }
 
-    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, dest_file);
-    let mut byte_bucket: [u8; 1] = [0u8; 1];
-    let mut byte_position: u64 = 0;
+        if current_line >= first_line && pos == 0 {
+            shape = LineShape {
+                start: offset,
+                protected: protection.in_region(),
+                ..LineShape::default()
+            };
+        }
+        protection.push_byte(byte);
+        offset += 1;
 
-    // PART A: Copy before target line
-    while byte_position < line_start_pos {
-        if byte_position >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
+        if current_line < first_line {
+            if byte == b'\n' {
+                current_line += 1;
+            }
+            continue;
         }
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-        };
+        if byte == b'\n' {
+            shape.terminated = true;
+            shape.len = pos - usize::from(previous_cr);
+        } else {
+            if split_column(found) == Some(pos) {
+                split_open = Some(shape.content_end);
+                split_boundary = byte & 0xC0 != 0x80;
+            }
 
-        if bytes_read == 0 {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
+            let mut at_body = phase == ShapePhase::Body;
+            if phase == ShapePhase::Indent {
+                if byte == b' ' || byte == b'\t' {
+                    shape.indent_len += 1;
+                } else {
+                    phase = ShapePhase::Flag(0);
+                }
+            }
+            if let ShapePhase::Flag(matched) = phase {
+                if matched < flag.len() && flag[matched] == byte {
+                    phase = ShapePhase::Flag(matched + 1);
+                    if matched + 1 == flag.len() {
+                        shape.marker[..flag.len()].copy_from_slice(flag);
+                        shape.marker_len = flag.len();
+                        phase = ShapePhase::Marker;
+                    }
+                } else {
+                    // Not a comment (or no flag): text from the indentation on
+                    shape.marker_len = 0;
+                    body_start = Some(shape.indent_len);
+                    phase = ShapePhase::Body;
+                    at_body = true;
+                }
+            } else if phase == ShapePhase::Marker {
+                let repeat = byte == flag[flag.len() - 1] || byte == b'!';
+                if repeat && shape.marker_len < MAX_COMMENT_MARKER {
+                    shape.marker[shape.marker_len] = byte;
+                    shape.marker_len += 1;
+                } else if byte == b' ' {
+                    shape.marker_space = true;
+                    body_start = Some(pos + 1);
+                    phase = ShapePhase::Body;
+                } else {
+                    body_start = Some(pos);
+                    phase = ShapePhase::Body;
+                    at_body = true;
+                }
+            }
+
+            if byte != b' ' && byte != b'\t' && byte != b'\r' {
+                shape.content_end = pos + 1;
+                if at_body && split_open.is_some() && split_right.is_none() {
+                    split_right = Some(pos);
+                }
+            }
+            previous_cr = byte == b'\r';
+            pos += 1;
         }
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        if shape.terminated {
+            finish_line_shape(
+                &mut shape,
+                body_start,
+                split_open,
+                split_right,
+                split_boundary,
+            );
+            shapes[found] = shape;
+            found += 1;
+            if found == shapes.len() {
+                return Ok(());
+            }
+            current_line += 1;
+            pos = 0;
+            phase = ShapePhase::Indent;
+            body_start = None;
+            previous_cr = false;
+            split_open = None;
+            split_right = None;
         }
+    }
 
-        byte_position += 1;
+    // Last line without a line ending
+    if current_line >= first_line && pos > 0 {
+        shape.len = pos;
+        finish_line_shape(
+            &mut shape,
+            body_start,
+            split_open,
+            split_right,
+            split_boundary,
+        );
+        shapes[found] = shape;
+        found += 1;
+    }
+    if found < shapes.len() {
+        return Err(ToggleCommentError::LineNotFound {
+            requested: first_line + found,
+            file_lines: current_line.max(first_line) + usize::from(pos > 0),
+        });
     }
+    Ok(())
+}
 
-    // PART B: Skip entire target line (don't write anything)
-    loop {
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-        };
+/// Settle the fields of a line shape known only once the line has ended
+#[cfg(feature = "fs")]
+fn finish_line_shape(
+    shape: &mut LineShape,
+    body_start: Option<usize>,
+    split_open: Option<usize>,
+    split_right: Option<usize>,
+    split_boundary: bool,
+) {
+    shape.body_start = body_start.unwrap_or(shape.len).min(shape.len);
+    shape.content_end = shape.content_end.min(shape.len);
+    shape.split = match (split_open, split_right) {
+        (Some(left_end), Some(right_start)) if split_boundary => Some((left_end, right_start)),
+        _ => None,
+    };
+}
 
-        if bytes_read == 0 {
-            break; // EOF - line had no newline
-        }
+/// Comment flag whose markers joins and splits recognise (empty: none)
+#[cfg(feature = "fs")]
+fn reflow_flag(absolute_path: &Path) -> &'static [u8] {
+    absolute_path
+        .extension()
+        .and_then(|ext| determine_comment_flag(&ext.to_string_lossy()))
+        .map(|flag| flag.as_bytes())
+        .unwrap_or(b"")
+}
 
-        if byte_bucket[0] == b'\n' {
-            break; // Found newline - skip it too, line is deleted
-        }
+/// Plan a join of line `index` with the next one
+///
+/// # Returns
+/// * `Ok((start, end, separated))` - Replace bytes `start..end` with a space
+///   (`separated`) or nothing
+/// * `Err(ToggleCommentError)` - LineNotFound, ProtectedRegion, LineTooLong, ...
+#[cfg(feature = "fs")]
+fn join_splice(absolute_path: &Path, index: usize) -> Result<(u64, u64, bool), ToggleCommentError> {
+    let source_file = match File::open(absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut shapes = [LineShape::default(); 2];
+    line_shapes_in(
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, source_file),
+        index,
+        &mut shapes,
+        reflow_flag(absolute_path),
+        None,
+    )?;
+    let [first, second] = shapes;
+    if first.protected {
+        return Err(ToggleCommentError::ProtectedRegion { line: index });
+    }
+    if second.protected {
+        return Err(ToggleCommentError::ProtectedRegion { line: index + 1 });
     }
 
-    // PART C: Copy rest of file
-    let mut remaining_bytes: u64 = 0;
-    loop {
-        if remaining_bytes >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
-        }
+    // Blank first line: the join is the second line as it is
+    if first.content_end == 0 {
+        return Ok((first.start, second.start, false));
+    }
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-        };
+    let same_marker = first.marker_len > 0
+        && first.marker[..first.marker_len] == second.marker[..second.marker_len];
+    let strip = if same_marker {
+        second.body_start
+    } else {
+        second.indent_len
+    };
+    let separated = second.content_end > strip;
+
+    let length = first.content_end + usize::from(separated) + second.len.saturating_sub(strip);
+    if length > MAX_LINE_LENGTH {
+        return Err(ToggleCommentError::LineTooLong {
+            line: index,
+            length,
+            max: MAX_LINE_LENGTH,
+        });
+    }
 
-        if bytes_read == 0 {
-            break;
-        }
+    let start = first.start + first.content_end as u64;
+    let end = if separated {
+        second.start + strip as u64
+    } else {
+        second.start + second.len as u64
+    };
+    Ok((start, end, separated))
+}
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleCommentError::IoError(IoOperation::Write));
+/// Plan a split of line `index` at byte `column`
+///
+/// # Returns
+/// * `Ok((start, end, buffer, len))` - Replace bytes `start..end` with
+///   `buffer[..len]` (line ending, indent, marker)
+/// * `Err(ToggleCommentError)` - LineNotFound, InvalidSplitColumn, ProtectedRegion, ...
+#[cfg(feature = "fs")]
+fn split_splice(
+    absolute_path: &Path,
+    index: usize,
+    column: usize,
+) -> Result<(u64, u64, [u8; MAX_STYLED_LINE], usize), ToggleCommentError> {
+    let source_file = match File::open(absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut shapes = [LineShape::default(); 1];
+    line_shapes_in(
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, source_file),
+        index,
+        &mut shapes,
+        reflow_flag(absolute_path),
+        Some(column),
+    )?;
+    let line = shapes[0];
+    if line.protected {
+        return Err(ToggleCommentError::ProtectedRegion { line: index });
+    }
+
+    let (left_end, right_start) = match line.split {
+        Some((left_end, right_start))
+            if left_end > line.body_start && right_start < line.content_end =>
+        {
+            (left_end, right_start)
+        }
+        _ => {
+            return Err(ToggleCommentError::InvalidSplitColumn {
+                line: index,
+                column,
+            });
         }
+    };
 
-        remaining_bytes += 1;
+    // New line: N's ending (the previous line's if N has none), indent, marker
+    let style = marker_style_of_line(absolute_path, index)?;
+    let ending: &[u8] = if style.crlf { b"\r\n" } else { b"\n" };
+    let space: &[u8] = if line.marker_space { b" " } else { b"" };
+    let mut buffer = [0u8; MAX_STYLED_LINE];
+    let mut len: usize = 0;
+    for byte in ending
+        .iter()
+        .chain(style.indent[..style.indent_len].iter())
+        .chain(line.marker[..line.marker_len].iter())
+        .chain(space.iter())
+    {
+        if len == MAX_SPLIT_PREFIX {
+            break;
+        }
+        buffer[len] = *byte;
+        len += 1;
     }
 
-    if let Err(_) = writer.flush() {
-        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+    let length = len - ending.len() + line.len - right_start;
+    if length > MAX_LINE_LENGTH {
+        return Err(ToggleCommentError::LineTooLong {
+            line: index + 1,
+            length,
+            max: MAX_LINE_LENGTH,
+        });
     }
 
-    Ok(())
+    Ok((
+        line.start + left_end as u64,
+        line.start + right_start as u64,
+        buffer,
+        len,
+    ))
 }
 
-/// Insert new line before target line (bytewise)
+/// Join a line with the next one
 ///
 /// # Overview
-/// Copies source to dest, inserting new line content before target line.
+/// Line `index` loses its trailing whitespace and line ending, line
+/// `index + 1` its indentation (and its comment marker when it repeats the
+/// first line's), with one space between: `// first` + `    // second` is
+/// `// first second`. The joined line keeps the second line's ending.
 ///
 /// # Arguments
-/// * `source_path` - Original file
-/// * `dest_path` - Temp file for output
-/// * `line_number` - Zero-indexed line to insert before
-/// * `content` - Bytes to insert (should include newline)
+/// * `file_path` - Path to file (relative or absolute)
+/// * `index` - Zero-indexed first line
 ///
 /// # Returns
-/// * `Ok(())` - Line inserted successfully
-/// * `Err(ToggleCommentError)` - Operation failed
-pub fn insert_line_before_bytewise(
-    source_path: &Path,
-    dest_path: &Path,
-    line_number: usize,
-    content: &[u8],
+/// * `Ok(())` - Lines joined
+/// * `Err(LineNotFound)` - File has no line `index + 1`
+/// * `Err(LineTooLong)` - The joined line would exceed MAX_LINE_LENGTH
+/// * `Err(ProtectedRegion)` - One of the lines is protected
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// join_line("./src/main.rs", 10)?; // lines 10 and 11 become line 10
+/// ```
+#[cfg(feature = "fs")]
+pub fn join_line(file_path: &str, index: usize) -> Result<(), ToggleCommentError> {
+    join_line_with_options(file_path, index, &ToggleOptions::default())
+}
+
+/// Same as `join_line()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn join_line_with_options(
+    file_path: &str,
+    index: usize,
+    options: &ToggleOptions,
 ) -> Result<(), ToggleCommentError> {
-    // Find line start position
-    let line_start_pos =
-        match find_line_start_position(source_path.to_str().unwrap_or(""), line_number).map_err(
-            |e| match e {
-                ToggleIndentError::FileNotFound => ToggleCommentError::FileNotFound,
-                ToggleIndentError::LineNotFound {
-                    requested,
-                    file_lines,
-                } => ToggleCommentError::LineNotFound {
-                    requested,
-                    file_lines,
-                },
-                ToggleIndentError::IoError(op) => ToggleCommentError::IoError(op),
-                ToggleIndentError::PathError => ToggleCommentError::PathError,
-            },
-        )? {
-            Some(pos) => pos,
-            None => {
-                return Err(ToggleCommentError::LineNotFound {
-                    requested: line_number,
-                    file_lines: 0,
-                });
-            }
-        };
+    edit_whole_line(file_path, WholeLineOp::Join { index }, options, "join")
+}
 
-    let mut source_file = match File::open(source_path) {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Open)),
-    };
+/// Split a line in two at a byte column
+///
+/// # Overview
+/// The whitespace around `column` is dropped; the text from there moves to a
+/// new line below, indented like the line and starting with its comment
+/// marker: `    // first second` split at `s` gives `    // first` and
+/// `    // second`. `join_line()` on the result restores the line, unless the
+/// column had no single space around it.
+///
+/// # Arguments
+/// * `file_path` - Path to file (relative or absolute)
+/// * `index` - Zero-indexed line to split
+/// * `column` - Zero-based byte offset in the line where the second part starts
+///
+/// # Returns
+/// * `Ok(())` - Line split
+/// * `Err(LineNotFound)` - File has no line `index`
+/// * `Err(InvalidSplitColumn)` - No text on one side of `column`, or inside a character
+/// * `Err(ProtectedRegion)` - The line is protected
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// split_line("./src/main.rs", 10, 80)?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn split_line(file_path: &str, index: usize, column: usize) -> Result<(), ToggleCommentError> {
+    split_line_with_options(file_path, index, column, &ToggleOptions::default())
+}
 
-    let dest_file = match OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(dest_path)
-    {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Create)),
-    };
+/// Same as `split_line()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn split_line_with_options(
+    file_path: &str,
+    index: usize,
+    column: usize,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    edit_whole_line(
+        file_path,
+        WholeLineOp::Split { index, column },
+        options,
+        "split",
+    )
+}
 
-    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, dest_file);
-    let mut byte_bucket: [u8; 1] = [0u8; 1];
-    let mut byte_position: u64 = 0;
+// ============================================================================
+// TESTS - PHASE 17: JOIN AND SPLIT LINES
+// ============================================================================
 
-    // PART A: Copy before target line
-    while byte_position < line_start_pos {
-        if byte_position >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
-        }
+#[cfg(all(test, feature = "fs"))]
+mod join_split_tests {
+    use super::*;
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-        };
+    #[test]
+    fn test_join_and_split_comment_lines() {
+        let original = "fn f() {\r\n    /// first part  \r\n    /// second part\r\n}\r\n";
+        let test_file = create_test_file("test_join_split.rs", original);
+        let path = test_file.to_str().unwrap();
+
+        // Marker repeated on the second line is dropped; its ending kept
+        assert_eq!(join_line(path, 1), Ok(()));
+        assert_eq!(
+            read_file_content(&test_file),
+            "fn f() {\r\n    /// first part second part\r\n}\r\n"
+        );
 
-        if bytes_read == 0 {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
-        }
+        // Split at "second": indent and marker carried to the new line
+        assert_eq!(split_line(path, 1, 19), Ok(()));
+        assert_eq!(
+            read_file_content(&test_file),
+            "fn f() {\r\n    /// first part\r\n    /// second part\r\n}\r\n"
+        );
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        // No text on one side, inside the marker, past the end
+        for column in [4, 6, 8, 40] {
+            assert_eq!(
+                split_line(path, 1, column),
+                Err(ToggleCommentError::InvalidSplitColumn { line: 1, column })
+            );
         }
+        assert!(matches!(
+            join_line(path, 3),
+            Err(ToggleCommentError::LineNotFound { .. })
+        ));
 
-        byte_position += 1;
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_join_split.rs"),
+        ]);
     }
 
-    // PART B: Insert new content
-    if let Err(_) = writer.write_all(content) {
-        return Err(ToggleCommentError::IoError(IoOperation::Write));
+    #[test]
+    fn test_join_code_lines() {
+        let test_file = create_test_file("test_join_code.py", "x = (1,\n     2)\n\n# end");
+        let path = test_file.to_str().unwrap();
+
+        // Code: indentation dropped; a blank second line just goes
+        assert_eq!(join_line(path, 0), Ok(()));
+        assert_eq!(join_line(path, 0), Ok(()));
+        assert_eq!(read_file_content(&test_file), "x = (1, 2)\n# end");
+
+        // Different markers are both kept (not the same comment)
+        assert_eq!(join_line(path, 0), Ok(()));
+        assert_eq!(read_file_content(&test_file), "x = (1, 2) # end");
+
+        cleanup_files(&[
+            &test_file,
+            &PathBuf::from("backup_toggle_comment_test_join_code.py"),
+        ]);
     }
+}
 
-    // PART C: Copy rest of file
-    let mut remaining_bytes: u64 = 0;
-    loop {
-        if remaining_bytes >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
-        }
+// ============================================================================
+// PHASE 18: COMMENT STYLE CONVERSION (`//` <-> `/* */`, `#` <-> `"""`)
+// ============================================================================
+//
+// ## Project Context
+// Some tools understand only one comment style. A line comment converts to a
+// one-line block comment and back, with the extension's markers:
+//
+//    // note       <->   /* note */
+//    # note        <->   """ note """
+//
+// The space after the line flag becomes one space inside each block marker,
+// so converting twice restores the line. Lines whose text would change
+// meaning are refused: a body holding a block marker (C does not nest, Rust
+// does), doc comments (`///`, `//!`, `/**`, `/*!`), a shebang, a body ending
+// in a backslash (it continues a C line comment onto the next line, and
+// escapes Python's closing quotes).
+//
+// File-wide conversion (C-style files only) streams the file once through the
+// brace scanner's lexer, so markers inside strings are left alone. A comment
+// is converted only when it is alone on its lines (indentation before it,
+// whitespace after it); it is held in a buffer of at most
+// MAX_CONVERTED_COMMENT_BYTES, and a longer one is copied unchanged. A
+// multi-line block comment becomes one `//` line per line of text: lines only
+// holding `/*` or `*/` are dropped, a leading ` * ` decoration is removed.
+// ============================================================================
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-        };
+/// Comment style to convert a line to (`convert_comment_style()`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// Line comment (`// text`, `# text`)
+    Line,
 
-        if bytes_read == 0 {
-            break;
-        }
+    /// One-line block comment (`/* text */`, `""" text """`)
+    Block,
+}
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleCommentError::IoError(IoOperation::Write));
+/// Line flag and block markers of an extension that has both styles
+#[cfg(feature = "fs")]
+fn comment_styles_of(absolute_path: &Path) -> Option<(&'static [u8], BlockMarkers)> {
+    let extension = absolute_path.extension()?.to_string_lossy().to_string();
+    let flag = match determine_comment_flag(&extension)? {
+        CommentFlag::DoubleSlash => b"//" as &'static [u8],
+        CommentFlag::Hash => b"#",
+        CommentFlag::TripleSlash
+        | CommentFlag::HtmlComment
+        | CommentFlag::SlashStar
+        | CommentFlag::DoxygenMember
+        | CommentFlag::QtMember => return None,
+    };
+    // Ruby's `=begin` / `=end` only work on their own lines at column 0
+    let markers = determine_block_markers(&extension)?;
+    (!marker_at_column_zero(markers.start)).then_some((flag, markers))
+}
+
+/// Byte offset of `needle` in `haystack`
+#[cfg(feature = "fs")]
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return None;
+    }
+    (0..=haystack.len() - needle.len()).find(|&i| &haystack[i..i + needle.len()] == needle)
+}
+
+/// Convert one comment's text (indentation and line ending excluded)
+///
+/// # Arguments
+/// * `text` - Line from its first non-blank byte to its last
+/// * `flag` / `markers` - The extension's line flag and block markers
+/// * `to` - Style to produce
+///
+/// # Returns
+/// * `Some((buffer, len))` - Converted text
+/// * `None` - `text` is not one comment of the other style, or would not
+///   survive the conversion
+#[cfg(feature = "fs")]
+fn convert_comment_text(
+    text: &[u8],
+    flag: &[u8],
+    markers: BlockMarkers,
+    to: CommentStyle,
+) -> Option<([u8; MAX_STYLED_LINE], usize)> {
+    let (marker, spaced, body): (&[u8], bool, &[u8]) = match to {
+        CommentStyle::Block => {
+            let after = text.strip_prefix(flag)?;
+            // `///`, `//!`, `##`, `#!`: doc comments and shebangs stay
+            if matches!(after.first(), Some(&b) if b == flag[flag.len() - 1] || b == b'!') {
+                return None;
+            }
+            let spaced = after.first() == Some(&b' ');
+            let body = &after[usize::from(spaced)..];
+            // No space: `//*x` would start a `/**` doc comment
+            if !spaced && matches!(body.first(), Some(b'*') | Some(b'!')) {
+                return None;
+            }
+            (markers.start, spaced, body)
+        }
+        CommentStyle::Line => {
+            let inner = text
+                .strip_prefix(markers.start)?
+                .strip_suffix(markers.end)?;
+            // `/**`, `/*!`: doc comments stay (`/**/` is an empty comment)
+            if markers.start == b"/*" && matches!(inner.first(), Some(b'*') | Some(b'!')) {
+                return None;
+            }
+            let spaced = inner.first() == Some(&b' ');
+            let trailing = spaced && inner.len() >= 2 && inner.ends_with(b" ");
+            let body = &inner[usize::from(spaced)..inner.len() - usize::from(trailing)];
+            (flag, spaced, body)
         }
+    };
 
-        remaining_bytes += 1;
+    if find_bytes(body, markers.start).is_some()
+        || find_bytes(body, markers.end).is_some()
+        || body.ends_with(b"\\")
+    {
+        return None;
     }
 
-    if let Err(_) = writer.flush() {
-        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+    let space: &[u8] = if spaced { b" " } else { b"" };
+    let (end, end_space): (&[u8], &[u8]) = match to {
+        CommentStyle::Block => (markers.end, space),
+        CommentStyle::Line => (b"", b""),
+    };
+    let mut buffer = [0u8; MAX_STYLED_LINE];
+    let mut len: usize = 0;
+    for part in [marker, space, body, end_space, end] {
+        if len + part.len() > MAX_STYLED_LINE {
+            return None;
+        }
+        buffer[len..len + part.len()].copy_from_slice(part);
+        len += part.len();
     }
+    Some((buffer, len))
+}
 
-    Ok(())
+/// Plan the style conversion of line `index`
+///
+/// # Returns
+/// * `Ok((start, end, buffer, len))` - Replace bytes `start..end` (the line
+///   without indentation, trailing whitespace and ending) with `buffer[..len]`
+/// * `Err(ToggleCommentError)` - NoStyleConversion, NotConvertibleComment,
+///   LineNotFound, ProtectedRegion, LineTooLong, ...
+#[cfg(feature = "fs")]
+fn convert_splice(
+    absolute_path: &Path,
+    index: usize,
+    to: CommentStyle,
+) -> Result<(u64, u64, [u8; MAX_STYLED_LINE], usize), ToggleCommentError> {
+    let (flag, markers) =
+        comment_styles_of(absolute_path).ok_or(ToggleCommentError::NoStyleConversion)?;
+
+    let source_file = match File::open(absolute_path) {
+        Ok(f) => f,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+    };
+    let mut shapes = [LineShape::default(); 1];
+    line_shapes_in(
+        &mut BufReader::with_capacity(IO_BUFFER_SIZE, source_file),
+        index,
+        &mut shapes,
+        flag,
+        None,
+    )?;
+    let line = shapes[0];
+    if line.protected {
+        return Err(ToggleCommentError::ProtectedRegion { line: index });
+    }
+
+    let (line_buffer, _) = copy_of_line(absolute_path, index)?;
+    let text = &line_buffer[line.indent_len..line.content_end.max(line.indent_len)];
+    let (buffer, len) = convert_comment_text(text, flag, markers, to)
+        .ok_or(ToggleCommentError::NotConvertibleComment { line: index })?;
+
+    Ok((
+        line.start + line.indent_len as u64,
+        line.start + line.content_end.max(line.indent_len) as u64,
+        buffer,
+        len,
+    ))
 }
 
-/// Insert new line after target line (bytewise)
+/// Convert a line comment to a one-line block comment, or back
 ///
 /// # Overview
-/// Copies source to dest, inserting new line content after target line's newline.
+/// The line's indentation, trailing whitespace and ending are kept:
+/// `    // note` becomes `    /* note */` (`# note` becomes `""" note """`
+/// in Python), and converting back restores it.
 ///
 /// # Arguments
-/// * `source_path` - Original file
-/// * `dest_path` - Temp file for output
-/// * `line_number` - Zero-indexed line to insert after
-/// * `content` - Bytes to insert (should include newline)
+/// * `file_path` - Path to file (relative or absolute)
+/// * `index` - Zero-indexed line to convert
+/// * `to` - Style the line should have afterwards
 ///
 /// # Returns
-/// * `Ok(())` - Line inserted successfully
-/// * `Err(ToggleCommentError)` - Operation failed
-pub fn insert_line_after_bytewise(
-    source_path: &Path,
-    dest_path: &Path,
-    line_number: usize,
-    content: &[u8],
+/// * `Ok(())` - Line converted
+/// * `Err(NoStyleConversion)` - File type without both comment styles
+/// * `Err(NotConvertibleComment)` - Not a comment of the other style, or its
+///   text would change meaning (holds a marker, doc comment)
+/// * `Err(LineTooLong)` - Line longer than MAX_LINE_CONTENT_BYTES
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// convert_comment_style("./src/main.c", 10, CommentStyle::Block)?;
+/// ```
+#[cfg(feature = "fs")]
+pub fn convert_comment_style(
+    file_path: &str,
+    index: usize,
+    to: CommentStyle,
 ) -> Result<(), ToggleCommentError> {
-    // Find line start position
-    let line_start_pos =
-        match find_line_start_position(source_path.to_str().unwrap_or(""), line_number).map_err(
-            |e| match e {
-                ToggleIndentError::FileNotFound => ToggleCommentError::FileNotFound,
-                ToggleIndentError::LineNotFound {
-                    requested,
-                    file_lines,
-                } => ToggleCommentError::LineNotFound {
-                    requested,
-                    file_lines,
-                },
-                ToggleIndentError::IoError(op) => ToggleCommentError::IoError(op),
-                ToggleIndentError::PathError => ToggleCommentError::PathError,
-            },
-        )? {
-            Some(pos) => pos,
-            None => {
-                return Err(ToggleCommentError::LineNotFound {
-                    requested: line_number,
-                    file_lines: 0,
-                });
-            }
-        };
-
-    let mut source_file = match File::open(source_path) {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Open)),
-    };
+    convert_comment_style_with_options(file_path, index, to, &ToggleOptions::default())
+}
 
-    let dest_file = match OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(dest_path)
-    {
-        Ok(f) => f,
-        Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Create)),
-    };
+/// Same as `convert_comment_style()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn convert_comment_style_with_options(
+    file_path: &str,
+    index: usize,
+    to: CommentStyle,
+    options: &ToggleOptions,
+) -> Result<(), ToggleCommentError> {
+    edit_whole_line(
+        file_path,
+        WholeLineOp::Convert { index, to },
+        options,
+        "convert",
+    )
+}
 
-    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, dest_file);
-    let mut byte_bucket: [u8; 1] = [0u8; 1];
-    let mut byte_position: u64 = 0;
+/// Longest comment (with its indentation and line ending) file-wide
+/// conversion buffers; longer comments are copied unchanged
+#[cfg(feature = "fs")]
+pub const MAX_CONVERTED_COMMENT_BYTES: usize = 16 * 1024;
 
-    // PART A: Copy up to and including target line
-    while byte_position < line_start_pos {
-        if byte_position >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
-        }
+/// Copy `reader` to `writer`, converting every comment that is alone on its
+/// lines to the style `to`
+///
+/// # Arguments
+/// * `flag` / `markers` - Line flag and `/* */` block markers
+/// * `rust_chars` - Treat `'` as Rust char literal / lifetime (lexer)
+///
+/// # Returns
+/// * `Ok((converted, removed, inserted))` - Comments converted, bytes they
+///   held and bytes written for them
+/// * `Err(ToggleCommentError)` - Read / write failed or iteration limit reached
+#[cfg(feature = "fs")]
+fn convert_comments_in<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    flag: &[u8],
+    markers: BlockMarkers,
+    rust_chars: bool,
+    to: CommentStyle,
+) -> Result<(usize, u64, u64), ToggleCommentError> {
+    /// What the held bytes are
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Held {
+        /// Nothing held: the rest of the line is copied
+        Copy,
+        /// Line start: indentation, maybe the first marker bytes
+        Start,
+        /// `// ...` after the indentation, up to the line ending
+        LineComment,
+        /// `/* ...` after the indentation, possibly over several lines
+        BlockComment,
+        /// The block comment closed; only whitespace after it so far
+        AfterBlock,
+    }
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-        };
+    let mut lexer = BraceBlockScanner::new(rust_chars);
+    let mut held = [0u8; MAX_CONVERTED_COMMENT_BYTES];
+    let mut held_len: usize = 0;
+    let mut indent_len: usize = 0;
+    let mut phase = Held::Start;
+    let mut converted: usize = 0;
+    let mut removed_bytes: u64 = 0;
+    let mut inserted_bytes: u64 = 0;
+    let mut byte_count: u64 = 0;
+
+    let write = |writer: &mut W, bytes: &[u8]| {
+        writer
+            .write_all(bytes)
+            .map_err(|_| ToggleCommentError::IoError(IoOperation::Write))
+    };
 
-        if bytes_read == 0 {
+    while let Some(byte) = read_single_byte(reader)? {
+        // Safety check
+        byte_count += 1;
+        if byte_count >= MAX_BYTE_ITERATIONS {
             return Err(ToggleCommentError::IoError(IoOperation::Read));
         }
-
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        if byte == b'\n' && cancel_requested() {
+            return Err(ToggleCommentError::Cancelled);
         }
 
-        byte_position += 1;
-    }
+        let before = lexer.state;
+        lexer.push_byte(byte);
 
-    // PART B: Copy target line until newline
-    loop {
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
-        };
+        // Over the bound: the comment is copied unchanged
+        if phase != Held::Copy && held_len == held.len() {
+            write(writer, &held[..held_len])?;
+            phase = Held::Copy;
+        }
+        if phase == Held::Copy {
+            write(writer, &[byte])?;
+        } else {
+            held[held_len] = byte;
+            held_len += 1;
+        }
 
-        if bytes_read == 0 {
-            // EOF - no newline at end, insert content here
-            if let Err(_) = writer.write_all(b"\n") {
-                return Err(ToggleCommentError::IoError(IoOperation::Write));
+        let blank = byte == b' ' || byte == b'\t' || byte == b'\r' || byte == b'\n';
+        phase = match phase {
+            Held::Start if held_len - 1 == indent_len && (byte == b' ' || byte == b'\t') => {
+                indent_len += 1;
+                Held::Start
             }
-            if let Err(_) = writer.write_all(content) {
-                return Err(ToggleCommentError::IoError(IoOperation::Write));
+            Held::Start
+                if to == CommentStyle::Block && lexer.state == BraceLexState::LineComment =>
+            {
+                Held::LineComment
             }
-            break;
-        }
+            Held::Start
+                if to == CommentStyle::Line
+                    && before == BraceLexState::Slash
+                    && lexer.state == BraceLexState::BlockComment =>
+            {
+                Held::BlockComment
+            }
+            // The first `/` of either marker
+            Held::Start if held_len - 1 == indent_len && byte == b'/' => Held::Start,
+            Held::Start => {
+                write(writer, &held[..held_len])?;
+                Held::Copy
+            }
+            Held::BlockComment if lexer.state == BraceLexState::Code => Held::AfterBlock,
+            Held::AfterBlock if !blank => {
+                write(writer, &held[..held_len])?;
+                Held::Copy
+            }
+            phase => phase,
+        };
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleCommentError::IoError(IoOperation::Write));
+        if byte == b'\n' && phase != Held::BlockComment {
+            if phase == Held::LineComment || phase == Held::AfterBlock {
+                match restyle_held(writer, &held[..held_len], indent_len, flag, markers, to)? {
+                    Some(written) => {
+                        converted += 1;
+                        removed_bytes += held_len as u64;
+                        inserted_bytes += written;
+                    }
+                    None => write(writer, &held[..held_len])?,
+                }
+            }
+            held_len = 0;
+            indent_len = 0;
+            phase = if lexer.state == BraceLexState::Code {
+                Held::Start
+            } else {
+                Held::Copy
+            };
         }
+    }
 
-        if byte_bucket[0] == b'\n' {
-            // Found newline - insert after it
-            if let Err(_) = writer.write_all(content) {
-                return Err(ToggleCommentError::IoError(IoOperation::Write));
+    // Last line without a line ending
+    if phase == Held::LineComment || phase == Held::AfterBlock {
+        match restyle_held(writer, &held[..held_len], indent_len, flag, markers, to)? {
+            Some(written) => {
+                converted += 1;
+                removed_bytes += held_len as u64;
+                inserted_bytes += written;
             }
-            break;
+            None => write(writer, &held[..held_len])?,
         }
+    } else if phase != Held::Copy {
+        write(writer, &held[..held_len])?;
     }
+    Ok((converted, removed_bytes, inserted_bytes))
+}
 
-    // PART C: Copy rest of file
-    let mut remaining_bytes: u64 = 0;
-    loop {
-        if remaining_bytes >= MAX_BYTE_ITERATIONS {
-            return Err(ToggleCommentError::IoError(IoOperation::Read));
-        }
+/// Write one held comment in the other style
+///
+/// # Arguments
+/// * `held` - Indentation, the comment, trailing whitespace and line ending
+/// * `indent_len` - Bytes of indentation at the start of `held`
+///
+/// # Returns
+/// * `Ok(Some(written))` - Converted; bytes written
+/// * `Ok(None)` - Not convertible, nothing written (the caller copies `held`)
+/// * `Err(IoError(Write))` - Write failed
+#[cfg(feature = "fs")]
+fn restyle_held<W: Write>(
+    writer: &mut W,
+    held: &[u8],
+    indent_len: usize,
+    flag: &[u8],
+    markers: BlockMarkers,
+    to: CommentStyle,
+) -> Result<Option<u64>, ToggleCommentError> {
+    let mut written: u64 = 0;
+    let mut put = |bytes: &[u8]| {
+        written += bytes.len() as u64;
+        writer
+            .write_all(bytes)
+            .map_err(|_| ToggleCommentError::IoError(IoOperation::Write))
+    };
 
-        let bytes_read = match source_file.read(&mut byte_bucket) {
-            Ok(n) => n,
-            Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+    let content_end = held
+        .iter()
+        .rposition(|&b| !matches!(b, b' ' | b'\t' | b'\r' | b'\n'))
+        .map_or(indent_len, |i| i + 1);
+    let indent = &held[..indent_len];
+    let text = &held[indent_len..content_end];
+    let tail = &held[content_end..];
+
+    // One line: same rules as `convert_comment_style()`
+    if !text.contains(&b'\n') {
+        let Some((buffer, len)) = convert_comment_text(text, flag, markers, to) else {
+            return Ok(None);
         };
+        put(indent)?;
+        put(&buffer[..len])?;
+        put(tail)?;
+        return Ok(Some(written));
+    }
 
-        if bytes_read == 0 {
-            break;
-        }
+    // Several lines (block comment only): no doc comments
+    let inner = &text[markers.start.len()..text.len() - markers.end.len()];
+    if matches!(inner.first(), Some(b'*') | Some(b'!')) {
+        return Ok(None);
+    }
+    let parts = inner.split(|&b| b == b'\n').count();
+    let text_column = indent_len + markers.start.len() + 1;
+    // A trailing backslash would continue a `//` comment onto the next line
+    if inner
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .any(|(k, part)| comment_line_text(part, k == 0, text_column).ends_with(b"\\"))
+    {
+        return Ok(None);
+    }
 
-        if let Err(_) = writer.write_all(&byte_bucket) {
-            return Err(ToggleCommentError::IoError(IoOperation::Write));
-        }
+    let ending: &[u8] = if inner
+        .split(|&b| b == b'\n')
+        .next()
+        .is_some_and(|l| l.ends_with(b"\r"))
+    {
+        b"\r\n"
+    } else {
+        b"\n"
+    };
+    let tail_ending: &[u8] = if tail.ends_with(b"\r\n") {
+        b"\r\n"
+    } else if tail.ends_with(b"\n") {
+        b"\n"
+    } else {
+        b""
+    };
 
-        remaining_bytes += 1;
+    let mut emitted: usize = 0;
+    for (k, part) in inner.split(|&b| b == b'\n').enumerate() {
+        let body = comment_line_text(part, k == 0, text_column);
+        // Lines holding only `/*` or `*/` go
+        if body.is_empty() && (k == 0 || k == parts - 1) {
+            continue;
+        }
+        if emitted > 0 {
+            put(ending)?;
+        }
+        put(indent)?;
+        put(flag)?;
+        if !body.is_empty() {
+            put(b" ")?;
+            put(body)?;
+        }
+        emitted += 1;
     }
-
-    if let Err(_) = writer.flush() {
-        return Err(ToggleCommentError::IoError(IoOperation::Flush));
+    if emitted == 0 {
+        put(indent)?;
+        put(flag)?;
     }
+    put(tail_ending)?;
+    Ok(Some(written))
+}
 
-    Ok(())
+/// Text of one line of a multi-line block comment, for its `//` line
+///
+/// # Arguments
+/// * `part` - The line between the markers (`/*` stripped on the first)
+/// * `first` - `part` is the opening line
+/// * `text_column` - Column of the text after `/* `: undecorated lines keep
+///   only the indentation beyond it
+#[cfg(feature = "fs")]
+fn comment_line_text(part: &[u8], first: bool, text_column: usize) -> &[u8] {
+    let part = part.strip_suffix(b"\r").unwrap_or(part);
+    let ws = part
+        .iter()
+        .take_while(|&&b| b == b' ' || b == b'\t')
+        .count();
+    let (body, spaced) = if first {
+        (part, true)
+    } else if part[ws..].first() == Some(&b'*') {
+        // ` * text` decoration
+        (&part[ws + 1..], true)
+    } else {
+        (&part[ws.min(text_column)..], false)
+    };
+    let end = body
+        .iter()
+        .rposition(|&b| b != b' ' && b != b'\t')
+        .map_or(0, |i| i + 1);
+    match &body[..end] {
+        [b' ', rest @ ..] if spaced => rest,
+        body => body,
+    }
 }
 
-/// Toggle block comment markers around range of lines (bytewise)
+/// Convert every comment of a C-style file to one style
 ///
 /// # Overview
-/// Adds or removes block comment markers (`/* */` or `"""`) at column 0.
-/// Markers placed on their own lines before/after content.
-///
-/// # Rules
-/// - **Column 0 only** - no indentation handling
-/// - **Single line (start == end)** - always ADD mode
-/// - **Detection** - if BOTH lines start with markers → REMOVE, else ADD
-/// - **ADD mode** - insert new line before start, new line after end
-/// - **REMOVE mode** - delete end line first, then start line
+/// `CommentStyle::Line`: each block comment alone on its lines becomes `//`
+/// lines (one per line of text, ` * ` decoration and marker-only lines
+/// dropped). `CommentStyle::Block`: each `//` comment alone on its line
+/// becomes `/* ... */`. Comments after code, doc comments and comments whose
+/// text would change meaning are left as they are; so are markers inside
+/// strings. Backup, temp file and atomic replace as for every edit.
 ///
 /// # Arguments
-/// * `file_path` - Path to source file
-/// * `start_line` - First line of content range (zero-indexed)
-/// * `end_line` - Last line of content range (zero-indexed)
-///
-/// # Returns
-/// * `Ok(())` - Block comment toggled successfully
-/// * `Err(ToggleCommentError)` - Specific error code
-///
-/// # Example (Rust - ADD mode)
-/// ```text
-/// Before:
-/// line 5: code line 1
-/// line 6: code line 2
-///
-/// After toggle_block_comment_bytewise(path, 5, 6):
-/// line 5: /*
-/// line 6: code line 1
-/// line 7: code line 2
-/// line 8: */
-/// ```
-///
-/// # Example (Rust - REMOVE mode)
-/// ```text
-/// Before:
-/// line 5: /*
-/// line 6: code line 1
-/// line 7: code line 2
-/// line 8: */
-///
-/// After toggle_block_comment_bytewise(path, 5, 8):
-/// line 5: code line 1
-/// line 6: code line 2
-/// ```
+/// * `file_path` - Path to a C-style file (`.c`, `.rs`, `.js`, ...)
+/// * `to` - Style every convertible comment should have afterwards
 ///
-/// # Example (Python)
-/// ```text
-/// Uses """ markers instead of /* */
+/// # Returns
+/// * `Ok(converted)` - Number of comments converted (0: file unchanged)
+/// * `Err(NoStyleConversion)` - File type without `//` and `/* */`
+/// * `Err(ToggleCommentError)` - Other specific error code
+///
+/// # Example
+/// ```no_run
+/// let converted = convert_comment_style_file("./src/main.c", CommentStyle::Line)?;
 /// ```
-pub fn toggle_block_comment_bytewise(
+#[cfg(feature = "fs")]
+pub fn convert_comment_style_file(
     file_path: &str,
-    start_line: usize,
-    end_line: usize,
-) -> Result<(), ToggleCommentError> {
-    // Sort range
-    let (start, end) = sort_range(start_line, end_line);
+    to: CommentStyle,
+) -> Result<usize, ToggleCommentError> {
+    convert_comment_style_file_with_options(file_path, to, &ToggleOptions::default())
+}
 
+/// Same as `convert_comment_style_file()`, with caller-provided `ToggleOptions`
+#[cfg(feature = "fs")]
+pub fn convert_comment_style_file_with_options(
+    file_path: &str,
+    to: CommentStyle,
+    options: &ToggleOptions,
+) -> Result<usize, ToggleCommentError> {
     // Convert to absolute path
     let absolute_path = match Path::new(file_path).canonicalize() {
         Ok(p) => p,
@@ -4367,355 +22748,208 @@ pub fn toggle_block_comment_bytewise(
             if e.kind() == std::io::ErrorKind::NotFound {
                 return Err(ToggleCommentError::FileNotFound);
             }
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                return Err(ToggleCommentError::PermissionDenied(IoOperation::Open));
+            }
             return Err(ToggleCommentError::PathError);
         }
     };
 
-    // Determine block markers from extension
-    let extension = match absolute_path.extension() {
-        Some(ext) => ext.to_string_lossy().to_string(),
-        None => return Err(ToggleCommentError::NoExtension),
-    };
+    // Refuse special files, own artifacts, read-only and oversized targets
+    // (before any backup is made)
+    check_edit_target(&absolute_path, options)?;
 
-    let markers = match determine_block_markers(&extension) {
-        Some(m) => m,
-        None => return Ok(()), // Unsupported - no-op
+    let (flag, markers) = match comment_styles_of(&absolute_path) {
+        Some((flag, markers)) if markers.start == b"/*" => (flag, markers),
+        _ => return Err(ToggleCommentError::NoStyleConversion),
     };
+    let rust_chars = absolute_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("rs"));
 
-    // Get filename
+    // Get filename for backup naming
     let filename = match absolute_path.file_name() {
-        Some(name) => name.to_string_lossy().to_string(),
+        Some(name) => safe_name_component(name),
         None => return Err(ToggleCommentError::PathError),
     };
 
-    // EDGE CASE: Single line always ADD mode
-    if start == end {
-        // Create backup
-        let backup_filename = format!("backup_toggle_comment_{}", filename);
-        let backup_path = PathBuf::from(&backup_filename);
-        if let Err(_) = std::fs::copy(&absolute_path, &backup_path) {
-            return Err(ToggleCommentError::IoError(IoOperation::Backup));
-        }
-
-        // Insert closing marker after line (do this first so line numbers don't shift)
-        let temp1_filename = format!("temp_block_1_{}_{}", std::process::id(), filename);
-        let temp1_path = PathBuf::from(&temp1_filename);
-        insert_line_after_bytewise(&absolute_path, &temp1_path, start, markers.end)?;
-
-        // Replace original with temp1
-        if let Err(_) = std::fs::copy(&temp1_path, &absolute_path) {
-            let _ = std::fs::remove_file(&temp1_path);
-            return Err(ToggleCommentError::IoError(IoOperation::Replace));
-        }
-
-        // Insert opening marker before line
-        let temp2_filename = format!("temp_block_2_{}_{}", std::process::id(), filename);
-        let temp2_path = PathBuf::from(&temp2_filename);
-        insert_line_before_bytewise(&absolute_path, &temp2_path, start, markers.start)?;
-
-        // Replace original with temp2
-        if let Err(_) = std::fs::copy(&temp2_path, &absolute_path) {
-            let _ = std::fs::remove_file(&temp2_path);
-            return Err(ToggleCommentError::IoError(IoOperation::Replace));
-        }
-
-        // Cleanup temps
-        let _ = std::fs::remove_file(&temp1_path);
-        let _ = std::fs::remove_file(&temp2_path);
-
-        return Ok(());
-    }
-
-    // DETECT MODE: Check if both markers present at column 0
-    let start_has_marker = detect_line_pattern(file_path, start, markers.start)?;
-    let end_has_marker = detect_line_pattern(file_path, end, markers.end)?;
-
-    let mode = if start_has_marker && end_has_marker {
-        BlockMode::Remove
-    } else {
-        BlockMode::Add
+    let trace = StageTrace::new(options, "convert-style-file");
+    let backup_path = backup_path_for(&filename, options);
+    let snapshot = match trace.backup(&absolute_path, &backup_path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Backup)),
     };
 
-    // Create backup
-    let backup_filename = format!("backup_toggle_comment_{}", filename);
-    let backup_path = PathBuf::from(&backup_filename);
-    if let Err(_) = std::fs::copy(&absolute_path, &backup_path) {
-        return Err(ToggleCommentError::IoError(IoOperation::Backup));
-    }
-
-    match mode {
-        BlockMode::Remove => {
-            // DELETE end_line FIRST (so start_line number stays valid)
-            let temp1_filename = format!("temp_block_1_{}_{}", std::process::id(), filename);
-            let temp1_path = PathBuf::from(&temp1_filename);
-            delete_line_bytewise(&absolute_path, &temp1_path, end)?;
-
-            // Replace original
-            if let Err(_) = std::fs::copy(&temp1_path, &absolute_path) {
-                let _ = std::fs::remove_file(&temp1_path);
-                return Err(ToggleCommentError::IoError(IoOperation::Replace));
-            }
-
-            // DELETE start_line
-            let temp2_filename = format!("temp_block_2_{}_{}", std::process::id(), filename);
-            let temp2_path = PathBuf::from(&temp2_filename);
-            delete_line_bytewise(&absolute_path, &temp2_path, start)?;
-
-            // Replace original
-            if let Err(_) = std::fs::copy(&temp2_path, &absolute_path) {
-                let _ = std::fs::remove_file(&temp2_path);
-                return Err(ToggleCommentError::IoError(IoOperation::Replace));
-            }
-
-            // Cleanup temps
-            let _ = std::fs::remove_file(&temp1_path);
-            let _ = std::fs::remove_file(&temp2_path);
-        }
-
-        BlockMode::Add => {
-            // INSERT closing marker after end_line (do this first)
-            let temp1_filename = format!("temp_block_1_{}_{}", std::process::id(), filename);
-            let temp1_path = PathBuf::from(&temp1_filename);
-            insert_line_after_bytewise(&absolute_path, &temp1_path, end, markers.end)?;
-
-            // Replace original
-            if let Err(_) = std::fs::copy(&temp1_path, &absolute_path) {
-                let _ = std::fs::remove_file(&temp1_path);
-                return Err(ToggleCommentError::IoError(IoOperation::Replace));
-            }
+    let temp_filename = format!("temp_toggle_restyle_{}_{}", std::process::id(), filename);
+    let temp_path = temp_file_path(options.temp_dir.as_deref(), &temp_filename);
+    let _remove_temp = TempFileGuard(&temp_path);
 
-            // INSERT opening marker before start_line
-            let temp2_filename = format!("temp_block_2_{}_{}", std::process::id(), filename);
-            let temp2_path = PathBuf::from(&temp2_filename);
-            insert_line_before_bytewise(&absolute_path, &temp2_path, start, markers.start)?;
-
-            // Replace original
-            if let Err(_) = std::fs::copy(&temp2_path, &absolute_path) {
-                let _ = std::fs::remove_file(&temp2_path);
-                return Err(ToggleCommentError::IoError(IoOperation::Replace));
+    let (converted, removed_bytes, inserted_bytes) =
+        trace.run_streaming(&absolute_path, &temp_path, || {
+            let source_file = match File::open(&absolute_path) {
+                Ok(f) => f,
+                Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Open)),
+            };
+            let temp_file = match File::create(&temp_path) {
+                Ok(f) => f,
+                Err(e) => return Err(ToggleCommentError::from_io_error(&e, IoOperation::Create)),
+            };
+            let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, TimedIo(temp_file));
+            let result = convert_comments_in(
+                &mut BufReader::with_capacity(IO_BUFFER_SIZE, TimedIo(source_file)),
+                &mut writer,
+                flag,
+                markers,
+                rust_chars,
+                to,
+            )?;
+            if writer.flush().is_err() {
+                return Err(ToggleCommentError::IoError(IoOperation::Write));
             }
+            Ok(result)
+        })?;
 
-            // Cleanup temps
-            let _ = std::fs::remove_file(&temp1_path);
-            let _ = std::fs::remove_file(&temp2_path);
-        }
+    if !trace.check(TraceStage::Verify, || {
+        replacement_size_is_plausible(&absolute_path, &temp_path, removed_bytes, inserted_bytes)
+    }) {
+        return Err(ToggleCommentError::IncompleteWrite);
+    }
+    if let Err(e) = trace.run(TraceStage::Replace, || {
+        replace_original(
+            &temp_path,
+            &absolute_path,
+            options.temp_dir.as_deref(),
+            options.durability,
+            &snapshot,
+        )
+    }) {
+        return Err(ToggleCommentError::from_io_error(&e, IoOperation::Replace));
     }
 
-    Ok(())
+    Ok(converted)
 }
 
 // ============================================================================
-// TESTS - PHASE 3: BLOCK COMMENTS BYTEWISE
+// TESTS - PHASE 18: COMMENT STYLE CONVERSION
 // ============================================================================
 
-#[cfg(test)]
-mod block_comment_bytewise_tests {
+#[cfg(all(test, feature = "fs"))]
+mod comment_style_tests {
     use super::*;
 
-    // ========================================
-    // Detection Tests
-    // ========================================
-
-    #[test]
-    fn test_detect_line_pattern_match() {
-        let content = "line 0\n/*\nline 2\n";
-        let test_file = create_test_file("test_detect_match.rs", content);
-
-        let result = detect_line_pattern(test_file.to_str().unwrap(), 1, b"/*\n");
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), true);
-
-        cleanup_files(&[&test_file]);
-    }
-
-    #[test]
-    fn test_detect_line_pattern_no_match() {
-        let content = "line 0\ncode\nline 2\n";
-        let test_file = create_test_file("test_detect_no_match.rs", content);
-
-        let result = detect_line_pattern(test_file.to_str().unwrap(), 1, b"/*\n");
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), false);
-
-        cleanup_files(&[&test_file]);
-    }
-
-    #[test]
-    fn test_detect_line_pattern_indented() {
-        let content = "line 0\n  /*\nline 2\n";
-        let test_file = create_test_file("test_detect_indented.rs", content);
-
-        // Should NOT match - pattern must be at column 0
-        let result = detect_line_pattern(test_file.to_str().unwrap(), 1, b"/*\n");
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), false);
-
-        cleanup_files(&[&test_file]);
-    }
-
-    // ========================================
-    // Delete Line Tests
-    // ========================================
-
-    #[test]
-    fn test_delete_line_bytewise_middle() {
-        let content = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_delete_middle.txt", content);
-        let temp_file = PathBuf::from("temp_delete_test.txt");
-
-        let result = delete_line_bytewise(&test_file, &temp_file, 1);
-        assert!(result.is_ok());
-
-        let new_content = read_file_content(&temp_file);
-        assert_eq!(new_content, "line 0\nline 2\n");
-
-        cleanup_files(&[&test_file, &temp_file]);
-    }
-
-    #[test]
-    fn test_delete_line_bytewise_first() {
-        let content = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_delete_first.txt", content);
-        let temp_file = PathBuf::from("temp_delete_first.txt");
-
-        let result = delete_line_bytewise(&test_file, &temp_file, 0);
-        assert!(result.is_ok());
-
-        let new_content = read_file_content(&temp_file);
-        assert_eq!(new_content, "line 1\nline 2\n");
-
-        cleanup_files(&[&test_file, &temp_file]);
-    }
-
-    // ========================================
-    // Insert Line Tests
-    // ========================================
-
-    #[test]
-    fn test_insert_line_before_bytewise() {
-        let content = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_insert_before.txt", content);
-        let temp_file = PathBuf::from("temp_insert_before.txt");
-
-        let result = insert_line_before_bytewise(&test_file, &temp_file, 1, b"NEW\n");
-        assert!(result.is_ok());
-
-        let new_content = read_file_content(&temp_file);
-        assert_eq!(new_content, "line 0\nNEW\nline 1\nline 2\n");
-
-        cleanup_files(&[&test_file, &temp_file]);
-    }
-
-    #[test]
-    fn test_insert_line_after_bytewise() {
-        let content = "line 0\nline 1\nline 2\n";
-        let test_file = create_test_file("test_insert_after.txt", content);
-        let temp_file = PathBuf::from("temp_insert_after.txt");
-
-        let result = insert_line_after_bytewise(&test_file, &temp_file, 1, b"NEW\n");
-        assert!(result.is_ok());
-
-        let new_content = read_file_content(&temp_file);
-        assert_eq!(new_content, "line 0\nline 1\nNEW\nline 2\n");
-
-        cleanup_files(&[&test_file, &temp_file]);
-    }
-
-    // ========================================
-    // Block Comment Toggle Tests
-    // ========================================
-
     #[test]
-    fn test_block_comment_bytewise_add_rust() {
-        let content = "code line 1\ncode line 2\n";
-        let test_file = create_test_file("test_block_add.rs", content);
-
-        let result = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 1);
-        assert!(result.is_ok());
-
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "/*\ncode line 1\ncode line 2\n*/\n");
-
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_block_add.rs"),
-        ]);
-    }
+    fn test_convert_comment_style_roundtrip() {
+        let original = "int x;\r\n    // note  \r\n/// doc\r\n// a */ b\r\n//x";
+        let test_file = create_test_file("test_convert_style.c", original);
+        let path = test_file.to_str().unwrap();
 
-    #[test]
-    fn test_block_comment_bytewise_remove_rust() {
-        let content = "/*\ncode line 1\ncode line 2\n*/\n";
-        let test_file = create_test_file("test_block_remove.rs", content);
+        assert_eq!(convert_comment_style(path, 1, CommentStyle::Block), Ok(()));
+        assert_eq!(convert_comment_style(path, 4, CommentStyle::Block), Ok(()));
+        assert_eq!(
+            read_file_content(&test_file),
+            "int x;\r\n    /* note */  \r\n/// doc\r\n// a */ b\r\n/*x*/"
+        );
 
-        let result = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 3);
-        assert!(result.is_ok());
+        // Already a block comment, code, doc comment, marker in the text
+        for (line, to) in [
+            (1, CommentStyle::Block),
+            (0, CommentStyle::Line),
+            (2, CommentStyle::Block),
+            (3, CommentStyle::Block),
+        ] {
+            assert_eq!(
+                convert_comment_style(path, line, to),
+                Err(ToggleCommentError::NotConvertibleComment { line })
+            );
+        }
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "code line 1\ncode line 2\n");
+        assert_eq!(convert_comment_style(path, 1, CommentStyle::Line), Ok(()));
+        assert_eq!(convert_comment_style(path, 4, CommentStyle::Line), Ok(()));
+        assert_eq!(read_file_content(&test_file), original);
 
         cleanup_files(&[
             &test_file,
-            &PathBuf::from("backup_toggle_comment_test_block_remove.rs"),
+            &PathBuf::from("backup_toggle_comment_test_convert_style.c"),
         ]);
     }
 
-    #[test]
-    fn test_block_comment_bytewise_single_line() {
-        let content = "code line\n";
-        let test_file = create_test_file("test_block_single.rs", content);
-
-        // Single line always ADD
-        let result = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 0);
-        assert!(result.is_ok());
-
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "/*\ncode line\n*/\n");
-
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_block_single.rs"),
-        ]);
+    fn convert_all(content: &str, to: CommentStyle) -> (String, usize) {
+        let markers = determine_block_markers("c").unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        let (converted, removed, inserted) = convert_comments_in(
+            &mut content.as_bytes(),
+            &mut output,
+            b"//",
+            markers,
+            false,
+            to,
+        )
+        .unwrap();
+        assert_eq!(
+            output.len() as u64,
+            content.len() as u64 - removed + inserted
+        );
+        (String::from_utf8(output).unwrap(), converted)
     }
 
     #[test]
-    fn test_block_comment_bytewise_python() {
-        let content = "code line 1\ncode line 2\n";
-        let test_file = create_test_file("test_block_python.py", content);
+    fn test_convert_comments_in_file_text() {
+        // Multi-line: marker lines dropped, ` * ` decoration removed, CRLF kept
+        let source = "/*\r\n * one\r\n *   two\r\n */\r\nint x; /* after */\r\n  /* a */ \r\n";
+        assert_eq!(
+            convert_all(source, CommentStyle::Line),
+            (
+                "// one\r\n//   two\r\nint x; /* after */\r\n  // a \r\n".to_string(),
+                2
+            )
+        );
 
-        let result = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 1);
-        assert!(result.is_ok());
+        // Strings, doc comments and code after a comment are left alone
+        let source = "s = \"/* x */\";\n/** doc\n */\n/* a */ f();\n/* b\n   c */\n";
+        assert_eq!(
+            convert_all(source, CommentStyle::Line),
+            (
+                "s = \"/* x */\";\n/** doc\n */\n/* a */ f();\n// b\n// c\n".to_string(),
+                1
+            )
+        );
 
-        let new_content = read_file_content(&test_file);
-        assert_eq!(new_content, "\"\"\"\ncode line 1\ncode line 2\n\"\"\"\n");
+        // Line comments to block: per line, reversible
+        let source = "  // a\nx(); // b\n\"//\"\n// c */\n//d";
+        let (blocks, converted) = convert_all(source, CommentStyle::Block);
+        assert_eq!(
+            (blocks.as_str(), converted),
+            ("  /* a */\nx(); // b\n\"//\"\n// c */\n/*d*/", 2)
+        );
+        assert_eq!(convert_all(&blocks, CommentStyle::Line).0, source);
 
-        cleanup_files(&[
-            &test_file,
-            &PathBuf::from("backup_toggle_comment_test_block_python.py"),
-        ]);
+        // Over the bound: copied unchanged
+        let long = format!("/* {} */\n", "x".repeat(MAX_CONVERTED_COMMENT_BYTES));
+        assert_eq!(convert_all(&long, CommentStyle::Line), (long.clone(), 0));
     }
 
     #[test]
-    fn test_block_comment_bytewise_roundtrip() {
-        let original = "code line 1\ncode line 2\n";
-        let test_file = create_test_file("test_block_roundtrip.rs", original);
-
-        // Add
-        let result1 = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 1);
-        assert!(result1.is_ok());
-
-        let content1 = read_file_content(&test_file);
-        assert_eq!(content1, "/*\ncode line 1\ncode line 2\n*/\n");
-
-        // Remove
-        let result2 = toggle_block_comment_bytewise(test_file.to_str().unwrap(), 0, 3);
-        assert!(result2.is_ok());
+    fn test_convert_comment_style_python_and_unsupported() {
+        let test_file = create_test_file("test_convert_style.py", "  # note\n");
+        let path = test_file.to_str().unwrap();
+        assert_eq!(convert_comment_style(path, 0, CommentStyle::Block), Ok(()));
+        assert_eq!(read_file_content(&test_file), "  \"\"\" note \"\"\"\n");
 
-        let content2 = read_file_content(&test_file);
-        assert_eq!(content2, original);
+        let toml_file = create_test_file("test_convert_style.toml", "# note\n");
+        assert_eq!(
+            convert_comment_style(toml_file.to_str().unwrap(), 0, CommentStyle::Block),
+            Err(ToggleCommentError::NoStyleConversion)
+        );
+        // File-wide: `/* */` languages only
+        assert_eq!(
+            convert_comment_style_file(path, CommentStyle::Line),
+            Err(ToggleCommentError::NoStyleConversion)
+        );
 
         cleanup_files(&[
             &test_file,
-            &PathBuf::from("backup_toggle_comment_test_block_roundtrip.rs"),
+            &toml_file,
+            &PathBuf::from("backup_toggle_comment_test_convert_style.py"),
         ]);
     }
 }
@@ -0,0 +1,607 @@
+//! # tui.rs
+//!
+//! Interactive line picker (`--tui <file>`): move through the file, select
+//! ranges and queue comment / indent edits, applied by the caller on exit
+//!
+//! ```text
+//! c         3 fn main() {
+//! c>        4     let x = 1;
+//!           5     let y = 2;
+//! lib.rs  line 5/12  sel 5-5  queued 2  c comment  > indent  < unindent  u undo  q apply ...
+//! ```
+//!
+//! ## Keys
+//! - `j` / `k` / arrows: move; PgDn / PgUp: one screen; `g` / `G` (Home / End): first / last line
+//! - `v` (or space): start a selection at the cursor, press again to clear it
+//! - `c`: queue a comment toggle, `>`: queue indent, `<`: queue unindent
+//!   (for the selection, or the cursor line without one)
+//! - `u`: drop the last queued edit
+//! - `q` (or `w`): leave and apply the queue; `Q`, Ctrl-C or end of input:
+//!   leave and discard it
+//!
+//! Line numbers are one-indexed on screen and zero-indexed in `QueuedEdit`.
+//!
+//! ## Policy
+//! - Standard library only: keys are decoded from raw bytes and the screen
+//!   is drawn with ANSI escapes; switching the terminal to raw mode is the
+//!   caller's job (the binary uses `stty`)
+//! - The file is not modified here: `run_tui` only returns the queue, in the
+//!   order the edits were made
+//! - Only edits that keep the line count are offered, so queued line numbers
+//!   stay valid while earlier edits are applied
+//! - Bounded memory: each redraw reads only the visible lines, cut at the
+//!   terminal width; at most MAX_QUEUED_EDITS edits, a screen of at most
+//!   MAX_TUI_ROWS x MAX_TUI_COLUMNS
+
+use crate::toggle_comment_indent_module::{IoOperation, ToggleCommentError, last_line_index};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// Most edits one session can queue
+pub const MAX_QUEUED_EDITS: usize = 64;
+
+/// Largest screen height used (taller terminals leave rows unused)
+pub const MAX_TUI_ROWS: usize = 200;
+
+/// Largest screen width used (longer lines are cut)
+pub const MAX_TUI_COLUMNS: usize = 500;
+
+/// Width of the gutter before the line text: two marks and a line number
+const GUTTER_WIDTH: usize = 12;
+
+/// Kind of a queued edit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TuiEdit {
+    /// Toggle the extension's line comment flag (basic mode)
+    Comment,
+
+    /// Indent the range
+    Indent,
+
+    /// Unindent the range
+    Unindent,
+}
+
+impl TuiEdit {
+    /// Gutter mark for lines this edit covers
+    fn mark(self) -> char {
+        match self {
+            TuiEdit::Comment => 'c',
+            TuiEdit::Indent => '>',
+            TuiEdit::Unindent => '<',
+        }
+    }
+}
+
+/// One edit queued in the picker (zero-indexed, inclusive range)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuedEdit {
+    pub edit: TuiEdit,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A decoded key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Char(u8),
+}
+
+/// Escape sequence decoder state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecodeState {
+    Ground,
+    Escape,
+    /// Inside `ESC [` or `ESC O`, with the numeric parameter so far
+    Sequence(u8),
+}
+
+/// Turns raw terminal bytes into keys, one byte at a time
+///
+/// Unknown escape sequences and a lone ESC are dropped.
+struct KeyDecoder {
+    state: DecodeState,
+}
+
+impl KeyDecoder {
+    fn new() -> Self {
+        KeyDecoder {
+            state: DecodeState::Ground,
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> Option<Key> {
+        match self.state {
+            DecodeState::Ground => {
+                if byte == 0x1b {
+                    self.state = DecodeState::Escape;
+                    None
+                } else {
+                    Some(Key::Char(byte))
+                }
+            }
+            DecodeState::Escape => {
+                self.state = if byte == b'[' || byte == b'O' {
+                    DecodeState::Sequence(0)
+                } else {
+                    DecodeState::Ground
+                };
+                None
+            }
+            DecodeState::Sequence(param) => {
+                if byte.is_ascii_digit() {
+                    // Parameters we know are one digit; larger ones saturate
+                    self.state = DecodeState::Sequence(param.saturating_mul(10) + (byte - b'0'));
+                    return None;
+                }
+                if byte == b';' {
+                    return None;
+                }
+                self.state = DecodeState::Ground;
+                match (byte, param) {
+                    (b'A', _) => Some(Key::Up),
+                    (b'B', _) => Some(Key::Down),
+                    (b'H', _) | (b'~', 1) | (b'~', 7) => Some(Key::Home),
+                    (b'F', _) | (b'~', 4) | (b'~', 8) => Some(Key::End),
+                    (b'~', 5) => Some(Key::PageUp),
+                    (b'~', 6) => Some(Key::PageDown),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// What the session loop does after a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Continue,
+    Apply,
+    Discard,
+}
+
+/// Cursor, selection and queue of one picker session
+struct Picker {
+    cursor: usize,
+    top: usize,
+    last_line: usize,
+    body_rows: usize,
+    anchor: Option<usize>,
+    queue: Vec<QueuedEdit>,
+    message: &'static str,
+}
+
+impl Picker {
+    fn new(last_line: usize, body_rows: usize) -> Self {
+        Picker {
+            cursor: 0,
+            top: 0,
+            last_line,
+            body_rows: body_rows.max(1),
+            anchor: None,
+            queue: Vec::new(),
+            message: "",
+        }
+    }
+
+    /// Selected range (the cursor line without a selection)
+    fn selection(&self) -> (usize, usize) {
+        match self.anchor {
+            Some(anchor) => (anchor.min(self.cursor), anchor.max(self.cursor)),
+            None => (self.cursor, self.cursor),
+        }
+    }
+
+    fn move_to(&mut self, line: usize) {
+        self.cursor = line.min(self.last_line);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + self.body_rows {
+            self.top = self.cursor + 1 - self.body_rows;
+        }
+    }
+
+    fn queue_edit(&mut self, edit: TuiEdit) {
+        if self.queue.len() >= MAX_QUEUED_EDITS {
+            self.message = "queue full";
+            return;
+        }
+        let (start, end) = self.selection();
+        self.queue.push(QueuedEdit { edit, start, end });
+        self.anchor = None;
+    }
+
+    fn handle(&mut self, key: Key) -> Step {
+        self.message = "";
+        match key {
+            Key::Down | Key::Char(b'j') => self.move_to(self.cursor.saturating_add(1)),
+            Key::Up | Key::Char(b'k') => self.move_to(self.cursor.saturating_sub(1)),
+            Key::PageDown => self.move_to(self.cursor.saturating_add(self.body_rows)),
+            Key::PageUp => self.move_to(self.cursor.saturating_sub(self.body_rows)),
+            Key::Home | Key::Char(b'g') => self.move_to(0),
+            Key::End | Key::Char(b'G') => self.move_to(self.last_line),
+            Key::Char(b'v') | Key::Char(b' ') => {
+                self.anchor = match self.anchor {
+                    Some(_) => None,
+                    None => Some(self.cursor),
+                };
+            }
+            Key::Char(b'c') => self.queue_edit(TuiEdit::Comment),
+            Key::Char(b'>') => self.queue_edit(TuiEdit::Indent),
+            Key::Char(b'<') => self.queue_edit(TuiEdit::Unindent),
+            Key::Char(b'u') => {
+                if self.queue.pop().is_none() {
+                    self.message = "nothing to undo";
+                }
+            }
+            Key::Char(b'q') | Key::Char(b'w') => return Step::Apply,
+            // Ctrl-C arrives as a byte in raw mode
+            Key::Char(b'Q') | Key::Char(0x03) => return Step::Discard,
+            Key::Char(_) => self.message = "unknown key",
+        }
+        Step::Continue
+    }
+
+    /// Gutter marks of a line: last two queued edits covering it, newest right
+    fn marks(&self, line: usize) -> [char; 2] {
+        let mut marks = [' ', ' '];
+        for queued in &self.queue {
+            if queued.start <= line && line <= queued.end {
+                marks = [marks[1], queued.edit.mark()];
+            }
+        }
+        if marks[0] == ' ' {
+            marks.swap(0, 1);
+        }
+        marks
+    }
+}
+
+/// Read lines `top..top + count` of a file, each cut at `max_bytes`
+///
+/// Tabs become spaces and other control bytes `.`; invalid UTF-8 is
+/// replaced. Lines past the end are simply missing.
+fn read_window(
+    file_path: &str,
+    top: usize,
+    count: usize,
+    max_bytes: usize,
+) -> Result<Vec<String>, ToggleCommentError> {
+    let file = File::open(file_path)
+        .map_err(|e| ToggleCommentError::from_io_error(&e, IoOperation::Open))?;
+    let mut reader = BufReader::new(file);
+    let mut lines = Vec::with_capacity(count);
+    let mut current: Vec<u8> = Vec::new();
+    let mut line = 0;
+
+    while line < top + count {
+        let (consumed, done) = {
+            let buffer = reader
+                .fill_buf()
+                .map_err(|_| ToggleCommentError::IoError(IoOperation::Read))?;
+            if buffer.is_empty() {
+                if line >= top && !current.is_empty() {
+                    lines.push(display_text(&current));
+                }
+                break;
+            }
+            match buffer.iter().position(|&b| b == b'\n') {
+                Some(newline) => {
+                    if line >= top {
+                        let room = max_bytes.saturating_sub(current.len());
+                        current.extend_from_slice(&buffer[..newline.min(room)]);
+                    }
+                    (newline + 1, true)
+                }
+                None => {
+                    if line >= top {
+                        let room = max_bytes.saturating_sub(current.len());
+                        current.extend_from_slice(&buffer[..buffer.len().min(room)]);
+                    }
+                    (buffer.len(), false)
+                }
+            }
+        };
+        reader.consume(consumed);
+        if done {
+            if line >= top {
+                lines.push(display_text(&current));
+            }
+            current.clear();
+            line += 1;
+        }
+    }
+    Ok(lines)
+}
+
+/// Screen-safe text of one line (no escapes reach the terminal)
+fn display_text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .chars()
+        .map(|c| match c {
+            '\t' => ' ',
+            c if c.is_control() => '.',
+            c => c,
+        })
+        .collect()
+}
+
+/// Cut text to `columns` characters
+fn fit(text: &str, columns: usize) -> &str {
+    match text.char_indices().nth(columns) {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
+
+/// Draw the visible lines and the status line
+fn render<W: Write>(
+    output: &mut W,
+    picker: &Picker,
+    file_path: &str,
+    columns: usize,
+) -> Result<(), ToggleCommentError> {
+    let text_columns = columns.saturating_sub(GUTTER_WIDTH);
+    let lines = read_window(file_path, picker.top, picker.body_rows, text_columns * 4)?;
+    let (sel_start, sel_end) = picker.selection();
+
+    let mut screen = String::from("\x1b[H\x1b[2J");
+    for (offset, text) in lines.iter().enumerate() {
+        let line = picker.top + offset;
+        let marks = picker.marks(line);
+        let highlight = line == picker.cursor
+            || (picker.anchor.is_some() && sel_start <= line && line <= sel_end);
+        if highlight {
+            screen.push_str("\x1b[7m");
+        }
+        screen.push_str(&format!(
+            "{}{} {:>8} {}",
+            marks[0],
+            marks[1],
+            line + 1,
+            fit(text, text_columns)
+        ));
+        if highlight {
+            screen.push_str("\x1b[0m");
+        }
+        screen.push_str("\r\n");
+    }
+
+    let name = std::path::Path::new(file_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let status = format!(
+        "{}  line {}/{}  sel {}-{}  queued {}  c comment  > indent  < unindent  u undo  q apply  Q discard  {}",
+        display_text(name.as_bytes()),
+        picker.cursor + 1,
+        picker.last_line + 1,
+        sel_start + 1,
+        sel_end + 1,
+        picker.queue.len(),
+        picker.message
+    );
+    screen.push_str(&format!("\x1b[{};1H", picker.body_rows + 1));
+    screen.push_str(fit(&status, columns));
+
+    output
+        .write_all(screen.as_bytes())
+        .and_then(|_| output.flush())
+        .map_err(|_| ToggleCommentError::IoError(IoOperation::Write))
+}
+
+/// Run a picker session on `file_path` and return the queued edits
+///
+/// `input` is the raw-mode terminal, `output` the screen; the session uses
+/// the alternate screen and leaves it before returning.
+///
+/// # Arguments
+/// * `rows` / `columns` - Terminal size (clamped to MAX_TUI_ROWS / MAX_TUI_COLUMNS)
+///
+/// # Returns
+/// * `Ok(Some(edits))` - Left with `q`: apply these, in order (may be empty)
+/// * `Ok(None)` - Left with `Q`, Ctrl-C or end of input: apply nothing
+/// * `Err(FileNotFound)` / `Err(NotRegularFile)` / ... - As in `last_line_index`
+/// * `Err(IoError)` - Reading the file, the keys or drawing failed
+pub fn run_tui<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    file_path: &str,
+    rows: usize,
+    columns: usize,
+) -> Result<Option<Vec<QueuedEdit>>, ToggleCommentError> {
+    let last_line = last_line_index(file_path)?;
+    let rows = rows.clamp(2, MAX_TUI_ROWS);
+    let columns = columns.clamp(GUTTER_WIDTH + 1, MAX_TUI_COLUMNS);
+
+    let mut picker = Picker::new(last_line, rows - 1);
+    let mut decoder = KeyDecoder::new();
+
+    output
+        .write_all(b"\x1b[?1049h\x1b[?25l")
+        .map_err(|_| ToggleCommentError::IoError(IoOperation::Write))?;
+    let result = session(input, output, file_path, columns, &mut picker, &mut decoder);
+    let restored = output
+        .write_all(b"\x1b[?25h\x1b[?1049l")
+        .and_then(|_| output.flush())
+        .map_err(|_| ToggleCommentError::IoError(IoOperation::Write));
+
+    let step = result?;
+    restored?;
+    Ok(match step {
+        Step::Apply => Some(picker.queue),
+        _ => None,
+    })
+}
+
+/// Key loop until the user leaves (or input ends)
+fn session<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    file_path: &str,
+    columns: usize,
+    picker: &mut Picker,
+    decoder: &mut KeyDecoder,
+) -> Result<Step, ToggleCommentError> {
+    let mut byte = [0u8; 1];
+    loop {
+        render(output, picker, file_path, columns)?;
+        let key = loop {
+            match input.read(&mut byte) {
+                Ok(0) => return Ok(Step::Discard),
+                Ok(_) => {
+                    if let Some(key) = decoder.push(byte[0]) {
+                        break key;
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => return Err(ToggleCommentError::IoError(IoOperation::Read)),
+            }
+        };
+        match picker.handle(key) {
+            Step::Continue => {}
+            step => return Ok(step),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tui_tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Vec<Key> {
+        let mut decoder = KeyDecoder::new();
+        bytes.iter().filter_map(|&b| decoder.push(b)).collect()
+    }
+
+    #[test]
+    fn test_key_decoder() {
+        assert_eq!(
+            decode(b"j\x1b[A\x1b[B\x1bOA\x1b[5~\x1b[6~\x1b[H\x1b[4~\x1b[1;5C\x1bxk"),
+            vec![
+                Key::Char(b'j'),
+                Key::Up,
+                Key::Down,
+                Key::Up,
+                Key::PageUp,
+                Key::PageDown,
+                Key::Home,
+                Key::End,
+                // Unknown sequence (Ctrl-Right) and ESC x are dropped
+                Key::Char(b'k'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_picker_queue() {
+        let mut picker = Picker::new(9, 3);
+        for &key in b"jjvjj" {
+            assert_eq!(picker.handle(Key::Char(key)), Step::Continue);
+        }
+        assert_eq!(picker.selection(), (2, 4));
+        // Scrolled to keep the cursor on screen
+        assert_eq!(picker.top, 2);
+
+        picker.handle(Key::Char(b'c'));
+        picker.handle(Key::Char(b'>'));
+        picker.handle(Key::End);
+        picker.handle(Key::Down);
+        picker.handle(Key::Char(b'<'));
+        assert_eq!(
+            picker.queue,
+            vec![
+                QueuedEdit {
+                    edit: TuiEdit::Comment,
+                    start: 2,
+                    end: 4
+                },
+                QueuedEdit {
+                    edit: TuiEdit::Indent,
+                    start: 4,
+                    end: 4
+                },
+                QueuedEdit {
+                    edit: TuiEdit::Unindent,
+                    start: 9,
+                    end: 9
+                },
+            ]
+        );
+        assert_eq!(picker.marks(4), ['c', '>']);
+        assert_eq!(picker.marks(3), ['c', ' ']);
+        assert_eq!(picker.marks(0), [' ', ' ']);
+
+        picker.handle(Key::Char(b'u'));
+        assert_eq!(picker.queue.len(), 2);
+        assert_eq!(picker.handle(Key::Char(b'q')), Step::Apply);
+        assert_eq!(picker.handle(Key::Char(0x03)), Step::Discard);
+
+        for _ in 0..MAX_QUEUED_EDITS + 1 {
+            picker.handle(Key::Char(b'c'));
+        }
+        assert_eq!(picker.queue.len(), MAX_QUEUED_EDITS);
+        assert_eq!(picker.message, "queue full");
+    }
+
+    #[test]
+    fn test_run_tui_session() {
+        let path = "test_run_tui_session.py";
+        std::fs::write(path, "a\n\tb\x1b[2J\nc").unwrap();
+
+        let mut output = Vec::new();
+        let edits = run_tui(&mut &b"jvjc\x1b[Aq"[..], &mut output, path, 5, 40).unwrap();
+        assert_eq!(
+            edits,
+            Some(vec![QueuedEdit {
+                edit: TuiEdit::Comment,
+                start: 1,
+                end: 2
+            }])
+        );
+        let screen = String::from_utf8_lossy(&output);
+        // The file's escape sequence is not passed through; last line shown
+        assert!(screen.contains(" b.[2J"));
+        assert!(screen.contains("       3 c"));
+        assert!(screen.ends_with("\x1b[?25h\x1b[?1049l"));
+
+        // End of input discards
+        let mut output = Vec::new();
+        assert_eq!(run_tui(&mut &b"c"[..], &mut output, path, 5, 40), Ok(None));
+
+        assert_eq!(
+            run_tui(
+                &mut &b"q"[..],
+                &mut Vec::new(),
+                "test_run_tui_missing.py",
+                5,
+                40
+            ),
+            Err(ToggleCommentError::FileNotFound)
+        );
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            read_window_lines("x\ny\nz\n", 1, 5, 80),
+            vec!["y".to_string(), "z".to_string()]
+        );
+        assert_eq!(
+            read_window_lines("long line", 0, 1, 4),
+            vec!["long".to_string()]
+        );
+    }
+
+    fn read_window_lines(content: &str, top: usize, count: usize, max: usize) -> Vec<String> {
+        let path = format!("test_read_window_{}_{}.txt", top, max);
+        std::fs::write(&path, content).unwrap();
+        let lines = read_window(&path, top, count, max).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        lines
+    }
+}